@@ -0,0 +1,240 @@
+//! Autocovariance least squares (ALS) identification of noise covariances
+//!
+//! The method follows Odelson, Rajamani and Rawlings, "A new autocovariance
+//! least-squares method for estimating noise covariances", Automatica 42
+//! (2006). A filter with a fixed, possibly suboptimal, gain is run over the
+//! data. The autocovariances of its innovations are linear in the unknown
+//! `Q` and `R`, which are then found by least squares.
+
+use na::{DMatrix, DVector, RealField};
+use nalgebra as na;
+
+use crate::riccati::{kalman_gain, steady_state_prior_covariance};
+use crate::{is_nan, Error, ErrorKind, ObservationModel, TransitionModelLinearNoControl};
+
+/// Process and observation noise covariances estimated by
+/// [autocovariance_least_squares]
+#[derive(Debug, Clone)]
+pub struct NoiseCovariances<R>
+where
+    R: RealField,
+{
+    /// The process covariance, `Q`.
+    pub Q: DMatrix<R>,
+    /// The observation noise covariance, `R`.
+    pub R: DMatrix<R>,
+}
+
+/// Estimate `Q` and `R` from the innovation autocovariances of a filter run
+///
+/// The steady-state gain of the filter given by `transition_model` and
+/// `observation_model` is computed and used to run a fixed-gain filter over
+/// `observations`, starting from `initial_state`. The `Q` and `R` of the models
+/// only determine this gain; they need not be accurate, but the resulting
+/// filter must be stable. The innovation autocovariances for lags
+/// `0..lags` are then matched in the least-squares sense.
+///
+/// The observation model must be linear. The estimates are symmetric but are
+/// not constrained to be positive semi-definite; with too few observations or
+/// lags they may not be. If `Q` is not fully identifiable from the data, the
+/// minimum-norm solution is returned.
+///
+/// Observations must not contain missing (NaN) values, or an
+/// [ErrorKind::MissingObservation] error is returned. An
+/// [ErrorKind::InvalidLags] error is returned unless `lags` is at least one
+/// and less than the number of observations.
+pub fn autocovariance_least_squares<R>(
+    transition_model: &dyn TransitionModelLinearNoControl<R>,
+    observation_model: &dyn ObservationModel<R>,
+    initial_state: &DVector<R>,
+    observations: &[DVector<R>],
+    lags: usize,
+) -> Result<NoiseCovariances<R>, Error>
+where
+    R: RealField,
+{
+    if lags == 0 || observations.len() <= lags {
+        return Err(ErrorKind::InvalidLags {
+            lags,
+            len: observations.len(),
+        }
+        .into());
+    }
+
+    let F = transition_model.F();
+    let H = observation_model.H();
+    let n = F.nrows();
+    let p = H.nrows();
+
+    let P = steady_state_prior_covariance(F, transition_model.Q(), H, observation_model.R())?;
+    let L = kalman_gain(&P, H, observation_model.R())?;
+
+    // Innovations of the fixed-gain filter.
+    let mut innovations = Vec::with_capacity(observations.len());
+    let mut x = initial_state.clone();
+    for observation in observations.iter() {
+        if observation.iter().any(|v| is_nan(v.clone())) {
+            return Err(ErrorKind::MissingObservation.into());
+        }
+        let innovation = observation - H * &x;
+        x = F * (&x + &L * &innovation);
+        innovations.push(innovation);
+    }
+
+    // Stacked estimated autocovariances [C_0; C_1; ...; C_{lags-1}].
+    let mut autocovariance = DMatrix::<R>::zeros(lags * p, p);
+    for j in 0..lags {
+        let count = innovations.len() - j;
+        let mut c = DMatrix::<R>::zeros(p, p);
+        for i in 0..count {
+            c += &innovations[i + j] * innovations[i].transpose();
+        }
+        c /= na::convert::<f64, R>(count as f64);
+        autocovariance.slice_mut((j * p, 0), (p, p)).copy_from(&c);
+    }
+
+    // Closed-loop error dynamics, e_{k+1} = A e_k + w_k - F L v_k.
+    let FL = F * &L;
+    let A = F - &FL * H;
+
+    // O = [H; H A; ...; H A^(lags-1)] and Gamma = [I; -H F L; -H A F L; ...].
+    let mut O = DMatrix::<R>::zeros(lags * p, n);
+    let mut Gamma = DMatrix::<R>::zeros(lags * p, p);
+    Gamma
+        .slice_mut((0, 0), (p, p))
+        .copy_from(&DMatrix::identity(p, p));
+    let mut HA_pow = H.clone();
+    for j in 0..lags {
+        O.slice_mut((j * p, 0), (p, n)).copy_from(&HA_pow);
+        if j + 1 < lags {
+            Gamma
+                .slice_mut(((j + 1) * p, 0), (p, p))
+                .copy_from(&-(&HA_pow * &FL));
+        }
+        HA_pow *= &A;
+    }
+
+    // vec(P) = (I - A (x) A)^-1 (vec(Q) + (FL (x) FL) vec(R))
+    let lyapunov = match (DMatrix::identity(n * n, n * n) - A.kronecker(&A)).try_inverse() {
+        Some(v) => v,
+        None => return Err(ErrorKind::SingularMatrix.into()),
+    };
+    let HO = H.kronecker(&O) * lyapunov;
+    let A_Q = &HO * duplication_matrix(n);
+    let A_R = (&HO * FL.kronecker(&FL) + DMatrix::identity(p, p).kronecker(&Gamma))
+        * duplication_matrix(p);
+
+    let n_q = A_Q.ncols();
+    let mut design = DMatrix::<R>::zeros(A_Q.nrows(), n_q + A_R.ncols());
+    design.slice_mut((0, 0), A_Q.shape()).copy_from(&A_Q);
+    design.slice_mut((0, n_q), A_R.shape()).copy_from(&A_R);
+    let target = DVector::from_column_slice(autocovariance.as_slice());
+
+    let solution = design
+        .svd(true, true)
+        .solve(&target, na::convert(1e-12))
+        .map_err(|_| Error::from(ErrorKind::SingularMatrix))?;
+
+    Ok(NoiseCovariances {
+        Q: unvech(&solution.rows(0, n_q).into_owned(), n),
+        R: unvech(&solution.rows(n_q, solution.nrows() - n_q).into_owned(), p),
+    })
+}
+
+/// The duplication matrix `D`, such that `vec(S) = D vech(S)` for symmetric
+/// `S` of size `n x n`.
+fn duplication_matrix<R: RealField>(n: usize) -> DMatrix<R> {
+    let mut D = DMatrix::zeros(n * n, n * (n + 1) / 2);
+    let mut k = 0;
+    for j in 0..n {
+        for i in j..n {
+            D[(j * n + i, k)] = R::one();
+            D[(i * n + j, k)] = R::one();
+            k += 1;
+        }
+    }
+    D
+}
+
+/// Inverse of `vech`, filling a symmetric matrix from its lower triangle.
+fn unvech<R: RealField>(v: &DVector<R>, n: usize) -> DMatrix<R> {
+    let mut S = DMatrix::zeros(n, n);
+    let mut k = 0;
+    for j in 0..n {
+        for i in j..n {
+            S[(i, j)] = v[k].clone();
+            S[(j, i)] = v[k].clone();
+            k += 1;
+        }
+    }
+    S
+}
+
+#[test]
+fn test_als_recovers_observation_noise() {
+    use crate::test_util::{simulate_positions, ConstantVelocity, PositionObservation};
+
+    // The model used to compute the gain is deliberately wrong.
+    let observations = simulate_positions(20_000, 0.1, 0.5, 2.0, 3);
+    let transition = ConstantVelocity::new(0.1, 1.0);
+    let observation = PositionObservation::new(0.5);
+    let estimate = autocovariance_least_squares(
+        &transition,
+        &observation,
+        &DVector::from_vec(vec![0.0, 1.0]),
+        &observations,
+        10,
+    )
+    .unwrap();
+    approx::assert_relative_eq!(estimate.R[(0, 0)], 2.0, max_relative = 0.1);
+}
+
+#[test]
+fn test_als_exact_autocovariances() {
+    use crate::test_util::{random_walk, scalars};
+
+    // The innovations 2, 2, -2, -2, 2 have variance 4 and lag-one
+    // autocovariance 0, those of the optimal filter of a random walk with
+    // Q = 1 and R = 2. Build the observations giving them: the steady-state
+    // gain is 1/2, so the state estimate moves by half of each innovation.
+    let (transition, observation) = random_walk();
+    let mut x = 0.0;
+    let mut values = Vec::new();
+    for e in [2.0, 2.0, -2.0, -2.0, 2.0] {
+        values.push(x + e);
+        x += 0.5 * e;
+    }
+    let estimate = autocovariance_least_squares(
+        &transition,
+        &observation,
+        &DVector::zeros(1),
+        &scalars(&values),
+        2,
+    )
+    .unwrap();
+    approx::assert_relative_eq!(estimate.Q[(0, 0)], 1.0, epsilon = 1e-9);
+    approx::assert_relative_eq!(estimate.R[(0, 0)], 2.0, epsilon = 1e-9);
+
+    values[3] = f64::NAN;
+    let err = autocovariance_least_squares(
+        &transition,
+        &observation,
+        &DVector::zeros(1),
+        &scalars(&values),
+        2,
+    )
+    .unwrap_err();
+    assert!(matches!(err.kind(), ErrorKind::MissingObservation));
+
+    for lags in [0, 5] {
+        let err = autocovariance_least_squares(
+            &transition,
+            &observation,
+            &DVector::zeros(1),
+            &scalars(&values),
+            lags,
+        )
+        .unwrap_err();
+        assert!(matches!(err.kind(), ErrorKind::InvalidLags { len: 5, .. }));
+    }
+}
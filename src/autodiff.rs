@@ -0,0 +1,383 @@
+//! Exact Jacobians by forward-mode automatic differentiation
+//!
+//! Models implement [AutoDiffTransition] or [AutoDiffObservation], writing
+//! their functions generically over an [AutoDiffScalar]. The functions are
+//! then evaluated with [Dual] numbers to compute Jacobians exactly, and the
+//! wrappers [AutoDiffTransitionModel] and [AutoDiffObservationModel] make
+//! them usable with the [ExtendedKalmanFilter](crate::ExtendedKalmanFilter).
+
+use alloc::borrow::Cow;
+use core::fmt::Debug;
+use core::ops::{Add, Div, Mul, Neg, Sub};
+
+use na::{ComplexField, DMatrix, DVector, RealField};
+use nalgebra as na;
+
+use crate::{NonlinearTransitionModel, ObservationModel};
+
+/// A dual number `value + derivative * e` with `e^2 = 0`
+#[derive(Debug, Clone, PartialEq)]
+pub struct Dual<R>
+where
+    R: RealField,
+{
+    /// The value.
+    pub value: R,
+    /// The derivative with respect to the seeded variable.
+    pub derivative: R,
+}
+
+impl<R: RealField> Dual<R> {
+    /// Create a new dual number.
+    pub fn new(value: R, derivative: R) -> Self {
+        Self { value, derivative }
+    }
+
+    fn chain(self, value: R, derivative: R) -> Self {
+        Self::new(value, derivative * self.derivative)
+    }
+}
+
+impl<R: RealField> Add for Dual<R> {
+    type Output = Self;
+    fn add(self, rhs: Self) -> Self {
+        Self::new(self.value + rhs.value, self.derivative + rhs.derivative)
+    }
+}
+
+impl<R: RealField> Sub for Dual<R> {
+    type Output = Self;
+    fn sub(self, rhs: Self) -> Self {
+        Self::new(self.value - rhs.value, self.derivative - rhs.derivative)
+    }
+}
+
+impl<R: RealField> Mul for Dual<R> {
+    type Output = Self;
+    fn mul(self, rhs: Self) -> Self {
+        Self::new(
+            self.value.clone() * rhs.value.clone(),
+            self.derivative * rhs.value + self.value * rhs.derivative,
+        )
+    }
+}
+
+impl<R: RealField> Div for Dual<R> {
+    type Output = Self;
+    fn div(self, rhs: Self) -> Self {
+        let value = self.value.clone() / rhs.value.clone();
+        let derivative = (self.derivative - value.clone() * rhs.derivative) / rhs.value;
+        Self::new(value, derivative)
+    }
+}
+
+impl<R: RealField> Neg for Dual<R> {
+    type Output = Self;
+    fn neg(self) -> Self {
+        Self::new(-self.value, -self.derivative)
+    }
+}
+
+/// A scalar type in which model functions can be evaluated
+///
+/// This is implemented for every `RealField`, for plain evaluation, and for
+/// [Dual] numbers, for differentiation.
+pub trait AutoDiffScalar<R>:
+    'static
+    + Debug
+    + Clone
+    + PartialEq
+    + Add<Output = Self>
+    + Sub<Output = Self>
+    + Mul<Output = Self>
+    + Div<Output = Self>
+    + Neg<Output = Self>
+where
+    R: RealField,
+{
+    /// Create a constant, i.e. a value which does not depend on the state.
+    fn constant(value: R) -> Self;
+    /// The value, discarding any derivative.
+    fn value(&self) -> R;
+    /// Sine.
+    fn sin(self) -> Self;
+    /// Cosine.
+    fn cos(self) -> Self;
+    /// Tangent.
+    fn tan(self) -> Self;
+    /// Exponential.
+    fn exp(self) -> Self;
+    /// Natural logarithm.
+    fn ln(self) -> Self;
+    /// Square root.
+    fn sqrt(self) -> Self;
+    /// Integer power.
+    fn powi(self, n: i32) -> Self;
+    /// Four-quadrant arctangent of `self / other`.
+    fn atan2(self, other: Self) -> Self;
+}
+
+impl<R: RealField> AutoDiffScalar<R> for R {
+    fn constant(value: R) -> Self {
+        value
+    }
+    fn value(&self) -> R {
+        self.clone()
+    }
+    fn sin(self) -> Self {
+        ComplexField::sin(self)
+    }
+    fn cos(self) -> Self {
+        ComplexField::cos(self)
+    }
+    fn tan(self) -> Self {
+        ComplexField::tan(self)
+    }
+    fn exp(self) -> Self {
+        ComplexField::exp(self)
+    }
+    fn ln(self) -> Self {
+        ComplexField::ln(self)
+    }
+    fn sqrt(self) -> Self {
+        ComplexField::sqrt(self)
+    }
+    fn powi(self, n: i32) -> Self {
+        ComplexField::powi(self, n)
+    }
+    fn atan2(self, other: Self) -> Self {
+        RealField::atan2(self, other)
+    }
+}
+
+impl<R: RealField> AutoDiffScalar<R> for Dual<R> {
+    fn constant(value: R) -> Self {
+        Self::new(value, R::zero())
+    }
+    fn value(&self) -> R {
+        self.value.clone()
+    }
+    fn sin(self) -> Self {
+        let (s, c) = self.value.clone().sin_cos();
+        self.chain(s, c)
+    }
+    fn cos(self) -> Self {
+        let (s, c) = self.value.clone().sin_cos();
+        self.chain(c, -s)
+    }
+    fn tan(self) -> Self {
+        let t = ComplexField::tan(self.value.clone());
+        let d = R::one() + t.clone() * t.clone();
+        self.chain(t, d)
+    }
+    fn exp(self) -> Self {
+        let e = ComplexField::exp(self.value.clone());
+        self.chain(e.clone(), e)
+    }
+    fn ln(self) -> Self {
+        let d = R::one() / self.value.clone();
+        let l = ComplexField::ln(self.value.clone());
+        self.chain(l, d)
+    }
+    fn sqrt(self) -> Self {
+        let s = ComplexField::sqrt(self.value.clone());
+        let d = R::one() / (s.clone() + s.clone());
+        self.chain(s, d)
+    }
+    fn powi(self, n: i32) -> Self {
+        let v = ComplexField::powi(self.value.clone(), n);
+        let d = ComplexField::powi(self.value.clone(), n - 1) * na::convert(n as f64);
+        self.chain(v, d)
+    }
+    fn atan2(self, other: Self) -> Self {
+        // d atan2(y, x) = (x dy - y dx) / (x^2 + y^2)
+        let (y, x) = (self.value, other.value);
+        let denominator = x.clone() * x.clone() + y.clone() * y.clone();
+        let derivative = (x.clone() * self.derivative - y.clone() * other.derivative) / denominator;
+        Self::new(RealField::atan2(y, x), derivative)
+    }
+}
+
+/// Jacobian of `f` at `x`, computed exactly by forward-mode automatic
+/// differentiation
+pub fn autodiff_jacobian<R, F>(f: F, x: &DVector<R>) -> DMatrix<R>
+where
+    R: RealField,
+    F: Fn(&DVector<Dual<R>>) -> DVector<Dual<R>>,
+{
+    let n = x.nrows();
+    let mut jacobian = DMatrix::zeros(0, n);
+    for i in 0..n {
+        let seeded = DVector::from_fn(n, |j, _| {
+            let derivative = if i == j { R::one() } else { R::zero() };
+            Dual::new(x[j].clone(), derivative)
+        });
+        let output = f(&seeded);
+        if i == 0 {
+            jacobian = DMatrix::zeros(output.nrows(), n);
+        }
+        for (row, value) in output.iter().enumerate() {
+            jacobian[(row, i)] = value.derivative.clone();
+        }
+    }
+    jacobian
+}
+
+/// A non-linear process model written generically over the scalar type
+pub trait AutoDiffTransition<R>
+where
+    R: RealField,
+{
+    /// The process function, `f(x)`.
+    fn f<S: AutoDiffScalar<R>>(&self, state: &DVector<S>) -> DVector<S>;
+
+    /// Get the process covariance, `Q`.
+    fn Q(&self) -> &DMatrix<R>;
+}
+
+/// A non-linear observation model written generically over the scalar type
+pub trait AutoDiffObservation<R>
+where
+    R: RealField,
+{
+    /// The observation function, `h(x)`.
+    fn h<S: AutoDiffScalar<R>>(&self, state: &DVector<S>) -> DVector<S>;
+
+    /// Get the observation noise covariance, `R`.
+    fn R(&self) -> &DMatrix<R>;
+}
+
+/// Wrapper implementing [NonlinearTransitionModel] for an
+/// [AutoDiffTransition]
+pub struct AutoDiffTransitionModel<M>(pub M);
+
+impl<M, R> NonlinearTransitionModel<R> for AutoDiffTransitionModel<M>
+where
+    M: AutoDiffTransition<R>,
+    R: RealField,
+{
+    fn f(&self, state: &DVector<R>) -> DVector<R> {
+        self.0.f(state)
+    }
+    fn jacobian_at(&self, state: &DVector<R>) -> DMatrix<R> {
+        autodiff_jacobian(|x| self.0.f(x), state)
+    }
+    fn Q(&self) -> &DMatrix<R> {
+        self.0.Q()
+    }
+}
+
+/// Wrapper implementing [ObservationModel] for an [AutoDiffObservation]
+///
+/// Updates linearize about the prior through
+/// [jacobian_at](ObservationModel::jacobian_at), with exact Jacobians. `H` is
+/// the linearization at the nominal state given to [new](Self::new).
+pub struct AutoDiffObservationModel<M, R>
+where
+    R: RealField,
+{
+    model: M,
+    H: DMatrix<R>,
+}
+
+impl<M, R> AutoDiffObservationModel<M, R>
+where
+    M: AutoDiffObservation<R>,
+    R: RealField,
+{
+    /// Wrap `model`, with `H` linearized at the nominal `state`.
+    pub fn new(model: M, state: &DVector<R>) -> Self {
+        let H = autodiff_jacobian(|x| model.h(x), state);
+        Self { model, H }
+    }
+}
+
+impl<M, R> ObservationModel<R> for AutoDiffObservationModel<M, R>
+where
+    M: AutoDiffObservation<R>,
+    R: RealField,
+{
+    fn predict_observation(&self, state: &DVector<R>) -> DVector<R> {
+        self.model.h(state)
+    }
+    fn H(&self) -> &DMatrix<R> {
+        &self.H
+    }
+    fn jacobian_at(&self, state: &DVector<R>) -> Cow<'_, DMatrix<R>> {
+        Cow::Owned(autodiff_jacobian(|x| self.model.h(x), state))
+    }
+    fn R(&self) -> &DMatrix<R> {
+        self.model.R()
+    }
+    fn state_dim(&self) -> usize {
+        self.H.ncols()
+    }
+    fn obs_dim(&self) -> usize {
+        self.H.nrows()
+    }
+}
+
+#[test]
+fn test_autodiff_jacobian() {
+    struct Polar {
+        R: DMatrix<f64>,
+    }
+    impl AutoDiffObservation<f64> for Polar {
+        fn h<S: AutoDiffScalar<f64>>(&self, x: &DVector<S>) -> DVector<S> {
+            let range = (x[0].clone() * x[0].clone() + x[1].clone() * x[1].clone()).sqrt();
+            let bearing = x[1].clone().atan2(x[0].clone());
+            DVector::from_vec(vec![range, bearing])
+        }
+        fn R(&self) -> &DMatrix<f64> {
+            &self.R
+        }
+    }
+    let model = AutoDiffObservationModel::new(
+        Polar {
+            R: DMatrix::identity(2, 2),
+        },
+        &DVector::from_vec(vec![3.0, 4.0]),
+    );
+    let expected = DMatrix::from_row_slice(2, 2, &[0.6, 0.8, -4.0 / 25.0, 3.0 / 25.0]);
+    approx::assert_relative_eq!(model.H(), &expected, epsilon = 1e-14);
+    approx::assert_relative_eq!(
+        model.predict_observation(&DVector::from_vec(vec![3.0, 4.0]))[0],
+        5.0
+    );
+
+    // The update linearizes about the prior at (0, 2), where the range and
+    // bearing have gradients [0, 1] and [-1/2, 0].
+    let prior =
+        crate::StateAndCovariance::new(DVector::from_vec(vec![0.0, 2.0]), DMatrix::identity(2, 2));
+    let expected = DMatrix::from_row_slice(2, 2, &[0.0, 1.0, -0.5, 0.0]);
+    approx::assert_relative_eq!(
+        &*model.jacobian_at(prior.state()),
+        &expected,
+        epsilon = 1e-14
+    );
+    let innovation = model.innovation(&prior, &DVector::from_vec(vec![2.0, 1.5]));
+    approx::assert_relative_eq!(
+        innovation.covariance(),
+        &DMatrix::from_diagonal(&DVector::from_vec(vec![2.0, 1.25])),
+        epsilon = 1e-14
+    );
+
+    let f = |x: &DVector<Dual<f64>>| {
+        DVector::from_vec(vec![
+            x[0].clone().sin() * x[1].clone().exp(),
+            x[0].clone().powi(3) / x[1].clone(),
+        ])
+    };
+    let x = DVector::from_vec(vec![0.5f64, 2.0]);
+    let expected = DMatrix::from_row_slice(
+        2,
+        2,
+        &[
+            x[0].cos() * x[1].exp(),
+            x[0].sin() * x[1].exp(),
+            3.0 * x[0] * x[0] / x[1],
+            -x[0].powi(3) / (x[1] * x[1]),
+        ],
+    );
+    approx::assert_relative_eq!(autodiff_jacobian(f, &x), expected, epsilon = 1e-14);
+}
@@ -0,0 +1,353 @@
+//! Grid search over scale factors of the process and observation noise
+
+use alloc::borrow::Cow;
+
+use na::{DMatrix, DVector, RealField};
+use nalgebra as na;
+
+use crate::{
+    is_nan, CovarianceUpdateMethod, Error, ObservationModel, StateAndCovariance,
+    TransitionModelLinearNoControl,
+};
+
+/// The score minimized by [autotune]
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum AutotuneCriterion {
+    /// Maximize the log-likelihood of the observations.
+    ///
+    /// Each observation is scored on its one-step-ahead prediction, so this
+    /// can equally be evaluated on held-out data.
+    LogLikelihood,
+    /// Bring the average normalized innovation squared (NIS) as close as
+    /// possible to its expected value, the observation dimension.
+    NisConsistency,
+}
+
+/// A transition model whose process covariance is a scaled copy of another's
+pub struct ScaledTransitionModel<'a, R>
+where
+    R: RealField,
+{
+    inner: &'a dyn TransitionModelLinearNoControl<R>,
+    Q: DMatrix<R>,
+    Qc: Option<DMatrix<R>>,
+}
+
+impl<'a, R> ScaledTransitionModel<'a, R>
+where
+    R: RealField,
+{
+    /// Wrap `inner`, multiplying its process covariance `Q`, and the
+    /// driving noise covariance `Qc` of any noise coupling, by `scale`.
+    pub fn new(inner: &'a dyn TransitionModelLinearNoControl<R>, scale: R) -> Self {
+        let Qc = inner.noise_coupling().map(|(_, Qc)| Qc * scale.clone());
+        let Q = inner.Q() * scale;
+        Self { inner, Q, Qc }
+    }
+}
+
+impl<'a, R> TransitionModelLinearNoControl<R> for ScaledTransitionModel<'a, R>
+where
+    R: RealField,
+{
+    fn state_dim(&self) -> usize {
+        self.inner.state_dim()
+    }
+    fn F(&self) -> &DMatrix<R> {
+        self.inner.F()
+    }
+    fn FT(&self) -> Cow<'_, DMatrix<R>> {
+        self.inner.FT()
+    }
+    fn Q(&self) -> &DMatrix<R> {
+        &self.Q
+    }
+    fn noise_coupling(&self) -> Option<(&DMatrix<R>, &DMatrix<R>)> {
+        let (G, _) = self.inner.noise_coupling()?;
+        Some((G, self.Qc.as_ref()?))
+    }
+}
+
+/// An observation model whose observation noise covariance is a scaled copy
+/// of another's
+pub struct ScaledObservationModel<'a, R>
+where
+    R: RealField,
+{
+    inner: &'a dyn ObservationModel<R>,
+    R: DMatrix<R>,
+}
+
+impl<'a, R> ScaledObservationModel<'a, R>
+where
+    R: RealField,
+{
+    /// Wrap `inner`, multiplying its observation noise covariance `R` by
+    /// `scale`.
+    pub fn new(inner: &'a dyn ObservationModel<R>, scale: R) -> Self {
+        let R = inner.R() * scale;
+        Self { inner, R }
+    }
+}
+
+impl<'a, R> ObservationModel<R> for ScaledObservationModel<'a, R>
+where
+    R: RealField,
+{
+    fn predict_observation(&self, state: &DVector<R>) -> DVector<R> {
+        self.inner.predict_observation(state)
+    }
+    fn H(&self) -> &DMatrix<R> {
+        self.inner.H()
+    }
+    fn HT(&self) -> Cow<'_, DMatrix<R>> {
+        self.inner.HT()
+    }
+    fn jacobian_at(&self, state: &DVector<R>) -> Cow<'_, DMatrix<R>> {
+        self.inner.jacobian_at(state)
+    }
+    fn R(&self) -> &DMatrix<R> {
+        &self.R
+    }
+    fn state_dim(&self) -> usize {
+        self.inner.state_dim()
+    }
+    fn obs_dim(&self) -> usize {
+        self.inner.obs_dim()
+    }
+}
+
+/// The best-scoring candidate found by [autotune]
+pub struct AutotuneResult<'a, R>
+where
+    R: RealField,
+{
+    /// The factor applied to the process covariance `Q`.
+    pub q_scale: R,
+    /// The factor applied to the observation noise covariance `R`.
+    pub r_scale: R,
+    /// The score of this candidate (lower is better).
+    pub score: R,
+    /// The transition model with scaled `Q`.
+    pub transition_model: ScaledTransitionModel<'a, R>,
+    /// The observation model with scaled `R`.
+    pub observation_model: ScaledObservationModel<'a, R>,
+}
+
+/// Search a grid of scale factors on `Q` and `R` for the best-scoring model
+///
+/// Every combination of `q_scales` and `r_scales` is run through the filter
+/// over `observations`, starting at `initial_estimate`, and scored according
+/// to `criterion`. Candidates for which the filter fails are skipped. To
+/// perform a random search, pass randomly drawn scale factors.
+///
+/// If any observation has a NaN component, it is treated as missing.
+///
+/// # Panics
+///
+/// Panics if `q_scales` or `r_scales` is empty.
+pub fn autotune<'a, R>(
+    transition_model: &'a dyn TransitionModelLinearNoControl<R>,
+    observation_model: &'a dyn ObservationModel<R>,
+    initial_estimate: &StateAndCovariance<R>,
+    observations: &[DVector<R>],
+    q_scales: &[R],
+    r_scales: &[R],
+    criterion: AutotuneCriterion,
+) -> Result<AutotuneResult<'a, R>, Error>
+where
+    R: RealField,
+{
+    assert!(!q_scales.is_empty());
+    assert!(!r_scales.is_empty());
+
+    let mut best: Option<AutotuneResult<'a, R>> = None;
+    let mut last_error = None;
+    for q_scale in q_scales.iter() {
+        for r_scale in r_scales.iter() {
+            let transition = ScaledTransitionModel::new(transition_model, q_scale.clone());
+            let observation = ScaledObservationModel::new(observation_model, r_scale.clone());
+            let score = match score(
+                &transition,
+                &observation,
+                initial_estimate,
+                observations,
+                criterion,
+            ) {
+                Ok(score) => score,
+                Err(e) => {
+                    last_error = Some(e);
+                    continue;
+                }
+            };
+            if best.as_ref().is_none_or(|b| score < b.score) {
+                best = Some(AutotuneResult {
+                    q_scale: q_scale.clone(),
+                    r_scale: r_scale.clone(),
+                    score,
+                    transition_model: transition,
+                    observation_model: observation,
+                });
+            }
+        }
+    }
+    match (best, last_error) {
+        (Some(best), _) => Ok(best),
+        (None, Some(e)) => Err(e),
+        (None, None) => unreachable!(),
+    }
+}
+
+fn score<R>(
+    transition_model: &dyn TransitionModelLinearNoControl<R>,
+    observation_model: &dyn ObservationModel<R>,
+    initial_estimate: &StateAndCovariance<R>,
+    observations: &[DVector<R>],
+    criterion: AutotuneCriterion,
+) -> Result<R, Error>
+where
+    R: RealField,
+{
+    let mut previous_estimate = initial_estimate.clone();
+    let mut total = R::zero();
+    let mut count = 0usize;
+    for observation in observations.iter() {
+        let prior = transition_model.predict(&previous_estimate);
+        if observation.iter().any(|x| is_nan(x.clone())) {
+            previous_estimate = prior;
+            continue;
+        }
+        let innovation = observation_model.innovation(&prior, observation);
+        total += match criterion {
+            AutotuneCriterion::LogLikelihood => -innovation.log_likelihood()?,
+            AutotuneCriterion::NisConsistency => {
+                innovation.nis()? / na::convert(observation.nrows() as f64)
+            }
+        };
+        count += 1;
+        previous_estimate =
+            observation_model.update(&prior, observation, CovarianceUpdateMethod::JosephForm)?;
+    }
+    Ok(match criterion {
+        AutotuneCriterion::LogLikelihood => total,
+        AutotuneCriterion::NisConsistency => {
+            let mean = if count == 0 {
+                R::one()
+            } else {
+                total / na::convert(count as f64)
+            };
+            (mean - R::one()).abs()
+        }
+    })
+}
+
+#[test]
+fn test_autotune_recovers_observation_noise() {
+    use crate::test_util::{
+        initial_estimate, simulate_positions, ConstantVelocity, PositionObservation,
+    };
+
+    let observations = simulate_positions(500, 0.1, 0.1, 4.0, 1);
+    let transition = ConstantVelocity::new(0.1, 0.1);
+    let observation = PositionObservation::new(1.0);
+    let scales = [0.25, 0.5, 1.0, 2.0, 4.0, 8.0, 16.0];
+    for criterion in [
+        AutotuneCriterion::LogLikelihood,
+        AutotuneCriterion::NisConsistency,
+    ] {
+        let result = autotune(
+            &transition,
+            &observation,
+            &initial_estimate(),
+            &observations,
+            &[1.0],
+            &scales,
+            criterion,
+        )
+        .unwrap();
+        assert_eq!(result.r_scale, 4.0);
+        assert_eq!(result.observation_model.R()[(0, 0)], 4.0);
+    }
+}
+
+#[test]
+fn test_autotune_single_observation() {
+    use crate::test_util::{random_walk, scalars};
+
+    // From the prior N(0, 1), a random walk with Q = q and R = 2 r predicts
+    // the first observation with variance S = 1 + q + 2 r. Both criteria
+    // are best for an observation of 3 at S = 9, i.e. r = 3.5 for q = 1.
+    let (transition, observation) = random_walk();
+    let prior = StateAndCovariance::new(DVector::zeros(1), DMatrix::identity(1, 1));
+    let observations = scalars(&[3.0]);
+    let log_likelihood = autotune(
+        &transition,
+        &observation,
+        &prior,
+        &observations,
+        &[1.0],
+        &[1.0, 3.5, 8.0],
+        AutotuneCriterion::LogLikelihood,
+    )
+    .unwrap();
+    assert_eq!(log_likelihood.r_scale, 3.5);
+    let expected = 0.5 * (2.0 * core::f64::consts::PI * 9.0).ln() + 0.5;
+    approx::assert_relative_eq!(log_likelihood.score, expected, epsilon = 1e-12);
+    let nis = autotune(
+        &transition,
+        &observation,
+        &prior,
+        &observations,
+        &[1.0],
+        &[1.0, 3.5, 8.0],
+        AutotuneCriterion::NisConsistency,
+    )
+    .unwrap();
+    assert_eq!(nis.r_scale, 3.5);
+    approx::assert_relative_eq!(nis.score, 0.0, epsilon = 1e-12);
+
+    // A negative R scale makes S negative, so that candidate fails and is
+    // skipped; if every candidate fails, the error is returned.
+    let result = autotune(
+        &transition,
+        &observation,
+        &prior,
+        &observations,
+        &[1.0],
+        &[-10.0, 8.0],
+        AutotuneCriterion::LogLikelihood,
+    )
+    .unwrap();
+    assert_eq!(result.r_scale, 8.0);
+    assert!(autotune(
+        &transition,
+        &observation,
+        &prior,
+        &observations,
+        &[1.0],
+        &[-10.0],
+        AutotuneCriterion::LogLikelihood,
+    )
+    .is_err());
+}
+
+#[test]
+fn test_scaled_noise_coupling() {
+    use crate::test_util::ConstantVelocity;
+    use crate::LinearTransitionModel;
+
+    // Scaling Q = G Qc G^T scales the driving noise Qc and keeps G.
+    let F = ConstantVelocity::new(0.1, 1.0).F().clone();
+    let G = DMatrix::from_column_slice(2, 1, &[0.005, 0.1]);
+    let Qc = DMatrix::from_element(1, 1, 4.0);
+    let model = LinearTransitionModel::from_noise_coupling(F, G.clone(), Qc);
+    let scaled = ScaledTransitionModel::new(&model, 3.0);
+    let expected = DMatrix::from_element(1, 1, 12.0);
+    assert_eq!(scaled.noise_coupling(), Some((&G, &expected)));
+    approx::assert_relative_eq!(scaled.Q(), &(&G * &expected * G.transpose()));
+
+    let uncoupled = ConstantVelocity::new(0.1, 1.0);
+    assert!(ScaledTransitionModel::new(&uncoupled, 3.0)
+        .noise_coupling()
+        .is_none());
+}
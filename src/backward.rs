@@ -0,0 +1,196 @@
+//! Reverse-time filtering in information form
+
+#[cfg(feature = "std")]
+use alloc::vec::Vec;
+
+use na::{DMatrix, DVector, RealField};
+use nalgebra as na;
+
+use crate::information::linearized_contribution;
+#[cfg(feature = "std")]
+use crate::StateAndCovariance;
+use crate::{is_nan, Error, ErrorKind, InformationState, KalmanFilterNoControl};
+
+impl<'a, R> KalmanFilterNoControl<'a, R>
+where
+    R: RealField,
+{
+    /// Propagate the information about a state, from later observations,
+    /// back to the state of the previous step
+    ///
+    /// With `x_{k+1} = F x_k + w_k`, the information `(y, Y)` about
+    /// `x_{k+1}` gives `F^T (I + Y Q)^-1 (y, Y F)` about `x_k`. No inverse of
+    /// `Y` or `F` is needed, so this holds while the information is still
+    /// incomplete, e.g. from none at the end of the series. The
+    /// fading-memory factor is not applied: it scales the covariance of the
+    /// forward filter, which the backward pass does not know.
+    pub fn predict_backward(
+        &self,
+        information: &InformationState<R>,
+    ) -> Result<InformationState<R>, Error> {
+        let n = self.transition_model.state_dim();
+        let Y = information.information_matrix();
+        let lu = (DMatrix::<R>::identity(n, n) + Y * self.transition_model.Q()).lu();
+        let vector = lu
+            .solve(information.information_vector())
+            .ok_or_else(|| Error::from(ErrorKind::SingularMatrix))?;
+        let matrix = lu
+            .solve(&(Y * self.transition_model.F()))
+            .ok_or_else(|| Error::from(ErrorKind::SingularMatrix))?;
+        let FT = self.transition_model.FT();
+        Ok(InformationState::new(
+            &*FT * vector,
+            (&*FT * matrix).symmetric_part(),
+        ))
+    }
+
+    /// Add the information of an observation of the same step
+    ///
+    /// The contribution is `H^T R^-1 (z, H)`; for a linearized model, `H` is
+    /// taken at the zero state. If any component of the observation is NaN,
+    /// the information is returned unchanged.
+    pub fn update_backward(
+        &self,
+        information: &InformationState<R>,
+        observation: &DVector<R>,
+    ) -> Result<InformationState<R>, Error> {
+        if observation.iter().any(|x| is_nan(x.clone())) {
+            return Ok(information.clone());
+        }
+        let n = self.transition_model.state_dim();
+        let zero = DVector::zeros(n);
+        let residual = observation - self.observation_matrix.predict_observation(&zero);
+        let contribution = linearized_contribution(
+            self.observation_matrix.H(),
+            self.observation_matrix.R(),
+            &zero,
+            residual,
+        )?;
+        let mut updated = information.clone();
+        updated.add(&contribution);
+        Ok(updated)
+    }
+
+    /// Backward information filter, processing the observations from the
+    /// last to the first
+    ///
+    /// Element `k` of the result is the information about the state at
+    /// observation `k` from observations `k` onwards alone, starting from no
+    /// information after the last. Until the observations determine the
+    /// whole state, the information matrix is singular; once they do,
+    /// [InformationState::to_estimate] gives an estimate independent of any
+    /// prior, e.g. of the initial conditions from element 0. This is also
+    /// the backward pass of
+    /// [smooth_two_filter](struct.KalmanFilterNoControl.html#method.smooth_two_filter).
+    #[cfg(feature = "std")]
+    pub fn filter_backward(
+        &self,
+        observations: &[DVector<R>],
+    ) -> Result<Vec<InformationState<R>>, Error> {
+        let n = self.transition_model.state_dim();
+        let mut results: Vec<InformationState<R>> = Vec::with_capacity(observations.len());
+        let mut information = InformationState::new(DVector::zeros(n), DMatrix::zeros(n, n));
+        for (k, observation) in observations.iter().enumerate().rev() {
+            if k + 1 < observations.len() {
+                information = self
+                    .predict_backward(&information)
+                    .map_err(|e| e.with_step(k))?;
+            }
+            information = self
+                .update_backward(&information, observation)
+                .map_err(|e| e.with_step(k))?;
+            results.push(information.clone());
+        }
+        results.reverse();
+        Ok(results)
+    }
+
+    /// Two-filter smoother, combining the forward Kalman filter with the
+    /// backward information filter
+    ///
+    /// The smoothed estimate at step `k` combines the forward filtered
+    /// estimate with the backward information from the observations after
+    /// `k`, as `P_s = (I + P Y)^-1 P` and `x_s = (I + P Y)^-1 (x + P y)`.
+    /// This gives the same estimates as the RTS smoother
+    /// [`smooth`](struct.KalmanFilterNoControl.html#method.smooth), without
+    /// inverting the predicted covariances, except with a fading-memory
+    /// factor, which the backward pass does not apply.
+    ///
+    /// If any observation has a NaN component, it is treated as missing.
+    #[cfg(feature = "std")]
+    pub fn smooth_two_filter(
+        &self,
+        initial_estimate: &StateAndCovariance<R>,
+        observations: &[DVector<R>],
+    ) -> Result<Vec<StateAndCovariance<R>>, Error> {
+        let forward = self.filter(initial_estimate, observations)?;
+        let backward = self.filter_backward(observations)?;
+        let n = self.transition_model.state_dim();
+        let identity = DMatrix::<R>::identity(n, n);
+        let mut smoothed = Vec::with_capacity(forward.len());
+        for (k, filtered) in forward.iter().enumerate() {
+            // The information from the observations after k alone.
+            let later = match backward.get(k + 1) {
+                Some(information) => self
+                    .predict_backward(information)
+                    .map_err(|e| e.with_step(k))?,
+                None => InformationState::new(DVector::zeros(n), DMatrix::zeros(n, n)),
+            };
+            let P = filtered.covariance();
+            let lu = (&identity + P * later.information_matrix()).lu();
+            let state = lu
+                .solve(&(filtered.state() + P * later.information_vector()))
+                .ok_or_else(|| Error::from(ErrorKind::SingularMatrix).with_step(k))?;
+            let covariance = lu
+                .solve(P)
+                .ok_or_else(|| Error::from(ErrorKind::SingularMatrix).with_step(k))?;
+            smoothed.push(StateAndCovariance::new(state, covariance.symmetric_part()));
+        }
+        Ok(smoothed)
+    }
+}
+
+#[test]
+fn test_backward_filter() {
+    use crate::test_util::{
+        initial_estimate, simulate_positions, ConstantVelocity, PositionObservation,
+    };
+
+    let transition = ConstantVelocity::new(0.1, 1.0);
+    let observation = PositionObservation::new(0.5);
+    let kf = KalmanFilterNoControl::new(&transition, &observation);
+    let mut observations = simulate_positions(20, 0.1, 1.0, 0.5, 31);
+    observations[7][0] = f64::NAN;
+
+    let rts = kf.smooth(&initial_estimate(), &observations).unwrap();
+    let two_filter = kf
+        .smooth_two_filter(&initial_estimate(), &observations)
+        .unwrap();
+    approx::assert_relative_eq!(two_filter.as_slice(), rts.as_slice(), epsilon = 1e-10);
+
+    // With no process noise, the backward estimate of the initial
+    // conditions is the least squares fit of a line to the observations.
+    let still = ConstantVelocity::new(0.1, 0.0);
+    let kf = KalmanFilterNoControl::new(&still, &observation);
+    let backward = kf.filter_backward(&observations).unwrap();
+    assert!(backward[19].to_estimate().is_err());
+    let start = backward[0].to_estimate().unwrap();
+    let used: Vec<usize> = (0..20).filter(|&k| k != 7).collect();
+    let A = DMatrix::from_fn(used.len(), 2, |i, j| {
+        if j == 0 {
+            1.0
+        } else {
+            0.1 * used[i] as f64
+        }
+    });
+    let b = DVector::from_iterator(used.len(), used.iter().map(|&k| observations[k][0]));
+    let AT = A.transpose();
+    let normal = &AT * &A;
+    let fit = normal.clone().lu().solve(&(&AT * b)).unwrap();
+    approx::assert_relative_eq!(start.state(), &fit, epsilon = 1e-10);
+    approx::assert_relative_eq!(
+        start.covariance(),
+        &(normal.try_inverse().unwrap() * 0.5),
+        epsilon = 1e-10
+    );
+}
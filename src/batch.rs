@@ -0,0 +1,313 @@
+//! Maximum a posteriori (MAP) trajectory estimation by batch least squares
+
+use na::{DMatrix, DVector, RealField};
+use nalgebra as na;
+
+use crate::{
+    is_nan, Error, ErrorKind, ObservationModel, StateAndCovariance, TransitionModelLinearNoControl,
+};
+
+/// The loss applied to each whitened observation residual
+#[derive(Debug, Clone, PartialEq)]
+pub enum RobustLoss<R>
+where
+    R: RealField,
+{
+    /// Ordinary least squares. With this loss, the MAP trajectory equals the
+    /// RTS smoothed trajectory.
+    Quadratic,
+    /// Huber loss, quadratic up to the given number of standard deviations
+    /// and linear beyond.
+    Huber(R),
+    /// Cauchy loss with the given scale in standard deviations, which
+    /// strongly downweights outliers.
+    Cauchy(R),
+}
+
+impl<R> RobustLoss<R>
+where
+    R: RealField,
+{
+    /// The weight of an observation whose whitened residual has norm `r`, for
+    /// iteratively reweighted least squares.
+    fn weight(&self, r: R) -> R {
+        match self {
+            RobustLoss::Quadratic => R::one(),
+            RobustLoss::Huber(k) => {
+                if r <= *k {
+                    R::one()
+                } else {
+                    k.clone() / r
+                }
+            }
+            RobustLoss::Cauchy(c) => {
+                let scaled = r / c.clone();
+                R::one() / (R::one() + scaled.clone() * scaled)
+            }
+        }
+    }
+}
+
+/// Batch MAP estimator of a whole trajectory
+///
+/// The initial estimate, every process step and every observation are
+/// factors of a least-squares problem in all of the states at once, which is
+/// solved through its normal equations. Unlike the recursive filter and
+/// smoother, this allows robust losses, which are handled by iteratively
+/// reweighted least squares. The normal equations are block tridiagonal but
+/// are solved densely, so this is intended for moderate trajectory lengths.
+pub struct BatchLeastSquares<'a, R>
+where
+    R: RealField,
+{
+    transition_model: &'a dyn TransitionModelLinearNoControl<R>,
+    observation_model: &'a dyn ObservationModel<R>,
+    loss: RobustLoss<R>,
+    max_iterations: usize,
+    tolerance: R,
+}
+
+impl<'a, R> BatchLeastSquares<'a, R>
+where
+    R: RealField,
+{
+    /// Create a new estimator with quadratic loss.
+    pub fn new(
+        transition_model: &'a dyn TransitionModelLinearNoControl<R>,
+        observation_model: &'a dyn ObservationModel<R>,
+    ) -> Self {
+        Self {
+            transition_model,
+            observation_model,
+            loss: RobustLoss::Quadratic,
+            max_iterations: 50,
+            tolerance: na::convert(1e-9),
+        }
+    }
+
+    /// Set the loss applied to observation residuals.
+    pub fn with_loss(mut self, loss: RobustLoss<R>) -> Self {
+        self.loss = loss;
+        self
+    }
+
+    /// Set the maximum number of reweighting iterations and the convergence
+    /// tolerance on the largest change of any state component.
+    pub fn with_iterations(mut self, max_iterations: usize, tolerance: R) -> Self {
+        self.max_iterations = max_iterations;
+        self.tolerance = tolerance;
+        self
+    }
+
+    /// Estimate the states at the time of each observation
+    ///
+    /// The covariances are the diagonal blocks of the inverse of the
+    /// information matrix, using the final observation weights. If any
+    /// observation has a NaN component, it is treated as missing.
+    pub fn solve(
+        &self,
+        initial_estimate: &StateAndCovariance<R>,
+        observations: &[DVector<R>],
+    ) -> Result<Vec<StateAndCovariance<R>>, Error> {
+        self.solve_with_prior(Some(initial_estimate), observations)
+    }
+
+    /// Estimate the state at the time of the last observation from the
+    /// observations alone, to initialize a filter
+    ///
+    /// The observations are fitted by weighted least squares against the
+    /// model, without any prior, so no initial state or covariance has to be
+    /// invented. The result is the estimate of a filter with a diffuse
+    /// initial covariance after the given observations, from which the
+    /// filter can continue with the next observation. There must be enough
+    /// observations to determine the state: an [ErrorKind::SingularMatrix]
+    /// error is returned otherwise.
+    pub fn initialize(&self, observations: &[DVector<R>]) -> Result<StateAndCovariance<R>, Error> {
+        if observations.is_empty() {
+            return Err(ErrorKind::SingularMatrix.into());
+        }
+        let mut estimates = self.solve_with_prior(None, observations)?;
+        Ok(estimates.pop().unwrap())
+    }
+
+    /// Solve with an optional prior on the state before the first
+    /// observation. Without one, there is no state before the first
+    /// observation.
+    fn solve_with_prior(
+        &self,
+        initial_estimate: Option<&StateAndCovariance<R>>,
+        observations: &[DVector<R>],
+    ) -> Result<Vec<StateAndCovariance<R>>, Error> {
+        let n = self.transition_model.state_dim();
+        let steps = observations.len();
+        let offset = usize::from(initial_estimate.is_some());
+        let dim = (steps + offset) * n;
+        let F = self.transition_model.F();
+        let H = self.observation_model.H();
+        let HT = &*self.observation_model.HT();
+
+        let invert = |m: &DMatrix<R>| match na::linalg::Cholesky::new(m.clone()) {
+            Some(chol) => Ok(chol.inverse()),
+            None => Err(Error::from(ErrorKind::CovarianceNotPositiveSemiDefinite)),
+        };
+        let Q_inv = invert(self.transition_model.Q())?;
+        let R_inv = invert(self.observation_model.R())?;
+        let FT_Q_inv = F.transpose() * &Q_inv;
+        let Q_inv_F = FT_Q_inv.transpose();
+        let HT_R_inv_H = HT * &R_inv * H;
+
+        // The prior and process factors do not depend on the weights.
+        let mut prior_information = DMatrix::zeros(dim, dim);
+        let mut prior_vector = DVector::zeros(dim);
+        if let Some(initial_estimate) = initial_estimate {
+            let P0_inv = invert(initial_estimate.covariance())?;
+            prior_information
+                .slice_mut((0, 0), (n, n))
+                .copy_from(&P0_inv);
+            prior_vector
+                .rows_mut(0, n)
+                .copy_from(&(&P0_inv * initial_estimate.state()));
+        }
+        for k in 1..steps + offset {
+            let (a, b) = ((k - 1) * n, k * n);
+            let mut block = prior_information.slice_mut((b, b), (n, n));
+            block += &Q_inv;
+            let mut block = prior_information.slice_mut((a, a), (n, n));
+            block += &FT_Q_inv * F;
+            let mut block = prior_information.slice_mut((b, a), (n, n));
+            block -= &Q_inv_F;
+            let mut block = prior_information.slice_mut((a, b), (n, n));
+            block -= &FT_Q_inv;
+        }
+
+        let mut weights = DVector::from_element(steps, R::one());
+        let mut solution: Option<DVector<R>> = None;
+        for _ in 0..self.max_iterations.max(1) {
+            let mut information = prior_information.clone();
+            let mut vector = prior_vector.clone();
+            for (k, observation) in observations.iter().enumerate() {
+                if observation.iter().any(|x| is_nan(x.clone())) {
+                    continue;
+                }
+                let b = (k + offset) * n;
+                let mut block = information.slice_mut((b, b), (n, n));
+                block += &HT_R_inv_H * weights[k].clone();
+                let mut rows = vector.rows_mut(b, n);
+                rows += HT * (&R_inv * observation) * weights[k].clone();
+            }
+            if initial_estimate.is_none() {
+                // Without a prior, the information matrix is singular if the
+                // observations do not determine the states, which round-off
+                // can hide from the factorization.
+                let tolerance = information.amax() * R::default_epsilon() * na::convert(dim as f64);
+                if information.rank(tolerance) < dim {
+                    return Err(ErrorKind::SingularMatrix.into());
+                }
+            }
+            let chol = match na::linalg::Cholesky::new(information) {
+                Some(v) => v,
+                None => return Err(ErrorKind::SingularMatrix.into()),
+            };
+            let x = chol.solve(&vector);
+
+            for (k, observation) in observations.iter().enumerate() {
+                if observation.iter().any(|x| is_nan(x.clone())) {
+                    continue;
+                }
+                let state = x.rows((k + offset) * n, n).into_owned();
+                let residual = observation - self.observation_model.predict_observation(&state);
+                let r = residual.dot(&(&R_inv * &residual)).sqrt();
+                weights[k] = self.loss.weight(r);
+            }
+
+            let converged = match &solution {
+                Some(previous) => (&x - previous).amax() <= self.tolerance.clone(),
+                None => self.loss == RobustLoss::Quadratic,
+            };
+            if converged {
+                return Ok(self.extract(&chol, &x, n, offset, steps));
+            }
+            solution = Some(x);
+        }
+        Err(ErrorKind::NotConverged.into())
+    }
+
+    fn extract(
+        &self,
+        chol: &na::linalg::Cholesky<R, na::Dynamic>,
+        x: &DVector<R>,
+        n: usize,
+        offset: usize,
+        steps: usize,
+    ) -> Vec<StateAndCovariance<R>> {
+        let covariance = chol.inverse();
+        (offset..steps + offset)
+            .map(|k| {
+                StateAndCovariance::new(
+                    x.rows(k * n, n).into_owned(),
+                    covariance.slice((k * n, k * n), (n, n)).into_owned(),
+                )
+            })
+            .collect()
+    }
+}
+
+#[test]
+fn test_batch_matches_smoother() {
+    use crate::test_util::{
+        initial_estimate, simulate_positions, ConstantVelocity, PositionObservation,
+    };
+    use crate::KalmanFilterNoControl;
+
+    let transition = ConstantVelocity::new(0.1, 1.0);
+    let observation = PositionObservation::new(0.5);
+    let mut observations = simulate_positions(20, 0.1, 1.0, 0.5, 6);
+    observations[7][0] = f64::NAN;
+    let smoothed = KalmanFilterNoControl::new(&transition, &observation)
+        .smooth(&initial_estimate(), &observations)
+        .unwrap();
+    let batch = BatchLeastSquares::new(&transition, &observation)
+        .solve(&initial_estimate(), &observations)
+        .unwrap();
+    for (s, b) in smoothed.iter().zip(batch.iter()) {
+        approx::assert_relative_eq!(s.state(), b.state(), epsilon = 1e-9);
+        approx::assert_relative_eq!(s.covariance(), b.covariance(), epsilon = 1e-9);
+    }
+
+    // A gross outlier has much less influence with a robust loss.
+    observations[10][0] += 100.0;
+    let quadratic = BatchLeastSquares::new(&transition, &observation)
+        .solve(&initial_estimate(), &observations)
+        .unwrap();
+    let huber = BatchLeastSquares::new(&transition, &observation)
+        .with_loss(RobustLoss::Huber(1.5))
+        .solve(&initial_estimate(), &observations)
+        .unwrap();
+    let shift = |estimates: &[StateAndCovariance<f64>]| {
+        (estimates[10].state()[0] - smoothed[10].state()[0]).abs()
+    };
+    assert!(shift(&huber) < 0.1 * shift(&quadratic));
+}
+
+#[test]
+fn test_batch_initialization() {
+    use crate::test_util::{simulate_positions, ConstantVelocity, PositionObservation};
+    use crate::KalmanFilterNoControl;
+
+    let transition = ConstantVelocity::new(0.1, 1.0);
+    let observation = PositionObservation::new(0.5);
+    let observations = simulate_positions(8, 0.1, 1.0, 0.5, 17);
+    let batch = BatchLeastSquares::new(&transition, &observation);
+    let initial = batch.initialize(&observations).unwrap();
+
+    // The same as filtering from a nearly diffuse prior.
+    let diffuse = StateAndCovariance::new(DVector::zeros(2), DMatrix::identity(2, 2) * 1e8);
+    let filtered = KalmanFilterNoControl::new(&transition, &observation)
+        .filter(&diffuse, &observations)
+        .unwrap();
+    approx::assert_relative_eq!(&initial, &filtered[7], epsilon = 1e-5);
+
+    // One position cannot determine the velocity.
+    assert!(batch.initialize(&observations[..1]).is_err());
+    assert!(batch.initialize(&[]).is_err());
+}
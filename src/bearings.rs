@@ -0,0 +1,327 @@
+//! Bearings-only target motion analysis
+//!
+//! A passive sensor measures only the direction to a target moving at
+//! constant velocity in the plane. The range is unobservable until the
+//! observer manoeuvres, and an extended Kalman filter with a Cartesian state
+//! then tends to become overconfident about the poorly known range and
+//! diverge. The classic remedy is the modified polar coordinate (MPC) state
+//! of Aidala and Hammel,
+//!
+//! `y = [β, β̇, ṙ/r, 1/r]`,
+//!
+//! in which the bearing is observed linearly and the unobservable inverse
+//! range is decoupled from the observable components until the observer
+//! manoeuvres.
+//!
+//! Bearings are measured anticlockwise from the x axis, in radians. The
+//! Cartesian target state is `[x, y, vx, vy]`.
+
+use alloc::borrow::Cow;
+
+use na::{DMatrix, DVector, RealField, Vector2};
+use nalgebra as na;
+
+use crate::{
+    numerical_jacobian, LinearObservationModel, NonlinearTransitionModel, ObservationModel,
+};
+
+/// The bearing observation of a target with Cartesian state from an observer
+/// at a known position
+///
+/// Updates linearize the bearing about the prior through
+/// [jacobian_at](ObservationModel::jacobian_at), as in an
+/// [ExtendedKalmanFilter](crate::ExtendedKalmanFilter). `H` is the
+/// linearization at the nominal state given to [new](Self::new), from the
+/// initial observer position. For sigma-point filters, use the observation
+/// function [bearing](Self::bearing) directly.
+pub struct BearingObservationModel<R>
+where
+    R: RealField,
+{
+    observer: Vector2<R>,
+    H: DMatrix<R>,
+    R: DMatrix<R>,
+}
+
+impl<R> BearingObservationModel<R>
+where
+    R: RealField,
+{
+    /// Create a new model for an observer at `observer`, with bearing
+    /// standard deviation `sigma`, with `H` linearized at the nominal
+    /// `state`.
+    pub fn new(observer: Vector2<R>, sigma: R, state: &DVector<R>) -> Self {
+        let H = bearing_jacobian(&observer, state);
+        Self {
+            observer,
+            H,
+            R: DMatrix::from_element(1, 1, sigma.clone() * sigma),
+        }
+    }
+
+    /// Move the observer.
+    pub fn set_observer(&mut self, observer: Vector2<R>) {
+        self.observer = observer;
+    }
+
+    /// The bearing from the observer to the target, `h(x)`.
+    pub fn bearing(&self, state: &DVector<R>) -> R {
+        let dx = state[0].clone() - self.observer[0].clone();
+        let dy = state[1].clone() - self.observer[1].clone();
+        dy.atan2(dx)
+    }
+
+    /// Shift a measured bearing by a multiple of 2π to within π of the
+    /// bearing of `state`, so that the residual does not jump across the
+    /// branch cut of the angle.
+    pub fn wrap(&self, observation: &DVector<R>, state: &DVector<R>) -> DVector<R> {
+        DVector::from_element(1, wrap_near(observation[0].clone(), self.bearing(state)))
+    }
+}
+
+impl<R> ObservationModel<R> for BearingObservationModel<R>
+where
+    R: RealField,
+{
+    fn predict_observation(&self, state: &DVector<R>) -> DVector<R> {
+        DVector::from_element(1, self.bearing(state))
+    }
+    fn H(&self) -> &DMatrix<R> {
+        &self.H
+    }
+    fn jacobian_at(&self, state: &DVector<R>) -> Cow<'_, DMatrix<R>> {
+        Cow::Owned(bearing_jacobian(&self.observer, state))
+    }
+    fn R(&self) -> &DMatrix<R> {
+        &self.R
+    }
+    fn state_dim(&self) -> usize {
+        4
+    }
+    fn obs_dim(&self) -> usize {
+        1
+    }
+}
+
+/// The Jacobian of the bearing from `observer` with respect to the
+/// Cartesian state.
+fn bearing_jacobian<R: RealField>(observer: &Vector2<R>, state: &DVector<R>) -> DMatrix<R> {
+    let dx = state[0].clone() - observer[0].clone();
+    let dy = state[1].clone() - observer[1].clone();
+    let r2 = dx.clone() * dx.clone() + dy.clone() * dy.clone();
+    DMatrix::from_row_slice(1, 4, &[-dy / r2.clone(), dx / r2, R::zero(), R::zero()])
+}
+
+/// The bearing observation of a modified polar coordinate state, which is
+/// linear, with standard deviation `sigma`
+///
+/// As for [BearingObservationModel], measured bearings should be shifted to
+/// within π of the predicted bearing before the update.
+pub fn modified_polar_bearing_observation<R>(sigma: R) -> LinearObservationModel<R>
+where
+    R: RealField,
+{
+    LinearObservationModel::from_matrices(
+        DMatrix::from_row_slice(1, 4, &[R::one(), R::zero(), R::zero(), R::zero()]),
+        DMatrix::from_element(1, 1, sigma.clone() * sigma),
+    )
+}
+
+/// Convert a Cartesian state of the target relative to the observer,
+/// `[x, y, vx, vy]`, to modified polar coordinates.
+pub fn cartesian_to_modified_polar<R>(relative: &DVector<R>) -> DVector<R>
+where
+    R: RealField,
+{
+    let (x, y, vx, vy) = (
+        relative[0].clone(),
+        relative[1].clone(),
+        relative[2].clone(),
+        relative[3].clone(),
+    );
+    let r2 = x.clone() * x.clone() + y.clone() * y.clone();
+    let r = r2.clone().sqrt();
+    DVector::from_column_slice(&[
+        y.clone().atan2(x.clone()),
+        (x.clone() * vy.clone() - y.clone() * vx.clone()) / r2.clone(),
+        (x * vx + y * vy) / r2,
+        R::one() / r,
+    ])
+}
+
+/// Convert a modified polar coordinate state to the Cartesian state of the
+/// target relative to the observer, `[x, y, vx, vy]`. The inverse range
+/// must be positive.
+pub fn modified_polar_to_cartesian<R>(mpc: &DVector<R>) -> DVector<R>
+where
+    R: RealField,
+{
+    let r = R::one() / mpc[3].clone();
+    let (sin, cos) = mpc[0].clone().sin_cos();
+    let range_rate = mpc[2].clone() * r.clone();
+    let tangential = mpc[1].clone() * r.clone();
+    DVector::from_column_slice(&[
+        r.clone() * cos.clone(),
+        r * sin.clone(),
+        range_rate.clone() * cos.clone() - tangential.clone() * sin.clone(),
+        range_rate * sin + tangential * cos,
+    ])
+}
+
+/// Constant-velocity target motion in modified polar coordinates
+///
+/// The target moves at constant velocity and the observer's motion over the
+/// interval is given by [set_observer_motion](Self::set_observer_motion).
+/// The transition is computed in Cartesian coordinates scaled by the inverse
+/// range, so it is defined even for an inverse range of zero. The Jacobian
+/// is computed by [numerical_jacobian].
+pub struct ModifiedPolarTransitionModel<R>
+where
+    R: RealField,
+{
+    dt: R,
+    displacement: Vector2<R>,
+    velocity_change: Vector2<R>,
+    Q: DMatrix<R>,
+    eps: R,
+}
+
+impl<R> ModifiedPolarTransitionModel<R>
+where
+    R: RealField,
+{
+    /// Create a new model with time step `dt`, process covariance `Q` in
+    /// modified polar coordinates and finite difference step `eps`, for an
+    /// observer moving at constant velocity.
+    pub fn new(dt: R, Q: DMatrix<R>, eps: R) -> Self {
+        Self {
+            dt,
+            displacement: Vector2::zeros(),
+            velocity_change: Vector2::zeros(),
+            Q,
+            eps,
+        }
+    }
+
+    /// Set the observer's motion over the next interval: its displacement
+    /// and its velocities at the start and end of the interval.
+    pub fn set_observer_motion(
+        &mut self,
+        displacement: Vector2<R>,
+        velocity_before: Vector2<R>,
+        velocity_after: Vector2<R>,
+    ) {
+        // Only the departure from constant velocity changes the relative
+        // motion.
+        self.displacement = displacement - &velocity_before * self.dt.clone();
+        self.velocity_change = velocity_after - velocity_before;
+    }
+
+    fn propagate_state(&self, mpc: &DVector<R>) -> DVector<R> {
+        let (sin, cos) = mpc[0].clone().sin_cos();
+        let inverse_range = mpc[3].clone();
+        // Relative position and velocity divided by the range.
+        let u = Vector2::new(cos.clone(), sin.clone());
+        let w = Vector2::new(
+            mpc[2].clone() * cos.clone() - mpc[1].clone() * sin.clone(),
+            mpc[2].clone() * sin + mpc[1].clone() * cos,
+        );
+        let p = u + &w * self.dt.clone() - &self.displacement * inverse_range.clone();
+        let q = w - &self.velocity_change * inverse_range.clone();
+        let p2 = p.norm_squared();
+        DVector::from_column_slice(&[
+            p[1].clone().atan2(p[0].clone()),
+            (p[0].clone() * q[1].clone() - p[1].clone() * q[0].clone()) / p2.clone(),
+            p.dot(&q) / p2,
+            inverse_range / p.norm(),
+        ])
+    }
+}
+
+impl<R> NonlinearTransitionModel<R> for ModifiedPolarTransitionModel<R>
+where
+    R: RealField,
+{
+    fn f(&self, state: &DVector<R>) -> DVector<R> {
+        self.propagate_state(state)
+    }
+    fn jacobian_at(&self, state: &DVector<R>) -> DMatrix<R> {
+        numerical_jacobian(|x| self.propagate_state(x), state, self.eps.clone())
+    }
+    fn Q(&self) -> &DMatrix<R> {
+        &self.Q
+    }
+}
+
+/// Shift `angle` by a multiple of 2π to within π of `reference`.
+pub(crate) fn wrap_near<R: RealField>(angle: R, reference: R) -> R {
+    let two_pi = R::two_pi();
+    let turns = ((angle.clone() - reference) / two_pi.clone()).round();
+    angle - turns * two_pi
+}
+
+#[test]
+fn test_bearings_only() {
+    let target = DVector::from_vec(vec![3.0, 4.0, -1.0, 0.5]);
+    let observer = Vector2::new(1.0, 1.0);
+    let mut model = BearingObservationModel::new(observer, 0.01, &target);
+    let numerical = numerical_jacobian(|x| model.predict_observation(x), &target, 1e-6);
+    approx::assert_relative_eq!(model.H(), &numerical, epsilon = 1e-8);
+    let observation =
+        DVector::from_element(1, model.bearing(&target) + 2.0 * core::f64::consts::PI);
+    approx::assert_relative_eq!(
+        model.wrap(&observation, &target)[0],
+        model.bearing(&target),
+        epsilon = 1e-12
+    );
+
+    // The MPC transition agrees with constant-velocity motion in Cartesian
+    // coordinates, including an observer manoeuvre.
+    let dt = 2.0;
+    let mut transition = ModifiedPolarTransitionModel::new(dt, DMatrix::zeros(4, 4), 1e-6);
+    let (velocity_before, velocity_after) = (Vector2::new(1.0, 0.0), Vector2::new(0.0, 1.0));
+    let displacement = Vector2::new(1.0, 1.0);
+    transition.set_observer_motion(displacement, velocity_before, velocity_after);
+    let relative = DVector::from_vec(vec![
+        target[0] - observer[0],
+        target[1] - observer[1],
+        target[2] - velocity_before[0],
+        target[3] - velocity_before[1],
+    ]);
+    let mpc = cartesian_to_modified_polar(&relative);
+    approx::assert_relative_eq!(modified_polar_to_cartesian(&mpc), relative, epsilon = 1e-12);
+    let expected = DVector::from_vec(vec![
+        target[0] + target[2] * dt - (observer[0] + displacement[0]),
+        target[1] + target[3] * dt - (observer[1] + displacement[1]),
+        target[2] - velocity_after[0],
+        target[3] - velocity_after[1],
+    ]);
+    approx::assert_relative_eq!(
+        modified_polar_to_cartesian(&transition.f(&mpc)),
+        expected,
+        epsilon = 1e-12
+    );
+
+    // The bearing of an MPC state is observed directly.
+    let observation = modified_polar_bearing_observation(0.01);
+    approx::assert_relative_eq!(
+        observation.predict_observation(&mpc)[0],
+        model.bearing(&target)
+    );
+
+    // After the observer moves, the innovation is linearized about the prior
+    // from the new position, not about the nominal state.
+    model.set_observer(Vector2::new(-2.0, 0.0));
+    let prior = crate::StateAndCovariance::new(
+        DVector::from_vec(vec![2.0, 3.0, 0.0, 1.0]),
+        DMatrix::identity(4, 4),
+    );
+    let H = numerical_jacobian(|x| model.predict_observation(x), prior.state(), 1e-6);
+    approx::assert_relative_eq!(&*model.jacobian_at(prior.state()), &H, epsilon = 1e-8);
+    let innovation = model.innovation(&prior, &DVector::from_element(1, 0.5));
+    approx::assert_relative_eq!(
+        innovation.covariance(),
+        &(&H * H.transpose() + model.R()),
+        epsilon = 1e-8
+    );
+}
@@ -0,0 +1,172 @@
+//! Estimates whose covariance is block diagonal, e.g. independent targets
+
+use na::{DMatrix, DVector, RealField};
+use nalgebra as na;
+
+use crate::{
+    is_nan, CovarianceUpdateMethod, Error, ObservationModel, StateAndCovariance,
+    TransitionModelLinearNoControl,
+};
+
+/// An estimate of a state made of independent blocks
+///
+/// The full state is the concatenation of the block states, and its
+/// covariance is block diagonal with the block covariances on the diagonal.
+/// Only the blocks are stored, so operations cost the sum over blocks rather
+/// than the cube of the full dimension.
+#[derive(Debug, Clone)]
+pub struct BlockDiagonalEstimate<R>
+where
+    R: RealField,
+{
+    blocks: Vec<StateAndCovariance<R>>,
+}
+
+impl<R> BlockDiagonalEstimate<R>
+where
+    R: RealField,
+{
+    /// Create a new estimate from its blocks.
+    pub fn new(blocks: Vec<StateAndCovariance<R>>) -> Self {
+        Self { blocks }
+    }
+
+    /// Get a reference to the blocks.
+    pub fn blocks(&self) -> &[StateAndCovariance<R>] {
+        &self.blocks
+    }
+
+    /// Get a reference to one block.
+    pub fn block(&self, index: usize) -> &StateAndCovariance<R> {
+        &self.blocks[index]
+    }
+
+    /// Get a mutable reference to one block.
+    pub fn block_mut(&mut self, index: usize) -> &mut StateAndCovariance<R> {
+        &mut self.blocks[index]
+    }
+
+    /// The dimension of the full state.
+    pub fn state_dim(&self) -> usize {
+        self.blocks.iter().map(|b| b.state().nrows()).sum()
+    }
+
+    /// Assemble the full estimate with a dense covariance matrix.
+    pub fn to_dense(&self) -> StateAndCovariance<R> {
+        let n = self.state_dim();
+        let mut state = DVector::zeros(n);
+        let mut covariance = DMatrix::zeros(n, n);
+        let mut offset = 0;
+        for block in self.blocks.iter() {
+            let m = block.state().nrows();
+            state.rows_mut(offset, m).copy_from(block.state());
+            covariance
+                .slice_mut((offset, offset), (m, m))
+                .copy_from(block.covariance());
+            offset += m;
+        }
+        StateAndCovariance::new(state, covariance)
+    }
+}
+
+/// A Kalman filter for a state made of independent blocks
+///
+/// Each block has its own transition model, so the full transition matrix
+/// and process covariance are block diagonal. Observations which depend on a
+/// single block keep the covariance block diagonal and are applied with
+/// [update_block](Self::update_block). An observation coupling several blocks
+/// would correlate them; use [BlockDiagonalEstimate::to_dense] and the dense
+/// filter in that case.
+pub struct BlockDiagonalFilter<'a, R>
+where
+    R: RealField,
+{
+    transition_models: Vec<&'a dyn TransitionModelLinearNoControl<R>>,
+}
+
+impl<'a, R> BlockDiagonalFilter<'a, R>
+where
+    R: RealField,
+{
+    /// Create a new filter with one transition model per block.
+    pub fn new(transition_models: Vec<&'a dyn TransitionModelLinearNoControl<R>>) -> Self {
+        Self { transition_models }
+    }
+
+    /// Predict each block forward by one step.
+    pub fn predict(&self, estimate: &BlockDiagonalEstimate<R>) -> BlockDiagonalEstimate<R> {
+        assert_eq!(estimate.blocks.len(), self.transition_models.len());
+        let blocks = self
+            .transition_models
+            .iter()
+            .zip(estimate.blocks.iter())
+            .map(|(model, block)| model.predict(block))
+            .collect();
+        BlockDiagonalEstimate::new(blocks)
+    }
+
+    /// Update one block with an observation depending only on that block
+    ///
+    /// The other blocks are unchanged. If any component of the observation
+    /// is NaN (not a number), the estimate is returned unchanged.
+    pub fn update_block(
+        &self,
+        estimate: &BlockDiagonalEstimate<R>,
+        index: usize,
+        observation_model: &dyn ObservationModel<R>,
+        observation: &DVector<R>,
+        covariance_update_method: CovarianceUpdateMethod,
+    ) -> Result<BlockDiagonalEstimate<R>, Error> {
+        let mut result = estimate.clone();
+        if !observation.iter().any(|x| is_nan(x.clone())) {
+            result.blocks[index] = observation_model.update(
+                &estimate.blocks[index],
+                observation,
+                covariance_update_method,
+            )?;
+        }
+        Ok(result)
+    }
+}
+
+#[test]
+fn test_block_diagonal_matches_dense() {
+    use crate::test_util::{
+        initial_estimate, ConstantVelocity, MatrixObservation, PositionObservation,
+    };
+
+    let slow = ConstantVelocity::new(0.1, 1.0);
+    let fast = ConstantVelocity::new(0.1, 4.0);
+    let filter = BlockDiagonalFilter::new(vec![&slow, &fast]);
+    let estimate = BlockDiagonalEstimate::new(vec![initial_estimate(), initial_estimate()]);
+    let predicted = filter.predict(&estimate);
+    let observation = PositionObservation::new(0.5);
+    let z = DVector::from_element(1, 0.3);
+    let updated = filter
+        .update_block(
+            &predicted,
+            1,
+            &observation,
+            &z,
+            CovarianceUpdateMethod::JosephForm,
+        )
+        .unwrap();
+
+    // The same observation of the second block, applied to the dense state.
+    let H = DMatrix::from_row_slice(1, 4, &[0.0, 0.0, 1.0, 0.0]);
+    let dense_observation = MatrixObservation::new(H, DMatrix::from_element(1, 1, 0.5));
+    let dense = dense_observation
+        .update(
+            &predicted.to_dense(),
+            &z,
+            CovarianceUpdateMethod::JosephForm,
+        )
+        .unwrap();
+    let blocks = updated.to_dense();
+    approx::assert_relative_eq!(blocks.state(), dense.state(), epsilon = 1e-12);
+    approx::assert_relative_eq!(blocks.covariance(), dense.covariance(), epsilon = 1e-12);
+    approx::assert_relative_eq!(
+        updated.block(0).covariance(),
+        TransitionModelLinearNoControl::predict(&slow, &initial_estimate()).covariance()
+    );
+}
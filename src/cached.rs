@@ -0,0 +1,463 @@
+//! Models with cached transposes and a cached factorization of `R`
+
+use alloc::borrow::Cow;
+
+use na::{DMatrix, DVector, Dynamic, RealField};
+use nalgebra as na;
+
+use crate::{
+    CovarianceUpdateMethod, Error, ErrorKind, ObservationModel, StateAndCovariance,
+    TransitionModelLinearNoControl,
+};
+
+type Cholesky<R> = na::linalg::Cholesky<R, Dynamic>;
+
+fn cholesky<R: RealField>(matrix: &DMatrix<R>) -> Result<Cholesky<R>, Error> {
+    Cholesky::new(matrix.clone()).ok_or_else(|| ErrorKind::CovarianceNotPositiveSemiDefinite.into())
+}
+
+/// `log det` of a matrix given its Cholesky factorization.
+fn log_det<R: RealField>(chol: &Cholesky<R>) -> R {
+    let two: R = na::convert(2.0);
+    chol.l_dirty()
+        .diagonal()
+        .iter()
+        .fold(R::zero(), |acc, d| acc + d.clone().ln())
+        * two
+}
+
+/// An observation model with the Cholesky factorization of its constant
+/// observation noise covariance `R`
+///
+/// With `R` factored once, an observation can be processed in information
+/// form, factoring only matrices of the state dimension: the gain is
+/// `(P^-1 + H^T R^-1 H)^-1 H^T R^-1`, and the likelihood follows from the
+/// Woodbury identity and the matrix determinant lemma. This factors the
+/// prior covariance and the posterior information, two matrices of the state
+/// dimension, so when there are more observation components than state
+/// components, it is cheaper than factoring the innovation covariance
+/// `H P H^T + R`. As in the update of [ObservationModel], `H` is the
+/// [jacobian_at](ObservationModel::jacobian_at) the prior state.
+///
+/// [CachedObservationModel] provides this for any [ObservationModel].
+pub trait FactoredObservationModel<R>: ObservationModel<R>
+where
+    R: RealField,
+{
+    /// Get the Cholesky factorization of `R`.
+    fn R_cholesky(&self) -> &na::linalg::Cholesky<R, Dynamic>;
+
+    /// Given prior state and observation, estimate the posterior state in
+    /// information form
+    ///
+    /// This gives the same result as [update](ObservationModel::update)
+    /// with the same `covariance_method`, up to round-off. An
+    /// [ErrorKind::CovarianceNotPositiveSemiDefinite] error is returned if the
+    /// prior or posterior covariance is not positive definite.
+    fn update_information_form(
+        &self,
+        prior: &StateAndCovariance<R>,
+        observation: &DVector<R>,
+        covariance_method: CovarianceUpdateMethod,
+    ) -> Result<StateAndCovariance<R>, Error> {
+        let H = self.jacobian_at(prior.state());
+        let HT_R_inv = self.R_cholesky().solve(&H).transpose();
+        let HT_R_inv_H = &HT_R_inv * &*H;
+        let prior_information = cholesky(prior.covariance())?.inverse();
+        let information = prior_information + &HT_R_inv_H;
+        let posterior_covariance = cholesky(&information)?.inverse();
+        let residual = observation - self.predict_observation(prior.state());
+        let state = prior.state() + &posterior_covariance * (HT_R_inv * residual);
+
+        // With the gain K = P+ H^T R^-1, KH = P+ H^T R^-1 H and
+        // K R K^T = P+ H^T R^-1 H P+, so every form needs only matrices of the
+        // state dimension.
+        let kh = &posterior_covariance * &HT_R_inv_H;
+        let one_minus_kh = DMatrix::identity(kh.nrows(), kh.ncols()) - kh;
+        let covariance = match covariance_method {
+            CovarianceUpdateMethod::JosephForm => {
+                &one_minus_kh * prior.covariance() * one_minus_kh.transpose()
+                    + &posterior_covariance * HT_R_inv_H * &posterior_covariance
+            }
+            CovarianceUpdateMethod::OptimalKalman => one_minus_kh * prior.covariance(),
+            CovarianceUpdateMethod::OptimalKalmanForcedSymmetric => {
+                (one_minus_kh * prior.covariance()).symmetric_part()
+            }
+        };
+        Ok(StateAndCovariance::new(state, covariance))
+    }
+
+    /// Log of the Gaussian probability density of an observation given the
+    /// prior, in information form
+    ///
+    /// This gives the same result as the
+    /// [log_likelihood](crate::Innovation::log_likelihood) of the
+    /// [innovation](ObservationModel::innovation), up to round-off.
+    fn log_likelihood(
+        &self,
+        prior: &StateAndCovariance<R>,
+        observation: &DVector<R>,
+    ) -> Result<R, Error> {
+        let R_chol = self.R_cholesky();
+        let residual = observation - self.predict_observation(prior.state());
+        let R_inv_residual = R_chol.solve(&residual);
+        let H = self.jacobian_at(prior.state());
+        let HT_R_inv_residual = match &H {
+            Cow::Borrowed(_) => &*self.HT() * &R_inv_residual,
+            Cow::Owned(H) => H.transpose() * &R_inv_residual,
+        };
+        let HT_R_inv = R_chol.solve(&H).transpose();
+
+        let P_chol = cholesky(prior.covariance())?;
+        let information = P_chol.inverse() + HT_R_inv * &*H;
+        let information_chol = cholesky(&information)?;
+
+        // S^-1 = R^-1 - R^-1 H (P^-1 + H^T R^-1 H)^-1 H^T R^-1 and
+        // det S = det R det P det(P^-1 + H^T R^-1 H).
+        let nis = residual.dot(&R_inv_residual)
+            - HT_R_inv_residual.dot(&information_chol.solve(&HT_R_inv_residual));
+        let log_det_S = log_det(R_chol) + log_det(&P_chol) + log_det(&information_chol);
+        let half: R = na::convert(0.5);
+        let n: R = na::convert(residual.nrows() as f64);
+        Ok(-half * (n * R::two_pi().ln() + nis + log_det_S))
+    }
+}
+
+/// Wraps a [TransitionModelLinearNoControl], computing `F^T` once, on
+/// construction
+///
+/// The other methods are delegated to the wrapped model, so the wrapped
+/// model's `F` must not change afterward.
+pub struct CachedTransitionModel<'a, R>
+where
+    R: RealField,
+{
+    model: &'a dyn TransitionModelLinearNoControl<R>,
+    FT: DMatrix<R>,
+}
+
+impl<'a, R> CachedTransitionModel<'a, R>
+where
+    R: RealField,
+{
+    /// Wrap a transition model, computing the transpose of its `F`.
+    pub fn new(model: &'a dyn TransitionModelLinearNoControl<R>) -> Self {
+        let FT = model.F().transpose();
+        Self { model, FT }
+    }
+}
+
+impl<'a, R> TransitionModelLinearNoControl<R> for CachedTransitionModel<'a, R>
+where
+    R: RealField,
+{
+    fn state_dim(&self) -> usize {
+        self.model.state_dim()
+    }
+    fn F(&self) -> &DMatrix<R> {
+        self.model.F()
+    }
+    fn FT(&self) -> Cow<'_, DMatrix<R>> {
+        Cow::Borrowed(&self.FT)
+    }
+    fn Q(&self) -> &DMatrix<R> {
+        self.model.Q()
+    }
+    fn noise_coupling(&self) -> Option<(&DMatrix<R>, &DMatrix<R>)> {
+        self.model.noise_coupling()
+    }
+}
+
+/// Wraps an [ObservationModel], computing `H^T` and factoring `R` once, on
+/// construction
+///
+/// When there are more observation components than state components, the
+/// update and likelihood, e.g. in the steps of
+/// [KalmanFilterNoControl](crate::KalmanFilterNoControl), use the factor of
+/// `R` in information form, see [FactoredObservationModel]. Otherwise, or
+/// for an update with another covariance in place of `R` or with a prior
+/// covariance which cannot be factored, they fall back to the wrapped model.
+/// The other methods of [ObservationModel] are delegated to the wrapped model,
+/// so the wrapped model's `H` and `R` must not change afterward. A non-linear
+/// wrapped model is linearized at the prior by its
+/// [jacobian_at](ObservationModel::jacobian_at), and the cached `H^T` is only
+/// used where the wrapped model returns its `H`.
+pub struct CachedObservationModel<'a, R>
+where
+    R: RealField,
+{
+    model: &'a dyn ObservationModel<R>,
+    HT: DMatrix<R>,
+    R_cholesky: na::linalg::Cholesky<R, Dynamic>,
+}
+
+impl<'a, R> CachedObservationModel<'a, R>
+where
+    R: RealField,
+{
+    /// Wrap an observation model, factoring its `R`. An
+    /// [ErrorKind::CovarianceNotPositiveSemiDefinite] error is returned if
+    /// `R` is not positive definite.
+    pub fn new(model: &'a dyn ObservationModel<R>) -> Result<Self, Error> {
+        let R_cholesky = cholesky(model.R())?;
+        let HT = model.H().transpose();
+        Ok(Self {
+            model,
+            HT,
+            R_cholesky,
+        })
+    }
+
+    /// Whether the information form is cheaper than factoring the
+    /// innovation covariance.
+    fn prefers_information_form(&self) -> bool {
+        self.model.obs_dim() > self.model.state_dim()
+    }
+}
+
+impl<'a, R> ObservationModel<R> for CachedObservationModel<'a, R>
+where
+    R: RealField,
+{
+    fn predict_observation(&self, state: &DVector<R>) -> DVector<R> {
+        self.model.predict_observation(state)
+    }
+    fn H(&self) -> &DMatrix<R> {
+        self.model.H()
+    }
+    fn HT(&self) -> Cow<'_, DMatrix<R>> {
+        Cow::Borrowed(&self.HT)
+    }
+    fn jacobian_at(&self, state: &DVector<R>) -> Cow<'_, DMatrix<R>> {
+        self.model.jacobian_at(state)
+    }
+    fn R(&self) -> &DMatrix<R> {
+        self.model.R()
+    }
+    fn state_dim(&self) -> usize {
+        self.model.state_dim()
+    }
+    fn obs_dim(&self) -> usize {
+        self.model.obs_dim()
+    }
+
+    /// The likelihood in information form if there are more observation
+    /// components than state components, otherwise, or if the prior
+    /// covariance cannot be factored, that of the wrapped model.
+    fn observation_log_likelihood(
+        &self,
+        prior: &StateAndCovariance<R>,
+        observation: &DVector<R>,
+    ) -> Result<R, Error> {
+        if self.prefers_information_form() {
+            if let Ok(log_likelihood) =
+                FactoredObservationModel::log_likelihood(self, prior, observation)
+            {
+                return Ok(log_likelihood);
+            }
+        }
+        self.model.observation_log_likelihood(prior, observation)
+    }
+
+    /// The update in information form if there are more observation
+    /// components than state components and `observation_covariance` is
+    /// `R`, otherwise, or if the prior covariance cannot be factored, the
+    /// update of the wrapped model.
+    fn update_with_covariance(
+        &self,
+        prior: &StateAndCovariance<R>,
+        observation: &DVector<R>,
+        observation_covariance: &DMatrix<R>,
+        covariance_method: CovarianceUpdateMethod,
+    ) -> Result<StateAndCovariance<R>, Error> {
+        if self.prefers_information_form() && observation_covariance == self.model.R() {
+            if let Ok(posterior) =
+                self.update_information_form(prior, observation, covariance_method)
+            {
+                return Ok(posterior);
+            }
+        }
+        self.model.update_with_covariance(
+            prior,
+            observation,
+            observation_covariance,
+            covariance_method,
+        )
+    }
+}
+
+impl<'a, R> FactoredObservationModel<R> for CachedObservationModel<'a, R>
+where
+    R: RealField,
+{
+    fn R_cholesky(&self) -> &na::linalg::Cholesky<R, Dynamic> {
+        &self.R_cholesky
+    }
+}
+
+#[test]
+fn test_cached_observation_model() {
+    use crate::test_util::{initial_estimate, ConstantVelocity, MatrixObservation};
+    use crate::KalmanFilterNoControl;
+
+    // More observation components than state components.
+    let observation = MatrixObservation::new(
+        DMatrix::from_row_slice(3, 2, &[1.0, 0.0, 0.0, 1.0, 1.0, -1.0]),
+        DMatrix::from_row_slice(3, 3, &[0.5, 0.1, 0.0, 0.1, 0.4, 0.0, 0.0, 0.0, 0.2]),
+    );
+    let cached = CachedObservationModel::new(&observation).unwrap();
+    assert_eq!(*cached.HT(), observation.H().transpose());
+    let prior = initial_estimate();
+    let z = DVector::from_vec(vec![0.3, 0.9, -0.4]);
+
+    for method in [
+        CovarianceUpdateMethod::JosephForm,
+        CovarianceUpdateMethod::OptimalKalman,
+        CovarianceUpdateMethod::OptimalKalmanForcedSymmetric,
+    ] {
+        let expected = observation.update(&prior, &z, method).unwrap();
+        let actual = cached.update_information_form(&prior, &z, method).unwrap();
+        approx::assert_relative_eq!(actual, expected, epsilon = 1e-12);
+    }
+
+    let expected = observation.innovation(&prior, &z).log_likelihood().unwrap();
+    approx::assert_relative_eq!(
+        cached.log_likelihood(&prior, &z).unwrap(),
+        expected,
+        epsilon = 1e-12
+    );
+
+    let indefinite = MatrixObservation::new(
+        DMatrix::identity(2, 2),
+        DMatrix::from_row_slice(2, 2, &[1.0, 2.0, 2.0, 1.0]),
+    );
+    assert!(CachedObservationModel::new(&indefinite).is_err());
+
+    let transition = ConstantVelocity::new(0.1, 1.0);
+
+    // The filter uses the cached factor, and gives the same estimates and
+    // likelihood.
+    struct CountingObservation {
+        inner: MatrixObservation,
+        updates: core::cell::Cell<usize>,
+    }
+    impl ObservationModel<f64> for CountingObservation {
+        fn H(&self) -> &DMatrix<f64> {
+            self.inner.H()
+        }
+        fn R(&self) -> &DMatrix<f64> {
+            self.inner.R()
+        }
+        fn state_dim(&self) -> usize {
+            self.inner.state_dim()
+        }
+        fn obs_dim(&self) -> usize {
+            self.inner.obs_dim()
+        }
+        fn update_with_covariance(
+            &self,
+            prior: &StateAndCovariance<f64>,
+            observation: &DVector<f64>,
+            observation_covariance: &DMatrix<f64>,
+            covariance_method: CovarianceUpdateMethod,
+        ) -> Result<StateAndCovariance<f64>, Error> {
+            self.updates.set(self.updates.get() + 1);
+            self.inner.update_with_covariance(
+                prior,
+                observation,
+                observation_covariance,
+                covariance_method,
+            )
+        }
+    }
+    let counting = CountingObservation {
+        inner: observation,
+        updates: core::cell::Cell::new(0),
+    };
+    let cached = CachedObservationModel::new(&counting).unwrap();
+    let observations = vec![z.clone(); 5];
+    let plain_kf = KalmanFilterNoControl::new(&transition, &counting);
+    let cached_kf = KalmanFilterNoControl::new(&transition, &cached);
+    let expected = plain_kf.filter(&prior, &observations).unwrap();
+    assert_eq!(counting.updates.get(), 5);
+    let actual = cached_kf.filter(&prior, &observations).unwrap();
+    assert_eq!(counting.updates.get(), 5);
+    for (a, b) in actual.iter().zip(expected.iter()) {
+        approx::assert_relative_eq!(a, b, epsilon = 1e-10);
+    }
+    let expected = plain_kf.log_likelihood(&prior, &observations).unwrap();
+    assert_eq!(counting.updates.get(), 10);
+    let actual = cached_kf.log_likelihood(&prior, &observations).unwrap();
+    assert_eq!(counting.updates.get(), 10);
+    approx::assert_relative_eq!(actual, expected, epsilon = 1e-10);
+
+    // A prior covariance which cannot be inverted falls back to the update
+    // of the wrapped model.
+    let singular = StateAndCovariance::new(prior.state().clone(), DMatrix::zeros(2, 2));
+    let posterior = cached
+        .update(&singular, &z, CovarianceUpdateMethod::JosephForm)
+        .unwrap();
+    assert_eq!(counting.updates.get(), 11);
+    assert_eq!(posterior.covariance(), &DMatrix::zeros(2, 2));
+
+    // With fewer observation components than state components, the
+    // innovation covariance is the smaller matrix to factor, so the wrapped
+    // model's update is used.
+    let position = CountingObservation {
+        inner: MatrixObservation::new(
+            DMatrix::from_row_slice(1, 2, &[1.0, 0.0]),
+            DMatrix::from_element(1, 1, 0.5),
+        ),
+        updates: core::cell::Cell::new(0),
+    };
+    let cached = CachedObservationModel::new(&position).unwrap();
+    let z = DVector::from_element(1, 0.3);
+    let expected = position
+        .inner
+        .update(&prior, &z, CovarianceUpdateMethod::JosephForm)
+        .unwrap();
+    let actual = cached
+        .update(&prior, &z, CovarianceUpdateMethod::JosephForm)
+        .unwrap();
+    assert_eq!(position.updates.get(), 1);
+    assert_eq!(actual, expected);
+
+    // A non-linear model is linearized at the prior, not at the nominal
+    // state of its `H`, in the innovation, the update in information form
+    // and the likelihood.
+    let nonlinear = crate::nonlinear::NumericalObservationModel::new(
+        |x: &DVector<f64>| DVector::from_vec(vec![x.norm(), x[0] * x[1], x[1].exp()]),
+        counting.inner.R().clone(),
+        1e-7,
+        &DVector::from_vec(vec![3.0, 4.0]),
+    );
+    let cached = CachedObservationModel::new(&nonlinear).unwrap();
+    let prior = StateAndCovariance::new(
+        DVector::from_vec(vec![1.0, -0.5]),
+        DMatrix::from_row_slice(2, 2, &[0.5, 0.1, 0.1, 0.3]),
+    );
+    let z = DVector::from_vec(vec![1.2, -0.4, 0.7]);
+    approx::assert_relative_eq!(
+        cached.innovation(&prior, &z).covariance(),
+        nonlinear.innovation(&prior, &z).covariance(),
+        epsilon = 1e-12
+    );
+    let expected = nonlinear
+        .update(&prior, &z, CovarianceUpdateMethod::JosephForm)
+        .unwrap();
+    let actual = cached
+        .update_information_form(&prior, &z, CovarianceUpdateMethod::JosephForm)
+        .unwrap();
+    approx::assert_relative_eq!(actual, expected, epsilon = 1e-10);
+    let expected = nonlinear.innovation(&prior, &z).log_likelihood().unwrap();
+    approx::assert_relative_eq!(
+        cached.log_likelihood(&prior, &z).unwrap(),
+        expected,
+        epsilon = 1e-10
+    );
+
+    let cached = CachedTransitionModel::new(&transition);
+    assert_eq!(*cached.FT(), transition.F().transpose());
+    let expected = transition.predict(&prior);
+    approx::assert_relative_eq!(cached.predict(&prior), expected, epsilon = 1e-12);
+}
@@ -0,0 +1,227 @@
+//! The central difference Kalman filter (CDKF)
+
+use na::{DMatrix, DVector, RealField};
+use nalgebra as na;
+
+use crate::{
+    is_nan, linalg, Error, ErrorKind, NonlinearTransitionModel, ObservationModel,
+    StateAndCovariance,
+};
+
+/// The order of the Stirling interpolation used by the
+/// [CentralDifferenceKalmanFilter]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum DividedDifferenceOrder {
+    /// First-order divided differences (DD1), a derivative-free analogue of
+    /// the extended Kalman filter.
+    First,
+    /// Second-order divided differences (DD2), which also capture the
+    /// effect of the curvature on the mean and covariance.
+    #[default]
+    Second,
+}
+
+/// A central difference Kalman filter with no control inputs
+///
+/// The divided difference filters of Nørgaard, Poulsen and Ravn replace the
+/// Taylor expansion of the extended Kalman filter by Stirling's
+/// interpolation formula, evaluating the non-linear functions at the mean
+/// and at the mean plus and minus `h` times each column of the Cholesky
+/// factor of the covariance. Like the UKF, no Jacobians are needed, but the
+/// covariance is computed from the divided differences rather than from
+/// weighted outer products, so the predicted covariance is positive
+/// semi-definite by construction.
+///
+/// The interval length `h` defaults to `sqrt(3)`, which is optimal for a
+/// Gaussian. Only the process function `f` and `Q` of the
+/// [NonlinearTransitionModel] and the observation function
+/// [predict_observation](ObservationModel::predict_observation) and `R` of
+/// the [ObservationModel] are used.
+pub struct CentralDifferenceKalmanFilter<'a, R>
+where
+    R: RealField,
+{
+    transition_model: &'a dyn NonlinearTransitionModel<R>,
+    observation_model: &'a dyn ObservationModel<R>,
+    order: DividedDifferenceOrder,
+    h: R,
+}
+
+impl<'a, R> CentralDifferenceKalmanFilter<'a, R>
+where
+    R: RealField,
+{
+    /// Initialize a new `CentralDifferenceKalmanFilter` struct with
+    /// second-order divided differences and `h = sqrt(3)`.
+    pub fn new(
+        transition_model: &'a dyn NonlinearTransitionModel<R>,
+        observation_model: &'a dyn ObservationModel<R>,
+    ) -> Self {
+        Self {
+            transition_model,
+            observation_model,
+            order: DividedDifferenceOrder::Second,
+            h: na::convert::<f64, R>(3.0).sqrt(),
+        }
+    }
+
+    /// Use divided differences of the given order.
+    pub fn with_order(mut self, order: DividedDifferenceOrder) -> Self {
+        self.order = order;
+        self
+    }
+
+    /// Use the interval length `h`, which must be greater than one.
+    pub fn with_interval(mut self, h: R) -> Self {
+        self.h = h;
+        self
+    }
+
+    /// Propagate a Gaussian through `f`, returning the mean and covariance of
+    /// `f(x)` and the cross-covariance of `x` and `f(x)`.
+    #[allow(clippy::type_complexity)]
+    fn transform<F>(
+        &self,
+        estimate: &StateAndCovariance<R>,
+        f: F,
+    ) -> Result<(DVector<R>, DMatrix<R>, DMatrix<R>), Error>
+    where
+        F: Fn(&DVector<R>) -> DVector<R>,
+    {
+        let mean = estimate.state();
+        let n = mean.nrows();
+        let sqrt = match na::linalg::Cholesky::new(estimate.covariance().clone()) {
+            Some(chol) => chol.unpack(),
+            None => return Err(ErrorKind::CovarianceNotPositiveSemiDefinite.into()),
+        };
+        let h = self.h.clone();
+        let h2 = h.clone() * h.clone();
+        let two: R = na::convert(2.0);
+        let center = f(mean);
+        let m = center.nrows();
+
+        // First- and second-order divided differences along each column of
+        // the Cholesky factor.
+        let mut S1 = DMatrix::zeros(m, n);
+        let mut S2 = DMatrix::zeros(m, n);
+        let mut sum = DVector::zeros(m);
+        let curvature_scale = (h2.clone() - R::one()).sqrt() / (two.clone() * h2.clone());
+        for j in 0..n {
+            let offset = sqrt.column(j) * h.clone();
+            let plus = f(&(mean + &offset));
+            let minus = f(&(mean - &offset));
+            S1.set_column(j, &((&plus - &minus) / (two.clone() * h.clone())));
+            S2.set_column(
+                j,
+                &((&plus + &minus - &center * two.clone()) * curvature_scale.clone()),
+            );
+            sum += plus + minus;
+        }
+
+        let cross_covariance = &sqrt * S1.transpose();
+        Ok(match self.order {
+            DividedDifferenceOrder::First => (center, &S1 * S1.transpose(), cross_covariance),
+            DividedDifferenceOrder::Second => {
+                let n_r: R = na::convert(n as f64);
+                let mean = center * ((h2.clone() - n_r) / h2.clone()) + sum / (two * h2);
+                (
+                    mean,
+                    &S1 * S1.transpose() + &S2 * S2.transpose(),
+                    cross_covariance,
+                )
+            }
+        })
+    }
+
+    /// Predict the prior from the previous estimate.
+    pub fn predict(
+        &self,
+        previous_estimate: &StateAndCovariance<R>,
+    ) -> Result<StateAndCovariance<R>, Error> {
+        let (state, covariance, _) =
+            self.transform(previous_estimate, |x| self.transition_model.f(x))?;
+        Ok(StateAndCovariance::new(
+            state,
+            covariance + self.transition_model.Q(),
+        ))
+    }
+
+    /// Update the prior with an observation.
+    pub fn update(
+        &self,
+        prior: &StateAndCovariance<R>,
+        observation: &DVector<R>,
+    ) -> Result<StateAndCovariance<R>, Error> {
+        let (predicted, Pyy, Pxy) =
+            self.transform(prior, |x| self.observation_model.predict_observation(x))?;
+        let S = Pyy + self.observation_model.R();
+        let S_inv = match linalg::cholesky_inverse(&S) {
+            Some(v) => v,
+            None => return Err(ErrorKind::CovarianceNotPositiveSemiDefinite.into()),
+        };
+        let K: DMatrix<R> = Pxy * S_inv;
+        let state = prior.state() + &K * (observation - predicted);
+        let covariance = prior.covariance() - linalg::matmul3(&K, &S, &K.transpose());
+        Ok(StateAndCovariance::new(state, covariance.symmetric_part()))
+    }
+
+    /// Perform prediction and update steps
+    ///
+    /// If any component of the observation is NaN (not a number), the
+    /// observation will not be used but rather the prior will be returned as
+    /// the posterior without performing the update step.
+    pub fn step(
+        &self,
+        previous_estimate: &StateAndCovariance<R>,
+        observation: &DVector<R>,
+    ) -> Result<StateAndCovariance<R>, Error> {
+        let prior = self.predict(previous_estimate)?;
+        if observation.iter().any(|x| is_nan(x.clone())) {
+            Ok(prior)
+        } else {
+            self.update(&prior, observation)
+        }
+    }
+}
+
+#[test]
+fn test_central_difference_kalman_filter() {
+    use crate::test_util::{
+        initial_estimate, simulate_positions, ConstantVelocity, PositionObservation,
+    };
+    use crate::{KalmanFilterNoControl, Linearized, NumericalTransitionModel};
+
+    // Squaring a standard normal: DD2 gives the exact mean 1 and variance
+    // 2, while DD1, like linearization at zero, gives 0 and 0.
+    let square = NumericalTransitionModel::new(
+        |x: &DVector<f64>| x.map(|v| v * v),
+        DMatrix::zeros(1, 1),
+        1e-6,
+    );
+    let observation = PositionObservation::new(1.0);
+    let estimate = StateAndCovariance::new(DVector::zeros(1), DMatrix::identity(1, 1));
+    let cdkf = CentralDifferenceKalmanFilter::new(&square, &observation);
+    let predicted = cdkf.predict(&estimate).unwrap();
+    approx::assert_relative_eq!(predicted.state()[0], 1.0, epsilon = 1e-12);
+    approx::assert_relative_eq!(predicted.covariance()[(0, 0)], 2.0, epsilon = 1e-12);
+    let cdkf = cdkf.with_order(DividedDifferenceOrder::First);
+    let predicted = cdkf.predict(&estimate).unwrap();
+    approx::assert_relative_eq!(predicted.state()[0], 0.0);
+    approx::assert_relative_eq!(predicted.covariance()[(0, 0)], 0.0);
+
+    // With linear models, the CDKF is the Kalman filter.
+    let transition = ConstantVelocity::new(0.1, 1.0);
+    let linearized = Linearized(&transition);
+    let observation = PositionObservation::new(0.5);
+    let observations = simulate_positions(10, 0.1, 1.0, 0.5, 5);
+    let kf = KalmanFilterNoControl::new(&transition, &observation);
+    let cdkf = CentralDifferenceKalmanFilter::new(&linearized, &observation);
+    let mut expected = initial_estimate();
+    let mut actual = initial_estimate();
+    for z in &observations {
+        expected = kf.step(&expected, z).unwrap();
+        actual = cdkf.step(&actual, z).unwrap();
+    }
+    approx::assert_relative_eq!(actual.state(), expected.state(), epsilon = 1e-9);
+    approx::assert_relative_eq!(actual.covariance(), expected.covariance(), epsilon = 1e-9);
+}
@@ -0,0 +1,271 @@
+//! Chandrasekhar-type fast recursions for time-invariant models
+
+use alloc::vec::Vec;
+
+use na::{DMatrix, DVector, RealField};
+use nalgebra as na;
+
+use crate::{
+    is_nan, linalg, Error, ErrorKind, KalmanFilterNoControl, ObservationModel, StateAndCovariance,
+    TransitionModelLinearNoControl,
+};
+
+/// A Kalman filter for time-invariant models propagating low-rank
+/// increments of the covariance
+///
+/// With constant `F`, `Q`, `H` and `R`, the change of the prior covariance
+/// from one step to the next, `P_{k+1} - P_k = L_k M_k L_k^T`, has a rank
+/// `r` no greater than that of the first change, and the Chandrasekhar
+/// recursions update `P_k H^T`, the innovation covariance `R_e = H P_k H^T +
+/// R` and the factors `L` and `M` without ever forming `P_k`:
+///
+/// ```text
+/// R_e' = R_e + H L M L^T H^T      G' = G + L M L^T H^T      (G = P H^T)
+/// L'   = F (L - G R_e^-1 H L)     M' = M - M L^T H^T R_e'^-1 H L M
+/// ```
+///
+/// A step then costs `O(n^2 r)` rather than the `O(n^3)` of the covariance
+/// recursion, a large saving for a big state when the increment has a small
+/// rank, e.g. when the initial covariance differs from the steady state
+/// only in a few directions. The estimates are those of the
+/// [KalmanFilterNoControl], up to round-off.
+///
+/// Create this with [KalmanFilterNoControl::into_fast]. As with the
+/// [SteadyStateKalmanFilter](crate::SteadyStateKalmanFilter), it is the
+/// caller's declaration that the models are time-invariant.
+pub struct FastTimeInvariantFilter<'a, R>
+where
+    R: RealField,
+{
+    transition_model: &'a dyn TransitionModelLinearNoControl<R>,
+    observation_model: &'a dyn ObservationModel<R>,
+    /// `F`, scaled by the fading-memory factor.
+    scaled_F: DMatrix<R>,
+    prior_state: DVector<R>,
+    /// `P H^T`, for the current prior covariance `P`.
+    gain_factor: DMatrix<R>,
+    innovation_covariance: DMatrix<R>,
+    L: DMatrix<R>,
+    M: DMatrix<R>,
+}
+
+impl<'a, R> KalmanFilterNoControl<'a, R>
+where
+    R: RealField,
+{
+    /// Convert to a filter using the Chandrasekhar recursions, starting
+    /// from `initial_estimate`, for time-invariant models
+    ///
+    /// As for [filter](Self::filter), the initial estimate is that before
+    /// the first prediction. The first two prior covariances are computed in
+    /// full, and the eigenvectors of their difference give the factors `L`
+    /// and `M`, dropping eigenvalues negligible at the machine precision.
+    /// The fading-memory factor is included. A
+    /// [ErrorKind::SingularMatrix] error is returned if the innovation
+    /// covariance is singular.
+    pub fn into_fast(
+        self,
+        initial_estimate: &StateAndCovariance<R>,
+    ) -> Result<FastTimeInvariantFilter<'a, R>, Error> {
+        self.check_dimensions(initial_estimate, None)?;
+        // Fading memory scales the propagated covariance F P F^T by alpha^2,
+        // the same as using alpha F with Q unchanged.
+        let alpha = self.fading_memory.clone().sqrt();
+        let F = self.transition_model.F() * alpha;
+        let Q = self.transition_model.Q();
+        let H = self.observation_matrix.H();
+        let R = self.observation_matrix.R();
+
+        let FT = F.transpose();
+        let P0 = (linalg::matmul3(&F, initial_estimate.covariance(), &FT) + Q).symmetric_part();
+        let gain_factor = &P0 * H.transpose();
+        let innovation_covariance = (H * &gain_factor + R).symmetric_part();
+        let S_inv = innovation_covariance
+            .clone()
+            .try_inverse()
+            .ok_or_else(|| Error::from(ErrorKind::SingularMatrix))?;
+        let FG = &F * &gain_factor;
+        let P1 = (linalg::matmul3(&F, &P0, &FT) + Q
+            - linalg::matmul3(&FG, &S_inv, &FG.transpose()))
+        .symmetric_part();
+
+        let eigen = (P1 - &P0).symmetric_eigen();
+        let n = P0.nrows();
+        let tolerance = P0.amax() * R::default_epsilon() * na::convert(n as f64);
+        let kept: Vec<usize> = (0..n)
+            .filter(|&i| eigen.eigenvalues[i].clone().abs() > tolerance)
+            .collect();
+        let L = eigen.eigenvectors.select_columns(&kept);
+        let M = DMatrix::from_diagonal(&eigen.eigenvalues.select_rows(&kept));
+
+        Ok(FastTimeInvariantFilter {
+            transition_model: self.transition_model,
+            observation_model: self.observation_matrix,
+            prior_state: self.transition_model.F() * initial_estimate.state(),
+            scaled_F: F,
+            gain_factor,
+            innovation_covariance,
+            L,
+            M,
+        })
+    }
+}
+
+impl<'a, R> FastTimeInvariantFilter<'a, R>
+where
+    R: RealField,
+{
+    /// Get the rank of the covariance increment, the number of columns of
+    /// `L`.
+    pub fn rank(&self) -> usize {
+        self.L.ncols()
+    }
+
+    /// Get the predicted state for the next observation.
+    pub fn prior_state(&self) -> &DVector<R> {
+        &self.prior_state
+    }
+
+    /// Get the covariance of the next innovation, `H P H^T + R`.
+    pub fn innovation_covariance(&self) -> &DMatrix<R> {
+        &self.innovation_covariance
+    }
+
+    /// Get the Kalman gain for the next observation, `K = P H^T R_e^-1`.
+    pub fn gain(&self) -> Result<DMatrix<R>, Error> {
+        let S_inv = self
+            .innovation_covariance
+            .clone()
+            .try_inverse()
+            .ok_or_else(|| Error::from(ErrorKind::SingularMatrix))?;
+        Ok(&self.gain_factor * S_inv)
+    }
+
+    /// Update with the next observation, returning the filtered state, and
+    /// predict to the following one
+    ///
+    /// The covariance is never formed, so only the state is returned. The
+    /// recursions assume every step is observed, so an
+    /// [ErrorKind::MissingObservation] error is returned if any component of
+    /// the observation is NaN.
+    pub fn step(&mut self, observation: &DVector<R>) -> Result<DVector<R>, Error> {
+        crate::check_dimension(
+            "observation",
+            (self.innovation_covariance.nrows(), 1),
+            observation.shape(),
+        )?;
+        if observation.iter().any(|x| is_nan(x.clone())) {
+            return Err(ErrorKind::MissingObservation.into());
+        }
+        let S_inv = self
+            .innovation_covariance
+            .clone()
+            .try_inverse()
+            .ok_or_else(|| Error::from(ErrorKind::SingularMatrix))?;
+        let H = self.observation_model.H();
+        let innovation = observation
+            - self
+                .observation_model
+                .predict_observation(&self.prior_state);
+        let gain = &self.gain_factor * &S_inv;
+        let state = &self.prior_state + &gain * innovation;
+
+        let HL = H * &self.L;
+        let HLM = &HL * &self.M;
+        let innovation_covariance =
+            (&self.innovation_covariance + &HLM * HL.transpose()).symmetric_part();
+        let next_S_inv = innovation_covariance
+            .clone()
+            .try_inverse()
+            .ok_or_else(|| Error::from(ErrorKind::SingularMatrix))?;
+        self.gain_factor += &self.L * HLM.transpose();
+        self.M = (&self.M - linalg::matmul3(&HLM.transpose(), &next_S_inv, &HLM)).symmetric_part();
+        self.L = &self.scaled_F * (&self.L - gain * HL);
+        self.innovation_covariance = innovation_covariance;
+        self.prior_state = self.transition_model.F() * &state;
+        Ok(state)
+    }
+
+    /// Filter a series of observations, returning the filtered states, see
+    /// [step](Self::step)
+    ///
+    /// Errors give the index of the failed observation.
+    #[cfg(feature = "std")]
+    pub fn filter(&mut self, observations: &[DVector<R>]) -> Result<Vec<DVector<R>>, Error> {
+        observations
+            .iter()
+            .enumerate()
+            .map(|(k, observation)| self.step(observation).map_err(|e| e.with_step(k)))
+            .collect()
+    }
+}
+
+#[test]
+fn test_chandrasekhar() {
+    use crate::test_util::{
+        initial_estimate, simulate_positions, ConstantVelocity, Normals, PositionObservation,
+    };
+    use crate::{LinearObservationModel, LinearTransitionModel};
+
+    let transition = ConstantVelocity::new(0.1, 1.0);
+    let observation = PositionObservation::new(0.5);
+    let kf = KalmanFilterNoControl::new(&transition, &observation);
+    let observations = simulate_positions(50, 0.1, 1.0, 0.5, 17);
+    let expected = kf.filter(&initial_estimate(), &observations).unwrap();
+
+    let mut fast = KalmanFilterNoControl::new(&transition, &observation)
+        .into_fast(&initial_estimate())
+        .unwrap();
+    assert!(fast.rank() <= 2);
+    let states = fast.filter(&observations).unwrap();
+    for (state, estimate) in states.iter().zip(expected.iter()) {
+        approx::assert_relative_eq!(state, estimate.state(), epsilon = 1e-9);
+    }
+
+    // Two axes of constant velocity, with noise on one velocity only and
+    // a known initial state: the first increment is F Q F^T, of rank one,
+    // and so are all later ones.
+    let F = DMatrix::from_row_slice(
+        4,
+        4,
+        &[
+            1.0, 0.1, 0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 0.0, 1.0, 0.1, 0.0, 0.0, 0.0, 1.0,
+        ],
+    );
+    let mut Q = DMatrix::zeros(4, 4);
+    Q[(1, 1)] = 1.0;
+    let H = DMatrix::from_row_slice(2, 4, &[1.0, 0.0, 0.0, 0.0, 0.0, 0.0, 1.0, 0.0]);
+    let planar = LinearTransitionModel::from_matrices(F, Q);
+    let positions = LinearObservationModel::from_matrices(H, DMatrix::identity(2, 2) * 0.5);
+    let start = StateAndCovariance::new(DVector::zeros(4), DMatrix::zeros(4, 4));
+    let mut normals = Normals::new(3);
+    let observations: Vec<DVector<f64>> = (0..30)
+        .map(|_| DVector::from_fn(2, |_, _| normals.sample()))
+        .collect();
+    let kf = KalmanFilterNoControl::new(&planar, &positions);
+    let expected = kf.filter(&start, &observations).unwrap();
+    let mut fast = KalmanFilterNoControl::new(&planar, &positions)
+        .into_fast(&start)
+        .unwrap();
+    assert_eq!(fast.rank(), 1);
+    let states = fast.filter(&observations).unwrap();
+    for (state, estimate) in states.iter().zip(expected.iter()) {
+        approx::assert_relative_eq!(state, estimate.state(), epsilon = 1e-9);
+    }
+
+    // With fading memory, the recursions follow the filter's prior
+    // covariance alpha^2 F P F^T + Q.
+    let observations = simulate_positions(50, 0.1, 1.0, 0.5, 17);
+    let kf = || KalmanFilterNoControl::new(&transition, &observation).with_fading_memory(1.1);
+    let expected = kf().filter(&initial_estimate(), &observations).unwrap();
+    let mut fast = kf().into_fast(&initial_estimate()).unwrap();
+    let states = fast.filter(&observations).unwrap();
+    for (state, estimate) in states.iter().zip(expected.iter()) {
+        approx::assert_relative_eq!(state, estimate.state(), epsilon = 1e-9);
+    }
+
+    let mut fast = KalmanFilterNoControl::new(&transition, &observation)
+        .into_fast(&initial_estimate())
+        .unwrap();
+    assert!(fast.step(&DVector::from_element(1, f64::NAN)).is_err());
+}
@@ -0,0 +1,216 @@
+//! Checkpointing of long-running filters for crash recovery
+
+use na::{DMatrix, DVector, RealField};
+use nalgebra as na;
+
+use crate::{
+    check_shape, Error, KalmanFilterNoControl, ObservationModel, StateAndCovariance,
+    TransitionModelLinearNoControl,
+};
+
+/// Everything needed to resume a filter where it left off
+///
+/// The filter itself only borrows its models, so the state of a run is the
+/// current estimate and the number of steps taken. Adaptive schemes which
+/// re-estimate the noise covariances can store their current values, and
+/// callers drawing random numbers can store the state of their generator,
+/// in whatever form it has, as bytes. With the `serde` feature, checkpoints
+/// can be serialized.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Checkpoint<R>
+where
+    R: RealField,
+{
+    /// The current estimate.
+    pub estimate: StateAndCovariance<R>,
+    /// The number of steps taken.
+    pub step: usize,
+    /// The current process covariance of an adaptive scheme, if any.
+    pub process_covariance: Option<DMatrix<R>>,
+    /// The current observation noise covariance of an adaptive scheme, if
+    /// any.
+    pub observation_covariance: Option<DMatrix<R>>,
+    /// The state of the caller's random number generator, if any.
+    pub rng_state: Option<Vec<u8>>,
+}
+
+impl<R> Checkpoint<R>
+where
+    R: RealField,
+{
+    /// Create a checkpoint of an estimate after `step` steps.
+    pub fn new(estimate: StateAndCovariance<R>, step: usize) -> Self {
+        Self {
+            estimate,
+            step,
+            process_covariance: None,
+            observation_covariance: None,
+            rng_state: None,
+        }
+    }
+
+    /// Record the current noise covariances of an adaptive scheme.
+    pub fn with_noise_covariances(
+        mut self,
+        process_covariance: DMatrix<R>,
+        observation_covariance: DMatrix<R>,
+    ) -> Self {
+        self.process_covariance = Some(process_covariance);
+        self.observation_covariance = Some(observation_covariance);
+        self
+    }
+
+    /// Record the state of the caller's random number generator.
+    pub fn with_rng_state(mut self, rng_state: Vec<u8>) -> Self {
+        self.rng_state = Some(rng_state);
+        self
+    }
+}
+
+impl<'a, R> KalmanFilterNoControl<'a, R>
+where
+    R: RealField,
+{
+    /// Create a filter to resume from a checkpoint
+    ///
+    /// This checks the models as [try_new](Self::try_new) does, and that the
+    /// checkpointed state and covariance, and any adaptive noise
+    /// covariances, have the dimensions of the models, returning an
+    /// [ErrorKind::DimensionMismatch] error otherwise. If the checkpoint
+    /// holds adaptive noise covariances, the models should be built from
+    /// them.
+    pub fn from_checkpoint(
+        transition_model: &'a dyn TransitionModelLinearNoControl<R>,
+        observation_model: &'a dyn ObservationModel<R>,
+        checkpoint: &Checkpoint<R>,
+    ) -> Result<Self, Error> {
+        let filter = Self::try_new(transition_model, observation_model)?;
+        filter.check_dimensions(&checkpoint.estimate, None)?;
+        let ss = transition_model.state_dim();
+        let os = observation_model.obs_dim();
+        if let Some(Q) = &checkpoint.process_covariance {
+            check_shape("Q", (ss, ss), Q)?;
+        }
+        if let Some(R) = &checkpoint.observation_covariance {
+            check_shape("R", (os, os), R)?;
+        }
+        Ok(filter)
+    }
+
+    /// Perform a step from a checkpoint, returning the next checkpoint
+    ///
+    /// This is [step](Self::step) on the checkpointed estimate, counting the
+    /// step and keeping the other contents of the checkpoint. An error is
+    /// annotated with the index of the step.
+    pub fn step_checkpoint(
+        &self,
+        checkpoint: &Checkpoint<R>,
+        observation: &DVector<R>,
+    ) -> Result<Checkpoint<R>, Error> {
+        let estimate = self
+            .step(&checkpoint.estimate, observation)
+            .map_err(|e| e.with_step(checkpoint.step))?;
+        Ok(Checkpoint {
+            estimate,
+            step: checkpoint.step + 1,
+            ..checkpoint.clone()
+        })
+    }
+}
+
+#[test]
+fn test_resume_from_checkpoint() {
+    use crate::test_util::PositionObservation;
+    use crate::test_util::{initial_estimate, simulate_positions, ConstantVelocity};
+
+    let transition = ConstantVelocity::new(0.1, 1.0);
+    let observation = PositionObservation::new(0.5);
+    let observations = simulate_positions(20, 0.1, 1.0, 0.5, 5);
+    let expected = KalmanFilterNoControl::new(&transition, &observation)
+        .filter(&initial_estimate(), &observations)
+        .unwrap();
+
+    // Run half way, then resume from the checkpoint with a new filter.
+    let kf = KalmanFilterNoControl::new(&transition, &observation);
+    let mut checkpoint = Checkpoint::new(initial_estimate(), 0).with_rng_state(vec![1, 2, 3]);
+    for z in observations[..10].iter() {
+        checkpoint = kf.step_checkpoint(&checkpoint, z).unwrap();
+    }
+    let saved = checkpoint.clone();
+    let kf = KalmanFilterNoControl::from_checkpoint(&transition, &observation, &saved).unwrap();
+    let mut checkpoint = saved;
+    for z in observations[10..].iter() {
+        checkpoint = kf.step_checkpoint(&checkpoint, z).unwrap();
+    }
+    assert_eq!(checkpoint.step, 20);
+    assert_eq!(checkpoint.rng_state, Some(vec![1, 2, 3]));
+    approx::assert_relative_eq!(checkpoint.estimate, expected[19], epsilon = 1e-12);
+}
+
+#[test]
+fn test_checkpoint_edge_cases() {
+    use crate::test_util::{random_walk, scalars};
+
+    // The step is counted from the checkpoint, and the adaptive covariances
+    // are carried along.
+    let (transition, observation) = random_walk();
+    let kf = KalmanFilterNoControl::new(&transition, &observation);
+    let start = StateAndCovariance::new(DVector::from_element(1, 1.0), DMatrix::identity(1, 1));
+    let checkpoint = Checkpoint::new(start.clone(), 41)
+        .with_noise_covariances(DMatrix::identity(1, 1), DMatrix::identity(1, 1) * 2.0);
+    let z = &scalars(&[3.0])[0];
+    let next = kf.step_checkpoint(&checkpoint, z).unwrap();
+    assert_eq!(next.step, 42);
+    assert_eq!(next.estimate, kf.step(&start, z).unwrap());
+    assert_eq!(next.process_covariance, checkpoint.process_covariance);
+    assert_eq!(
+        next.observation_covariance,
+        checkpoint.observation_covariance
+    );
+
+    // A failed step is annotated with the index of the step.
+    let err = kf
+        .step_checkpoint(&next, &DVector::from_element(2, 0.0))
+        .unwrap_err();
+    assert_eq!(err.step(), Some(42));
+
+    // A checkpoint of another model cannot be resumed, whether its state,
+    // its covariance or its adaptive covariances have the wrong dimension.
+    let resume = |checkpoint: &Checkpoint<f64>| {
+        KalmanFilterNoControl::from_checkpoint(&transition, &observation, checkpoint)
+            .err()
+            .expect("a checkpoint of the wrong dimension was accepted")
+    };
+    let other = Checkpoint::new(
+        StateAndCovariance::new(DVector::zeros(2), DMatrix::identity(2, 2)),
+        0,
+    );
+    assert!(matches!(
+        resume(&other).kind(),
+        crate::ErrorKind::DimensionMismatch {
+            expected: (1, 1),
+            actual: (2, 1),
+            matrix: "state"
+        }
+    ));
+    let other = Checkpoint::new(
+        StateAndCovariance::new(DVector::zeros(1), DMatrix::identity(2, 2)),
+        0,
+    );
+    assert!(matches!(
+        resume(&other).kind(),
+        crate::ErrorKind::DimensionMismatch {
+            actual: (2, 2),
+            matrix: "covariance",
+            ..
+        }
+    ));
+    let other = checkpoint
+        .clone()
+        .with_noise_covariances(DMatrix::identity(1, 1), DMatrix::identity(2, 2));
+    assert!(matches!(
+        resume(&other).kind(),
+        crate::ErrorKind::DimensionMismatch { matrix: "R", .. }
+    ));
+}
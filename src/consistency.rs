@@ -0,0 +1,397 @@
+//! Statistical tests of filter consistency
+
+use alloc::vec::Vec;
+
+use na::{DMatrix, DVector, RealField};
+use nalgebra as na;
+
+use crate::stats::{chi_squared_interval, chi_squared_quantile};
+use crate::{
+    check_dimension, Error, ErrorKind, Innovation, NonlinearTransitionModel, ObservationModel,
+    StateAndCovariance,
+};
+
+/// Normalized estimation error squared, `e^T P^-1 e` with `e = x - x_est`
+///
+/// This requires the true state `truth` and is therefore used in simulation.
+/// For a consistent filter it is chi-square distributed with as many degrees
+/// of freedom as the state has dimensions.
+pub fn nees<R>(truth: &DVector<R>, estimate: &StateAndCovariance<R>) -> Result<R, Error>
+where
+    R: RealField,
+{
+    let error = truth - estimate.state();
+    let p_chol = match na::linalg::Cholesky::new(estimate.covariance().clone()) {
+        Some(v) => v,
+        None => return Err(ErrorKind::CovarianceNotPositiveSemiDefinite.into()),
+    };
+    Ok(error.dot(&p_chol.solve(&error)))
+}
+
+/// The posterior Cramér-Rao bound (PCRB) on the error covariance at each
+/// step, along a true trajectory
+///
+/// No unbiased estimator has a mean squared error below the bound, so
+/// comparing a filter's errors, e.g. averaged over simulated runs, with the
+/// bound shows how much accuracy is left to gain. For additive Gaussian
+/// noise, the information recursion of Tichavský et al. (1998) is, in
+/// covariance form, the Riccati recursion of the Kalman filter with the
+/// Jacobians evaluated at the true states rather than the estimates:
+/// the step to `truth[k]` uses `F` at `truth[k - 1]`, or at the mean of
+/// `initial` for the first, and the measurement uses `H` of the observation
+/// model. Observations are taken at the steps where `schedule` is true, so
+/// different measurement schedules can be compared before any data exist.
+/// For linear models the bound is the covariance of the Kalman filter.
+///
+/// An [ErrorKind::DimensionMismatch] error is returned if `schedule` does not
+/// have an entry for each true state, and an
+/// [ErrorKind::CovarianceNotPositiveSemiDefinite] error if an innovation
+/// covariance cannot be factored.
+pub fn posterior_cramer_rao_bound<R>(
+    transition_model: &dyn NonlinearTransitionModel<R>,
+    observation_model: &dyn ObservationModel<R>,
+    initial: &StateAndCovariance<R>,
+    truth: &[DVector<R>],
+    schedule: &[bool],
+) -> Result<Vec<DMatrix<R>>, Error>
+where
+    R: RealField,
+{
+    if schedule.len() != truth.len() {
+        return Err(ErrorKind::DimensionMismatch {
+            expected: (truth.len(), 1),
+            actual: (schedule.len(), 1),
+            matrix: "schedule",
+        }
+        .into());
+    }
+    let H = observation_model.H();
+    let HT = observation_model.HT();
+    let mut bounds: Vec<DMatrix<R>> = Vec::with_capacity(truth.len());
+    let mut previous_state = initial.state();
+    for (k, (state, &observed)) in truth.iter().zip(schedule).enumerate() {
+        let previous = bounds.last().unwrap_or(initial.covariance());
+        let F = transition_model.jacobian_at(previous_state);
+        let mut bound = &F * previous * F.transpose() + transition_model.Q();
+        if observed {
+            let PHT = &bound * &*HT;
+            let S = H * &PHT + observation_model.R();
+            let s_chol = na::linalg::Cholesky::new(S).ok_or_else(|| {
+                Error::from(ErrorKind::CovarianceNotPositiveSemiDefinite).with_step(k)
+            })?;
+            bound -= &PHT * s_chol.solve(&PHT.transpose());
+        }
+        bounds.push(bound.symmetric_part());
+        previous_state = state;
+    }
+    Ok(bounds)
+}
+
+/// Innovation whitened by its covariance, `L^-1 y` where `S = L L^T`
+///
+/// For a consistent filter the components of the normalized innovations are
+/// independent standard normal variables.
+pub fn normalized_innovation<R>(innovation: &Innovation<R>) -> Result<DVector<R>, Error>
+where
+    R: RealField,
+{
+    let s_chol = match na::linalg::Cholesky::new(innovation.covariance().clone()) {
+        Some(v) => v,
+        None => return Err(ErrorKind::CovarianceNotPositiveSemiDefinite.into()),
+    };
+    match s_chol
+        .l_dirty()
+        .solve_lower_triangular(innovation.residual())
+    {
+        Some(v) => Ok(v),
+        None => Err(ErrorKind::SingularMatrix.into()),
+    }
+}
+
+/// The result of [ljung_box_test]
+#[derive(Debug, Clone)]
+pub struct WhitenessTest<R>
+where
+    R: RealField,
+{
+    /// The Ljung–Box statistic of each innovation component.
+    pub statistics: DVector<R>,
+    /// The chi-square threshold at the requested confidence.
+    pub threshold: R,
+}
+
+impl<R> WhitenessTest<R>
+where
+    R: RealField,
+{
+    /// Whether every component passes the test, i.e. whiteness is not
+    /// rejected.
+    pub fn is_white(&self) -> bool {
+        self.statistics.iter().all(|q| *q <= self.threshold)
+    }
+}
+
+/// Ljung–Box test for whiteness of the innovations of a filter run
+///
+/// Each component of the [normalized innovations](normalized_innovation) is
+/// tested separately for autocorrelation at lags `1..=lags`. Under the null
+/// hypothesis of white innovations, each statistic is chi-square distributed
+/// with `lags` degrees of freedom. Whiteness is rejected for a component when
+/// its statistic exceeds the quantile at `confidence` (e.g. 0.95).
+///
+/// Missing observations should simply be left out of `innovations`. An
+/// [ErrorKind::InvalidLags] error is returned unless `lags` is at least one
+/// and less than the number of innovations, an
+/// [ErrorKind::DimensionMismatch] error if the innovations do not all have
+/// the same dimension, and an [ErrorKind::ZeroVariance] error if a
+/// component is constant.
+pub fn ljung_box_test<R>(
+    innovations: &[Innovation<R>],
+    lags: usize,
+    confidence: R,
+) -> Result<WhitenessTest<R>, Error>
+where
+    R: RealField,
+{
+    let n = innovations.len();
+    if lags == 0 || n <= lags {
+        return Err(ErrorKind::InvalidLags { lags, len: n }.into());
+    }
+    let dim = innovations[0].residual().nrows();
+    let mut normalized = DMatrix::<R>::zeros(n, dim);
+    for (i, innovation) in innovations.iter().enumerate() {
+        check_dimension("innovation", (dim, 1), innovation.residual().shape())
+            .map_err(|e| e.with_step(i))?;
+        normalized
+            .row_mut(i)
+            .copy_from(&normalized_innovation(innovation)?.transpose());
+    }
+
+    let n_r: R = na::convert(n as f64);
+    let mut statistics = DVector::<R>::zeros(dim);
+    for (c, column) in normalized.column_iter().enumerate() {
+        let mean = column.mean();
+        let centered = column.add_scalar(-mean);
+        let variance = centered.norm_squared();
+        if variance.is_zero() {
+            return Err(ErrorKind::ZeroVariance { component: c }.into());
+        }
+        let mut q = R::zero();
+        for j in 1..=lags {
+            let autocovariance = centered.rows(j, n - j).dot(&centered.rows(0, n - j));
+            let r = autocovariance / variance.clone();
+            q += r.clone() * r / na::convert((n - j) as f64);
+        }
+        statistics[c] = n_r.clone() * (n_r.clone() + na::convert(2.0)) * q;
+    }
+    Ok(WhitenessTest {
+        statistics,
+        threshold: chi_squared_quantile(confidence, lags),
+    })
+}
+
+/// Status reported by an [InnovationMonitor]
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum NisStatus {
+    /// Not enough innovations have been seen to fill the window.
+    Filling,
+    /// The average NIS over the window is within the chi-square bounds.
+    Consistent,
+    /// The average NIS is below the lower bound; the filter is pessimistic
+    /// (e.g. `Q` or `R` is too large).
+    BelowBounds,
+    /// The average NIS is above the upper bound; the filter is overconfident
+    /// or the model does not match the data.
+    AboveBounds,
+}
+
+/// Sliding-window monitor of the normalized innovation squared (NIS)
+///
+/// The average NIS over the last `window` updates is compared with the
+/// two-sided chi-square bounds for the requested confidence. All storage is
+/// allocated on construction, so the monitor is suitable for real-time loops.
+#[derive(Debug, Clone)]
+pub struct InnovationMonitor<R>
+where
+    R: RealField,
+{
+    window: DVector<R>,
+    next: usize,
+    filled: usize,
+    lower: R,
+    upper: R,
+}
+
+impl<R> InnovationMonitor<R>
+where
+    R: RealField,
+{
+    /// Create a new `InnovationMonitor`.
+    ///
+    /// `window` is the number of updates averaged, `obs_dim` the dimension of
+    /// the observations and `confidence` (e.g. 0.95) the probability that a
+    /// consistent filter stays within the bounds.
+    pub fn new(window: usize, obs_dim: usize, confidence: R) -> Self {
+        assert!(window > 0);
+        // The sum of the NIS over the window is chi-square distributed with
+        // window * obs_dim degrees of freedom.
+        let (lower, upper) = chi_squared_interval(confidence, window * obs_dim);
+        let window_r: R = na::convert(window as f64);
+        Self {
+            window: DVector::zeros(window),
+            next: 0,
+            filled: 0,
+            lower: lower / window_r.clone(),
+            upper: upper / window_r,
+        }
+    }
+
+    /// Record the NIS of a new innovation and return the updated status.
+    pub fn push(&mut self, innovation: &Innovation<R>) -> Result<NisStatus, Error> {
+        Ok(self.push_nis(innovation.nis()?))
+    }
+
+    /// Record a new NIS value and return the updated status.
+    pub fn push_nis(&mut self, nis: R) -> NisStatus {
+        self.window[self.next] = nis;
+        self.next = (self.next + 1) % self.window.nrows();
+        self.filled = (self.filled + 1).min(self.window.nrows());
+        self.status()
+    }
+
+    /// Average NIS over the window, if it has been filled.
+    pub fn average_nis(&self) -> Option<R> {
+        if self.filled < self.window.nrows() {
+            None
+        } else {
+            Some(self.window.mean())
+        }
+    }
+
+    /// Lower and upper bounds of the average NIS.
+    pub fn bounds(&self) -> (R, R) {
+        (self.lower.clone(), self.upper.clone())
+    }
+
+    /// The current status.
+    pub fn status(&self) -> NisStatus {
+        match self.average_nis() {
+            None => NisStatus::Filling,
+            Some(nis) if nis < self.lower => NisStatus::BelowBounds,
+            Some(nis) if nis > self.upper => NisStatus::AboveBounds,
+            Some(_) => NisStatus::Consistent,
+        }
+    }
+
+    /// Forget all recorded values.
+    pub fn reset(&mut self) {
+        self.next = 0;
+        self.filled = 0;
+    }
+}
+
+#[test]
+fn test_ljung_box() {
+    let mut normals = crate::test_util::Normals::new(7);
+    let white: Vec<f64> = (0..1000).map(|_| normals.sample()).collect();
+    let mut correlated = white.clone();
+    for i in 1..correlated.len() {
+        correlated[i] += 0.7 * correlated[i - 1];
+    }
+    let to_innovations = |values: &[f64]| -> Vec<Innovation<f64>> {
+        values
+            .iter()
+            .map(|v| Innovation::new(DVector::from_element(1, *v), DMatrix::identity(1, 1)))
+            .collect()
+    };
+    assert!(ljung_box_test(&to_innovations(&white), 10, 0.95)
+        .unwrap()
+        .is_white());
+    assert!(!ljung_box_test(&to_innovations(&correlated), 10, 0.95)
+        .unwrap()
+        .is_white());
+
+    let innovations = to_innovations(&white[..10]);
+    for lags in [0, 10] {
+        match ljung_box_test(&innovations, lags, 0.95) {
+            Err(e) => assert!(matches!(e.kind(), ErrorKind::InvalidLags { len: 10, .. })),
+            Ok(_) => panic!("{} lags were accepted for 10 innovations", lags),
+        }
+    }
+    match ljung_box_test(&to_innovations(&[1.0; 20]), 5, 0.95) {
+        Err(e) => assert!(matches!(e.kind(), ErrorKind::ZeroVariance { component: 0 })),
+        Ok(_) => panic!("a constant series was tested"),
+    }
+}
+
+#[test]
+fn test_innovation_monitor() {
+    let mut normals = crate::test_util::Normals::new(11);
+    let mut monitor = InnovationMonitor::new(50, 1, 0.99);
+    assert_eq!(monitor.status(), NisStatus::Filling);
+    for _ in 0..200 {
+        let v: f64 = normals.sample();
+        monitor.push_nis(v * v);
+    }
+    assert_eq!(monitor.status(), NisStatus::Consistent);
+    for _ in 0..50 {
+        let v: f64 = 3.0 * normals.sample();
+        monitor.push_nis(v * v);
+    }
+    assert_eq!(monitor.status(), NisStatus::AboveBounds);
+}
+
+#[test]
+fn test_posterior_cramer_rao_bound() {
+    use crate::test_util::{initial_estimate, ConstantVelocity, PositionObservation};
+    use crate::{KalmanFilterNoControl, Linearized};
+
+    // For a linear model, the bound is the covariance of the Kalman filter,
+    // whatever the true states, with missing observations where none are
+    // scheduled.
+    let transition = ConstantVelocity::new(0.1, 1.0);
+    let linearized = Linearized(&transition);
+    let observation = PositionObservation::new(0.5);
+    let truth: Vec<DVector<f64>> = (0..10)
+        .map(|k| DVector::from_vec(vec![0.1 * k as f64, 1.0]))
+        .collect();
+    let schedule: Vec<bool> = (0..10).map(|k| k % 3 != 2).collect();
+    let bounds = posterior_cramer_rao_bound(
+        &linearized,
+        &observation,
+        &initial_estimate(),
+        &truth,
+        &schedule,
+    )
+    .unwrap();
+    let observations: Vec<DVector<f64>> = schedule
+        .iter()
+        .map(|&observed| DVector::from_element(1, if observed { 0.0 } else { f64::NAN }))
+        .collect();
+    let filtered = KalmanFilterNoControl::new(&transition, &observation)
+        .filter(&initial_estimate(), &observations)
+        .unwrap();
+    for (bound, estimate) in bounds.iter().zip(filtered.iter()) {
+        approx::assert_relative_eq!(bound, estimate.covariance(), epsilon = 1e-12);
+    }
+
+    // Observing at every step gives a tighter bound.
+    let every = posterior_cramer_rao_bound(
+        &linearized,
+        &observation,
+        &initial_estimate(),
+        &truth,
+        &[true; 10],
+    )
+    .unwrap();
+    assert!(every[9][(0, 0)] < bounds[9][(0, 0)]);
+
+    assert!(posterior_cramer_rao_bound(
+        &linearized,
+        &observation,
+        &initial_estimate(),
+        &truth,
+        &schedule[1..]
+    )
+    .is_err());
+}
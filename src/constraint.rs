@@ -0,0 +1,86 @@
+//! Soft constraints applied as pseudo-measurements
+
+use na::{DMatrix, DVector, RealField};
+use nalgebra as na;
+
+use crate::{
+    CovarianceUpdateMethod, Error, LinearObservationModel, ObservationModel, StateAndCovariance,
+};
+
+/// A soft linear constraint `H x ≈ value`, applied as a pseudo-measurement
+///
+/// Knowledge such as "the speed is about zero, give or take 0.1 m/s" is not
+/// an observation from a sensor, but can be used in the same way: as an
+/// observation of `H x` equal to `value` with noise covariance `R`
+/// expressing the confidence in the constraint. A small `R` makes the
+/// constraint nearly hard.
+#[derive(Debug, Clone)]
+pub struct SoftConstraint<R>
+where
+    R: RealField,
+{
+    model: LinearObservationModel<R>,
+    value: DVector<R>,
+}
+
+impl<R> SoftConstraint<R>
+where
+    R: RealField,
+{
+    /// Create the constraint `H x ≈ value` with covariance `R`.
+    pub fn new(H: DMatrix<R>, value: DVector<R>, R: DMatrix<R>) -> Self {
+        Self {
+            model: LinearObservationModel::from_matrices(H, R),
+            value,
+        }
+    }
+
+    /// Constrain the state components at `indices` to `values`, each
+    /// independently with standard deviation `sigma`.
+    pub fn on_components(state_dim: usize, indices: &[usize], values: &[R], sigma: R) -> Self {
+        assert_eq!(indices.len(), values.len());
+        let mut H = DMatrix::zeros(indices.len(), state_dim);
+        for (row, &index) in indices.iter().enumerate() {
+            H[(row, index)] = R::one();
+        }
+        let R = DMatrix::identity(indices.len(), indices.len()) * (sigma.clone() * sigma);
+        Self::new(H, DVector::from_column_slice(values), R)
+    }
+
+    /// Get a reference to the pseudo-measurement model.
+    pub fn model(&self) -> &LinearObservationModel<R> {
+        &self.model
+    }
+
+    /// Get a reference to the constrained value.
+    pub fn value(&self) -> &DVector<R> {
+        &self.value
+    }
+
+    /// Apply the constraint to an estimate.
+    pub fn apply(&self, estimate: &StateAndCovariance<R>) -> Result<StateAndCovariance<R>, Error> {
+        self.model
+            .update(estimate, &self.value, CovarianceUpdateMethod::JosephForm)
+    }
+}
+
+#[test]
+fn test_soft_constraint() {
+    use crate::test_util::initial_estimate;
+
+    // Velocity about zero, with a standard deviation of 0.1.
+    let constraint = SoftConstraint::on_components(2, &[1], &[0.0], 0.1);
+    let estimate = initial_estimate();
+    let constrained = constraint.apply(&estimate).unwrap();
+
+    // The prior velocity of one with unit variance is combined with the
+    // pseudo-measurement of zero with variance 0.01.
+    approx::assert_relative_eq!(constrained.state()[1], 1.0 / 101.0, epsilon = 1e-12);
+    approx::assert_relative_eq!(
+        constrained.covariance()[(1, 1)],
+        0.01 / 1.01,
+        epsilon = 1e-12
+    );
+    // The position, uncorrelated with the velocity, is unchanged.
+    approx::assert_relative_eq!(constrained.state()[0], estimate.state()[0]);
+}
@@ -0,0 +1,311 @@
+//! Continuous-discrete filtering of systems described by differential
+//! equations
+
+use na::{DMatrix, DVector, RealField};
+use nalgebra as na;
+
+use crate::{
+    is_nan, CovarianceUpdateMethod, Error, ErrorKind, ObservationModel, StateAndCovariance,
+};
+
+/// A continuous-time model of process dynamics, potentially non-linear
+///
+/// The state evolves as `dx/dt = f(x) + w(t)` where `w` is white noise with
+/// spectral density `Qc`.
+pub trait ContinuousTransitionModel<R>
+where
+    R: RealField,
+{
+    /// The time derivative of the state, `f(x)`.
+    fn f(&self, state: &DVector<R>) -> DVector<R>;
+
+    /// Get the Jacobian of `f` evaluated at `state`.
+    fn jacobian_at(&self, state: &DVector<R>) -> DMatrix<R>;
+
+    /// Get the spectral density of the process noise, `Qc`.
+    fn Qc(&self) -> &DMatrix<R>;
+}
+
+/// Time derivatives of the mean and of the covariance, which obeys the
+/// Riccati differential equation `dP/dt = A P + P A^T + Qc` without
+/// measurements.
+fn derivatives<R: RealField>(
+    model: &dyn ContinuousTransitionModel<R>,
+    state: &DVector<R>,
+    covariance: &DMatrix<R>,
+) -> (DVector<R>, DMatrix<R>) {
+    let A = model.jacobian_at(state);
+    let AP = &A * covariance;
+    let dP = &AP + AP.transpose() + model.Qc();
+    (model.f(state), dP)
+}
+
+/// Integrate the mean and covariance over `dt` with `substeps` steps of the
+/// classical fourth-order Runge-Kutta method.
+pub fn propagate_rk4<R>(
+    model: &dyn ContinuousTransitionModel<R>,
+    estimate: &StateAndCovariance<R>,
+    dt: R,
+    substeps: usize,
+) -> StateAndCovariance<R>
+where
+    R: RealField,
+{
+    assert!(substeps > 0);
+    let h = dt / na::convert(substeps as f64);
+    let half_h = h.clone() * na::convert(0.5);
+    let sixth_h = h.clone() / na::convert(6.0);
+    let two: R = na::convert(2.0);
+    let mut x = estimate.state().clone();
+    let mut P = estimate.covariance().clone();
+    for _ in 0..substeps {
+        let (k1x, k1P) = derivatives(model, &x, &P);
+        let (k2x, k2P) = derivatives(
+            model,
+            &(&x + &k1x * half_h.clone()),
+            &(&P + &k1P * half_h.clone()),
+        );
+        let (k3x, k3P) = derivatives(
+            model,
+            &(&x + &k2x * half_h.clone()),
+            &(&P + &k2P * half_h.clone()),
+        );
+        let (k4x, k4P) = derivatives(model, &(&x + &k3x * h.clone()), &(&P + &k3P * h.clone()));
+        x += (k1x + (k2x + k3x) * two.clone() + k4x) * sixth_h.clone();
+        P += (k1P + (k2P + k3P) * two.clone() + k4P) * sixth_h.clone();
+    }
+    StateAndCovariance::new(x, P.symmetric_part())
+}
+
+/// A continuous-discrete extended Kalman filter
+///
+/// Between observations, the mean and covariance are propagated by
+/// numerically integrating the continuous-time model, so observations may
+/// arrive at arbitrary intervals.
+pub struct ContinuousDiscreteFilter<'a, R>
+where
+    R: RealField,
+{
+    transition_model: &'a dyn ContinuousTransitionModel<R>,
+    observation_model: &'a dyn ObservationModel<R>,
+    substeps: usize,
+}
+
+impl<'a, R> ContinuousDiscreteFilter<'a, R>
+where
+    R: RealField,
+{
+    /// Initialize a new `ContinuousDiscreteFilter` struct.
+    ///
+    /// By default, propagation uses a single Runge-Kutta step per interval;
+    /// see [with_substeps](Self::with_substeps).
+    pub fn new(
+        transition_model: &'a dyn ContinuousTransitionModel<R>,
+        observation_model: &'a dyn ObservationModel<R>,
+    ) -> Self {
+        Self {
+            transition_model,
+            observation_model,
+            substeps: 1,
+        }
+    }
+
+    /// Set the number of Runge-Kutta steps used to propagate over each
+    /// interval.
+    pub fn with_substeps(mut self, substeps: usize) -> Self {
+        assert!(substeps > 0);
+        self.substeps = substeps;
+        self
+    }
+
+    /// Propagate an estimate forward by `dt` without an observation.
+    pub fn predict(&self, estimate: &StateAndCovariance<R>, dt: R) -> StateAndCovariance<R> {
+        propagate_rk4(self.transition_model, estimate, dt, self.substeps)
+    }
+
+    /// Propagate by `dt` and then update with an observation
+    ///
+    /// If any component of the observation is NaN (not a number), the
+    /// observation will not be used but rather the prior will be returned as
+    /// the posterior without performing the update step.
+    pub fn step(
+        &self,
+        previous_estimate: &StateAndCovariance<R>,
+        dt: R,
+        observation: &DVector<R>,
+    ) -> Result<StateAndCovariance<R>, Error> {
+        let prior = self.predict(previous_estimate, dt);
+        if observation.iter().any(|x| is_nan(x.clone())) {
+            Ok(prior)
+        } else {
+            self.observation_model
+                .update(&prior, observation, CovarianceUpdateMethod::JosephForm)
+        }
+    }
+}
+
+/// A continuous-time Kalman-Bucy filter for linear systems with continuous
+/// measurements
+///
+/// The state evolves as `dx/dt = A x + w(t)` and is measured continuously as
+/// `z(t) = C x + v(t)`, where `w` and `v` are white noise with spectral
+/// densities `Qc` and `Rc`. The estimate obeys `dx/dt = A x + K (z - C x)`
+/// with gain `K = P C^T Rc^-1`, and the covariance obeys the Riccati
+/// differential equation `dP/dt = A P + P A^T + Qc - P C^T Rc^-1 C P`.
+pub struct KalmanBucyFilter<R>
+where
+    R: RealField,
+{
+    A: DMatrix<R>,
+    Qc: DMatrix<R>,
+    C: DMatrix<R>,
+    Rc_inv: DMatrix<R>,
+    substeps: usize,
+}
+
+impl<R> KalmanBucyFilter<R>
+where
+    R: RealField,
+{
+    /// Create a new filter from the system matrix `A`, the process noise
+    /// spectral density `Qc`, the measurement matrix `C` and the measurement
+    /// noise spectral density `Rc`.
+    ///
+    /// Returns an error if `Rc` is singular.
+    pub fn new(
+        A: DMatrix<R>,
+        Qc: DMatrix<R>,
+        C: DMatrix<R>,
+        Rc: DMatrix<R>,
+    ) -> Result<Self, Error> {
+        let Rc_inv = match Rc.try_inverse() {
+            Some(Rc_inv) => Rc_inv,
+            None => return Err(ErrorKind::SingularMatrix.into()),
+        };
+        Ok(Self {
+            A,
+            Qc,
+            C,
+            Rc_inv,
+            substeps: 1,
+        })
+    }
+
+    /// Set the number of Runge-Kutta steps used to integrate over each
+    /// interval.
+    pub fn with_substeps(mut self, substeps: usize) -> Self {
+        assert!(substeps > 0);
+        self.substeps = substeps;
+        self
+    }
+
+    /// The Kalman gain `K = P C^T Rc^-1` for covariance `P`.
+    pub fn gain(&self, covariance: &DMatrix<R>) -> DMatrix<R> {
+        covariance * self.C.transpose() * &self.Rc_inv
+    }
+
+    fn derivatives(
+        &self,
+        state: &DVector<R>,
+        covariance: &DMatrix<R>,
+        measurement: &DVector<R>,
+    ) -> (DVector<R>, DMatrix<R>) {
+        let K = self.gain(covariance);
+        let dx = &self.A * state + &K * (measurement - &self.C * state);
+        let AP = &self.A * covariance;
+        let dP = &AP + AP.transpose() + &self.Qc - &K * &self.C * covariance;
+        (dx, dP)
+    }
+
+    /// Integrate the estimate over `dt` with the measurement held at
+    /// `measurement` throughout the interval, using the classical
+    /// fourth-order Runge-Kutta method.
+    pub fn step(
+        &self,
+        estimate: &StateAndCovariance<R>,
+        measurement: &DVector<R>,
+        dt: R,
+    ) -> StateAndCovariance<R> {
+        let h = dt / na::convert(self.substeps as f64);
+        let half_h = h.clone() * na::convert(0.5);
+        let sixth_h = h.clone() / na::convert(6.0);
+        let two: R = na::convert(2.0);
+        let z = measurement;
+        let mut x = estimate.state().clone();
+        let mut P = estimate.covariance().clone();
+        for _ in 0..self.substeps {
+            let (k1x, k1P) = self.derivatives(&x, &P, z);
+            let (k2x, k2P) = self.derivatives(
+                &(&x + &k1x * half_h.clone()),
+                &(&P + &k1P * half_h.clone()),
+                z,
+            );
+            let (k3x, k3P) = self.derivatives(
+                &(&x + &k2x * half_h.clone()),
+                &(&P + &k2P * half_h.clone()),
+                z,
+            );
+            let (k4x, k4P) =
+                self.derivatives(&(&x + &k3x * h.clone()), &(&P + &k3P * h.clone()), z);
+            x += (k1x + (k2x + k3x) * two.clone() + k4x) * sixth_h.clone();
+            P += (k1P + (k2P + k3P) * two.clone() + k4P) * sixth_h.clone();
+        }
+        StateAndCovariance::new(x, P.symmetric_part())
+    }
+}
+
+#[test]
+fn test_rk4_matches_exact_discretization() {
+    use crate::test_util::{initial_estimate, ConstantVelocity};
+    use crate::TransitionModelLinearNoControl;
+
+    struct ContinuousConstantVelocity {
+        Qc: DMatrix<f64>,
+    }
+    impl ContinuousTransitionModel<f64> for ContinuousConstantVelocity {
+        fn f(&self, x: &DVector<f64>) -> DVector<f64> {
+            DVector::from_vec(vec![x[1], 0.0])
+        }
+        fn jacobian_at(&self, _x: &DVector<f64>) -> DMatrix<f64> {
+            DMatrix::from_row_slice(2, 2, &[0.0, 1.0, 0.0, 0.0])
+        }
+        fn Qc(&self) -> &DMatrix<f64> {
+            &self.Qc
+        }
+    }
+    let continuous = ContinuousConstantVelocity {
+        Qc: DMatrix::from_row_slice(2, 2, &[0.0, 0.0, 0.0, 2.0]),
+    };
+    let expected = ConstantVelocity::new(0.5, 2.0).predict(&initial_estimate());
+    let propagated = propagate_rk4(&continuous, &initial_estimate(), 0.5, 1);
+    approx::assert_relative_eq!(propagated.state(), expected.state(), epsilon = 1e-12);
+    approx::assert_relative_eq!(
+        propagated.covariance(),
+        expected.covariance(),
+        epsilon = 1e-12
+    );
+}
+
+#[test]
+fn test_kalman_bucy_steady_state() {
+    // For a random walk measured directly, the steady-state variance is
+    // sqrt(Qc Rc).
+    let filter = KalmanBucyFilter::new(
+        DMatrix::zeros(1, 1),
+        DMatrix::from_element(1, 1, 4.0),
+        DMatrix::identity(1, 1),
+        DMatrix::from_element(1, 1, 1.0),
+    )
+    .unwrap()
+    .with_substeps(10);
+    let mut estimate = StateAndCovariance::new(
+        DVector::from_element(1, 0.0),
+        DMatrix::from_element(1, 1, 10.0),
+    );
+    let measurement = DVector::from_element(1, 3.0);
+    for _ in 0..100 {
+        estimate = filter.step(&estimate, &measurement, 0.1);
+    }
+    approx::assert_relative_eq!(estimate.covariance()[(0, 0)], 2.0, epsilon = 1e-8);
+    approx::assert_relative_eq!(estimate.state()[0], 3.0, epsilon = 1e-8);
+}
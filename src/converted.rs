@@ -0,0 +1,261 @@
+//! Converted measurements for polar sensors (CMKF)
+//!
+//! A radar measures range and bearing, and possibly elevation, which are
+//! non-linear functions of a Cartesian position. Converting each
+//! measurement to Cartesian coordinates gives a linear observation, but the
+//! naive conversion `r cos θ` is biased, since `E[cos(θ + ε)] = λ cos θ`
+//! with `λ = exp(-σ²/2)` for a Gaussian bearing error `ε` of variance `σ²`.
+//! At long range with poor angular accuracy, the bias exceeds the standard
+//! deviation and the linearized covariance is inconsistent.
+//!
+//! Here the converted position is debiased by dividing by `λ`. Its
+//! covariance is an unbiased estimate, from the measurement, of the error
+//! covariance given the true position, following the modified unbiased
+//! converted measurement of Duan, Han and Li (2004). Conditioning instead on
+//! the measurement, as Mo et al. (1998) did, gives a covariance which is
+//! itself biased.
+//!
+//! Angles are in radians. Bearing (azimuth) is measured anticlockwise from
+//! the x axis and elevation from the x-y plane. The position is relative to
+//! the sensor.
+
+use na::{DMatrix, DVector, RealField};
+use nalgebra as na;
+
+use crate::LinearObservationModel;
+
+/// A polar measurement converted to Cartesian coordinates
+#[derive(Debug, Clone, PartialEq)]
+pub struct ConvertedMeasurement<R>
+where
+    R: RealField,
+{
+    position: DVector<R>,
+    covariance: DMatrix<R>,
+}
+
+impl<R> ConvertedMeasurement<R>
+where
+    R: RealField,
+{
+    /// Convert a range and bearing measurement, with standard deviations
+    /// `sigma_range` and `sigma_bearing`, to a position `[x, y]`.
+    pub fn from_range_bearing(range: R, bearing: R, sigma_range: R, sigma_bearing: R) -> Self {
+        let factors = [[(0, Trig::Cos), NONE], [(0, Trig::Sin), NONE]];
+        Self::convert(range, sigma_range, &[bearing], &[sigma_bearing], &factors)
+    }
+
+    /// Convert a range, bearing and elevation measurement, with standard
+    /// deviations `sigma_range`, `sigma_bearing` and `sigma_elevation`, to
+    /// a position `[x, y, z]`.
+    pub fn from_range_bearing_elevation(
+        range: R,
+        bearing: R,
+        elevation: R,
+        sigma_range: R,
+        sigma_bearing: R,
+        sigma_elevation: R,
+    ) -> Self {
+        let factors = [
+            [(1, Trig::Cos), (0, Trig::Cos)],
+            [(1, Trig::Cos), (0, Trig::Sin)],
+            [(1, Trig::Sin), NONE],
+        ];
+        Self::convert(
+            range,
+            sigma_range,
+            &[bearing, elevation],
+            &[sigma_bearing, sigma_elevation],
+            &factors,
+        )
+    }
+
+    /// The debiased position, to be used as the observation.
+    pub fn position(&self) -> &DVector<R> {
+        &self.position
+    }
+
+    /// The covariance of the position error.
+    pub fn covariance(&self) -> &DMatrix<R> {
+        &self.covariance
+    }
+
+    /// A linear observation model for this measurement, where `H` selects
+    /// the position components, relative to the sensor, from the state.
+    ///
+    /// As the covariance depends on the measurement, the model is only
+    /// valid for this measurement.
+    pub fn observation_model(&self, H: DMatrix<R>) -> LinearObservationModel<R> {
+        LinearObservationModel::from_matrices(H, self.covariance.clone())
+    }
+
+    /// Convert a measurement whose Cartesian components are the range times
+    /// products of trigonometric functions of the angles.
+    fn convert(
+        range: R,
+        sigma_range: R,
+        angles: &[R],
+        sigmas: &[R],
+        factors: &[[(usize, Trig); 2]],
+    ) -> Self {
+        let estimates = |a: usize| AngleEstimates::new(angles[a].clone(), sigmas[a].clone());
+        let n = factors.len();
+
+        let mut position = DVector::zeros(n);
+        for (i, component) in factors.iter().enumerate() {
+            let mut converted = range.clone();
+            for (a, trig) in component {
+                converted *= estimates(*a).unbiased(*trig, Trig::One);
+            }
+            position[i] = converted;
+        }
+
+        // The error covariance given the truth is E[u u^T] - x x^T, of which
+        // u u^T and r^2 = r_m^2 - σ_r^2 times the unbiased estimates of the
+        // products of trigonometric functions are unbiased estimates.
+        let range_squared = range.clone() * range - sigma_range.clone() * sigma_range;
+        let mut covariance = DMatrix::zeros(n, n);
+        for i in 0..n {
+            for j in 0..=i {
+                let mut product = range_squared.clone();
+                for a in 0..angles.len() {
+                    let fi = factor_of(&factors[i], a);
+                    let fj = factor_of(&factors[j], a);
+                    product *= estimates(a).unbiased(fi, fj);
+                }
+                let value = position[i].clone() * position[j].clone() - product;
+                covariance[(i, j)] = value.clone();
+                covariance[(j, i)] = value;
+            }
+        }
+        Self {
+            position,
+            covariance,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Trig {
+    One,
+    Cos,
+    Sin,
+}
+
+const NONE: (usize, Trig) = (0, Trig::One);
+
+/// The trigonometric factor of angle `a` in a component.
+fn factor_of(component: &[(usize, Trig); 2], a: usize) -> Trig {
+    component
+        .iter()
+        .find(|(b, trig)| *b == a && *trig != Trig::One)
+        .map_or(Trig::One, |(_, trig)| *trig)
+}
+
+/// Unbiased estimates of trigonometric functions of the true angle from a
+/// measured angle with Gaussian error
+///
+/// `E[cos(kθ_m)] = λ^(k²) cos(kθ)` with `λ = exp(-σ²/2)`, and likewise for
+/// the sine.
+struct AngleEstimates<R: RealField> {
+    sin: R,
+    cos: R,
+    sin2: R,
+    cos2: R,
+    lambda: R,
+}
+
+impl<R: RealField> AngleEstimates<R> {
+    fn new(angle: R, sigma: R) -> Self {
+        let half: R = na::convert(0.5);
+        let lambda = (-(sigma.clone() * sigma) * half).exp();
+        let (sin, cos) = angle.clone().sin_cos();
+        let (sin2, cos2) = (angle.clone() + angle).sin_cos();
+        Self {
+            sin,
+            cos,
+            sin2,
+            cos2,
+            lambda,
+        }
+    }
+
+    /// An unbiased estimate of `f(θ) g(θ)`.
+    fn unbiased(&self, f: Trig, g: Trig) -> R {
+        let half: R = na::convert(0.5);
+        let lambda4 = self.lambda.clone().powi(4);
+        match (f, g) {
+            (Trig::One, Trig::One) => R::one(),
+            (Trig::Cos, Trig::One) | (Trig::One, Trig::Cos) => {
+                self.cos.clone() / self.lambda.clone()
+            }
+            (Trig::Sin, Trig::One) | (Trig::One, Trig::Sin) => {
+                self.sin.clone() / self.lambda.clone()
+            }
+            (Trig::Cos, Trig::Cos) => half * (R::one() + self.cos2.clone() / lambda4),
+            (Trig::Sin, Trig::Sin) => half * (R::one() - self.cos2.clone() / lambda4),
+            _ => half * self.sin2.clone() / lambda4,
+        }
+    }
+}
+
+#[test]
+fn test_converted_measurement() {
+    use crate::test_util::Normals;
+
+    // Monte Carlo check of the bias and covariance at long range with a
+    // poor bearing accuracy, where the naive conversion is badly biased.
+    let (range, bearing, elevation) = (10_000.0f64, 0.7f64, 0.3f64);
+    let (sigma_range, sigma_angle) = (10.0, 0.3);
+    let truth = DVector::from_vec(vec![
+        range * elevation.cos() * bearing.cos(),
+        range * elevation.cos() * bearing.sin(),
+        range * elevation.sin(),
+    ]);
+    let mut normals = Normals::new(1);
+    let trials = 20_000;
+    let mut mean_error = DVector::zeros(3);
+    let mut naive_error = DVector::zeros(3);
+    let mut mean_covariance = DMatrix::zeros(3, 3);
+    let mut scatter = DMatrix::zeros(3, 3);
+    for _ in 0..trials {
+        let r = range + sigma_range * normals.sample();
+        let b = bearing + sigma_angle * normals.sample();
+        let e = elevation + sigma_angle * normals.sample();
+        let converted = ConvertedMeasurement::from_range_bearing_elevation(
+            r,
+            b,
+            e,
+            sigma_range,
+            sigma_angle,
+            sigma_angle,
+        );
+        let error = converted.position() - &truth;
+        scatter += &error * error.transpose();
+        mean_error += error;
+        naive_error += DVector::from_vec(vec![
+            r * e.cos() * b.cos(),
+            r * e.cos() * b.sin(),
+            r * e.sin(),
+        ]) - &truth;
+        mean_covariance += converted.covariance();
+    }
+    let n = trials as f64;
+    mean_error /= n;
+    naive_error /= n;
+    scatter /= n;
+    mean_covariance /= n;
+    assert!(naive_error.norm() > 500.0);
+    assert!(mean_error.norm() < 100.0);
+    // The averaged covariance matches the scatter of the errors.
+    approx::assert_relative_eq!(mean_covariance, scatter, epsilon = 0.05 * scatter.norm());
+
+    // In two dimensions along the x axis, the covariance has the closed
+    // form of Duan et al.
+    let converted = ConvertedMeasurement::from_range_bearing(100.0, 0.0, 1.0, 0.1);
+    let lambda = (-0.01f64 / 2.0).exp();
+    approx::assert_relative_eq!(converted.position()[0], 100.0 / lambda, epsilon = 1e-9);
+    let expected_yy = -0.5 * (100.0f64.powi(2) - 1.0) * (1.0 - lambda.powi(-4));
+    approx::assert_relative_eq!(converted.covariance()[(1, 1)], expected_yy, epsilon = 1e-9);
+    approx::assert_relative_eq!(converted.covariance()[(0, 1)], 0.0, epsilon = 1e-9);
+}
@@ -0,0 +1,240 @@
+//! Moment-matched updates for count-valued observations
+
+use alloc::borrow::Cow;
+
+use na::{DMatrix, DVector, RealField};
+use nalgebra as na;
+
+use crate::{CovarianceUpdateMethod, Error, Innovation, ObservationModel, StateAndCovariance};
+
+/// The distribution of a count given the state
+#[derive(Debug, Clone, PartialEq)]
+pub enum CountDistribution<R>
+where
+    R: RealField,
+{
+    /// A Poisson count whose rate, over the counting interval, is `h(x)`,
+    /// e.g. photon counts; the variance equals the mean.
+    Poisson,
+    /// A binomial count of successes in `trials` trials, each with the
+    /// probability `h(x)`; the variance is `n p (1 - p)`.
+    Binomial {
+        /// The number of trials.
+        trials: R,
+    },
+}
+
+impl<R> CountDistribution<R>
+where
+    R: RealField,
+{
+    /// The factor from `h(x)` to the mean count.
+    fn scale(&self) -> R {
+        match self {
+            Self::Poisson => R::one(),
+            Self::Binomial { trials } => trials.clone(),
+        }
+    }
+}
+
+/// An observation model for Poisson or binomial count sensors
+///
+/// The noise of a count is not additive with a fixed covariance: it depends
+/// on the state through the mean. The update matches the moments of the
+/// count: with the conditional mean `E[z | x]` and variance `Var[z | x]`,
+/// the innovation covariance is `H P H^T + E[Var[z | x]]`, where the
+/// expectation is over the prior, and the Gaussian update then uses this in
+/// place of `R`. For a linear rate or probability `h(x) = H x`, the mean and
+/// variance of the count are matched exactly.
+///
+/// The wrapped model gives `h(x)` and `H`, and its `R`, usually zero, is
+/// additional noise, e.g. a dark count variance. The components are
+/// independent counts. For the sigma-point filters, the conditional mean and
+/// variance are available as
+/// [conditional_mean](Self::conditional_mean) and
+/// [conditional_variance](Self::conditional_variance).
+pub struct CountObservationModel<'a, R>
+where
+    R: RealField,
+{
+    inner: &'a dyn ObservationModel<R>,
+    distribution: CountDistribution<R>,
+    H: DMatrix<R>,
+    HT: DMatrix<R>,
+}
+
+impl<'a, R> CountObservationModel<'a, R>
+where
+    R: RealField,
+{
+    /// Wrap `inner`, whose `h(x)` is the rate or probability of each count.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the number of trials of a binomial count is not positive.
+    pub fn new(inner: &'a dyn ObservationModel<R>, distribution: CountDistribution<R>) -> Self {
+        if let CountDistribution::Binomial { trials } = &distribution {
+            assert!(
+                *trials > R::zero(),
+                "a binomial count needs a positive number of trials"
+            );
+        }
+        let H = inner.H() * distribution.scale();
+        let HT = H.transpose();
+        Self {
+            inner,
+            distribution,
+            H,
+            HT,
+        }
+    }
+
+    /// The conditional mean of the counts, `E[z | x]`.
+    pub fn conditional_mean(&self, state: &DVector<R>) -> DVector<R> {
+        self.inner.predict_observation(state) * self.distribution.scale()
+    }
+
+    /// The conditional variance of each count, `Var[z | x]`, with the rate
+    /// or probability clamped to its valid range.
+    pub fn conditional_variance(&self, state: &DVector<R>) -> DVector<R> {
+        let h = self.inner.predict_observation(state);
+        match &self.distribution {
+            CountDistribution::Poisson => h.map(|rate| rate.max(R::zero())),
+            CountDistribution::Binomial { trials } => h.map(|p| {
+                let p = p.max(R::zero()).min(R::one());
+                trials.clone() * p.clone() * (R::one() - p)
+            }),
+        }
+    }
+
+    /// The expected conditional covariance of the counts over the prior,
+    /// plus the covariance of the wrapped model.
+    fn count_covariance(
+        &self,
+        prior: &StateAndCovariance<R>,
+        observation_covariance: &DMatrix<R>,
+    ) -> DMatrix<R> {
+        let mut variance = self.conditional_variance(prior.state());
+        if let CountDistribution::Binomial { trials } = &self.distribution {
+            // E[p (1 - p)] = p (1 - p) - Var[p] for the prior mean p of a
+            // linear probability.
+            let H = self.inner.H();
+            let spread = (H * prior.covariance() * &*self.inner.HT()).diagonal();
+            variance -= spread * trials.clone();
+            variance.apply(|v| *v = v.clone().max(R::zero()));
+        }
+        observation_covariance + DMatrix::from_diagonal(&variance)
+    }
+}
+
+impl<'a, R> ObservationModel<R> for CountObservationModel<'a, R>
+where
+    R: RealField,
+{
+    fn predict_observation(&self, state: &DVector<R>) -> DVector<R> {
+        self.conditional_mean(state)
+    }
+    fn H(&self) -> &DMatrix<R> {
+        &self.H
+    }
+    fn HT(&self) -> Cow<'_, DMatrix<R>> {
+        Cow::Borrowed(&self.HT)
+    }
+    fn R(&self) -> &DMatrix<R> {
+        self.inner.R()
+    }
+    fn state_dim(&self) -> usize {
+        self.inner.state_dim()
+    }
+    fn obs_dim(&self) -> usize {
+        self.inner.obs_dim()
+    }
+
+    /// The innovation, with the expected count covariance added to `R`.
+    fn innovation(&self, prior: &StateAndCovariance<R>, observation: &DVector<R>) -> Innovation<R> {
+        let residual = observation - self.conditional_mean(prior.state());
+        let covariance =
+            &self.H * prior.covariance() * &self.HT + self.count_covariance(prior, self.inner.R());
+        Innovation::new(residual, covariance)
+    }
+
+    /// The Gaussian update, with the expected count covariance added to the
+    /// given covariance.
+    fn update_with_covariance(
+        &self,
+        prior: &StateAndCovariance<R>,
+        observation: &DVector<R>,
+        observation_covariance: &DMatrix<R>,
+        covariance_method: CovarianceUpdateMethod,
+    ) -> Result<StateAndCovariance<R>, Error> {
+        // The update of the wrapped model with the counts divided by the
+        // scale, e.g. the fractions of the trials.
+        let scale = self.distribution.scale();
+        let covariance =
+            self.count_covariance(prior, observation_covariance) / (scale.clone() * scale.clone());
+        self.inner.update_with_covariance(
+            prior,
+            &(observation / scale),
+            &covariance,
+            covariance_method,
+        )
+    }
+}
+
+#[test]
+fn test_count_observations() {
+    use crate::test_util::MatrixObservation;
+
+    // A Poisson rate with prior N(10, 4) and a count of 15: the count
+    // variance is the expected rate, 10.
+    let rate = MatrixObservation::new(DMatrix::identity(1, 1), DMatrix::zeros(1, 1));
+    let poisson = CountObservationModel::new(&rate, CountDistribution::Poisson);
+    let prior = StateAndCovariance::new(
+        DVector::from_element(1, 10.0),
+        DMatrix::identity(1, 1) * 4.0,
+    );
+    let z = DVector::from_element(1, 15.0);
+    assert_eq!(poisson.innovation(&prior, &z).covariance()[(0, 0)], 14.0);
+    let posterior = poisson
+        .update(&prior, &z, CovarianceUpdateMethod::JosephForm)
+        .unwrap();
+    approx::assert_relative_eq!(
+        posterior.state()[0],
+        10.0 + 5.0 * 4.0 / 14.0,
+        epsilon = 1e-12
+    );
+    approx::assert_relative_eq!(
+        posterior.covariance()[(0, 0)],
+        4.0 - 16.0 / 14.0,
+        epsilon = 1e-12
+    );
+
+    // 100 binomial trials of probability p ~ N(0.3, 0.01): the mean count is
+    // 30, and its variance 100 (0.21 - 0.01) from the trials and 100 from p.
+    let binomial = CountObservationModel::new(&rate, CountDistribution::Binomial { trials: 100.0 });
+    let prior = StateAndCovariance::new(
+        DVector::from_element(1, 0.3),
+        DMatrix::identity(1, 1) * 0.01,
+    );
+    assert_eq!(binomial.conditional_mean(prior.state())[0], 30.0);
+    approx::assert_relative_eq!(binomial.conditional_variance(prior.state())[0], 21.0);
+    let innovation = binomial.innovation(&prior, &DVector::from_element(1, 40.0));
+    approx::assert_relative_eq!(innovation.covariance()[(0, 0)], 120.0, epsilon = 1e-12);
+    let posterior = binomial
+        .update(
+            &prior,
+            &DVector::from_element(1, 40.0),
+            CovarianceUpdateMethod::JosephForm,
+        )
+        .unwrap();
+    approx::assert_relative_eq!(posterior.state()[0], 0.3 + 10.0 / 120.0, epsilon = 1e-12);
+}
+
+#[test]
+#[should_panic(expected = "positive number of trials")]
+fn test_binomial_without_trials() {
+    use crate::test_util::MatrixObservation;
+
+    let rate = MatrixObservation::new(DMatrix::identity(1, 1), DMatrix::zeros(1, 1));
+    CountObservationModel::new(&rate, CountDistribution::Binomial { trials: 0.0 });
+}
@@ -0,0 +1,196 @@
+//! Intermediate results of batch filter runs
+
+use na::{DMatrix, DVector, RealField};
+use nalgebra as na;
+
+use crate::{
+    nan, CovarianceUpdateMethod, Error, Innovation, KalmanFilterNoControl, Recording,
+    StateAndCovariance, StepRecord,
+};
+
+/// The intermediate results of each step of a filter run
+///
+/// See [KalmanFilterNoControl::filter_with_diagnostics]. Each field has one
+/// entry per observation.
+#[derive(Debug, Clone, Default)]
+pub struct FilterDiagnostics<R>
+where
+    R: RealField,
+{
+    /// The innovation, with its covariance, if the observation was used.
+    pub innovations: Vec<Option<Innovation<R>>>,
+    /// The Kalman gain, if the observation was used and the innovation
+    /// covariance could be inverted.
+    pub gains: Vec<Option<DMatrix<R>>>,
+    /// The contribution of the observation to the log-likelihood, zero if it
+    /// was skipped and NaN if the innovation covariance could not be
+    /// factored.
+    pub log_likelihoods: Vec<R>,
+    /// Whether the observation was skipped because it had a NaN component.
+    pub skipped: Vec<bool>,
+}
+
+impl<R> FilterDiagnostics<R>
+where
+    R: RealField,
+{
+    /// The log-likelihood of all the observations.
+    pub fn log_likelihood(&self) -> R {
+        self.log_likelihoods
+            .iter()
+            .fold(R::zero(), |acc, x| acc + x.clone())
+    }
+}
+
+impl<R> FilterDiagnostics<R>
+where
+    R: RealField,
+{
+    fn push(&mut self, step: StepRecord<R>) {
+        let log_likelihood = match &step.innovation {
+            Some(innovation) => innovation.log_likelihood().unwrap_or_else(|_| nan()),
+            None => R::zero(),
+        };
+        self.skipped.push(step.innovation.is_none());
+        self.log_likelihoods.push(log_likelihood);
+        self.innovations.push(step.innovation);
+        self.gains.push(step.gain);
+    }
+}
+
+impl<'a, R> KalmanFilterNoControl<'a, R>
+where
+    R: RealField,
+{
+    /// Kalman filter returning the intermediate results of each step
+    ///
+    /// This gives the same state estimates as
+    /// [filter](struct.KalmanFilterNoControl.html#method.filter), together
+    /// with the innovations, gains and log-likelihood contributions, which
+    /// would otherwise be discarded.
+    pub fn filter_with_diagnostics(
+        &self,
+        initial_estimate: &StateAndCovariance<R>,
+        observations: &[DVector<R>],
+    ) -> Result<(Vec<StateAndCovariance<R>>, FilterDiagnostics<R>), Error> {
+        let mut recording = Recording::new(initial_estimate.clone());
+        let mut state_estimates = Vec::with_capacity(observations.len());
+        let mut previous_estimate = initial_estimate.clone();
+        for (i, observation) in observations.iter().enumerate() {
+            previous_estimate = self
+                .step_recorded(
+                    &previous_estimate,
+                    observation,
+                    CovarianceUpdateMethod::JosephForm,
+                    &mut recording,
+                )
+                .map_err(|e| e.with_step(i))?;
+            state_estimates.push(previous_estimate.clone());
+        }
+        let mut diagnostics = FilterDiagnostics {
+            innovations: Vec::with_capacity(observations.len()),
+            gains: Vec::with_capacity(observations.len()),
+            log_likelihoods: Vec::with_capacity(observations.len()),
+            skipped: Vec::with_capacity(observations.len()),
+        };
+        for step in recording.steps() {
+            diagnostics.push(step.clone());
+        }
+        Ok((state_estimates, diagnostics))
+    }
+}
+
+#[test]
+fn test_filter_diagnostics() {
+    use crate::test_util::{
+        initial_estimate, simulate_positions, ConstantVelocity, PositionObservation,
+    };
+
+    let transition = ConstantVelocity::new(0.1, 1.0);
+    let observation = PositionObservation::new(0.5);
+    let kf = KalmanFilterNoControl::new(&transition, &observation);
+    let mut observations = simulate_positions(8, 0.1, 1.0, 0.5, 10);
+    observations[3] = DVector::from_element(1, f64::NAN);
+
+    let (estimates, diagnostics) = kf
+        .filter_with_diagnostics(&initial_estimate(), &observations)
+        .unwrap();
+    assert_eq!(
+        estimates,
+        kf.filter(&initial_estimate(), &observations).unwrap()
+    );
+    approx::assert_relative_eq!(
+        diagnostics.log_likelihood(),
+        kf.log_likelihood(&initial_estimate(), &observations)
+            .unwrap(),
+        epsilon = 1e-12
+    );
+    assert_eq!(diagnostics.skipped.iter().filter(|s| **s).count(), 1);
+    assert!(diagnostics.skipped[3]);
+    assert!(diagnostics.innovations[3].is_none());
+    assert_eq!(diagnostics.gains[0].as_ref().unwrap().shape(), (2, 1));
+}
+
+#[test]
+fn test_diagnostics_per_step() {
+    use crate::test_util::{
+        initial_estimate, simulate_positions, ConstantVelocity, PositionObservation,
+    };
+    use crate::{ErrorKind, ObservationModel, TransitionModelLinearNoControl};
+
+    // Each step's entries are those of its update from the prediction of
+    // the previous estimate, so they line up with the observations.
+    let transition = ConstantVelocity::new(0.1, 1.0);
+    let observation = PositionObservation::new(0.5);
+    let kf = KalmanFilterNoControl::new(&transition, &observation);
+    let mut observations = simulate_positions(6, 0.1, 1.0, 0.5, 19);
+    observations[0] = DVector::from_element(1, f64::NAN);
+    observations[4] = DVector::from_element(1, f64::NAN);
+    let (estimates, diagnostics) = kf
+        .filter_with_diagnostics(&initial_estimate(), &observations)
+        .unwrap();
+    assert_eq!(diagnostics.log_likelihoods.len(), observations.len());
+    assert_eq!(
+        diagnostics.skipped,
+        [true, false, false, false, true, false]
+    );
+    let previous = core::iter::once(initial_estimate()).chain(estimates);
+    for (k, (z, estimate)) in observations.iter().zip(previous).enumerate() {
+        if diagnostics.skipped[k] {
+            assert!(diagnostics.innovations[k].is_none());
+            assert!(diagnostics.gains[k].is_none());
+            assert_eq!(diagnostics.log_likelihoods[k], 0.0);
+            continue;
+        }
+        let prior = transition.predict(&estimate);
+        let expected = observation.innovation(&prior, z);
+        let innovation = diagnostics.innovations[k].as_ref().unwrap();
+        approx::assert_relative_eq!(innovation.residual(), expected.residual(), epsilon = 1e-12);
+        approx::assert_relative_eq!(
+            innovation.covariance(),
+            expected.covariance(),
+            epsilon = 1e-12
+        );
+        let gain = prior.covariance()
+            * observation.H().transpose()
+            * expected.covariance().clone().try_inverse().unwrap();
+        approx::assert_relative_eq!(
+            diagnostics.gains[k].as_ref().unwrap(),
+            &gain,
+            epsilon = 1e-12
+        );
+        approx::assert_relative_eq!(
+            diagnostics.log_likelihoods[k],
+            expected.log_likelihood().unwrap(),
+            epsilon = 1e-12
+        );
+    }
+
+    // Errors give the index of the failed step.
+    observations[2] = DVector::zeros(2);
+    let err = kf
+        .filter_with_diagnostics(&initial_estimate(), &observations)
+        .unwrap_err();
+    assert!(matches!(err.kind(), ErrorKind::DimensionMismatch { .. }));
+    assert_eq!(err.step(), Some(2));
+}
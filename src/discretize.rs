@@ -0,0 +1,372 @@
+//! Exact discretization of linear time-invariant continuous-time models
+
+use alloc::borrow::Cow;
+
+use na::{DMatrix, RealField};
+use nalgebra as na;
+
+use crate::TransitionModelLinearNoControl;
+#[cfg(feature = "std")]
+use crate::{Error, KalmanFilterNoControl, StateAndCovariance};
+#[cfg(feature = "std")]
+use na::DVector;
+
+/// The method computing the discretization of a continuous-time model
+///
+/// Both are exact up to round-off and agree closely; they differ in the
+/// sizes of the matrices exponentiated.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum DiscretizationMethod {
+    /// Exponentiate the `2n x 2n` block matrix `[[-A, Qc], [0, A^T]] dt`,
+    /// whose blocks give `F` and `Q` (Van Loan's method).
+    #[default]
+    VanLoan,
+    /// Exponentiate `A dt / 2^s`, scaled so that its norm is at most 1/2,
+    /// and sum the power series of the integral for `Q` over the same
+    /// interval. `F` and `Q` are then doubled `s` times, with
+    /// `Q(2t) = Q(t) + F(t) Q(t) F(t)^T`. Only `n x n` matrices are
+    /// exponentiated.
+    MatrixExponential,
+}
+
+/// A linear transition model obtained by discretizing a continuous-time
+/// model over a fixed interval
+#[derive(Debug, Clone)]
+pub struct DiscretizedTransitionModel<R>
+where
+    R: RealField,
+{
+    F: DMatrix<R>,
+    FT: DMatrix<R>,
+    Q: DMatrix<R>,
+}
+
+impl<R> DiscretizedTransitionModel<R>
+where
+    R: RealField,
+{
+    /// Discretize `dx/dt = A x + w(t)`, where `w` is white noise with
+    /// spectral density `Qc`, over the interval `dt`.
+    ///
+    /// `F = exp(A dt)` and `Q` is the integral of `exp(A t) Qc exp(A^T t)`
+    /// over `[0, dt]`. Both are computed from a single matrix exponential of
+    /// the block matrix `[[-A, Qc], [0, A^T]] dt` (Van Loan's method), so no
+    /// first-order approximation is made.
+    pub fn new(A: &DMatrix<R>, Qc: &DMatrix<R>, dt: R) -> Self {
+        let n = A.nrows();
+        let mut M = DMatrix::zeros(2 * n, 2 * n);
+        M.slice_mut((0, 0), (n, n)).copy_from(&-A);
+        M.slice_mut((0, n), (n, n)).copy_from(Qc);
+        M.slice_mut((n, n), (n, n)).copy_from(&A.transpose());
+        M *= dt;
+        let E = M.exp();
+        let FT: DMatrix<R> = E.slice((n, n), (n, n)).into_owned();
+        let F = FT.transpose();
+        let Q = (&F * E.slice((0, n), (n, n))).symmetric_part();
+        Self { F, FT, Q }
+    }
+
+    /// Discretize `dx/dt = A x + w(t)` over the interval `dt`, like
+    /// [new](Self::new), with the given method.
+    pub fn with_method(
+        A: &DMatrix<R>,
+        Qc: &DMatrix<R>,
+        dt: R,
+        method: DiscretizationMethod,
+    ) -> Self {
+        match method {
+            DiscretizationMethod::VanLoan => Self::new(A, Qc, dt),
+            DiscretizationMethod::MatrixExponential => Self::by_matrix_exponential(A, Qc, dt),
+        }
+    }
+
+    /// See [DiscretizationMethod::MatrixExponential].
+    fn by_matrix_exponential(A: &DMatrix<R>, Qc: &DMatrix<R>, dt: R) -> Self {
+        let half: R = na::convert(0.5);
+        let mut h = dt;
+        let mut doublings = 0;
+        while (A * h.clone()).norm() > half && doublings < 64 {
+            h *= half.clone();
+            doublings += 1;
+        }
+        let mut F = (A * h.clone()).exp();
+
+        // exp(A t) Qc exp(A^T t) = sum_k t^k / k! M_k, with M_0 = Qc and
+        // M_k = A M_(k-1) + M_(k-1) A^T, integrated term by term.
+        let AT = A.transpose();
+        let mut M = Qc.clone();
+        let mut coefficient = h.clone();
+        let mut Q = &M * coefficient.clone();
+        for k in 2..64 {
+            M = A * &M + &M * &AT;
+            coefficient = coefficient * h.clone() / na::convert(k as f64);
+            let term = &M * coefficient.clone();
+            Q += &term;
+            if term.norm() <= R::default_epsilon() * Q.norm() {
+                break;
+            }
+        }
+
+        for _ in 0..doublings {
+            Q = &Q + &F * &Q * F.transpose();
+            F = &F * &F;
+        }
+        let FT = F.transpose();
+        Self {
+            F,
+            FT,
+            Q: Q.symmetric_part(),
+        }
+    }
+}
+
+impl<R> TransitionModelLinearNoControl<R> for DiscretizedTransitionModel<R>
+where
+    R: RealField,
+{
+    fn state_dim(&self) -> usize {
+        self.F.nrows()
+    }
+    fn F(&self) -> &DMatrix<R> {
+        &self.F
+    }
+    fn FT(&self) -> Cow<'_, DMatrix<R>> {
+        Cow::Borrowed(&self.FT)
+    }
+    fn Q(&self) -> &DMatrix<R> {
+        &self.Q
+    }
+}
+
+/// A continuous-time linear model which caches its discretization for
+/// recently used intervals
+///
+/// Computing a matrix exponential is relatively costly, so when observations
+/// arrive at a few distinct intervals, the discretized models are reused.
+#[cfg(feature = "std")]
+#[derive(Debug, Clone)]
+pub struct ContinuousLinearModel<R>
+where
+    R: RealField,
+{
+    A: DMatrix<R>,
+    Qc: DMatrix<R>,
+    method: DiscretizationMethod,
+    capacity: usize,
+    cache: Vec<(R, DiscretizedTransitionModel<R>)>,
+}
+
+#[cfg(feature = "std")]
+impl<R> ContinuousLinearModel<R>
+where
+    R: RealField,
+{
+    /// Create a new model `dx/dt = A x + w(t)` with process noise spectral
+    /// density `Qc`, caching discretizations for up to 16 intervals.
+    pub fn new(A: DMatrix<R>, Qc: DMatrix<R>) -> Self {
+        Self::with_capacity(A, Qc, 16)
+    }
+
+    /// Create a new model caching discretizations for up to `capacity`
+    /// intervals.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `capacity` is zero: the most recent discretization is
+    /// always kept, as [discretize](Self::discretize) returns a reference
+    /// to it.
+    pub fn with_capacity(A: DMatrix<R>, Qc: DMatrix<R>, capacity: usize) -> Self {
+        assert!(
+            capacity > 0,
+            "the cache must hold at least one discretization"
+        );
+        Self {
+            A,
+            Qc,
+            method: DiscretizationMethod::VanLoan,
+            capacity,
+            cache: Vec::new(),
+        }
+    }
+
+    /// Set the method of discretization. The default is
+    /// [DiscretizationMethod::VanLoan]. Cached discretizations made with
+    /// another method are discarded.
+    pub fn with_discretization_method(mut self, method: DiscretizationMethod) -> Self {
+        if method != self.method {
+            self.cache.clear();
+        }
+        self.method = method;
+        self
+    }
+
+    /// The discretized model for the interval `dt`.
+    ///
+    /// Intervals are matched exactly. When the cache is full, the least
+    /// recently used discretization is evicted.
+    pub fn discretize(&mut self, dt: R) -> &DiscretizedTransitionModel<R> {
+        match self.cache.iter().position(|(cached, _)| *cached == dt) {
+            Some(i) => {
+                let entry = self.cache.remove(i);
+                self.cache.push(entry);
+            }
+            None => {
+                let model = DiscretizedTransitionModel::with_method(
+                    &self.A,
+                    &self.Qc,
+                    dt.clone(),
+                    self.method,
+                );
+                if self.cache.len() >= self.capacity {
+                    self.cache.remove(0);
+                }
+                self.cache.push((dt, model));
+            }
+        }
+        // The requested model is now the most recently used entry.
+        &self.cache.last().unwrap().1
+    }
+
+    /// The number of cached discretizations.
+    pub fn cached(&self) -> usize {
+        self.cache.len()
+    }
+
+    /// The discretized model for each interval, in order.
+    fn discretize_all(&mut self, intervals: &[R]) -> Vec<DiscretizedTransitionModel<R>> {
+        intervals
+            .iter()
+            .map(|dt| self.discretize(dt.clone()).clone())
+            .collect()
+    }
+
+    /// Kalman filter irregularly sampled observations, where `intervals[k]`
+    /// is the time from the previous estimate to observation `k`, with the
+    /// observation model and configuration of `filter`, see
+    /// [filter_time_varying](crate::KalmanFilterNoControl::filter_time_varying).
+    pub fn filter_irregular(
+        &mut self,
+        filter: &KalmanFilterNoControl<R>,
+        initial_estimate: &StateAndCovariance<R>,
+        observations: &[DVector<R>],
+        intervals: &[R],
+    ) -> Result<Vec<StateAndCovariance<R>>, Error> {
+        let models = self.discretize_all(intervals);
+        let models: Vec<&dyn TransitionModelLinearNoControl<R>> =
+            models.iter().map(|m| m as _).collect();
+        filter.filter_time_varying(&models, initial_estimate, observations)
+    }
+
+    /// Rauch-Tung-Striebel (RTS) smooth irregularly sampled observations,
+    /// where `intervals[k]` is the time from the previous estimate to
+    /// observation `k`, with the observation model and configuration of
+    /// `filter`, see
+    /// [smooth_time_varying](crate::KalmanFilterNoControl::smooth_time_varying).
+    pub fn smooth_irregular(
+        &mut self,
+        filter: &KalmanFilterNoControl<R>,
+        initial_estimate: &StateAndCovariance<R>,
+        observations: &[DVector<R>],
+        intervals: &[R],
+    ) -> Result<Vec<StateAndCovariance<R>>, Error> {
+        let models = self.discretize_all(intervals);
+        let models: Vec<&dyn TransitionModelLinearNoControl<R>> =
+            models.iter().map(|m| m as _).collect();
+        filter.smooth_time_varying(&models, initial_estimate, observations)
+    }
+}
+
+#[test]
+fn test_van_loan_constant_velocity() {
+    use crate::test_util::ConstantVelocity;
+
+    let A = DMatrix::from_row_slice(2, 2, &[0.0, 1.0, 0.0, 0.0]);
+    let Qc = DMatrix::from_row_slice(2, 2, &[0.0, 0.0, 0.0, 3.0]);
+    let expected = ConstantVelocity::new(0.7, 3.0);
+
+    let mut model = ContinuousLinearModel::with_capacity(A, Qc, 2);
+    let discretized = model.discretize(0.7);
+    approx::assert_relative_eq!(discretized.F(), expected.F(), epsilon = 1e-12);
+    approx::assert_relative_eq!(
+        TransitionModelLinearNoControl::Q(discretized),
+        TransitionModelLinearNoControl::Q(&expected),
+        epsilon = 1e-12
+    );
+    model.discretize(0.7);
+    model.discretize(0.1);
+    model.discretize(0.2);
+    assert_eq!(model.cached(), 2);
+
+    // Irregular intervals are each discretized exactly.
+    use crate::test_util::{initial_estimate, PositionObservation};
+    let observation = PositionObservation::new(0.5);
+    let observations = [1.0, 1.2, 2.0].map(|z| na::DVector::from_element(1, z));
+    let intervals = [0.1, 0.7, 0.2];
+    let short = ConstantVelocity::new(0.1, 3.0);
+    let long = ConstantVelocity::new(0.7, 3.0);
+    let shorter = ConstantVelocity::new(0.2, 3.0);
+    let models: Vec<&dyn TransitionModelLinearNoControl<f64>> = vec![&short, &long, &shorter];
+    let kf = crate::KalmanFilterNoControl::new(&short, &observation);
+    let expected = kf
+        .smooth_time_varying(&models, &initial_estimate(), &observations)
+        .unwrap();
+    let actual = model
+        .smooth_irregular(&kf, &initial_estimate(), &observations, &intervals)
+        .unwrap();
+    approx::assert_relative_eq!(actual.as_slice(), expected.as_slice(), epsilon = 1e-9);
+}
+
+#[test]
+fn test_matrix_exponential_discretization() {
+    use crate::test_util::ConstantVelocity;
+
+    // The constant velocity model, for which the series terminates.
+    let A = DMatrix::from_row_slice(2, 2, &[0.0, 1.0, 0.0, 0.0]);
+    let Qc = DMatrix::from_row_slice(2, 2, &[0.0, 0.0, 0.0, 3.0]);
+    let expected = ConstantVelocity::new(0.7, 3.0);
+    let discretized = DiscretizedTransitionModel::with_method(
+        &A,
+        &Qc,
+        0.7,
+        DiscretizationMethod::MatrixExponential,
+    );
+    approx::assert_relative_eq!(discretized.F(), expected.F(), epsilon = 1e-12);
+    approx::assert_relative_eq!(
+        TransitionModelLinearNoControl::Q(&discretized),
+        TransitionModelLinearNoControl::Q(&expected),
+        epsilon = 1e-12
+    );
+
+    // A scalar Ornstein-Uhlenbeck process over a long interval, which is
+    // scaled and doubled: F = exp(-dt) and Q = (1 - exp(-2 dt)) / 2.
+    let A = DMatrix::from_element(1, 1, -1.0);
+    let Qc = DMatrix::from_element(1, 1, 1.0);
+    let discretized = DiscretizedTransitionModel::with_method(
+        &A,
+        &Qc,
+        5.0,
+        DiscretizationMethod::MatrixExponential,
+    );
+    approx::assert_relative_eq!(discretized.F()[(0, 0)], (-5.0f64).exp(), epsilon = 1e-12);
+    approx::assert_relative_eq!(
+        discretized.Q[(0, 0)],
+        (1.0 - (-10.0f64).exp()) / 2.0,
+        epsilon = 1e-12
+    );
+
+    // A damped oscillator agrees with Van Loan's method.
+    let A = DMatrix::from_row_slice(2, 2, &[0.0, 1.0, -4.0, -0.4]);
+    let Qc = DMatrix::from_row_slice(2, 2, &[0.0, 0.0, 0.0, 0.5]);
+    let mut model = ContinuousLinearModel::new(A.clone(), Qc.clone())
+        .with_discretization_method(DiscretizationMethod::MatrixExponential);
+    let expected = DiscretizedTransitionModel::new(&A, &Qc, 3.0);
+    let actual = model.discretize(3.0);
+    approx::assert_relative_eq!(actual.F(), expected.F(), epsilon = 1e-10);
+    approx::assert_relative_eq!(actual.Q, expected.Q, epsilon = 1e-10);
+}
+
+#[test]
+#[should_panic]
+fn test_zero_capacity() {
+    ContinuousLinearModel::<f64>::with_capacity(DMatrix::zeros(1, 1), DMatrix::zeros(1, 1), 0);
+}
@@ -0,0 +1,1032 @@
+//! Double-double scalar type for high-precision validation runs
+//!
+//! A [DoubleDouble] is the unevaluated sum of two `f64`, the second no
+//! larger than half an ulp of the first, giving about 106 bits (32 decimal
+//! digits) of precision. Running the filter and smoother with this type
+//! gives golden baselines against which the round-off of the `f64` and `f32`
+//! paths can be measured.
+//!
+//! The type is implemented here rather than wrapping `twofloat` or `rug`.
+//! Neither implements the `nalgebra`/`simba` scalar traits, so a wrapper
+//! would need every trait implementation below anyway, and the
+//! double-double arithmetic itself is only a small part of this module.
+//! `rug` additionally links the GMP and MPFR C libraries and is not `Copy`,
+//! which `nalgebra` matrices of it would pay for in every operation. The
+//! arithmetic and the elementary functions used by the filter are tested
+//! against reference values computed to 60 decimal digits.
+
+use core::cmp::Ordering;
+use core::fmt;
+use core::ops::{
+    Add, AddAssign, Div, DivAssign, Mul, MulAssign, Neg, Rem, RemAssign, Sub, SubAssign,
+};
+use core::str::FromStr;
+
+use approx::{AbsDiffEq, RelativeEq, UlpsEq};
+use na::{ComplexField, RealField, SimdValue};
+use nalgebra as na;
+use num_traits::{FromPrimitive, Num, One, Signed, Zero};
+use simba::scalar::SubsetOf;
+
+/// A real number represented as the unevaluated sum of two `f64`
+///
+/// Addition, subtraction, multiplication, division, `sqrt`, `exp` and `ln`
+/// (and the functions derived from them, such as `powf` and `log10`) are
+/// accurate to about 106 bits. The trigonometric and hyperbolic functions and
+/// their inverses are only evaluated to the precision of `f64`; none of them
+/// is used by the linear filter and smoother.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct DoubleDouble {
+    hi: f64,
+    lo: f64,
+}
+
+/// The error returned when parsing a [DoubleDouble] fails
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ParseDoubleDoubleError;
+
+impl fmt::Display for ParseDoubleDoubleError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "invalid double-double literal")
+    }
+}
+
+impl std::error::Error for ParseDoubleDoubleError {}
+
+const PI: DoubleDouble = DoubleDouble {
+    hi: core::f64::consts::PI,
+    lo: 1.2246467991473532e-16,
+};
+const E: DoubleDouble = DoubleDouble {
+    hi: core::f64::consts::E,
+    lo: 1.4456468917292502e-16,
+};
+const LN_2: DoubleDouble = DoubleDouble {
+    hi: core::f64::consts::LN_2,
+    lo: 2.3190468138462996e-17,
+};
+const LN_10: DoubleDouble = DoubleDouble {
+    hi: core::f64::consts::LN_10,
+    lo: -2.1707562233822494e-16,
+};
+
+/// `a + b` and its rounding error.
+fn two_sum(a: f64, b: f64) -> (f64, f64) {
+    let s = a + b;
+    let bb = s - a;
+    (s, (a - (s - bb)) + (b - bb))
+}
+
+/// `a + b` and its rounding error, given `|a| >= |b|`.
+fn quick_two_sum(a: f64, b: f64) -> (f64, f64) {
+    let s = a + b;
+    (s, b - (s - a))
+}
+
+/// `a * b` and its rounding error.
+fn two_prod(a: f64, b: f64) -> (f64, f64) {
+    let p = a * b;
+    (p, a.mul_add(b, -p))
+}
+
+impl DoubleDouble {
+    /// The difference between one and the next larger representable number,
+    /// `2^-104`.
+    pub const EPSILON: Self = Self {
+        hi: 4.930380657631324e-32,
+        lo: 0.0,
+    };
+
+    /// Create the sum `hi + lo`, renormalized.
+    pub fn new(hi: f64, lo: f64) -> Self {
+        let (hi, lo) = two_sum(hi, lo);
+        Self { hi, lo }
+    }
+
+    /// The leading component, the value rounded to `f64`.
+    pub fn hi(&self) -> f64 {
+        self.hi
+    }
+
+    /// The trailing component.
+    pub fn lo(&self) -> f64 {
+        self.lo
+    }
+
+    fn from_quick_two_sum(a: f64, b: f64) -> Self {
+        let (hi, lo) = quick_two_sum(a, b);
+        Self { hi, lo }
+    }
+
+    fn is_nan(&self) -> bool {
+        self.hi.is_nan()
+    }
+
+    fn mul_f64(self, b: f64) -> Self {
+        let (p, e) = two_prod(self.hi, b);
+        if !p.is_finite() {
+            return p.into();
+        }
+        Self::from_quick_two_sum(p, e + self.lo * b)
+    }
+
+    /// Multiply by `2^n`, exactly unless the result overflows or underflows.
+    fn ldexp(self, n: i32) -> Self {
+        let half = 2f64.powi(n / 2);
+        let rest = 2f64.powi(n - n / 2);
+        Self {
+            hi: self.hi * half * rest,
+            lo: self.lo * half * rest,
+        }
+    }
+
+    /// `exp(x) - 1` for `|x|` no larger than about 0.35.
+    fn exp_m1_reduced(self) -> Self {
+        // Sum the series for x / 2^10, then double the argument ten times
+        // with exp(2x) - 1 = (exp(x) - 1) (exp(x) + 1).
+        let r = self.ldexp(-10);
+        let mut term = r;
+        let mut sum = r;
+        for n in 2..20 {
+            term = term * r / Self::from(n as f64);
+            sum += term;
+            if term.hi.abs() <= Self::EPSILON.hi * sum.hi.abs() {
+                break;
+            }
+        }
+        for _ in 0..10 {
+            sum = sum * (sum + Self::from(2.0));
+        }
+        sum
+    }
+}
+
+impl From<f64> for DoubleDouble {
+    fn from(x: f64) -> Self {
+        Self { hi: x, lo: 0.0 }
+    }
+}
+
+impl From<DoubleDouble> for f64 {
+    fn from(x: DoubleDouble) -> Self {
+        x.hi
+    }
+}
+
+impl PartialOrd for DoubleDouble {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        match self.hi.partial_cmp(&other.hi) {
+            Some(Ordering::Equal) => self.lo.partial_cmp(&other.lo),
+            ordering => ordering,
+        }
+    }
+}
+
+impl Neg for DoubleDouble {
+    type Output = Self;
+    fn neg(self) -> Self {
+        Self {
+            hi: -self.hi,
+            lo: -self.lo,
+        }
+    }
+}
+
+impl Add for DoubleDouble {
+    type Output = Self;
+    fn add(self, other: Self) -> Self {
+        let (s, e) = two_sum(self.hi, other.hi);
+        if !s.is_finite() {
+            return s.into();
+        }
+        let (t, f) = two_sum(self.lo, other.lo);
+        let (s, e) = quick_two_sum(s, e + t);
+        Self::from_quick_two_sum(s, e + f)
+    }
+}
+
+impl Sub for DoubleDouble {
+    type Output = Self;
+    fn sub(self, other: Self) -> Self {
+        self + -other
+    }
+}
+
+impl Mul for DoubleDouble {
+    type Output = Self;
+    fn mul(self, other: Self) -> Self {
+        let (p, e) = two_prod(self.hi, other.hi);
+        if !p.is_finite() {
+            return p.into();
+        }
+        Self::from_quick_two_sum(p, e + (self.hi * other.lo + self.lo * other.hi))
+    }
+}
+
+impl Div for DoubleDouble {
+    type Output = Self;
+    fn div(self, other: Self) -> Self {
+        let q1 = self.hi / other.hi;
+        if !q1.is_finite() || other.hi == 0.0 {
+            return q1.into();
+        }
+        let r = self - other.mul_f64(q1);
+        let q2 = r.hi / other.hi;
+        let r = r - other.mul_f64(q2);
+        let q3 = r.hi / other.hi;
+        Self::from_quick_two_sum(q1, q2) + Self::from(q3)
+    }
+}
+
+impl Rem for DoubleDouble {
+    type Output = Self;
+    fn rem(self, other: Self) -> Self {
+        self - other * (self / other).trunc()
+    }
+}
+
+macro_rules! impl_assign {
+    ($($Trait:ident, $method:ident, $op:tt);*) => {$(
+        impl $Trait for DoubleDouble {
+            fn $method(&mut self, other: Self) {
+                *self = *self $op other;
+            }
+        }
+    )*};
+}
+
+impl_assign!(
+    AddAssign, add_assign, +;
+    SubAssign, sub_assign, -;
+    MulAssign, mul_assign, *;
+    DivAssign, div_assign, /;
+    RemAssign, rem_assign, %
+);
+
+impl Zero for DoubleDouble {
+    fn zero() -> Self {
+        0.0.into()
+    }
+    fn is_zero(&self) -> bool {
+        self.hi == 0.0
+    }
+}
+
+impl One for DoubleDouble {
+    fn one() -> Self {
+        1.0.into()
+    }
+}
+
+impl Num for DoubleDouble {
+    type FromStrRadixErr = ParseDoubleDoubleError;
+
+    /// Parse a decimal literal such as `-1.25e-3`; only radix 10 is
+    /// supported.
+    fn from_str_radix(s: &str, radix: u32) -> Result<Self, Self::FromStrRadixErr> {
+        if radix != 10 {
+            return Err(ParseDoubleDoubleError);
+        }
+        if let Ok(x) = s.parse::<f64>() {
+            if !x.is_finite() {
+                return Ok(x.into());
+            }
+        }
+        let (negative, s) = match s.strip_prefix('-') {
+            Some(rest) => (true, rest),
+            None => (false, s.strip_prefix('+').unwrap_or(s)),
+        };
+        let (mantissa, exponent) = match s.find(['e', 'E']) {
+            Some(i) => (
+                &s[..i],
+                s[i + 1..]
+                    .parse::<i32>()
+                    .map_err(|_| ParseDoubleDoubleError)?,
+            ),
+            None => (s, 0),
+        };
+        let (integer, fraction) = mantissa.split_once('.').unwrap_or((mantissa, ""));
+        if integer.is_empty() && fraction.is_empty() {
+            return Err(ParseDoubleDoubleError);
+        }
+        let ten = Self::from(10.0);
+        let mut value = Self::zero();
+        for c in integer.chars().chain(fraction.chars()) {
+            let digit = c.to_digit(10).ok_or(ParseDoubleDoubleError)?;
+            value = value * ten + Self::from(digit as f64);
+        }
+        let exponent = exponent - fraction.len() as i32;
+        if exponent < 0 {
+            value /= ten.powi(-exponent);
+        } else {
+            value *= ten.powi(exponent);
+        }
+        Ok(if negative { -value } else { value })
+    }
+}
+
+impl FromStr for DoubleDouble {
+    type Err = ParseDoubleDoubleError;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::from_str_radix(s, 10)
+    }
+}
+
+impl fmt::Display for DoubleDouble {
+    /// Format in scientific notation, with the given precision or 31 digits
+    /// after the decimal point.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if !self.hi.is_finite() || self.is_zero() {
+            return fmt::Display::fmt(&self.hi, f);
+        }
+        let precision = f.precision().unwrap_or(31);
+        let ten = Self::from(10.0);
+        let mut x = self.abs();
+        let mut exponent = x.hi.log10().floor() as i32;
+        x = if exponent < 0 {
+            x * ten.powi(-exponent)
+        } else {
+            x / ten.powi(exponent)
+        };
+        if x >= ten {
+            x /= ten;
+            exponent += 1;
+        } else if x < Self::one() {
+            x *= ten;
+            exponent -= 1;
+        }
+        let mut digits = Vec::with_capacity(precision + 2);
+        for _ in 0..precision + 2 {
+            let digit = x.hi.floor().clamp(0.0, 9.0);
+            digits.push(digit as u8);
+            x = (x - Self::from(digit)) * ten;
+        }
+        // Round to nearest on the extra digit, carrying to the left.
+        let round_up = digits.pop().unwrap() >= 5;
+        if round_up {
+            let mut i = digits.len();
+            loop {
+                if i == 0 {
+                    digits.insert(0, 1);
+                    digits.pop();
+                    exponent += 1;
+                    break;
+                }
+                i -= 1;
+                if digits[i] == 9 {
+                    digits[i] = 0;
+                } else {
+                    digits[i] += 1;
+                    break;
+                }
+            }
+        }
+        if self.hi < 0.0 {
+            write!(f, "-")?;
+        }
+        write!(f, "{}", digits[0])?;
+        if digits.len() > 1 {
+            write!(f, ".")?;
+            for digit in &digits[1..] {
+                write!(f, "{}", digit)?;
+            }
+        }
+        write!(f, "e{}", exponent)
+    }
+}
+
+impl FromPrimitive for DoubleDouble {
+    fn from_i64(n: i64) -> Option<Self> {
+        let hi = n as f64;
+        Some(Self::from_quick_two_sum(
+            hi,
+            (n as i128 - hi as i128) as f64,
+        ))
+    }
+    fn from_u64(n: u64) -> Option<Self> {
+        let hi = n as f64;
+        Some(Self::from_quick_two_sum(
+            hi,
+            (n as i128 - hi as i128) as f64,
+        ))
+    }
+    fn from_f64(x: f64) -> Option<Self> {
+        Some(x.into())
+    }
+}
+
+impl Signed for DoubleDouble {
+    fn abs(&self) -> Self {
+        if self.hi < 0.0 {
+            -*self
+        } else {
+            *self
+        }
+    }
+    fn abs_sub(&self, other: &Self) -> Self {
+        if *self <= *other {
+            Self::zero()
+        } else {
+            *self - *other
+        }
+    }
+    fn signum(&self) -> Self {
+        self.hi.signum().into()
+    }
+    fn is_positive(&self) -> bool {
+        self.hi.is_sign_positive()
+    }
+    fn is_negative(&self) -> bool {
+        self.hi.is_sign_negative()
+    }
+}
+
+impl AbsDiffEq for DoubleDouble {
+    type Epsilon = Self;
+    fn default_epsilon() -> Self {
+        Self::EPSILON
+    }
+    fn abs_diff_eq(&self, other: &Self, epsilon: Self) -> bool {
+        (*self - *other).abs() <= epsilon
+    }
+}
+
+impl RelativeEq for DoubleDouble {
+    fn default_max_relative() -> Self {
+        Self::EPSILON
+    }
+    fn relative_eq(&self, other: &Self, epsilon: Self, max_relative: Self) -> bool {
+        if self == other {
+            return true;
+        }
+        if self.hi.is_infinite() || other.hi.is_infinite() {
+            return false;
+        }
+        let difference = (*self - *other).abs();
+        difference <= epsilon || difference <= self.abs().max(other.abs()) * max_relative
+    }
+}
+
+impl UlpsEq for DoubleDouble {
+    fn default_max_ulps() -> u32 {
+        4
+    }
+    /// Compare in units of [EPSILON](DoubleDouble::EPSILON) relative to the
+    /// larger magnitude, as the spacing of double-double numbers is not
+    /// uniform.
+    fn ulps_eq(&self, other: &Self, epsilon: Self, max_ulps: u32) -> bool {
+        if self.abs_diff_eq(other, epsilon) {
+            return true;
+        }
+        if self.hi.is_sign_positive() != other.hi.is_sign_positive() {
+            return false;
+        }
+        let tolerance = Self::EPSILON * Self::from(max_ulps as f64);
+        (*self - *other).abs() <= self.abs().max(other.abs()) * tolerance
+    }
+}
+
+impl SimdValue for DoubleDouble {
+    type Element = Self;
+    type SimdBool = bool;
+
+    fn lanes() -> usize {
+        1
+    }
+    fn splat(val: Self) -> Self {
+        val
+    }
+    fn extract(&self, _: usize) -> Self {
+        *self
+    }
+    unsafe fn extract_unchecked(&self, _: usize) -> Self {
+        *self
+    }
+    fn replace(&mut self, _: usize, val: Self) {
+        *self = val
+    }
+    unsafe fn replace_unchecked(&mut self, _: usize, val: Self) {
+        *self = val
+    }
+    fn select(self, cond: bool, other: Self) -> Self {
+        if cond {
+            self
+        } else {
+            other
+        }
+    }
+}
+
+impl na::Field for DoubleDouble {}
+
+impl SubsetOf<DoubleDouble> for DoubleDouble {
+    fn to_superset(&self) -> Self {
+        *self
+    }
+    fn from_superset_unchecked(element: &Self) -> Self {
+        *element
+    }
+    fn is_in_subset(_: &Self) -> bool {
+        true
+    }
+}
+
+impl SubsetOf<DoubleDouble> for f64 {
+    fn to_superset(&self) -> DoubleDouble {
+        (*self).into()
+    }
+    fn from_superset_unchecked(element: &DoubleDouble) -> Self {
+        element.hi
+    }
+    fn is_in_subset(_: &DoubleDouble) -> bool {
+        true
+    }
+}
+
+impl SubsetOf<f64> for DoubleDouble {
+    fn to_superset(&self) -> f64 {
+        self.hi
+    }
+    fn from_superset_unchecked(element: &f64) -> Self {
+        (*element).into()
+    }
+    fn is_in_subset(_: &f64) -> bool {
+        true
+    }
+}
+
+/// Evaluate an `f64` function of the leading component.
+macro_rules! f64_functions {
+    ($($name:ident),*) => {$(
+        fn $name(self) -> Self {
+            self.hi.$name().into()
+        }
+    )*};
+}
+
+impl ComplexField for DoubleDouble {
+    type RealField = Self;
+
+    fn from_real(re: Self) -> Self {
+        re
+    }
+    fn real(self) -> Self {
+        self
+    }
+    fn imaginary(self) -> Self {
+        Self::zero()
+    }
+    fn modulus(self) -> Self {
+        self.abs()
+    }
+    fn modulus_squared(self) -> Self {
+        self * self
+    }
+    fn argument(self) -> Self {
+        if self >= Self::zero() {
+            Self::zero()
+        } else {
+            PI
+        }
+    }
+    fn norm1(self) -> Self {
+        self.abs()
+    }
+    fn scale(self, factor: Self) -> Self {
+        self * factor
+    }
+    fn unscale(self, factor: Self) -> Self {
+        self / factor
+    }
+
+    fn floor(self) -> Self {
+        let hi = self.hi.floor();
+        if hi == self.hi {
+            Self::from_quick_two_sum(hi, self.lo.floor())
+        } else {
+            hi.into()
+        }
+    }
+    fn ceil(self) -> Self {
+        -(-self).floor()
+    }
+    fn round(self) -> Self {
+        let half = Self::from(0.5);
+        if self.hi < 0.0 {
+            -(-self + half).floor()
+        } else {
+            (self + half).floor()
+        }
+    }
+    fn trunc(self) -> Self {
+        if self.hi < 0.0 {
+            self.ceil()
+        } else {
+            self.floor()
+        }
+    }
+    fn fract(self) -> Self {
+        self - self.trunc()
+    }
+    fn mul_add(self, a: Self, b: Self) -> Self {
+        self * a + b
+    }
+
+    fn abs(self) -> Self {
+        Signed::abs(&self)
+    }
+    fn hypot(self, other: Self) -> Self {
+        let (a, b) = (self.abs(), other.abs());
+        let scale = a.max(b);
+        if scale.is_zero() || !scale.hi.is_finite() {
+            return scale;
+        }
+        let (a, b) = (a / scale, b / scale);
+        scale * (a * a + b * b).sqrt()
+    }
+
+    fn recip(self) -> Self {
+        Self::one() / self
+    }
+    fn conjugate(self) -> Self {
+        self
+    }
+
+    f64_functions!(sin, cos, tan, asin, acos, atan, sinh, cosh, tanh, asinh, acosh, atanh);
+
+    fn sin_cos(self) -> (Self, Self) {
+        (self.sin(), self.cos())
+    }
+
+    fn log(self, base: Self) -> Self {
+        self.ln() / base.ln()
+    }
+    fn log2(self) -> Self {
+        self.ln() / LN_2
+    }
+    fn log10(self) -> Self {
+        self.ln() / LN_10
+    }
+    fn ln(self) -> Self {
+        if self.hi.is_nan() || self.hi <= 0.0 || self.hi.is_infinite() {
+            return self.hi.ln().into();
+        }
+        // One Newton step on exp(y) = x doubles the precision of the f64
+        // logarithm.
+        let y = Self::from(self.hi.ln());
+        y + self * (-y).exp() - Self::one()
+    }
+    fn ln_1p(self) -> Self {
+        if self.hi.is_nan() || self.hi <= -1.0 || self.hi.is_infinite() {
+            return self.hi.ln_1p().into();
+        }
+        let y = Self::from(self.hi.ln_1p());
+        let e = y.exp_m1();
+        y - (e - self) / (e + Self::one())
+    }
+    fn sqrt(self) -> Self {
+        if self.is_zero() {
+            return self;
+        }
+        if self.hi.is_nan() || self.hi < 0.0 || self.hi.is_infinite() {
+            return self.hi.sqrt().into();
+        }
+        // One Newton step from the f64 square root.
+        let x = 1.0 / self.hi.sqrt();
+        let ax = self.hi * x;
+        let (p, e) = two_prod(ax, ax);
+        let (hi, lo) = two_sum(ax, (self - Self::new(p, e)).hi * (x * 0.5));
+        Self { hi, lo }
+    }
+    fn exp(self) -> Self {
+        if self.hi > 709.8 {
+            return f64::INFINITY.into();
+        }
+        if self.hi < -745.2 {
+            return Self::zero();
+        }
+        if self.is_nan() {
+            return self;
+        }
+        // exp(x) = 2^k exp(r) with |r| <= ln(2) / 2.
+        let k = (self.hi / LN_2.hi).round();
+        let r = self - LN_2.mul_f64(k);
+        (r.exp_m1_reduced() + Self::one()).ldexp(k as i32)
+    }
+    fn exp2(self) -> Self {
+        (self * LN_2).exp()
+    }
+    fn exp_m1(self) -> Self {
+        if self.hi.abs() <= 0.35 {
+            self.exp_m1_reduced()
+        } else {
+            self.exp() - Self::one()
+        }
+    }
+    fn powi(self, n: i32) -> Self {
+        let mut base = self;
+        let mut power = n.unsigned_abs();
+        let mut result = Self::one();
+        while power > 0 {
+            if power & 1 == 1 {
+                result *= base;
+            }
+            base *= base;
+            power >>= 1;
+        }
+        if n < 0 {
+            result.recip()
+        } else {
+            result
+        }
+    }
+    fn powf(self, n: Self) -> Self {
+        if self.is_zero() {
+            return match n.partial_cmp(&Self::zero()) {
+                Some(Ordering::Greater) => Self::zero(),
+                Some(Ordering::Equal) => Self::one(),
+                _ => f64::INFINITY.into(),
+            };
+        }
+        (n * self.ln()).exp()
+    }
+    fn powc(self, n: Self) -> Self {
+        self.powf(n)
+    }
+    fn cbrt(self) -> Self {
+        if self.is_zero() || !self.hi.is_finite() {
+            return self;
+        }
+        // One Newton step on y^3 = x from the f64 cube root.
+        let y = Self::from(self.hi.cbrt());
+        y - (y * y * y - self) / (Self::from(3.0) * y * y)
+    }
+
+    fn is_finite(&self) -> bool {
+        self.hi.is_finite()
+    }
+    fn try_sqrt(self) -> Option<Self> {
+        if self >= Self::zero() {
+            Some(self.sqrt())
+        } else {
+            None
+        }
+    }
+}
+
+impl RealField for DoubleDouble {
+    fn is_sign_positive(&self) -> bool {
+        self.hi.is_sign_positive()
+    }
+    fn is_sign_negative(&self) -> bool {
+        self.hi.is_sign_negative()
+    }
+    fn copysign(self, sign: Self) -> Self {
+        if sign.hi.is_sign_negative() {
+            -self.abs()
+        } else {
+            self.abs()
+        }
+    }
+
+    fn max(self, other: Self) -> Self {
+        if self.is_nan() || other > self {
+            other
+        } else {
+            self
+        }
+    }
+    fn min(self, other: Self) -> Self {
+        if self.is_nan() || other < self {
+            other
+        } else {
+            self
+        }
+    }
+    fn clamp(self, min: Self, max: Self) -> Self {
+        assert!(min <= max);
+        self.max(min).min(max)
+    }
+    fn atan2(self, other: Self) -> Self {
+        self.hi.atan2(other.hi).into()
+    }
+
+    fn min_value() -> Option<Self> {
+        Some(f64::MIN.into())
+    }
+    fn max_value() -> Option<Self> {
+        Some(f64::MAX.into())
+    }
+
+    fn pi() -> Self {
+        PI
+    }
+    fn two_pi() -> Self {
+        PI.ldexp(1)
+    }
+    fn frac_pi_2() -> Self {
+        PI.ldexp(-1)
+    }
+    fn frac_pi_3() -> Self {
+        PI / Self::from(3.0)
+    }
+    fn frac_pi_4() -> Self {
+        PI.ldexp(-2)
+    }
+    fn frac_pi_6() -> Self {
+        PI / Self::from(6.0)
+    }
+    fn frac_pi_8() -> Self {
+        PI.ldexp(-3)
+    }
+    fn frac_1_pi() -> Self {
+        PI.recip()
+    }
+    fn frac_2_pi() -> Self {
+        PI.recip().ldexp(1)
+    }
+    fn frac_2_sqrt_pi() -> Self {
+        PI.sqrt().recip().ldexp(1)
+    }
+
+    fn e() -> Self {
+        E
+    }
+    fn log2_e() -> Self {
+        LN_2.recip()
+    }
+    fn log10_e() -> Self {
+        LN_10.recip()
+    }
+    fn ln_2() -> Self {
+        LN_2
+    }
+    fn ln_10() -> Self {
+        LN_10
+    }
+}
+
+#[test]
+fn test_double_double_arithmetic() {
+    let one = DoubleDouble::one();
+    let tiny = DoubleDouble::from(2f64.powi(-80));
+    assert_eq!((one + tiny) - one, tiny);
+    assert_eq!(((one + tiny) * (one + tiny)).lo(), 2f64.powi(-79));
+
+    let third = one / DoubleDouble::from(3.0);
+    assert!((third * DoubleDouble::from(3.0) - one).abs().hi() < 1e-31);
+    let root = DoubleDouble::from(2.0).sqrt();
+    assert!((root * root - DoubleDouble::from(2.0)).abs().hi() < 1e-31);
+
+    // The constants against their series: ln(2) = sum 1 / (k 2^k) and
+    // e = sum 1 / k!.
+    let mut ln_2 = DoubleDouble::zero();
+    let mut e = DoubleDouble::zero();
+    let mut factorial = one;
+    for k in 1..120 {
+        let k = DoubleDouble::from(k as f64);
+        ln_2 += (k * DoubleDouble::from(2.0).powi(k.hi() as i32)).recip();
+        e += factorial.recip();
+        factorial *= k;
+    }
+    assert!((ln_2 - LN_2).abs().hi() < 1e-31);
+    assert!((e - E).abs().hi() < 1e-31);
+    assert!((DoubleDouble::from(10.0).ln() - LN_10).abs().hi() < 1e-30);
+    assert!((E.ln() - one).abs().hi() < 1e-31);
+    assert!(
+        (DoubleDouble::from(0.5).exp().ln() - DoubleDouble::from(0.5))
+            .abs()
+            .hi()
+            < 1e-31
+    );
+    // exp(x) - 1 = x + x^2 / 2 + ... keeps its relative precision.
+    let x = DoubleDouble::from(1e-20);
+    assert!(
+        (x.exp_m1() - (x + x * x / DoubleDouble::from(2.0)))
+            .abs()
+            .hi()
+            < 1e-51
+    );
+
+    let x: DoubleDouble = "3.1415926535897932384626433832795".parse().unwrap();
+    assert!((x - PI).abs().hi() < 1e-31);
+    assert_eq!(
+        format!("{:.20}", PI),
+        "3.14159265358979323846e0".to_string()
+    );
+    assert_eq!(format!("{:.2}", DoubleDouble::from(-0.0999)), "-9.99e-2");
+    assert_eq!(format!("{:.1}", DoubleDouble::from(9.96)), "1.0e1");
+    assert!("1.2.3".parse::<DoubleDouble>().is_err());
+}
+
+#[test]
+fn test_double_double_reference_values() {
+    // The exact values rounded to double-double, from 60-digit decimal
+    // arithmetic. The argument of the last logarithm is the f64 nearest to
+    // 0.001, which is 0.001000000000000000020816681711721685...
+    let dd = DoubleDouble::from;
+    let cases = [
+        (
+            dd(2.0).sqrt(),
+            (core::f64::consts::SQRT_2, -9.667293313452913e-17),
+        ),
+        (dd(3.0).sqrt(), (1.7320508075688772, 1.0035084221806903e-16)),
+        (
+            dd(10.0) / dd(7.0),
+            (1.4285714285714286, -3.172065784643304e-17),
+        ),
+        (
+            dd(1.0).exp(),
+            (core::f64::consts::E, 1.4456468917292502e-16),
+        ),
+        (dd(0.5).exp(), (1.6487212707001282, -4.731568479435833e-17)),
+        (
+            dd(-3.0).exp(),
+            (0.049787068367863944, -1.4831389691394365e-18),
+        ),
+        (dd(20.0).exp(), (485165195.4097903, 4.880277289790406e-10)),
+        (dd(3.0).ln(), (1.0986122886681098, -9.07129723500153e-17)),
+        (
+            dd(0.001).ln(),
+            (-6.907755278982137, -2.1613487097372872e-16),
+        ),
+    ];
+    for (value, (hi, lo)) in cases {
+        let reference = DoubleDouble::new(hi, lo);
+        let error = ((value - reference) / reference).abs();
+        assert!(
+            error.hi() < 4.0 * DoubleDouble::EPSILON.hi(),
+            "{} {}",
+            value,
+            error
+        );
+    }
+}
+
+#[test]
+fn test_double_double_pipeline() {
+    use crate::test_util::{self, ConstantVelocity, PositionObservation};
+    use crate::{KalmanFilterNoControl, LinearObservationModel, LinearTransitionModel};
+    use crate::{StateAndCovariance, TransitionModelLinearNoControl};
+    use na::{DMatrix, DVector};
+
+    // A constant scalar observed n times with noise variance 3, from prior
+    // variance 1, has posterior variance 1 / (1 + n / 3): the f64 filter
+    // is accurate to about 1e-16, the double-double one to about 1e-31.
+    let constant = LinearTransitionModel::from_matrices(
+        DMatrix::<DoubleDouble>::identity(1, 1),
+        DMatrix::zeros(1, 1),
+    );
+    let observation = LinearObservationModel::from_matrices(
+        DMatrix::<DoubleDouble>::identity(1, 1),
+        DMatrix::from_element(1, 1, DoubleDouble::from(3.0)),
+    );
+    let kf = KalmanFilterNoControl::new(&constant, &observation);
+    let initial = StateAndCovariance::new(DVector::zeros(1), DMatrix::identity(1, 1));
+    let observations = vec![DVector::from_element(1, DoubleDouble::one()); 10];
+    let filtered = kf.filter(&initial, &observations).unwrap();
+    for (n, estimate) in filtered.iter().enumerate() {
+        let n = DoubleDouble::from(n as f64 + 1.0);
+        let expected = DoubleDouble::from(3.0) / (DoubleDouble::from(3.0) + n);
+        let error = (estimate.covariance()[(0, 0)] - expected).abs();
+        assert!(error.hi() < 1e-30, "{}", error);
+    }
+
+    // The round-off of the f64 smoother, measured against the double-double
+    // baseline, is small but not zero.
+    let transition = ConstantVelocity::new(0.1, 1.0);
+    let observation = PositionObservation::new(0.5);
+    let observations = test_util::simulate_positions(50, 0.1, 1.0, 0.5, 3);
+    let initial = test_util::initial_estimate();
+    let reference = KalmanFilterNoControl::new(&transition, &observation)
+        .smooth(&initial, &observations)
+        .unwrap();
+
+    let convert = |m: &DMatrix<f64>| m.map(DoubleDouble::from);
+    let transition = LinearTransitionModel::from_matrices(
+        convert(transition.F()),
+        convert(TransitionModelLinearNoControl::Q(&transition)),
+    );
+    let observation = LinearObservationModel::from_matrices(
+        convert(crate::ObservationModel::H(&observation)),
+        convert(crate::ObservationModel::R(&observation)),
+    );
+    let observations: Vec<DVector<DoubleDouble>> = observations
+        .iter()
+        .map(|z| z.map(DoubleDouble::from))
+        .collect();
+    let initial = StateAndCovariance::new(
+        initial.state().map(DoubleDouble::from),
+        convert(initial.covariance()),
+    );
+    let baseline = KalmanFilterNoControl::new(&transition, &observation)
+        .smooth(&initial, &observations)
+        .unwrap();
+    let mut largest = 0.0f64;
+    for (a, b) in reference.iter().zip(baseline.iter()) {
+        for (x, y) in a.state().iter().zip(b.state().iter()) {
+            largest = largest.max((*y - DoubleDouble::from(*x)).abs().hi());
+        }
+    }
+    assert!(largest > 0.0 && largest < 1e-12, "{}", largest);
+}
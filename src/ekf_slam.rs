@@ -0,0 +1,482 @@
+//! EKF-SLAM with range-bearing landmark measurements in the plane
+//!
+//! The state is the robot pose `[x, y, θ]` followed by the positions
+//! `[x, y]` of the landmarks mapped so far. Each measurement of a landmark is
+//! associated with a mapped landmark by the nearest-neighbour rule on the
+//! normalized innovation squared (NIS), and then either updates the state,
+//! initializes a new landmark, or is ignored as ambiguous. Landmarks are
+//! added with [StateAndCovariance::augment_correlated] and deleted with
+//! [StateAndCovariance::marginalize].
+//!
+//! Bearings are measured anticlockwise from the robot heading, in radians.
+
+use na::{DMatrix, DVector, RealField};
+use nalgebra as na;
+
+use crate::bearings::wrap_near;
+use crate::{
+    ChiSquareGate, CovarianceUpdateMethod, Error, ErrorKind, ObservationModel, StateAndCovariance,
+};
+
+/// The dimension of the robot pose.
+const POSE_DIM: usize = 3;
+
+/// A range and bearing measurement of a landmark from the robot
+#[derive(Debug, Clone, PartialEq)]
+pub struct RangeBearing<R>
+where
+    R: RealField,
+{
+    /// The distance to the landmark.
+    pub range: R,
+    /// The direction of the landmark, anticlockwise from the robot heading.
+    pub bearing: R,
+}
+
+/// What a measurement was used for, see [EkfSlam::observe]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Association {
+    /// The measurement was of the mapped landmark with this identifier, and
+    /// updated the state.
+    Existing(usize),
+    /// The measurement initialized a new landmark with this identifier.
+    New(usize),
+    /// The measurement was too close to a mapped landmark to be a new one,
+    /// but outside its association gate, and was not used.
+    Ignored,
+}
+
+/// A mapped landmark
+#[derive(Debug, Clone)]
+struct Landmark {
+    id: usize,
+    observations: usize,
+}
+
+/// The linearized measurement of one landmark, as an observation model of
+/// the whole state
+struct LandmarkObservation<R>
+where
+    R: RealField,
+{
+    index: usize,
+    H: DMatrix<R>,
+    R: DMatrix<R>,
+}
+
+impl<R> LandmarkObservation<R>
+where
+    R: RealField,
+{
+    /// Linearize the measurement of the landmark at `index` at `state`.
+    fn new(index: usize, state: &DVector<R>, R: &DMatrix<R>) -> Self {
+        let (dx, dy) = relative_position(state, index);
+        let q = dx.clone() * dx.clone() + dy.clone() * dy.clone();
+        let r = q.clone().sqrt();
+        let mut H = DMatrix::zeros(2, state.nrows());
+        let j = POSE_DIM + 2 * index;
+        H[(0, 0)] = -dx.clone() / r.clone();
+        H[(0, 1)] = -dy.clone() / r.clone();
+        H[(0, j)] = dx.clone() / r.clone();
+        H[(0, j + 1)] = dy.clone() / r;
+        H[(1, 0)] = dy.clone() / q.clone();
+        H[(1, 1)] = -dx.clone() / q.clone();
+        H[(1, 2)] = -R::one();
+        H[(1, j)] = -dy / q.clone();
+        H[(1, j + 1)] = dx / q;
+        Self {
+            index,
+            H,
+            R: R.clone(),
+        }
+    }
+}
+
+impl<R> ObservationModel<R> for LandmarkObservation<R>
+where
+    R: RealField,
+{
+    fn predict_observation(&self, state: &DVector<R>) -> DVector<R> {
+        let (dx, dy) = relative_position(state, self.index);
+        let range = (dx.clone() * dx.clone() + dy.clone() * dy.clone()).sqrt();
+        let bearing = dy.atan2(dx) - state[2].clone();
+        DVector::from_vec(vec![range, bearing])
+    }
+    fn H(&self) -> &DMatrix<R> {
+        &self.H
+    }
+    fn R(&self) -> &DMatrix<R> {
+        &self.R
+    }
+    fn state_dim(&self) -> usize {
+        self.H.ncols()
+    }
+    fn obs_dim(&self) -> usize {
+        2
+    }
+}
+
+/// The position of the landmark at `index` relative to the robot.
+fn relative_position<R: RealField>(state: &DVector<R>, index: usize) -> (R, R) {
+    let j = POSE_DIM + 2 * index;
+    (
+        state[j].clone() - state[0].clone(),
+        state[j + 1].clone() - state[1].clone(),
+    )
+}
+
+/// Simultaneous localization and mapping by an extended Kalman filter
+///
+/// Landmarks are identified by the order in which they were first seen,
+/// which stays fixed as others are deleted.
+pub struct EkfSlam<R>
+where
+    R: RealField,
+{
+    estimate: StateAndCovariance<R>,
+    landmarks: Vec<Landmark>,
+    next_id: usize,
+    measurement_covariance: DMatrix<R>,
+    association_gate: ChiSquareGate<R>,
+    new_landmark_gate: ChiSquareGate<R>,
+}
+
+impl<R> EkfSlam<R>
+where
+    R: RealField,
+{
+    /// Start with the estimate of the initial pose, `[x, y, θ]`, and no
+    /// landmarks
+    ///
+    /// The range and bearing measurements have standard deviations
+    /// `range_sigma` and `bearing_sigma`. A measurement is associated with
+    /// the nearest landmark if its NIS passes the 99% gate, and initializes
+    /// a new landmark if it fails the 99.99% gate of every landmark; see
+    /// [with_gates](Self::with_gates). An [ErrorKind::DimensionMismatch]
+    /// error is returned if the initial pose is not of dimension three.
+    pub fn new(
+        initial_pose: StateAndCovariance<R>,
+        range_sigma: R,
+        bearing_sigma: R,
+    ) -> Result<Self, Error> {
+        crate::check_shape(
+            "initial pose",
+            (POSE_DIM, POSE_DIM),
+            initial_pose.covariance(),
+        )?;
+        crate::check_dimension("initial pose", (POSE_DIM, 1), initial_pose.state().shape())?;
+        let measurement_covariance = DMatrix::from_diagonal(&DVector::from_vec(vec![
+            range_sigma.clone() * range_sigma,
+            bearing_sigma.clone() * bearing_sigma,
+        ]));
+        Ok(Self {
+            estimate: initial_pose,
+            landmarks: Vec::new(),
+            next_id: 0,
+            measurement_covariance,
+            association_gate: ChiSquareGate::new(2, na::convert(0.99)),
+            new_landmark_gate: ChiSquareGate::new(2, na::convert(0.9999)),
+        })
+    }
+
+    /// Set the gate for associating a measurement with a landmark, and the
+    /// gate which a measurement must fail for every landmark to initialize a
+    /// new one. The second should be at least as wide as the first.
+    pub fn with_gates(
+        mut self,
+        association_gate: ChiSquareGate<R>,
+        new_landmark_gate: ChiSquareGate<R>,
+    ) -> Self {
+        self.association_gate = association_gate;
+        self.new_landmark_gate = new_landmark_gate;
+        self
+    }
+
+    /// The estimate of the whole state, the pose followed by the landmarks in
+    /// the order of [landmark_ids](Self::landmark_ids).
+    pub fn estimate(&self) -> &StateAndCovariance<R> {
+        &self.estimate
+    }
+
+    /// The estimate of the robot pose, `[x, y, θ]`.
+    pub fn pose(&self) -> StateAndCovariance<R> {
+        let n = self.estimate.state().nrows();
+        let landmarks: Vec<usize> = (POSE_DIM..n).collect();
+        self.estimate.marginalize(&landmarks).unwrap()
+    }
+
+    /// The identifiers of the mapped landmarks.
+    pub fn landmark_ids(&self) -> impl Iterator<Item = usize> + '_ {
+        self.landmarks.iter().map(|l| l.id)
+    }
+
+    /// The estimate of the position of a landmark and the number of
+    /// measurements associated with it, including the one which initialized
+    /// it, or `None` if there is no such landmark.
+    pub fn landmark(&self, id: usize) -> Option<(StateAndCovariance<R>, usize)> {
+        let index = self.index(id)?;
+        let j = POSE_DIM + 2 * index;
+        let estimate = StateAndCovariance::new(
+            self.estimate.state().rows(j, 2).into_owned(),
+            self.estimate
+                .covariance()
+                .slice((j, j), (2, 2))
+                .into_owned(),
+        );
+        Some((estimate, self.landmarks[index].observations))
+    }
+
+    fn index(&self, id: usize) -> Option<usize> {
+        self.landmarks.iter().position(|l| l.id == id)
+    }
+
+    /// Predict the pose after the robot drives `distance` along its heading
+    /// and then turns by `rotation`
+    ///
+    /// `odometry_covariance` is the 2×2 covariance of the errors of the
+    /// distance and rotation. The landmarks are static, so only the pose
+    /// block and its cross-covariances with the landmarks change.
+    pub fn predict(&mut self, distance: R, rotation: R, odometry_covariance: &DMatrix<R>) {
+        let (state, covariance) = self.estimate.clone().inner();
+        let theta = state[2].clone();
+        let (sin, cos) = (theta.clone().sin(), theta.cos());
+        let mut new_state = state;
+        new_state[0] += distance.clone() * cos.clone();
+        new_state[1] += distance.clone() * sin.clone();
+        new_state[2] += rotation;
+
+        // The Jacobians of the pose with respect to the pose and to the
+        // odometry.
+        let G = DMatrix::from_row_slice(
+            3,
+            3,
+            &[
+                R::one(),
+                R::zero(),
+                -distance.clone() * sin.clone(),
+                R::zero(),
+                R::one(),
+                distance * cos.clone(),
+                R::zero(),
+                R::zero(),
+                R::one(),
+            ],
+        );
+        let V =
+            DMatrix::from_row_slice(3, 2, &[cos, R::zero(), sin, R::zero(), R::zero(), R::one()]);
+        let mut new_covariance = covariance.clone();
+        let pose_rows = &G * covariance.rows(0, POSE_DIM);
+        new_covariance.rows_mut(0, POSE_DIM).copy_from(&pose_rows);
+        new_covariance
+            .columns_mut(0, POSE_DIM)
+            .copy_from(&pose_rows.transpose());
+        let pose_block = pose_rows.columns(0, POSE_DIM) * G.transpose()
+            + &V * odometry_covariance * V.transpose();
+        new_covariance
+            .slice_mut((0, 0), (POSE_DIM, POSE_DIM))
+            .copy_from(&pose_block.symmetric_part());
+        self.estimate = StateAndCovariance::new(new_state, new_covariance);
+    }
+
+    /// Process a measurement of a landmark
+    ///
+    /// The measurement is associated with the landmark of the smallest NIS.
+    /// If that passes the association gate, the state is updated. If the
+    /// measurement fails the new-landmark gate of every landmark, a new
+    /// landmark is initialized at the measured position, with its
+    /// covariance and cross-covariances from the pose uncertainty and the
+    /// measurement noise. Otherwise it is ignored.
+    pub fn observe(&mut self, measurement: &RangeBearing<R>) -> Result<Association, Error> {
+        let mut nearest: Option<(usize, R, LandmarkObservation<R>, DVector<R>)> = None;
+        for index in 0..self.landmarks.len() {
+            let model = LandmarkObservation::new(
+                index,
+                self.estimate.state(),
+                &self.measurement_covariance,
+            );
+            let predicted = model.predict_observation(self.estimate.state());
+            let observation = DVector::from_vec(vec![
+                measurement.range.clone(),
+                wrap_near(measurement.bearing.clone(), predicted[1].clone()),
+            ]);
+            let nis = model.innovation(&self.estimate, &observation).nis()?;
+            if nearest.as_ref().is_none_or(|(_, best, _, _)| nis < *best) {
+                nearest = Some((index, nis, model, observation));
+            }
+        }
+        match nearest {
+            Some((index, nis, model, observation)) if self.association_gate.accepts_nis(&nis) => {
+                self.estimate = model.update(
+                    &self.estimate,
+                    &observation,
+                    CovarianceUpdateMethod::JosephForm,
+                )?;
+                self.landmarks[index].observations += 1;
+                Ok(Association::Existing(self.landmarks[index].id))
+            }
+            Some((_, nis, _, _)) if self.new_landmark_gate.accepts_nis(&nis) => {
+                Ok(Association::Ignored)
+            }
+            _ => Ok(Association::New(self.add_landmark(measurement)?)),
+        }
+    }
+
+    /// Initialize a landmark from a measurement, returning its identifier.
+    fn add_landmark(&mut self, measurement: &RangeBearing<R>) -> Result<usize, Error> {
+        let state = self.estimate.state();
+        let angle = state[2].clone() + measurement.bearing.clone();
+        let (sin, cos) = (angle.clone().sin(), angle.cos());
+        let r = measurement.range.clone();
+        let position = DVector::from_vec(vec![
+            state[0].clone() + r.clone() * cos.clone(),
+            state[1].clone() + r.clone() * sin.clone(),
+        ]);
+        // The Jacobians of the position with respect to the pose and to the
+        // measurement.
+        let G_pose = DMatrix::from_row_slice(
+            2,
+            3,
+            &[
+                R::one(),
+                R::zero(),
+                -r.clone() * sin.clone(),
+                R::zero(),
+                R::one(),
+                r.clone() * cos.clone(),
+            ],
+        );
+        let G_measurement =
+            DMatrix::from_row_slice(2, 2, &[cos.clone(), -r.clone() * sin.clone(), sin, r * cos]);
+        let P = self.estimate.covariance();
+        let P_pose = P.slice((0, 0), (POSE_DIM, POSE_DIM)).into_owned();
+        let covariance = &G_pose * P_pose * G_pose.transpose()
+            + &G_measurement * &self.measurement_covariance * G_measurement.transpose();
+        let cross = P.columns(0, POSE_DIM) * G_pose.transpose();
+        let landmark = StateAndCovariance::new(position, covariance.symmetric_part());
+        self.estimate = self.estimate.augment_correlated(&landmark, &cross)?;
+
+        let id = self.next_id;
+        self.next_id += 1;
+        self.landmarks.push(Landmark {
+            id,
+            observations: 1,
+        });
+        Ok(id)
+    }
+
+    /// Delete a landmark from the map, marginalizing it out of the state
+    ///
+    /// An [ErrorKind::DimensionMismatch] error is returned if there is no
+    /// landmark with this identifier.
+    pub fn remove_landmark(&mut self, id: usize) -> Result<(), Error> {
+        let index = match self.index(id) {
+            Some(index) => index,
+            None => {
+                return Err(ErrorKind::DimensionMismatch {
+                    expected: (self.next_id, 1),
+                    actual: (id + 1, 1),
+                    matrix: "landmark",
+                }
+                .into())
+            }
+        };
+        let j = POSE_DIM + 2 * index;
+        self.estimate = self.estimate.marginalize(&[j, j + 1])?;
+        self.landmarks.remove(index);
+        Ok(())
+    }
+
+    /// Delete the landmarks with fewer than `min_observations` associated
+    /// measurements, e.g. spurious landmarks initialized from clutter,
+    /// returning their identifiers.
+    pub fn prune_landmarks(&mut self, min_observations: usize) -> Vec<usize> {
+        let spurious: Vec<usize> = self
+            .landmarks
+            .iter()
+            .filter(|l| l.observations < min_observations)
+            .map(|l| l.id)
+            .collect();
+        for &id in &spurious {
+            self.remove_landmark(id).unwrap();
+        }
+        spurious
+    }
+}
+
+#[test]
+fn test_ekf_slam() {
+    use crate::numerical_jacobian;
+    use crate::test_util::Normals;
+
+    // The Jacobian of the linearized measurement.
+    let state = DVector::from_vec(vec![0.5, -0.2, 0.3, 4.0, 1.0, -2.0, 3.0]);
+    let model = LandmarkObservation::new(1, &state, &DMatrix::identity(2, 2));
+    let numerical = numerical_jacobian(|x| model.predict_observation(x), &state, 1e-6);
+    approx::assert_relative_eq!(model.H(), &numerical, epsilon = 1e-7);
+
+    // A robot driving around a circle among four landmarks.
+    let landmarks = [(5.0, 0.0), (0.0, 5.0), (-5.0, 0.0), (0.0, -5.0)];
+    let (range_sigma, bearing_sigma) = (0.05, 0.01);
+    let initial = StateAndCovariance::new(
+        DVector::from_vec(vec![2.0, 0.0, core::f64::consts::FRAC_PI_2]),
+        DMatrix::identity(3, 3) * 1e-6,
+    );
+    let mut slam = EkfSlam::new(initial, range_sigma, bearing_sigma).unwrap();
+    let mut normals = Normals::new(19);
+    let (distance, rotation) = (0.2, 0.1);
+    let odometry = DMatrix::from_diagonal(&DVector::from_vec(vec![1e-4, 1e-5]));
+    let mut pose = (2.0f64, 0.0f64, core::f64::consts::FRAC_PI_2);
+    // About 1% of the measurements fall between the gates and are ignored.
+    let mut ignored = 0;
+    for _ in 0..150 {
+        let d = distance + normals.sample() * 1e-2;
+        let t = rotation + normals.sample() * 1e-2f64.powf(1.5);
+        pose = (
+            pose.0 + d * pose.2.cos(),
+            pose.1 + d * pose.2.sin(),
+            pose.2 + t,
+        );
+        slam.predict(distance, rotation, &odometry);
+        for &(lx, ly) in &landmarks {
+            let (dx, dy) = (lx - pose.0, ly - pose.1);
+            let measurement = RangeBearing {
+                range: (dx * dx + dy * dy).sqrt() + normals.sample() * range_sigma,
+                bearing: dy.atan2(dx) - pose.2 + normals.sample() * bearing_sigma,
+            };
+            match slam.observe(&measurement).unwrap() {
+                Association::Ignored => ignored += 1,
+                Association::New(id) => assert!(id < landmarks.len()),
+                Association::Existing(_) => {}
+            }
+        }
+    }
+
+    assert!(ignored < 15, "{} measurements ignored", ignored);
+
+    // Each landmark was mapped once and is consistently estimated.
+    let ids: Vec<usize> = slam.landmark_ids().collect();
+    assert_eq!(ids, vec![0, 1, 2, 3]);
+    for (id, &(lx, ly)) in ids.iter().zip(&landmarks) {
+        let (estimate, observations) = slam.landmark(*id).unwrap();
+        assert!(observations > 140);
+        let error = estimate.state() - DVector::from_vec(vec![lx, ly]);
+        let nees = error.dot(&(estimate.covariance().clone().try_inverse().unwrap() * &error));
+        assert!(nees < 13.8, "landmark {}: NEES {}", id, nees);
+    }
+    let true_pose = DVector::from_vec(vec![pose.0, pose.1]);
+    assert!((slam.pose().state().rows(0, 2) - true_pose).norm() < 0.2);
+
+    // A spurious landmark far from the others is added, then pruned.
+    let clutter = RangeBearing {
+        range: 30.0,
+        bearing: 0.0,
+    };
+    assert_eq!(slam.observe(&clutter).unwrap(), Association::New(4));
+    assert_eq!(slam.estimate().state().nrows(), 3 + 2 * 5);
+    assert_eq!(slam.prune_landmarks(2), vec![4]);
+    assert_eq!(slam.estimate().state().nrows(), 3 + 2 * 4);
+    slam.remove_landmark(0).unwrap();
+    assert!(slam.landmark(0).is_none());
+    assert!(slam.landmark(1).is_some());
+    assert!(slam.remove_landmark(0).is_err());
+}
@@ -2,6 +2,10 @@
 #[derive(Debug)]
 pub struct Error {
     kind: ErrorKind,
+    operation: Option<Operation>,
+    step: Option<usize>,
+    #[cfg(feature = "std")]
+    matrix: Option<String>,
 }
 
 /// The kinds of errors
@@ -9,6 +13,139 @@ pub struct Error {
 pub enum ErrorKind {
     /// The covariance matrix is not positive semi-definite (or is not symmetric).
     CovarianceNotPositiveSemiDefinite,
+    /// A matrix which must be inverted is singular.
+    SingularMatrix,
+    /// An iterative computation did not converge.
+    NotConverged,
+    /// An observation was missing (NaN) where a complete one is required.
+    MissingObservation,
+    /// An entry of a model matrix has units inconsistent with the units of
+    /// the state and observation components.
+    UnitMismatch {
+        /// The name of the matrix.
+        matrix: &'static str,
+        /// The row of the entry.
+        row: usize,
+        /// The column of the entry.
+        col: usize,
+    },
+    /// A recording could not be encoded or decoded.
+    InvalidRecording,
+    /// Logging to a visualization recording stream failed.
+    LoggingFailed,
+    /// The timestamps of a measurement stream decrease, or precede the start
+    /// time.
+    UnorderedTimestamps {
+        /// The index of the stream.
+        stream: usize,
+    },
+    /// A sensor index does not refer to an added sensor.
+    UnknownSensor {
+        /// The index of the sensor.
+        sensor: usize,
+    },
+    /// The observation rows assigned to a sensor are out of range, or are
+    /// every row, leaving nothing to observe without the sensor.
+    InvalidSensorRows {
+        /// The index of the sensor.
+        sensor: usize,
+    },
+    /// A delayed measurement is older than the buffered steps.
+    DelayNotBuffered {
+        /// The delay of the measurement, in steps.
+        delay: usize,
+        /// The number of buffered steps.
+        buffered: usize,
+    },
+    /// A number of lags is zero, or is not less than the length of the
+    /// series.
+    InvalidLags {
+        /// The number of lags.
+        lags: usize,
+        /// The length of the series.
+        len: usize,
+    },
+    /// A model is inconsistent, as found by
+    /// [validate_models](crate::validate_models).
+    InvalidModel(ModelError),
+    /// A component of a series is constant, so its autocorrelation is
+    /// undefined.
+    ZeroVariance {
+        /// The index of the component.
+        component: usize,
+    },
+    /// A matrix or vector does not have the shape required by the models.
+    DimensionMismatch {
+        /// The required shape, as (rows, columns).
+        expected: (usize, usize),
+        /// The actual shape, as (rows, columns).
+        actual: (usize, usize),
+        /// The name of the matrix or vector.
+        matrix: &'static str,
+    },
+}
+
+/// The operation during which an error occurred
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Operation {
+    /// The update step, which inverts the innovation covariance.
+    Update,
+    /// A step of the RTS smoother, which inverts the prior covariance.
+    SmoothStep,
+    /// Evaluating the likelihood of an innovation.
+    Likelihood,
+}
+
+impl Error {
+    /// The kind of error.
+    pub fn kind(&self) -> &ErrorKind {
+        &self.kind
+    }
+
+    /// The operation during which the error occurred, if known.
+    pub fn operation(&self) -> Option<Operation> {
+        self.operation
+    }
+
+    /// The index of the time step at which the error occurred, if it
+    /// occurred in a method processing a whole time series.
+    pub fn step(&self) -> Option<usize> {
+        self.step
+    }
+
+    /// The offending matrix, formatted, if available.
+    #[cfg(feature = "std")]
+    pub fn matrix(&self) -> Option<&str> {
+        self.matrix.as_deref()
+    }
+
+    pub(crate) fn with_operation(mut self, operation: Operation) -> Self {
+        self.operation = Some(operation);
+        self
+    }
+
+    pub(crate) fn with_step(mut self, step: usize) -> Self {
+        // Keep the innermost step if the error passed through nested loops.
+        self.step.get_or_insert(step);
+        self
+    }
+
+    #[cfg(feature = "std")]
+    pub(crate) fn with_matrix<R>(mut self, matrix: &nalgebra::DMatrix<R>) -> Self
+    where
+        R: nalgebra::ComplexField,
+    {
+        self.matrix = Some(format!("{}", matrix));
+        self
+    }
+
+    #[cfg(not(feature = "std"))]
+    pub(crate) fn with_matrix<R>(self, _matrix: &nalgebra::DMatrix<R>) -> Self
+    where
+        R: nalgebra::ComplexField,
+    {
+        self
+    }
 }
 
 #[cfg(feature = "std")]
@@ -19,6 +156,72 @@ impl std::fmt::Display for ErrorKind {
             CovarianceNotPositiveSemiDefinite => {
                 "The covariance matrix is not positive semi-definite (or is not symmetric)"
             }
+            SingularMatrix => "A matrix which must be inverted is singular",
+            NotConverged => "An iterative computation did not converge",
+            MissingObservation => "An observation was missing where one is required",
+            InvalidRecording => "The recording is truncated, has an unknown format or is too large",
+            LoggingFailed => "Logging to the recording stream failed",
+            UnorderedTimestamps { stream } => {
+                return write!(f, "The timestamps of stream {} are out of order", stream);
+            }
+            UnknownSensor { sensor } => {
+                return write!(f, "There is no sensor {}", sensor);
+            }
+            InvalidSensorRows { sensor } => {
+                return write!(
+                    f,
+                    "The observation rows of sensor {} are out of range or include every row",
+                    sensor
+                );
+            }
+            DelayNotBuffered { delay, buffered } => {
+                return write!(
+                    f,
+                    "A delay of {} steps exceeds the {} buffered steps",
+                    delay, buffered
+                );
+            }
+            InvalidLags { lags, len } => {
+                return write!(
+                    f,
+                    "{} lags are invalid for a series of length {}",
+                    lags, len
+                );
+            }
+            InvalidModel(e) => return write!(f, "{}", e),
+            ZeroVariance { component } => {
+                return write!(f, "Component {} of the series has zero variance", component);
+            }
+            UnitMismatch { matrix, row, col } => {
+                return write!(
+                    f,
+                    "Entry ({}, {}) of {} has inconsistent units",
+                    row, col, matrix
+                );
+            }
+            DimensionMismatch {
+                expected,
+                actual,
+                matrix,
+            } => {
+                return write!(
+                    f,
+                    "{} has shape {}x{} but {}x{} is required",
+                    matrix, actual.0, actual.1, expected.0, expected.1
+                );
+            }
+        };
+        f.write_str(s)
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::fmt::Display for Operation {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        let s = match self {
+            Operation::Update => "update",
+            Operation::SmoothStep => "smoothing step",
+            Operation::Likelihood => "likelihood evaluation",
         };
         f.write_str(s)
     }
@@ -26,7 +229,52 @@ impl std::fmt::Display for ErrorKind {
 
 impl From<ErrorKind> for Error {
     fn from(kind: ErrorKind) -> Error {
-        Error { kind }
+        Error {
+            kind,
+            operation: None,
+            step: None,
+            #[cfg(feature = "std")]
+            matrix: None,
+        }
+    }
+}
+
+/// A problem with a model other than the shape of its matrices, found by
+/// [validate_models](crate::validate_models) and reported as
+/// [ErrorKind::InvalidModel]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ModelError {
+    /// A stored transpose, `FT` or `HT`, is not the transpose of its matrix.
+    NotTranspose {
+        /// The name of the stored transpose.
+        matrix: &'static str,
+    },
+    /// A covariance matrix is not symmetric.
+    NotSymmetric {
+        /// The name of the matrix.
+        matrix: &'static str,
+    },
+    /// A covariance matrix has a negative eigenvalue.
+    NotPositiveSemiDefinite {
+        /// The name of the matrix.
+        matrix: &'static str,
+    },
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for ModelError {}
+#[cfg(feature = "std")]
+impl std::fmt::Display for ModelError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            ModelError::NotTranspose { matrix } => {
+                write!(f, "{} is not the transpose of its matrix", matrix)
+            }
+            ModelError::NotSymmetric { matrix } => write!(f, "{} is not symmetric", matrix),
+            ModelError::NotPositiveSemiDefinite { matrix } => {
+                write!(f, "{} is not positive semi-definite", matrix)
+            }
+        }
     }
 }
 
@@ -35,6 +283,16 @@ impl std::error::Error for Error {}
 #[cfg(feature = "std")]
 impl std::fmt::Display for Error {
     fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
-        write!(f, "Kalman Filter Error: {}", self.kind)
+        write!(f, "Kalman Filter Error: {}", self.kind)?;
+        if let Some(operation) = self.operation {
+            write!(f, " during {}", operation)?;
+        }
+        if let Some(step) = self.step {
+            write!(f, " at step {}", step)?;
+        }
+        if let Some(matrix) = &self.matrix {
+            write!(f, "; matrix:{}", matrix)?;
+        }
+        Ok(())
     }
 }
@@ -0,0 +1,162 @@
+//! Recovery from numerical failures in long-running filters
+
+use approx::AbsDiffEq;
+use na::{ComplexField, DMatrix, RealField};
+use nalgebra as na;
+
+/// The number of times [FailurePolicy::InflateAndRetry] inflates a
+/// covariance before giving up.
+const MAX_INFLATIONS: usize = 10;
+
+/// What to do when a covariance cannot be factored
+///
+/// The update step inverts the innovation covariance and the RTS smoother
+/// inverts the prior covariance, using a Cholesky decomposition. Round-off
+/// can leave these slightly indefinite or asymmetric after many steps. By
+/// default this is an error, which aborts a whole batch; the other policies
+/// let an unattended filter continue.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub enum FailurePolicy<R>
+where
+    R: RealField,
+{
+    /// Return the error.
+    #[default]
+    Error,
+    /// Skip the failed operation. A failed update returns the prior, and a
+    /// failed smoothing step returns the filtered estimate.
+    SkipUpdate,
+    /// Symmetrize the prior covariance, multiply its diagonal by `factor`
+    /// and retry, up to ten times. A retried update whose posterior
+    /// covariance is still not positive semidefinite counts as failed.
+    InflateAndRetry {
+        /// The factor by which the diagonal is multiplied, greater than one.
+        factor: R,
+    },
+    /// Retry once with the prior covariance replaced by its diagonal, i.e.
+    /// discarding all correlations. As for
+    /// [InflateAndRetry](Self::InflateAndRetry), the posterior covariance
+    /// must be positive semidefinite.
+    ResetCovariance,
+}
+
+impl<R> FailurePolicy<R>
+where
+    R: RealField,
+{
+    /// The covariance to retry with after `attempt` failed retries, or
+    /// `None` to give up. The covariance may be over the complex field whose
+    /// real numbers are `R`.
+    pub(crate) fn repair<T>(&self, covariance: &DMatrix<T>, attempt: usize) -> Option<DMatrix<T>>
+    where
+        T: ComplexField<RealField = R>,
+    {
+        match self {
+            FailurePolicy::InflateAndRetry { factor } if attempt < MAX_INFLATIONS => {
+                let mut repaired = covariance.hermitian_part();
+                for i in 0..repaired.nrows() {
+                    repaired[(i, i)] *= T::from_real(factor.clone());
+                }
+                Some(repaired)
+            }
+            FailurePolicy::ResetCovariance if attempt == 0 => Some(DMatrix::from_diagonal(
+                &covariance.diagonal().map(|x| T::from_real(x.modulus())),
+            )),
+            _ => None,
+        }
+    }
+}
+
+/// Whether a symmetric (Hermitian) matrix is positive semidefinite, allowing
+/// for round-off relative to its largest element.
+pub(crate) fn is_positive_semidefinite<R: ComplexField>(covariance: &DMatrix<R>) -> bool {
+    let tolerance = R::RealField::default_epsilon().sqrt() * covariance.camax();
+    covariance
+        .clone()
+        .symmetric_eigenvalues()
+        .iter()
+        .all(|x| *x >= -tolerance.clone())
+}
+
+#[test]
+fn test_failure_policies() {
+    use crate::test_util::{ConstantVelocity, MatrixObservation, PositionObservation};
+    use crate::{KalmanFilterNoControl, StateAndCovariance};
+    use na::DVector;
+
+    // A prior covariance whose position components are perfectly
+    // correlated, and which round-off has left slightly indefinite. A
+    // noiseless observation of their difference has a negative innovation
+    // variance.
+    let transition = ConstantVelocity::new(0.1, 0.0);
+    let observation = MatrixObservation::new(
+        DMatrix::from_row_slice(1, 2, &[1.0, -1.0]),
+        DMatrix::zeros(1, 1),
+    );
+    let initial = StateAndCovariance::new(
+        DVector::from_vec(vec![0.0, 0.0]),
+        DMatrix::from_row_slice(2, 2, &[1.0, 1.0 + 1e-9, 1.0 + 1e-9, 1.0]),
+    );
+    let z = DVector::from_element(1, 0.1);
+    let stationary = ConstantVelocity::new(0.0, 0.0);
+    let kf = KalmanFilterNoControl::new(&stationary, &observation);
+    assert!(kf.step(&initial, &z).is_err());
+
+    let kf = kf.with_failure_policy(FailurePolicy::SkipUpdate);
+    let posterior = kf.step(&initial, &z).unwrap();
+    assert_eq!(&posterior, &initial);
+    // A skipped update does not contribute to the likelihood.
+    assert_eq!(
+        kf.log_likelihood(&initial, core::slice::from_ref(&z))
+            .unwrap(),
+        0.0
+    );
+
+    // Doubling the diagonal gives the prior covariance [[2, 1], [1, 2]], an
+    // innovation variance of 2 and the gain [0.5, -0.5].
+    let kf = kf.with_failure_policy(FailurePolicy::InflateAndRetry { factor: 2.0 });
+    let posterior = kf.step(&initial, &z).unwrap();
+    approx::assert_relative_eq!(posterior.state()[0], 0.05, epsilon = 1e-8);
+    approx::assert_relative_eq!(posterior.state()[1], -0.05, epsilon = 1e-8);
+    let expected = DMatrix::from_element(2, 2, 1.5);
+    approx::assert_relative_eq!(posterior.covariance(), &expected, epsilon = 1e-8);
+    assert!(is_positive_semidefinite(posterior.covariance()));
+
+    // The likelihood follows the same policy, scoring the observation
+    // against the inflated prior, N(0, 2) for the difference.
+    let log_likelihood = kf
+        .log_likelihood(&initial, core::slice::from_ref(&z))
+        .unwrap();
+    let expected = -0.5 * ((2.0 * core::f64::consts::PI * 2.0).ln() + 0.1 * 0.1 / 2.0);
+    approx::assert_relative_eq!(log_likelihood, expected, epsilon = 1e-8);
+
+    // Discarding the correlations also recovers.
+    let kf = kf.with_failure_policy(FailurePolicy::ResetCovariance);
+    let posterior = kf.step(&initial, &z).unwrap();
+    assert!(is_positive_semidefinite(posterior.covariance()));
+
+    // With an invalid, negative observation variance, no inflation gives a
+    // positive semidefinite posterior, so the policy fails.
+    let negative = PositionObservation::new(-0.2);
+    let kf = KalmanFilterNoControl::new(&transition, &negative)
+        .with_failure_policy(FailurePolicy::InflateAndRetry { factor: 2.0 });
+    let initial = StateAndCovariance::new(
+        DVector::from_vec(vec![0.0, 1.0]),
+        DMatrix::from_diagonal(&DVector::from_vec(vec![0.1, 0.0])),
+    );
+    assert!(kf.step(&initial, &z).is_err());
+
+    // The prior covariance is singular, so the smoother cannot invert it.
+    let observation = PositionObservation::new(0.5);
+    let initial = StateAndCovariance::new(
+        DVector::from_vec(vec![0.0, 1.0]),
+        DMatrix::from_diagonal(&DVector::from_vec(vec![1.0, 0.0])),
+    );
+    let observations = crate::test_util::simulate_positions(5, 0.1, 1.0, 0.5, 3);
+    let kf = KalmanFilterNoControl::new(&transition, &observation);
+    assert!(kf.smooth(&initial, &observations).is_err());
+    let kf = kf.with_failure_policy(FailurePolicy::SkipUpdate);
+    let filtered = kf.filter(&initial, &observations).unwrap();
+    let smoothed = kf.smooth(&initial, &observations).unwrap();
+    approx::assert_relative_eq!(smoothed[3].state(), filtered[3].state());
+}
@@ -0,0 +1,270 @@
+//! Residual-based fault detection and isolation (FDI)
+//!
+//! A bank of filters is run in parallel: one using every observation
+//! component and, for each sensor, one which excludes that sensor's
+//! components. When a sensor fails, every filter using it becomes
+//! inconsistent while the filter excluding it does not, which isolates the
+//! fault.
+
+use na::{DVector, RealField};
+use nalgebra as na;
+
+use crate::subset::SubsetObservationModel;
+use crate::{
+    is_nan, Error, ErrorKind, InnovationMonitor, NisStatus, ObservationModel, StateAndCovariance,
+    TransitionModelLinearNoControl,
+};
+
+/// The outcome of a [FaultDetector] step
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FaultReport {
+    /// Whether the filter using all observations is inconsistent.
+    pub fault_detected: bool,
+    /// Indices of the sensors whose exclusion restores consistency.
+    pub suspect_sensors: Vec<usize>,
+    /// The observation rows of the suspect sensors, which should not be
+    /// trusted.
+    pub suspect_rows: Vec<usize>,
+}
+
+struct Channel<'a, R>
+where
+    R: RealField,
+{
+    model: SubsetObservationModel<'a, R>,
+    estimate: StateAndCovariance<R>,
+    monitor: InnovationMonitor<R>,
+}
+
+impl<'a, R> Channel<'a, R>
+where
+    R: RealField,
+{
+    fn step(
+        &mut self,
+        transition_model: &dyn TransitionModelLinearNoControl<R>,
+        observation: &DVector<R>,
+    ) -> Result<(), Error> {
+        let prior = transition_model.predict(&self.estimate);
+        let observation = self.model.select(observation);
+        self.estimate = if observation.iter().any(|x| is_nan(x.clone())) {
+            prior
+        } else {
+            self.monitor
+                .push(&self.model.innovation(&prior, &observation))?;
+            self.model.update(
+                &prior,
+                &observation,
+                crate::CovarianceUpdateMethod::JosephForm,
+            )?
+        };
+        Ok(())
+    }
+}
+
+/// A bank of filters for detecting and isolating a faulty sensor
+pub struct FaultDetector<'a, R>
+where
+    R: RealField,
+{
+    transition_model: &'a dyn TransitionModelLinearNoControl<R>,
+    sensor_rows: Vec<Vec<usize>>,
+    full: Channel<'a, R>,
+    excluding: Vec<Channel<'a, R>>,
+}
+
+impl<'a, R> FaultDetector<'a, R>
+where
+    R: RealField,
+{
+    /// Create a new `FaultDetector`.
+    ///
+    /// `sensor_rows` lists, for each sensor, the rows of the observation
+    /// vector it provides. Consistency of each filter is judged by an
+    /// [InnovationMonitor] over `window` steps at the given `confidence`.
+    ///
+    /// Returns [ErrorKind::InvalidSensorRows] if a sensor's rows are out of
+    /// range or include every row, as the filter excluding it would then
+    /// have nothing to observe.
+    pub fn new(
+        transition_model: &'a dyn TransitionModelLinearNoControl<R>,
+        observation_model: &'a dyn ObservationModel<R>,
+        initial_estimate: &StateAndCovariance<R>,
+        sensor_rows: Vec<Vec<usize>>,
+        window: usize,
+        confidence: R,
+    ) -> Result<Self, Error> {
+        let obs_dim = observation_model.obs_dim();
+        for (sensor, rows) in sensor_rows.iter().enumerate() {
+            let in_range = rows.iter().all(|&row| row < obs_dim);
+            if !in_range || (0..obs_dim).all(|row| rows.contains(&row)) {
+                return Err(ErrorKind::InvalidSensorRows { sensor }.into());
+            }
+        }
+        let channel = |rows: Vec<usize>| {
+            let monitor = InnovationMonitor::new(window, rows.len(), confidence.clone());
+            Channel {
+                model: SubsetObservationModel::new(observation_model, rows),
+                estimate: initial_estimate.clone(),
+                monitor,
+            }
+        };
+        let full = channel((0..obs_dim).collect());
+        let excluding = sensor_rows
+            .iter()
+            .map(|excluded| channel((0..obs_dim).filter(|row| !excluded.contains(row)).collect()))
+            .collect();
+        Ok(Self {
+            transition_model,
+            sensor_rows,
+            full,
+            excluding,
+        })
+    }
+
+    /// Create a new `FaultDetector` treating every observation row as a
+    /// separate sensor.
+    ///
+    /// Returns [ErrorKind::InvalidSensorRows] if there is only one row.
+    pub fn per_row(
+        transition_model: &'a dyn TransitionModelLinearNoControl<R>,
+        observation_model: &'a dyn ObservationModel<R>,
+        initial_estimate: &StateAndCovariance<R>,
+        window: usize,
+        confidence: R,
+    ) -> Result<Self, Error> {
+        let sensor_rows = (0..observation_model.obs_dim())
+            .map(|row| vec![row])
+            .collect();
+        Self::new(
+            transition_model,
+            observation_model,
+            initial_estimate,
+            sensor_rows,
+            window,
+            confidence,
+        )
+    }
+
+    /// Step every filter in the bank with a new observation.
+    ///
+    /// Components of the observation which are NaN are treated as missing;
+    /// filters using them perform only the prediction step.
+    pub fn step(&mut self, observation: &DVector<R>) -> Result<FaultReport, Error> {
+        self.full.step(self.transition_model, observation)?;
+        for channel in self.excluding.iter_mut() {
+            channel.step(self.transition_model, observation)?;
+        }
+        Ok(self.report())
+    }
+
+    /// The current fault report.
+    pub fn report(&self) -> FaultReport {
+        let fault_detected = self.full.monitor.status() == NisStatus::AboveBounds;
+        let mut suspect_sensors = Vec::new();
+        let mut suspect_rows = Vec::new();
+        if fault_detected {
+            for (sensor, channel) in self.excluding.iter().enumerate() {
+                if channel.monitor.status() != NisStatus::AboveBounds {
+                    suspect_sensors.push(sensor);
+                    suspect_rows.extend_from_slice(&self.sensor_rows[sensor]);
+                }
+            }
+        }
+        FaultReport {
+            fault_detected,
+            suspect_sensors,
+            suspect_rows,
+        }
+    }
+
+    /// The estimate of the filter using all observations.
+    pub fn estimate(&self) -> &StateAndCovariance<R> {
+        &self.full.estimate
+    }
+
+    /// The estimate of the filter excluding the given sensor.
+    pub fn estimate_excluding(&self, sensor: usize) -> &StateAndCovariance<R> {
+        &self.excluding[sensor].estimate
+    }
+}
+
+#[test]
+fn test_fault_isolation() {
+    use crate::test_util::{initial_estimate, ConstantVelocity, MatrixObservation, Normals};
+
+    let transition = ConstantVelocity::new(0.1, 0.01);
+    // Three sensors observing position.
+    let observation = MatrixObservation::new(
+        na::DMatrix::from_row_slice(3, 2, &[1.0, 0.0, 1.0, 0.0, 1.0, 0.0]),
+        na::DMatrix::identity(3, 3) * 0.01,
+    );
+    let mut detector =
+        FaultDetector::per_row(&transition, &observation, &initial_estimate(), 20, 0.99).unwrap();
+    let mut normals = Normals::new(5);
+    let mut report = None;
+    for i in 0..300 {
+        let position = 0.1 * i as f64;
+        let bias = if i >= 200 { 1.0 } else { 0.0 };
+        let y = DVector::from_vec(vec![
+            position + 0.1 * normals.sample(),
+            position + 0.1 * normals.sample() + bias,
+            position + 0.1 * normals.sample(),
+        ]);
+        let r = detector.step(&y).unwrap();
+        if i == 199 {
+            assert!(!r.fault_detected);
+        }
+        report = Some(r);
+    }
+    let report = report.unwrap();
+    assert!(report.fault_detected);
+    assert_eq!(report.suspect_sensors, vec![1]);
+    assert_eq!(report.suspect_rows, vec![1]);
+}
+
+#[test]
+fn test_fault_detector_sensor_rows() {
+    use crate::test_util::{initial_estimate, ConstantVelocity, MatrixObservation};
+
+    let transition = ConstantVelocity::new(0.1, 0.01);
+    let observation = MatrixObservation::new(
+        na::DMatrix::from_row_slice(2, 2, &[1.0, 0.0, 0.0, 1.0]),
+        na::DMatrix::identity(2, 2) * 0.01,
+    );
+    let detector = |sensor_rows: Vec<Vec<usize>>| {
+        FaultDetector::new(
+            &transition,
+            &observation,
+            &initial_estimate(),
+            sensor_rows,
+            10,
+            0.99,
+        )
+    };
+    // A sensor providing every row leaves its exclusion filter nothing to
+    // observe, as does the only row of a one-row model.
+    let err = detector(vec![vec![1], vec![0, 1]]).err().unwrap();
+    assert!(matches!(
+        err.kind(),
+        ErrorKind::InvalidSensorRows { sensor: 1 }
+    ));
+    let err = detector(vec![vec![0, 1]]).err().unwrap();
+    assert!(matches!(
+        err.kind(),
+        ErrorKind::InvalidSensorRows { sensor: 0 }
+    ));
+    let err = detector(vec![vec![0], vec![2]]).err().unwrap();
+    assert!(matches!(
+        err.kind(),
+        ErrorKind::InvalidSensorRows { sensor: 1 }
+    ));
+    let position = MatrixObservation::new(
+        na::DMatrix::from_row_slice(1, 2, &[1.0, 0.0]),
+        na::DMatrix::identity(1, 1),
+    );
+    assert!(FaultDetector::per_row(&transition, &position, &initial_estimate(), 10, 0.99).is_err());
+
+    // A single sensor with a row to spare is fine.
+    assert!(detector(vec![vec![1]]).is_ok());
+}
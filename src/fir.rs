@@ -0,0 +1,150 @@
+//! Limited-memory, unbiased finite impulse response (FIR) estimation
+
+use na::{DMatrix, DVector, RealField};
+use nalgebra as na;
+
+use crate::{
+    is_nan, Error, ErrorKind, ObservationModel, StateAndCovariance, TransitionModelLinearNoControl,
+};
+
+/// An unbiased FIR filter using only the most recent observations
+///
+/// The Kalman filter has infinite memory: every past observation influences
+/// the estimate, weighted according to the process covariance `Q`. If `Q`
+/// is unknown or the model is wrong, errors accumulate. This estimator
+/// instead uses only the last `horizon` observations and ignores `Q`
+/// entirely, finding the state which best explains them under the
+/// noise-free transition model. The estimate is unbiased whatever the
+/// process noise, and the effect of old model mismatch is forgotten after
+/// `horizon` steps, at the cost of more noise than a well-tuned Kalman
+/// filter.
+///
+/// The observations are weighted by the inverse of the observation noise
+/// covariance `R`. The reported covariance accounts for observation noise
+/// only.
+pub struct LimitedMemoryFilter<'a, R>
+where
+    R: RealField,
+{
+    transition_model: &'a dyn TransitionModelLinearNoControl<R>,
+    observation_model: &'a dyn ObservationModel<R>,
+    horizon: usize,
+}
+
+impl<'a, R> LimitedMemoryFilter<'a, R>
+where
+    R: RealField,
+{
+    /// Create a new filter using the last `horizon` observations. The
+    /// horizon must be long enough for the state to be observable.
+    pub fn new(
+        transition_model: &'a dyn TransitionModelLinearNoControl<R>,
+        observation_model: &'a dyn ObservationModel<R>,
+        horizon: usize,
+    ) -> Self {
+        Self {
+            transition_model,
+            observation_model,
+            horizon,
+        }
+    }
+
+    /// Estimate the state at the time of the last observation from the last
+    /// `horizon` observations
+    ///
+    /// Observations with a NaN component are skipped. An
+    /// [ErrorKind::SingularMatrix] error is returned if the remaining
+    /// observations do not determine the state.
+    pub fn estimate(&self, observations: &[DVector<R>]) -> Result<StateAndCovariance<R>, Error> {
+        let n = self.transition_model.state_dim();
+        let F = self.transition_model.F();
+        let H = self.observation_model.H();
+        let R_inv = match na::linalg::Cholesky::new(self.observation_model.R().clone()) {
+            Some(chol) => chol.inverse(),
+            None => return Err(ErrorKind::CovarianceNotPositiveSemiDefinite.into()),
+        };
+
+        // Each observation in the window is y_i = H F^i x_0 + v_i, with x_0
+        // the state at the start of the window. Accumulate the normal
+        // equations for x_0.
+        let start = observations.len().saturating_sub(self.horizon);
+        let mut phi = DMatrix::<R>::identity(n, n);
+        let mut information = DMatrix::<R>::zeros(n, n);
+        let mut rhs = DVector::<R>::zeros(n);
+        for (i, observation) in observations[start..].iter().enumerate() {
+            if i > 0 {
+                phi = F * phi;
+            }
+            if observation.iter().any(|x| is_nan(x.clone())) {
+                continue;
+            }
+            let C = H * &phi;
+            let CT_R_inv = C.transpose() * &R_inv;
+            information += &CT_R_inv * C;
+            rhs += CT_R_inv * observation;
+        }
+        let chol = match na::linalg::Cholesky::new(information) {
+            Some(chol) => chol,
+            None => return Err(ErrorKind::SingularMatrix.into()),
+        };
+
+        // Propagate to the end of the window, where phi is F^(N-1).
+        let state = &phi * chol.solve(&rhs);
+        let covariance = &phi * chol.inverse() * phi.transpose();
+        Ok(StateAndCovariance::new(state, covariance))
+    }
+
+    /// Estimate the state at the time of each observation from a sliding
+    /// window of observations
+    ///
+    /// The first estimate is at the time of observation `horizon - 1`, the
+    /// first with a full window, so the result has
+    /// `observations.len() - horizon + 1` elements.
+    pub fn filter(&self, observations: &[DVector<R>]) -> Result<Vec<StateAndCovariance<R>>, Error> {
+        (self.horizon.max(1)..=observations.len())
+            .map(|end| {
+                self.estimate(&observations[..end])
+                    .map_err(|e| e.with_step(end - 1))
+            })
+            .collect()
+    }
+}
+
+#[test]
+fn test_limited_memory_forgets_model_mismatch() {
+    use crate::test_util::{ConstantVelocity, PositionObservation};
+
+    // A target moving at constant velocity which abruptly reverses, a
+    // change the noise-free model does not allow for.
+    let dt = 0.1;
+    let positions: Vec<f64> = (0..40)
+        .map(|k| {
+            let t = k as f64 * dt;
+            if k < 20 {
+                t
+            } else {
+                4.0 - t
+            }
+        })
+        .collect();
+    let observations: Vec<DVector<f64>> = positions
+        .iter()
+        .map(|p| DVector::from_element(1, *p))
+        .collect();
+
+    let transition = ConstantVelocity::new(dt, 0.0);
+    let observation = PositionObservation::new(0.5);
+    let filter = LimitedMemoryFilter::new(&transition, &observation, 10);
+    let estimates = filter.filter(&observations).unwrap();
+    assert_eq!(estimates.len(), 31);
+
+    // Once the window is past the reversal, the estimate is exact.
+    let last = estimates.last().unwrap();
+    approx::assert_relative_eq!(last.state()[0], positions[39], epsilon = 1e-9);
+    approx::assert_relative_eq!(last.state()[1], -1.0, epsilon = 1e-9);
+
+    // A single observation cannot determine position and velocity.
+    let filter = LimitedMemoryFilter::new(&transition, &observation, 1);
+    let err = filter.estimate(&observations).unwrap_err();
+    assert!(matches!(err.kind(), ErrorKind::SingularMatrix));
+}
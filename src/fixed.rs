@@ -0,0 +1,203 @@
+//! Kalman filter on fixed-size, stack-allocated matrices
+//!
+//! For small dimensions, e.g. `f32` filters at IMU or audio rates with a
+//! state of eight or fewer components, the heap allocation and dynamic
+//! dimension checks of `DMatrix` dominate the cost of each step. Here the
+//! dimensions are const generic parameters, so every matrix lives in a
+//! contiguous stack buffer and the compiler can unroll the small kernels.
+
+use na::{RealField, SMatrix, SVector};
+use nalgebra as na;
+
+use crate::{is_nan, Error, ErrorKind, StateAndCovariance};
+
+/// State and covariance of a fixed-size estimate with `SS` state components
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct FixedStateAndCovariance<R, const SS: usize>
+where
+    R: RealField,
+{
+    /// The state vector.
+    pub state: SVector<R, SS>,
+    /// The covariance matrix.
+    pub covariance: SMatrix<R, SS, SS>,
+}
+
+impl<R, const SS: usize> FixedStateAndCovariance<R, SS>
+where
+    R: RealField,
+{
+    /// Create a new estimate.
+    pub fn new(state: SVector<R, SS>, covariance: SMatrix<R, SS, SS>) -> Self {
+        Self { state, covariance }
+    }
+
+    /// Convert to a dynamically sized estimate.
+    pub fn to_dynamic(&self) -> StateAndCovariance<R> {
+        StateAndCovariance::new(
+            na::DVector::from_column_slice(self.state.as_slice()),
+            na::DMatrix::from_column_slice(SS, SS, self.covariance.as_slice()),
+        )
+    }
+}
+
+/// A linear Kalman filter with `SS` state and `OS` observation components
+///
+/// Unlike [KalmanFilterNoControl](crate::KalmanFilterNoControl), this owns
+/// its model matrices and never allocates. The covariance update uses the
+/// Joseph form.
+#[derive(Debug, Clone)]
+pub struct FixedKalmanFilter<R, const SS: usize, const OS: usize>
+where
+    R: RealField,
+{
+    F: SMatrix<R, SS, SS>,
+    FT: SMatrix<R, SS, SS>,
+    Q: SMatrix<R, SS, SS>,
+    H: SMatrix<R, OS, SS>,
+    HT: SMatrix<R, SS, OS>,
+    R: SMatrix<R, OS, OS>,
+}
+
+impl<R, const SS: usize, const OS: usize> FixedKalmanFilter<R, SS, OS>
+where
+    R: RealField,
+{
+    /// Create a new filter from the state transition matrix `F`, process
+    /// covariance `Q`, observation matrix `H` and observation noise
+    /// covariance `R`.
+    pub fn new(
+        F: SMatrix<R, SS, SS>,
+        Q: SMatrix<R, SS, SS>,
+        H: SMatrix<R, OS, SS>,
+        R: SMatrix<R, OS, OS>,
+    ) -> Self {
+        let FT = F.transpose();
+        let HT = H.transpose();
+        Self { F, FT, Q, H, HT, R }
+    }
+
+    /// Predict the next state.
+    #[inline]
+    pub fn predict(
+        &self,
+        estimate: &FixedStateAndCovariance<R, SS>,
+    ) -> FixedStateAndCovariance<R, SS> {
+        FixedStateAndCovariance::new(
+            &self.F * &estimate.state,
+            &self.F * &estimate.covariance * &self.FT + &self.Q,
+        )
+    }
+
+    /// Update the prior with an observation.
+    #[inline]
+    pub fn update(
+        &self,
+        prior: &FixedStateAndCovariance<R, SS>,
+        observation: &SVector<R, OS>,
+    ) -> Result<FixedStateAndCovariance<R, SS>, Error> {
+        let PHT = &prior.covariance * &self.HT;
+        let S = &self.H * &PHT + &self.R;
+        let S_chol = match na::linalg::Cholesky::new(S) {
+            Some(v) => v,
+            None => return Err(ErrorKind::CovarianceNotPositiveSemiDefinite.into()),
+        };
+        // K = P H^T S^-1, computed as (S^-1 H P)^T since S is symmetric.
+        let K = S_chol.solve(&PHT.transpose()).transpose();
+        let residual = observation - &self.H * &prior.state;
+        let state = &prior.state + &K * residual;
+        let one_minus_kh = SMatrix::<R, SS, SS>::identity() - &K * &self.H;
+        let covariance = &one_minus_kh * &prior.covariance * one_minus_kh.transpose()
+            + &K * &self.R * K.transpose();
+        Ok(FixedStateAndCovariance::new(state, covariance))
+    }
+
+    /// Perform prediction and update steps
+    ///
+    /// If any component of the observation is NaN (not a number), the
+    /// observation will not be used but rather the prior will be returned as
+    /// the posterior without performing the update step.
+    #[inline]
+    pub fn step(
+        &self,
+        previous_estimate: &FixedStateAndCovariance<R, SS>,
+        observation: &SVector<R, OS>,
+    ) -> Result<FixedStateAndCovariance<R, SS>, Error> {
+        let prior = self.predict(previous_estimate);
+        if observation.iter().any(|x| is_nan(x.clone())) {
+            Ok(prior)
+        } else {
+            self.update(&prior, observation)
+        }
+    }
+}
+
+#[test]
+fn test_fixed_matches_dynamic() {
+    use crate::test_util::{
+        initial_estimate, simulate_positions, ConstantVelocity, PositionObservation,
+    };
+    use crate::{KalmanFilterNoControl, ObservationModel, TransitionModelLinearNoControl};
+
+    let transition = ConstantVelocity::new(0.1, 1.0);
+    let observation = PositionObservation::new(0.5);
+    let fixed = FixedKalmanFilter::<f64, 2, 1>::new(
+        SMatrix::from_column_slice(transition.F().as_slice()),
+        SMatrix::from_column_slice(TransitionModelLinearNoControl::Q(&transition).as_slice()),
+        SMatrix::from_column_slice(observation.H().as_slice()),
+        SMatrix::from_column_slice(observation.R().as_slice()),
+    );
+    let observations = simulate_positions(10, 0.1, 1.0, 0.5, 9);
+    let expected = KalmanFilterNoControl::new(&transition, &observation)
+        .filter(&initial_estimate(), &observations)
+        .unwrap();
+    let mut estimate = FixedStateAndCovariance::new(
+        SVector::<f64, 2>::new(0.0, 1.0),
+        SMatrix::<f64, 2, 2>::identity(),
+    );
+    for (z, e) in observations.iter().zip(expected.iter()) {
+        estimate = fixed
+            .step(&estimate, &SVector::<f64, 1>::new(z[0]))
+            .unwrap();
+        let dynamic = estimate.to_dynamic();
+        approx::assert_relative_eq!(dynamic.state(), e.state(), epsilon = 1e-12);
+        approx::assert_relative_eq!(dynamic.covariance(), e.covariance(), epsilon = 1e-12);
+    }
+}
+
+#[test]
+fn test_fixed_missing_and_singular() {
+    use crate::test_util::{ConstantVelocity, PositionObservation};
+    use crate::{ObservationModel, TransitionModelLinearNoControl};
+
+    // A missing observation only predicts.
+    let transition = ConstantVelocity::new(0.1, 1.0);
+    let observation = PositionObservation::new(0.5);
+    let kf = FixedKalmanFilter::<f64, 2, 1>::new(
+        SMatrix::from_column_slice(transition.F().as_slice()),
+        SMatrix::from_column_slice(TransitionModelLinearNoControl::Q(&transition).as_slice()),
+        SMatrix::from_column_slice(observation.H().as_slice()),
+        SMatrix::from_column_slice(observation.R().as_slice()),
+    );
+    let estimate = FixedStateAndCovariance::new(
+        SVector::<f64, 2>::new(0.0, 1.0),
+        SMatrix::<f64, 2, 2>::identity(),
+    );
+    let predicted = kf
+        .step(&estimate, &SVector::<f64, 1>::new(f64::NAN))
+        .unwrap();
+    assert_eq!(predicted, kf.predict(&estimate));
+
+    // Without any noise, the innovation covariance cannot be inverted.
+    let one = SMatrix::<f64, 1, 1>::new(1.0);
+    let zero = SMatrix::<f64, 1, 1>::zeros();
+    let exact = FixedKalmanFilter::<f64, 1, 1>::new(one, zero, one, zero);
+    let known = FixedStateAndCovariance::new(SVector::<f64, 1>::new(1.0), zero);
+    let err = exact
+        .update(&known, &SVector::<f64, 1>::new(1.0))
+        .unwrap_err();
+    assert!(matches!(
+        err.kind(),
+        ErrorKind::CovarianceNotPositiveSemiDefinite
+    ));
+}
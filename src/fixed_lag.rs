@@ -0,0 +1,518 @@
+//! Real-time fixed-lag smoothing in caller-provided memory
+
+use alloc::borrow::Cow;
+
+use na::{
+    DMatrix, DMatrixSlice, DMatrixSliceMut, DVector, DVectorSlice, DVectorSliceMut, RealField,
+};
+use nalgebra as na;
+
+use crate::{
+    is_nan, linalg, Error, ErrorKind, ObservationModel, StateAndCovariance,
+    TransitionModelLinearNoControl,
+};
+
+/// A fixed-lag smoother for real-time use, with constant memory
+///
+/// The smoother runs a Kalman filter on the augmented state
+/// `[x_k, x_k-1, ..., x_k-L]` of the last `L + 1` states, for a lag `L`.
+/// Each observation of `x_k` also updates the past states through their
+/// cross-covariances, so that after each step the oldest component is the
+/// smoothed estimate `x_k-L | k`, the same as the RTS smoother would give
+/// with the observations up to `k`.
+///
+/// The augmented state and covariance are stored in caller-provided
+/// buffers, of the lengths given by [buffer_lengths](Self::buffer_lengths),
+/// which are used as a ring buffer of the component states. Advancing a step
+/// overwrites the oldest component in place rather than shifting the others,
+/// so the augmented state and covariance need no memory beyond the buffers.
+/// Each step still allocates temporaries of the state dimension by the
+/// augmented dimension, for the cross-covariances and the gain.
+///
+/// A non-linear observation model is linearized at the predicted newest
+/// state by its [jacobian_at](ObservationModel::jacobian_at).
+pub struct FixedLagSmoother<'a, 'b, R>
+where
+    R: RealField,
+{
+    transition_model: &'a dyn TransitionModelLinearNoControl<R>,
+    observation_model: &'a dyn ObservationModel<R>,
+    lag: usize,
+    state: &'b mut [R],
+    covariance: &'b mut [R],
+    head: usize,
+    steps: usize,
+}
+
+impl<'a, 'b, R> FixedLagSmoother<'a, 'b, R>
+where
+    R: RealField,
+{
+    /// The lengths of the buffers for the augmented state and covariance,
+    /// for the given state dimension and lag.
+    pub fn buffer_lengths(state_dim: usize, lag: usize) -> (usize, usize) {
+        let m = state_dim * (lag + 1);
+        (m, m * m)
+    }
+
+    /// Initialize a new `FixedLagSmoother` struct, starting from the
+    /// estimate before the first observation
+    ///
+    /// An [ErrorKind::DimensionMismatch] error is returned if the buffers
+    /// are shorter than given by [buffer_lengths](Self::buffer_lengths).
+    pub fn new(
+        transition_model: &'a dyn TransitionModelLinearNoControl<R>,
+        observation_model: &'a dyn ObservationModel<R>,
+        lag: usize,
+        initial_estimate: &StateAndCovariance<R>,
+        state: &'b mut [R],
+        covariance: &'b mut [R],
+    ) -> Result<Self, Error> {
+        let n = transition_model.state_dim();
+        let (state_len, covariance_len) = Self::buffer_lengths(n, lag);
+        for (name, expected, actual) in [
+            ("state buffer", state_len, state.len()),
+            ("covariance buffer", covariance_len, covariance.len()),
+        ] {
+            if actual < expected {
+                return Err(ErrorKind::DimensionMismatch {
+                    expected: (expected, 1),
+                    actual: (actual, 1),
+                    matrix: name,
+                }
+                .into());
+            }
+        }
+        let mut smoother = Self {
+            transition_model,
+            observation_model,
+            lag,
+            state: &mut state[..state_len],
+            covariance: &mut covariance[..covariance_len],
+            head: 0,
+            steps: 0,
+        };
+        // Before the first step, every component is the initial state, so
+        // all the blocks of the covariance are its covariance.
+        for block in 0..=lag {
+            smoother
+                .state_mut()
+                .rows_mut(block * n, n)
+                .copy_from(initial_estimate.state());
+            for other in 0..=lag {
+                smoother
+                    .covariance_mut()
+                    .slice_mut((block * n, other * n), (n, n))
+                    .copy_from(initial_estimate.covariance());
+            }
+        }
+        Ok(smoother)
+    }
+
+    /// The number of observations processed so far.
+    pub fn steps(&self) -> usize {
+        self.steps
+    }
+
+    fn dim(&self) -> usize {
+        self.transition_model.state_dim()
+    }
+
+    fn state(&self) -> DVectorSlice<'_, R> {
+        DVectorSlice::from_slice(self.state, self.state.len())
+    }
+
+    fn state_mut(&mut self) -> DVectorSliceMut<'_, R> {
+        let m = self.state.len();
+        DVectorSliceMut::from_slice(self.state, m)
+    }
+
+    fn covariance(&self) -> DMatrixSlice<'_, R> {
+        let m = self.state.len();
+        DMatrixSlice::from_slice(self.covariance, m, m)
+    }
+
+    fn covariance_mut(&mut self) -> DMatrixSliceMut<'_, R> {
+        let m = self.state.len();
+        DMatrixSliceMut::from_slice(self.covariance, m, m)
+    }
+
+    /// The block of the augmented state holding `x_k-i`.
+    fn block(&self, i: usize) -> usize {
+        (self.head + i) % (self.lag + 1)
+    }
+
+    /// The estimate of `x_k-i` given the observations up to `k`, for `i` up
+    /// to the lag, or `None` if `i` is larger than the lag or the number of
+    /// steps.
+    pub fn estimate(&self, i: usize) -> Option<StateAndCovariance<R>> {
+        if i > self.lag || i > self.steps {
+            return None;
+        }
+        let n = self.dim();
+        let b = self.block(i) * n;
+        Some(StateAndCovariance::new(
+            self.state().rows(b, n).into_owned(),
+            self.covariance().slice((b, b), (n, n)).into_owned(),
+        ))
+    }
+
+    /// Process the next observation, returning the smoothed estimate of the
+    /// state `lag` steps back once there is one
+    ///
+    /// If any component of the observation is NaN, it is treated as missing.
+    /// If the update fails, e.g. as the innovation covariance is not positive
+    /// definite, the error is returned and the smoother is left as before
+    /// the step.
+    pub fn step(
+        &mut self,
+        observation: &DVector<R>,
+    ) -> Result<Option<StateAndCovariance<R>>, Error> {
+        let prediction = self.predict();
+        if !observation.iter().any(|x| is_nan(x.clone())) {
+            let os = self.observation_model.obs_dim();
+            let gain = crate::check_dimension("observation", (os, 1), observation.shape())
+                .and_then(|_| self.innovation_inverse(&prediction))
+                .map_err(|e| e.with_step(self.steps))?;
+            self.advance(prediction);
+            self.update(observation, gain);
+        } else {
+            self.advance(prediction);
+        }
+        self.steps += 1;
+        Ok(if self.steps > self.lag {
+            self.estimate(self.lag)
+        } else {
+            None
+        })
+    }
+
+    /// Predict the next component from the newest, without changing the
+    /// buffers.
+    fn predict(&self) -> Prediction<R> {
+        let n = self.dim();
+        let F = self.transition_model.F();
+        let old = self.head * n;
+        let state = F * self.state().rows(old, n);
+        // The cross-covariances of the new state with all others,
+        // `F P_old,j`, computed before the oldest block is overwritten.
+        let cross: DMatrix<R> = F * self.covariance().rows(old, n);
+        let covariance =
+            cross.columns(old, n) * &*self.transition_model.FT() + self.transition_model.Q();
+        Prediction {
+            state,
+            cross,
+            covariance,
+        }
+    }
+
+    /// The transpose of the observation matrix, linearized at the predicted
+    /// state, and the inverse of the innovation covariance, or an error if
+    /// it cannot be factored.
+    fn innovation_inverse(
+        &self,
+        prediction: &Prediction<R>,
+    ) -> Result<(DMatrix<R>, DMatrix<R>), Error> {
+        let model = self.observation_model;
+        let (H, HT) = match model.jacobian_at(&prediction.state) {
+            Cow::Borrowed(H) => (H.clone(), model.HT().into_owned()),
+            Cow::Owned(H) => {
+                let HT = H.transpose();
+                (H, HT)
+            }
+        };
+        let S = linalg::matmul3(&H, &prediction.covariance, &HT) + model.R();
+        match linalg::cholesky_inverse(&S) {
+            Some(S_inv) => Ok((HT, S_inv)),
+            None => Err(Error::from(ErrorKind::CovarianceNotPositiveSemiDefinite).with_matrix(&S)),
+        }
+    }
+
+    /// Advance the ring buffer, overwriting the oldest component with the
+    /// prediction of the newest.
+    fn advance(&mut self, prediction: Prediction<R>) {
+        let n = self.dim();
+        let new = self.block(self.lag) * n;
+        self.state_mut()
+            .rows_mut(new, n)
+            .copy_from(&prediction.state);
+        let mut P = self.covariance_mut();
+        P.rows_mut(new, n).copy_from(&prediction.cross);
+        P.columns_mut(new, n)
+            .copy_from(&prediction.cross.transpose());
+        P.slice_mut((new, new), (n, n))
+            .copy_from(&prediction.covariance);
+        self.head = new / n;
+    }
+
+    /// Update the augmented state with an observation of the newest
+    /// component, given the transpose of the linearized observation matrix
+    /// and the inverse of the innovation covariance.
+    fn update(&mut self, observation: &DVector<R>, (HT, S_inv): (DMatrix<R>, DMatrix<R>)) {
+        let n = self.dim();
+        let head = self.head * n;
+        let x = self.state().rows(head, n).into_owned();
+        let PHT = self.covariance().columns(head, n) * HT;
+        let K = &PHT * S_inv;
+        let innovation = observation - self.observation_model.predict_observation(&x);
+        let correction = &K * innovation;
+        let mut state = self.state_mut();
+        state += correction;
+        let mut P = self.covariance_mut();
+        P.gemm(-R::one(), &K, &PHT.transpose(), R::one());
+    }
+}
+
+/// The prediction of the newest component of the augmented state: its
+/// state, its cross-covariances with all the blocks, and its covariance
+struct Prediction<R>
+where
+    R: RealField,
+{
+    state: DVector<R>,
+    cross: DMatrix<R>,
+    covariance: DMatrix<R>,
+}
+
+#[test]
+fn test_fixed_lag_smoother() {
+    use crate::test_util::{
+        initial_estimate, simulate_positions, ConstantVelocity, PositionObservation,
+    };
+    use crate::KalmanFilterNoControl;
+
+    let transition = ConstantVelocity::new(0.1, 1.0);
+    let observation = PositionObservation::new(0.5);
+    let kf = KalmanFilterNoControl::new(&transition, &observation);
+    let mut observations = simulate_positions(15, 0.1, 1.0, 0.5, 13);
+    observations[6] = DVector::from_element(1, f64::NAN);
+    let lag = 4;
+
+    let (state_len, covariance_len) = FixedLagSmoother::<f64>::buffer_lengths(2, lag);
+    let mut state = vec![0.0; state_len];
+    let mut covariance = vec![0.0; covariance_len];
+    let mut smoother = FixedLagSmoother::new(
+        &transition,
+        &observation,
+        lag,
+        &initial_estimate(),
+        &mut state,
+        &mut covariance,
+    )
+    .unwrap();
+    for (k, z) in observations.iter().enumerate() {
+        let smoothed = smoother.step(z).unwrap();
+        let expected = kf.smooth(&initial_estimate(), &observations[..=k]).unwrap();
+        approx::assert_relative_eq!(smoother.estimate(0).unwrap(), expected[k], epsilon = 1e-9);
+        match smoothed {
+            Some(smoothed) => {
+                approx::assert_relative_eq!(smoothed, expected[k - lag], epsilon = 1e-9)
+            }
+            None => assert!(k < lag),
+        }
+    }
+
+    let mut short = vec![0.0; covariance_len - 1];
+    assert!(FixedLagSmoother::new(
+        &transition,
+        &observation,
+        lag,
+        &initial_estimate(),
+        &mut state,
+        &mut short,
+    )
+    .is_err());
+}
+
+#[test]
+fn test_fixed_lag_failed_update() {
+    use crate::test_util::{
+        initial_estimate, simulate_positions, ConstantVelocity, PositionObservation,
+    };
+    use crate::KalmanFilterNoControl;
+    use core::cell::Cell;
+
+    // A position sensor whose noise covariance is negative while `broken`
+    // is set, so that the innovation covariance cannot be factored.
+    struct Faulty {
+        inner: PositionObservation,
+        negative: DMatrix<f64>,
+        broken: Cell<bool>,
+    }
+    impl ObservationModel<f64> for Faulty {
+        fn H(&self) -> &DMatrix<f64> {
+            self.inner.H()
+        }
+        fn R(&self) -> &DMatrix<f64> {
+            if self.broken.get() {
+                &self.negative
+            } else {
+                self.inner.R()
+            }
+        }
+        fn state_dim(&self) -> usize {
+            2
+        }
+        fn obs_dim(&self) -> usize {
+            1
+        }
+    }
+    let transition = ConstantVelocity::new(0.1, 1.0);
+    let observation = Faulty {
+        inner: PositionObservation::new(0.5),
+        negative: DMatrix::from_element(1, 1, -100.0),
+        broken: Cell::new(false),
+    };
+    let lag = 2;
+    let (state_len, covariance_len) = FixedLagSmoother::<f64>::buffer_lengths(2, lag);
+    let mut state = vec![0.0; state_len];
+    let mut covariance = vec![0.0; covariance_len];
+    let mut smoother = FixedLagSmoother::new(
+        &transition,
+        &observation,
+        lag,
+        &initial_estimate(),
+        &mut state,
+        &mut covariance,
+    )
+    .unwrap();
+    let observations = simulate_positions(8, 0.1, 1.0, 0.5, 4);
+    for z in &observations[..4] {
+        smoother.step(z).unwrap();
+    }
+
+    // A failed update, and an observation of the wrong length, leave the
+    // smoother as it was, so it carries on as if the step never happened.
+    let before: Vec<_> = (0..=lag).map(|i| smoother.estimate(i).unwrap()).collect();
+    observation.broken.set(true);
+    let err = smoother.step(&observations[4]).unwrap_err();
+    assert!(matches!(
+        err.kind(),
+        ErrorKind::CovarianceNotPositiveSemiDefinite
+    ));
+    assert_eq!(err.step(), Some(4));
+    observation.broken.set(false);
+    assert!(smoother.step(&DVector::zeros(2)).is_err());
+    assert_eq!(smoother.steps(), 4);
+    let after: Vec<_> = (0..=lag).map(|i| smoother.estimate(i).unwrap()).collect();
+    assert_eq!(before, after);
+
+    let kept: Vec<_> = observations[..4]
+        .iter()
+        .chain(&observations[5..])
+        .cloned()
+        .collect();
+    let kf = KalmanFilterNoControl::new(&transition, &observation);
+    for k in 4..kept.len() {
+        smoother.step(&kept[k]).unwrap();
+        let expected = kf.smooth(&initial_estimate(), &kept[..=k]).unwrap();
+        for i in 0..=lag {
+            approx::assert_relative_eq!(
+                smoother.estimate(i).unwrap(),
+                expected[k - i],
+                epsilon = 1e-9
+            );
+        }
+    }
+}
+
+#[test]
+fn test_fixed_lag_nonlinear() {
+    use crate::nonlinear::NumericalObservationModel;
+    use crate::test_util::{initial_estimate, simulate_positions, ConstantVelocity};
+    use crate::KalmanFilterNoControl;
+
+    // The newest component follows the extended Kalman filter, linearized
+    // at each prior rather than at the nominal state of `H`.
+    let transition = ConstantVelocity::new(0.1, 1.0);
+    let observation = NumericalObservationModel::new(
+        |x: &DVector<f64>| DVector::from_element(1, x[0] + 0.1 * x[0] * x[0]),
+        DMatrix::from_element(1, 1, 0.5),
+        1e-7,
+        &DVector::from_vec(vec![5.0, 0.0]),
+    );
+    let (state_len, covariance_len) = FixedLagSmoother::<f64>::buffer_lengths(2, 3);
+    let mut state = vec![0.0; state_len];
+    let mut covariance = vec![0.0; covariance_len];
+    let mut smoother = FixedLagSmoother::new(
+        &transition,
+        &observation,
+        3,
+        &initial_estimate(),
+        &mut state,
+        &mut covariance,
+    )
+    .unwrap();
+    let observations = simulate_positions(10, 0.1, 1.0, 0.5, 8);
+    let expected = KalmanFilterNoControl::new(&transition, &observation)
+        .filter(&initial_estimate(), &observations)
+        .unwrap();
+    for (z, expected) in observations.iter().zip(expected.iter()) {
+        smoother.step(z).unwrap();
+        approx::assert_relative_eq!(smoother.estimate(0).unwrap(), expected, epsilon = 1e-9);
+    }
+}
+
+#[test]
+fn test_fixed_lag_wraparound() {
+    use crate::test_util::{
+        initial_estimate, simulate_positions, ConstantVelocity, PositionObservation,
+    };
+    use crate::KalmanFilterNoControl;
+
+    // The newest component moves back one block each step, wrapping around
+    // the ring buffer several times, while every component stays the
+    // estimate smoothed with the observations so far.
+    let transition = ConstantVelocity::new(0.1, 1.0);
+    let observation = PositionObservation::new(0.5);
+    let kf = KalmanFilterNoControl::new(&transition, &observation);
+    let mut observations = simulate_positions(10, 0.1, 1.0, 0.5, 21);
+    observations[4] = DVector::from_element(1, f64::NAN);
+    let lag = 2;
+    let (state_len, covariance_len) = FixedLagSmoother::<f64>::buffer_lengths(2, lag);
+    let mut state = vec![0.0; state_len];
+    let mut covariance = vec![0.0; covariance_len];
+    let mut smoother = FixedLagSmoother::new(
+        &transition,
+        &observation,
+        lag,
+        &initial_estimate(),
+        &mut state,
+        &mut covariance,
+    )
+    .unwrap();
+    for (k, z) in observations.iter().enumerate() {
+        smoother.step(z).unwrap();
+        assert_eq!(smoother.head, (lag + 1 - (k + 1) % (lag + 1)) % (lag + 1));
+        let expected = kf.smooth(&initial_estimate(), &observations[..=k]).unwrap();
+        for i in 0..=lag.min(k) {
+            let estimate = smoother.estimate(i).unwrap();
+            let b = smoother.block(i) * 2;
+            assert_eq!(estimate.state().as_slice(), &smoother.state[b..b + 2]);
+            approx::assert_relative_eq!(estimate, expected[k - i], epsilon = 1e-9);
+        }
+        assert!(smoother.estimate(lag + 1).is_none());
+    }
+
+    // With no lag, the ring buffer holds a single component, which is the
+    // filtered estimate.
+    let (state_len, covariance_len) = FixedLagSmoother::<f64>::buffer_lengths(2, 0);
+    assert_eq!((state_len, covariance_len), (2, 4));
+    let mut state = vec![0.0; state_len];
+    let mut covariance = vec![0.0; covariance_len];
+    let mut smoother = FixedLagSmoother::new(
+        &transition,
+        &observation,
+        0,
+        &initial_estimate(),
+        &mut state,
+        &mut covariance,
+    )
+    .unwrap();
+    let filtered = kf.filter(&initial_estimate(), &observations).unwrap();
+    for (z, expected) in observations.iter().zip(filtered.iter()) {
+        let emitted = smoother.step(z).unwrap().unwrap();
+        approx::assert_relative_eq!(emitted, expected, epsilon = 1e-9);
+        assert_eq!(smoother.head, 0);
+    }
+}
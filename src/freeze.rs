@@ -0,0 +1,112 @@
+//! Freezing selected state components during a step
+
+use na::{DMatrix, DVector, RealField};
+use nalgebra as na;
+
+use crate::{
+    is_nan, linalg, Error, ErrorKind, KalmanFilterNoControl, Operation, StateAndCovariance,
+};
+
+impl<'a, R> KalmanFilterNoControl<'a, R>
+where
+    R: RealField,
+{
+    /// Perform prediction and update steps, with the components where
+    /// `frozen` is true left out of the update
+    ///
+    /// The frozen components coast: their states and variances are those of
+    /// the prediction, as if the observation were missing for them, while
+    /// the other components are updated as usual. This suits known
+    /// anomalies, such as wheel slip corrupting the relation between the
+    /// observations and some of the states. The rows of the Kalman gain for
+    /// the frozen components are set to zero (the Schmidt-Kalman "consider"
+    /// update), and the covariance is updated in the Joseph form, which
+    /// stays correct for such a suboptimal gain, so the cross-covariances
+    /// remain consistent.
+    ///
+    /// An [ErrorKind::DimensionMismatch] error is returned if `frozen` does
+    /// not have a flag for each state component. With no component frozen,
+    /// this is the same as [step](struct.KalmanFilterNoControl.html#method.step).
+    pub fn step_with_frozen(
+        &self,
+        previous_estimate: &StateAndCovariance<R>,
+        observation: &DVector<R>,
+        frozen: &[bool],
+    ) -> Result<StateAndCovariance<R>, Error> {
+        let n = self.transition_model.state_dim();
+        if frozen.len() != n {
+            return Err(ErrorKind::DimensionMismatch {
+                expected: (n, 1),
+                actual: (frozen.len(), 1),
+                matrix: "frozen",
+            }
+            .into());
+        }
+        if !frozen.iter().any(|&f| f) {
+            return self.step(previous_estimate, observation);
+        }
+        self.check_dimensions(previous_estimate, Some(observation))?;
+        let prior = self.predict(previous_estimate);
+        if observation.iter().any(|x| is_nan(x.clone())) {
+            return Ok(prior);
+        }
+
+        let H = self.observation_matrix.H();
+        let R = self.observation_matrix.R();
+        let P = prior.covariance();
+        let (residual, S) = self
+            .observation_matrix
+            .innovation(&prior, observation)
+            .inner();
+        let S_inv = linalg::cholesky_inverse(&S).ok_or_else(|| {
+            Error::from(ErrorKind::CovarianceNotPositiveSemiDefinite)
+                .with_operation(Operation::Update)
+        })?;
+        let mut K = linalg::matmul3(P, &self.observation_matrix.HT(), &S_inv);
+        for (i, _) in frozen.iter().enumerate().filter(|(_, &f)| f) {
+            K.row_mut(i).fill(R::zero());
+        }
+
+        let state = prior.state() + &K * residual;
+        let one_minus_kh = DMatrix::<R>::identity(n, n) - &K * H;
+        let covariance = linalg::matmul3(&one_minus_kh, P, &one_minus_kh.transpose())
+            + linalg::matmul3(&K, R, &K.transpose());
+        Ok(StateAndCovariance::new(state, covariance.symmetric_part()))
+    }
+}
+
+#[test]
+fn test_frozen_components() {
+    use crate::test_util::{initial_estimate, ConstantVelocity, PositionObservation};
+
+    let transition = ConstantVelocity::new(0.1, 1.0);
+    let observation = PositionObservation::new(0.5);
+    let kf = KalmanFilterNoControl::new(&transition, &observation);
+    let previous = initial_estimate();
+    let z = DVector::from_element(1, 0.7);
+
+    // Freezing the velocity leaves its state and variance at the prediction.
+    let prior = kf.predict(&previous);
+    let frozen = kf.step_with_frozen(&previous, &z, &[false, true]).unwrap();
+    assert_eq!(frozen.state()[1], prior.state()[1]);
+    approx::assert_relative_eq!(
+        frozen.covariance()[(1, 1)],
+        prior.covariance()[(1, 1)],
+        epsilon = 1e-12
+    );
+    // The position is updated exactly as by the full update, as its gain is
+    // unchanged.
+    let full = kf.step(&previous, &z).unwrap();
+    approx::assert_relative_eq!(frozen.state()[0], full.state()[0], epsilon = 1e-12);
+    approx::assert_relative_eq!(
+        frozen.covariance()[(0, 0)],
+        full.covariance()[(0, 0)],
+        epsilon = 1e-12
+    );
+
+    let all = kf.step_with_frozen(&previous, &z, &[true, true]).unwrap();
+    approx::assert_relative_eq!(all, prior, epsilon = 1e-12);
+    let none = kf.step_with_frozen(&previous, &z, &[false, false]).unwrap();
+    assert_eq!(none, full);
+    assert!(kf.step_with_frozen(&previous, &z, &[true]).is_err());
+}
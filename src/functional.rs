@@ -0,0 +1,104 @@
+//! One-shot filtering and smoothing from plain matrices
+
+use na::{DMatrix, DVector, RealField};
+use nalgebra as na;
+
+use crate::{
+    Error, KalmanFilterNoControl, LinearObservationModel, LinearTransitionModel, StateAndCovariance,
+};
+
+/// Kalman filter a sequence of observations with a linear model given by
+/// its matrices
+///
+/// This builds a [LinearTransitionModel] from `F` and `Q`, a
+/// [LinearObservationModel] from `H` and `R`, and runs
+/// [filter](KalmanFilterNoControl::filter) from the initial state `x0` with
+/// covariance `P0`. The dimensions are checked first, giving an
+/// [ErrorKind::DimensionMismatch](crate::ErrorKind::DimensionMismatch) error
+/// if they are inconsistent. Observations with a NaN component are treated
+/// as missing.
+pub fn filter<R>(
+    F: DMatrix<R>,
+    Q: DMatrix<R>,
+    H: DMatrix<R>,
+    R: DMatrix<R>,
+    x0: DVector<R>,
+    P0: DMatrix<R>,
+    observations: &[DVector<R>],
+) -> Result<Vec<StateAndCovariance<R>>, Error>
+where
+    R: RealField,
+{
+    let transition = LinearTransitionModel::from_matrices(F, Q);
+    let observation = LinearObservationModel::from_matrices(H, R);
+    KalmanFilterNoControl::try_new(&transition, &observation)?
+        .filter(&StateAndCovariance::new(x0, P0), observations)
+}
+
+/// Rauch-Tung-Striebel smooth a sequence of observations with a linear
+/// model given by its matrices
+///
+/// This is like [filter], but runs
+/// [smooth](KalmanFilterNoControl::smooth).
+pub fn smooth<R>(
+    F: DMatrix<R>,
+    Q: DMatrix<R>,
+    H: DMatrix<R>,
+    R: DMatrix<R>,
+    x0: DVector<R>,
+    P0: DMatrix<R>,
+    observations: &[DVector<R>],
+) -> Result<Vec<StateAndCovariance<R>>, Error>
+where
+    R: RealField,
+{
+    let transition = LinearTransitionModel::from_matrices(F, Q);
+    let observation = LinearObservationModel::from_matrices(H, R);
+    KalmanFilterNoControl::try_new(&transition, &observation)?
+        .smooth(&StateAndCovariance::new(x0, P0), observations)
+}
+
+#[test]
+fn test_functional_api() {
+    use crate::test_util::{
+        initial_estimate, simulate_positions, ConstantVelocity, PositionObservation,
+    };
+    use crate::{ObservationModel, TransitionModelLinearNoControl};
+
+    let transition = ConstantVelocity::new(0.1, 1.0);
+    let observation = PositionObservation::new(0.5);
+    let observations = simulate_positions(10, 0.1, 1.0, 0.5, 9);
+    let expected = KalmanFilterNoControl::new(&transition, &observation)
+        .smooth(&initial_estimate(), &observations)
+        .unwrap();
+    let (x0, P0) = initial_estimate().inner();
+    let actual = smooth(
+        transition.F().clone(),
+        TransitionModelLinearNoControl::Q(&transition).clone(),
+        observation.H().clone(),
+        observation.R().clone(),
+        x0.clone(),
+        P0.clone(),
+        &observations,
+    )
+    .unwrap();
+    for (a, e) in actual.iter().zip(expected.iter()) {
+        approx::assert_relative_eq!(a, e);
+    }
+
+    // Mismatched dimensions are reported rather than panicking.
+    let err = filter(
+        transition.F().clone(),
+        TransitionModelLinearNoControl::Q(&transition).clone(),
+        DMatrix::zeros(1, 3),
+        observation.R().clone(),
+        x0,
+        P0,
+        &observations,
+    )
+    .unwrap_err();
+    assert!(matches!(
+        err.kind(),
+        crate::ErrorKind::DimensionMismatch { .. }
+    ));
+}
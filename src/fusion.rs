@@ -0,0 +1,389 @@
+//! Sequential fusion of several sensors with gating and health tracking
+
+use na::{DVector, RealField};
+use nalgebra as na;
+
+use crate::{
+    is_nan, CovarianceUpdateMethod, Error, ErrorKind, Gate, ObservationModel, StateAndCovariance,
+    TransitionModelLinearNoControl,
+};
+
+/// Parameters of the [SensorHealth] state machine
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct HealthConfig {
+    /// A sensor is excluded after this many consecutive gate rejections.
+    pub max_consecutive_rejections: usize,
+    /// An excluded sensor is re-admitted after this many consecutive
+    /// observations which would have passed the gate.
+    pub recovery_acceptances: usize,
+}
+
+impl Default for HealthConfig {
+    fn default() -> Self {
+        Self {
+            max_consecutive_rejections: 5,
+            recovery_acceptances: 10,
+        }
+    }
+}
+
+/// Health of a single sensor, derived from its gating history
+#[derive(Debug, Clone)]
+pub struct SensorHealth<R>
+where
+    R: RealField,
+{
+    config: HealthConfig,
+    score: R,
+    consecutive_rejections: usize,
+    consecutive_acceptances: usize,
+    excluded: bool,
+}
+
+impl<R> SensorHealth<R>
+where
+    R: RealField,
+{
+    /// Create a healthy sensor.
+    pub fn new(config: HealthConfig) -> Self {
+        Self {
+            config,
+            score: R::one(),
+            consecutive_rejections: 0,
+            consecutive_acceptances: 0,
+            excluded: false,
+        }
+    }
+
+    /// Record the outcome of gating an observation of this sensor.
+    pub fn record(&mut self, accepted: bool) {
+        // Exponentially weighted acceptance rate.
+        let alpha: R = na::convert(0.1);
+        let outcome = if accepted { R::one() } else { R::zero() };
+        self.score = self.score.clone() + alpha * (outcome - self.score.clone());
+        if accepted {
+            self.consecutive_rejections = 0;
+            self.consecutive_acceptances += 1;
+            if self.excluded && self.consecutive_acceptances >= self.config.recovery_acceptances {
+                self.excluded = false;
+            }
+        } else {
+            self.consecutive_acceptances = 0;
+            self.consecutive_rejections += 1;
+            if self.consecutive_rejections >= self.config.max_consecutive_rejections {
+                self.excluded = true;
+            }
+        }
+    }
+
+    /// Recent gate acceptance rate, between 0 and 1.
+    pub fn score(&self) -> &R {
+        &self.score
+    }
+
+    /// Whether the sensor is currently excluded from fusion.
+    pub fn is_excluded(&self) -> bool {
+        self.excluded
+    }
+
+    /// Number of consecutive gate rejections.
+    pub fn consecutive_rejections(&self) -> usize {
+        self.consecutive_rejections
+    }
+}
+
+/// What happened to the observation of one sensor in a fusion step
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SensorOutcome {
+    /// The observation was used to update the estimate.
+    Applied,
+    /// The observation failed the gate and was not used.
+    Rejected,
+    /// The sensor is excluded; its observation was only used to assess
+    /// recovery.
+    Excluded,
+    /// There was no observation (or it had NaN components).
+    Missing,
+}
+
+/// The result of a [MultiSensorFusion] step
+#[derive(Debug, Clone)]
+pub struct FusionResult<R>
+where
+    R: RealField,
+{
+    /// The posterior estimate.
+    pub estimate: StateAndCovariance<R>,
+    /// The outcome for each sensor, in the order the sensors were added.
+    pub outcomes: Vec<SensorOutcome>,
+}
+
+struct Sensor<'a, R>
+where
+    R: RealField,
+{
+    model: &'a dyn ObservationModel<R>,
+    gate: Box<dyn Gate<R> + 'a>,
+    health: SensorHealth<R>,
+}
+
+/// Sequential multi-sensor fusion with gating and automatic exclusion of
+/// unhealthy sensors
+pub struct MultiSensorFusion<'a, R>
+where
+    R: RealField,
+{
+    transition_model: &'a dyn TransitionModelLinearNoControl<R>,
+    sensors: Vec<Sensor<'a, R>>,
+}
+
+impl<'a, R> MultiSensorFusion<'a, R>
+where
+    R: RealField,
+{
+    /// Create a new `MultiSensorFusion` with no sensors.
+    pub fn new(transition_model: &'a dyn TransitionModelLinearNoControl<R>) -> Self {
+        Self {
+            transition_model,
+            sensors: Vec::new(),
+        }
+    }
+
+    /// Add a sensor, returning its index.
+    ///
+    /// The gate is e.g. a [ChiSquareGate](struct.ChiSquareGate.html), or an
+    /// [AdaptiveGate](struct.AdaptiveGate.html) widening after the
+    /// observations of this sensor are rejected.
+    pub fn add_sensor<G>(
+        &mut self,
+        model: &'a dyn ObservationModel<R>,
+        gate: G,
+        config: HealthConfig,
+    ) -> usize
+    where
+        G: Gate<R> + 'a,
+    {
+        self.sensors.push(Sensor {
+            model,
+            gate: Box::new(gate),
+            health: SensorHealth::new(config),
+        });
+        self.sensors.len() - 1
+    }
+
+    /// The health of the given sensor
+    ///
+    /// An [ErrorKind::UnknownSensor] error is returned if no sensor has the
+    /// index `sensor`.
+    pub fn health(&self, sensor: usize) -> Result<&SensorHealth<R>, Error> {
+        self.sensors
+            .get(sensor)
+            .map(|s| &s.health)
+            .ok_or_else(|| ErrorKind::UnknownSensor { sensor }.into())
+    }
+
+    /// Predict and then sequentially update with each sensor's observation
+    ///
+    /// `observations` has one entry per sensor. Each available observation is
+    /// gated against the current estimate; accepted observations of healthy
+    /// sensors are applied in order. The gate of an excluded sensor is reset
+    /// after each of its observations.
+    ///
+    /// # Panics
+    ///
+    /// Panics if there is not one observation entry per sensor.
+    pub fn step(
+        &mut self,
+        previous_estimate: &StateAndCovariance<R>,
+        observations: &[Option<DVector<R>>],
+        covariance_update_method: CovarianceUpdateMethod,
+    ) -> Result<FusionResult<R>, Error> {
+        assert_eq!(observations.len(), self.sensors.len());
+        let mut estimate = self.transition_model.predict(previous_estimate);
+        let mut outcomes = Vec::with_capacity(self.sensors.len());
+        for (sensor, observation) in self.sensors.iter_mut().zip(observations.iter()) {
+            let observation = match observation {
+                Some(v) if !v.iter().any(|x| is_nan(x.clone())) => v,
+                _ => {
+                    outcomes.push(SensorOutcome::Missing);
+                    continue;
+                }
+            };
+            let innovation = sensor.model.innovation(&estimate, observation);
+            let accepted = sensor.gate.check(&innovation)?;
+            sensor.health.record(accepted);
+            let outcome = if sensor.health.is_excluded() {
+                // Recovery is judged against the base gate, not one widened
+                // by every rejection since the exclusion.
+                sensor.gate.reset();
+                SensorOutcome::Excluded
+            } else if accepted {
+                estimate = sensor
+                    .model
+                    .update(&estimate, observation, covariance_update_method)?;
+                SensorOutcome::Applied
+            } else {
+                SensorOutcome::Rejected
+            };
+            outcomes.push(outcome);
+        }
+        Ok(FusionResult { estimate, outcomes })
+    }
+}
+
+#[test]
+fn test_sensor_exclusion_and_recovery() {
+    use crate::test_util::{initial_estimate, ConstantVelocity, Normals, PositionObservation};
+    use crate::ChiSquareGate;
+
+    let transition = ConstantVelocity::new(0.1, 0.01);
+    let good = PositionObservation::new(0.01);
+    let flaky = PositionObservation::new(0.01);
+    let mut fusion = MultiSensorFusion::new(&transition);
+    let config = HealthConfig {
+        max_consecutive_rejections: 3,
+        recovery_acceptances: 5,
+    };
+    fusion.add_sensor(&good, ChiSquareGate::new(1, 0.999), config);
+    fusion.add_sensor(&flaky, ChiSquareGate::new(1, 0.999), config);
+
+    let mut normals = Normals::new(9);
+    let mut estimate = initial_estimate();
+    for i in 0..100 {
+        let position = 0.1 * i as f64;
+        let offset = if (40..60).contains(&i) { 5.0 } else { 0.0 };
+        let observations = [
+            Some(DVector::from_element(1, position + 0.1 * normals.sample())),
+            Some(DVector::from_element(
+                1,
+                position + 0.1 * normals.sample() + offset,
+            )),
+        ];
+        let result = fusion
+            .step(&estimate, &observations, CovarianceUpdateMethod::JosephForm)
+            .unwrap();
+        if i == 45 {
+            assert!(fusion.health(1).unwrap().is_excluded());
+            assert_eq!(result.outcomes[1], SensorOutcome::Excluded);
+        }
+        estimate = result.estimate;
+    }
+    assert!(!fusion.health(0).unwrap().is_excluded());
+    assert!(!fusion.health(1).unwrap().is_excluded());
+    assert!((estimate.state()[0] - 9.9).abs() < 0.5);
+}
+
+#[test]
+fn test_adaptive_gate_sensor() {
+    use crate::test_util::{ConstantVelocity, PositionObservation};
+    use crate::{AdaptiveGate, ChiSquareGate};
+
+    // A stationary target, known exactly apart from unit position variance,
+    // observed with unit noise, so the NIS of an observation y is y^2 / 2.
+    let transition = ConstantVelocity::new(0.0, 0.0);
+    let observation = PositionObservation::new(1.0);
+    let config = HealthConfig {
+        max_consecutive_rejections: 100,
+        recovery_acceptances: 1,
+    };
+    let mut fusion = MultiSensorFusion::new(&transition);
+    fusion.add_sensor(
+        &observation,
+        AdaptiveGate::new(ChiSquareGate::from_threshold(2.0), 4.0, 100.0),
+        config,
+    );
+    fusion.add_sensor(&observation, ChiSquareGate::from_threshold(2.0), config);
+    let estimate = StateAndCovariance::new(
+        DVector::zeros(2),
+        na::DMatrix::from_diagonal(&DVector::from_vec(vec![1.0, 0.0])),
+    );
+
+    // An observation at 4 has NIS 8: the thresholds of the adaptive gate are
+    // 2, then 8, so it is accepted at the second step; the static gate
+    // keeps rejecting it.
+    let observations = vec![Some(DVector::from_element(1, 4.0)); 2];
+    let outcomes: Vec<_> = (0..2)
+        .map(|_| {
+            fusion
+                .step(&estimate, &observations, CovarianceUpdateMethod::JosephForm)
+                .unwrap()
+                .outcomes
+        })
+        .collect();
+    assert_eq!(
+        outcomes,
+        [
+            [SensorOutcome::Rejected, SensorOutcome::Rejected],
+            [SensorOutcome::Applied, SensorOutcome::Rejected],
+        ]
+    );
+
+    // Once the sensor is excluded, its gate stays at the base threshold, so
+    // it cannot widen its way to re-admission.
+    let mut fusion = MultiSensorFusion::new(&transition);
+    let config = HealthConfig {
+        max_consecutive_rejections: 1,
+        recovery_acceptances: 1,
+    };
+    fusion.add_sensor(
+        &observation,
+        AdaptiveGate::new(ChiSquareGate::from_threshold(2.0), 4.0, 100.0),
+        config,
+    );
+    let observations = [Some(DVector::from_element(1, 4.0))];
+    for _ in 0..3 {
+        let result = fusion
+            .step(&estimate, &observations, CovarianceUpdateMethod::JosephForm)
+            .unwrap();
+        assert_eq!(result.outcomes, [SensorOutcome::Excluded]);
+    }
+}
+
+#[test]
+fn test_sensor_health_state_machine() {
+    let mut health = SensorHealth::<f64>::new(HealthConfig {
+        max_consecutive_rejections: 2,
+        recovery_acceptances: 3,
+    });
+
+    // The score decays by a factor of 0.9 with each rejection.
+    health.record(false);
+    assert!(!health.is_excluded());
+    approx::assert_relative_eq!(*health.score(), 0.9);
+    health.record(false);
+    assert!(health.is_excluded());
+    approx::assert_relative_eq!(*health.score(), 0.81);
+
+    // An acceptance resets the rejections, but re-admission needs three in
+    // a row, and a rejection in between starts the count again.
+    health.record(true);
+    health.record(true);
+    assert_eq!(health.consecutive_rejections(), 0);
+    assert!(health.is_excluded());
+    health.record(false);
+    for _ in 0..2 {
+        health.record(true);
+        assert!(health.is_excluded());
+    }
+    health.record(true);
+    assert!(!health.is_excluded());
+}
+
+#[test]
+fn test_unknown_sensor() {
+    use crate::test_util::{ConstantVelocity, PositionObservation};
+    use crate::ChiSquareGate;
+
+    let transition = ConstantVelocity::new(0.1, 1.0);
+    let observation = PositionObservation::new(1.0);
+    let mut fusion = MultiSensorFusion::new(&transition);
+    let index = fusion.add_sensor(
+        &observation,
+        ChiSquareGate::new(1, 0.99),
+        HealthConfig::default(),
+    );
+    assert_eq!(index, 0);
+    assert!(fusion.health(0).is_ok());
+    let err = fusion.health(1).unwrap_err();
+    assert!(matches!(err.kind(), ErrorKind::UnknownSensor { sensor: 1 }));
+}
@@ -0,0 +1,180 @@
+//! Validation gates deciding which observations are used
+
+use na::RealField;
+use nalgebra as na;
+
+use crate::stats::chi_squared_quantile;
+use crate::{Error, Innovation};
+
+/// A validation gate deciding whether an observation is used
+///
+/// Implemented by [ChiSquareGate] and [AdaptiveGate], so that either can be
+/// given where observations are gated, e.g. to
+/// [MultiSensorFusion::add_sensor](struct.MultiSensorFusion.html#method.add_sensor).
+pub trait Gate<R>
+where
+    R: RealField,
+{
+    /// Whether the given normalized innovation squared (NIS) passes the
+    /// gate, updating any state of the gate.
+    fn check_nis(&mut self, nis: &R) -> bool;
+
+    /// Whether the given innovation passes the gate, updating any state of
+    /// the gate.
+    fn check(&mut self, innovation: &Innovation<R>) -> Result<bool, Error> {
+        Ok(self.check_nis(&innovation.nis()?))
+    }
+
+    /// Return the gate to its initial state, e.g. when the observations it
+    /// gates stop being used. Stateless gates need not implement this.
+    fn reset(&mut self) {}
+}
+
+/// Chi-square validation gate on the normalized innovation squared (NIS)
+///
+/// An observation passes the gate when its NIS is no larger than the
+/// threshold. A correct observation of a consistent filter passes with the
+/// probability used to construct the gate.
+#[derive(Debug, Clone)]
+pub struct ChiSquareGate<R>
+where
+    R: RealField,
+{
+    threshold: R,
+}
+
+impl<R> ChiSquareGate<R>
+where
+    R: RealField,
+{
+    /// Create a gate passing a correct observation of dimension `obs_dim`
+    /// with the given `probability`, e.g. 0.99.
+    pub fn new(obs_dim: usize, probability: R) -> Self {
+        Self::from_threshold(chi_squared_quantile(probability, obs_dim))
+    }
+
+    /// Create a gate with an explicit NIS threshold.
+    pub fn from_threshold(threshold: R) -> Self {
+        Self { threshold }
+    }
+
+    /// The NIS threshold.
+    pub fn threshold(&self) -> &R {
+        &self.threshold
+    }
+
+    /// Whether the given NIS passes the gate.
+    pub fn accepts_nis(&self, nis: &R) -> bool {
+        *nis <= self.threshold
+    }
+
+    /// Whether the given innovation passes the gate.
+    pub fn accepts(&self, innovation: &Innovation<R>) -> Result<bool, Error> {
+        Ok(self.accepts_nis(&innovation.nis()?))
+    }
+}
+
+impl<R> Gate<R> for ChiSquareGate<R>
+where
+    R: RealField,
+{
+    fn check_nis(&mut self, nis: &R) -> bool {
+        self.accepts_nis(nis)
+    }
+}
+
+/// Chi-square gate whose threshold widens after consecutive rejections
+///
+/// A static gate tight enough to reject clutter also rejects the valid
+/// observations of a target which has just maneuvered, and the track is then
+/// lost for good. Here each consecutive rejection multiplies the threshold
+/// by a widening factor, up to a maximum, so that the gate eventually admits
+/// the observations and the track recovers. An accepted observation resets
+/// the threshold to its base value.
+#[derive(Debug, Clone)]
+pub struct AdaptiveGate<R>
+where
+    R: RealField,
+{
+    base: ChiSquareGate<R>,
+    widening: R,
+    max_threshold: R,
+    threshold: R,
+    consecutive_rejections: usize,
+}
+
+impl<R> AdaptiveGate<R>
+where
+    R: RealField,
+{
+    /// Create an adaptive gate from a base gate, the factor by which the
+    /// threshold widens after each rejection, and the largest threshold.
+    pub fn new(base: ChiSquareGate<R>, widening: R, max_threshold: R) -> Self {
+        let threshold = base.threshold().clone();
+        Self {
+            base,
+            widening,
+            max_threshold,
+            threshold,
+            consecutive_rejections: 0,
+        }
+    }
+
+    /// The current NIS threshold.
+    pub fn threshold(&self) -> &R {
+        &self.threshold
+    }
+
+    /// The number of consecutive rejections.
+    pub fn consecutive_rejections(&self) -> usize {
+        self.consecutive_rejections
+    }
+}
+
+impl<R> Gate<R> for AdaptiveGate<R>
+where
+    R: RealField,
+{
+    /// Whether the given NIS passes the gate, adapting the threshold.
+    fn check_nis(&mut self, nis: &R) -> bool {
+        if *nis <= self.threshold {
+            self.threshold = self.base.threshold().clone();
+            self.consecutive_rejections = 0;
+            true
+        } else {
+            self.consecutive_rejections += 1;
+            let widened = self.threshold.clone() * self.widening.clone();
+            self.threshold = widened.min(self.max_threshold.clone());
+            false
+        }
+    }
+
+    /// Restore the base threshold and clear the rejection count.
+    fn reset(&mut self) {
+        self.threshold = self.base.threshold().clone();
+        self.consecutive_rejections = 0;
+    }
+}
+
+#[test]
+fn test_adaptive_gate() {
+    let mut gate = AdaptiveGate::new(ChiSquareGate::from_threshold(4.0), 2.0, 20.0);
+
+    // After a maneuver, the NIS stays at 10 until the gate widens to it.
+    assert!(!gate.check_nis(&10.0));
+    assert_eq!(*gate.threshold(), 8.0);
+    assert!(!gate.check_nis(&10.0));
+    assert_eq!(gate.consecutive_rejections(), 2);
+    assert_eq!(*gate.threshold(), 16.0);
+    assert!(gate.check_nis(&10.0));
+    assert_eq!(*gate.threshold(), 4.0);
+
+    // The threshold is capped.
+    for _ in 0..5 {
+        gate.check_nis(&100.0);
+    }
+    assert_eq!(*gate.threshold(), 20.0);
+    gate.reset();
+    assert_eq!(*gate.threshold(), 4.0);
+    assert_eq!(gate.consecutive_rejections(), 0);
+}
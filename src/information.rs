@@ -0,0 +1,382 @@
+//! Extended and unscented information filters
+//!
+//! In information form, an estimate is held as the information matrix
+//! `Y = P^-1` and the information vector `y = P^-1 x`. The update with the
+//! observations of several sensors then reduces to adding their
+//! contributions `(i, I)` to the prior, in any order. Each sensor can
+//! compute its contribution locally from the shared prior, which makes
+//! decentralized fusion simple.
+
+use na::{DMatrix, DVector, RealField};
+use nalgebra as na;
+
+use crate::{
+    unscented_transform, Error, ErrorKind, NonlinearTransitionModel, ObservationModel,
+    SigmaPointStrategy, StateAndCovariance,
+};
+
+/// An estimate in information form
+#[derive(Debug, Clone, PartialEq)]
+pub struct InformationState<R>
+where
+    R: RealField,
+{
+    vector: DVector<R>,
+    matrix: DMatrix<R>,
+}
+
+impl<R> InformationState<R>
+where
+    R: RealField,
+{
+    /// Create a new information state from the information vector `y` and
+    /// matrix `Y`.
+    pub fn new(vector: DVector<R>, matrix: DMatrix<R>) -> Self {
+        Self { vector, matrix }
+    }
+
+    /// Convert an estimate to information form. An
+    /// [ErrorKind::CovarianceNotPositiveSemiDefinite] error is returned if
+    /// its covariance is not positive definite.
+    pub fn from_estimate(estimate: &StateAndCovariance<R>) -> Result<Self, Error> {
+        let matrix = invert(estimate.covariance())?;
+        let vector = &matrix * estimate.state();
+        Ok(Self { vector, matrix })
+    }
+
+    /// Convert to an estimate with a state and covariance. An
+    /// [ErrorKind::SingularMatrix] error is returned if the information
+    /// matrix is singular, i.e. some component of the state is unknown.
+    pub fn to_estimate(&self) -> Result<StateAndCovariance<R>, Error> {
+        let covariance = match na::linalg::Cholesky::new(self.matrix.clone()) {
+            Some(chol) => chol.inverse(),
+            None => return Err(ErrorKind::SingularMatrix.into()),
+        };
+        let state = &covariance * &self.vector;
+        Ok(StateAndCovariance::new(state, covariance))
+    }
+
+    /// Get the information vector, `y`.
+    pub fn information_vector(&self) -> &DVector<R> {
+        &self.vector
+    }
+
+    /// Get the information matrix, `Y`.
+    pub fn information_matrix(&self) -> &DMatrix<R> {
+        &self.matrix
+    }
+
+    /// Add the contribution of an observation.
+    pub fn add(&mut self, contribution: &InformationContribution<R>) {
+        self.vector += &contribution.vector;
+        self.matrix += &contribution.matrix;
+    }
+}
+
+/// The information contributed by an observation, `i = H^T R^-1 z'` and
+/// `I = H^T R^-1 H`, where `z'` is the observation adjusted for the
+/// linearization.
+#[derive(Debug, Clone, PartialEq)]
+pub struct InformationContribution<R>
+where
+    R: RealField,
+{
+    /// The contribution to the information vector, `i`.
+    pub vector: DVector<R>,
+    /// The contribution to the information matrix, `I`.
+    pub matrix: DMatrix<R>,
+}
+
+fn invert<R: RealField>(matrix: &DMatrix<R>) -> Result<DMatrix<R>, Error> {
+    match na::linalg::Cholesky::new(matrix.clone()) {
+        Some(chol) => Ok(chol.inverse()),
+        None => Err(ErrorKind::CovarianceNotPositiveSemiDefinite.into()),
+    }
+}
+
+/// The contribution given the pseudo-observation matrix `H`, i.e. the
+/// linearization of the observation model, and the residual of the
+/// observation at the prior mean.
+pub(crate) fn linearized_contribution<R: RealField>(
+    H: &DMatrix<R>,
+    R: &DMatrix<R>,
+    prior_state: &DVector<R>,
+    residual: DVector<R>,
+) -> Result<InformationContribution<R>, Error> {
+    let HT_R_inv = H.transpose() * invert(R)?;
+    let vector = &HT_R_inv * (residual + H * prior_state);
+    let matrix = HT_R_inv * H;
+    Ok(InformationContribution { vector, matrix })
+}
+
+/// An extended information filter with no control inputs
+///
+/// The prediction is that of the
+/// [ExtendedKalmanFilter](crate::ExtendedKalmanFilter), performed in
+/// covariance form. Observation contributions use `H` of the
+/// [ObservationModel], which must be linearized about the prior state.
+pub struct ExtendedInformationFilter<'a, R>
+where
+    R: RealField,
+{
+    transition_model: &'a dyn NonlinearTransitionModel<R>,
+}
+
+impl<'a, R> ExtendedInformationFilter<'a, R>
+where
+    R: RealField,
+{
+    /// Initialize a new `ExtendedInformationFilter` struct.
+    pub fn new(transition_model: &'a dyn NonlinearTransitionModel<R>) -> Self {
+        Self { transition_model }
+    }
+
+    /// Predict the prior from the previous estimate.
+    pub fn predict(
+        &self,
+        previous_estimate: &InformationState<R>,
+    ) -> Result<InformationState<R>, Error> {
+        let estimate = previous_estimate.to_estimate()?;
+        InformationState::from_estimate(&self.transition_model.propagate(&estimate))
+    }
+
+    /// The contribution of an observation by one sensor, given the prior.
+    pub fn contribution(
+        &self,
+        prior: &InformationState<R>,
+        observation_model: &dyn ObservationModel<R>,
+        observation: &DVector<R>,
+    ) -> Result<InformationContribution<R>, Error> {
+        let state = prior.to_estimate()?.state().clone();
+        let residual = observation - observation_model.predict_observation(&state);
+        linearized_contribution(
+            observation_model.H(),
+            observation_model.R(),
+            &state,
+            residual,
+        )
+    }
+
+    /// Update the prior with the observations of several sensors, given
+    /// with their observation models.
+    pub fn update(
+        &self,
+        prior: &InformationState<R>,
+        observations: &[(&dyn ObservationModel<R>, DVector<R>)],
+    ) -> Result<InformationState<R>, Error> {
+        let mut posterior = prior.clone();
+        for (model, observation) in observations {
+            posterior.add(&self.contribution(prior, *model, observation)?);
+        }
+        Ok(posterior)
+    }
+}
+
+/// An unscented information filter with no control inputs
+///
+/// The prediction is that of the
+/// [UnscentedKalmanFilter](crate::UnscentedKalmanFilter), performed in
+/// covariance form. Following Lee (2008), an observation contributes as if
+/// observed through the statistical linearization
+/// `H = (P^-1 Pxy)^T`, where `Pxy` is the cross-covariance of the state and
+/// the observation given by the unscented transform.
+pub struct UnscentedInformationFilter<'a, R, S>
+where
+    R: RealField,
+    S: SigmaPointStrategy<R>,
+{
+    transition_model: &'a dyn NonlinearTransitionModel<R>,
+    strategy: S,
+}
+
+impl<'a, R, S> UnscentedInformationFilter<'a, R, S>
+where
+    R: RealField,
+    S: SigmaPointStrategy<R>,
+{
+    /// Initialize a new `UnscentedInformationFilter` struct.
+    pub fn new(transition_model: &'a dyn NonlinearTransitionModel<R>, strategy: S) -> Self {
+        Self {
+            transition_model,
+            strategy,
+        }
+    }
+
+    /// Predict the prior from the previous estimate.
+    pub fn predict(
+        &self,
+        previous_estimate: &InformationState<R>,
+    ) -> Result<InformationState<R>, Error> {
+        let estimate = previous_estimate.to_estimate()?;
+        let (state, covariance, _) = unscented_transform(
+            estimate.state(),
+            estimate.covariance(),
+            |x| self.transition_model.f(x),
+            &self.strategy,
+        )?;
+        InformationState::from_estimate(&StateAndCovariance::new(
+            state,
+            covariance + self.transition_model.Q(),
+        ))
+    }
+
+    /// The contribution of an observation by one sensor, given the prior.
+    pub fn contribution(
+        &self,
+        prior: &InformationState<R>,
+        observation_model: &dyn ObservationModel<R>,
+        observation: &DVector<R>,
+    ) -> Result<InformationContribution<R>, Error> {
+        let estimate = prior.to_estimate()?;
+        let (predicted, _, Pxy) = unscented_transform(
+            estimate.state(),
+            estimate.covariance(),
+            |x| observation_model.predict_observation(x),
+            &self.strategy,
+        )?;
+        let H = (prior.information_matrix() * Pxy).transpose();
+        linearized_contribution(
+            &H,
+            observation_model.R(),
+            estimate.state(),
+            observation - predicted,
+        )
+    }
+
+    /// Update the prior with the observations of several sensors, given
+    /// with their observation models.
+    pub fn update(
+        &self,
+        prior: &InformationState<R>,
+        observations: &[(&dyn ObservationModel<R>, DVector<R>)],
+    ) -> Result<InformationState<R>, Error> {
+        let mut posterior = prior.clone();
+        for (model, observation) in observations {
+            posterior.add(&self.contribution(prior, *model, observation)?);
+        }
+        Ok(posterior)
+    }
+}
+
+#[test]
+fn test_information_filters() {
+    use crate::test_util::{initial_estimate, ConstantVelocity, MatrixObservation};
+    use crate::{
+        CovarianceUpdateMethod, Linearized, MerweScaledSigmaPoints, TransitionModelLinearNoControl,
+    };
+
+    // Two sensors, observing position and velocity.
+    let transition = ConstantVelocity::new(0.1, 1.0);
+    let linearized = Linearized(&transition);
+    let position = MatrixObservation::new(
+        DMatrix::from_row_slice(1, 2, &[1.0, 0.0]),
+        DMatrix::from_element(1, 1, 0.5),
+    );
+    let velocity = MatrixObservation::new(
+        DMatrix::from_row_slice(1, 2, &[0.0, 1.0]),
+        DMatrix::from_element(1, 1, 0.2),
+    );
+    let z1 = DVector::from_element(1, 0.3);
+    let z2 = DVector::from_element(1, 0.8);
+
+    // Adding the contributions is the same as updating sequentially.
+    let method = CovarianceUpdateMethod::JosephForm;
+    let prior = TransitionModelLinearNoControl::predict(&transition, &initial_estimate());
+    let expected = position.update(&prior, &z1, method).unwrap();
+    let expected = velocity.update(&expected, &z2, method).unwrap();
+
+    let previous = InformationState::from_estimate(&initial_estimate()).unwrap();
+    let observations: [(&dyn ObservationModel<f64>, DVector<f64>); 2] =
+        [(&position, z1), (&velocity, z2)];
+
+    let eif = ExtendedInformationFilter::new(&linearized);
+    let prior = eif.predict(&previous).unwrap();
+    let posterior = eif
+        .update(&prior, &observations)
+        .unwrap()
+        .to_estimate()
+        .unwrap();
+    approx::assert_relative_eq!(posterior.state(), expected.state(), epsilon = 1e-9);
+    approx::assert_relative_eq!(
+        posterior.covariance(),
+        expected.covariance(),
+        epsilon = 1e-9
+    );
+
+    let uif = UnscentedInformationFilter::new(&linearized, MerweScaledSigmaPoints::default());
+    let prior = uif.predict(&previous).unwrap();
+    let posterior = uif
+        .update(&prior, &observations)
+        .unwrap()
+        .to_estimate()
+        .unwrap();
+    approx::assert_relative_eq!(posterior.state(), expected.state(), epsilon = 1e-6);
+    approx::assert_relative_eq!(
+        posterior.covariance(),
+        expected.covariance(),
+        epsilon = 1e-6
+    );
+}
+
+#[test]
+fn test_information_contributions() {
+    use crate::test_util::{initial_estimate, ConstantVelocity, MatrixObservation};
+    use crate::Linearized;
+
+    let transition = ConstantVelocity::new(0.1, 1.0);
+    let linearized = Linearized(&transition);
+    let position = MatrixObservation::new(
+        DMatrix::from_row_slice(1, 2, &[1.0, 0.0]),
+        DMatrix::from_element(1, 1, 0.5),
+    );
+    let both = MatrixObservation::new(
+        DMatrix::from_row_slice(2, 2, &[1.0, 0.0, 1.0, 1.0]),
+        DMatrix::from_diagonal(&DVector::from_vec(vec![0.4, 0.2])),
+    );
+    let z1 = DVector::from_element(1, 0.3);
+    let z2 = DVector::from_vec(vec![0.1, 0.9]);
+
+    // Converting to information form and back gives the estimate.
+    let previous = InformationState::from_estimate(&initial_estimate()).unwrap();
+    approx::assert_relative_eq!(
+        previous.to_estimate().unwrap(),
+        initial_estimate(),
+        epsilon = 1e-12
+    );
+
+    // A linear sensor contributes `H^T R^-1 z` and `H^T R^-1 H` whatever the
+    // prior, and each sensor's contribution can be computed on its own and
+    // added in any order.
+    let eif = ExtendedInformationFilter::new(&linearized);
+    let prior = eif.predict(&previous).unwrap();
+    let contribution = eif.contribution(&prior, &both, &z2).unwrap();
+    let HT_R_inv = both.H().transpose() * both.R().clone().try_inverse().unwrap();
+    approx::assert_relative_eq!(contribution.vector, &HT_R_inv * &z2, epsilon = 1e-12);
+    approx::assert_relative_eq!(contribution.matrix, &HT_R_inv * both.H(), epsilon = 1e-12);
+    let mut fused = prior.clone();
+    fused.add(&contribution);
+    fused.add(&eif.contribution(&prior, &position, &z1).unwrap());
+    let forward = eif
+        .update(&prior, &[(&position, z1.clone()), (&both, z2.clone())])
+        .unwrap();
+    let backward = eif.update(&prior, &[(&both, z2), (&position, z1)]).unwrap();
+    approx::assert_relative_eq!(forward.vector, fused.vector, epsilon = 1e-12);
+    approx::assert_relative_eq!(forward.matrix, fused.matrix, epsilon = 1e-12);
+    approx::assert_relative_eq!(backward.vector, fused.vector, epsilon = 1e-12);
+    approx::assert_relative_eq!(backward.matrix, fused.matrix, epsilon = 1e-12);
+    assert_eq!(eif.update(&prior, &[]).unwrap(), prior);
+
+    // Without any information, there is no estimate, and a covariance which
+    // is not positive definite has no information form.
+    let unknown = InformationState::<f64>::new(DVector::zeros(2), DMatrix::zeros(2, 2));
+    let err = unknown.to_estimate().unwrap_err();
+    assert!(matches!(err.kind(), ErrorKind::SingularMatrix));
+    let err = eif.predict(&unknown).unwrap_err();
+    assert!(matches!(err.kind(), ErrorKind::SingularMatrix));
+    let indefinite = StateAndCovariance::new(DVector::zeros(2), -DMatrix::<f64>::identity(2, 2));
+    let err = InformationState::from_estimate(&indefinite).unwrap_err();
+    assert!(matches!(
+        err.kind(),
+        ErrorKind::CovarianceNotPositiveSemiDefinite
+    ));
+}
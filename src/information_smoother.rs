@@ -0,0 +1,139 @@
+//! Smoothing in information form, without inverting predicted covariances
+
+use na::{DMatrix, DVector, RealField};
+use nalgebra as na;
+
+use crate::{is_nan, Error, ErrorKind, KalmanFilterNoControl, StateAndCovariance};
+
+/// The quantities of one forward step needed by the backward pass
+struct ForwardStep<R>
+where
+    R: RealField,
+{
+    prior: StateAndCovariance<R>,
+    /// `H^T S^-1 e`, `H^T S^-1 H` and `I - K H`, if the step was updated.
+    update: Option<(DVector<R>, DMatrix<R>, DMatrix<R>)>,
+}
+
+impl<'a, R> KalmanFilterNoControl<'a, R>
+where
+    R: RealField,
+{
+    /// Smoother in information form (modified Bryson-Frazier)
+    ///
+    /// This gives the same estimates as the RTS smoother
+    /// [`smooth`](struct.KalmanFilterNoControl.html#method.smooth), but the
+    /// backward pass propagates the information vector and matrix of the
+    /// future observations instead of inverting each predicted covariance.
+    /// The only matrices inverted are the innovation covariances, so it
+    /// succeeds when a predicted covariance is singular or nearly so, e.g.
+    /// with states that have no process noise.
+    ///
+    /// If any observation has a NaN component, it is treated as missing.
+    pub fn smooth_information_form(
+        &self,
+        initial_estimate: &StateAndCovariance<R>,
+        observations: &[DVector<R>],
+    ) -> Result<Vec<StateAndCovariance<R>>, Error> {
+        let n = initial_estimate.state().nrows();
+        let identity = DMatrix::<R>::identity(n, n);
+        let H = self.observation_matrix.H();
+        let HT = &*self.observation_matrix.HT();
+
+        let mut forward = Vec::with_capacity(observations.len());
+        let mut previous_estimate = initial_estimate.clone();
+        for observation in observations.iter() {
+            let prior = self.predict(&previous_estimate);
+            if observation.iter().any(|x| is_nan(x.clone())) {
+                previous_estimate = prior.clone();
+                forward.push(ForwardStep {
+                    prior,
+                    update: None,
+                });
+                continue;
+            }
+            let innovation = self.observation_matrix.innovation(&prior, observation);
+            let s_chol = match na::linalg::Cholesky::new(innovation.covariance().clone()) {
+                Some(v) => v,
+                None => return Err(ErrorKind::CovarianceNotPositiveSemiDefinite.into()),
+            };
+            let S_inv_H = s_chol.solve(H);
+            let K = prior.covariance() * S_inv_H.transpose();
+            let one_minus_kh = &identity - &K * H;
+            let information_vector = HT * s_chol.solve(innovation.residual());
+            let information_matrix = HT * S_inv_H;
+
+            let state = prior.state() + &K * innovation.residual();
+            let covariance = (&one_minus_kh * prior.covariance() * one_minus_kh.transpose()
+                + &K * self.observation_matrix.R() * K.transpose())
+            .symmetric_part();
+            previous_estimate = StateAndCovariance::new(state, covariance);
+            forward.push(ForwardStep {
+                prior,
+                update: Some((information_vector, information_matrix, one_minus_kh)),
+            });
+        }
+
+        let F = self.transition_model.F();
+        let FT = &*self.transition_model.FT();
+        // Information from future observations about the posterior state.
+        let mut lambda = DVector::<R>::zeros(n);
+        let mut Lambda = DMatrix::<R>::zeros(n, n);
+        let mut smoothed = Vec::with_capacity(forward.len());
+        for step in forward.iter().rev() {
+            // Include this step's observation, giving the information about
+            // the prior state.
+            if let Some((information_vector, information_matrix, one_minus_kh)) = &step.update {
+                lambda = information_vector + one_minus_kh.transpose() * lambda;
+                Lambda = information_matrix + one_minus_kh.transpose() * Lambda * one_minus_kh;
+            }
+            let P = step.prior.covariance();
+            let state = step.prior.state() + P * &lambda;
+            let covariance = (P - P * &Lambda * P).symmetric_part();
+            smoothed.push(StateAndCovariance::new(state, covariance));
+
+            lambda = FT * lambda;
+            Lambda = FT * Lambda * F;
+        }
+        smoothed.reverse();
+        Ok(smoothed)
+    }
+}
+
+#[test]
+fn test_information_smoother() {
+    use crate::test_util::{
+        initial_estimate, simulate_positions, ConstantVelocity, PositionObservation,
+    };
+
+    let transition = ConstantVelocity::new(0.1, 1.0);
+    let observation = PositionObservation::new(0.5);
+    let kf = KalmanFilterNoControl::new(&transition, &observation);
+    let mut observations = simulate_positions(20, 0.1, 1.0, 0.5, 8);
+    observations[5][0] = f64::NAN;
+    let rts = kf.smooth(&initial_estimate(), &observations).unwrap();
+    let information = kf
+        .smooth_information_form(&initial_estimate(), &observations)
+        .unwrap();
+    for (a, b) in rts.iter().zip(information.iter()) {
+        approx::assert_relative_eq!(a.state(), b.state(), epsilon = 1e-10);
+        approx::assert_relative_eq!(a.covariance(), b.covariance(), epsilon = 1e-10);
+    }
+
+    // With no process noise and a known velocity, every predicted covariance
+    // is singular, so the RTS smoother fails.
+    let transition = ConstantVelocity::new(0.1, 0.0);
+    let kf = KalmanFilterNoControl::new(&transition, &observation);
+    let initial = StateAndCovariance::new(
+        initial_estimate().state().clone(),
+        DMatrix::from_diagonal(&DVector::from_vec(vec![1.0, 0.0])),
+    );
+    assert!(kf.smooth(&initial, &observations).is_err());
+    let smoothed = kf.smooth_information_form(&initial, &observations).unwrap();
+    let filtered = kf.filter(&initial, &observations).unwrap();
+    approx::assert_relative_eq!(
+        smoothed[0].state()[0],
+        filtered[19].state()[0] - 1.9,
+        epsilon = 1e-10
+    );
+}
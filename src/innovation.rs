@@ -0,0 +1,91 @@
+use na::{DMatrix, DVector, RealField};
+use nalgebra as na;
+
+use crate::{Error, ErrorKind, Operation};
+
+/// Innovation (measurement residual) and its covariance for a single update
+#[derive(Debug, Clone)]
+pub struct Innovation<R>
+where
+    R: RealField,
+{
+    residual: DVector<R>,
+    covariance: DMatrix<R>,
+}
+
+impl<R> Innovation<R>
+where
+    R: RealField,
+{
+    /// Create a new `Innovation` from the residual `y - h(x)` and the
+    /// innovation covariance `S = H P H^T + R`.
+    pub fn new(residual: DVector<R>, covariance: DMatrix<R>) -> Self {
+        Self {
+            residual,
+            covariance,
+        }
+    }
+    /// Get a reference to the residual vector.
+    #[inline]
+    pub fn residual(&self) -> &DVector<R> {
+        &self.residual
+    }
+    /// Get a reference to the innovation covariance matrix.
+    #[inline]
+    pub fn covariance(&self) -> &DMatrix<R> {
+        &self.covariance
+    }
+    /// Get the residual vector and innovation covariance matrix.
+    #[inline]
+    pub fn inner(self) -> (DVector<R>, DMatrix<R>) {
+        (self.residual, self.covariance)
+    }
+
+    fn cholesky(&self) -> Result<na::linalg::Cholesky<R, na::Dynamic>, Error> {
+        na::linalg::Cholesky::new(self.covariance.clone()).ok_or_else(|| {
+            Error::from(ErrorKind::CovarianceNotPositiveSemiDefinite)
+                .with_operation(Operation::Likelihood)
+                .with_matrix(&self.covariance)
+        })
+    }
+
+    /// Normalized innovation squared, `y^T S^-1 y`.
+    ///
+    /// For a consistent filter this is chi-square distributed with as many
+    /// degrees of freedom as the observation has dimensions.
+    pub fn nis(&self) -> Result<R, Error> {
+        let s_chol = self.cholesky()?;
+        let solved = s_chol.solve(&self.residual);
+        Ok(self.residual.dot(&solved))
+    }
+
+    /// Log of the Gaussian probability density of the residual given the
+    /// innovation covariance.
+    pub fn log_likelihood(&self) -> Result<R, Error> {
+        let s_chol = self.cholesky()?;
+        let solved = s_chol.solve(&self.residual);
+        let nis = self.residual.dot(&solved);
+        let log_det = s_chol
+            .l_dirty()
+            .diagonal()
+            .iter()
+            .fold(R::zero(), |acc, d| acc + d.clone().ln());
+        let half: R = na::convert(0.5);
+        let n: R = na::convert(self.residual.nrows() as f64);
+        Ok(-half.clone() * (n * R::two_pi().ln() + nis) - log_det)
+    }
+}
+
+#[test]
+fn test_innovation_log_likelihood() {
+    let innovation = Innovation::new(
+        DVector::from_vec(vec![1.0, -2.0]),
+        DMatrix::from_row_slice(2, 2, &[2.0, 0.0, 0.0, 4.0]),
+    );
+    approx::assert_relative_eq!(innovation.nis().unwrap(), 1.5);
+    // Product of two independent univariate normal densities.
+    let expected = -0.5 * (2.0 * std::f64::consts::PI * 2.0).ln() - 0.25
+        + -0.5 * (2.0 * std::f64::consts::PI * 4.0).ln()
+        - 0.5;
+    approx::assert_relative_eq!(innovation.log_likelihood().unwrap(), expected);
+}
@@ -0,0 +1,328 @@
+//! Compensating measurement latency by re-filtering buffered steps
+
+use std::collections::VecDeque;
+
+use na::{DVector, RealField};
+use nalgebra as na;
+
+use crate::{
+    check_dimension, is_nan, CovarianceUpdateMethod, Error, ErrorKind, KalmanFilterNoControl,
+    ObservationModel, StateAndCovariance,
+};
+
+struct BufferedStep<'a, R>
+where
+    R: RealField,
+{
+    observation: DVector<R>,
+    delayed: Vec<(&'a dyn ObservationModel<R>, DVector<R>)>,
+    estimate: StateAndCovariance<R>,
+}
+
+/// A Kalman filter which applies delayed measurements at their true time
+///
+/// The observations and posteriors of the most recent steps are buffered. A
+/// measurement which arrives late, e.g. from a camera pipeline, is applied as
+/// an extra update at the step when it was taken, and the later steps are
+/// filtered again from there, so the current estimate is as if the
+/// measurement had arrived on time. Updating the current estimate with it
+/// instead would compare it with the wrong state, biasing the estimates of
+/// rates such as velocity.
+pub struct LatencyCompensator<'a, R>
+where
+    R: RealField,
+{
+    kf: KalmanFilterNoControl<'a, R>,
+    base: StateAndCovariance<R>,
+    buffer: VecDeque<BufferedStep<'a, R>>,
+    capacity: usize,
+}
+
+impl<'a, R> LatencyCompensator<'a, R>
+where
+    R: RealField,
+{
+    /// Create a compensator buffering up to `capacity` steps, which is the
+    /// longest delay, in steps, that can be compensated.
+    pub fn new(
+        kf: KalmanFilterNoControl<'a, R>,
+        initial_estimate: StateAndCovariance<R>,
+        capacity: usize,
+    ) -> Self {
+        Self {
+            kf,
+            base: initial_estimate,
+            buffer: VecDeque::with_capacity(capacity + 1),
+            capacity,
+        }
+    }
+
+    /// The current estimate.
+    pub fn estimate(&self) -> &StateAndCovariance<R> {
+        self.buffer
+            .back()
+            .map(|step| &step.estimate)
+            .unwrap_or(&self.base)
+    }
+
+    /// The number of buffered steps.
+    pub fn len(&self) -> usize {
+        self.buffer.len()
+    }
+
+    /// Whether no steps are buffered.
+    pub fn is_empty(&self) -> bool {
+        self.buffer.is_empty()
+    }
+
+    /// Perform prediction and update steps with the on-time observation,
+    /// see [KalmanFilterNoControl::step], returning the current estimate.
+    ///
+    /// A NaN observation, for a step without one, is buffered as missing.
+    pub fn step(&mut self, observation: DVector<R>) -> Result<&StateAndCovariance<R>, Error> {
+        let estimate = self.kf.step(self.estimate(), &observation)?;
+        self.buffer.push_back(BufferedStep {
+            observation,
+            delayed: Vec::new(),
+            estimate,
+        });
+        if self.buffer.len() > self.capacity {
+            self.base = self.buffer.pop_front().unwrap().estimate;
+        }
+        Ok(self.estimate())
+    }
+
+    /// Apply a measurement taken `delay` steps before the current step,
+    /// returning the corrected current estimate
+    ///
+    /// The measurement is applied with `observation_model` after the other
+    /// observations of its step, following the failure policy of the
+    /// filter, and the steps since are filtered again. A delay of zero is
+    /// the current step. An [ErrorKind::DimensionMismatch] error is returned
+    /// if `observation_model` is not for the state of the filter or the
+    /// observation is not of its size, an [ErrorKind::DelayNotBuffered]
+    /// error if the step is no longer buffered, and errors of
+    /// the re-filtering give the delay of the failed step. On error, the
+    /// measurement is discarded and the buffered estimates are unchanged.
+    pub fn apply_delayed(
+        &mut self,
+        delay: usize,
+        observation_model: &'a dyn ObservationModel<R>,
+        observation: DVector<R>,
+    ) -> Result<&StateAndCovariance<R>, Error> {
+        let ss = self.kf.transition_model.state_dim();
+        let os = observation_model.obs_dim();
+        check_dimension("H", (os, ss), (os, observation_model.state_dim()))?;
+        check_dimension("observation", (os, 1), observation.shape())?;
+        if delay >= self.buffer.len() {
+            return Err(ErrorKind::DelayNotBuffered {
+                delay,
+                buffered: self.buffer.len(),
+            }
+            .into());
+        }
+        let index = self.buffer.len() - 1 - delay;
+        self.buffer[index]
+            .delayed
+            .push((observation_model, observation));
+        match self.refilter(index) {
+            Ok(estimates) => {
+                for (step, estimate) in self.buffer.range_mut(index..).zip(estimates) {
+                    step.estimate = estimate;
+                }
+                Ok(self.estimate())
+            }
+            Err(e) => {
+                self.buffer[index].delayed.pop();
+                Err(e)
+            }
+        }
+    }
+
+    /// Filter the buffered steps again, from the given index, returning the
+    /// new estimates of those steps.
+    fn refilter(&self, from: usize) -> Result<Vec<StateAndCovariance<R>>, Error> {
+        let last = self.buffer.len() - 1;
+        let mut estimates: Vec<StateAndCovariance<R>> = Vec::with_capacity(last + 1 - from);
+        for i in from..self.buffer.len() {
+            let previous = match estimates.last() {
+                Some(estimate) => estimate,
+                None if i == 0 => &self.base,
+                None => &self.buffer[i - 1].estimate,
+            };
+            let step = &self.buffer[i];
+            let mut estimate = self
+                .kf
+                .step(previous, &step.observation)
+                .map_err(|e| e.with_step(last - i))?;
+            for (model, observation) in step.delayed.iter() {
+                if observation.iter().any(|x| is_nan(x.clone())) {
+                    continue;
+                }
+                estimate = self
+                    .kf
+                    .with_observation_model(*model)
+                    .update_with_policy(
+                        &estimate,
+                        observation,
+                        model.R(),
+                        CovarianceUpdateMethod::JosephForm,
+                    )
+                    .map_err(|e| e.with_step(last - i))?;
+            }
+            estimates.push(estimate);
+        }
+        Ok(estimates)
+    }
+}
+
+#[test]
+fn test_latency_compensation() {
+    use crate::test_util::{
+        initial_estimate, simulate_positions, ConstantVelocity, MatrixObservation,
+        PositionObservation,
+    };
+    use na::DMatrix;
+
+    let transition = ConstantVelocity::new(0.1, 1.0);
+    let position = PositionObservation::new(0.5);
+    let velocity = MatrixObservation::new(
+        DMatrix::from_row_slice(1, 2, &[0.0, 1.0]),
+        DMatrix::from_element(1, 1, 0.1),
+    );
+    let observations = simulate_positions(10, 0.1, 1.0, 0.5, 5);
+    let kf = KalmanFilterNoControl::new(&transition, &position);
+    let mut compensator = LatencyCompensator::new(
+        KalmanFilterNoControl::new(&transition, &position),
+        initial_estimate(),
+        4,
+    );
+    for observation in observations.iter() {
+        compensator.step(observation.clone()).unwrap();
+    }
+    assert_eq!(compensator.len(), 4);
+
+    // A velocity measured at step 7 arrives at step 9.
+    let measured = DVector::from_element(1, 0.8);
+    let actual = compensator
+        .apply_delayed(2, &velocity, measured.clone())
+        .unwrap()
+        .clone();
+    let mut expected = initial_estimate();
+    for (k, observation) in observations.iter().enumerate() {
+        expected = kf.step(&expected, observation).unwrap();
+        if k == 7 {
+            expected = velocity
+                .update(&expected, &measured, CovarianceUpdateMethod::JosephForm)
+                .unwrap();
+        }
+    }
+    approx::assert_relative_eq!(actual, expected, epsilon = 1e-12);
+
+    // Steps after the correction build on it.
+    let next = DVector::from_element(1, 1.0);
+    let corrected = compensator.step(next.clone()).unwrap().clone();
+    assert_eq!(corrected, kf.step(&expected, &next).unwrap());
+
+    let err = compensator
+        .apply_delayed(4, &velocity, measured)
+        .unwrap_err();
+    assert!(matches!(
+        err.kind(),
+        ErrorKind::DelayNotBuffered {
+            delay: 4,
+            buffered: 4
+        }
+    ));
+}
+
+#[test]
+fn test_failed_delayed_measurement() {
+    use crate::test_util::{
+        initial_estimate, ConstantVelocity, MatrixObservation, PositionObservation,
+    };
+    use na::DMatrix;
+
+    let transition = ConstantVelocity::new(0.1, 1.0);
+    let position = PositionObservation::new(0.5);
+    let kf = KalmanFilterNoControl::new(&transition, &position);
+    let mut compensator = LatencyCompensator::new(
+        KalmanFilterNoControl::new(&transition, &position),
+        initial_estimate(),
+        3,
+    );
+    let observations: Vec<_> = (0..3)
+        .map(|k| DVector::from_element(1, k as f64 * 0.1))
+        .collect();
+    for observation in observations.iter() {
+        compensator.step(observation.clone()).unwrap();
+    }
+    let before = compensator.estimate().clone();
+
+    // A negative noise variance far larger than the position variance makes
+    // the innovation covariance negative, failing the update.
+    let broken = MatrixObservation::new(
+        DMatrix::from_row_slice(1, 2, &[1.0, 0.0]),
+        DMatrix::from_element(1, 1, -100.0),
+    );
+    let err = compensator
+        .apply_delayed(1, &broken, DVector::from_element(1, 0.0))
+        .unwrap_err();
+    assert!(matches!(
+        err.kind(),
+        ErrorKind::CovarianceNotPositiveSemiDefinite
+    ));
+    assert_eq!(err.step(), Some(1));
+    assert_eq!(compensator.estimate(), &before);
+
+    // The failed measurement is not kept, so refiltering all the steps for
+    // another one reproduces the plain filter.
+    compensator
+        .apply_delayed(2, &position, DVector::from_element(1, f64::NAN))
+        .unwrap();
+    compensator.step(DVector::from_element(1, 0.3)).unwrap();
+    let mut expected = initial_estimate();
+    for observation in observations.iter() {
+        expected = kf.step(&expected, observation).unwrap();
+    }
+    expected = kf.step(&expected, &DVector::from_element(1, 0.3)).unwrap();
+    approx::assert_relative_eq!(compensator.estimate(), &expected, epsilon = 1e-12);
+
+    // The failure policy of the filter applies to delayed measurements, so
+    // a filter skipping failed updates ignores the broken one.
+    let mut compensator = LatencyCompensator::new(
+        KalmanFilterNoControl::new(&transition, &position)
+            .with_failure_policy(crate::FailurePolicy::SkipUpdate),
+        initial_estimate(),
+        3,
+    );
+    for observation in observations.iter() {
+        compensator.step(observation.clone()).unwrap();
+    }
+    let estimate = compensator
+        .apply_delayed(1, &broken, DVector::from_element(1, 0.0))
+        .unwrap();
+    assert_eq!(estimate, &before);
+
+    // A model for another state, or an observation of the wrong size, is
+    // rejected before it is buffered.
+    let is_mismatch = |result: Result<&StateAndCovariance<f64>, Error>, name: &str| match result {
+        Err(e) => {
+            matches!(e.kind(), ErrorKind::DimensionMismatch { matrix, .. } if *matrix == name)
+        }
+        Ok(_) => false,
+    };
+    let three = MatrixObservation::new(
+        DMatrix::from_row_slice(1, 3, &[1.0, 0.0, 0.0]),
+        DMatrix::from_element(1, 1, 0.5),
+    );
+    assert!(is_mismatch(
+        compensator.apply_delayed(1, &three, DVector::from_element(1, 0.0)),
+        "H"
+    ));
+    assert!(is_mismatch(
+        compensator.apply_delayed(1, &position, DVector::from_element(2, 0.0)),
+        "observation"
+    ));
+    assert_eq!(compensator.estimate(), &before);
+}
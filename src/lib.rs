@@ -23,14 +23,15 @@
 #![cfg_attr(not(feature = "std"), no_std)]
 #![allow(non_snake_case)]
 
-#[cfg(debug_assertions)]
-use approx::assert_relative_eq;
+extern crate alloc;
+
+use alloc::borrow::Cow;
 #[cfg(feature = "std")]
 use log::trace;
 use na::{DMatrix, DVector};
 use nalgebra as na;
 
-use na::{RealField};
+use na::{ComplexField, RealField};
 
 // Without std, create a dummy trace!() macro.
 #[cfg(not(feature = "std"))]
@@ -39,14 +40,62 @@ macro_rules! trace {
     ($e:expr, $($es:expr),+) => {{}};
 }
 
-/// perform a runtime check that matrix is symmetric
+/// perform a runtime check that matrix is symmetric (Hermitian, for a
+/// complex field)
 ///
 /// only compiled in debug mode
 macro_rules! debug_assert_symmetric {
     ($mat:expr) => {
         #[cfg(debug_assertions)]
         {
-            assert_relative_eq!($mat, &$mat.transpose(), max_relative = na::convert(1e-5));
+            assert!(is_hermitian(&$mat), "the matrix is not symmetric");
+        }
+    };
+}
+
+/// perform a runtime check that a matrix is the (conjugate) transpose of
+/// another, as a transpose supplied by a model may be stale
+///
+/// only compiled in debug mode
+macro_rules! debug_assert_transpose {
+    ($mat:expr, $transpose:expr) => {
+        #[cfg(debug_assertions)]
+        {
+            assert!(
+                $mat.adjoint() == *$transpose,
+                "the transpose supplied by the model is inconsistent"
+            );
+        }
+    };
+}
+
+/// perform a runtime check that an update agrees with the reference
+/// implementation of the [testing] module, within a tolerance of the square
+/// root of the machine epsilon
+///
+/// only compiled with the `cross-check` feature
+macro_rules! cross_check_update {
+    ($h:expr, $r:expr, $prior:expr, $innovation:expr, $state:expr, $covariance:expr) => {
+        #[cfg(feature = "cross-check")]
+        {
+            let expected = testing::reference_update_with_residual($h, $r, $prior, $innovation)
+                .expect("the reference update failed");
+            let tolerance: R::RealField =
+                ComplexField::sqrt(<R::RealField as approx::AbsDiffEq>::default_epsilon());
+            // Each element must agree within the tolerance, either absolutely
+            // or relative to its magnitude.
+            let close = |actual: &[R], expected: &[R]| {
+                actual.iter().zip(expected.iter()).all(|(a, b)| {
+                    let scale = a.clone().modulus().max(b.clone().modulus());
+                    (a.clone() - b.clone()).modulus()
+                        <= tolerance.clone() * (na::one::<R::RealField>() + scale)
+                })
+            };
+            assert!(
+                close($state.as_slice(), expected.state().as_slice())
+                    && close($covariance.as_slice(), expected.covariance().as_slice()),
+                "the update differs from the reference implementation"
+            );
         }
     };
 }
@@ -55,51 +104,384 @@ macro_rules! debug_assert_symmetric {
 #[cfg(feature = "std")]
 macro_rules! pretty_print {
     ($arr:expr) => {{
-        let indent = 4;
-        let prefix = String::from_utf8(vec![b' '; indent]).unwrap();
-        let mut result_els = vec!["".to_string()];
-        for i in 0..$arr.nrows() {
-            let mut row_els = vec![];
-            for j in 0..$arr.ncols() {
-                row_els.push(format!("{:12.3}", $arr[(i, j)]));
-            }
-            let row_str = row_els.into_iter().collect::<Vec<_>>().join(" ");
-            let row_str = format!("{}{}", prefix, row_str);
-            result_els.push(row_str);
-        }
-        result_els.into_iter().collect::<Vec<_>>().join("\n")
+        crate::pretty_print(&$arr)
     }};
 }
 
 mod error;
-pub use error::{Error, ErrorKind};
+pub use error::{Error, ErrorKind, ModelError, Operation};
+
+mod linalg;
+
+#[cfg(feature = "std")]
+mod pretty;
+#[cfg(feature = "std")]
+pub use pretty::pretty_print;
+
+mod failure;
+pub use failure::FailurePolicy;
+
+mod validate;
+pub use validate::validate_models;
+
+pub mod testing;
+
+mod models;
+pub use models::{LinearObservationModel, LinearTransitionModel};
+
+mod cached;
+pub use cached::{CachedObservationModel, CachedTransitionModel, FactoredObservationModel};
+
+mod precision;
+pub use precision::{MixedPrecisionKalmanFilter, PrecisionPolicy, StoreF32ComputeF64, Uniform};
+
+mod constraint;
+pub use constraint::SoftConstraint;
+
+mod nav;
+pub use nav::{
+    gps_position_observation, gps_position_velocity_observation, gps_velocity_observation,
+    ins_error_model, InertialNoise, InsErrorStates,
+};
 
 mod state_and_covariance;
 pub use state_and_covariance::StateAndCovariance;
 
+mod nonlinear;
+pub use nonlinear::{
+    numerical_jacobian, ExtendedKalmanFilter, Linearized, NonlinearTransitionModel,
+    NumericalObservationModel, NumericalTransitionModel,
+};
+
+mod bearings;
+pub use bearings::{
+    cartesian_to_modified_polar, modified_polar_bearing_observation, modified_polar_to_cartesian,
+    BearingObservationModel, ModifiedPolarTransitionModel,
+};
+
+mod converted;
+pub use converted::ConvertedMeasurement;
+
+mod unscented;
+pub use unscented::{
+    unscented_transform, JulierSigmaPoints, MerweScaledSigmaPoints, SigmaPointStrategy,
+    SigmaPoints, SphericalSimplexSigmaPoints,
+};
+
+mod ukf;
+pub use ukf::UnscentedKalmanFilter;
+
+mod sr_ukf;
+pub use sr_ukf::{SquareRootEstimate, SquareRootUnscentedKalmanFilter};
+
+mod quadrature;
+pub use quadrature::{GaussHermiteKalmanFilter, GaussHermiteSigmaPoints};
+
+mod cdkf;
+pub use cdkf::{CentralDifferenceKalmanFilter, DividedDifferenceOrder};
+
+mod information;
+pub use information::{
+    ExtendedInformationFilter, InformationContribution, InformationState,
+    UnscentedInformationFilter,
+};
+
+mod fixed_lag;
+pub use fixed_lag::FixedLagSmoother;
+
+mod continuous;
+pub use continuous::{
+    propagate_rk4, ContinuousDiscreteFilter, ContinuousTransitionModel, KalmanBucyFilter,
+};
+
+mod discretize;
+#[cfg(feature = "std")]
+pub use discretize::ContinuousLinearModel;
+pub use discretize::{DiscretizationMethod, DiscretizedTransitionModel};
+
+mod shaping;
+pub use shaping::{augment_with_shaping_filters, NoiseSpectrum};
+
+mod structural;
+pub use structural::StructuralModel;
+
+#[cfg(feature = "autodiff")]
+mod autodiff;
+#[cfg(feature = "autodiff")]
+pub use autodiff::{
+    autodiff_jacobian, AutoDiffObservation, AutoDiffObservationModel, AutoDiffScalar,
+    AutoDiffTransition, AutoDiffTransitionModel, Dual,
+};
+
+mod fixed;
+pub use fixed::{FixedKalmanFilter, FixedStateAndCovariance};
+
+#[cfg(feature = "double-double")]
+mod double_double;
+#[cfg(feature = "double-double")]
+pub use double_double::{DoubleDouble, ParseDoubleDoubleError};
+
+mod innovation;
+pub use innovation::Innovation;
+
+#[cfg(test)]
+mod test_util;
+
+mod riccati;
+
+mod steady_state;
+pub use steady_state::SteadyStateKalmanFilter;
+
+mod chandrasekhar;
+pub use chandrasekhar::FastTimeInvariantFilter;
+
+mod lqr;
+pub use lqr::LinearQuadraticRegulator;
+
+mod reinitialize;
+
+mod freeze;
+
+mod backward;
+
+mod stats;
+pub use stats::{chi_squared_cdf, chi_squared_interval, chi_squared_quantile};
+
+mod consistency;
+pub use consistency::{
+    ljung_box_test, nees, normalized_innovation, posterior_cramer_rao_bound, InnovationMonitor,
+    NisStatus, WhitenessTest,
+};
+
+mod autotune;
+pub use autotune::{
+    autotune, AutotuneCriterion, AutotuneResult, ScaledObservationModel, ScaledTransitionModel,
+};
+
+mod gating;
+pub use gating::{AdaptiveGate, ChiSquareGate, Gate};
+
+mod maneuver;
+pub use maneuver::QBoostingFilter;
+
+mod quantized;
+pub use quantized::QuantizedObservationModel;
+
+mod saturated;
+pub use saturated::SaturatedObservationModel;
+
+mod counts;
+pub use counts::{CountDistribution, CountObservationModel};
+
+mod subset;
+
+#[cfg(feature = "std")]
+mod zupt;
+#[cfg(feature = "std")]
+pub use zupt::{ZeroVelocityUpdate, ZuptDetector};
+
+#[cfg(feature = "std")]
+mod stacked;
+#[cfg(feature = "std")]
+pub use stacked::StackedObservationModel;
+
+#[cfg(feature = "std")]
+mod fusion;
+#[cfg(feature = "std")]
+pub use fusion::{FusionResult, HealthConfig, MultiSensorFusion, SensorHealth, SensorOutcome};
+
+#[cfg(feature = "std")]
+mod stream_merge;
+#[cfg(feature = "std")]
+pub use stream_merge::{filter_events, merge_streams, MeasurementEvent};
+
+#[cfg(feature = "std")]
+mod latency;
+#[cfg(feature = "std")]
+pub use latency::LatencyCompensator;
+
+#[cfg(feature = "std")]
+mod fdi;
+#[cfg(feature = "std")]
+pub use fdi::{FaultDetector, FaultReport};
+
+#[cfg(feature = "std")]
+mod preprocess;
+#[cfg(feature = "std")]
+pub use preprocess::{MeasurementPipeline, MeasurementStage};
+
+#[cfg(feature = "std")]
+mod simulate;
+#[cfg(feature = "std")]
+pub use simulate::{sample_gaussian, simulate};
+
+#[cfg(feature = "std")]
+mod monte_carlo;
+#[cfg(feature = "std")]
+pub use monte_carlo::{monte_carlo_consistency, MonteCarloConfig, MonteCarloReport};
+
+#[cfg(feature = "std")]
+mod sensitivity;
+#[cfg(feature = "std")]
+pub use sensitivity::ParameterDerivatives;
+
+#[cfg(feature = "std")]
+mod switching;
+#[cfg(feature = "std")]
+pub use switching::{merge_gaussians, GpbFilter, GpbOrder, SwitchingEstimate};
+
+#[cfg(feature = "std")]
+mod mmae;
+#[cfg(feature = "std")]
+pub use mmae::MultipleModelEstimator;
+
+#[cfg(feature = "std")]
+mod mixture_noise;
+#[cfg(feature = "std")]
+pub use mixture_noise::GaussianMixtureNoise;
+
+#[cfg(feature = "std")]
+mod switchable;
+#[cfg(feature = "std")]
+pub use switchable::SwitchableTransitionModel;
+
+#[cfg(feature = "std")]
+mod time_varying;
+
+#[cfg(feature = "std")]
+mod ekf_slam;
+#[cfg(feature = "std")]
+pub use ekf_slam::{Association, EkfSlam, RangeBearing};
+
+#[cfg(feature = "std")]
+mod sliding_window;
+#[cfg(feature = "std")]
+pub use sliding_window::SlidingWindowFilter;
+
+#[cfg(feature = "std")]
+mod particle;
+#[cfg(feature = "std")]
+pub use particle::{
+    ParticleFilter, ParticleFilterVariant, ParticleSet, ResamplingConfig, ResamplingScheme,
+};
+
+#[cfg(feature = "std")]
+mod batch;
+#[cfg(feature = "std")]
+pub use batch::{BatchLeastSquares, RobustLoss};
+
+#[cfg(feature = "std")]
+mod information_smoother;
+
+#[cfg(feature = "std")]
+mod block_diagonal;
+#[cfg(feature = "std")]
+pub use block_diagonal::{BlockDiagonalEstimate, BlockDiagonalFilter};
+
+#[cfg(feature = "std")]
+mod als;
+#[cfg(feature = "std")]
+pub use als::{autocovariance_least_squares, NoiseCovariances};
+
+#[cfg(feature = "std")]
+mod fir;
+#[cfg(feature = "std")]
+pub use fir::LimitedMemoryFilter;
+
+#[cfg(feature = "std")]
+mod checkpoint;
+#[cfg(feature = "std")]
+pub use checkpoint::Checkpoint;
+
+#[cfg(feature = "std")]
+mod record;
+#[cfg(feature = "std")]
+pub use record::{Recording, StepDifference, StepRecord};
+
+#[cfg(feature = "std")]
+mod diagnostics;
+#[cfg(feature = "std")]
+pub use diagnostics::FilterDiagnostics;
+
+#[cfg(feature = "std")]
+mod streaming;
+#[cfg(feature = "std")]
+pub use streaming::StreamingSmoother;
+
+#[cfg(feature = "std")]
+mod parallel;
+
+#[cfg(feature = "std")]
+mod functional;
+#[cfg(feature = "std")]
+pub use functional::{filter, smooth};
+
+#[cfg(feature = "units")]
+mod units;
+#[cfg(feature = "units")]
+pub use units::{DynQuantity, ModelUnits};
+
+#[cfg(feature = "plot")]
+mod plot;
+#[cfg(feature = "plot")]
+pub use plot::{
+    plot_estimates_png, plot_estimates_svg, plot_innovations_png, plot_innovations_svg,
+};
+
+#[cfg(feature = "rerun")]
+mod rerun;
+#[cfg(feature = "rerun")]
+pub use rerun::RerunLogger;
+
 /// A linear model of process dynamics with no control inputs
+///
+/// The scalar type may be complex, e.g. for phasor or baseband signals. The
+/// noise is then circularly-symmetric complex Gaussian, covariances are
+/// Hermitian, and conjugate transposes take the place of transposes.
 pub trait TransitionModelLinearNoControl<R>
 where
-    R: RealField,
+    R: ComplexField,
 {
     fn state_dim(&self)->usize;
 
     /// Get the state transition model, `F`.
     fn F(&self) -> &DMatrix<R>;
 
-    /// Get the transpose of the state transition model, `FT`.
-    fn FT(&self) -> &DMatrix<R>;
+    /// Get the transpose of the state transition model, `FT`, which is the
+    /// conjugate transpose over a complex field.
+    ///
+    /// The default computes it from [Self::F], so that it is always
+    /// consistent. Implement this only to return a transpose stored with
+    /// `F`, which must then be kept up to date; see
+    /// [CachedTransitionModel] for a wrapper which does this.
+    fn FT(&self) -> Cow<'_, DMatrix<R>> {
+        Cow::Owned(self.F().adjoint())
+    }
 
     /// Get the process covariance, `Q`.
     fn Q(&self) -> &DMatrix<R>;
 
+    /// Get the noise coupling matrix `G` and the covariance `Qc` of the
+    /// driving noise, if the process covariance is `Q = G Qc G^T`.
+    ///
+    /// Process noise is often specified as a few driving noise terms, e.g.
+    /// accelerations, coupled into the state by `G`. [Self::Q] must still
+    /// return the full covariance, which the filters use, but the lower
+    /// dimensional factors are used where possible, e.g. when simulating.
+    /// The default is `None`.
+    fn noise_coupling(&self) -> Option<(&DMatrix<R>, &DMatrix<R>)> {
+        None
+    }
+
     /// Predict new state from previous estimate.
     fn predict(&self, previous_estimate: &StateAndCovariance<R>) -> StateAndCovariance<R> {
         // The prior.
         let P = previous_estimate.state();
         let F = self.F();
         let state = F * P;
-        let covariance = ((F * previous_estimate.covariance()) * self.FT()) + self.Q();
+        let FT = self.FT();
+        debug_assert_transpose!(F, FT);
+        let covariance = linalg::matmul3(F, previous_estimate.covariance(), &FT) + self.Q();
         StateAndCovariance::new(state, covariance)
     }
 
@@ -107,14 +489,17 @@ where
 
 /// An observation model, potentially non-linear.
 ///
-/// To use a non-linear observation model, the non-linear model must be
-/// linearized (e.g. using the prior state estimate) and use this linearization
-/// as the basis for a `ObservationModel` implementation. This would be done
-/// every timestep. For an example, see
-/// [`nonlinear_observation.rs`](https://github.com/strawlab/adskalman-rs/blob/main/examples/src/bin/nonlinear_observation.rs).
+/// To use a non-linear observation model, implement
+/// [predict_observation](Self::predict_observation) with the non-linear
+/// function and [jacobian_at](Self::jacobian_at) with its linearization,
+/// which the update evaluates at the prior state estimate. See
+/// [NumericalObservationModel] for a model given only by its function.
+///
+/// As for [TransitionModelLinearNoControl], the scalar type may be complex.
+/// The innovation and likelihood methods require a real field.
 pub trait ObservationModel<R>
 where
-    R: RealField,
+    R: ComplexField,
 {
     /// For a given state, predict the observation.
     ///
@@ -136,8 +521,27 @@ where
     /// Get the observation matrix, `H`.
     fn H(&self) -> &DMatrix<R>;
 
-    /// Get the transpose of the observation matrix, `HT`.
-    fn HT(&self) -> &DMatrix<R>;
+    /// Get the transpose of the observation matrix, `HT`, which is the
+    /// conjugate transpose over a complex field.
+    ///
+    /// The default computes it from [Self::H], so that it is always
+    /// consistent. Implement this only to return a transpose stored with
+    /// `H`, which must then be kept up to date; see
+    /// [CachedObservationModel] for a wrapper which does this.
+    fn HT(&self) -> Cow<'_, DMatrix<R>> {
+        Cow::Owned(self.H().adjoint())
+    }
+
+    /// Get the observation matrix linearized about `state`.
+    ///
+    /// The default returns [Self::H], which suits linear models. A non-linear
+    /// model implements this to linearize its observation function, and the
+    /// [innovation](Self::innovation) and
+    /// [update](Self::update_with_covariance) then use it at the prior state
+    /// in place of `H`, as in the extended Kalman filter.
+    fn jacobian_at(&self, _state: &DVector<R>) -> Cow<'_, DMatrix<R>> {
+        Cow::Borrowed(self.H())
+    }
 
     /// Get the observation noise covariance, `R`.
     // TODO: ensure this is positive definite?
@@ -147,6 +551,44 @@ where
 
     fn obs_dim(&self)->usize;
 
+    /// Compute the innovation of an observation with respect to the prior.
+    ///
+    /// The residual is `y - h(x)`, using
+    /// [predict_observation](trait.ObservationModel.html#method.predict_observation),
+    /// and its covariance is `H P H^T + R`, with `H` from
+    /// [jacobian_at](Self::jacobian_at) the prior state.
+    fn innovation(&self, prior: &StateAndCovariance<R>, observation: &DVector<R>) -> Innovation<R>
+    where
+        R: RealField,
+    {
+        let predicted = self.predict_observation(prior.state());
+        let residual = observation - predicted;
+        let hpht = match self.jacobian_at(prior.state()) {
+            Cow::Borrowed(h) => h * prior.covariance() * &*self.HT(),
+            Cow::Owned(h) => &h * prior.covariance() * h.adjoint(),
+        };
+        let covariance = hpht + self.R();
+        Innovation::new(residual, covariance)
+    }
+
+    /// Log of the Gaussian probability density of an observation given the
+    /// prior.
+    ///
+    /// The default is the [log_likelihood](Innovation::log_likelihood) of
+    /// the [innovation](trait.ObservationModel.html#method.innovation).
+    /// [CachedObservationModel] overrides this to use its factorization of
+    /// `R`.
+    fn observation_log_likelihood(
+        &self,
+        prior: &StateAndCovariance<R>,
+        observation: &DVector<R>,
+    ) -> Result<R, Error>
+    where
+        R: RealField,
+    {
+        self.innovation(prior, observation).log_likelihood()
+    }
+
     /// Given prior state and observation, estimate the posterior state.
     ///
     /// This is the *update* step in the Kalman filter literature.
@@ -156,17 +598,39 @@ where
         observation: &DVector<R>,
         covariance_method: CovarianceUpdateMethod,
     ) -> Result<StateAndCovariance<R>, Error> {
-        let h = self.H();
+        self.update_with_covariance(prior, observation, self.R(), covariance_method)
+    }
+
+    /// Given prior state and observation, estimate the posterior state using
+    /// the given observation noise covariance in place of `R`.
+    ///
+    /// This suits sensors which report the accuracy of each sample, such as
+    /// the dilution of precision of a GPS fix, without constructing a new
+    /// model for every observation.
+    fn update_with_covariance(
+        &self,
+        prior: &StateAndCovariance<R>,
+        observation: &DVector<R>,
+        observation_covariance: &DMatrix<R>,
+        covariance_method: CovarianceUpdateMethod,
+    ) -> Result<StateAndCovariance<R>, Error> {
+        let h = self.jacobian_at(prior.state());
+        let ht = match &h {
+            Cow::Borrowed(_) => self.HT(),
+            Cow::Owned(h) => Cow::Owned(h.adjoint()),
+        };
+        let h: &DMatrix<R> = &h;
         trace!("h {}", pretty_print!(h));
 
         let p = prior.covariance();
         trace!("p {}", pretty_print!(p));
         debug_assert_symmetric!(p);
 
-        let ht = self.HT();
+        debug_assert_transpose!(h, ht);
+        let ht: &DMatrix<R> = &ht;
         trace!("ht {}", pretty_print!(ht));
 
-        let r = self.R();
+        let r = observation_covariance;
         trace!("r {}", pretty_print!(r));
 
         // Calculate innovation covariance
@@ -175,23 +639,24 @@ where
         // positive definite. If p is positive definite, then (h*p*ht) is at
         // least positive semi-definite. If h is full rank, it is positive
         // definite.
-        let s = (h * p * ht) + r;
+        let s = linalg::matmul3(h, p, ht) + r;
         trace!("s {}", pretty_print!(s));
 
         // Calculate kalman gain by inverting.
-        let s_chol = match na::linalg::Cholesky::new(s) {
+        let s_inv: DMatrix<R> = match linalg::cholesky_inverse(&s) {
             Some(v) => v,
             None => {
                 // Maybe state covariance is not symmetric or
                 // for from positive definite? Also, observation
                 // noise should be positive definite.
-                return Err(ErrorKind::CovarianceNotPositiveSemiDefinite.into());
+                return Err(Error::from(ErrorKind::CovarianceNotPositiveSemiDefinite)
+                    .with_operation(Operation::Update)
+                    .with_matrix(&s));
             }
         };
-        let s_inv: DMatrix<R> = s_chol.inverse();
         trace!("s_inv {}", pretty_print!(s_inv));
 
-        let k_gain: DMatrix<R> = p * ht * s_inv;
+        let k_gain: DMatrix<R> = linalg::matmul3(p, ht, &s_inv);
         // let k_gain: OMatrix<R,SS,OS> = solve!( (p*ht), s );
         trace!("k_gain {}", pretty_print!(k_gain));
 
@@ -200,11 +665,10 @@ where
         trace!("observation {}", pretty_print!(observation));
         let innovation: DVector<R> = observation - predicted;
         trace!("innovation {}", pretty_print!(innovation));
-        let state: DVector<R> = prior.state() + &k_gain * innovation;
+        let state: DVector<R> = prior.state() + &k_gain * &innovation;
         trace!("state {}", pretty_print!(state));
 
-        trace!("self.observation_matrix() {}", pretty_print!(self.H()));
-        let kh: DMatrix<R> = &k_gain * self.H();
+        let kh: DMatrix<R> = linalg::matmul(&k_gain, h);
         trace!("kh {}", pretty_print!(kh));
         let one_minus_kh = DMatrix::<R>::identity(kh.nrows(), kh.ncols()) - kh;//warning
         trace!("one_minus_kh {}", pretty_print!(one_minus_kh));
@@ -213,22 +677,26 @@ where
             CovarianceUpdateMethod::JosephForm => {
                 // Joseph form of covariance update keeps covariance matrix symmetric.
 
-                let left = &one_minus_kh * prior.covariance() * &one_minus_kh.transpose();
-                let right = &k_gain * r * &k_gain.transpose();
+                let one_minus_kh_t = one_minus_kh.adjoint();
+                let left = linalg::matmul3(&one_minus_kh, prior.covariance(), &one_minus_kh_t);
+                let right = linalg::matmul3(&k_gain, r, &k_gain.adjoint());
                 left + right
             }
-            CovarianceUpdateMethod::OptimalKalman => one_minus_kh * prior.covariance(),
+            CovarianceUpdateMethod::OptimalKalman => {
+                linalg::matmul(&one_minus_kh, prior.covariance())
+            }
             CovarianceUpdateMethod::OptimalKalmanForcedSymmetric => {
-                let covariance1 = one_minus_kh * prior.covariance();
+                let covariance1 = linalg::matmul(&one_minus_kh, prior.covariance());
                 trace!("covariance1 {}", pretty_print!(covariance1));
                 // Hack to force covariance to be symmetric.
                 // See https://math.stackexchange.com/q/2335831
-                covariance1.symmetric_part()
+                covariance1.hermitian_part()
             }
         };
         trace!("covariance {}", pretty_print!(covariance));
 
         debug_assert_symmetric!(covariance);
+        cross_check_update!(h, r, prior, &innovation, state, covariance);
 
         Ok(StateAndCovariance::new(state, covariance))
     }
@@ -250,6 +718,112 @@ pub enum CovarianceUpdateMethod {
     JosephForm,
 }
 
+/// Specifies the form of the covariance update of an RTS smoothing step
+///
+/// With the smoother gain `J` and the filtered, predicted and future smoothed
+/// covariances `P`, `P_pred` and `P_future`, all forms compute
+/// `P + J (P_future - P_pred) J^T` in exact arithmetic.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum SmootherCovarianceMethod {
+    /// Computes `P + J (P_future - P_pred) J^T` directly.
+    ///
+    /// The difference of covariances can lose symmetry and positive
+    /// semi-definiteness to rounding, especially in `f32`.
+    Standard,
+    /// Computes the standard form and then forces symmetry, `(P + P^T) / 2`.
+    ForcedSymmetric,
+    /// Computes `(I - J F) P (I - J F)^T + J (P_future + Q') J^T`, where
+    /// `Q' = P_pred - F P F^T` is the noise added by the prediction.
+    ///
+    /// Like the Joseph form of the filter update, this is a sum of positive
+    /// semi-definite terms, so it stays positive semi-definite despite
+    /// rounding. It is also symmetrized.
+    JosephForm,
+}
+
+/// The result of RTS smoothing with disturbance estimates
+#[cfg(feature = "std")]
+#[derive(Debug, Clone)]
+pub struct DisturbanceSmootherResult<R>
+where
+    R: ComplexField,
+{
+    /// The smoothed state estimates.
+    pub states: Vec<StateAndCovariance<R>>,
+    /// The smoothed disturbance between each pair of consecutive states, so
+    /// there is one fewer disturbance than states.
+    pub disturbances: Vec<StateAndCovariance<R>>,
+}
+
+/// Whether a time step of a filter used an observation
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum StepKind {
+    /// The observation was missing, so the estimate is the prediction only.
+    Predicted,
+    /// The prediction was updated with the observation.
+    Updated,
+}
+
+impl StepKind {
+    /// The kind of step performed for `observation`, which is missing if any
+    /// component is NaN.
+    pub fn for_observation<R: ComplexField>(observation: &DVector<R>) -> Self {
+        if observation.iter().any(|x| is_nan(x.clone())) {
+            StepKind::Predicted
+        } else {
+            StepKind::Updated
+        }
+    }
+
+    /// Whether the step used an observation.
+    pub fn is_updated(&self) -> bool {
+        *self == StepKind::Updated
+    }
+}
+
+fn check_dimension(
+    matrix: &'static str,
+    expected: (usize, usize),
+    actual: (usize, usize),
+) -> Result<(), Error> {
+    if expected == actual {
+        Ok(())
+    } else {
+        Err(ErrorKind::DimensionMismatch {
+            expected,
+            actual,
+            matrix,
+        }
+        .into())
+    }
+}
+
+fn check_shape<R: ComplexField>(
+    matrix: &'static str,
+    expected: (usize, usize),
+    actual: &DMatrix<R>,
+) -> Result<(), Error> {
+    check_dimension(matrix, expected, actual.shape())
+}
+
+/// Check that the model matrices and their transposes have shapes consistent
+/// with the state and observation dimensions reported by the models.
+fn check_model_shapes<R: ComplexField>(
+    transition_model: &dyn TransitionModelLinearNoControl<R>,
+    observation_model: &dyn ObservationModel<R>,
+) -> Result<(), Error> {
+    let ss = transition_model.state_dim();
+    let os = observation_model.obs_dim();
+    let observation_ss = observation_model.state_dim();
+    check_dimension("observation model state", (ss, 1), (observation_ss, 1))?;
+    check_shape("F", (ss, ss), transition_model.F())?;
+    check_shape("FT", (ss, ss), &transition_model.FT())?;
+    check_shape("Q", (ss, ss), transition_model.Q())?;
+    check_shape("H", (os, ss), observation_model.H())?;
+    check_shape("HT", (ss, os), &observation_model.HT())?;
+    check_shape("R", (os, os), observation_model.R())
+}
+
 /// A Kalman filter with no control inputs, a linear process model and linear
 /// observation model
 ///
@@ -259,17 +833,24 @@ pub enum CovarianceUpdateMethod {
 /// bound of this struct, a useful strategy to avoid requiring lifetime
 /// annotations is to construct it just before [Self::step] and then dropping it
 /// immediately afterward.
+///
+/// The scalar type may be complex, see [TransitionModelLinearNoControl].
+/// The likelihood methods and most of the other estimators of this crate
+/// require a real field.
 pub struct KalmanFilterNoControl<'a, R>
 where
-    R: RealField,
+    R: ComplexField,
 {
     transition_model: &'a dyn TransitionModelLinearNoControl<R>,
     observation_matrix: &'a dyn ObservationModel<R>,
+    failure_policy: FailurePolicy<R::RealField>,
+    fading_memory: R::RealField,
+    smoother_covariance_method: SmootherCovarianceMethod,
 }
 
 impl<'a, R> KalmanFilterNoControl<'a, R>
 where
-    R: RealField,
+    R: ComplexField,
 {
     /// Initialize a new `KalmanFilterNoControl` struct.
     ///
@@ -285,7 +866,107 @@ where
         Self {
             transition_model,
             observation_matrix,
+            failure_policy: FailurePolicy::Error,
+            fading_memory: na::one(),
+            smoother_covariance_method: SmootherCovarianceMethod::Standard,
+        }
+    }
+
+    /// Set the fading-memory factor `alpha`
+    ///
+    /// Each prediction multiplies the propagated covariance by `alpha^2`,
+    /// giving the prior covariance `alpha^2 F P F^T + Q`. This discounts old
+    /// observations exponentially, the classic remedy for model mismatch
+    /// making the filter overconfident ("smug") and ignoring new
+    /// observations. The default of one gives the standard Kalman filter.
+    ///
+    /// The factor applies to the prediction, filtering, smoothing and
+    /// likelihood methods, including
+    /// [smooth_information_form](struct.KalmanFilterNoControl.html#method.smooth_information_form),
+    /// [log_likelihood_with_gradient](struct.KalmanFilterNoControl.html#method.log_likelihood_with_gradient)
+    /// and the parallel scans, which then run sequentially. It does not
+    /// apply to the reverse-time information filter,
+    /// [predict_backward](struct.KalmanFilterNoControl.html#method.predict_backward)
+    /// and the methods built on it, since the factor scales the covariance
+    /// of the forward filter.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `alpha` is less than one.
+    pub fn with_fading_memory(mut self, alpha: R::RealField) -> Self {
+        assert!(
+            alpha >= na::one(),
+            "the fading-memory factor must be at least one"
+        );
+        self.fading_memory = alpha.clone() * alpha;
+        self
+    }
+
+    /// Whether a fading-memory factor other than one is set.
+    fn has_fading_memory(&self) -> bool {
+        self.fading_memory != na::one()
+    }
+
+    /// Predict the next state, applying the fading-memory factor
+    pub fn predict(&self, estimate: &StateAndCovariance<R>) -> StateAndCovariance<R> {
+        if !self.has_fading_memory() {
+            return self.transition_model.predict(estimate);
+        }
+        let F = self.transition_model.F();
+        let FT = self.transition_model.FT();
+        let state = F * estimate.state();
+        let covariance = linalg::matmul3(F, estimate.covariance(), &FT)
+            * R::from_real(self.fading_memory.clone())
+            + self.transition_model.Q();
+        StateAndCovariance::new(state, covariance)
+    }
+
+    /// Set the policy for recovering when a covariance cannot be factored
+    /// during the update or smoothing steps. The default is
+    /// [FailurePolicy::Error].
+    pub fn with_failure_policy(mut self, failure_policy: FailurePolicy<R::RealField>) -> Self {
+        self.failure_policy = failure_policy;
+        self
+    }
+
+    /// Set the form of the covariance update of the RTS smoothing steps. The
+    /// default is [SmootherCovarianceMethod::Standard].
+    pub fn with_smoother_covariance_method(mut self, method: SmootherCovarianceMethod) -> Self {
+        self.smoother_covariance_method = method;
+        self
+    }
+
+    /// Initialize a new `KalmanFilterNoControl`, checking the dimensions of
+    /// the model matrices
+    ///
+    /// Like [new](struct.KalmanFilterNoControl.html#method.new), but returns
+    /// an [ErrorKind::DimensionMismatch] error if the shapes of `F`, `Q`, `H`
+    /// and `R` and of their transposes are not consistent with the state and
+    /// observation dimensions reported by the models, rather than panicking
+    /// later inside nalgebra.
+    pub fn try_new(
+        transition_model: &'a dyn TransitionModelLinearNoControl<R>,
+        observation_matrix: &'a dyn ObservationModel<R>,
+    ) -> Result<Self, Error> {
+        check_model_shapes(transition_model, observation_matrix)?;
+        Ok(Self::new(transition_model, observation_matrix))
+    }
+
+    /// Check the estimate and, if given, the observation have the dimensions
+    /// of the models.
+    fn check_dimensions(
+        &self,
+        estimate: &StateAndCovariance<R>,
+        observation: Option<&DVector<R>>,
+    ) -> Result<(), Error> {
+        let ss = self.transition_model.state_dim();
+        check_dimension("state", (ss, 1), estimate.state().shape())?;
+        check_shape("covariance", (ss, ss), estimate.covariance())?;
+        if let Some(observation) = observation {
+            let os = self.observation_matrix.obs_dim();
+            check_dimension("observation", (os, 1), observation.shape())?;
         }
+        Ok(())
     }
 
     /// Perform Kalman prediction and update steps with default values
@@ -322,27 +1003,175 @@ where
     /// This calls the prediction step of the transition model and then, if
     /// there is a (non-`nan`) observation, calls the update step of the
     /// observation model using the specified covariance update method.
+    ///
+    /// An [ErrorKind::DimensionMismatch] error is returned if the estimate
+    /// or observation does not have the dimensions of the models.
     pub fn step_with_options(
         &self,
         previous_estimate: &StateAndCovariance<R>,
         observation: &DVector<R>,
         covariance_update_method: CovarianceUpdateMethod,
     ) -> Result<StateAndCovariance<R>, Error> {
-        let prior = self.transition_model.predict(previous_estimate);
+        self.check_dimensions(previous_estimate, Some(observation))?;
+        let prior = self.predict(previous_estimate);
         if observation.iter().any(|x| is_nan(x.clone())) {
             Ok(prior)
         } else {
-            self.observation_matrix
-                .update(&prior, observation, covariance_update_method)
+            self.update_with_policy(
+                &prior,
+                observation,
+                self.observation_matrix.R(),
+                covariance_update_method,
+            )
         }
     }
 
-    /// Kalman filter (operates on in-place data without allocating)
+    /// Perform Kalman prediction and update steps with the observation noise
+    /// covariance of this observation
     ///
-    /// Operates on entire time series (by repeatedly calling
-    /// [`step`](struct.KalmanFilterNoControl.html#method.step) for each
-    /// observation) and returns a vector of state estimates. To be
-    /// mathematically correct, the interval between observations must be the
+    /// This is like [step](struct.KalmanFilterNoControl.html#method.step), but
+    /// `observation_covariance` is used in place of `R` of the observation
+    /// model, e.g. for sensors reporting a per-sample accuracy. See
+    /// [ObservationModel::update_with_covariance]. An
+    /// [ErrorKind::DimensionMismatch] error is returned if its shape is not
+    /// that of `R`.
+    pub fn step_with_covariance(
+        &self,
+        previous_estimate: &StateAndCovariance<R>,
+        observation: &DVector<R>,
+        observation_covariance: &DMatrix<R>,
+    ) -> Result<StateAndCovariance<R>, Error> {
+        self.check_dimensions(previous_estimate, Some(observation))?;
+        let os = self.observation_matrix.obs_dim();
+        check_shape("R", (os, os), observation_covariance)?;
+        let prior = self.predict(previous_estimate);
+        if observation.iter().any(|x| is_nan(x.clone())) {
+            Ok(prior)
+        } else {
+            self.update_with_policy(
+                &prior,
+                observation,
+                observation_covariance,
+                CovarianceUpdateMethod::JosephForm,
+            )
+        }
+    }
+
+    /// This filter's transition model and configuration, with another
+    /// observation model.
+    fn with_observation_model<'b>(
+        &'b self,
+        observation_matrix: &'b dyn ObservationModel<R>,
+    ) -> KalmanFilterNoControl<'b, R> {
+        KalmanFilterNoControl {
+            transition_model: self.transition_model,
+            observation_matrix,
+            failure_policy: self.failure_policy.clone(),
+            fading_memory: self.fading_memory.clone(),
+            smoother_covariance_method: self.smoother_covariance_method,
+        }
+    }
+
+    /// Update, applying the failure policy if the innovation covariance
+    /// cannot be factored.
+    fn update_with_policy(
+        &self,
+        prior: &StateAndCovariance<R>,
+        observation: &DVector<R>,
+        observation_covariance: &DMatrix<R>,
+        covariance_update_method: CovarianceUpdateMethod,
+    ) -> Result<StateAndCovariance<R>, Error> {
+        let updated = self.repaired_update(
+            prior,
+            observation,
+            observation_covariance,
+            covariance_update_method,
+        )?;
+        Ok(match updated {
+            Some((_, posterior)) => posterior,
+            None => prior.clone(),
+        })
+    }
+
+    /// Update as [update_with_policy](Self::update_with_policy), returning
+    /// the prior the update started from, which the policy may have
+    /// repaired, with the posterior, or `None` if the policy skipped the
+    /// update.
+    #[allow(clippy::type_complexity)]
+    fn repaired_update<'p>(
+        &self,
+        prior: &'p StateAndCovariance<R>,
+        observation: &DVector<R>,
+        observation_covariance: &DMatrix<R>,
+        covariance_update_method: CovarianceUpdateMethod,
+    ) -> Result<Option<(Cow<'p, StateAndCovariance<R>>, StateAndCovariance<R>)>, Error> {
+        let mut error = match self.observation_matrix.update_with_covariance(
+            prior,
+            observation,
+            observation_covariance,
+            covariance_update_method,
+        ) {
+            Ok(posterior) => return Ok(Some((Cow::Borrowed(prior), posterior))),
+            Err(e) => e,
+        };
+        if !matches!(error.kind(), ErrorKind::CovarianceNotPositiveSemiDefinite) {
+            return Err(error);
+        }
+        if self.failure_policy == FailurePolicy::SkipUpdate {
+            return Ok(None);
+        }
+        let mut covariance = prior.covariance().clone();
+        let mut attempt = 0;
+        while let Some(repaired) = self.failure_policy.repair(&covariance, attempt) {
+            covariance = repaired;
+            let retry = StateAndCovariance::new(prior.state().clone(), covariance.clone());
+            match self.observation_matrix.update_with_covariance(
+                &retry,
+                observation,
+                observation_covariance,
+                covariance_update_method,
+            ) {
+                Ok(posterior) if failure::is_positive_semidefinite(posterior.covariance()) => {
+                    return Ok(Some((Cow::Owned(retry), posterior)))
+                }
+                Ok(posterior) => {
+                    error = Error::from(ErrorKind::CovarianceNotPositiveSemiDefinite)
+                        .with_matrix(posterior.covariance())
+                }
+                Err(e) => error = e,
+            }
+            attempt += 1;
+        }
+        Err(error)
+    }
+
+    /// Perform prediction and update steps with an optional observation
+    ///
+    /// This is like [step](struct.KalmanFilterNoControl.html#method.step), but
+    /// a missing observation is given as `None` rather than marked with NaN.
+    /// This suits scalar types which cannot represent NaN, such as some
+    /// arbitrary-precision types. The observation is still treated as missing
+    /// if it is `Some` but has a NaN component.
+    pub fn step_optional(
+        &self,
+        previous_estimate: &StateAndCovariance<R>,
+        observation: Option<&DVector<R>>,
+    ) -> Result<StateAndCovariance<R>, Error> {
+        match observation {
+            Some(observation) => self.step(previous_estimate, observation),
+            None => {
+                self.check_dimensions(previous_estimate, None)?;
+                Ok(self.predict(previous_estimate))
+            }
+        }
+    }
+
+    /// Kalman filter (operates on in-place data without allocating)
+    ///
+    /// Operates on entire time series (by repeatedly calling
+    /// [`step`](struct.KalmanFilterNoControl.html#method.step) for each
+    /// observation) and returns a vector of state estimates. To be
+    /// mathematically correct, the interval between observations must be the
     /// `dt` specified in the motion model.
     ///
     /// If any observation has a NaN component, it is treated as missing.
@@ -355,16 +1184,106 @@ where
         let mut previous_estimate = initial_estimate.clone();
         assert!(state_estimates.len() >= observations.len());
 
-        for (this_observation, state_estimate) in
-            observations.iter().zip(state_estimates.iter_mut())
+        for (i, (this_observation, state_estimate)) in observations
+            .iter()
+            .zip(state_estimates.iter_mut())
+            .enumerate()
         {
-            let this_estimate = self.step(&previous_estimate, this_observation)?;
+            let this_estimate = self
+                .step(&previous_estimate, this_observation)
+                .map_err(|e| e.with_step(i))?;
             *state_estimate = this_estimate.clone();
             previous_estimate = this_estimate;
         }
         Ok(())
     }
 
+    /// Rauch-Tung-Striebel (RTS) smoother operating in place on Kalman
+    /// filtered estimates
+    ///
+    /// The filtered estimates in `state_estimates`, e.g. from
+    /// [`filter_inplace`](struct.KalmanFilterNoControl.html#method.filter_inplace),
+    /// are overwritten by the smoothed estimates, working backward from the
+    /// last. No collection is allocated; the only scratch space is the
+    /// matrices of a single smoothing step. This is available without the
+    /// `std` feature.
+    pub fn smooth_inplace(
+        &self,
+        state_estimates: &mut [StateAndCovariance<R>],
+    ) -> Result<(), Error> {
+        for i in (1..state_estimates.len()).rev() {
+            let (past, future) = state_estimates.split_at_mut(i);
+            let smoothed = self
+                .smooth_step(&future[0], &past[i - 1])
+                .map_err(|e| e.with_step(i - 1))?;
+            past[i - 1] = smoothed;
+        }
+        Ok(())
+    }
+
+    /// Rauch-Tung-Striebel (RTS) smoother operating in place on Kalman
+    /// filtered estimates and their [StepKind]s
+    ///
+    /// This gives the same estimates as
+    /// [`smooth_inplace`](struct.KalmanFilterNoControl.html#method.smooth_inplace),
+    /// using the step kinds to save work. After the last updated step there
+    /// is no further information, so the filtered estimates there are
+    /// already smoothed and are left as they are. At a step which was only
+    /// predicted, the filtered estimate is the prior, which the smoothing
+    /// step of the step before uses rather than predicting again.
+    ///
+    /// # Panics
+    ///
+    /// Panics if there is not one step kind per estimate.
+    pub fn smooth_inplace_with_kinds(
+        &self,
+        state_estimates: &mut [StateAndCovariance<R>],
+        step_kinds: &[StepKind],
+    ) -> Result<(), Error> {
+        assert_eq!(state_estimates.len(), step_kinds.len());
+        let last_updated = match step_kinds.iter().rposition(StepKind::is_updated) {
+            Some(last_updated) => last_updated,
+            None => return Ok(()),
+        };
+        let mut filtered_future = state_estimates[last_updated].clone();
+        for i in (1..=last_updated).rev() {
+            let (past, future) = state_estimates.split_at_mut(i);
+            let filtered = past[i - 1].clone();
+            let smoothed = if step_kinds[i].is_updated() {
+                self.smooth_step(&future[0], &filtered)
+            } else {
+                self.smooth_step_with_prior(&future[0], &filtered, &filtered_future)
+                    .map(|(smoothed, _)| smoothed)
+            }
+            .map_err(|e| e.with_step(i - 1))?;
+            past[i - 1] = smoothed;
+            filtered_future = filtered;
+        }
+        Ok(())
+    }
+
+    /// Kalman filter recording which steps used an observation (operates on
+    /// in-place data without allocating)
+    ///
+    /// This is like
+    /// [`filter_inplace`](struct.KalmanFilterNoControl.html#method.filter_inplace)
+    /// but also stores the [StepKind] of each step in `step_kinds`, so that
+    /// the locations of gaps in the observations are retained.
+    pub fn filter_inplace_with_kinds(
+        &self,
+        initial_estimate: &StateAndCovariance<R>,
+        observations: &[DVector<R>],
+        state_estimates: &mut [StateAndCovariance<R>],
+        step_kinds: &mut [StepKind],
+    ) -> Result<(), Error> {
+        assert!(step_kinds.len() >= observations.len());
+        self.filter_inplace(initial_estimate, observations, state_estimates)?;
+        for (observation, step_kind) in observations.iter().zip(step_kinds.iter_mut()) {
+            *step_kind = StepKind::for_observation(observation);
+        }
+        Ok(())
+    }
+
     /// Kalman filter
     ///
     /// This is a convenience function that calls [`filter_inplace`](struct.KalmanFilterNoControl.html#method.filter_inplace).
@@ -383,6 +1302,137 @@ where
         Ok(state_estimates)
     }
 
+    /// Kalman filter with an observation noise covariance for each
+    /// observation
+    ///
+    /// This is like [filter](struct.KalmanFilterNoControl.html#method.filter),
+    /// but `observation_covariances[i]` is used in place of `R` to update with
+    /// `observations[i]`, see
+    /// [step_with_covariance](struct.KalmanFilterNoControl.html#method.step_with_covariance).
+    /// This suits reprocessing logged data with a recorded accuracy for each
+    /// sample.
+    ///
+    /// # Panics
+    ///
+    /// Panics if there is not a covariance for each observation.
+    #[cfg(feature = "std")]
+    pub fn filter_with_covariances(
+        &self,
+        initial_estimate: &StateAndCovariance<R>,
+        observations: &[DVector<R>],
+        observation_covariances: &[DMatrix<R>],
+    ) -> Result<Vec<StateAndCovariance<R>>, Error> {
+        assert_eq!(observations.len(), observation_covariances.len());
+        let mut state_estimates = Vec::with_capacity(observations.len());
+        let mut previous_estimate = initial_estimate.clone();
+        for (i, (observation, observation_covariance)) in observations
+            .iter()
+            .zip(observation_covariances.iter())
+            .enumerate()
+        {
+            previous_estimate = self
+                .step_with_covariance(&previous_estimate, observation, observation_covariance)
+                .map_err(|e| e.with_step(i))?;
+            state_estimates.push(previous_estimate.clone());
+        }
+        Ok(state_estimates)
+    }
+
+    /// Kalman filter with the observation noise covariance `R` scaled for
+    /// each observation
+    ///
+    /// Like
+    /// [filter_with_covariances](struct.KalmanFilterNoControl.html#method.filter_with_covariances),
+    /// with the covariance for `observations[i]` being `scales[i] * R`. A
+    /// scale above one down-weights an observation, e.g. one flagged as of
+    /// poor quality, and a scale of zero makes an observation exact.
+    ///
+    /// # Panics
+    ///
+    /// Panics if there is not a scale for each observation.
+    #[cfg(feature = "std")]
+    pub fn filter_with_covariance_scales(
+        &self,
+        initial_estimate: &StateAndCovariance<R>,
+        observations: &[DVector<R>],
+        scales: &[R],
+    ) -> Result<Vec<StateAndCovariance<R>>, Error> {
+        let R = self.observation_matrix.R();
+        let observation_covariances: Vec<DMatrix<R>> =
+            scales.iter().map(|scale| R * scale.clone()).collect();
+        self.filter_with_covariances(initial_estimate, observations, &observation_covariances)
+    }
+
+    /// Open-loop (dead-reckoning) prediction without observations
+    ///
+    /// Returns the `n_steps` estimates predicted by repeatedly applying
+    /// [predict](struct.KalmanFilterNoControl.html#method.predict) from
+    /// `initial_estimate`, which is not itself included. This shows how the
+    /// uncertainty grows when no observations are made.
+    #[cfg(feature = "std")]
+    pub fn predict_series(
+        &self,
+        initial_estimate: &StateAndCovariance<R>,
+        n_steps: usize,
+    ) -> Vec<StateAndCovariance<R>> {
+        let mut estimates = Vec::with_capacity(n_steps);
+        let mut previous_estimate = initial_estimate.clone();
+        for _ in 0..n_steps {
+            previous_estimate = self.predict(&previous_estimate);
+            estimates.push(previous_estimate.clone());
+        }
+        estimates
+    }
+
+    /// Kalman filter with optional observations
+    ///
+    /// Missing observations are given as `None`, see
+    /// [step_optional](struct.KalmanFilterNoControl.html#method.step_optional).
+    #[cfg(feature = "std")]
+    pub fn filter_optional(
+        &self,
+        initial_estimate: &StateAndCovariance<R>,
+        observations: &[Option<DVector<R>>],
+    ) -> Result<Vec<StateAndCovariance<R>>, Error> {
+        let mut state_estimates = Vec::with_capacity(observations.len());
+        let mut previous_estimate = initial_estimate.clone();
+        for (i, observation) in observations.iter().enumerate() {
+            previous_estimate = self
+                .step_optional(&previous_estimate, observation.as_ref())
+                .map_err(|e| e.with_step(i))?;
+            state_estimates.push(previous_estimate.clone());
+        }
+        Ok(state_estimates)
+    }
+
+    /// RTS smoother with optional observations
+    ///
+    /// Missing observations are given as `None`, see
+    /// [step_optional](struct.KalmanFilterNoControl.html#method.step_optional).
+    #[cfg(feature = "std")]
+    pub fn smooth_optional(
+        &self,
+        initial_estimate: &StateAndCovariance<R>,
+        observations: &[Option<DVector<R>>],
+    ) -> Result<Vec<StateAndCovariance<R>>, Error> {
+        let forward_results = self.filter_optional(initial_estimate, observations)?;
+        self.smooth_from_filtered(forward_results)
+    }
+
+    /// Kalman filter recording which steps used an observation
+    ///
+    /// Returns the state estimates and the [StepKind] of each step.
+    #[cfg(feature = "std")]
+    pub fn filter_with_kinds(
+        &self,
+        initial_estimate: &StateAndCovariance<R>,
+        observations: &[DVector<R>],
+    ) -> Result<(Vec<StateAndCovariance<R>>, Vec<StepKind>), Error> {
+        let state_estimates = self.filter(initial_estimate, observations)?;
+        let step_kinds = observations.iter().map(StepKind::for_observation).collect();
+        Ok((state_estimates, step_kinds))
+    }
+
     /// Rauch-Tung-Striebel (RTS) smoother
     ///
     /// Operates on entire time series (by calling
@@ -391,10 +1441,12 @@ where
     /// and returns a vector of state estimates. To be mathematically correct,
     /// the interval between observations must be the `dt` specified in the
     /// motion model.
-
+    ///
     /// Operates on entire time series in one shot and returns a vector of state
     /// estimates. To be mathematically correct, the interval between
-    /// observations must be the `dt` specified in the motion model.
+    /// observations must be the `dt` specified in the motion model; for
+    /// irregular intervals, see
+    /// [smooth_time_varying](struct.KalmanFilterNoControl.html#method.smooth_time_varying).
     ///
     /// If any observation has a NaN component, it is treated as missing.
     #[cfg(feature = "std")]
@@ -407,72 +1459,355 @@ where
         self.smooth_from_filtered(forward_results)
     }
 
+    /// Rauch-Tung-Striebel (RTS) smoother recording which steps used an
+    /// observation
+    ///
+    /// Returns the smoothed state estimates and the [StepKind] of each step,
+    /// as returned by
+    /// [`filter_with_kinds`](struct.KalmanFilterNoControl.html#method.filter_with_kinds).
+    #[cfg(feature = "std")]
+    pub fn smooth_with_kinds(
+        &self,
+        initial_estimate: &StateAndCovariance<R>,
+        observations: &[DVector<R>],
+    ) -> Result<(Vec<StateAndCovariance<R>>, Vec<StepKind>), Error> {
+        let (forward_results, step_kinds) =
+            self.filter_with_kinds(initial_estimate, observations)?;
+        self.smooth_from_filtered_with_kinds(forward_results, step_kinds)
+    }
+
+    /// Interpolate the states at the missing observations
+    ///
+    /// The observations are smoothed with
+    /// [`smooth_with_kinds`](struct.KalmanFilterNoControl.html#method.smooth_with_kinds),
+    /// and the smoothed estimates of the steps whose observation is missing
+    /// (has a NaN component) are returned with their indices, in order.
+    /// Their covariances give the uncertainty of the interpolation, which is
+    /// largest in the middle of a gap.
+    #[cfg(feature = "std")]
+    pub fn interpolate(
+        &self,
+        initial_estimate: &StateAndCovariance<R>,
+        observations: &[DVector<R>],
+    ) -> Result<Vec<(usize, StateAndCovariance<R>)>, Error> {
+        let (smoothed, step_kinds) = self.smooth_with_kinds(initial_estimate, observations)?;
+        Ok(smoothed
+            .into_iter()
+            .zip(step_kinds)
+            .enumerate()
+            .filter(|(_, (_, kind))| !kind.is_updated())
+            .map(|(k, (estimate, _))| (k, estimate))
+            .collect())
+    }
+
+    /// Forecast the observations of the next `horizon` steps
+    ///
+    /// The estimate is predicted repeatedly without updates, and element
+    /// `k` of the result is the predicted observation `h(x)` of step `k + 1`
+    /// after `last_estimate`, as the state of a [StateAndCovariance], with
+    /// the covariance `H P H^T + R` of an actual observation about it. The
+    /// forecast intervals follow from these, e.g. with
+    /// [chi_squared_quantile] for an ellipsoid.
+    #[cfg(feature = "std")]
+    pub fn forecast(
+        &self,
+        last_estimate: &StateAndCovariance<R>,
+        horizon: usize,
+    ) -> Vec<StateAndCovariance<R>> {
+        let obs = self.observation_matrix;
+        let mut estimate = last_estimate.clone();
+        (0..horizon)
+            .map(|_| {
+                estimate = self.predict(&estimate);
+                let covariance = (linalg::matmul3(obs.H(), estimate.covariance(), &obs.HT())
+                    + obs.R())
+                .hermitian_part();
+                StateAndCovariance::new(obs.predict_observation(estimate.state()), covariance)
+            })
+            .collect()
+    }
+
+    /// Rauch-Tung-Striebel (RTS) smoother using already Kalman filtered
+    /// estimates and their [StepKind]s
+    ///
+    /// See
+    /// [`smooth_inplace_with_kinds`](struct.KalmanFilterNoControl.html#method.smooth_inplace_with_kinds).
+    /// The step kinds are returned with the smoothed estimates, so that the
+    /// estimates at gaps can be identified downstream.
+    #[cfg(feature = "std")]
+    pub fn smooth_from_filtered_with_kinds(
+        &self,
+        mut forward_results: Vec<StateAndCovariance<R>>,
+        step_kinds: Vec<StepKind>,
+    ) -> Result<(Vec<StateAndCovariance<R>>, Vec<StepKind>), Error> {
+        self.smooth_inplace_with_kinds(&mut forward_results, &step_kinds)?;
+        Ok((forward_results, step_kinds))
+    }
+
     /// Rauch-Tung-Striebel (RTS) smoother using already Kalman filtered estimates
     ///
     /// Operates on entire time series in one shot and returns a vector of state
     /// estimates. To be mathematically correct, the interval between
-    /// observations must be the `dt` specified in the motion model.
+    /// observations must be the `dt` specified in the motion model; for
+    /// irregular intervals, see
+    /// [smooth_filtered_time_varying](struct.KalmanFilterNoControl.html#method.smooth_filtered_time_varying).
     #[cfg(feature = "std")]
     pub fn smooth_from_filtered(
         &self,
         mut forward_results: Vec<StateAndCovariance<R,>>,
     ) -> Result<Vec<StateAndCovariance<R>>, Error> {
+        self.smooth_inplace(&mut forward_results)?;
+        Ok(forward_results)
+    }
+
+    /// RTS smoother which also estimates the process noise (disturbances)
+    ///
+    /// In addition to the smoothed state estimates, this returns the smoothed
+    /// disturbances `E[w_k | all data]` with their covariances, where
+    /// `x_{k+1} = F x_k + w_k`. These are useful as diagnostics of the process
+    /// model and for estimating `Q`.
+    ///
+    /// If any observation has a NaN component, it is treated as missing.
+    #[cfg(feature = "std")]
+    pub fn smooth_with_disturbances(
+        &self,
+        initial_estimate: &StateAndCovariance<R>,
+        observations: &[DVector<R>],
+    ) -> Result<DisturbanceSmootherResult<R>, Error> {
+        let forward_results = self.filter(initial_estimate, observations)?;
+        self.smooth_from_filtered_with_disturbances(forward_results)
+    }
+
+    /// RTS smoother using already Kalman filtered estimates, which also
+    /// estimates the process noise (disturbances)
+    ///
+    /// See
+    /// [`smooth_with_disturbances`](struct.KalmanFilterNoControl.html#method.smooth_with_disturbances).
+    #[cfg(feature = "std")]
+    pub fn smooth_from_filtered_with_disturbances(
+        &self,
+        mut forward_results: Vec<StateAndCovariance<R>>,
+    ) -> Result<DisturbanceSmootherResult<R>, Error> {
+        if forward_results.is_empty() {
+            return Ok(DisturbanceSmootherResult {
+                states: Vec::new(),
+                disturbances: Vec::new(),
+            });
+        }
         forward_results.reverse();
 
+        let F = self.transition_model.F();
+        let FT = self.transition_model.FT();
         let mut smoothed_backwards = Vec::with_capacity(forward_results.len());
+        let mut disturbances_backwards = Vec::with_capacity(forward_results.len());
 
+        let n = forward_results.len();
         let mut smooth_future = forward_results[0].clone();
         smoothed_backwards.push(smooth_future.clone());
-        for filt in forward_results.iter().skip(1) {
-            smooth_future = self.smooth_step(&smooth_future, filt)?;
+        for (i, filt) in forward_results.iter().enumerate().skip(1) {
+            let (smooth, j) = self
+                .smooth_step_with_gain(&smooth_future, filt)
+                .map_err(|e| e.with_step(n - 1 - i))?;
+
+            // w = x_future - F x, whose covariance involves the lag-one
+            // cross-covariance Cov(x_future, x) = P_future J^T.
+            let state = smooth_future.state() - F * smooth.state();
+            let cross = smooth_future.covariance() * j.adjoint() * &*FT;
+            let covariance = smooth_future.covariance() + F * smooth.covariance() * &*FT
+                - &cross
+                - cross.adjoint();
+            let disturbance = StateAndCovariance::new(state, covariance.hermitian_part());
+            disturbances_backwards.push(disturbance);
+
+            smooth_future = smooth;
             smoothed_backwards.push(smooth_future.clone());
         }
 
         smoothed_backwards.reverse();
-        Ok(smoothed_backwards)
+        disturbances_backwards.reverse();
+        Ok(DisturbanceSmootherResult {
+            states: smoothed_backwards,
+            disturbances: disturbances_backwards,
+        })
     }
 
-    #[cfg(feature = "std")]
     fn smooth_step(
         &self,
         smooth_future: &StateAndCovariance<R>,
         filt: &StateAndCovariance<R>,
     ) -> Result<StateAndCovariance<R>, Error> {
-        let prior = self.transition_model.predict(filt);
+        Ok(self.smooth_step_with_gain(smooth_future, filt)?.0)
+    }
 
-        let v_chol = match na::linalg::Cholesky::new(prior.covariance().clone()) {
-            Some(v) => v,
-            None => {
-                return Err(ErrorKind::CovarianceNotPositiveSemiDefinite.into());
+    /// Smooth one step, also returning the smoother gain `J`.
+    fn smooth_step_with_gain(
+        &self,
+        smooth_future: &StateAndCovariance<R>,
+        filt: &StateAndCovariance<R>,
+    ) -> Result<(StateAndCovariance<R>, DMatrix<R>), Error> {
+        self.smooth_step_with_prior(smooth_future, filt, &self.predict(filt))
+    }
+
+    /// Smooth one step, given the prior of the next step predicted from
+    /// `filt`.
+    fn smooth_step_with_prior(
+        &self,
+        smooth_future: &StateAndCovariance<R>,
+        filt: &StateAndCovariance<R>,
+        prior: &StateAndCovariance<R>,
+    ) -> Result<(StateAndCovariance<R>, DMatrix<R>), Error> {
+        let mut prior_covariance = prior.covariance().clone();
+
+        let mut attempt = 0;
+        let inv_prior_covariance: DMatrix<R> = loop {
+            if let Some(v) = linalg::cholesky_inverse(&prior_covariance) {
+                break v;
+            }
+            match self.failure_policy.repair(&prior_covariance, attempt) {
+                Some(repaired) => prior_covariance = repaired,
+                None if self.failure_policy == FailurePolicy::SkipUpdate => {
+                    let n = filt.state().nrows();
+                    return Ok((filt.clone(), DMatrix::zeros(n, n)));
+                }
+                None => {
+                    return Err(Error::from(ErrorKind::CovarianceNotPositiveSemiDefinite)
+                        .with_operation(Operation::SmoothStep)
+                        .with_matrix(prior.covariance()));
+                }
             }
+            attempt += 1;
         };
-        let inv_prior_covariance: DMatrix<R> = v_chol.inverse();
         trace!(
             "inv_prior_covariance {}",
             pretty_print!(inv_prior_covariance)
         );
 
         // J = dot(Vfilt, dot(A.T, inv(Vpred)))  # smoother gain matrix
-        let j = filt.covariance() * (self.transition_model.FT() * inv_prior_covariance);
+        let FT = self.transition_model.FT();
+        debug_assert_transpose!(self.transition_model.F(), FT);
+        let j = linalg::matmul3(filt.covariance(), &FT, &inv_prior_covariance);
 
         // xsmooth = xfilt + dot(J, xsmooth_future - xpred)
         let residuals = smooth_future.state() - prior.state();
         let state = filt.state() + &j * residuals;
 
-        // Vsmooth = Vfilt + dot(J, dot(Vsmooth_future - Vpred, J.T))
-        let covar_residuals = smooth_future.covariance() - prior.covariance();
-        let covariance = filt.covariance() + &j * (covar_residuals * j.transpose());
+        let covariance = match self.smoother_covariance_method {
+            SmootherCovarianceMethod::JosephForm => {
+                // Vsmooth = (I - J A) Vfilt (I - J A).T + J (Vsmooth_future + Q') J.T, where
+                // Vpred = A Vfilt A.T + Q', including the fading-memory factor.
+                let F = self.transition_model.F();
+                let mut noise = self.transition_model.Q().clone();
+                if self.has_fading_memory() {
+                    let propagated = linalg::matmul3(F, filt.covariance(), &FT);
+                    noise += propagated * R::from_real(self.fading_memory.clone() - na::one());
+                }
+                let n = filt.state().nrows();
+                let one_minus_jf = DMatrix::<R>::identity(n, n) - linalg::matmul(&j, F);
+                let left =
+                    linalg::matmul3(&one_minus_jf, filt.covariance(), &one_minus_jf.adjoint());
+                let future = smooth_future.covariance() + noise;
+                let right = linalg::matmul3(&j, &future, &j.adjoint());
+                (left + right).hermitian_part()
+            }
+            method => {
+                // Vsmooth = Vfilt + dot(J, dot(Vsmooth_future - Vpred, J.T))
+                let covar_residuals = smooth_future.covariance() - &prior_covariance;
+                let covariance =
+                    filt.covariance() + linalg::matmul3(&j, &covar_residuals, &j.adjoint());
+                if method == SmootherCovarianceMethod::ForcedSymmetric {
+                    covariance.hermitian_part()
+                } else {
+                    covariance
+                }
+            }
+        };
+
+        Ok((StateAndCovariance::new(state, covariance), j))
+    }
+}
 
-        Ok(StateAndCovariance::new(state, covariance))
+impl<'a, R> KalmanFilterNoControl<'a, R>
+where
+    R: RealField,
+{
+    /// Log-likelihood of a sequence of observations
+    ///
+    /// This is the sum of the log probability densities of each innovation,
+    /// i.e. of each observation as predicted from all of the observations
+    /// before it. Because each observation is scored before it is used, this
+    /// is also a measure of out-of-sample predictive performance and can be
+    /// used to compare models.
+    ///
+    /// If any observation has a NaN component, it is treated as missing and
+    /// does not contribute to the likelihood. The failure policy applies as
+    /// in [filter](Self::filter): an observation whose update is skipped does
+    /// not contribute either, and one whose prior is repaired is scored
+    /// against the repaired prior.
+    pub fn log_likelihood(
+        &self,
+        initial_estimate: &StateAndCovariance<R>,
+        observations: &[DVector<R>],
+    ) -> Result<R, Error> {
+        let mut previous_estimate = initial_estimate.clone();
+        let mut log_likelihood = R::zero();
+        for (i, observation) in observations.iter().enumerate() {
+            let prior = self.predict(&previous_estimate);
+            if observation.iter().any(|x| is_nan(x.clone())) {
+                previous_estimate = prior;
+                continue;
+            }
+            let updated = self
+                .repaired_update(
+                    &prior,
+                    observation,
+                    self.observation_matrix.R(),
+                    CovarianceUpdateMethod::JosephForm,
+                )
+                .map_err(|e| e.with_step(i))?;
+            previous_estimate = match updated {
+                Some((scored, posterior)) => {
+                    log_likelihood += self
+                        .observation_matrix
+                        .observation_log_likelihood(&scored, observation)
+                        .map_err(|e| e.with_step(i))?;
+                    posterior
+                }
+                None => prior,
+            };
+        }
+        Ok(log_likelihood)
     }
 }
 
+/// Create a NaN (not a number) value, used to mark missing observations.
+#[cfg(feature = "std")]
 #[inline]
-fn is_nan<R: RealField>(x: R) -> bool {
-    x.partial_cmp(&R::zero()).is_none()
+fn nan<R: RealField>() -> R {
+    R::zero() / R::zero()
+}
+
+#[inline]
+fn is_nan<R: ComplexField>(x: R) -> bool {
+    let zero = na::zero::<R::RealField>();
+    x.clone().real().partial_cmp(&zero).is_none() || x.imaginary().partial_cmp(&zero).is_none()
+}
+
+/// Whether a matrix equals its conjugate transpose, elementwise within the
+/// machine epsilon or a relative tolerance of 1e-5.
+#[cfg(debug_assertions)]
+fn is_hermitian<R: ComplexField>(matrix: &DMatrix<R>) -> bool {
+    let epsilon = <R::RealField as approx::AbsDiffEq>::default_epsilon();
+    let max_relative: R::RealField = na::convert(1e-5);
+    let adjoint = matrix.adjoint();
+    matrix.iter().zip(adjoint.iter()).all(|(a, b)| {
+        let difference = (a.clone() - b.clone()).modulus();
+        let scale = a.clone().modulus().max(b.clone().modulus());
+        difference <= epsilon.clone() || difference <= scale * max_relative.clone()
+    })
 }
 
 #[test]
+#[allow(clippy::bool_assert_comparison, clippy::legacy_numeric_constants)]
 fn test_is_nan() {
     assert_eq!(is_nan::<f64>(-1.0), false);
     assert_eq!(is_nan::<f64>(0.0), false);
@@ -488,3 +1823,680 @@ fn test_is_nan() {
     assert_eq!(is_nan::<f32>(-1.0 / 0.0), false);
     assert_eq!(is_nan::<f32>(std::f32::NAN), true);
 }
+
+#[test]
+fn test_step_kinds() {
+    use test_util::{initial_estimate, simulate_positions, ConstantVelocity, PositionObservation};
+
+    let transition = ConstantVelocity::new(0.1, 1.0);
+    let observation = PositionObservation::new(0.5);
+    let kf = KalmanFilterNoControl::new(&transition, &observation);
+    let mut observations = simulate_positions(10, 0.1, 1.0, 0.5, 1);
+    observations[3][0] = f64::NAN;
+    let (smoothed, kinds) = kf
+        .smooth_with_kinds(&initial_estimate(), &observations)
+        .unwrap();
+    assert_eq!(smoothed.len(), 10);
+    assert_eq!(kinds[3], StepKind::Predicted);
+    assert_eq!(kinds.iter().filter(|k| k.is_updated()).count(), 9);
+
+    // With a trailing gap, the estimates from the last update on are the
+    // filtered ones exactly, and the others match the plain smoother.
+    observations[8][0] = f64::NAN;
+    observations[9][0] = f64::NAN;
+    let (filtered, kinds) = kf
+        .filter_with_kinds(&initial_estimate(), &observations)
+        .unwrap();
+    let (smoothed, _) = kf
+        .smooth_from_filtered_with_kinds(filtered.clone(), kinds)
+        .unwrap();
+    let expected = kf.smooth(&initial_estimate(), &observations).unwrap();
+    assert_eq!(&smoothed[7..], &filtered[7..]);
+    for (a, b) in smoothed.iter().zip(expected.iter()) {
+        approx::assert_relative_eq!(a.state(), b.state(), epsilon = 1e-12);
+        approx::assert_relative_eq!(a.covariance(), b.covariance(), epsilon = 1e-12);
+    }
+    assert_ne!(smoothed[6], filtered[6]);
+
+    // Without any update, nothing is smoothed.
+    let mut estimates = filtered.clone();
+    kf.smooth_inplace_with_kinds(&mut estimates, &[StepKind::Predicted; 10])
+        .unwrap();
+    assert_eq!(estimates, filtered);
+}
+
+#[test]
+fn test_disturbance_smoothing() {
+    use test_util::{initial_estimate, simulate_positions, ConstantVelocity, PositionObservation};
+
+    let transition = ConstantVelocity::new(0.1, 1.0);
+    let observation = PositionObservation::new(0.5);
+    let kf = KalmanFilterNoControl::new(&transition, &observation);
+    let observations = simulate_positions(20, 0.1, 1.0, 0.5, 2);
+    let result = kf
+        .smooth_with_disturbances(&initial_estimate(), &observations)
+        .unwrap();
+    let (smoothed, disturbances) = (result.states, result.disturbances);
+    let expected = kf.smooth(&initial_estimate(), &observations).unwrap();
+    approx::assert_relative_eq!(smoothed[5].state(), expected[5].state());
+    assert_eq!(disturbances.len(), 19);
+    for disturbance in disturbances.iter() {
+        // Conditioning on data cannot increase uncertainty beyond Q.
+        let excess = TransitionModelLinearNoControl::Q(&transition) - disturbance.covariance();
+        assert!(excess.symmetric_eigenvalues().min() > -1e-12);
+    }
+}
+
+#[test]
+fn test_disturbance_smoothing_random_walk() {
+    // A random walk x_1 = x_0 + w_0 with Q = 1, and filtered estimates
+    // N(0, 1) and N(2, 1). The smoother gain is J = 1 / (1 + 1), so the
+    // smoothed x_0 is N(1, 1 + J^2 (1 - 2)) = N(1, 0.75), and w_0 = x_1 - x_0
+    // has mean 1 and variance 1 + 0.75 - 2 J = 0.75.
+    let one = || DMatrix::from_element(1, 1, 1.0);
+    let transition = LinearTransitionModel::from_matrices(one(), one());
+    let observation = LinearObservationModel::from_matrices(one(), one());
+    let kf = KalmanFilterNoControl::new(&transition, &observation);
+    let filtered = vec![
+        StateAndCovariance::new(DVector::from_element(1, 0.0), one()),
+        StateAndCovariance::new(DVector::from_element(1, 2.0), one()),
+    ];
+    let result = kf.smooth_from_filtered_with_disturbances(filtered).unwrap();
+    approx::assert_relative_eq!(result.states[0].state()[0], 1.0);
+    approx::assert_relative_eq!(result.states[0].covariance()[(0, 0)], 0.75);
+    approx::assert_relative_eq!(result.states[1].state()[0], 2.0);
+    assert_eq!(result.disturbances.len(), 1);
+    approx::assert_relative_eq!(result.disturbances[0].state()[0], 1.0);
+    approx::assert_relative_eq!(result.disturbances[0].covariance()[(0, 0)], 0.75);
+
+    // With a single estimate there is nothing to smooth, and with none
+    // there is nothing at all.
+    let single = vec![StateAndCovariance::new(
+        DVector::from_element(1, 3.0),
+        one(),
+    )];
+    let result = kf.smooth_from_filtered_with_disturbances(single).unwrap();
+    assert_eq!(result.states.len(), 1);
+    assert!(result.disturbances.is_empty());
+    let result = kf
+        .smooth_from_filtered_with_disturbances(Vec::new())
+        .unwrap();
+    assert!(result.states.is_empty() && result.disturbances.is_empty());
+    let result = kf
+        .smooth_with_disturbances(&StateAndCovariance::new(DVector::zeros(1), one()), &[])
+        .unwrap();
+    assert!(result.states.is_empty());
+}
+
+#[test]
+fn test_generic_scalar_pipeline() {
+    // Run the filter and smoother with a scalar type other than the one of
+    // the reference, as is done to quantify round-off against a
+    // higher-precision baseline.
+    fn smooth<R: RealField>(observations: &[Option<f64>]) -> Vec<StateAndCovariance<R>> {
+        struct Model<R: RealField> {
+            F: DMatrix<R>,
+            Q: DMatrix<R>,
+            H: DMatrix<R>,
+            R: DMatrix<R>,
+        }
+        impl<R: RealField> TransitionModelLinearNoControl<R> for Model<R> {
+            fn state_dim(&self) -> usize {
+                2
+            }
+            fn F(&self) -> &DMatrix<R> {
+                &self.F
+            }
+            fn Q(&self) -> &DMatrix<R> {
+                &self.Q
+            }
+        }
+        impl<R: RealField> ObservationModel<R> for Model<R> {
+            fn H(&self) -> &DMatrix<R> {
+                &self.H
+            }
+            fn R(&self) -> &DMatrix<R> {
+                &self.R
+            }
+            fn state_dim(&self) -> usize {
+                2
+            }
+            fn obs_dim(&self) -> usize {
+                1
+            }
+        }
+        let c = |x: f64| na::convert::<f64, R>(x);
+        let F = DMatrix::from_row_slice(2, 2, &[c(1.0), c(0.1), c(0.0), c(1.0)]);
+        let H = DMatrix::from_row_slice(1, 2, &[c(1.0), c(0.0)]);
+        let model = Model {
+            F,
+            Q: DMatrix::from_row_slice(2, 2, &[c(1e-3 / 3.0), c(5e-3), c(5e-3), c(0.1)]),
+            H,
+            R: DMatrix::from_element(1, 1, c(0.5)),
+        };
+        let observations: Vec<Option<DVector<R>>> = observations
+            .iter()
+            .map(|z| z.map(|z| DVector::from_element(1, c(z))))
+            .collect();
+        let initial = StateAndCovariance::new(
+            DVector::from_vec(vec![c(0.0), c(1.0)]),
+            DMatrix::identity(2, 2),
+        );
+        KalmanFilterNoControl::new(&model, &model)
+            .smooth_optional(&initial, &observations)
+            .unwrap()
+    }
+
+    let mut observations: Vec<Option<f64>> = test_util::simulate_positions(30, 0.1, 1.0, 0.5, 10)
+        .iter()
+        .map(|z| Some(z[0]))
+        .collect();
+    observations[12] = None;
+    let reference = smooth::<f64>(&observations);
+    let single = smooth::<f32>(&observations);
+    for (a, b) in reference.iter().zip(single.iter()) {
+        approx::assert_relative_eq!(a.state()[0], b.state()[0] as f64, epsilon = 1e-4);
+    }
+
+    // None and NaN mark missing observations equivalently.
+    let transition = test_util::ConstantVelocity::new(0.1, 1.0);
+    let observation = test_util::PositionObservation::new(0.5);
+    let kf = KalmanFilterNoControl::new(&transition, &observation);
+    let with_none: Vec<Option<DVector<f64>>> = observations
+        .iter()
+        .map(|z| z.map(|z| DVector::from_element(1, z)))
+        .collect();
+    let with_nan: Vec<DVector<f64>> = observations
+        .iter()
+        .map(|z| DVector::from_element(1, z.unwrap_or(f64::NAN)))
+        .collect();
+    let initial = test_util::initial_estimate();
+    let a = kf.smooth_optional(&initial, &with_none).unwrap();
+    let b = kf.smooth(&initial, &with_nan).unwrap();
+    approx::assert_relative_eq!(a[12].state(), b[12].state());
+}
+
+#[test]
+fn test_error_context() {
+    use test_util::{initial_estimate, ConstantVelocity, PositionObservation};
+
+    // A negative observation variance makes the innovation covariance
+    // indefinite. The first observation is missing, so the update first
+    // fails at step 1.
+    let transition = ConstantVelocity::new(0.1, 1.0);
+    let observation = PositionObservation::new(-100.0);
+    let kf = KalmanFilterNoControl::new(&transition, &observation);
+    let observations = vec![
+        DVector::from_element(1, f64::NAN),
+        DVector::from_element(1, 0.0),
+        DVector::from_element(1, 0.0),
+    ];
+    let err = kf.filter(&initial_estimate(), &observations).unwrap_err();
+    assert!(matches!(
+        err.kind(),
+        ErrorKind::CovarianceNotPositiveSemiDefinite
+    ));
+    assert_eq!(err.operation(), Some(Operation::Update));
+    assert_eq!(err.step(), Some(1));
+    assert!(err.matrix().is_some());
+
+    // Without process noise and with a certain velocity, the prior
+    // covariance is singular and the smoother cannot invert it.
+    let transition = ConstantVelocity::new(0.1, 0.0);
+    let observation = PositionObservation::new(0.5);
+    let kf = KalmanFilterNoControl::new(&transition, &observation);
+    let initial = StateAndCovariance::new(
+        DVector::from_vec(vec![0.0, 1.0]),
+        DMatrix::from_diagonal(&DVector::from_vec(vec![1.0, 0.0])),
+    );
+    let observations = test_util::simulate_positions(5, 0.1, 1.0, 0.5, 3);
+    let err = kf.smooth(&initial, &observations).unwrap_err();
+    assert_eq!(err.operation(), Some(Operation::SmoothStep));
+    assert_eq!(err.step(), Some(3));
+    assert!(format!("{}", err).contains("at step 3"));
+}
+
+#[test]
+fn test_dimension_mismatch() {
+    use test_util::{initial_estimate, ConstantVelocity, MatrixObservation, PositionObservation};
+
+    let transition = ConstantVelocity::new(0.1, 1.0);
+    let observation = MatrixObservation::new(DMatrix::zeros(1, 3), DMatrix::identity(1, 1));
+    let err = KalmanFilterNoControl::try_new(&transition, &observation)
+        .err()
+        .unwrap();
+    assert!(matches!(
+        err.kind(),
+        ErrorKind::DimensionMismatch {
+            matrix: "observation model state",
+            ..
+        }
+    ));
+
+    let observation = PositionObservation::new(0.5);
+    let kf = KalmanFilterNoControl::try_new(&transition, &observation).unwrap();
+    let err = kf
+        .step(&initial_estimate(), &DVector::from_element(2, 0.0))
+        .unwrap_err();
+    assert!(matches!(
+        err.kind(),
+        ErrorKind::DimensionMismatch {
+            expected: (1, 1),
+            actual: (2, 1),
+            matrix: "observation",
+        }
+    ));
+}
+
+#[test]
+fn test_fading_memory() {
+    use test_util::{initial_estimate, ConstantVelocity, PositionObservation};
+
+    let transition = ConstantVelocity::new(0.1, 1.0);
+    let observation = PositionObservation::new(0.5);
+    let standard = KalmanFilterNoControl::new(&transition, &observation);
+    let fading = KalmanFilterNoControl::new(&transition, &observation).with_fading_memory(1.1);
+    // Only the propagated covariance is scaled, not Q.
+    let model: &dyn TransitionModelLinearNoControl<f64> = &transition;
+    let F = model.F();
+    approx::assert_relative_eq!(
+        fading.predict(&initial_estimate()).covariance(),
+        &(F * initial_estimate().covariance() * F.transpose() * 1.21 + model.Q()),
+        epsilon = 1e-12
+    );
+
+    // Discounting old observations leaves the estimate less certain.
+    let observations = test_util::simulate_positions(20, 0.1, 1.0, 0.5, 4);
+    let a = standard.smooth(&initial_estimate(), &observations).unwrap();
+    let b = fading.smooth(&initial_estimate(), &observations).unwrap();
+    for (a, b) in a.iter().zip(b.iter()).skip(1) {
+        assert!(b.covariance()[(0, 0)] > a.covariance()[(0, 0)]);
+    }
+
+    // The other smoothers and the likelihoods apply the factor too.
+    let information = fading
+        .smooth_information_form(&initial_estimate(), &observations)
+        .unwrap();
+    let parallel = fading
+        .smooth_parallel(&initial_estimate(), &observations)
+        .unwrap();
+    for ((b, c), d) in b.iter().zip(information.iter()).zip(parallel.iter()) {
+        approx::assert_relative_eq!(b.covariance(), c.covariance(), epsilon = 1e-10);
+        approx::assert_relative_eq!(b.covariance(), d.covariance(), epsilon = 1e-10);
+    }
+    let likelihood = fading
+        .log_likelihood(&initial_estimate(), &observations)
+        .unwrap();
+    let (with_gradient, _) = fading
+        .log_likelihood_with_gradient(&initial_estimate(), &observations, &[])
+        .unwrap();
+    approx::assert_relative_eq!(likelihood, with_gradient, epsilon = 1e-10);
+}
+
+#[test]
+#[should_panic]
+fn test_fading_memory_below_one() {
+    use test_util::{ConstantVelocity, PositionObservation};
+
+    let transition = ConstantVelocity::new(0.1, 1.0);
+    let observation = PositionObservation::new(0.5);
+    let _ = KalmanFilterNoControl::new(&transition, &observation).with_fading_memory(0.9);
+}
+
+#[test]
+fn test_smooth_inplace() {
+    use test_util::{initial_estimate, ConstantVelocity, PositionObservation};
+
+    let transition = ConstantVelocity::new(0.1, 1.0);
+    let observation = PositionObservation::new(0.5);
+    let kf = KalmanFilterNoControl::new(&transition, &observation);
+    let observations = test_util::simulate_positions(15, 0.1, 1.0, 0.5, 11);
+    let expected = kf.smooth(&initial_estimate(), &observations).unwrap();
+
+    let mut estimates = [(); 15].map(|_| initial_estimate());
+    kf.filter_inplace(&initial_estimate(), &observations, &mut estimates)
+        .unwrap();
+    kf.smooth_inplace(&mut estimates).unwrap();
+    for (a, e) in estimates.iter().zip(expected.iter()) {
+        approx::assert_relative_eq!(a, e);
+    }
+}
+
+#[test]
+fn test_smooth_inplace_random_walk() {
+    use test_util::{random_walk, scalar_estimate, scalars, walk_filtered, walk_smoothed};
+
+    // A buffer longer than the observations is filled only as far as there
+    // are observations, and the filled part is smoothed in place, across a
+    // gap and from a prior away from the steady state.
+    let (transition, observation) = random_walk();
+    let kf = KalmanFilterNoControl::new(&transition, &observation);
+    let prior = scalar_estimate(1.0, 5.0);
+    let values = [2.0, f64::NAN, -1.0, 3.0];
+    let noise = [(1.0, 2.0); 4];
+    let unused = scalar_estimate(-7.0, 9.0);
+    let mut estimates = [(); 6].map(|_| unused.clone());
+    kf.filter_inplace(&prior, &scalars(&values), &mut estimates)
+        .unwrap();
+    assert_eq!(estimates[4..], [unused.clone(), unused]);
+    let estimates = &mut estimates[..4];
+    let expected = walk_filtered(&prior, &noise, &values);
+    approx::assert_relative_eq!(&estimates[..], expected.as_slice(), epsilon = 1e-12);
+    kf.smooth_inplace(estimates).unwrap();
+    let expected = walk_smoothed(&prior, &noise, &values);
+    approx::assert_relative_eq!(&estimates[..], expected.as_slice(), epsilon = 1e-12);
+
+    // A single estimate, or none, is already smoothed.
+    let mut single = [scalar_estimate(3.0, 1.0)];
+    kf.smooth_inplace(&mut single).unwrap();
+    assert_eq!(single, [scalar_estimate(3.0, 1.0)]);
+    kf.smooth_inplace(&mut []).unwrap();
+}
+
+#[test]
+fn test_predict_series() {
+    use test_util::{initial_estimate, ConstantVelocity, PositionObservation};
+
+    // Prediction is the same as filtering with every observation missing.
+    let transition = ConstantVelocity::new(0.1, 1.0);
+    let observation = PositionObservation::new(0.5);
+    let kf = KalmanFilterNoControl::new(&transition, &observation);
+    let predicted = kf.predict_series(&initial_estimate(), 5);
+    let missing = vec![DVector::from_element(1, f64::NAN); 5];
+    let expected = kf.filter(&initial_estimate(), &missing).unwrap();
+    assert_eq!(predicted, expected);
+    assert!(predicted[4].covariance()[(0, 0)] > predicted[0].covariance()[(0, 0)]);
+}
+
+#[test]
+fn test_per_measurement_covariance() {
+    use test_util::{initial_estimate, ConstantVelocity, PositionObservation};
+
+    // Overriding R is the same as using a model with that R.
+    let transition = ConstantVelocity::new(0.1, 1.0);
+    let observation = PositionObservation::new(0.5);
+    let accurate = PositionObservation::new(0.01);
+    let z = DVector::from_element(1, 0.3);
+    let expected = KalmanFilterNoControl::new(&transition, &accurate)
+        .step(&initial_estimate(), &z)
+        .unwrap();
+    let kf = KalmanFilterNoControl::new(&transition, &observation);
+    let actual = kf
+        .step_with_covariance(&initial_estimate(), &z, accurate.R())
+        .unwrap();
+    assert_eq!(actual, expected);
+    assert!(kf
+        .step_with_covariance(&initial_estimate(), &z, &DMatrix::identity(2, 2))
+        .is_err());
+}
+
+#[test]
+fn test_heteroscedastic_filter() {
+    use test_util::{initial_estimate, simulate_positions, ConstantVelocity, PositionObservation};
+
+    let transition = ConstantVelocity::new(0.1, 1.0);
+    let observation = PositionObservation::new(0.5);
+    let kf = KalmanFilterNoControl::new(&transition, &observation);
+    let observations = simulate_positions(6, 0.1, 1.0, 0.5, 9);
+    let scales = [1.0, 4.0, 0.5, 1.0, 100.0, 2.0];
+
+    let mut expected = initial_estimate();
+    let actual = kf
+        .filter_with_covariance_scales(&initial_estimate(), &observations, &scales)
+        .unwrap();
+    for ((z, scale), a) in observations.iter().zip(scales).zip(actual.iter()) {
+        let R = observation.R() * scale;
+        expected = kf.step_with_covariance(&expected, z, &R).unwrap();
+        assert_eq!(a, &expected);
+    }
+
+    // With unit scales, this is the ordinary filter.
+    let unit = kf
+        .filter_with_covariance_scales(&initial_estimate(), &observations, &[1.0; 6])
+        .unwrap();
+    assert_eq!(unit, kf.filter(&initial_estimate(), &observations).unwrap());
+}
+
+#[test]
+fn test_heteroscedastic_edge_scales() {
+    use test_util::{initial_estimate, simulate_positions, ConstantVelocity, PositionObservation};
+
+    // A scale of zero makes an observation exact, so it replaces the
+    // position, and the scale of a missing observation is ignored.
+    let transition = ConstantVelocity::new(0.1, 1.0);
+    let observation = PositionObservation::new(0.5);
+    let kf = KalmanFilterNoControl::new(&transition, &observation);
+    let mut observations = simulate_positions(4, 0.1, 1.0, 0.5, 29);
+    observations[3] = DVector::from_element(1, f64::NAN);
+    let actual = kf
+        .filter_with_covariance_scales(&initial_estimate(), &observations, &[1.0, 0.0, 2.0, 4.0])
+        .unwrap();
+    approx::assert_relative_eq!(actual[1].state()[0], observations[1][0], epsilon = 1e-12);
+    approx::assert_relative_eq!(actual[1].covariance()[(0, 0)], 0.0, epsilon = 1e-12);
+    let ignored = kf
+        .filter_with_covariance_scales(&initial_estimate(), &observations, &[1.0, 0.0, 2.0, 1.0])
+        .unwrap();
+    assert_eq!(actual, ignored);
+}
+
+#[test]
+#[should_panic]
+fn test_heteroscedastic_missing_scale() {
+    use test_util::{initial_estimate, simulate_positions, ConstantVelocity, PositionObservation};
+
+    let transition = ConstantVelocity::new(0.1, 1.0);
+    let observation = PositionObservation::new(0.5);
+    let kf = KalmanFilterNoControl::new(&transition, &observation);
+    let observations = simulate_positions(2, 0.1, 1.0, 0.5, 9);
+    let _ = kf.filter_with_covariance_scales(&initial_estimate(), &observations, &[1.0]);
+}
+
+#[test]
+fn test_smoother_covariance_methods() {
+    use test_util::{initial_estimate, simulate_positions, ConstantVelocity, PositionObservation};
+
+    // In f64, all forms agree, with fading memory too.
+    let transition = ConstantVelocity::new(0.1, 1.0);
+    let observation = PositionObservation::new(0.5);
+    let observations = simulate_positions(20, 0.1, 1.0, 0.5, 12);
+    let kf = || KalmanFilterNoControl::new(&transition, &observation).with_fading_memory(1.02);
+    let standard = kf().smooth(&initial_estimate(), &observations).unwrap();
+    for method in [
+        SmootherCovarianceMethod::ForcedSymmetric,
+        SmootherCovarianceMethod::JosephForm,
+    ] {
+        let smoothed = kf()
+            .with_smoother_covariance_method(method)
+            .smooth(&initial_estimate(), &observations)
+            .unwrap();
+        approx::assert_relative_eq!(smoothed.as_slice(), standard.as_slice(), epsilon = 1e-10);
+    }
+
+    // In f32, with little process noise, the standard form loses positive
+    // semi-definiteness, unless products are accumulated in f64, but the
+    // Joseph form keeps it.
+    let dt = 0.01f32;
+    let q = 1e-7f32;
+    let F = DMatrix::from_row_slice(2, 2, &[1.0, dt, 0.0, 1.0]);
+    let Q = DMatrix::from_row_slice(
+        2,
+        2,
+        &[
+            q * dt * dt * dt / 3.0,
+            q * dt * dt / 2.0,
+            q * dt * dt / 2.0,
+            q * dt,
+        ],
+    );
+    let transition = LinearTransitionModel::from_matrices(F, Q);
+    let observation = LinearObservationModel::from_matrices(
+        DMatrix::from_row_slice(1, 2, &[1.0, 0.0]),
+        DMatrix::from_element(1, 1, 1.0),
+    );
+    let initial = StateAndCovariance::new(DVector::zeros(2), DMatrix::identity(2, 2) * 100.0);
+    let observations: Vec<DVector<f32>> = (0..2000)
+        .map(|k| DVector::from_element(1, (k as f32 * dt).sin()))
+        .collect();
+    let smallest_eigenvalue = |method| {
+        KalmanFilterNoControl::new(&transition, &observation)
+            .with_smoother_covariance_method(method)
+            .smooth(&initial, &observations)
+            .unwrap()
+            .iter()
+            .map(|e| e.covariance().clone().symmetric_eigen().eigenvalues.min())
+            .fold(f32::INFINITY, f32::min)
+    };
+    if !cfg!(feature = "compensated") {
+        assert!(smallest_eigenvalue(SmootherCovarianceMethod::Standard) < 0.0);
+    }
+    assert!(smallest_eigenvalue(SmootherCovarianceMethod::JosephForm) > 0.0);
+}
+
+#[test]
+fn test_interpolate() {
+    use crate::test_util::{
+        initial_estimate, simulate_positions, ConstantVelocity, PositionObservation,
+    };
+
+    let transition = ConstantVelocity::new(0.1, 1.0);
+    let observation = PositionObservation::new(0.5);
+    let kf = KalmanFilterNoControl::new(&transition, &observation);
+    let mut observations = simulate_positions(30, 0.1, 1.0, 0.5, 8);
+    for k in [3, 12, 13, 14, 15, 16] {
+        observations[k][0] = f64::NAN;
+    }
+    let smoothed = kf.smooth(&initial_estimate(), &observations).unwrap();
+    let interpolated = kf.interpolate(&initial_estimate(), &observations).unwrap();
+    let steps: Vec<usize> = interpolated.iter().map(|(k, _)| *k).collect();
+    assert_eq!(steps, vec![3, 12, 13, 14, 15, 16]);
+    for (k, estimate) in interpolated.iter() {
+        assert_eq!(estimate, &smoothed[*k]);
+    }
+    // The uncertainty is largest in the middle of the gap.
+    let variance = |i: usize| interpolated[i].1.covariance()[(0, 0)];
+    assert!(variance(3) > variance(1) && variance(3) > variance(5));
+}
+
+#[test]
+fn test_interpolate_gap_positions() {
+    use test_util::{initial_estimate, simulate_positions, ConstantVelocity, PositionObservation};
+
+    // Missing steps at the start and at the end are returned in order with
+    // the others, with their smoothed estimates.
+    let transition = ConstantVelocity::new(0.1, 1.0);
+    let observation = PositionObservation::new(0.5);
+    let kf = KalmanFilterNoControl::new(&transition, &observation);
+    let mut observations = simulate_positions(8, 0.1, 1.0, 0.5, 31);
+    for k in [0, 2, 3, 7] {
+        observations[k][0] = f64::NAN;
+    }
+    let smoothed = kf.smooth(&initial_estimate(), &observations).unwrap();
+    let interpolated = kf.interpolate(&initial_estimate(), &observations).unwrap();
+    let steps: Vec<usize> = interpolated.iter().map(|(k, _)| *k).collect();
+    assert_eq!(steps, [0, 2, 3, 7]);
+    for (k, estimate) in interpolated.iter() {
+        assert_eq!(estimate, &smoothed[*k]);
+    }
+
+    // Without gaps there is nothing to interpolate; without observations,
+    // the interpolation is the prediction.
+    let observed = simulate_positions(3, 0.1, 1.0, 0.5, 31);
+    assert!(kf
+        .interpolate(&initial_estimate(), &observed)
+        .unwrap()
+        .is_empty());
+    let missing = vec![DVector::from_element(1, f64::NAN); 3];
+    let interpolated = kf.interpolate(&initial_estimate(), &missing).unwrap();
+    let predicted = kf.predict_series(&initial_estimate(), 3);
+    let steps: Vec<usize> = interpolated.iter().map(|(k, _)| *k).collect();
+    assert_eq!(steps, [0, 1, 2]);
+    for ((_, estimate), expected) in interpolated.iter().zip(predicted.iter()) {
+        approx::assert_relative_eq!(estimate, expected, epsilon = 1e-12);
+    }
+}
+
+#[test]
+fn test_forecast() {
+    use crate::test_util::{initial_estimate, ConstantVelocity, PositionObservation};
+
+    let transition = ConstantVelocity::new(0.1, 1.0);
+    let observation = PositionObservation::new(0.5);
+    let kf = KalmanFilterNoControl::new(&transition, &observation);
+    let forecasts = kf.forecast(&initial_estimate(), 20);
+    assert_eq!(forecasts.len(), 20);
+
+    let prior = kf.predict(&initial_estimate());
+    let innovation = observation.innovation(&prior, &DVector::zeros(1));
+    approx::assert_relative_eq!(forecasts[0].covariance(), innovation.covariance());
+    // The velocity of 1 is extrapolated, with widening intervals.
+    approx::assert_relative_eq!(forecasts[19].state()[0], 2.0, epsilon = 1e-12);
+    assert!(forecasts
+        .windows(2)
+        .all(|w| w[1].covariance()[(0, 0)] > w[0].covariance()[(0, 0)]));
+}
+
+#[test]
+fn test_complex_phasor_tracking() {
+    use na::Complex;
+
+    // A phasor rotating by a known angle each step, observed in noise.
+    let rotation = Complex::new(0.3f64.cos(), 0.3f64.sin());
+    let transition = LinearTransitionModel::from_matrices(
+        DMatrix::from_element(1, 1, rotation),
+        DMatrix::from_element(1, 1, Complex::new(0.01, 0.0)),
+    );
+    let observation = LinearObservationModel::from_matrices(
+        DMatrix::from_element(1, 1, Complex::new(1.0, 0.0)),
+        DMatrix::from_element(1, 1, Complex::new(0.5, 0.0)),
+    );
+    let kf = KalmanFilterNoControl::new(&transition, &observation);
+    let initial = StateAndCovariance::new(
+        DVector::from_element(1, Complex::new(0.0, 0.0)),
+        DMatrix::from_element(1, 1, Complex::new(10.0, 0.0)),
+    );
+    let mut truth = Complex::new(2.0 * 1.0f64.cos(), 2.0 * 1.0f64.sin());
+    let mut observations = Vec::new();
+    for k in 0..50 {
+        truth *= rotation;
+        let noise = Complex::new(0.3 * (k as f64).sin(), -0.2 * (k as f64).cos());
+        observations.push(DVector::from_element(1, truth + noise));
+    }
+    let filtered = kf.filter(&initial, &observations).unwrap();
+
+    // The variance follows the real scalar Riccati recursion.
+    let mut variance = 10.0f64;
+    for estimate in filtered.iter() {
+        let prior = variance + 0.01;
+        variance = prior * 0.5 / (prior + 0.5);
+        approx::assert_relative_eq!(estimate.covariance()[0].re, variance, epsilon = 1e-12);
+        approx::assert_relative_eq!(estimate.covariance()[0].im, 0.0, epsilon = 1e-12);
+    }
+    assert!((filtered[49].state()[0] - truth).modulus() < 0.3);
+
+    // The smoothed variances, which do not depend on the phase of the
+    // rotation, are those of the real random walk.
+    let smoothed = kf.smooth(&initial, &observations).unwrap();
+    let walk = LinearTransitionModel::from_matrices(
+        DMatrix::from_element(1, 1, 1.0),
+        DMatrix::from_element(1, 1, 0.01),
+    );
+    let real_observation = LinearObservationModel::from_matrices(
+        DMatrix::from_element(1, 1, 1.0),
+        DMatrix::from_element(1, 1, 0.5),
+    );
+    let real_initial =
+        StateAndCovariance::new(DVector::zeros(1), DMatrix::from_element(1, 1, 10.0));
+    let expected = KalmanFilterNoControl::new(&walk, &real_observation)
+        .smooth(&real_initial, &vec![DVector::zeros(1); 50])
+        .unwrap();
+    for (actual, expected) in smoothed.iter().zip(expected.iter()) {
+        let covariance = actual.covariance()[0];
+        approx::assert_relative_eq!(covariance.re, expected.covariance()[0], epsilon = 1e-12);
+        approx::assert_relative_eq!(covariance.im, 0.0, epsilon = 1e-12);
+    }
+
+    // A missing observation has a NaN component, real or imaginary.
+    observations[10][0] = Complex::new(0.0, f64::NAN);
+    let (_, kinds) = kf.filter_with_kinds(&initial, &observations).unwrap();
+    assert_eq!(kinds[10], StepKind::Predicted);
+}
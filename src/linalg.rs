@@ -0,0 +1,248 @@
+//! Dense linear algebra used by the filter and smoother
+//!
+//! The core predict, update and smoothing steps call the functions here
+//! rather than using nalgebra directly, so that another backend can be used
+//! where it is faster. nalgebra is always the default. With the `faer`
+//! feature, products and Cholesky inversions of `f32` and `f64` matrices with
+//! a dimension of at least [FAER_MIN_DIM] are computed by `faer`.
+//!
+//! With the `compensated` feature, products and Cholesky inversions of `f32`
+//! matrices are accumulated in `f64` and rounded once, while the estimates
+//! are still stored in `f32`. Over long runs, this stops round-off in the
+//! `f32` sums from accumulating in the covariance. It takes precedence over
+//! `faer` for `f32` matrices.
+
+use na::{ComplexField, DMatrix};
+use nalgebra as na;
+
+/// Operations on dense matrices provided by a linear algebra backend
+pub(crate) trait LinalgBackend<R>
+where
+    R: ComplexField,
+{
+    /// The product `a b`.
+    fn matmul(a: &DMatrix<R>, b: &DMatrix<R>) -> DMatrix<R>;
+
+    /// The inverse of a Hermitian positive definite matrix by Cholesky
+    /// decomposition, or `None` if it is not positive definite.
+    fn cholesky_inverse(a: DMatrix<R>) -> Option<DMatrix<R>>;
+}
+
+/// The default backend, for any scalar type
+pub(crate) struct NalgebraBackend;
+
+impl<R> LinalgBackend<R> for NalgebraBackend
+where
+    R: ComplexField,
+{
+    #[inline]
+    fn matmul(a: &DMatrix<R>, b: &DMatrix<R>) -> DMatrix<R> {
+        a * b
+    }
+
+    #[inline]
+    fn cholesky_inverse(a: DMatrix<R>) -> Option<DMatrix<R>> {
+        na::linalg::Cholesky::new(a).map(|chol| chol.inverse())
+    }
+}
+
+/// The smallest dimension for which the `faer` backend is used.
+#[cfg(feature = "faer")]
+pub(crate) const FAER_MIN_DIM: usize = 32;
+
+#[cfg(feature = "faer")]
+pub(crate) struct FaerBackend;
+
+#[cfg(feature = "faer")]
+macro_rules! impl_faer_backend {
+    ($r:ty) => {
+        impl LinalgBackend<$r> for FaerBackend {
+            fn matmul(a: &DMatrix<$r>, b: &DMatrix<$r>) -> DMatrix<$r> {
+                from_faer(&(to_faer(a) * to_faer(b)))
+            }
+
+            fn cholesky_inverse(a: DMatrix<$r>) -> Option<DMatrix<$r>> {
+                use faer::linalg::solvers::DenseSolveCore;
+                let llt = to_faer(&a).llt(faer::Side::Lower).ok()?;
+                Some(from_faer(&llt.inverse()))
+            }
+        }
+    };
+}
+
+#[cfg(feature = "faer")]
+impl_faer_backend!(f32);
+#[cfg(feature = "faer")]
+impl_faer_backend!(f64);
+
+#[cfg(feature = "faer")]
+fn to_faer<T: na::RealField + Copy>(m: &DMatrix<T>) -> faer::Mat<T> {
+    faer::Mat::from_fn(m.nrows(), m.ncols(), |i, j| m[(i, j)])
+}
+
+#[cfg(feature = "faer")]
+fn from_faer<T: na::RealField + Copy>(m: &faer::Mat<T>) -> DMatrix<T> {
+    DMatrix::from_fn(m.nrows(), m.ncols(), |i, j| m[(i, j)])
+}
+
+/// The `compensated` backend, accumulating `f32` matrices in `f64`
+#[cfg(feature = "compensated")]
+pub(crate) struct WidenedBackend;
+
+#[cfg(feature = "compensated")]
+impl WidenedBackend {
+    fn widen(a: &DMatrix<f32>) -> DMatrix<f64> {
+        a.map(f64::from)
+    }
+
+    fn narrow(a: &DMatrix<f64>) -> DMatrix<f32> {
+        a.map(|x| x as f32)
+    }
+
+    /// The product `a b c`, without rounding the intermediate `a b`.
+    fn matmul3(a: &DMatrix<f32>, b: &DMatrix<f32>, c: &DMatrix<f32>) -> DMatrix<f32> {
+        Self::narrow(&(Self::widen(a) * Self::widen(b) * Self::widen(c)))
+    }
+}
+
+#[cfg(feature = "compensated")]
+impl LinalgBackend<f32> for WidenedBackend {
+    fn matmul(a: &DMatrix<f32>, b: &DMatrix<f32>) -> DMatrix<f32> {
+        Self::narrow(&(Self::widen(a) * Self::widen(b)))
+    }
+
+    fn cholesky_inverse(a: DMatrix<f32>) -> Option<DMatrix<f32>> {
+        NalgebraBackend::cholesky_inverse(Self::widen(&a)).map(|m| Self::narrow(&m))
+    }
+}
+
+/// Call `f` with the matrices reinterpreted as the concrete scalar type `T`,
+/// if `R` is `T`.
+#[cfg(any(feature = "faer", feature = "compensated"))]
+fn with_concrete<R, T, O>(
+    matrices: &[&DMatrix<R>],
+    f: impl FnOnce(&[&DMatrix<T>]) -> O,
+) -> Option<O>
+where
+    R: ComplexField,
+    T: na::RealField,
+{
+    use alloc::vec::Vec;
+    use core::any::Any;
+    let concrete: Option<Vec<&DMatrix<T>>> = matrices
+        .iter()
+        .map(|m| (*m as &dyn Any).downcast_ref::<DMatrix<T>>())
+        .collect();
+    concrete.map(|c| f(&c))
+}
+
+/// Convert a matrix of the concrete scalar type `T` back to `R`, which must
+/// be the same type.
+#[cfg(any(feature = "faer", feature = "compensated"))]
+fn to_generic<T: na::RealField, R: ComplexField>(m: DMatrix<T>) -> DMatrix<R> {
+    let boxed: alloc::boxed::Box<dyn core::any::Any> = alloc::boxed::Box::new(m);
+    *boxed.downcast::<DMatrix<R>>().unwrap()
+}
+
+/// The product `a b`.
+#[inline]
+pub(crate) fn matmul<R: ComplexField>(a: &DMatrix<R>, b: &DMatrix<R>) -> DMatrix<R> {
+    #[cfg(feature = "compensated")]
+    if let Some(m) = with_concrete::<R, f32, _>(&[a, b], |m| WidenedBackend::matmul(m[0], m[1])) {
+        return to_generic(m);
+    }
+    #[cfg(feature = "faer")]
+    if a.nrows().max(a.ncols()).max(b.ncols()) >= FAER_MIN_DIM {
+        let product = with_concrete::<R, f64, _>(&[a, b], |m| FaerBackend::matmul(m[0], m[1]));
+        if let Some(m) = product {
+            return to_generic(m);
+        }
+        let product = with_concrete::<R, f32, _>(&[a, b], |m| FaerBackend::matmul(m[0], m[1]));
+        if let Some(m) = product {
+            return to_generic(m);
+        }
+    }
+    NalgebraBackend::matmul(a, b)
+}
+
+/// The product `a b c`.
+#[inline]
+pub(crate) fn matmul3<R>(a: &DMatrix<R>, b: &DMatrix<R>, c: &DMatrix<R>) -> DMatrix<R>
+where
+    R: ComplexField,
+{
+    #[cfg(feature = "compensated")]
+    if let Some(m) =
+        with_concrete::<R, f32, _>(&[a, b, c], |m| WidenedBackend::matmul3(m[0], m[1], m[2]))
+    {
+        return to_generic(m);
+    }
+    matmul(&matmul(a, b), c)
+}
+
+/// The inverse of a Hermitian (for a real field, symmetric) positive
+/// definite matrix, or `None` if it is not positive definite.
+#[inline]
+pub(crate) fn cholesky_inverse<R: ComplexField>(a: &DMatrix<R>) -> Option<DMatrix<R>> {
+    #[cfg(feature = "compensated")]
+    if let Some(m) =
+        with_concrete::<R, f32, _>(&[a], |m| WidenedBackend::cholesky_inverse(m[0].clone()))
+    {
+        return m.map(to_generic);
+    }
+    #[cfg(feature = "faer")]
+    if a.nrows() >= FAER_MIN_DIM {
+        if let Some(m) =
+            with_concrete::<R, f64, _>(&[a], |m| FaerBackend::cholesky_inverse(m[0].clone()))
+        {
+            return m.map(to_generic);
+        }
+        if let Some(m) =
+            with_concrete::<R, f32, _>(&[a], |m| FaerBackend::cholesky_inverse(m[0].clone()))
+        {
+            return m.map(to_generic);
+        }
+    }
+    NalgebraBackend::cholesky_inverse(a.clone())
+}
+
+#[cfg(feature = "faer")]
+#[test]
+fn test_faer_matches_nalgebra() {
+    let n = FAER_MIN_DIM + 3;
+    let a = DMatrix::<f64>::from_fn(n, n, |i, j| ((i * 7 + j * 3) % 11) as f64 / 11.0);
+    let spd = &a * a.transpose() + DMatrix::identity(n, n);
+    approx::assert_relative_eq!(
+        matmul(&a, &spd),
+        NalgebraBackend::matmul(&a, &spd),
+        epsilon = 1e-10
+    );
+    approx::assert_relative_eq!(
+        cholesky_inverse(&spd).unwrap(),
+        NalgebraBackend::cholesky_inverse(spd).unwrap(),
+        epsilon = 1e-10
+    );
+}
+
+#[cfg(feature = "compensated")]
+#[test]
+fn test_compensated_accumulation() {
+    // Many terms of very different magnitudes, whose sum loses the small
+    // terms when accumulated in f32.
+    let n = 1000;
+    let a = DMatrix::<f32>::from_fn(1, n, |_, j| if j == 0 { 1.0e4 } else { 1.0e-3 });
+    let b = DMatrix::<f32>::from_element(n, 1, 1.0);
+    let exact = 1.0e4 + (n - 1) as f64 * 1.0e-3;
+    let actual = matmul(&a, &b)[(0, 0)];
+    assert_eq!(actual, exact as f32);
+    let actual = matmul3(
+        &b.transpose(),
+        &a.transpose(),
+        &DMatrix::from_element(1, 1, 1.0),
+    );
+    assert_eq!(actual[(0, 0)], exact as f32);
+
+    let spd = DMatrix::<f32>::from_row_slice(2, 2, &[4.0, 1.0, 1.0, 3.0]);
+    let expected = NalgebraBackend::cholesky_inverse(spd.map(f64::from)).unwrap();
+    assert_eq!(cholesky_inverse(&spd).unwrap(), expected.map(|x| x as f32));
+}
@@ -0,0 +1,131 @@
+//! The linear quadratic regulator, the control counterpart of the Kalman
+//! filter
+
+use na::{DMatrix, DVector, RealField};
+use nalgebra as na;
+
+use crate::riccati::{kalman_gain, steady_state_prior_covariance};
+use crate::{Error, StateAndCovariance, TransitionModelLinearNoControl};
+
+/// The infinite-horizon linear quadratic regulator (LQR)
+///
+/// For `x_{k+1} = F x_k + B u_k`, the control `u_k = -L x_k` minimizes the
+/// cost `sum x_k^T Q x_k + u_k^T R u_k`. The cost-to-go matrix `P` solves
+/// the control algebraic Riccati equation
+/// `P = F^T (P - P B (B^T P B + R)^-1 B^T P) F + Q`, which is that of the
+/// Kalman filter with `F^T` and `B^T` in place of `F` and `H`, so both are
+/// solved the same way, and `L = (B^T P B + R)^-1 B^T P F`.
+///
+/// Together with a Kalman filter of the same model, feeding back the
+/// estimated state as `u = -L x` gives the linear quadratic Gaussian (LQG)
+/// controller, by the separation principle.
+#[derive(Debug, Clone)]
+pub struct LinearQuadraticRegulator<R>
+where
+    R: RealField,
+{
+    gain: DMatrix<R>,
+    cost_to_go: DMatrix<R>,
+}
+
+impl<R> LinearQuadraticRegulator<R>
+where
+    R: RealField,
+{
+    /// Compute the regulator for the dynamics `F` and `B` and the costs `Q`
+    /// and `R`
+    ///
+    /// An [ErrorKind::DimensionMismatch](crate::ErrorKind::DimensionMismatch)
+    /// error is returned if the shapes are inconsistent, and an
+    /// [ErrorKind::NotConverged](crate::ErrorKind::NotConverged) error if
+    /// there is no steady state, e.g. for an unstable, uncontrollable model.
+    pub fn new(
+        F: &DMatrix<R>,
+        B: &DMatrix<R>,
+        Q: &DMatrix<R>,
+        R: &DMatrix<R>,
+    ) -> Result<Self, Error> {
+        let n = F.nrows();
+        let m = B.ncols();
+        crate::check_shape("F", (n, n), F)?;
+        crate::check_shape("B", (n, m), B)?;
+        crate::check_shape("Q", (n, n), Q)?;
+        crate::check_shape("R", (m, m), R)?;
+        let BT = B.transpose();
+        let cost_to_go = steady_state_prior_covariance(&F.transpose(), Q, &BT, R)?;
+        // The Kalman gain of the dual problem is P B (B^T P B + R)^-1.
+        let gain = kalman_gain(&cost_to_go, &BT, R)?.transpose() * F;
+        Ok(Self { gain, cost_to_go })
+    }
+
+    /// Compute the regulator for the `F` of a transition model, see
+    /// [new](Self::new).
+    pub fn from_transition_model(
+        transition_model: &dyn TransitionModelLinearNoControl<R>,
+        B: &DMatrix<R>,
+        Q: &DMatrix<R>,
+        R: &DMatrix<R>,
+    ) -> Result<Self, Error> {
+        Self::new(transition_model.F(), B, Q, R)
+    }
+
+    /// Get the feedback gain, `L`.
+    pub fn gain(&self) -> &DMatrix<R> {
+        &self.gain
+    }
+
+    /// Get the cost-to-go matrix, `P`; the cost from state `x` onward is
+    /// `x^T P x`.
+    pub fn cost_to_go(&self) -> &DMatrix<R> {
+        &self.cost_to_go
+    }
+
+    /// The control for a state, `u = -L x`.
+    pub fn control(&self, state: &DVector<R>) -> DVector<R> {
+        -(&self.gain * state)
+    }
+
+    /// The control for an estimated state, `u = -L x`, as used by the LQG
+    /// controller.
+    pub fn control_estimate(&self, estimate: &StateAndCovariance<R>) -> DVector<R> {
+        self.control(estimate.state())
+    }
+}
+
+#[test]
+fn test_lqr() {
+    use crate::test_util::ConstantVelocity;
+
+    // A scalar integrator with unit costs: P^2 = P + 1, so P is the golden
+    // ratio and L = P / (P + 1).
+    let one = DMatrix::from_element(1, 1, 1.0);
+    let lqr = LinearQuadraticRegulator::new(&one, &one, &one, &one).unwrap();
+    let golden = (1.0 + 5f64.sqrt()) / 2.0;
+    approx::assert_relative_eq!(lqr.cost_to_go()[(0, 0)], golden, epsilon = 1e-9);
+    approx::assert_relative_eq!(lqr.gain()[(0, 0)], golden / (golden + 1.0), epsilon = 1e-9);
+
+    // An accelerated double integrator is stabilized by the feedback.
+    let transition = ConstantVelocity::new(0.1, 1.0);
+    let B = DMatrix::from_row_slice(2, 1, &[0.005, 0.1]);
+    let lqr = LinearQuadraticRegulator::from_transition_model(
+        &transition,
+        &B,
+        &DMatrix::identity(2, 2),
+        &one,
+    )
+    .unwrap();
+    let closed_loop = transition.F() - &B * lqr.gain();
+    let radius = closed_loop
+        .complex_eigenvalues()
+        .iter()
+        .map(|e| e.re.hypot(e.im))
+        .fold(0.0, f64::max);
+    assert!(radius < 1.0);
+    let mut x = DVector::from_vec(vec![1.0, 0.0]);
+    for _ in 0..500 {
+        x = transition.F() * &x + &B * lqr.control(&x);
+    }
+    assert!(x.norm() < 1e-3);
+
+    assert!(LinearQuadraticRegulator::new(&one, &B, &one, &one).is_err());
+}
@@ -0,0 +1,146 @@
+//! Boosting the process noise when the innovations reveal a maneuver
+
+use na::{DVector, RealField};
+use nalgebra as na;
+
+use crate::{
+    is_nan, ChiSquareGate, CovarianceUpdateMethod, Error, ObservationModel, ScaledTransitionModel,
+    StateAndCovariance, TransitionModelLinearNoControl,
+};
+
+/// A Kalman filter which scales up `Q` while the target maneuvers
+///
+/// A filter tuned for a target moving steadily lags behind it when it
+/// maneuvers, and its innovations grow. Here, when the normalized
+/// innovation squared (NIS) of an observation fails the gate, the step is
+/// predicted again with `Q` multiplied by `boost`, so the filter follows the
+/// maneuver at once. After each step, the excess of the scale over one is
+/// multiplied by `decay`, so `Q` returns to its tuned value once the
+/// innovations are small again.
+///
+/// This is a crude detector, without the mode probabilities of an IMM or
+/// [GpbFilter](crate::GpbFilter), but needs only one filter.
+pub struct QBoostingFilter<'a, R>
+where
+    R: RealField,
+{
+    transition_model: &'a dyn TransitionModelLinearNoControl<R>,
+    observation_model: &'a dyn ObservationModel<R>,
+    gate: ChiSquareGate<R>,
+    boost: R,
+    decay: R,
+    scale: R,
+}
+
+impl<'a, R> QBoostingFilter<'a, R>
+where
+    R: RealField,
+{
+    /// Create a new filter boosting `Q` by `boost` when an innovation fails
+    /// `gate`, with the boost decaying by `decay`, between zero and one, at
+    /// each step.
+    pub fn new(
+        transition_model: &'a dyn TransitionModelLinearNoControl<R>,
+        observation_model: &'a dyn ObservationModel<R>,
+        gate: ChiSquareGate<R>,
+        boost: R,
+        decay: R,
+    ) -> Self {
+        Self {
+            transition_model,
+            observation_model,
+            gate,
+            boost,
+            decay,
+            scale: R::one(),
+        }
+    }
+
+    /// The current factor by which `Q` is scaled.
+    pub fn scale(&self) -> &R {
+        &self.scale
+    }
+
+    /// Whether `Q` is still boosted by more than 1%.
+    pub fn is_boosted(&self) -> bool {
+        self.scale > na::convert(1.01)
+    }
+
+    /// Perform prediction and update steps, boosting `Q` if the innovation
+    /// fails the gate
+    ///
+    /// If any component of the observation is NaN (not a number), the
+    /// estimate is only predicted.
+    pub fn step(
+        &mut self,
+        previous_estimate: &StateAndCovariance<R>,
+        observation: &DVector<R>,
+    ) -> Result<StateAndCovariance<R>, Error> {
+        let mut prior = self.predict(previous_estimate);
+        let estimate = if observation.iter().any(|x| is_nan(x.clone())) {
+            prior
+        } else {
+            let innovation = self.observation_model.innovation(&prior, observation);
+            if !self.gate.accepts(&innovation)? && self.boost > self.scale {
+                self.scale = self.boost.clone();
+                prior = self.predict(previous_estimate);
+            }
+            self.observation_model.update(
+                &prior,
+                observation,
+                CovarianceUpdateMethod::JosephForm,
+            )?
+        };
+        self.scale = R::one() + (self.scale.clone() - R::one()) * self.decay.clone();
+        Ok(estimate)
+    }
+
+    fn predict(&self, estimate: &StateAndCovariance<R>) -> StateAndCovariance<R> {
+        if self.scale == R::one() {
+            self.transition_model.predict(estimate)
+        } else {
+            ScaledTransitionModel::new(self.transition_model, self.scale.clone()).predict(estimate)
+        }
+    }
+}
+
+#[test]
+fn test_q_boosting() {
+    use crate::test_util::{initial_estimate, ConstantVelocity, PositionObservation};
+    use crate::KalmanFilterNoControl;
+
+    let transition = ConstantVelocity::new(0.1, 0.01);
+    let observation = PositionObservation::new(0.01);
+    // The target moves at unit speed, then reverses at step 50.
+    let mut position = 0.0;
+    let observations: Vec<DVector<f64>> = (0..120)
+        .map(|k| {
+            position += if k < 50 { 0.1 } else { -0.1 };
+            DVector::from_element(1, position)
+        })
+        .collect();
+
+    let gate = ChiSquareGate::new(1, 0.99);
+    let mut boosting = QBoostingFilter::new(&transition, &observation, gate, 100.0, 0.7);
+    let kf = KalmanFilterNoControl::new(&transition, &observation);
+    let mut boosted = initial_estimate();
+    let mut plain = initial_estimate();
+    let mut was_boosted = false;
+    for (k, z) in observations.iter().enumerate() {
+        boosted = boosting.step(&boosted, z).unwrap();
+        plain = kf.step(&plain, z).unwrap();
+        if k < 45 {
+            assert!(!boosting.is_boosted());
+        }
+        was_boosted |= boosting.is_boosted();
+        if k == 60 {
+            // The boosted filter has picked up the new velocity; the plain
+            // one lags.
+            let lag = |estimate: &StateAndCovariance<f64>| (estimate.state()[1] + 1.0).abs();
+            assert!(lag(&boosted) < 0.5 * lag(&plain));
+        }
+    }
+    assert!(was_boosted);
+    assert!(!boosting.is_boosted());
+    approx::assert_relative_eq!(boosted.state()[1], -1.0, epsilon = 1e-3);
+}
@@ -0,0 +1,312 @@
+//! Observation noise modelled as a mixture of nominal and outlier Gaussians
+
+use alloc::borrow::Cow;
+
+use na::{DMatrix, DVector, RealField};
+use nalgebra as na;
+
+use crate::switching::normalize_log_weights;
+use crate::{
+    merge_gaussians, CovarianceUpdateMethod, Error, Innovation, ObservationModel,
+    StateAndCovariance,
+};
+
+/// An observation model whose noise is a two-component Gaussian mixture
+///
+/// With probability `1 - p` the noise has the covariance `R` of the wrapped
+/// model, and with the outlier probability `p` the broader covariance `k R`,
+/// e.g. for the glint of a radar or the multipath of a GNSS receiver. The
+/// update weights the posteriors of the two components by their posterior
+/// probabilities, from the likelihoods of the innovation, and collapses
+/// them into a single Gaussian with the same mean and covariance. Unlike
+/// gating, a likely outlier still contributes, with a small gain, and
+/// nothing depends on a hard threshold.
+///
+/// [R](ObservationModel::R) is the nominal covariance, and the model can be
+/// used with [KalmanFilterNoControl](crate::KalmanFilterNoControl) like any
+/// other. The covariance given to
+/// [update_with_covariance](ObservationModel::update_with_covariance)
+/// replaces the nominal covariance, and the outlier covariance is scaled
+/// with it. The
+/// [observation_log_likelihood](ObservationModel::observation_log_likelihood),
+/// and so the filter's log-likelihood, is the density of the mixture.
+pub struct GaussianMixtureNoise<'a, R>
+where
+    R: RealField,
+{
+    inner: &'a dyn ObservationModel<R>,
+    outlier_probability: R,
+    outlier_scale: R,
+}
+
+impl<'a, R> GaussianMixtureNoise<'a, R>
+where
+    R: RealField,
+{
+    /// Wrap `inner`, whose `R` is the nominal noise, with outliers of
+    /// probability `outlier_probability` and covariance `outlier_scale` times
+    /// `R`.
+    pub fn new(
+        inner: &'a dyn ObservationModel<R>,
+        outlier_probability: R,
+        outlier_scale: R,
+    ) -> Self {
+        Self {
+            inner,
+            outlier_probability,
+            outlier_scale,
+        }
+    }
+
+    /// The log-likelihoods of an observation under the nominal and outlier
+    /// components, each including its prior probability, for the nominal
+    /// covariance `nominal_R`.
+    ///
+    /// As in the update of the wrapped model, `H` is its
+    /// [jacobian_at](ObservationModel::jacobian_at) the prior state.
+    fn component_log_weights(
+        &self,
+        prior: &StateAndCovariance<R>,
+        observation: &DVector<R>,
+        nominal_R: &DMatrix<R>,
+    ) -> Result<DVector<R>, Error> {
+        let residual = observation - self.inner.predict_observation(prior.state());
+        let HPHT = match self.inner.jacobian_at(prior.state()) {
+            Cow::Borrowed(H) => H * prior.covariance() * &*self.inner.HT(),
+            Cow::Owned(H) => &H * prior.covariance() * H.transpose(),
+        };
+        let nominal = Innovation::new(residual.clone(), &HPHT + nominal_R);
+        let outlier = Innovation::new(residual, HPHT + nominal_R * self.outlier_scale.clone());
+        Ok(DVector::from_vec(vec![
+            nominal.log_likelihood()? + (R::one() - self.outlier_probability.clone()).ln(),
+            outlier.log_likelihood()? + self.outlier_probability.clone().ln(),
+        ]))
+    }
+
+    /// The posterior probabilities of the nominal and outlier components,
+    /// for the nominal covariance `nominal_R`.
+    fn component_probabilities(
+        &self,
+        prior: &StateAndCovariance<R>,
+        observation: &DVector<R>,
+        nominal_R: &DMatrix<R>,
+    ) -> Result<DVector<R>, Error> {
+        let log_weights = self.component_log_weights(prior, observation, nominal_R)?;
+        Ok(normalize_log_weights(&log_weights))
+    }
+
+    /// The posterior probability that an observation is an outlier.
+    pub fn outlier_probability(
+        &self,
+        prior: &StateAndCovariance<R>,
+        observation: &DVector<R>,
+    ) -> Result<R, Error> {
+        Ok(self.component_probabilities(prior, observation, self.inner.R())?[1].clone())
+    }
+}
+
+impl<'a, R> ObservationModel<R> for GaussianMixtureNoise<'a, R>
+where
+    R: RealField,
+{
+    fn predict_observation(&self, state: &DVector<R>) -> DVector<R> {
+        self.inner.predict_observation(state)
+    }
+    fn H(&self) -> &DMatrix<R> {
+        self.inner.H()
+    }
+    fn HT(&self) -> Cow<'_, DMatrix<R>> {
+        self.inner.HT()
+    }
+    fn jacobian_at(&self, state: &DVector<R>) -> Cow<'_, DMatrix<R>> {
+        self.inner.jacobian_at(state)
+    }
+    fn R(&self) -> &DMatrix<R> {
+        self.inner.R()
+    }
+    fn state_dim(&self) -> usize {
+        self.inner.state_dim()
+    }
+    fn obs_dim(&self) -> usize {
+        self.inner.obs_dim()
+    }
+
+    /// The innovation, whose covariance is that of the mixture,
+    /// `H P H^T + (1 - p + p k) R`, so that e.g. its NIS is consistent.
+    fn innovation(&self, prior: &StateAndCovariance<R>, observation: &DVector<R>) -> Innovation<R> {
+        let (residual, covariance) = self.inner.innovation(prior, observation).inner();
+        let inflation = self.outlier_probability.clone() * (self.outlier_scale.clone() - R::one());
+        Innovation::new(residual, covariance + self.inner.R() * inflation)
+    }
+
+    /// The log of the mixture density of the observation, the sum of the
+    /// densities of the components weighted by their prior probabilities.
+    fn observation_log_likelihood(
+        &self,
+        prior: &StateAndCovariance<R>,
+        observation: &DVector<R>,
+    ) -> Result<R, Error> {
+        let log_weights = self.component_log_weights(prior, observation, self.inner.R())?;
+        let max = log_weights.max();
+        let total = log_weights.map(|w| (w - max.clone()).exp()).sum();
+        Ok(max + total.ln())
+    }
+
+    /// Update with each component of the noise, and collapse the weighted
+    /// posteriors into a single Gaussian.
+    fn update_with_covariance(
+        &self,
+        prior: &StateAndCovariance<R>,
+        observation: &DVector<R>,
+        observation_covariance: &DMatrix<R>,
+        covariance_method: CovarianceUpdateMethod,
+    ) -> Result<StateAndCovariance<R>, Error> {
+        let weights = self.component_probabilities(prior, observation, observation_covariance)?;
+        let outlier_R = observation_covariance * self.outlier_scale.clone();
+        let nominal = self.inner.update_with_covariance(
+            prior,
+            observation,
+            observation_covariance,
+            covariance_method,
+        )?;
+        let outlier =
+            self.inner
+                .update_with_covariance(prior, observation, &outlier_R, covariance_method)?;
+        let merged = merge_gaussians(&weights, &[nominal, outlier]);
+        let (state, covariance) = merged.inner();
+        Ok(StateAndCovariance::new(state, covariance.symmetric_part()))
+    }
+}
+
+#[test]
+fn test_gaussian_mixture_noise() {
+    use crate::test_util::{initial_estimate, ConstantVelocity, PositionObservation};
+    use crate::KalmanFilterNoControl;
+
+    let transition = ConstantVelocity::new(0.1, 1.0);
+    let position = PositionObservation::new(0.5);
+    let mixture = GaussianMixtureNoise::new(&position, 0.1, 100.0);
+
+    let nominal_kf = KalmanFilterNoControl::new(&transition, &position);
+    let mixture_kf = KalmanFilterNoControl::new(&transition, &mixture);
+    let prior = nominal_kf.predict(&initial_estimate());
+
+    // A consistent observation is almost surely nominal, and is used nearly
+    // as by the plain filter.
+    let z = DVector::from_element(1, 0.2);
+    assert!(mixture.outlier_probability(&prior, &z).unwrap() < 0.2);
+    let plain = nominal_kf.step(&initial_estimate(), &z).unwrap();
+    let robust = mixture_kf.step(&initial_estimate(), &z).unwrap();
+    approx::assert_relative_eq!(robust.state()[0], plain.state()[0], epsilon = 0.02);
+
+    // A wild observation is almost surely an outlier, and barely moves the
+    // estimate.
+    let z = DVector::from_element(1, 30.0);
+    assert!(mixture.outlier_probability(&prior, &z).unwrap() > 0.999);
+    let plain = nominal_kf.step(&initial_estimate(), &z).unwrap();
+    let robust = mixture_kf.step(&initial_estimate(), &z).unwrap();
+    assert!(plain.state()[0] > 15.0);
+    assert!(robust.state()[0] < 2.0);
+}
+
+#[test]
+fn test_gaussian_mixture_collapse() {
+    use crate::test_util::{initial_estimate, ConstantVelocity, PositionObservation};
+    use crate::TransitionModelLinearNoControl;
+
+    // The update is the collapse of the updates with the nominal and the
+    // outlier covariance, weighted by the component probabilities, with the
+    // covariance given to the update in place of the nominal one.
+    let transition = ConstantVelocity::new(0.1, 1.0);
+    let position = PositionObservation::new(0.5);
+    let mixture = GaussianMixtureNoise::new(&position, 0.3, 9.0);
+    let prior = transition.predict(&initial_estimate());
+    let z = DVector::from_element(1, 2.5);
+    let method = CovarianceUpdateMethod::JosephForm;
+    let nominal_R = DMatrix::from_element(1, 1, 0.8);
+    let weights = mixture
+        .component_probabilities(&prior, &z, &nominal_R)
+        .unwrap();
+    assert!(weights[0] > 0.1 && weights[1] > 0.1);
+    let components = [
+        position
+            .update_with_covariance(&prior, &z, &nominal_R, method)
+            .unwrap(),
+        position
+            .update_with_covariance(&prior, &z, &(&nominal_R * 9.0), method)
+            .unwrap(),
+    ];
+    let mean = components[0].state() * weights[0] + components[1].state() * weights[1];
+    let mut covariance = DMatrix::zeros(2, 2);
+    for (w, component) in weights.iter().zip(components.iter()) {
+        let spread = component.state() - &mean;
+        covariance += (component.covariance() + &spread * spread.transpose()) * *w;
+    }
+    let posterior = mixture
+        .update_with_covariance(&prior, &z, &nominal_R, method)
+        .unwrap();
+    approx::assert_relative_eq!(posterior.state(), &mean, epsilon = 1e-12);
+    approx::assert_relative_eq!(posterior.covariance(), &covariance, epsilon = 1e-12);
+
+    // The likelihood is that of the mixture, and the innovation covariance
+    // that of the mixture, H P H^T + (1 - p + p k) R.
+    let nominal = position.innovation(&prior, &z);
+    let outlier = Innovation::new(
+        nominal.residual().clone(),
+        nominal.covariance() + position.R() * 8.0,
+    );
+    let density = 0.7 * nominal.log_likelihood().unwrap().exp()
+        + 0.3 * outlier.log_likelihood().unwrap().exp();
+    approx::assert_relative_eq!(
+        mixture.observation_log_likelihood(&prior, &z).unwrap(),
+        density.ln(),
+        epsilon = 1e-12
+    );
+    approx::assert_relative_eq!(
+        mixture.innovation(&prior, &z).covariance(),
+        &(nominal.covariance() + position.R() * 2.4),
+        epsilon = 1e-12
+    );
+}
+
+#[test]
+fn test_gaussian_mixture_nonlinear() {
+    use crate::nonlinear::NumericalObservationModel;
+
+    // A range, linearized at the nominal state (3, 4), is linearized at the
+    // prior (1, -2) for the component probabilities and the likelihood, as
+    // in its update.
+    let range = NumericalObservationModel::new(
+        |x: &DVector<f64>| DVector::from_element(1, x.norm()),
+        DMatrix::from_element(1, 1, 0.1),
+        1e-7,
+        &DVector::from_vec(vec![3.0, 4.0]),
+    );
+    let mixture = GaussianMixtureNoise::new(&range, 0.2, 25.0);
+    let prior = StateAndCovariance::new(
+        DVector::from_vec(vec![1.0, -2.0]),
+        DMatrix::from_row_slice(2, 2, &[0.5, 0.1, 0.1, 0.3]),
+    );
+    let z = DVector::from_element(1, 3.5);
+    let nominal = range.innovation(&prior, &z);
+    let outlier = Innovation::new(
+        nominal.residual().clone(),
+        nominal.covariance() + range.R() * 24.0,
+    );
+    let nominal = nominal.log_likelihood().unwrap().exp() * 0.8;
+    let outlier = outlier.log_likelihood().unwrap().exp() * 0.2;
+    approx::assert_relative_eq!(
+        mixture.outlier_probability(&prior, &z).unwrap(),
+        outlier / (nominal + outlier),
+        epsilon = 1e-9
+    );
+    approx::assert_relative_eq!(
+        mixture.observation_log_likelihood(&prior, &z).unwrap(),
+        (nominal + outlier).ln(),
+        epsilon = 1e-9
+    );
+    approx::assert_relative_eq!(
+        &*mixture.jacobian_at(prior.state()),
+        &*range.jacobian_at(prior.state())
+    );
+}
@@ -0,0 +1,255 @@
+//! Static multiple-model adaptive estimation
+
+use na::{DVector, RealField};
+use nalgebra as na;
+
+use crate::switching::normalize_log_weights;
+use crate::{
+    is_nan, CovarianceUpdateMethod, Error, ObservationModel, StateAndCovariance, SwitchingEstimate,
+    TransitionModelLinearNoControl,
+};
+
+struct Hypothesis<'a, R>
+where
+    R: RealField,
+{
+    transition_model: &'a dyn TransitionModelLinearNoControl<R>,
+    observation_model: &'a dyn ObservationModel<R>,
+}
+
+/// A bank of Kalman filters, one per candidate model, with the posterior
+/// probability of each model (MMAE)
+///
+/// Unlike the [GpbFilter](crate::GpbFilter), the true model is assumed not
+/// to change, so each filter runs independently and the probability of each
+/// model is multiplied by the likelihood of its innovation at every step.
+/// This tests hypotheses about the model, e.g. which of several `Q` is
+/// right, and the probability-weighted estimate is
+/// [SwitchingEstimate::combined]. The candidate models must share the state.
+///
+/// The probabilities of the wrong models decay geometrically, so a model
+/// which becomes right later is only recovered slowly; a probability floor,
+/// see [with_probability_floor](Self::with_probability_floor), keeps the
+/// estimator responsive.
+pub struct MultipleModelEstimator<'a, R>
+where
+    R: RealField,
+{
+    hypotheses: Vec<Hypothesis<'a, R>>,
+    probability_floor: R,
+}
+
+impl<'a, R> Default for MultipleModelEstimator<'a, R>
+where
+    R: RealField,
+{
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<'a, R> MultipleModelEstimator<'a, R>
+where
+    R: RealField,
+{
+    /// Create a new estimator with no models. Add them with
+    /// [add_model](Self::add_model).
+    pub fn new() -> Self {
+        Self {
+            hypotheses: Vec::new(),
+            probability_floor: R::zero(),
+        }
+    }
+
+    /// Keep the probability of each model at least `floor`, renormalizing
+    /// after each step. The default is zero, for the exact posterior.
+    pub fn with_probability_floor(mut self, floor: R) -> Self {
+        self.probability_floor = floor;
+        self
+    }
+
+    /// Add a candidate model, returning its index.
+    pub fn add_model(
+        &mut self,
+        transition_model: &'a dyn TransitionModelLinearNoControl<R>,
+        observation_model: &'a dyn ObservationModel<R>,
+    ) -> usize {
+        self.hypotheses.push(Hypothesis {
+            transition_model,
+            observation_model,
+        });
+        self.hypotheses.len() - 1
+    }
+
+    /// The number of candidate models.
+    pub fn len(&self) -> usize {
+        self.hypotheses.len()
+    }
+
+    /// Whether there are no candidate models.
+    pub fn is_empty(&self) -> bool {
+        self.hypotheses.is_empty()
+    }
+
+    /// Start every model from the same estimate, with equal probabilities.
+    pub fn initial_estimate(&self, estimate: StateAndCovariance<R>) -> SwitchingEstimate<R> {
+        let r = self.hypotheses.len();
+        SwitchingEstimate::new(
+            estimate,
+            DVector::from_element(r, R::one() / na::convert(r as f64)),
+        )
+    }
+
+    /// Perform prediction and update steps in every model, and update the
+    /// model probabilities with the likelihoods of the innovations
+    ///
+    /// If any component of the observation is NaN (not a number), the
+    /// estimates are only predicted and the probabilities are unchanged.
+    pub fn step(
+        &self,
+        previous: &SwitchingEstimate<R>,
+        observation: &DVector<R>,
+    ) -> Result<SwitchingEstimate<R>, Error> {
+        let r = self.hypotheses.len();
+        assert_eq!(r, previous.mode_probabilities.nrows());
+        let missing = observation.iter().any(|x| is_nan(x.clone()));
+        let mut mode_estimates = Vec::with_capacity(r);
+        let mut log_weights = DVector::zeros(r);
+        for (j, hypothesis) in self.hypotheses.iter().enumerate() {
+            let prior = hypothesis
+                .transition_model
+                .predict(&previous.mode_estimates[j]);
+            log_weights[j] = previous.mode_probabilities[j].clone().ln();
+            if missing {
+                mode_estimates.push(prior);
+                continue;
+            }
+            log_weights[j] += hypothesis
+                .observation_model
+                .innovation(&prior, observation)
+                .log_likelihood()?;
+            mode_estimates.push(hypothesis.observation_model.update(
+                &prior,
+                observation,
+                CovarianceUpdateMethod::JosephForm,
+            )?);
+        }
+        let mut mode_probabilities = normalize_log_weights(&log_weights);
+        if self.probability_floor > R::zero() {
+            let floor = self.probability_floor.clone();
+            mode_probabilities.apply(|p| *p = p.clone().max(floor.clone()));
+            let total = mode_probabilities.sum();
+            mode_probabilities /= total;
+        }
+        Ok(SwitchingEstimate {
+            mode_estimates,
+            mode_probabilities,
+        })
+    }
+}
+
+#[test]
+fn test_mmae_identifies_process_noise() {
+    use crate::test_util::{
+        initial_estimate, simulate_positions, ConstantVelocity, PositionObservation,
+    };
+    use crate::KalmanFilterNoControl;
+
+    let candidates = [
+        ConstantVelocity::new(0.1, 0.01),
+        ConstantVelocity::new(0.1, 1.0),
+        ConstantVelocity::new(0.1, 100.0),
+    ];
+    let observation = PositionObservation::new(0.5);
+    let observations = simulate_positions(200, 0.1, 1.0, 0.5, 12);
+
+    let mut mmae = MultipleModelEstimator::new();
+    for candidate in candidates.iter() {
+        mmae.add_model(candidate, &observation);
+    }
+    assert_eq!(mmae.len(), 3);
+    let mut estimate = mmae.initial_estimate(initial_estimate());
+    for z in observations.iter() {
+        estimate = mmae.step(&estimate, z).unwrap();
+    }
+    assert_eq!(estimate.most_probable_mode(), 1);
+    assert!(estimate.mode_probabilities[1] > 0.99);
+
+    // Each model is filtered independently.
+    let expected = KalmanFilterNoControl::new(&candidates[0], &observation)
+        .filter(&initial_estimate(), &observations)
+        .unwrap();
+    approx::assert_relative_eq!(estimate.mode_estimates[0], expected[199], epsilon = 1e-9);
+
+    let mut floored = MultipleModelEstimator::new().with_probability_floor(0.01);
+    for candidate in candidates.iter() {
+        floored.add_model(candidate, &observation);
+    }
+    let mut estimate = floored.initial_estimate(initial_estimate());
+    for z in observations.iter() {
+        estimate = floored.step(&estimate, z).unwrap();
+    }
+    assert!(estimate.mode_probabilities.min() >= 0.0099);
+}
+
+#[test]
+fn test_mmae_probabilities() {
+    use crate::test_util::{
+        initial_estimate, simulate_positions, ConstantVelocity, PositionObservation,
+    };
+    use crate::KalmanFilterNoControl;
+
+    // Each model is filtered on its own, with its own observation model, and
+    // its probability is multiplied by the likelihood of its innovation; a
+    // missing observation leaves the probabilities unchanged.
+    let calm = ConstantVelocity::new(0.1, 0.1);
+    let agitated = ConstantVelocity::new(0.1, 10.0);
+    let precise = PositionObservation::new(0.5);
+    let coarse = PositionObservation::new(2.0);
+    let models: [(
+        &dyn TransitionModelLinearNoControl<f64>,
+        &dyn ObservationModel<f64>,
+    ); 2] = [(&calm, &precise), (&agitated, &coarse)];
+    let mut mmae = MultipleModelEstimator::new();
+    for (transition, observation) in models {
+        mmae.add_model(transition, observation);
+    }
+    let mut observations = simulate_positions(5, 0.1, 1.0, 0.5, 27);
+    observations[2] = DVector::from_element(1, f64::NAN);
+    let mut estimate = mmae.initial_estimate(initial_estimate());
+    for z in observations.iter() {
+        let next = mmae.step(&estimate, z).unwrap();
+        let mut weights = estimate.mode_probabilities.clone();
+        for (j, (transition, observation)) in models.iter().enumerate() {
+            let kf = KalmanFilterNoControl::new(*transition, *observation);
+            let previous = &estimate.mode_estimates[j];
+            let expected = kf.step(previous, z).unwrap();
+            approx::assert_relative_eq!(next.mode_estimates[j], expected, epsilon = 1e-12);
+            if !z[0].is_nan() {
+                let prior = transition.predict(previous);
+                let innovation = observation.innovation(&prior, z);
+                weights[j] *= innovation.log_likelihood().unwrap().exp();
+            }
+        }
+        weights /= weights.sum();
+        approx::assert_relative_eq!(next.mode_probabilities, weights, epsilon = 1e-12);
+        estimate = next;
+    }
+
+    // The floor raises the probabilities below it before they are
+    // renormalized.
+    let mut floored = MultipleModelEstimator::new().with_probability_floor(0.45);
+    for (transition, observation) in models {
+        floored.add_model(transition, observation);
+    }
+    let initial = floored.initial_estimate(initial_estimate());
+    let exact = mmae.step(&initial, &observations[0]).unwrap();
+    let floored = floored.step(&initial, &observations[0]).unwrap();
+    let raised = exact.mode_probabilities.map(|p| p.max(0.45));
+    assert_ne!(raised, exact.mode_probabilities);
+    approx::assert_relative_eq!(
+        floored.mode_probabilities,
+        &raised / raised.sum(),
+        epsilon = 1e-12
+    );
+}
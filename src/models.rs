@@ -0,0 +1,191 @@
+//! Linear models given directly by their matrices
+
+use alloc::borrow::Cow;
+
+use na::{ComplexField, DMatrix};
+use nalgebra as na;
+
+use crate::{ObservationModel, TransitionModelLinearNoControl};
+
+/// A linear transition model given by its matrices `F` and `Q`
+///
+/// The transpose of `F` (the conjugate transpose, over a complex field) is
+/// computed once, on construction. `Q` may instead
+/// be given by a noise coupling matrix `G` and the covariance `Qc` of the
+/// driving noise, see [from_noise_coupling](Self::from_noise_coupling).
+#[derive(Debug, Clone)]
+pub struct LinearTransitionModel<R>
+where
+    R: ComplexField,
+{
+    F: DMatrix<R>,
+    FT: DMatrix<R>,
+    Q: DMatrix<R>,
+    noise_coupling: Option<(DMatrix<R>, DMatrix<R>)>,
+}
+
+impl<R> LinearTransitionModel<R>
+where
+    R: ComplexField,
+{
+    /// Create a new model from the state transition matrix `F` and the
+    /// process covariance `Q`.
+    pub fn from_matrices(F: DMatrix<R>, Q: DMatrix<R>) -> Self {
+        let FT = F.adjoint();
+        Self {
+            F,
+            FT,
+            Q,
+            noise_coupling: None,
+        }
+    }
+
+    /// Create a new model from the state transition matrix `F`, the noise
+    /// coupling matrix `G` and the covariance `Qc` of the driving noise, so
+    /// that the process covariance is `Q = G Qc G^T`.
+    pub fn from_noise_coupling(F: DMatrix<R>, G: DMatrix<R>, Qc: DMatrix<R>) -> Self {
+        let FT = F.adjoint();
+        let Q = &G * &Qc * G.adjoint();
+        Self {
+            F,
+            FT,
+            Q,
+            noise_coupling: Some((G, Qc)),
+        }
+    }
+}
+
+impl<R> TransitionModelLinearNoControl<R> for LinearTransitionModel<R>
+where
+    R: ComplexField,
+{
+    fn state_dim(&self) -> usize {
+        self.F.nrows()
+    }
+    fn F(&self) -> &DMatrix<R> {
+        &self.F
+    }
+    fn FT(&self) -> Cow<'_, DMatrix<R>> {
+        Cow::Borrowed(&self.FT)
+    }
+    fn Q(&self) -> &DMatrix<R> {
+        &self.Q
+    }
+    fn noise_coupling(&self) -> Option<(&DMatrix<R>, &DMatrix<R>)> {
+        self.noise_coupling.as_ref().map(|(G, Qc)| (G, Qc))
+    }
+}
+
+/// A linear observation model given by its matrices `H` and `R`
+///
+/// The transpose of `H` (the conjugate transpose, over a complex field) is
+/// computed once, on construction.
+#[derive(Debug, Clone)]
+pub struct LinearObservationModel<R>
+where
+    R: ComplexField,
+{
+    H: DMatrix<R>,
+    HT: DMatrix<R>,
+    R: DMatrix<R>,
+}
+
+impl<R> LinearObservationModel<R>
+where
+    R: ComplexField,
+{
+    /// Create a new model from the observation matrix `H` and the
+    /// observation noise covariance `R`.
+    pub fn from_matrices(H: DMatrix<R>, R: DMatrix<R>) -> Self {
+        let HT = H.adjoint();
+        Self { H, HT, R }
+    }
+}
+
+impl<R> ObservationModel<R> for LinearObservationModel<R>
+where
+    R: ComplexField,
+{
+    fn H(&self) -> &DMatrix<R> {
+        &self.H
+    }
+    fn HT(&self) -> Cow<'_, DMatrix<R>> {
+        Cow::Borrowed(&self.HT)
+    }
+    fn R(&self) -> &DMatrix<R> {
+        &self.R
+    }
+    fn state_dim(&self) -> usize {
+        self.H.ncols()
+    }
+    fn obs_dim(&self) -> usize {
+        self.H.nrows()
+    }
+}
+
+#[test]
+fn test_from_matrices() {
+    use crate::test_util::{
+        initial_estimate, simulate_positions, ConstantVelocity, PositionObservation,
+    };
+    use crate::KalmanFilterNoControl;
+
+    let reference_transition = ConstantVelocity::new(0.1, 1.0);
+    let reference_observation = PositionObservation::new(0.5);
+    let transition = LinearTransitionModel::from_matrices(
+        reference_transition.F().clone(),
+        TransitionModelLinearNoControl::Q(&reference_transition).clone(),
+    );
+    let observation = LinearObservationModel::from_matrices(
+        reference_observation.H().clone(),
+        reference_observation.R().clone(),
+    );
+    crate::validate_models(&transition, &observation).unwrap();
+
+    let observations = simulate_positions(10, 0.1, 1.0, 0.5, 8);
+    let expected = KalmanFilterNoControl::new(&reference_transition, &reference_observation)
+        .smooth(&initial_estimate(), &observations)
+        .unwrap();
+    let actual = KalmanFilterNoControl::new(&transition, &observation)
+        .smooth(&initial_estimate(), &observations)
+        .unwrap();
+    for (a, e) in actual.iter().zip(expected.iter()) {
+        approx::assert_relative_eq!(a, e);
+    }
+}
+
+#[test]
+fn test_noise_coupling() {
+    use crate::test_util::ConstantVelocity;
+
+    // Continuous white noise acceleration, sampled at the start of each
+    // interval and held, couples into the state through G = [dt^2/2, dt].
+    let dt = 0.1;
+    let F = ConstantVelocity::new(dt, 1.0).F().clone();
+    let G = DMatrix::from_column_slice(2, 1, &[dt * dt / 2.0, dt]);
+    let Qc = DMatrix::from_element(1, 1, 4.0);
+    let model = LinearTransitionModel::from_noise_coupling(F, G.clone(), Qc.clone());
+    let expected = DMatrix::from_row_slice(
+        2,
+        2,
+        &[
+            dt.powi(4),
+            2.0 * dt.powi(3),
+            2.0 * dt.powi(3),
+            4.0 * dt * dt,
+        ],
+    );
+    approx::assert_relative_eq!(model.Q(), &expected, epsilon = 1e-12);
+    assert_eq!(model.noise_coupling(), Some((&G, &Qc)));
+
+    let bad_G = LinearTransitionModel::from_noise_coupling(
+        DMatrix::identity(2, 2),
+        DMatrix::from_element(3, 1, 1.0),
+        Qc,
+    );
+    let observation = LinearObservationModel::from_matrices(
+        DMatrix::from_row_slice(1, 2, &[1.0, 0.0]),
+        DMatrix::from_element(1, 1, 1.0),
+    );
+    assert!(crate::validate_models(&bad_G, &observation).is_err());
+}
@@ -0,0 +1,234 @@
+//! Monte Carlo benchmarking of filter consistency
+
+use na::RealField;
+use nalgebra as na;
+
+use crate::{
+    chi_squared_interval, nees, simulate, Error, KalmanFilterNoControl, ObservationModel,
+    StateAndCovariance, TransitionModelLinearNoControl,
+};
+
+/// Parameters of [monte_carlo_consistency]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct MonteCarloConfig<R>
+where
+    R: RealField,
+{
+    /// The number of simulated runs.
+    pub runs: usize,
+    /// The number of steps of each run.
+    pub steps: usize,
+    /// The probability of the two-sided chi-square bands, e.g. 0.95.
+    pub confidence: R,
+}
+
+impl<R> Default for MonteCarloConfig<R>
+where
+    R: RealField,
+{
+    fn default() -> Self {
+        Self {
+            runs: 50,
+            steps: 100,
+            confidence: na::convert(0.95),
+        }
+    }
+}
+
+/// The result of [monte_carlo_consistency]
+///
+/// For a consistent filter, the sum over `N` runs of the NEES of a step is
+/// chi-square distributed with `N n` degrees of freedom, for a state of
+/// dimension `n`, so the average NEES lies within the bands at about the
+/// confidence of the steps; likewise for the NIS, with the observation
+/// dimension. An average above the bands means the filter is
+/// overconfident, below them that it is too cautious.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MonteCarloReport<R>
+where
+    R: RealField,
+{
+    /// The number of runs averaged.
+    pub runs: usize,
+    /// The confidence of the bands.
+    pub confidence: R,
+    /// The average NEES over the runs, for each step.
+    pub average_nees: Vec<R>,
+    /// The average NIS over the runs, for each step.
+    pub average_nis: Vec<R>,
+    /// The lower and upper bands of the average NEES.
+    pub nees_bounds: (R, R),
+    /// The lower and upper bands of the average NIS.
+    pub nis_bounds: (R, R),
+}
+
+impl<R> MonteCarloReport<R>
+where
+    R: RealField,
+{
+    /// The fraction of the steps whose average NEES is within its bands.
+    pub fn nees_fraction_within(&self) -> R {
+        fraction_within(&self.average_nees, &self.nees_bounds)
+    }
+
+    /// The fraction of the steps whose average NIS is within its bands.
+    pub fn nis_fraction_within(&self) -> R {
+        fraction_within(&self.average_nis, &self.nis_bounds)
+    }
+
+    /// Whether at least `min_fraction` of the steps have both their average
+    /// NEES and NIS within the bands; the confidence of the bands less a
+    /// margin is a typical choice.
+    pub fn is_consistent(&self, min_fraction: R) -> bool {
+        self.nees_fraction_within() >= min_fraction.clone()
+            && self.nis_fraction_within() >= min_fraction
+    }
+}
+
+fn fraction_within<R: RealField>(values: &[R], bounds: &(R, R)) -> R {
+    let within = values
+        .iter()
+        .filter(|v| **v >= bounds.0 && **v <= bounds.1)
+        .count();
+    na::convert::<f64, R>(within as f64) / na::convert(values.len().max(1) as f64)
+}
+
+fn mean<R: RealField>(values: &[R]) -> R {
+    let sum = values.iter().fold(R::zero(), |a, b| a + b.clone());
+    sum / na::convert(values.len().max(1) as f64)
+}
+
+impl<R> std::fmt::Display for MonteCarloReport<R>
+where
+    R: RealField,
+{
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        writeln!(
+            f,
+            "{} runs of {} steps, {} confidence bands",
+            self.runs,
+            self.average_nees.len(),
+            self.confidence
+        )?;
+        writeln!(
+            f,
+            "NEES: mean {:.3}, bands [{:.3}, {:.3}], {:.1}% of steps within",
+            mean(&self.average_nees),
+            self.nees_bounds.0,
+            self.nees_bounds.1,
+            self.nees_fraction_within() * na::convert(100.0)
+        )?;
+        write!(
+            f,
+            "NIS:  mean {:.3}, bands [{:.3}, {:.3}], {:.1}% of steps within",
+            mean(&self.average_nis),
+            self.nis_bounds.0,
+            self.nis_bounds.1,
+            self.nis_fraction_within() * na::convert(100.0)
+        )
+    }
+}
+
+/// Benchmark the consistency of a filter by Monte Carlo simulation
+///
+/// Each run simulates states and observations with the given models, see
+/// [simulate], and filters the observations with `filter`, whose models may
+/// differ, e.g. to assess a mis-tuned `Q`. The NEES against the simulated
+/// states and the NIS of the innovations are averaged over the runs at each
+/// step, and reported with their chi-square bands. `normal` must return
+/// independent standard normal samples.
+pub fn monte_carlo_consistency<R, N>(
+    transition_model: &dyn TransitionModelLinearNoControl<R>,
+    observation_model: &dyn ObservationModel<R>,
+    filter: &KalmanFilterNoControl<R>,
+    initial_estimate: &StateAndCovariance<R>,
+    config: MonteCarloConfig<R>,
+    normal: &mut N,
+) -> Result<MonteCarloReport<R>, Error>
+where
+    R: RealField,
+    N: FnMut() -> R,
+{
+    let steps = config.steps;
+    let mut average_nees = vec![R::zero(); steps];
+    let mut average_nis = vec![R::zero(); steps];
+    let scale = R::one() / na::convert(config.runs.max(1) as f64);
+    for _ in 0..config.runs {
+        let (states, observations) = simulate(
+            transition_model,
+            observation_model,
+            initial_estimate,
+            steps,
+            normal,
+        );
+        let mut estimate = initial_estimate.clone();
+        for (k, (state, observation)) in states.iter().zip(observations.iter()).enumerate() {
+            let prior = filter.predict(&estimate);
+            let nis = filter
+                .observation_matrix
+                .innovation(&prior, observation)
+                .nis()
+                .map_err(|e| e.with_step(k))?;
+            estimate = filter.step(&estimate, observation)?;
+            let nees = nees(state, &estimate).map_err(|e| e.with_step(k))?;
+            average_nees[k] += nees * scale.clone();
+            average_nis[k] += nis * scale.clone();
+        }
+    }
+    let bounds = |dim: usize| {
+        let (lower, upper) = chi_squared_interval(config.confidence.clone(), config.runs * dim);
+        (lower * scale.clone(), upper * scale.clone())
+    };
+    Ok(MonteCarloReport {
+        runs: config.runs,
+        confidence: config.confidence.clone(),
+        average_nees,
+        average_nis,
+        nees_bounds: bounds(transition_model.state_dim()),
+        nis_bounds: bounds(observation_model.obs_dim()),
+    })
+}
+
+#[test]
+fn test_monte_carlo_consistency() {
+    use crate::test_util::{initial_estimate, ConstantVelocity, Normals, PositionObservation};
+
+    let transition = ConstantVelocity::new(0.1, 1.0);
+    let observation = PositionObservation::new(0.5);
+    let mut normals = Normals::new(43);
+    let mut normal = || normals.sample();
+    let config = MonteCarloConfig {
+        runs: 40,
+        steps: 50,
+        confidence: 0.95,
+    };
+
+    let matched = KalmanFilterNoControl::new(&transition, &observation);
+    let report = monte_carlo_consistency(
+        &transition,
+        &observation,
+        &matched,
+        &initial_estimate(),
+        config,
+        &mut normal,
+    )
+    .unwrap();
+    assert_eq!(report.average_nees.len(), 50);
+    assert!(report.is_consistent(0.8), "{}", report);
+    assert!(report.to_string().starts_with("40 runs of 50 steps"));
+
+    // A filter assuming far less process noise is overconfident.
+    let underestimated = ConstantVelocity::new(0.1, 0.01);
+    let mistuned = KalmanFilterNoControl::new(&underestimated, &observation);
+    let report = monte_carlo_consistency(
+        &transition,
+        &observation,
+        &mistuned,
+        &initial_estimate(),
+        config,
+        &mut normal,
+    )
+    .unwrap();
+    assert!(!report.is_consistent(0.8));
+    assert!(report.average_nees[49] > report.nees_bounds.1);
+}
@@ -0,0 +1,187 @@
+//! Error-state models for loosely coupled GPS/INS navigation
+//!
+//! In loose coupling, a strapdown inertial navigation system (INS)
+//! integrates the IMU at a high rate and the filter estimates the errors of
+//! its solution, which are corrected using GPS position and velocity fixes.
+//! The error state is
+//!
+//! - `δp`, `δv`: position and velocity errors, in the navigation frame,
+//! - `φ`: attitude error, such that the true body-to-navigation rotation is
+//!   `(I + [φ×])` times the INS one,
+//! - and optionally `δb_a`, `δb_g`: errors of the estimated accelerometer
+//!   and gyroscope biases, in the body frame,
+//!
+//! in that order, for 9 or 15 states. The effects of Earth's rotation and
+//! curvature are neglected, which suits short, local trajectories.
+
+use na::{DMatrix, Matrix3, RealField, Vector3};
+use nalgebra as na;
+
+use crate::{DiscretizedTransitionModel, LinearObservationModel};
+
+/// Which error states to estimate
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InsErrorStates {
+    /// Position, velocity and attitude errors.
+    Nine,
+    /// Also the accelerometer and gyroscope bias errors.
+    Fifteen,
+}
+
+impl InsErrorStates {
+    /// The number of states.
+    pub fn dim(&self) -> usize {
+        match self {
+            InsErrorStates::Nine => 9,
+            InsErrorStates::Fifteen => 15,
+        }
+    }
+}
+
+/// Noise densities of an inertial measurement unit
+#[derive(Debug, Clone)]
+pub struct InertialNoise<R>
+where
+    R: RealField,
+{
+    /// Accelerometer white noise density (velocity random walk), e.g. in
+    /// m/s/√s.
+    pub accel_noise: R,
+    /// Gyroscope white noise density (angle random walk), e.g. in rad/√s.
+    pub gyro_noise: R,
+    /// Accelerometer bias random walk density, e.g. in m/s²/√s.
+    pub accel_bias_walk: R,
+    /// Gyroscope bias random walk density, e.g. in rad/s/√s.
+    pub gyro_bias_walk: R,
+}
+
+/// The transition model of the INS errors over an IMU interval `dt`
+///
+/// `attitude` is the INS body-to-navigation rotation matrix and
+/// `specific_force` the measured specific force in the body frame over the
+/// interval. The model is exactly discretized with
+/// [DiscretizedTransitionModel], holding the attitude and specific force
+/// constant over the interval, so it must be rebuilt as they change.
+pub fn ins_error_model<R>(
+    states: InsErrorStates,
+    attitude: &Matrix3<R>,
+    specific_force: &Vector3<R>,
+    dt: R,
+    noise: &InertialNoise<R>,
+) -> DiscretizedTransitionModel<R>
+where
+    R: RealField,
+{
+    let n = states.dim();
+    let identity = Matrix3::<R>::identity();
+    let f_n = attitude * specific_force;
+    let mut A = DMatrix::<R>::zeros(n, n);
+    A.slice_mut((0, 3), (3, 3)).copy_from(&identity);
+    A.slice_mut((3, 6), (3, 3)).copy_from(&-f_n.cross_matrix());
+    let mut Qc = DMatrix::<R>::zeros(n, n);
+    let square = |x: &R| x.clone() * x.clone();
+    Qc.slice_mut((3, 3), (3, 3))
+        .copy_from(&(identity.clone() * square(&noise.accel_noise)));
+    Qc.slice_mut((6, 6), (3, 3))
+        .copy_from(&(identity.clone() * square(&noise.gyro_noise)));
+    if states == InsErrorStates::Fifteen {
+        A.slice_mut((3, 9), (3, 3)).copy_from(&-attitude);
+        A.slice_mut((6, 12), (3, 3)).copy_from(&-attitude);
+        Qc.slice_mut((9, 9), (3, 3))
+            .copy_from(&(identity.clone() * square(&noise.accel_bias_walk)));
+        Qc.slice_mut((12, 12), (3, 3))
+            .copy_from(&(identity.clone() * square(&noise.gyro_bias_walk)));
+    }
+    DiscretizedTransitionModel::new(&A, &Qc, dt)
+}
+
+/// Observation of the position error by a GPS fix, `z = p_gps - p_ins`,
+/// with standard deviation `sigma` per axis.
+pub fn gps_position_observation<R>(states: InsErrorStates, sigma: R) -> LinearObservationModel<R>
+where
+    R: RealField,
+{
+    gps_observation(states, &[(0, sigma)])
+}
+
+/// Observation of the velocity error by a GPS fix, `z = v_gps - v_ins`,
+/// with standard deviation `sigma` per axis.
+pub fn gps_velocity_observation<R>(states: InsErrorStates, sigma: R) -> LinearObservationModel<R>
+where
+    R: RealField,
+{
+    gps_observation(states, &[(3, sigma)])
+}
+
+/// Observation of the position and velocity errors by a GPS fix, with
+/// standard deviations per axis. The observation is the position difference
+/// followed by the velocity difference.
+pub fn gps_position_velocity_observation<R>(
+    states: InsErrorStates,
+    position_sigma: R,
+    velocity_sigma: R,
+) -> LinearObservationModel<R>
+where
+    R: RealField,
+{
+    gps_observation(states, &[(0, position_sigma), (3, velocity_sigma)])
+}
+
+/// Observe blocks of three states starting at the given offsets.
+fn gps_observation<R: RealField>(
+    states: InsErrorStates,
+    blocks: &[(usize, R)],
+) -> LinearObservationModel<R> {
+    let m = 3 * blocks.len();
+    let mut H = DMatrix::zeros(m, states.dim());
+    let mut R = DMatrix::zeros(m, m);
+    for (k, (offset, sigma)) in blocks.iter().enumerate() {
+        for i in 0..3 {
+            H[(3 * k + i, offset + i)] = R::one();
+            R[(3 * k + i, 3 * k + i)] = sigma.clone() * sigma.clone();
+        }
+    }
+    LinearObservationModel::from_matrices(H, R)
+}
+
+#[test]
+fn test_ins_error_model() {
+    use crate::{
+        validate_models, KalmanFilterNoControl, ObservationModel, StateAndCovariance,
+        TransitionModelLinearNoControl,
+    };
+    use na::DVector;
+
+    let noise = InertialNoise {
+        accel_noise: 0.01,
+        gyro_noise: 1e-3,
+        accel_bias_walk: 1e-4,
+        gyro_bias_walk: 1e-5,
+    };
+    let attitude = Matrix3::identity();
+    let specific_force = Vector3::new(0.0, 0.0, -9.81);
+    let dt = 0.01;
+    let transition = ins_error_model(
+        InsErrorStates::Fifteen,
+        &attitude,
+        &specific_force,
+        dt,
+        &noise,
+    );
+    let observation = gps_position_velocity_observation(InsErrorStates::Fifteen, 2.0, 0.1);
+    validate_models(&transition, &observation).unwrap();
+    assert_eq!(observation.obs_dim(), 6);
+
+    // A tilt error about the x axis makes gravity appear as a horizontal
+    // acceleration along y, which integrates into a velocity error.
+    let F = transition.F();
+    approx::assert_relative_eq!(F[(4, 6)], 9.81 * dt, epsilon = 1e-6);
+    approx::assert_relative_eq!(F[(0, 3)], dt, epsilon = 1e-12);
+
+    // With GPS fixes, the filter runs and the position error becomes known.
+    let initial = StateAndCovariance::new(DVector::zeros(15), DMatrix::identity(15, 15));
+    let kf = KalmanFilterNoControl::new(&transition, &observation);
+    let fix = DVector::from_vec(vec![1.0, -0.5, 0.2, 0.0, 0.0, 0.0]);
+    let estimate = kf.step(&initial, &fix).unwrap();
+    assert!(estimate.covariance()[(0, 0)] < 1.0);
+}
@@ -0,0 +1,332 @@
+//! Non-linear process models and the extended Kalman filter (EKF)
+
+use alloc::borrow::Cow;
+
+use na::{DMatrix, DVector, RealField};
+use nalgebra as na;
+
+use crate::{
+    is_nan, CovarianceUpdateMethod, Error, ObservationModel, StateAndCovariance,
+    TransitionModelLinearNoControl,
+};
+
+/// A model of process dynamics, potentially non-linear, with no control
+/// inputs
+///
+/// The state evolves as `x' = f(x) + w` with process noise `w` of covariance
+/// `Q`. A [TransitionModelLinearNoControl] can be used wherever a
+/// `NonlinearTransitionModel` is expected by wrapping it in [Linearized].
+pub trait NonlinearTransitionModel<R>
+where
+    R: RealField,
+{
+    /// Propagate the state through the process function, `f(x)`.
+    fn f(&self, state: &DVector<R>) -> DVector<R>;
+
+    /// Get the Jacobian of the process function evaluated at `state`.
+    fn jacobian_at(&self, state: &DVector<R>) -> DMatrix<R>;
+
+    /// Get the process covariance, `Q`.
+    fn Q(&self) -> &DMatrix<R>;
+
+    /// Predict new state from previous estimate by linearizing about it.
+    ///
+    /// This is the prediction step of the extended Kalman filter.
+    fn propagate(&self, previous_estimate: &StateAndCovariance<R>) -> StateAndCovariance<R> {
+        let F = self.jacobian_at(previous_estimate.state());
+        let state = self.f(previous_estimate.state());
+        let covariance = (&F * previous_estimate.covariance() * F.transpose()) + self.Q();
+        StateAndCovariance::new(state, covariance)
+    }
+}
+
+/// Adapter using a [TransitionModelLinearNoControl] as a
+/// [NonlinearTransitionModel] with `f(x) = F x`
+///
+/// Prediction through the adapter is the same as
+/// [predict](TransitionModelLinearNoControl::predict) on the wrapped model.
+pub struct Linearized<'a, T: ?Sized>(pub &'a T);
+
+impl<'a, R, T> NonlinearTransitionModel<R> for Linearized<'a, T>
+where
+    R: RealField,
+    T: TransitionModelLinearNoControl<R> + ?Sized,
+{
+    fn f(&self, state: &DVector<R>) -> DVector<R> {
+        self.0.F() * state
+    }
+    fn jacobian_at(&self, _state: &DVector<R>) -> DMatrix<R> {
+        self.0.F().clone()
+    }
+    fn Q(&self) -> &DMatrix<R> {
+        self.0.Q()
+    }
+    fn propagate(&self, previous_estimate: &StateAndCovariance<R>) -> StateAndCovariance<R> {
+        self.0.predict(previous_estimate)
+    }
+}
+
+/// An extended Kalman filter with no control inputs
+///
+/// The prediction step linearizes the [NonlinearTransitionModel] about the
+/// previous estimate. For a non-linear observation model, the
+/// [ObservationModel] implementation must predict observations with the
+/// non-linear function and implement
+/// [jacobian_at](ObservationModel::jacobian_at), which the update evaluates
+/// at the prior.
+pub struct ExtendedKalmanFilter<'a, R>
+where
+    R: RealField,
+{
+    transition_model: &'a dyn NonlinearTransitionModel<R>,
+    observation_model: &'a dyn ObservationModel<R>,
+}
+
+impl<'a, R> ExtendedKalmanFilter<'a, R>
+where
+    R: RealField,
+{
+    /// Initialize a new `ExtendedKalmanFilter` struct.
+    pub fn new(
+        transition_model: &'a dyn NonlinearTransitionModel<R>,
+        observation_model: &'a dyn ObservationModel<R>,
+    ) -> Self {
+        Self {
+            transition_model,
+            observation_model,
+        }
+    }
+
+    /// Perform prediction and update steps with default values
+    ///
+    /// If any component of the observation is NaN (not a number), the
+    /// observation will not be used but rather the prior will be returned as
+    /// the posterior without performing the update step.
+    pub fn step(
+        &self,
+        previous_estimate: &StateAndCovariance<R>,
+        observation: &DVector<R>,
+    ) -> Result<StateAndCovariance<R>, Error> {
+        self.step_with_options(
+            previous_estimate,
+            observation,
+            CovarianceUpdateMethod::JosephForm,
+        )
+    }
+
+    /// Perform prediction and update steps with the specified covariance
+    /// update method
+    pub fn step_with_options(
+        &self,
+        previous_estimate: &StateAndCovariance<R>,
+        observation: &DVector<R>,
+        covariance_update_method: CovarianceUpdateMethod,
+    ) -> Result<StateAndCovariance<R>, Error> {
+        let prior = self.transition_model.propagate(previous_estimate);
+        if observation.iter().any(|x| is_nan(x.clone())) {
+            Ok(prior)
+        } else {
+            self.observation_model
+                .update(&prior, observation, covariance_update_method)
+        }
+    }
+}
+
+/// Compute the Jacobian of `f` at `x` by central finite differences
+///
+/// Each component of `x` is perturbed by `eps` in either direction. A
+/// reasonable choice of `eps` is the square root of machine epsilon times the
+/// typical magnitude of the state components.
+pub fn numerical_jacobian<R, F>(f: F, x: &DVector<R>, eps: R) -> DMatrix<R>
+where
+    R: RealField,
+    F: Fn(&DVector<R>) -> DVector<R>,
+{
+    let two: R = na::convert(2.0);
+    let n = x.nrows();
+    if n == 0 {
+        return DMatrix::zeros(f(x).nrows(), 0);
+    }
+    let mut jacobian = DMatrix::zeros(0, 0);
+    for i in 0..n {
+        let mut forward = x.clone();
+        forward[i] += eps.clone();
+        let mut backward = x.clone();
+        backward[i] -= eps.clone();
+        let column = (f(&forward) - f(&backward)) / (two.clone() * eps.clone());
+        if i == 0 {
+            jacobian = DMatrix::zeros(column.nrows(), n);
+        }
+        jacobian.set_column(i, &column);
+    }
+    jacobian
+}
+
+/// A [NonlinearTransitionModel] given only by its process function, with the
+/// Jacobian computed by [numerical_jacobian]
+pub struct NumericalTransitionModel<R, F>
+where
+    R: RealField,
+    F: Fn(&DVector<R>) -> DVector<R>,
+{
+    f: F,
+    Q: DMatrix<R>,
+    eps: R,
+}
+
+impl<R, F> NumericalTransitionModel<R, F>
+where
+    R: RealField,
+    F: Fn(&DVector<R>) -> DVector<R>,
+{
+    /// Create a new model from the process function `f`, the process
+    /// covariance `Q` and the finite difference step `eps`.
+    pub fn new(f: F, Q: DMatrix<R>, eps: R) -> Self {
+        Self { f, Q, eps }
+    }
+}
+
+impl<R, F> NonlinearTransitionModel<R> for NumericalTransitionModel<R, F>
+where
+    R: RealField,
+    F: Fn(&DVector<R>) -> DVector<R>,
+{
+    fn f(&self, state: &DVector<R>) -> DVector<R> {
+        (self.f)(state)
+    }
+    fn jacobian_at(&self, state: &DVector<R>) -> DMatrix<R> {
+        numerical_jacobian(&self.f, state, self.eps.clone())
+    }
+    fn Q(&self) -> &DMatrix<R> {
+        &self.Q
+    }
+}
+
+/// An [ObservationModel] given only by its observation function, with its
+/// Jacobian computed by [numerical_jacobian]
+///
+/// Updates linearize about the prior through
+/// [jacobian_at](ObservationModel::jacobian_at). `H` is the linearization at
+/// the nominal state given to [new](Self::new).
+pub struct NumericalObservationModel<R, F>
+where
+    R: RealField,
+    F: Fn(&DVector<R>) -> DVector<R>,
+{
+    h: F,
+    H: DMatrix<R>,
+    R: DMatrix<R>,
+    eps: R,
+}
+
+impl<R, F> NumericalObservationModel<R, F>
+where
+    R: RealField,
+    F: Fn(&DVector<R>) -> DVector<R>,
+{
+    /// Create a new model from the observation function `h`, the observation
+    /// noise covariance `R` and the finite difference step `eps`, with `H`
+    /// linearized at the nominal `state`.
+    pub fn new(h: F, R: DMatrix<R>, eps: R, state: &DVector<R>) -> Self {
+        let H = numerical_jacobian(&h, state, eps.clone());
+        Self { h, H, R, eps }
+    }
+}
+
+impl<R, F> ObservationModel<R> for NumericalObservationModel<R, F>
+where
+    R: RealField,
+    F: Fn(&DVector<R>) -> DVector<R>,
+{
+    fn predict_observation(&self, state: &DVector<R>) -> DVector<R> {
+        (self.h)(state)
+    }
+    fn H(&self) -> &DMatrix<R> {
+        &self.H
+    }
+    fn jacobian_at(&self, state: &DVector<R>) -> Cow<'_, DMatrix<R>> {
+        Cow::Owned(numerical_jacobian(&self.h, state, self.eps.clone()))
+    }
+    fn R(&self) -> &DMatrix<R> {
+        &self.R
+    }
+    fn state_dim(&self) -> usize {
+        self.H.ncols()
+    }
+    fn obs_dim(&self) -> usize {
+        self.H.nrows()
+    }
+}
+
+#[test]
+fn test_linear_model_is_nonlinear_model() {
+    use crate::test_util::{initial_estimate, ConstantVelocity};
+
+    let model = ConstantVelocity::new(0.5, 1.0);
+    let linear = TransitionModelLinearNoControl::predict(&model, &initial_estimate());
+    let nonlinear = Linearized(&model).propagate(&initial_estimate());
+    approx::assert_relative_eq!(linear.state(), nonlinear.state());
+    approx::assert_relative_eq!(linear.covariance(), nonlinear.covariance());
+
+    // Pendulum, x = [angle, angular velocity].
+    struct Pendulum {
+        Q: DMatrix<f64>,
+    }
+    impl NonlinearTransitionModel<f64> for Pendulum {
+        fn f(&self, x: &DVector<f64>) -> DVector<f64> {
+            DVector::from_vec(vec![x[0] + 0.1 * x[1], x[1] - 0.1 * x[0].sin()])
+        }
+        fn jacobian_at(&self, x: &DVector<f64>) -> DMatrix<f64> {
+            DMatrix::from_row_slice(2, 2, &[1.0, 0.1, -0.1 * x[0].cos(), 1.0])
+        }
+        fn Q(&self) -> &DMatrix<f64> {
+            &self.Q
+        }
+    }
+    let pendulum = Pendulum {
+        Q: DMatrix::identity(2, 2) * 1e-4,
+    };
+    let predicted = pendulum.propagate(&initial_estimate());
+    approx::assert_relative_eq!(predicted.state()[1], 1.0);
+    approx::assert_relative_eq!(predicted.covariance()[(1, 0)], 0.0);
+}
+
+#[test]
+fn test_numerical_jacobian() {
+    let f = |x: &DVector<f64>| DVector::from_vec(vec![x[0] * x[1], x[0].sin(), x[1].exp()]);
+    let x = DVector::from_vec(vec![0.3f64, -1.2]);
+    let expected = DMatrix::from_row_slice(3, 2, &[x[1], x[0], x[0].cos(), 0.0, 0.0, x[1].exp()]);
+    approx::assert_relative_eq!(numerical_jacobian(f, &x, 1e-6), expected, epsilon = 1e-8);
+
+    let range = NumericalObservationModel::new(
+        |x: &DVector<f64>| DVector::from_element(1, x.norm()),
+        DMatrix::identity(1, 1),
+        1e-6,
+        &DVector::from_vec(vec![3.0, 4.0]),
+    );
+    approx::assert_relative_eq!(
+        range.H(),
+        &DMatrix::from_row_slice(1, 2, &[0.6, 0.8]),
+        epsilon = 1e-8
+    );
+
+    // The EKF linearizes the range about the prior at (0, 5), not about the
+    // nominal (3, 4): with H = [0, 1], P = I and R = 1, the gain is
+    // [0, 1/2], so a range of 6 moves the prior to (0, 5.5) and halves the
+    // variance along y only.
+    let identity =
+        NumericalTransitionModel::new(|x: &DVector<f64>| x.clone(), DMatrix::zeros(2, 2), 1e-6);
+    let ekf = ExtendedKalmanFilter::new(&identity, &range);
+    let prior = StateAndCovariance::new(DVector::from_vec(vec![0.0, 5.0]), DMatrix::identity(2, 2));
+    let posterior = ekf.step(&prior, &DVector::from_element(1, 6.0)).unwrap();
+    approx::assert_relative_eq!(
+        posterior.state(),
+        &DVector::from_vec(vec![0.0, 5.5]),
+        epsilon = 1e-8
+    );
+    approx::assert_relative_eq!(
+        posterior.covariance(),
+        &DMatrix::from_diagonal(&DVector::from_vec(vec![1.0, 0.5])),
+        epsilon = 1e-8
+    );
+}
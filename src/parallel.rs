@@ -0,0 +1,424 @@
+//! Temporally parallel Kalman filtering and RTS smoothing
+//!
+//! Following Särkkä and García-Fernández (2021), each time step is
+//! represented by an element, and the filtered (or smoothed) estimates are
+//! the prefix (or suffix) combinations of the elements under an associative
+//! operator. These are computed by a parallel scan, which has a span of
+//! `O(log N)` steps for a series of length `N`, rather than the `O(N)` of the
+//! sequential passes. With the `parallel` feature, the scan runs on the
+//! rayon thread pool; without it, the same scan runs on the calling thread.
+
+use alloc::borrow::Cow;
+
+use na::{DMatrix, DVector, RealField};
+use nalgebra as na;
+
+use crate::{is_nan, linalg, Error, ErrorKind, KalmanFilterNoControl, StateAndCovariance};
+
+/// Evaluate `f` for each of `0..n`, in parallel with the `parallel` feature.
+#[cfg(feature = "parallel")]
+fn map_indices<T, F>(n: usize, f: F) -> Vec<T>
+where
+    T: Send,
+    F: Fn(usize) -> T + Sync + Send,
+{
+    use rayon::prelude::*;
+    (0..n).into_par_iter().map(f).collect()
+}
+
+/// Evaluate `f` for each of `0..n`, in parallel with the `parallel` feature.
+#[cfg(not(feature = "parallel"))]
+fn map_indices<T, F>(n: usize, f: F) -> Vec<T>
+where
+    F: Fn(usize) -> T,
+{
+    (0..n).map(f).collect()
+}
+
+/// The inclusive prefix scan of `elements` under the associative `op`
+///
+/// Adjacent pairs are combined, the pairs are scanned recursively, and the
+/// remaining prefixes are filled in, each stage being evaluated in parallel.
+fn prefix_scan<T, F>(elements: &[T], op: &F) -> Vec<T>
+where
+    T: Clone + Send + Sync,
+    F: Fn(&T, &T) -> T + Sync,
+{
+    let n = elements.len();
+    if n <= 1 {
+        return elements.to_vec();
+    }
+    let pairs = map_indices(n / 2, |i| op(&elements[2 * i], &elements[2 * i + 1]));
+    let scanned = prefix_scan(&pairs, op);
+    map_indices(n, |k| {
+        if k == 0 {
+            elements[0].clone()
+        } else if k % 2 == 1 {
+            scanned[k / 2].clone()
+        } else {
+            op(&scanned[k / 2 - 1], &elements[k])
+        }
+    })
+}
+
+/// The element of the filtering scan for one step, `(A, b, C, η, J)`
+#[derive(Clone)]
+struct FilterElement<R: RealField> {
+    A: DMatrix<R>,
+    b: DVector<R>,
+    C: DMatrix<R>,
+    eta: DVector<R>,
+    J: DMatrix<R>,
+}
+
+/// Combine the filtering elements of an earlier and a later step.
+fn combine_filter<R: RealField>(
+    i: &Option<FilterElement<R>>,
+    j: &Option<FilterElement<R>>,
+) -> Option<FilterElement<R>> {
+    let (i, j) = (i.as_ref()?, j.as_ref()?);
+    let n = i.b.nrows();
+    let identity = DMatrix::<R>::identity(n, n);
+    let M = &j.A * (&identity + &i.C * &j.J).try_inverse()?;
+    let N = i.A.transpose() * (&identity + &j.J * &i.C).try_inverse()?;
+    Some(FilterElement {
+        A: &M * &i.A,
+        b: &M * (&i.b + &i.C * &j.eta) + &j.b,
+        C: linalg::matmul3(&M, &i.C, &j.A.transpose()) + &j.C,
+        eta: &N * (&j.eta - &j.J * &i.b) + &i.eta,
+        J: linalg::matmul3(&N, &j.J, &i.A) + &i.J,
+    })
+}
+
+/// The element of the smoothing scan for one step, `(E, g, L)`
+#[derive(Clone)]
+struct SmootherElement<R: RealField> {
+    E: DMatrix<R>,
+    g: DVector<R>,
+    L: DMatrix<R>,
+}
+
+/// Combine the smoothing elements of an earlier and a later step.
+fn combine_smoother<R: RealField>(
+    i: &SmootherElement<R>,
+    j: &SmootherElement<R>,
+) -> SmootherElement<R> {
+    SmootherElement {
+        E: &i.E * &j.E,
+        g: &i.E * &j.g + &i.g,
+        L: linalg::matmul3(&i.E, &j.L, &i.E.transpose()) + &i.L,
+    }
+}
+
+fn invert<R: RealField>(matrix: &DMatrix<R>) -> Result<DMatrix<R>, Error> {
+    linalg::cholesky_inverse(matrix)
+        .ok_or_else(|| Error::from(ErrorKind::CovarianceNotPositiveSemiDefinite))
+}
+
+impl<'a, R> KalmanFilterNoControl<'a, R>
+where
+    R: RealField,
+{
+    /// Kalman filter by a parallel scan
+    ///
+    /// This gives the same state estimates as
+    /// [filter](struct.KalmanFilterNoControl.html#method.filter), up to
+    /// round-off, but the steps are combined by a parallel scan (Särkkä and
+    /// García-Fernández, 2021) with a span of `O(log N)` steps for `N`
+    /// observations. With the `parallel` feature, the scan runs on the rayon
+    /// thread pool. The failure policy is not applied.
+    ///
+    /// The elements are fixed in advance only for linear observation models,
+    /// `y = H x`, without a fading-memory factor. A model whose
+    /// [jacobian_at](crate::ObservationModel::jacobian_at) is not its `H`, i.e. a
+    /// non-linear model, is linearized at each prior, and with fading memory
+    /// the prior covariance of each step depends on the previous one, so in
+    /// both cases the sequential
+    /// [filter](struct.KalmanFilterNoControl.html#method.filter) is used.
+    ///
+    /// If any observation has a NaN component, it is treated as missing.
+    /// Errors give the index of the failed step.
+    pub fn filter_parallel(
+        &self,
+        initial_estimate: &StateAndCovariance<R>,
+        observations: &[DVector<R>],
+    ) -> Result<Vec<StateAndCovariance<R>>, Error> {
+        self.check_dimensions(initial_estimate, None)?;
+        let os = self.observation_matrix.obs_dim();
+        for (k, observation) in observations.iter().enumerate() {
+            crate::check_dimension("observation", (os, 1), observation.shape())
+                .map_err(|e| e.with_step(k))?;
+        }
+        let linear = matches!(
+            self.observation_matrix
+                .jacobian_at(initial_estimate.state()),
+            Cow::Borrowed(_)
+        );
+        if self.has_fading_memory() || !linear {
+            return self.filter(initial_estimate, observations);
+        }
+        let F = self.transition_model.F();
+        let Q = self.transition_model.Q();
+        let H = self.observation_matrix.H();
+        let HT = &*self.observation_matrix.HT();
+        let R = self.observation_matrix.R();
+        let n = F.nrows();
+        let identity = DMatrix::<R>::identity(n, n);
+        let prior = self.transition_model.predict(initial_estimate);
+
+        // The terms common to every step after the first.
+        let S_inv = invert(&(linalg::matmul3(H, Q, HT) + R))?;
+        let K = linalg::matmul3(Q, HT, &S_inv);
+        let I_KH = &identity - &K * H;
+        let A = &I_KH * F;
+        let C = &I_KH * Q;
+        let FT_HT_S_inv = linalg::matmul3(&F.transpose(), HT, &S_inv);
+        let J = &FT_HT_S_inv * H * F;
+
+        let mut elements = Vec::with_capacity(observations.len());
+        for (k, observation) in observations.iter().enumerate() {
+            let missing = observation.iter().any(|x| is_nan(x.clone()));
+            let element = match (k, missing) {
+                (0, true) => FilterElement {
+                    A: DMatrix::zeros(n, n),
+                    b: prior.state().clone(),
+                    C: prior.covariance().clone(),
+                    eta: DVector::zeros(n),
+                    J: DMatrix::zeros(n, n),
+                },
+                (0, false) => {
+                    let P = prior.covariance();
+                    let S = linalg::matmul3(H, P, HT) + R;
+                    let S_inv = invert(&S).map_err(|e| e.with_step(0))?;
+                    let K = linalg::matmul3(P, HT, &S_inv);
+                    let residual = observation - H * prior.state();
+                    FilterElement {
+                        A: DMatrix::zeros(n, n),
+                        b: prior.state() + &K * residual,
+                        C: P - linalg::matmul3(&K, &S, &K.transpose()),
+                        eta: DVector::zeros(n),
+                        J: DMatrix::zeros(n, n),
+                    }
+                }
+                (_, true) => FilterElement {
+                    A: F.clone(),
+                    b: DVector::zeros(n),
+                    C: Q.clone(),
+                    eta: DVector::zeros(n),
+                    J: DMatrix::zeros(n, n),
+                },
+                (_, false) => FilterElement {
+                    A: A.clone(),
+                    b: &K * observation,
+                    C: C.clone(),
+                    eta: &FT_HT_S_inv * observation,
+                    J: J.clone(),
+                },
+            };
+            elements.push(Some(element));
+        }
+
+        prefix_scan(&elements, &combine_filter)
+            .into_iter()
+            .map(|element| match element {
+                Some(element) => Ok(StateAndCovariance::new(
+                    element.b,
+                    element.C.symmetric_part(),
+                )),
+                None => Err(ErrorKind::SingularMatrix.into()),
+            })
+            .collect()
+    }
+
+    /// Rauch-Tung-Striebel (RTS) smoother by parallel scans
+    ///
+    /// This gives the same state estimates as
+    /// [smooth](struct.KalmanFilterNoControl.html#method.smooth), up to
+    /// round-off. Both the filtering and the smoothing pass are parallel
+    /// scans, see
+    /// [filter_parallel](struct.KalmanFilterNoControl.html#method.filter_parallel),
+    /// which falls back to the sequential filter for a non-linear
+    /// observation model; the smoothing scan depends only on the transition
+    /// model. With a fading-memory factor, the sequential
+    /// [smooth](struct.KalmanFilterNoControl.html#method.smooth) is used.
+    pub fn smooth_parallel(
+        &self,
+        initial_estimate: &StateAndCovariance<R>,
+        observations: &[DVector<R>],
+    ) -> Result<Vec<StateAndCovariance<R>>, Error> {
+        if self.has_fading_memory() {
+            return self.smooth(initial_estimate, observations);
+        }
+        let filtered = self.filter_parallel(initial_estimate, observations)?;
+        let F = self.transition_model.F();
+        let FT = &*self.transition_model.FT();
+        let Q = self.transition_model.Q();
+        let last = filtered.len().saturating_sub(1);
+        let mut elements = Vec::with_capacity(filtered.len());
+        for (k, estimate) in filtered.iter().enumerate() {
+            let (x, P) = (estimate.state(), estimate.covariance());
+            let element = if k == last {
+                let n = x.nrows();
+                SmootherElement {
+                    E: DMatrix::zeros(n, n),
+                    g: x.clone(),
+                    L: P.clone(),
+                }
+            } else {
+                let prior_covariance = linalg::matmul3(F, P, FT) + Q;
+                let inverse = invert(&prior_covariance).map_err(|e| e.with_step(k))?;
+                let E = linalg::matmul3(P, FT, &inverse);
+                let EF = &E * F;
+                SmootherElement {
+                    g: x - &EF * x,
+                    L: P - EF * P,
+                    E,
+                }
+            };
+            elements.push(element);
+        }
+
+        // The smoothed estimates are the suffix combinations, found by
+        // scanning the reversed elements with the operator reversed.
+        elements.reverse();
+        let mut scanned = prefix_scan(&elements, &|later, earlier| {
+            combine_smoother(earlier, later)
+        });
+        scanned.reverse();
+        Ok(scanned
+            .into_iter()
+            .map(|element| StateAndCovariance::new(element.g, element.L.symmetric_part()))
+            .collect())
+    }
+}
+
+#[test]
+fn test_parallel_scan() {
+    use crate::test_util::{
+        initial_estimate, simulate_positions, ConstantVelocity, PositionObservation,
+    };
+
+    let transition = ConstantVelocity::new(0.1, 1.0);
+    let observation = PositionObservation::new(0.5);
+    let kf = KalmanFilterNoControl::new(&transition, &observation);
+    let mut observations = simulate_positions(37, 0.1, 1.0, 0.5, 12);
+    observations[0] = DVector::from_element(1, f64::NAN);
+    observations[20] = DVector::from_element(1, f64::NAN);
+
+    let expected = kf.filter(&initial_estimate(), &observations).unwrap();
+    let actual = kf
+        .filter_parallel(&initial_estimate(), &observations)
+        .unwrap();
+    for (a, e) in actual.iter().zip(expected.iter()) {
+        approx::assert_relative_eq!(a, e, epsilon = 1e-9);
+    }
+
+    let expected = kf.smooth(&initial_estimate(), &observations).unwrap();
+    let actual = kf
+        .smooth_parallel(&initial_estimate(), &observations)
+        .unwrap();
+    assert_eq!(actual.len(), expected.len());
+    for (a, e) in actual.iter().zip(expected.iter()) {
+        approx::assert_relative_eq!(a, e, epsilon = 1e-9);
+    }
+}
+
+#[test]
+fn test_parallel_nonlinear_fallback() {
+    use crate::nonlinear::NumericalObservationModel;
+    use crate::test_util::{initial_estimate, simulate_positions, ConstantVelocity};
+
+    // A non-linear observation of the position is filtered sequentially,
+    // linearized at each prior.
+    let transition = ConstantVelocity::new(0.1, 1.0);
+    let observation = NumericalObservationModel::new(
+        |x: &DVector<f64>| DVector::from_element(1, x[0] + 0.1 * x[0] * x[0]),
+        DMatrix::from_element(1, 1, 0.5),
+        1e-7,
+        &DVector::zeros(2),
+    );
+    let kf = KalmanFilterNoControl::new(&transition, &observation);
+    let observations = simulate_positions(20, 0.1, 1.0, 0.5, 5);
+    let expected = kf.filter(&initial_estimate(), &observations).unwrap();
+    let actual = kf
+        .filter_parallel(&initial_estimate(), &observations)
+        .unwrap();
+    assert_eq!(actual, expected);
+    let expected = kf.smooth(&initial_estimate(), &observations).unwrap();
+    let actual = kf
+        .smooth_parallel(&initial_estimate(), &observations)
+        .unwrap();
+    for (a, e) in actual.iter().zip(expected.iter()) {
+        approx::assert_relative_eq!(a, e, epsilon = 1e-9);
+    }
+
+    // Observations of the wrong length are rejected, with their step.
+    let mut observations = observations;
+    observations[3] = DVector::zeros(2);
+    let err = kf
+        .filter_parallel(&initial_estimate(), &observations)
+        .unwrap_err();
+    assert!(matches!(
+        err.kind(),
+        ErrorKind::DimensionMismatch {
+            expected: (1, 1),
+            actual: (2, 1),
+            matrix: "observation"
+        }
+    ));
+    assert_eq!(err.step(), Some(3));
+}
+
+#[test]
+fn test_prefix_scan_pairing() {
+    // Under concatenation, which is associative but not commutative, every
+    // prefix holds the elements in order, whether or not an element is left
+    // unpaired at each level of the scan.
+    let concat = |a: &Vec<usize>, b: &Vec<usize>| [a.as_slice(), b.as_slice()].concat();
+    for n in 0..=17 {
+        let elements: Vec<Vec<usize>> = (0..n).map(|k| vec![k]).collect();
+        let scanned = prefix_scan(&elements, &concat);
+        assert_eq!(scanned.len(), n);
+        for (k, prefix) in scanned.iter().enumerate() {
+            assert_eq!(prefix, &(0..=k).collect::<Vec<_>>());
+        }
+    }
+}
+
+#[test]
+fn test_parallel_short_series() {
+    use crate::test_util::{
+        initial_estimate, simulate_positions, ConstantVelocity, PositionObservation,
+    };
+
+    // Series of every length up to a few levels of the scan, with gaps at
+    // both ends, give the sequential estimates.
+    let transition = ConstantVelocity::new(0.1, 1.0);
+    let observation = PositionObservation::new(0.5);
+    let kf = KalmanFilterNoControl::new(&transition, &observation);
+    let mut all = simulate_positions(9, 0.1, 1.0, 0.5, 17);
+    all[0] = DVector::from_element(1, f64::NAN);
+    for n in 1..=all.len() {
+        let mut observations = all[..n].to_vec();
+        observations[n - 1] = DVector::from_element(1, f64::NAN);
+        let expected = kf.filter(&initial_estimate(), &observations).unwrap();
+        let actual = kf
+            .filter_parallel(&initial_estimate(), &observations)
+            .unwrap();
+        approx::assert_relative_eq!(actual.as_slice(), expected.as_slice(), epsilon = 1e-9);
+        let expected = kf.smooth(&initial_estimate(), &observations).unwrap();
+        let actual = kf
+            .smooth_parallel(&initial_estimate(), &observations)
+            .unwrap();
+        approx::assert_relative_eq!(actual.as_slice(), expected.as_slice(), epsilon = 1e-9);
+    }
+
+    assert!(kf
+        .filter_parallel(&initial_estimate(), &[])
+        .unwrap()
+        .is_empty());
+    assert!(kf
+        .smooth_parallel(&initial_estimate(), &[])
+        .unwrap()
+        .is_empty());
+}
@@ -0,0 +1,533 @@
+//! Particle filters for non-linear, non-Gaussian posteriors
+//!
+//! The posterior is represented by weighted samples, or particles, rather
+//! than a mean and covariance, so that multimodal and strongly skewed
+//! distributions are tracked. The process and observation noise are the
+//! Gaussians of the [NonlinearTransitionModel] and [ObservationModel], as
+//! for the other filters in this crate.
+//!
+//! As with [simulate](crate::simulate), random numbers are drawn by
+//! closures passed by the caller, which keeps the choice of generator with
+//! the caller.
+
+use alloc::borrow::Cow;
+
+use na::{DMatrix, DVector, RealField};
+use nalgebra as na;
+
+use crate::simulate::psd_sqrt;
+use crate::switching::normalize_log_weights;
+use crate::{
+    is_nan, sample_gaussian, Error, ErrorKind, NonlinearTransitionModel, ObservationModel,
+    StateAndCovariance,
+};
+
+/// A weighted set of particles
+#[derive(Debug, Clone)]
+pub struct ParticleSet<R>
+where
+    R: RealField,
+{
+    particles: Vec<DVector<R>>,
+    weights: DVector<R>,
+}
+
+impl<R> ParticleSet<R>
+where
+    R: RealField,
+{
+    /// Create a new set from particles and their weights, which are
+    /// normalized to sum to one.
+    pub fn new(particles: Vec<DVector<R>>, weights: DVector<R>) -> Self {
+        assert_eq!(particles.len(), weights.nrows());
+        let total = weights.sum();
+        Self {
+            particles,
+            weights: weights / total,
+        }
+    }
+
+    /// Draw `n` equally weighted particles from a Gaussian estimate.
+    /// `normal` must return independent standard normal samples.
+    pub fn from_gaussian<N>(estimate: &StateAndCovariance<R>, n: usize, normal: &mut N) -> Self
+    where
+        N: FnMut() -> R,
+    {
+        let particles = (0..n).map(|_| sample_gaussian(estimate, normal)).collect();
+        Self::new(particles, DVector::from_element(n, R::one()))
+    }
+
+    /// The particles.
+    pub fn particles(&self) -> &[DVector<R>] {
+        &self.particles
+    }
+
+    /// The normalized weights.
+    pub fn weights(&self) -> &DVector<R> {
+        &self.weights
+    }
+
+    /// The effective sample size, `1 / Σ w_i²`, which is the number of
+    /// particles for equal weights and one if a single particle has all the
+    /// weight.
+    pub fn effective_sample_size(&self) -> R {
+        R::one() / self.weights.norm_squared()
+    }
+
+    /// The weighted mean and covariance of the particles.
+    pub fn estimate(&self) -> StateAndCovariance<R> {
+        let n = self.particles[0].nrows();
+        let mut mean = DVector::zeros(n);
+        for (w, particle) in self.weights.iter().zip(&self.particles) {
+            mean += particle * w.clone();
+        }
+        let mut covariance = DMatrix::zeros(n, n);
+        for (w, particle) in self.weights.iter().zip(&self.particles) {
+            let spread = particle - &mean;
+            covariance += &spread * spread.transpose() * w.clone();
+        }
+        StateAndCovariance::new(mean, covariance)
+    }
+}
+
+/// How particles are drawn in proportion to their weights when resampling
+///
+/// All schemes are unbiased. They differ in the variance they add, which is
+/// highest for multinomial resampling, and in the number of uniform random
+/// numbers they use.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ResamplingScheme {
+    /// Draw each particle independently, using `n` uniform samples.
+    Multinomial,
+    /// Draw one particle from each of `n` equal strata of the cumulative
+    /// weights, using `n` uniform samples.
+    Stratified,
+    /// As stratified, but with the same offset in every stratum, using a
+    /// single uniform sample.
+    #[default]
+    Systematic,
+    /// Keep `floor(n w_i)` copies of each particle and draw the remainder
+    /// by multinomial resampling of the residual weights.
+    Residual,
+}
+
+/// When and how a [ParticleFilter] resamples
+#[derive(Debug, Clone, PartialEq)]
+pub struct ResamplingConfig<R>
+where
+    R: RealField,
+{
+    /// The resampling scheme.
+    pub scheme: ResamplingScheme,
+    /// Resample when the effective sample size falls below this fraction
+    /// of the number of particles. One resamples at every step and zero
+    /// never.
+    pub ess_threshold: R,
+}
+
+impl<R> Default for ResamplingConfig<R>
+where
+    R: RealField,
+{
+    /// Systematic resampling when the effective sample size falls below
+    /// half the number of particles.
+    fn default() -> Self {
+        Self {
+            scheme: ResamplingScheme::Systematic,
+            ess_threshold: na::convert(0.5),
+        }
+    }
+}
+
+/// The indices of `n` particles drawn with probabilities `weights`, which
+/// sum to one.
+fn resample<R, U>(
+    scheme: ResamplingScheme,
+    weights: &DVector<R>,
+    n: usize,
+    uniform: &mut U,
+) -> Vec<usize>
+where
+    R: RealField,
+    U: FnMut() -> R,
+{
+    let n_r: R = na::convert(n as f64);
+    match scheme {
+        ResamplingScheme::Multinomial => {
+            let mut targets: Vec<R> = (0..n).map(|_| uniform()).collect();
+            targets.sort_by(|a, b| a.partial_cmp(b).unwrap_or(core::cmp::Ordering::Equal));
+            select(weights, targets)
+        }
+        ResamplingScheme::Stratified => {
+            let targets = (0..n)
+                .map(|i| (na::convert::<f64, R>(i as f64) + uniform()) / n_r.clone())
+                .collect();
+            select(weights, targets)
+        }
+        ResamplingScheme::Systematic => {
+            let u = uniform();
+            let targets = (0..n)
+                .map(|i| (na::convert::<f64, R>(i as f64) + u.clone()) / n_r.clone())
+                .collect();
+            select(weights, targets)
+        }
+        ResamplingScheme::Residual => {
+            let mut indices = Vec::with_capacity(n);
+            let mut residuals = weights * n_r;
+            for (i, r) in residuals.iter_mut().enumerate() {
+                let copies = r.clone().floor();
+                *r -= copies.clone();
+                let copies: f64 = na::try_convert(copies).unwrap_or(0.0);
+                indices.extend(core::iter::repeat_n(i, copies as usize));
+            }
+            let remaining = n - indices.len();
+            if remaining > 0 {
+                let total = residuals.sum();
+                indices.extend(resample(
+                    ResamplingScheme::Multinomial,
+                    &(residuals / total),
+                    remaining,
+                    uniform,
+                ));
+            }
+            indices
+        }
+    }
+}
+
+/// The index at which each of the ascending `targets` in `[0, 1)` falls in
+/// the cumulative weights.
+fn select<R: RealField>(weights: &DVector<R>, targets: Vec<R>) -> Vec<usize> {
+    let mut cumulative = weights[0].clone();
+    let mut j = 0;
+    targets
+        .into_iter()
+        .map(|target| {
+            while target > cumulative && j + 1 < weights.nrows() {
+                j += 1;
+                cumulative += weights[j].clone();
+            }
+            j
+        })
+        .collect()
+}
+
+/// The variant of the particle filter
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ParticleFilterVariant {
+    /// The bootstrap filter: particles are propagated blindly through the
+    /// process model and weighted by the likelihood of the observation.
+    #[default]
+    Bootstrap,
+    /// The auxiliary particle filter of Pitt and Shephard: particles are
+    /// resampled in advance according to the likelihood of the observation
+    /// at their predicted mean, with the process noise added to the
+    /// observation noise through the linearization `H`, so that particles likely to land where the
+    /// likelihood is high are propagated. This helps greatly when the
+    /// likelihood is peaked, e.g. for precise measurements, where most
+    /// blindly propagated particles would get negligible weight.
+    Auxiliary,
+}
+
+/// A particle filter with no control inputs
+///
+/// The process noise is drawn from the Gaussian with covariance `Q` and
+/// particles are weighted by the Gaussian likelihood with covariance `R`
+/// of the observation function
+/// [predict_observation](ObservationModel::predict_observation). By
+/// default, particles are resampled with systematic resampling when the
+/// effective sample size falls below half the number of particles; see
+/// [ResamplingConfig].
+pub struct ParticleFilter<'a, R>
+where
+    R: RealField,
+{
+    transition_model: &'a dyn NonlinearTransitionModel<R>,
+    observation_model: &'a dyn ObservationModel<R>,
+    variant: ParticleFilterVariant,
+    resampling: ResamplingConfig<R>,
+}
+
+impl<'a, R> ParticleFilter<'a, R>
+where
+    R: RealField,
+{
+    /// Initialize a new bootstrap `ParticleFilter` struct.
+    pub fn new(
+        transition_model: &'a dyn NonlinearTransitionModel<R>,
+        observation_model: &'a dyn ObservationModel<R>,
+    ) -> Self {
+        Self {
+            transition_model,
+            observation_model,
+            variant: ParticleFilterVariant::Bootstrap,
+            resampling: ResamplingConfig::default(),
+        }
+    }
+
+    /// Use the given variant of the particle filter.
+    pub fn with_variant(mut self, variant: ParticleFilterVariant) -> Self {
+        self.variant = variant;
+        self
+    }
+
+    /// Resample according to `config`. The auxiliary variant resamples at
+    /// every step, as its first stage, and only uses the scheme.
+    pub fn with_resampling(mut self, config: ResamplingConfig<R>) -> Self {
+        self.resampling = config;
+        self
+    }
+
+    /// The logarithm of the likelihood of `observation` given `state`, up to
+    /// a constant, given the Cholesky factor of `R`.
+    fn log_likelihood(
+        &self,
+        chol: &na::linalg::Cholesky<R, na::Dynamic>,
+        state: &DVector<R>,
+        observation: &DVector<R>,
+    ) -> R {
+        let half: R = na::convert(0.5);
+        let residual = observation - self.observation_model.predict_observation(state);
+        let whitened = chol.l().solve_lower_triangular(&residual);
+        match whitened {
+            Some(z) => -(z.norm_squared() * half),
+            None => -R::one() / R::zero(),
+        }
+    }
+
+    /// The first-stage log-weights of the auxiliary particle filter
+    ///
+    /// Each particle is weighted by the likelihood at its predicted mean,
+    /// widened by the process noise as seen through the observation model
+    /// linearized at that mean. As the widened covariance differs between
+    /// particles of a non-linear model, its log-determinant is included.
+    fn first_stage_log_weights(
+        &self,
+        particles: &[DVector<R>],
+        observation: &DVector<R>,
+    ) -> Result<DVector<R>, Error> {
+        let Q = self.transition_model.Q();
+        let R = self.observation_model.R();
+        let predictive = |H: &DMatrix<R>, HT: &DMatrix<R>| {
+            let chol = na::linalg::Cholesky::new(H * Q * HT + R)
+                .ok_or_else(|| Error::from(ErrorKind::CovarianceNotPositiveSemiDefinite))?;
+            let half_log_det = chol.l().diagonal().map(|d| d.ln()).sum();
+            Ok::<_, Error>((chol, half_log_det))
+        };
+        let nominal = predictive(self.observation_model.H(), &self.observation_model.HT())?;
+        let mut log_weights = Vec::with_capacity(particles.len());
+        for x in particles.iter() {
+            let mean = self.transition_model.f(x);
+            let log_weight = match self.observation_model.jacobian_at(&mean) {
+                Cow::Borrowed(_) => {
+                    let (chol, half_log_det) = &nominal;
+                    self.log_likelihood(chol, &mean, observation) - half_log_det.clone()
+                }
+                Cow::Owned(H) => {
+                    let (chol, half_log_det) = predictive(&H, &H.transpose())?;
+                    self.log_likelihood(&chol, &mean, observation) - half_log_det
+                }
+            };
+            log_weights.push(log_weight);
+        }
+        Ok(DVector::from_vec(log_weights))
+    }
+
+    /// Perform prediction and update steps
+    ///
+    /// `normal` must return independent standard normal samples and
+    /// `uniform` independent samples uniform in `[0, 1)`. If any component
+    /// of the observation is NaN (not a number), the particles are
+    /// propagated without weighting. An
+    /// [ErrorKind::CovarianceNotPositiveSemiDefinite] error is returned if
+    /// `R` is not positive definite.
+    pub fn step<N, U>(
+        &self,
+        previous: &ParticleSet<R>,
+        observation: &DVector<R>,
+        normal: &mut N,
+        uniform: &mut U,
+    ) -> Result<ParticleSet<R>, Error>
+    where
+        N: FnMut() -> R,
+        U: FnMut() -> R,
+    {
+        let n = previous.particles.len();
+        let Q_sqrt = psd_sqrt(self.transition_model.Q());
+        let mut propagate = |x: &DVector<R>| {
+            let w = DVector::from_fn(Q_sqrt.ncols(), |_, _| normal());
+            self.transition_model.f(x) + &Q_sqrt * w
+        };
+        let observed = !observation.iter().any(|x| is_nan(x.clone()));
+        let uniform_log_weights = || DVector::<R>::zeros(n);
+
+        // The ancestor of each new particle and the logarithm of its weight
+        // before the observation.
+        let (indices, log_weights) = match self.variant {
+            ParticleFilterVariant::Auxiliary if observed => {
+                let first_stage = self.first_stage_log_weights(&previous.particles, observation)?;
+                let weights =
+                    normalize_log_weights(&(&first_stage + previous.weights.map(|w| w.ln())));
+                let indices = resample(self.resampling.scheme, &weights, n, uniform);
+                // The second stage corrects for the first-stage weights.
+                let log_weights =
+                    DVector::from_iterator(n, indices.iter().map(|&i| -first_stage[i].clone()));
+                (indices, log_weights)
+            }
+            _ => {
+                let threshold = self.resampling.ess_threshold.clone() * na::convert(n as f64);
+                if previous.effective_sample_size() < threshold {
+                    let indices = resample(self.resampling.scheme, &previous.weights, n, uniform);
+                    (indices, uniform_log_weights())
+                } else {
+                    ((0..n).collect(), previous.weights.map(|w| w.ln()))
+                }
+            }
+        };
+
+        let particles: Vec<DVector<R>> = indices
+            .iter()
+            .map(|&i| propagate(&previous.particles[i]))
+            .collect();
+        if !observed {
+            return Ok(ParticleSet::new(
+                particles,
+                normalize_log_weights(&log_weights),
+            ));
+        }
+
+        let chol = match na::linalg::Cholesky::new(self.observation_model.R().clone()) {
+            Some(chol) => chol,
+            None => return Err(ErrorKind::CovarianceNotPositiveSemiDefinite.into()),
+        };
+        let log_weights = DVector::from_iterator(
+            n,
+            particles
+                .iter()
+                .zip(log_weights.iter())
+                .map(|(x, w)| w.clone() + self.log_likelihood(&chol, x, observation)),
+        );
+        Ok(ParticleSet::new(
+            particles,
+            normalize_log_weights(&log_weights),
+        ))
+    }
+}
+
+#[test]
+fn test_auxiliary_particle_filter() {
+    use crate::test_util::{ConstantVelocity, Normals, PositionObservation};
+    use crate::{KalmanFilterNoControl, Linearized};
+
+    // A precise position measurement makes the likelihood peaked compared
+    // with the spread of the predicted particles.
+    let transition = ConstantVelocity::new(0.1, 1.0);
+    let linearized = Linearized(&transition);
+    let observation = PositionObservation::new(1e-4);
+    let initial_estimate = StateAndCovariance::new(
+        DVector::from_vec(vec![0.0, 1.0]),
+        DMatrix::identity(2, 2) * 0.01,
+    );
+    let z = DVector::from_element(1, 0.15);
+    let kf = KalmanFilterNoControl::new(&transition, &observation);
+    let expected = kf.step(&initial_estimate, &z).unwrap();
+
+    let mut normals = Normals::new(12);
+    let mut normal = || normals.sample();
+    let mut state = 1u64;
+    let mut uniform = || {
+        state = state
+            .wrapping_mul(6364136223846793005)
+            .wrapping_add(1442695040888963407);
+        (state >> 11) as f64 / (1u64 << 53) as f64
+    };
+    let initial = ParticleSet::from_gaussian(&initial_estimate, 2000, &mut normal);
+
+    let bootstrap = ParticleFilter::new(&linearized, &observation);
+    let auxiliary = ParticleFilter::new(&linearized, &observation)
+        .with_variant(ParticleFilterVariant::Auxiliary);
+    let bootstrap = bootstrap
+        .step(&initial, &z, &mut normal, &mut uniform)
+        .unwrap();
+    let auxiliary = auxiliary
+        .step(&initial, &z, &mut normal, &mut uniform)
+        .unwrap();
+
+    // Looking ahead keeps many more particles with significant weight, and
+    // the estimate agrees with the Kalman filter.
+    assert!(auxiliary.effective_sample_size() > 2.0 * bootstrap.effective_sample_size());
+    let estimate = auxiliary.estimate();
+    approx::assert_relative_eq!(estimate.state()[0], expected.state()[0], epsilon = 0.01);
+    approx::assert_relative_eq!(estimate.state()[1], expected.state()[1], epsilon = 0.05);
+}
+
+#[test]
+fn test_auxiliary_nonlinear_first_stage() {
+    use crate::test_util::ConstantVelocity;
+    use crate::{Innovation, Linearized, TransitionModelLinearNoControl};
+
+    // A range measurement, linearized differently at each predicted mean.
+    // The first-stage weights differ as the predictive densities do.
+    let transition = ConstantVelocity::new(0.1, 1.0);
+    let linearized = Linearized(&transition);
+    let range = crate::nonlinear::NumericalObservationModel::new(
+        |x: &DVector<f64>| DVector::from_element(1, x.norm()),
+        DMatrix::from_element(1, 1, 0.01),
+        1e-7,
+        &DVector::from_vec(vec![0.0, 1.0]),
+    );
+    let filter =
+        ParticleFilter::new(&linearized, &range).with_variant(ParticleFilterVariant::Auxiliary);
+    let particles = vec![
+        DVector::from_vec(vec![1.0, 0.2]),
+        DVector::from_vec(vec![0.1, 1.5]),
+    ];
+    let z = DVector::from_element(1, 1.2);
+    let log_weights = filter.first_stage_log_weights(&particles, &z).unwrap();
+
+    let predictive = |x: &DVector<f64>| {
+        let mean = transition.F() * x;
+        let H = range.jacobian_at(&mean).into_owned();
+        let S = &H * transition.Q() * H.transpose() + range.R();
+        Innovation::new(&z - range.predict_observation(&mean), S)
+            .log_likelihood()
+            .unwrap()
+    };
+    approx::assert_relative_eq!(
+        log_weights[0] - log_weights[1],
+        predictive(&particles[0]) - predictive(&particles[1]),
+        epsilon = 1e-6
+    );
+}
+
+#[test]
+fn test_resampling() {
+    let weights = DVector::from_vec(vec![0.05, 0.6, 0.0, 0.35]);
+    let mut state = 7u64;
+    let mut uniform = || {
+        state = state
+            .wrapping_mul(6364136223846793005)
+            .wrapping_add(1442695040888963407);
+        (state >> 11) as f64 / (1u64 << 53) as f64
+    };
+    for scheme in [
+        ResamplingScheme::Multinomial,
+        ResamplingScheme::Stratified,
+        ResamplingScheme::Systematic,
+        ResamplingScheme::Residual,
+    ] {
+        let indices = resample(scheme, &weights, 1000, &mut uniform);
+        assert_eq!(indices.len(), 1000);
+        let counts: Vec<usize> = (0..4)
+            .map(|i| indices.iter().filter(|&&j| j == i).count())
+            .collect();
+        assert_eq!(counts[2], 0);
+        for (count, w) in counts.iter().zip(weights.iter()) {
+            approx::assert_relative_eq!(*count as f64, 1000.0 * w, epsilon = 50.0);
+        }
+        // The low-variance schemes are within one of the expected count.
+        if scheme != ResamplingScheme::Multinomial {
+            assert!((counts[1] as f64 - 600.0).abs() <= 1.0);
+        }
+    }
+}
@@ -0,0 +1,320 @@
+//! Quick diagnostic plots of filter runs as SVG or PNG
+//!
+//! Each component is drawn in its own panel as a line with a shaded band of
+//! plus and minus one standard deviation. The SVG output is a standalone
+//! document which can be written to a file and opened in a browser, with
+//! each panel labelled by its component and vertical range. The PNG output
+//! is the same drawing without the labels, as an RGB image.
+//!
+//! The drawing is done by the SVG and bitmap backends of `plotters`. It is
+//! built without fonts, so that no system font library is needed, which is
+//! why the PNG output is not labelled.
+
+use image::codecs::png::PngEncoder;
+use image::{ColorType, ImageEncoder};
+use na::RealField;
+use nalgebra as na;
+use plotters::coord::Shift;
+use plotters::prelude::*;
+use plotters::style::text_anchor::{HPos, Pos, VPos};
+
+use crate::{Innovation, StateAndCovariance};
+
+const PANEL_HEIGHT: u32 = 160;
+const MARGIN: u32 = 40;
+
+const LINE: RGBColor = RGBColor(0x1f, 0x77, 0xb4);
+
+/// A panel: its label, and the value and standard deviation at each step.
+type Panel = (String, Vec<f64>, Vec<f64>);
+
+/// Plot each state component of a sequence of estimates, e.g. the output of
+/// [filter](crate::KalmanFilterNoControl::filter) or
+/// [smooth](crate::KalmanFilterNoControl::smooth), with a band of plus and
+/// minus one standard deviation.
+pub fn plot_estimates_svg<R>(estimates: &[StateAndCovariance<R>], width: u32) -> String
+where
+    R: RealField,
+{
+    render_svg(&estimate_panels(estimates), width)
+}
+
+/// Plot each component of a sequence of innovations with a band of plus and
+/// minus one standard deviation, as predicted by the innovation covariance.
+/// For a consistent filter, about two thirds of the residuals lie in the
+/// band.
+pub fn plot_innovations_svg<R>(innovations: &[Innovation<R>], width: u32) -> String
+where
+    R: RealField,
+{
+    render_svg(&innovation_panels(innovations), width)
+}
+
+/// Plot estimates as [plot_estimates_svg] does, as a PNG image `width`
+/// pixels wide and 160 pixels high per component.
+pub fn plot_estimates_png<R>(estimates: &[StateAndCovariance<R>], width: u32) -> Vec<u8>
+where
+    R: RealField,
+{
+    render_png(&estimate_panels(estimates), width)
+}
+
+/// Plot innovations as [plot_innovations_svg] does, as a PNG image `width`
+/// pixels wide and 160 pixels high per component.
+pub fn plot_innovations_png<R>(innovations: &[Innovation<R>], width: u32) -> Vec<u8>
+where
+    R: RealField,
+{
+    render_png(&innovation_panels(innovations), width)
+}
+
+fn estimate_panels<R: RealField>(estimates: &[StateAndCovariance<R>]) -> Vec<Panel> {
+    let n = estimates.first().map_or(0, |e| e.state().nrows());
+    (0..n)
+        .map(|i| {
+            let means = estimates.iter().map(|e| to_f64(&e.state()[i])).collect();
+            let sigmas = estimates
+                .iter()
+                .map(|e| sigma(to_f64(&e.covariance()[(i, i)])))
+                .collect();
+            (format!("state {}", i), means, sigmas)
+        })
+        .collect()
+}
+
+fn innovation_panels<R: RealField>(innovations: &[Innovation<R>]) -> Vec<Panel> {
+    let n = innovations.first().map_or(0, |e| e.residual().nrows());
+    (0..n)
+        .map(|i| {
+            let residuals = innovations
+                .iter()
+                .map(|e| to_f64(&e.residual()[i]))
+                .collect();
+            let sigmas = innovations
+                .iter()
+                .map(|e| sigma(to_f64(&e.covariance()[(i, i)])))
+                .collect();
+            (format!("innovation {}", i), residuals, sigmas)
+        })
+        .collect()
+}
+
+fn to_f64<R: RealField>(x: &R) -> f64 {
+    na::try_convert(x.clone()).unwrap_or(f64::NAN)
+}
+
+/// The standard deviation for a variance, clamping small negative variances
+/// from roundoff to zero but keeping a NaN variance as missing.
+fn sigma(variance: f64) -> f64 {
+    if variance < 0.0 {
+        0.0
+    } else {
+        variance.sqrt()
+    }
+}
+
+/// The vertical range of a panel: the values plus and minus one standard
+/// deviation, widened if it is empty or a single point.
+fn range(values: &[f64], sigmas: &[f64]) -> (f64, f64) {
+    let (mut lo, mut hi) = (f64::INFINITY, f64::NEG_INFINITY);
+    for (v, s) in values.iter().zip(sigmas.iter()) {
+        if v.is_finite() && s.is_finite() {
+            lo = lo.min(v - s);
+            hi = hi.max(v + s);
+        }
+    }
+    if lo > hi {
+        // Nothing to plot.
+        (-1.0, 1.0)
+    } else if lo == hi {
+        (lo - 1.0, hi + 1.0)
+    } else {
+        (lo, hi)
+    }
+}
+
+/// The ranges of consecutive steps whose value and standard deviation are
+/// finite. Missing values, e.g. for skipped updates, break the band and line.
+fn segments(values: &[f64], sigmas: &[f64]) -> Vec<core::ops::Range<usize>> {
+    let finite = |j: usize| values[j].is_finite() && sigmas[j].is_finite();
+    let mut segments = Vec::new();
+    let mut i = 0;
+    while i < values.len() {
+        let start = i;
+        while i < values.len() && finite(i) {
+            i += 1;
+        }
+        if i > start {
+            segments.push(start..i);
+        }
+        i += 1;
+    }
+    segments
+}
+
+/// The height in pixels of a plot of `panels`; without any, a single blank
+/// panel is drawn.
+fn height(panels: &[Panel]) -> u32 {
+    PANEL_HEIGHT * panels.len().max(1) as u32
+}
+
+/// Draw the panels one above the other, with their labels if `labelled`.
+fn draw<DB>(
+    root: &DrawingArea<DB, Shift>,
+    panels: &[Panel],
+    labelled: bool,
+) -> Result<(), DrawingAreaErrorKind<DB::ErrorType>>
+where
+    DB: DrawingBackend,
+{
+    root.fill(&WHITE)?;
+    let areas = root.split_evenly((panels.len().max(1), 1));
+    for (area, (label, values, sigmas)) in areas.iter().zip(panels.iter()) {
+        let (lo, hi) = range(values, sigmas);
+        let steps = (values.len().max(2) - 1) as f64;
+        let chart = ChartBuilder::on(area)
+            .margin(MARGIN)
+            .build_cartesian_2d(0.0..steps, lo..hi)?;
+        let plotting_area = chart.plotting_area();
+        for segment in segments(values, sigmas) {
+            let upper = segment.clone().map(|j| (j as f64, values[j] + sigmas[j]));
+            let lower = segment
+                .clone()
+                .rev()
+                .map(|j| (j as f64, values[j] - sigmas[j]));
+            plotting_area.draw(&Polygon::new(
+                upper.chain(lower).collect::<Vec<_>>(),
+                LINE.mix(0.25).filled(),
+            ))?;
+            plotting_area.draw(&PathElement::new(
+                segment.map(|j| (j as f64, values[j])).collect::<Vec<_>>(),
+                LINE,
+            ))?;
+        }
+        plotting_area.draw(&Rectangle::new([(0.0, lo), (steps, hi)], BLACK))?;
+        if labelled {
+            let top = MARGIN as i32;
+            area.draw(&Text::new(
+                format!("{} [{:.3}, {:.3}]", label, lo, hi),
+                (top, top - 6),
+                ("sans-serif", 12)
+                    .into_font()
+                    .color(&BLACK)
+                    .pos(Pos::new(HPos::Left, VPos::Bottom)),
+            ))?;
+        }
+    }
+    Ok(())
+}
+
+fn render_svg(panels: &[Panel], width: u32) -> String {
+    let mut svg = String::new();
+    {
+        let root = SVGBackend::with_string(&mut svg, (width, height(panels))).into_drawing_area();
+        draw(&root, panels, true).expect("drawing to a string cannot fail");
+        root.present().expect("drawing to a string cannot fail");
+    }
+    svg
+}
+
+/// Draw the panels into an RGB buffer, without labels, and encode it as PNG.
+fn render_png(panels: &[Panel], width: u32) -> Vec<u8> {
+    let height = height(panels);
+    let mut pixels = vec![0; 3 * width as usize * height as usize];
+    {
+        let root = BitMapBackend::with_buffer(&mut pixels, (width, height)).into_drawing_area();
+        draw(&root, panels, false).expect("drawing to a buffer cannot fail");
+        root.present().expect("drawing to a buffer cannot fail");
+    }
+    let mut png = Vec::new();
+    PngEncoder::new(&mut png)
+        .write_image(&pixels, width, height, ColorType::Rgb8)
+        .expect("encoding a buffer of the image size cannot fail");
+    png
+}
+
+#[cfg(test)]
+fn estimates_of(values: &[f64], variance: f64) -> Vec<StateAndCovariance<f64>> {
+    values
+        .iter()
+        .map(|&v| {
+            StateAndCovariance::new(
+                na::DVector::from_element(1, v),
+                na::DMatrix::from_element(1, 1, variance),
+            )
+        })
+        .collect()
+}
+
+#[test]
+fn test_plot_estimates() {
+    // Values 0, 1, 2 with unit standard deviation span [-1, 3]. At a width
+    // of 280, the plotting area spans pixels 40 to 239 across and 40 to 119
+    // down, with the steps at x = 40, 139 and 239.
+    let svg = plot_estimates_svg(&estimates_of(&[0.0, 1.0, 2.0], 1.0), 280);
+    assert!(svg.starts_with(r#"<svg width="280" height="160""#));
+    assert!(svg.contains(r#"<rect x="40" y="40" width="199" height="79""#));
+    assert!(svg.contains(">\nstate 0 [-1.000, 3.000]\n</text>"));
+    assert!(svg.contains(r#"points="40,100 139,80 239,60 ""#));
+    assert!(svg.contains(r#"points="40,80 139,60 239,40 239,80 139,100 40,119 ""#));
+    assert!(svg.trim_end().ends_with("</svg>"));
+
+    // A missing value splits the band and line.
+    let svg = plot_estimates_svg(&estimates_of(&[0.0, 1.0, f64::NAN, 2.0, 3.0], 1.0), 280);
+    assert_eq!(svg.matches("<polygon").count(), 2);
+    assert_eq!(svg.matches("<polyline").count(), 2);
+
+    // With nothing finite to plot, the range defaults to [-1, 1].
+    for estimates in [
+        estimates_of(&[f64::NAN; 3], 1.0),
+        estimates_of(&[1.0; 3], f64::NAN),
+    ] {
+        let svg = plot_estimates_svg(&estimates, 280);
+        assert!(svg.contains("state 0 [-1.000, 1.000]"));
+        assert!(!svg.contains("<polygon"));
+        assert!(!svg.contains("inf") && !svg.contains("NaN"));
+    }
+    // A constant value without uncertainty is centred in the panel.
+    let svg = plot_estimates_svg(&estimates_of(&[5.0; 2], 0.0), 280);
+    assert!(svg.contains("state 0 [4.000, 6.000]"));
+    assert!(svg.contains(r#"points="40,80 239,80 ""#));
+
+    // Each component has its own panel, and an empty plot is blank.
+    let innovations: Vec<_> = estimates_of(&[0.0, 1.0], 1.0)
+        .iter()
+        .map(|e| {
+            let residual = na::DVector::from_vec(vec![e.state()[0], 2.0]);
+            Innovation::new(residual, na::DMatrix::identity(2, 2))
+        })
+        .collect();
+    let svg = plot_innovations_svg(&innovations, 280);
+    assert!(svg.starts_with(r#"<svg width="280" height="320""#));
+    assert!(svg.contains("innovation 0 [-1.000, 2.000]"));
+    assert!(svg.contains("innovation 1 [1.000, 3.000]"));
+    let svg = plot_estimates_svg(&estimates_of(&[], 1.0), 280);
+    assert!(svg.starts_with(r#"<svg width="280" height="160""#));
+    assert!(!svg.contains("<polyline"));
+}
+
+#[test]
+fn test_plot_estimates_png() {
+    let png = plot_estimates_png(&estimates_of(&[0.0, 1.0, 2.0], 1.0), 280);
+    let image = image::load_from_memory_with_format(&png, image::ImageFormat::Png)
+        .unwrap()
+        .to_rgb8();
+    assert_eq!(image.dimensions(), (280, 160));
+    let pixel = |x: u32, y: u32| image.get_pixel(x, y).0;
+
+    // As in the SVG, the value 1 at x = 139 is at y = 80, with the band
+    // from 60 to 100 drawn at an opacity of 0.25 over white, inside the
+    // frame from (40, 40) to (239, 119).
+    let band = [LINE.0, LINE.1, LINE.2].map(|c| (0.25 * f64::from(c) + 0.75 * 255.0) as u8);
+    let close = |a: [u8; 3], b: [u8; 3]| a.iter().zip(b.iter()).all(|(a, b)| a.abs_diff(*b) <= 1);
+    assert_eq!(pixel(139, 80), [LINE.0, LINE.1, LINE.2]);
+    assert!(close(pixel(139, 65), band));
+    assert!(close(pixel(139, 95), band));
+    assert_eq!(pixel(139, 50), [255, 255, 255]);
+    assert_eq!(pixel(40, 40), [0, 0, 0]);
+    assert_eq!(pixel(239, 119), [0, 0, 0]);
+    assert_eq!(pixel(10, 10), [255, 255, 255]);
+}
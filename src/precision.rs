@@ -0,0 +1,232 @@
+//! Filtering with separate storage and computation precision
+
+use core::marker::PhantomData;
+
+use na::{DVector, RealField};
+use nalgebra as na;
+
+use crate::{Error, KalmanFilterNoControl, StateAndCovariance};
+
+/// The scalar types in which estimates are stored and steps are computed
+///
+/// Storing estimates and observations in a narrower type than the
+/// computation halves their memory, e.g. for long histories on embedded
+/// targets, while the gain and covariance are computed at full precision.
+/// The models are defined in the computation type, so existing models
+/// generic over the scalar type need no changes.
+pub trait PrecisionPolicy {
+    /// The scalar type of stored estimates and observations.
+    type Storage: RealField;
+    /// The scalar type of the models and of the computation of each step.
+    type Compute: RealField;
+
+    /// Convert a stored value to the computation type.
+    fn widen(value: Self::Storage) -> Self::Compute;
+
+    /// Round a computed value to the storage type.
+    fn narrow(value: Self::Compute) -> Self::Storage;
+}
+
+/// Store and compute in the same scalar type, `R`
+pub struct Uniform<R>(PhantomData<R>);
+
+impl<R> PrecisionPolicy for Uniform<R>
+where
+    R: RealField,
+{
+    type Storage = R;
+    type Compute = R;
+
+    #[inline]
+    fn widen(value: R) -> R {
+        value
+    }
+
+    #[inline]
+    fn narrow(value: R) -> R {
+        value
+    }
+}
+
+/// Store in `f32` and compute in `f64`
+pub struct StoreF32ComputeF64;
+
+impl PrecisionPolicy for StoreF32ComputeF64 {
+    type Storage = f32;
+    type Compute = f64;
+
+    #[inline]
+    fn widen(value: f32) -> f64 {
+        value.into()
+    }
+
+    #[inline]
+    fn narrow(value: f64) -> f32 {
+        value as f32
+    }
+}
+
+/// A Kalman filter storing estimates in the precision policy's storage type
+/// and computing each step in its computation type
+///
+/// Each step widens the previous estimate and the observation, runs the
+/// wrapped [KalmanFilterNoControl], and rounds the posterior back, so the
+/// only loss of precision is the rounding of the stored estimates. The
+/// fading-memory factor and failure policy of the wrapped filter apply.
+pub struct MixedPrecisionKalmanFilter<'a, P>
+where
+    P: PrecisionPolicy,
+{
+    filter: KalmanFilterNoControl<'a, P::Compute>,
+}
+
+impl<'a, P> MixedPrecisionKalmanFilter<'a, P>
+where
+    P: PrecisionPolicy,
+{
+    /// Wrap a filter whose models are in the computation type.
+    pub fn new(filter: KalmanFilterNoControl<'a, P::Compute>) -> Self {
+        Self { filter }
+    }
+
+    /// Convert a stored estimate to the computation type.
+    pub fn widen(estimate: &StateAndCovariance<P::Storage>) -> StateAndCovariance<P::Compute> {
+        StateAndCovariance::new(
+            widen_matrix::<P, _, _>(estimate.state()),
+            widen_matrix::<P, _, _>(estimate.covariance()),
+        )
+    }
+
+    /// Round a computed estimate to the storage type.
+    pub fn narrow(estimate: &StateAndCovariance<P::Compute>) -> StateAndCovariance<P::Storage> {
+        StateAndCovariance::new(
+            estimate.state().map(|x| P::narrow(x)),
+            estimate.covariance().map(|x| P::narrow(x)),
+        )
+    }
+
+    /// Perform Kalman prediction and update steps, see
+    /// [KalmanFilterNoControl::step].
+    pub fn step(
+        &self,
+        previous_estimate: &StateAndCovariance<P::Storage>,
+        observation: &DVector<P::Storage>,
+    ) -> Result<StateAndCovariance<P::Storage>, Error> {
+        let estimate = self.filter.step(
+            &Self::widen(previous_estimate),
+            &widen_matrix::<P, _, _>(observation),
+        )?;
+        Ok(Self::narrow(&estimate))
+    }
+
+    /// Kalman filter, storing the estimates in the storage type
+    ///
+    /// Errors give the index of the failed step.
+    #[cfg(feature = "std")]
+    pub fn filter(
+        &self,
+        initial_estimate: &StateAndCovariance<P::Storage>,
+        observations: &[DVector<P::Storage>],
+    ) -> Result<Vec<StateAndCovariance<P::Storage>>, Error> {
+        let mut estimates = Vec::with_capacity(observations.len());
+        let mut previous = Self::widen(initial_estimate);
+        for (i, observation) in observations.iter().enumerate() {
+            let estimate = self
+                .filter
+                .step(&previous, &widen_matrix::<P, _, _>(observation))
+                .map_err(|e| e.with_step(i))?;
+            let stored = Self::narrow(&estimate);
+            // Continue from the stored estimate, so that the results do not
+            // depend on whether the caller steps or filters.
+            previous = Self::widen(&stored);
+            estimates.push(stored);
+        }
+        Ok(estimates)
+    }
+}
+
+fn widen_matrix<P, Rows, Cols>(
+    matrix: &na::OMatrix<P::Storage, Rows, Cols>,
+) -> na::OMatrix<P::Compute, Rows, Cols>
+where
+    P: PrecisionPolicy,
+    Rows: na::Dim,
+    Cols: na::Dim,
+    na::DefaultAllocator: na::allocator::Allocator<P::Storage, Rows, Cols>
+        + na::allocator::Allocator<P::Compute, Rows, Cols>,
+{
+    matrix.map(|x| P::widen(x))
+}
+
+#[test]
+fn test_mixed_precision() {
+    use crate::test_util::{
+        initial_estimate, simulate_positions, ConstantVelocity, PositionObservation,
+    };
+
+    let transition = ConstantVelocity::new(0.1, 1.0);
+    let observation = PositionObservation::new(0.5);
+    let observations = simulate_positions(50, 0.1, 1.0, 0.5, 15);
+    let expected = KalmanFilterNoControl::new(&transition, &observation)
+        .filter(&initial_estimate(), &observations)
+        .unwrap();
+
+    type Mixed<'a> = MixedPrecisionKalmanFilter<'a, StoreF32ComputeF64>;
+    let mixed = Mixed::new(KalmanFilterNoControl::new(&transition, &observation));
+    let stored: Vec<DVector<f32>> = observations.iter().map(|z| z.map(|x| x as f32)).collect();
+    let initial = Mixed::narrow(&initial_estimate());
+    let actual = mixed.filter(&initial, &stored).unwrap();
+    for (a, e) in actual.iter().zip(&expected) {
+        approx::assert_relative_eq!(&Mixed::widen(a), e, epsilon = 1e-4);
+    }
+    let mut estimate = initial;
+    for (z, a) in stored.iter().zip(&actual) {
+        estimate = mixed.step(&estimate, z).unwrap();
+        assert_eq!(&estimate, a);
+    }
+
+    let uniform: MixedPrecisionKalmanFilter<Uniform<f64>> =
+        MixedPrecisionKalmanFilter::new(KalmanFilterNoControl::new(&transition, &observation));
+    let actual = uniform.filter(&initial_estimate(), &observations).unwrap();
+    assert_eq!(actual, expected);
+}
+
+#[test]
+fn test_mixed_precision_rounding() {
+    use crate::test_util::{
+        initial_estimate, simulate_positions, ConstantVelocity, PositionObservation,
+    };
+
+    // Each step continues from the stored estimate, rounded to f32, so the
+    // estimates are those of the wrapped filter stepped from each rounded
+    // estimate in turn, then rounded again.
+    let transition = ConstantVelocity::new(0.1, 1.0);
+    let observation = PositionObservation::new(0.5);
+    let kf = KalmanFilterNoControl::new(&transition, &observation);
+    type Mixed<'a> = MixedPrecisionKalmanFilter<'a, StoreF32ComputeF64>;
+    let mixed = Mixed::new(KalmanFilterNoControl::new(&transition, &observation));
+    let mut stored: Vec<DVector<f32>> = simulate_positions(8, 0.1, 1.0, 0.5, 9)
+        .iter()
+        .map(|z| z.map(|x| x as f32))
+        .collect();
+    stored[3] = DVector::from_element(1, f32::NAN);
+    let initial = Mixed::narrow(&initial_estimate());
+    let actual = mixed.filter(&initial, &stored).unwrap();
+    let mut previous = initial.clone();
+    for (estimate, z) in actual.iter().zip(stored.iter()) {
+        let expected = kf
+            .step(&Mixed::widen(&previous), &z.map(f64::from))
+            .unwrap();
+        previous = Mixed::narrow(&expected);
+        assert_eq!(estimate, &previous);
+    }
+
+    // Widening is exact, so a stored estimate survives the round trip.
+    assert_eq!(Mixed::narrow(&Mixed::widen(&actual[5])), actual[5]);
+    assert!(mixed.filter(&initial, &[]).unwrap().is_empty());
+
+    // Errors give the index of the failed step.
+    stored[2] = DVector::zeros(2);
+    let err = mixed.filter(&initial, &stored).unwrap_err();
+    assert_eq!(err.step(), Some(2));
+}
@@ -0,0 +1,133 @@
+//! Composable preprocessing of raw measurements before the update step
+
+use na::{DVector, RealField};
+use nalgebra as na;
+
+use crate::nan;
+
+/// A single preprocessing operation, applied componentwise
+#[derive(Debug, Clone)]
+pub enum MeasurementStage<R>
+where
+    R: RealField,
+{
+    /// Multiply each component by a factor, e.g. to convert units.
+    Scale(DVector<R>),
+    /// Subtract a static bias from each component.
+    RemoveBias(DVector<R>),
+    /// Set components whose magnitude does not exceed the given width to
+    /// zero.
+    Deadband(DVector<R>),
+    /// Mark components at or beyond the sensor's limits as missing (NaN),
+    /// since their true value is unknown.
+    Saturation {
+        /// Lower limit of each component.
+        min: DVector<R>,
+        /// Upper limit of each component.
+        max: DVector<R>,
+    },
+}
+
+impl<R> MeasurementStage<R>
+where
+    R: RealField,
+{
+    fn apply(&self, measurement: &mut DVector<R>) {
+        match self {
+            MeasurementStage::Scale(factor) => measurement.component_mul_assign(factor),
+            MeasurementStage::RemoveBias(bias) => *measurement -= bias,
+            MeasurementStage::Deadband(width) => {
+                for (x, w) in measurement.iter_mut().zip(width.iter()) {
+                    if x.clone().abs() <= *w {
+                        *x = R::zero();
+                    }
+                }
+            }
+            MeasurementStage::Saturation { min, max } => {
+                for ((x, lo), hi) in measurement.iter_mut().zip(min.iter()).zip(max.iter()) {
+                    if *x <= *lo || *x >= *hi {
+                        *x = nan();
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// A sequence of [MeasurementStage]s applied to each raw measurement
+///
+/// Components marked missing are NaN, which the filter treats as a missing
+/// observation. Missing components stay missing through later stages.
+#[derive(Debug, Clone)]
+pub struct MeasurementPipeline<R>
+where
+    R: RealField,
+{
+    stages: Vec<MeasurementStage<R>>,
+}
+
+impl<R> Default for MeasurementPipeline<R>
+where
+    R: RealField,
+{
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<R> MeasurementPipeline<R>
+where
+    R: RealField,
+{
+    /// Create an empty pipeline, which passes measurements through unchanged.
+    pub fn new() -> Self {
+        Self { stages: Vec::new() }
+    }
+
+    /// Append a stage to the pipeline.
+    pub fn with_stage(mut self, stage: MeasurementStage<R>) -> Self {
+        self.stages.push(stage);
+        self
+    }
+
+    /// The stages of the pipeline, in order.
+    pub fn stages(&self) -> &[MeasurementStage<R>] {
+        &self.stages
+    }
+
+    /// Apply all stages to a measurement in place.
+    pub fn apply_inplace(&self, measurement: &mut DVector<R>) {
+        for stage in self.stages.iter() {
+            stage.apply(measurement);
+        }
+    }
+
+    /// Apply all stages to a measurement.
+    pub fn apply(&self, measurement: &DVector<R>) -> DVector<R> {
+        let mut result = measurement.clone();
+        self.apply_inplace(&mut result);
+        result
+    }
+}
+
+#[test]
+fn test_measurement_pipeline() {
+    let pipeline = MeasurementPipeline::<f64>::new()
+        .with_stage(MeasurementStage::Saturation {
+            min: DVector::from_vec(vec![-100.0, -100.0, -100.0]),
+            max: DVector::from_vec(vec![100.0, 100.0, 100.0]),
+        })
+        .with_stage(MeasurementStage::Scale(DVector::from_vec(vec![
+            0.1, 0.1, 0.1,
+        ])))
+        .with_stage(MeasurementStage::RemoveBias(DVector::from_vec(vec![
+            1.0, 1.0, 1.0,
+        ])))
+        .with_stage(MeasurementStage::Deadband(DVector::from_vec(vec![
+            0.5, 0.5, 0.5,
+        ])));
+    let result = pipeline.apply(&DVector::from_vec(vec![50.0, 12.0, 100.0]));
+    assert_eq!(result[0], 4.0);
+    assert_eq!(result[1], 0.0);
+    assert!(result[2].is_nan());
+}
@@ -0,0 +1,48 @@
+//! Readable formatting of matrices and estimates for logging
+
+use core::fmt::Display;
+
+use na::{Dim, Matrix, RawStorage};
+use nalgebra as na;
+
+/// Format a matrix or vector as indented rows with three decimal places
+///
+/// The result starts with a newline, so it can follow a label, e.g.
+/// `format!("covariance {}", pretty_print(&covariance))`.
+pub fn pretty_print<T, R, C, S>(arr: &Matrix<T, R, C, S>) -> String
+where
+    T: Display,
+    R: Dim,
+    C: Dim,
+    S: RawStorage<T, R, C>,
+{
+    let indent = 4;
+    let prefix = " ".repeat(indent);
+    let mut result_els = vec!["".to_string()];
+    for i in 0..arr.nrows() {
+        let mut row_els = vec![];
+        for j in 0..arr.ncols() {
+            row_els.push(format!("{:12.3}", arr[(i, j)]));
+        }
+        let row_str = row_els.join(" ");
+        let row_str = format!("{}{}", prefix, row_str);
+        result_els.push(row_str);
+    }
+    result_els.join("\n")
+}
+
+#[test]
+fn test_pretty_print() {
+    let m = na::DMatrix::from_row_slice(2, 2, &[1.0, 0.5, 0.5, 2.0]);
+    assert_eq!(
+        pretty_print(&m),
+        "\n           1.000        0.500\n           0.500        2.000"
+    );
+
+    let estimate = crate::StateAndCovariance::new(na::DVector::from_vec(vec![1.0, -2.0]), m * 0.01);
+    assert_eq!(
+        estimate.summary(),
+        "       1.000 ± 0.100\n      -2.000 ± 0.141"
+    );
+    assert!(format!("{}", estimate).starts_with("state:\n"));
+}
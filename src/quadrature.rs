@@ -0,0 +1,92 @@
+//! Gauss-Hermite quadrature points for the quadrature Kalman filter
+
+use na::{DMatrix, DVector, RealField};
+use nalgebra as na;
+
+use crate::{SigmaPointStrategy, SigmaPoints, UnscentedKalmanFilter};
+
+/// Gauss-Hermite quadrature points
+///
+/// The points are the tensor product of the `order`-point Gauss-Hermite rule
+/// in each dimension, transformed by the Cholesky factor of the covariance.
+/// Expectations of polynomials up to degree `2 order - 1` are exact, where
+/// the symmetric sigma points are exact only up to degree three. The number
+/// of points, `order^n`, grows exponentially with the state dimension `n`,
+/// so this suits low-dimensional, strongly non-linear problems.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct GaussHermiteSigmaPoints {
+    /// The number of points per dimension, at least one.
+    pub order: usize,
+}
+
+/// The unscented Kalman filter with Gauss-Hermite points, known as the
+/// quadrature Kalman filter or Gauss-Hermite filter
+pub type GaussHermiteKalmanFilter<'a, R> = UnscentedKalmanFilter<'a, R, GaussHermiteSigmaPoints>;
+
+/// The nodes and weights of the `order`-point Gauss-Hermite rule for the
+/// standard normal distribution, by the Golub-Welsch algorithm.
+fn gauss_hermite_rule<R: RealField>(order: usize) -> (DVector<R>, DVector<R>) {
+    // The Jacobi matrix of the probabilists' Hermite polynomials.
+    let jacobi = DMatrix::<R>::from_fn(order, order, |i, j| {
+        if i + 1 == j || j + 1 == i {
+            na::convert::<f64, R>(i.max(j) as f64).sqrt()
+        } else {
+            R::zero()
+        }
+    });
+    let eigen = na::linalg::SymmetricEigen::new(jacobi);
+    let weights = eigen.eigenvectors.row(0).transpose().map(|v| v.clone() * v);
+    (eigen.eigenvalues, weights)
+}
+
+impl<R> SigmaPointStrategy<R> for GaussHermiteSigmaPoints
+where
+    R: RealField,
+{
+    fn sigma_points_from_sqrt(&self, mean: &DVector<R>, sqrt: &DMatrix<R>) -> SigmaPoints<R> {
+        let n = mean.nrows();
+        let (nodes, node_weights) = gauss_hermite_rule::<R>(self.order.max(1));
+        let m = nodes.nrows();
+        let count = m.pow(n as u32);
+
+        let mut unit = DMatrix::<R>::zeros(n, count);
+        let mut weights = DVector::<R>::from_element(count, R::one());
+        for k in 0..count {
+            // The digits of k in base m index the node in each dimension.
+            let mut index = k;
+            for i in 0..n {
+                unit[(i, k)] = nodes[index % m].clone();
+                weights[k] *= node_weights[index % m].clone();
+                index /= m;
+            }
+        }
+        let points = sqrt * unit + DMatrix::from_fn(n, count, |i, _| mean[i].clone());
+        SigmaPoints::new(points, weights.clone(), weights)
+    }
+}
+
+#[test]
+fn test_gauss_hermite() {
+    use crate::unscented_transform;
+
+    // The three-point rule integrates x^4 exactly, giving the Gaussian
+    // kurtosis, which the symmetric sigma points with κ = 0 do not.
+    let mean = DVector::from_element(1, 0.0);
+    let covariance = DMatrix::from_element(1, 1, 1.0);
+    let square = |x: &DVector<f64>| x.map(|v| v * v);
+    let strategy = GaussHermiteSigmaPoints { order: 3 };
+    let (m, P, _) = unscented_transform(&mean, &covariance, square, &strategy).unwrap();
+    approx::assert_relative_eq!(m[0], 1.0, epsilon = 1e-12);
+    approx::assert_relative_eq!(P[(0, 0)], 2.0, epsilon = 1e-12);
+
+    // A strongly non-linear function of two variables is integrated
+    // accurately: E[cos(x) cos(y)] = exp(-(σx² + σy²) / 2).
+    let mean = DVector::from_vec(vec![0.0, 0.0]);
+    let covariance = DMatrix::from_diagonal(&DVector::from_vec(vec![4.0, 1.0]));
+    let f = |x: &DVector<f64>| DVector::from_element(1, x[0].cos() * x[1].cos());
+    let strategy = GaussHermiteSigmaPoints { order: 20 };
+    let points = strategy.sigma_points(&mean, &covariance).unwrap();
+    assert_eq!(points.points().ncols(), 400);
+    let (m, _, _) = unscented_transform(&mean, &covariance, f, &strategy).unwrap();
+    approx::assert_relative_eq!(m[0], (-2.5f64).exp(), epsilon = 1e-8);
+}
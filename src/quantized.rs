@@ -0,0 +1,114 @@
+//! Accounting for the quantization of measurements
+
+use alloc::borrow::Cow;
+
+use na::{DMatrix, DVector, RealField};
+use nalgebra as na;
+
+use crate::ObservationModel;
+
+/// An observation model whose measurements are quantized with a known step
+///
+/// A reading of an analog-to-digital converter with least significant bit
+/// `q` differs from the value by up to `q / 2`. When `q` is not small
+/// compared with the noise, this error is not negligible, and a filter
+/// ignoring it becomes overconfident. Modelling the quantization error as
+/// uniform and independent of the noise, its variance `q^2 / 12`
+/// (Sheppard's correction) is added to the diagonal of `R` of the wrapped
+/// model, one step per component.
+pub struct QuantizedObservationModel<'a, R>
+where
+    R: RealField,
+{
+    inner: &'a dyn ObservationModel<R>,
+    R: DMatrix<R>,
+}
+
+impl<'a, R> QuantizedObservationModel<'a, R>
+where
+    R: RealField,
+{
+    /// Wrap `inner`, whose measurements have the quantization steps
+    /// `steps`, one per component; a step of zero is not quantized.
+    ///
+    /// # Panics
+    ///
+    /// Panics if there is not one step per observation component.
+    pub fn new(inner: &'a dyn ObservationModel<R>, steps: &DVector<R>) -> Self {
+        assert_eq!(steps.nrows(), inner.obs_dim());
+        let twelfth: R = na::convert(1.0 / 12.0);
+        let variance = steps.map(|q| q.clone() * q * twelfth.clone());
+        let R = inner.R() + DMatrix::from_diagonal(&variance);
+        Self { inner, R }
+    }
+
+    /// The quantization variance of each component, the diagonal added to
+    /// the `R` of the wrapped model.
+    pub fn quantization_variance(&self) -> DVector<R> {
+        (&self.R - self.inner.R()).diagonal()
+    }
+}
+
+impl<'a, R> ObservationModel<R> for QuantizedObservationModel<'a, R>
+where
+    R: RealField,
+{
+    fn predict_observation(&self, state: &DVector<R>) -> DVector<R> {
+        self.inner.predict_observation(state)
+    }
+    fn H(&self) -> &DMatrix<R> {
+        self.inner.H()
+    }
+    fn HT(&self) -> Cow<'_, DMatrix<R>> {
+        self.inner.HT()
+    }
+    fn jacobian_at(&self, state: &DVector<R>) -> Cow<'_, DMatrix<R>> {
+        self.inner.jacobian_at(state)
+    }
+    fn R(&self) -> &DMatrix<R> {
+        &self.R
+    }
+    fn state_dim(&self) -> usize {
+        self.inner.state_dim()
+    }
+    fn obs_dim(&self) -> usize {
+        self.inner.obs_dim()
+    }
+}
+
+#[test]
+fn test_quantized_observations() {
+    use crate::test_util::{initial_estimate, ConstantVelocity, Normals, PositionObservation};
+    use crate::KalmanFilterNoControl;
+
+    let transition = ConstantVelocity::new(0.1, 1e-4);
+    let fine = PositionObservation::new(1e-4);
+    let quantized = QuantizedObservationModel::new(&fine, &DVector::from_element(1, 1.0));
+    approx::assert_relative_eq!(quantized.R()[(0, 0)], 1e-4 + 1.0 / 12.0);
+    approx::assert_relative_eq!(quantized.quantization_variance()[0], 1.0 / 12.0);
+
+    // A target at unit speed read by a coarse converter.
+    let mut normals = Normals::new(4);
+    let observations: Vec<DVector<f64>> = (1..=300)
+        .map(|k| {
+            let reading = 0.1 * k as f64 + 0.01 * normals.sample();
+            DVector::from_element(1, reading.round())
+        })
+        .collect();
+    let average_nis = |model: &dyn ObservationModel<f64>| {
+        let kf = KalmanFilterNoControl::new(&transition, model);
+        let mut estimate = initial_estimate();
+        let mut total = 0.0;
+        for z in observations.iter() {
+            let prior = kf.predict(&estimate);
+            total += model.innovation(&prior, z).nis().unwrap();
+            estimate = kf.step(&estimate, z).unwrap();
+        }
+        total / observations.len() as f64
+    };
+    // The NIS is about its expected value of one only with the quantization
+    // accounted for.
+    assert!(average_nis(&fine) > 10.0);
+    let nis = average_nis(&quantized);
+    assert!(nis > 0.5 && nis < 2.0, "{}", nis);
+}
@@ -0,0 +1,582 @@
+//! Recording filter runs and replaying them to find differences
+//!
+//! A [Recording] holds every input to a run of the filter (the initial
+//! estimate, the observations and the covariance update method of each step)
+//! and every intermediate result (prior, innovation, gain and posterior). It
+//! can be written to a compact binary log, e.g. on an embedded target, and
+//! read back elsewhere to re-execute the run and compare the results, to
+//! track down differences between versions or platforms.
+//!
+//! The log stores each number as a little-endian `f64`, which is lossless
+//! for `f32` and `f64` filters.
+
+use na::{DMatrix, DVector, Dim, Matrix, RawStorage, RealField};
+use nalgebra as na;
+
+use alloc::borrow::Cow;
+
+use crate::{
+    check_dimension, is_nan, linalg, CovarianceUpdateMethod, Error, ErrorKind, Innovation,
+    KalmanFilterNoControl, StateAndCovariance,
+};
+
+const MAGIC: &[u8; 4] = b"KFRC";
+const VERSION: u8 = 1;
+
+/// The inputs and intermediate results of one step
+#[derive(Debug, Clone)]
+pub struct StepRecord<R>
+where
+    R: RealField,
+{
+    /// The observation.
+    pub observation: DVector<R>,
+    /// The covariance update method.
+    pub covariance_update_method: CovarianceUpdateMethod,
+    /// The prior, after prediction.
+    pub prior: StateAndCovariance<R>,
+    /// The innovation, if the observation was used.
+    pub innovation: Option<Innovation<R>>,
+    /// The Kalman gain, if the observation was used and the innovation
+    /// covariance could be inverted.
+    pub gain: Option<DMatrix<R>>,
+    /// The posterior.
+    pub posterior: StateAndCovariance<R>,
+}
+
+/// The largest absolute differences between two recordings at one step
+///
+/// A quantity present in only one of the recordings, e.g. an innovation for
+/// an observation used in one run but not the other, differs by infinity.
+#[derive(Debug, Clone, PartialEq)]
+pub struct StepDifference<R>
+where
+    R: RealField,
+{
+    /// The index of the step.
+    pub step: usize,
+    /// The difference of the priors, states and covariances.
+    pub prior: R,
+    /// The difference of the innovations, residuals and covariances.
+    pub innovation: R,
+    /// The difference of the gains.
+    pub gain: R,
+    /// The difference of the posteriors, states and covariances.
+    pub posterior: R,
+}
+
+impl<R> StepDifference<R>
+where
+    R: RealField,
+{
+    /// The largest of the differences.
+    pub fn max(&self) -> R {
+        self.prior
+            .clone()
+            .max(self.innovation.clone())
+            .max(self.gain.clone())
+            .max(self.posterior.clone())
+    }
+}
+
+/// A record of a run of the filter
+#[derive(Debug, Clone)]
+pub struct Recording<R>
+where
+    R: RealField,
+{
+    initial_estimate: StateAndCovariance<R>,
+    steps: Vec<StepRecord<R>>,
+}
+
+impl<R> Recording<R>
+where
+    R: RealField,
+{
+    /// Start a recording of a run from an initial estimate.
+    pub fn new(initial_estimate: StateAndCovariance<R>) -> Self {
+        Self {
+            initial_estimate,
+            steps: Vec::new(),
+        }
+    }
+
+    /// Get a reference to the initial estimate.
+    pub fn initial_estimate(&self) -> &StateAndCovariance<R> {
+        &self.initial_estimate
+    }
+
+    /// Get a reference to the recorded steps.
+    pub fn steps(&self) -> &[StepRecord<R>] {
+        &self.steps
+    }
+
+    /// Re-execute the recorded run with a filter
+    ///
+    /// Starting from the recorded initial estimate, each recorded
+    /// observation is processed with its recorded covariance update method.
+    /// The new recording and its differences from this one are returned.
+    pub fn replay(
+        &self,
+        filter: &KalmanFilterNoControl<R>,
+    ) -> Result<(Recording<R>, Vec<StepDifference<R>>), Error> {
+        let mut replayed = Recording::new(self.initial_estimate.clone());
+        let mut estimate = self.initial_estimate.clone();
+        for (i, step) in self.steps.iter().enumerate() {
+            estimate = filter
+                .step_recorded(
+                    &estimate,
+                    &step.observation,
+                    step.covariance_update_method,
+                    &mut replayed,
+                )
+                .map_err(|e| e.with_step(i))?;
+        }
+        let differences = self.diff(&replayed);
+        Ok((replayed, differences))
+    }
+
+    /// The differences from another recording, for each step recorded in
+    /// both.
+    pub fn diff(&self, other: &Recording<R>) -> Vec<StepDifference<R>> {
+        let infinity: R = na::convert(f64::INFINITY);
+        let optional = |a: Option<R>| a.unwrap_or_else(|| infinity.clone());
+        self.steps
+            .iter()
+            .zip(other.steps.iter())
+            .enumerate()
+            .map(|(step, (a, b))| StepDifference {
+                step,
+                prior: estimate_difference(&a.prior, &b.prior),
+                innovation: optional(match (&a.innovation, &b.innovation) {
+                    (Some(a), Some(b)) => Some(
+                        difference(a.residual(), b.residual())
+                            .max(difference(a.covariance(), b.covariance())),
+                    ),
+                    (None, None) => Some(R::zero()),
+                    _ => None,
+                }),
+                gain: optional(match (&a.gain, &b.gain) {
+                    (Some(a), Some(b)) => Some(difference(a, b)),
+                    (None, None) => Some(R::zero()),
+                    _ => None,
+                }),
+                posterior: estimate_difference(&a.posterior, &b.posterior),
+            })
+            .collect()
+    }
+
+    /// Encode the recording as a binary log
+    ///
+    /// An [ErrorKind::DimensionMismatch] error is returned if the steps do
+    /// not all have observations of the same size, and an
+    /// [ErrorKind::InvalidRecording] error if a dimension or the number of
+    /// steps does not fit the log's 32-bit header fields.
+    pub fn to_bytes(&self) -> Result<Vec<u8>, Error> {
+        let ss = self.initial_estimate.state().nrows();
+        let os = self.steps.first().map_or(0, |s| s.observation.nrows());
+        for (i, step) in self.steps.iter().enumerate() {
+            check_dimension("observation", (os, 1), step.observation.shape())
+                .map_err(|e| e.with_step(i))?;
+        }
+        let mut out = Vec::new();
+        out.extend_from_slice(MAGIC);
+        out.push(VERSION);
+        for n in [ss, os, self.steps.len()] {
+            let n = u32::try_from(n).map_err(|_| Error::from(ErrorKind::InvalidRecording))?;
+            out.extend_from_slice(&n.to_le_bytes());
+        }
+        write_estimate(&mut out, &self.initial_estimate);
+        for step in self.steps.iter() {
+            out.push(match step.covariance_update_method {
+                CovarianceUpdateMethod::OptimalKalman => 0,
+                CovarianceUpdateMethod::OptimalKalmanForcedSymmetric => 1,
+                CovarianceUpdateMethod::JosephForm => 2,
+            });
+            let flags = step.innovation.is_some() as u8 | (step.gain.is_some() as u8) << 1;
+            out.push(flags);
+            write_matrix(&mut out, &step.observation);
+            write_estimate(&mut out, &step.prior);
+            if let Some(innovation) = &step.innovation {
+                write_matrix(&mut out, innovation.residual());
+                write_matrix(&mut out, innovation.covariance());
+            }
+            if let Some(gain) = &step.gain {
+                write_matrix(&mut out, gain);
+            }
+            write_estimate(&mut out, &step.posterior);
+        }
+        Ok(out)
+    }
+
+    /// Decode a binary log written by [to_bytes](Self::to_bytes)
+    ///
+    /// An [ErrorKind::InvalidRecording] error is returned if the log is
+    /// truncated or was not written by this version.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, Error> {
+        let mut reader = Reader { bytes, pos: 0 };
+        if reader.take(4)? != MAGIC || reader.take(1)?[0] != VERSION {
+            return Err(ErrorKind::InvalidRecording.into());
+        }
+        let ss = reader.read_u32()?;
+        let os = reader.read_u32()?;
+        let n = reader.read_u32()?;
+        let initial_estimate = reader.read_estimate(ss)?;
+        // The step count is not trusted to size the allocation: a step
+        // takes at least its flags and its observation, prior and
+        // posterior.
+        let step_size = [os, ss, ss, ss, ss]
+            .iter()
+            .zip([1, 1, ss, 1, ss])
+            .try_fold(2usize, |acc, (a, b)| {
+                acc.checked_add(a.checked_mul(b)?.checked_mul(8)?)
+            })
+            .ok_or_else(|| Error::from(ErrorKind::InvalidRecording))?;
+        let mut steps = Vec::with_capacity(n.min(reader.remaining() / step_size));
+        for _ in 0..n {
+            let covariance_update_method = match reader.take(1)?[0] {
+                0 => CovarianceUpdateMethod::OptimalKalman,
+                1 => CovarianceUpdateMethod::OptimalKalmanForcedSymmetric,
+                2 => CovarianceUpdateMethod::JosephForm,
+                _ => return Err(ErrorKind::InvalidRecording.into()),
+            };
+            let flags = reader.take(1)?[0];
+            let observation = reader.read_vector(os)?;
+            let prior = reader.read_estimate(ss)?;
+            let innovation = if flags & 1 != 0 {
+                let residual = reader.read_vector(os)?;
+                Some(Innovation::new(residual, reader.read_matrix(os, os)?))
+            } else {
+                None
+            };
+            let gain = if flags & 2 != 0 {
+                Some(reader.read_matrix(ss, os)?)
+            } else {
+                None
+            };
+            let posterior = reader.read_estimate(ss)?;
+            steps.push(StepRecord {
+                observation,
+                covariance_update_method,
+                prior,
+                innovation,
+                gain,
+                posterior,
+            });
+        }
+        Ok(Self {
+            initial_estimate,
+            steps,
+        })
+    }
+}
+
+impl<'a, R> KalmanFilterNoControl<'a, R>
+where
+    R: RealField,
+{
+    /// Perform prediction and update steps, recording the inputs and
+    /// intermediate results
+    ///
+    /// This gives the same result as
+    /// [step_with_options](Self::step_with_options). The recorded innovation
+    /// and gain are those of the update that produced the posterior: if the
+    /// [FailurePolicy](crate::FailurePolicy) repaired the prior covariance,
+    /// they are computed from the repaired prior, and if it skipped the
+    /// update, none are recorded.
+    pub fn step_recorded(
+        &self,
+        previous_estimate: &StateAndCovariance<R>,
+        observation: &DVector<R>,
+        covariance_update_method: CovarianceUpdateMethod,
+        recording: &mut Recording<R>,
+    ) -> Result<StateAndCovariance<R>, Error> {
+        let step = self.record_step(previous_estimate, observation, covariance_update_method)?;
+        let posterior = step.posterior.clone();
+        recording.steps.push(step);
+        Ok(posterior)
+    }
+
+    /// Perform prediction and update steps as
+    /// [step_recorded](Self::step_recorded), returning the record of the
+    /// step.
+    pub(crate) fn record_step(
+        &self,
+        previous_estimate: &StateAndCovariance<R>,
+        observation: &DVector<R>,
+        covariance_update_method: CovarianceUpdateMethod,
+    ) -> Result<StepRecord<R>, Error> {
+        self.check_dimensions(previous_estimate, Some(observation))?;
+        let prior = self.predict(previous_estimate);
+        let updated = if observation.iter().any(|x| is_nan(x.clone())) {
+            None
+        } else {
+            self.repaired_update(
+                &prior,
+                observation,
+                self.observation_matrix.R(),
+                covariance_update_method,
+            )?
+        };
+        let (innovation, gain, posterior) = match updated {
+            Some((used, posterior)) => {
+                let innovation = self.observation_matrix.innovation(&used, observation);
+                let HT = match self.observation_matrix.jacobian_at(used.state()) {
+                    Cow::Borrowed(_) => self.observation_matrix.HT(),
+                    Cow::Owned(H) => Cow::Owned(H.transpose()),
+                };
+                let gain = linalg::cholesky_inverse(innovation.covariance())
+                    .map(|s_inv| used.covariance() * &*HT * s_inv);
+                (Some(innovation), gain, posterior)
+            }
+            None => (None, None, prior.clone()),
+        };
+        Ok(StepRecord {
+            observation: observation.clone(),
+            covariance_update_method,
+            prior,
+            innovation,
+            gain,
+            posterior,
+        })
+    }
+}
+
+fn difference<R, D1, D2, S>(a: &Matrix<R, D1, D2, S>, b: &Matrix<R, D1, D2, S>) -> R
+where
+    R: RealField,
+    D1: Dim,
+    D2: Dim,
+    S: RawStorage<R, D1, D2>,
+{
+    if a.shape() != b.shape() {
+        return na::convert(f64::INFINITY);
+    }
+    a.iter().zip(b.iter()).fold(R::zero(), |acc, (a, b)| {
+        acc.max((a.clone() - b.clone()).abs())
+    })
+}
+
+fn estimate_difference<R: RealField>(a: &StateAndCovariance<R>, b: &StateAndCovariance<R>) -> R {
+    difference(a.state(), b.state()).max(difference(a.covariance(), b.covariance()))
+}
+
+fn write_matrix<R, D1, D2, S>(out: &mut Vec<u8>, m: &Matrix<R, D1, D2, S>)
+where
+    R: RealField,
+    D1: Dim,
+    D2: Dim,
+    S: RawStorage<R, D1, D2>,
+{
+    for x in m.iter() {
+        let x: f64 = na::try_convert(x.clone()).unwrap_or(f64::NAN);
+        out.extend_from_slice(&x.to_le_bytes());
+    }
+}
+
+fn write_estimate<R: RealField>(out: &mut Vec<u8>, estimate: &StateAndCovariance<R>) {
+    write_matrix(out, estimate.state());
+    write_matrix(out, estimate.covariance());
+}
+
+struct Reader<'b> {
+    bytes: &'b [u8],
+    pos: usize,
+}
+
+impl<'b> Reader<'b> {
+    fn take(&mut self, n: usize) -> Result<&'b [u8], Error> {
+        let end = self.pos + n;
+        if end > self.bytes.len() {
+            return Err(ErrorKind::InvalidRecording.into());
+        }
+        let taken = &self.bytes[self.pos..end];
+        self.pos = end;
+        Ok(taken)
+    }
+
+    fn remaining(&self) -> usize {
+        self.bytes.len() - self.pos
+    }
+
+    fn read_u32(&mut self) -> Result<usize, Error> {
+        let mut buf = [0; 4];
+        buf.copy_from_slice(self.take(4)?);
+        Ok(u32::from_le_bytes(buf) as usize)
+    }
+
+    fn read_matrix<R: RealField>(
+        &mut self,
+        nrows: usize,
+        ncols: usize,
+    ) -> Result<DMatrix<R>, Error> {
+        let len = nrows
+            .checked_mul(ncols)
+            .filter(|&len| len <= self.remaining() / 8)
+            .ok_or_else(|| Error::from(ErrorKind::InvalidRecording))?;
+        let mut values = Vec::with_capacity(len);
+        for _ in 0..len {
+            let mut buf = [0; 8];
+            buf.copy_from_slice(self.take(8)?);
+            values.push(na::convert(f64::from_le_bytes(buf)));
+        }
+        Ok(DMatrix::from_vec(nrows, ncols, values))
+    }
+
+    fn read_vector<R: RealField>(&mut self, n: usize) -> Result<DVector<R>, Error> {
+        Ok(self.read_matrix(n, 1)?.column(0).into_owned())
+    }
+
+    fn read_estimate<R: RealField>(&mut self, n: usize) -> Result<StateAndCovariance<R>, Error> {
+        let state = self.read_vector(n)?;
+        Ok(StateAndCovariance::new(state, self.read_matrix(n, n)?))
+    }
+}
+
+#[test]
+fn test_record_and_replay() {
+    use crate::test_util::{
+        initial_estimate, simulate_positions, ConstantVelocity, PositionObservation,
+    };
+
+    let transition = ConstantVelocity::new(0.1, 1.0);
+    let observation = PositionObservation::new(0.5);
+    let kf = KalmanFilterNoControl::new(&transition, &observation);
+    let mut observations = simulate_positions(10, 0.1, 1.0, 0.5, 6);
+    observations[4][0] = f64::NAN;
+    let mut recording = Recording::new(initial_estimate());
+    let mut estimate = initial_estimate();
+    for z in observations.iter() {
+        estimate = kf
+            .step_recorded(
+                &estimate,
+                z,
+                CovarianceUpdateMethod::JosephForm,
+                &mut recording,
+            )
+            .unwrap();
+    }
+    assert!(recording.steps()[4].innovation.is_none());
+
+    // The log round-trips, and replaying with the same filter reproduces
+    // the run exactly.
+    let bytes = recording.to_bytes().unwrap();
+    let decoded = Recording::<f64>::from_bytes(&bytes).unwrap();
+    let (_, differences) = decoded.replay(&kf).unwrap();
+    assert_eq!(differences.len(), 10);
+    assert!(differences.iter().all(|d| d.max() == 0.0));
+
+    // A different model shows up as differing from the first update on.
+    let other = PositionObservation::new(0.6);
+    let kf = KalmanFilterNoControl::new(&transition, &other);
+    let (_, differences) = decoded.replay(&kf).unwrap();
+    assert_eq!(differences[0].prior, 0.0);
+    assert!(differences[0].gain > 0.0);
+
+    assert!(Recording::<f64>::from_bytes(&bytes[..bytes.len() - 1]).is_err());
+}
+
+#[test]
+fn test_corrupt_recording() {
+    let is_invalid = |bytes: &[u8]| match Recording::<f64>::from_bytes(bytes) {
+        Err(e) => matches!(e.kind(), ErrorKind::InvalidRecording),
+        Ok(_) => false,
+    };
+    let header = |ss: u32, os: u32, n: u32| {
+        let mut bytes = MAGIC.to_vec();
+        bytes.push(VERSION);
+        for x in [ss, os, n] {
+            bytes.extend_from_slice(&x.to_le_bytes());
+        }
+        bytes
+    };
+    assert!(is_invalid(b"KFR"));
+    assert!(is_invalid(b"XXXX\x01"));
+
+    // A header claiming huge dimensions or step counts is rejected without
+    // attempting the allocations.
+    assert!(is_invalid(&header(u32::MAX, u32::MAX, u32::MAX)));
+    let mut bytes = header(1, 1, u32::MAX);
+    bytes.extend_from_slice(&[0; 16]);
+    assert_eq!(bytes.len(), 33);
+    assert!(is_invalid(&bytes));
+
+    // An empty run round-trips.
+    let empty = Recording::new(StateAndCovariance::new(
+        DVector::from_element(1, 1.0),
+        DMatrix::identity(1, 1),
+    ));
+    let decoded = Recording::<f64>::from_bytes(&empty.to_bytes().unwrap()).unwrap();
+    assert!(decoded.steps().is_empty());
+    assert_eq!(decoded.initial_estimate(), empty.initial_estimate());
+}
+
+#[test]
+fn test_recorded_update() {
+    use crate::test_util::{ConstantVelocity, MatrixObservation};
+    use crate::FailurePolicy;
+
+    // The indefinite prior of the failure policy tests: skipping the update
+    // records no innovation or gain, and inflating the prior covariance to
+    // [[2, 1], [1, 2]] records the gain [0.5, -0.5] of the repaired update.
+    let stationary = ConstantVelocity::new(0.0, 0.0);
+    let difference = MatrixObservation::new(
+        DMatrix::from_row_slice(1, 2, &[1.0, -1.0]),
+        DMatrix::zeros(1, 1),
+    );
+    let initial = StateAndCovariance::new(
+        DVector::from_vec(vec![0.0, 0.0]),
+        DMatrix::from_row_slice(2, 2, &[1.0, 1.0 + 1e-9, 1.0 + 1e-9, 1.0]),
+    );
+    let z = DVector::from_element(1, 0.1);
+    let kf = KalmanFilterNoControl::new(&stationary, &difference)
+        .with_failure_policy(FailurePolicy::SkipUpdate);
+    let mut recording = Recording::new(initial.clone());
+    let method = CovarianceUpdateMethod::JosephForm;
+    let posterior = kf
+        .step_recorded(&initial, &z, method, &mut recording)
+        .unwrap();
+    assert_eq!(posterior, initial);
+    assert!(recording.steps()[0].innovation.is_none());
+    assert!(recording.steps()[0].gain.is_none());
+
+    let kf = kf.with_failure_policy(FailurePolicy::InflateAndRetry { factor: 2.0 });
+    kf.step_recorded(&initial, &z, method, &mut recording)
+        .unwrap();
+    let step = &recording.steps()[1];
+    approx::assert_relative_eq!(
+        step.innovation.as_ref().unwrap().covariance()[(0, 0)],
+        2.0,
+        epsilon = 1e-8
+    );
+    let expected = DMatrix::from_column_slice(2, 1, &[0.5, -0.5]);
+    approx::assert_relative_eq!(step.gain.as_ref().unwrap(), &expected, epsilon = 1e-8);
+
+    // The gain of a non-linear model uses its Jacobian at the prior, so the
+    // recorded gain and residual give the posterior state.
+    let nonlinear = crate::nonlinear::NumericalObservationModel::new(
+        |x: &DVector<f64>| DVector::from_vec(vec![x.norm(), x[0] * x[1], x[1].exp()]),
+        DMatrix::from_diagonal_element(3, 3, 0.1),
+        1e-7,
+        &DVector::from_vec(vec![3.0, 4.0]),
+    );
+    let kf = KalmanFilterNoControl::new(&stationary, &nonlinear);
+    let prior = StateAndCovariance::new(
+        DVector::from_vec(vec![1.0, -0.5]),
+        DMatrix::from_row_slice(2, 2, &[0.5, 0.1, 0.1, 0.3]),
+    );
+    let z = DVector::from_vec(vec![1.2, -0.4, 0.7]);
+    let posterior = kf
+        .step_recorded(&prior, &z, method, &mut recording)
+        .unwrap();
+    let step = &recording.steps()[2];
+    let residual = step.innovation.as_ref().unwrap().residual();
+    let expected = prior.state() + step.gain.as_ref().unwrap() * residual;
+    approx::assert_relative_eq!(posterior.state(), &expected, epsilon = 1e-10);
+
+    // The log has a single observation size.
+    match recording.to_bytes() {
+        Err(e) => assert!(matches!(e.kind(), ErrorKind::DimensionMismatch { .. })),
+        Ok(_) => panic!("observations of different sizes were encoded"),
+    }
+}
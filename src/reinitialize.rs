@@ -0,0 +1,167 @@
+//! Re-initializing estimates from absolute measurements
+
+use alloc::vec::Vec;
+
+use na::{DMatrix, DVector, RealField};
+use nalgebra as na;
+
+use crate::{is_nan, linalg, Error, ErrorKind, KalmanFilterNoControl, StateAndCovariance};
+
+impl<'a, R> KalmanFilterNoControl<'a, R>
+where
+    R: RealField,
+{
+    /// Create an estimate from an absolute measurement alone, e.g. a GPS fix,
+    /// discarding any previous estimate
+    ///
+    /// The state is the weighted least squares solution of `y = H x`, and
+    /// its covariance is `(H^T R'^-1 H)^-1` with `R' = inflation R`. An
+    /// inflation above one makes the new estimate less confident than the
+    /// measurement alone, leaving room for the filter to settle. The
+    /// observation must determine the whole state: an
+    /// [ErrorKind::SingularMatrix] error is returned if `H` does not have
+    /// full column rank, and an [ErrorKind::MissingObservation] error if any
+    /// component of the observation is NaN. For a linearized model, `H` is
+    /// taken at the zero state.
+    pub fn reinitialize(
+        &self,
+        observation: &DVector<R>,
+        inflation: R,
+    ) -> Result<StateAndCovariance<R>, Error> {
+        let n = self.transition_model.state_dim();
+        let components: Vec<usize> = (0..n).collect();
+        let estimate = StateAndCovariance::new(DVector::zeros(n), DMatrix::zeros(n, n));
+        self.reset_components(&estimate, observation, &components, inflation)
+    }
+
+    /// Re-estimate selected components of an estimate from an absolute
+    /// measurement, keeping the others
+    ///
+    /// The selected components are found by weighted least squares from the
+    /// observation, treating the other components as known up to their
+    /// covariance, which is added to the inflated observation noise. The
+    /// cross-covariances between the reset and the kept components follow
+    /// from this dependence, so the result is a consistent estimate. This
+    /// recovers from the divergence of some components, e.g. the position,
+    /// without discarding the estimates of others, e.g. sensor biases.
+    ///
+    /// An [ErrorKind::DimensionMismatch] error is returned if a component
+    /// index is out of range, and otherwise the errors are those of
+    /// [reinitialize](Self::reinitialize), for the columns of `H` of the
+    /// selected components.
+    pub fn reset_components(
+        &self,
+        estimate: &StateAndCovariance<R>,
+        observation: &DVector<R>,
+        components: &[usize],
+        inflation: R,
+    ) -> Result<StateAndCovariance<R>, Error> {
+        let n = estimate.state().nrows();
+        if let Some(&i) = components.iter().find(|&&i| i >= n) {
+            return Err(ErrorKind::DimensionMismatch {
+                expected: (n, 1),
+                actual: (i + 1, 1),
+                matrix: "components",
+            }
+            .into());
+        }
+        if observation.iter().any(|x| is_nan(x.clone())) {
+            return Err(ErrorKind::MissingObservation.into());
+        }
+        let kept: Vec<usize> = (0..n).filter(|i| !components.contains(i)).collect();
+        let H = self.observation_matrix.H();
+        let H_reset = H.select_columns(components);
+        let H_kept = H.select_columns(&kept);
+        let P = estimate.covariance();
+        let P_kept = P.select_rows(&kept).select_columns(&kept);
+
+        // The kept components contribute their uncertainty to the noise of
+        // y - H_kept x_kept = H_reset x_reset + v.
+        let W = self.observation_matrix.R() * inflation
+            + linalg::matmul3(&H_kept, &P_kept, &H_kept.transpose());
+        let W_inv = linalg::cholesky_inverse(&W)
+            .ok_or_else(|| Error::from(ErrorKind::CovarianceNotPositiveSemiDefinite))?;
+        let HT_W_inv = H_reset.transpose() * &W_inv;
+        let information = &HT_W_inv * &H_reset;
+        // Round-off can let a singular matrix be factored, so check the rank
+        // relative to the largest entry.
+        let tolerance = information.amax() * R::default_epsilon() * na::convert(n as f64);
+        if information.rank(tolerance) < components.len() {
+            return Err(ErrorKind::SingularMatrix.into());
+        }
+        let P_reset = linalg::cholesky_inverse(&information)
+            .ok_or_else(|| Error::from(ErrorKind::SingularMatrix))?;
+        // x_reset = A (y - H_kept x_kept).
+        let A = &P_reset * HT_W_inv;
+
+        // Since A H_reset = I, x_reset = x_reset_old + A (y - H x_old), which
+        // uses the linearization point of the model.
+        let residual = observation
+            - self
+                .observation_matrix
+                .predict_observation(estimate.state());
+        let correction = &A * residual;
+        // The covariance of x_reset is A W A^T = P_reset, and its
+        // cross-covariance with x_kept is -A H_kept P_kept.
+        let cross = -linalg::matmul3(&A, &H_kept, &P_kept);
+
+        let mut state = estimate.state().clone();
+        let mut covariance = P.clone();
+        for (j, &i) in components.iter().enumerate() {
+            state[i] += correction[j].clone();
+            for (l, &k) in components.iter().enumerate() {
+                covariance[(i, k)] = P_reset[(j, l)].clone();
+            }
+            for (l, &k) in kept.iter().enumerate() {
+                covariance[(i, k)] = cross[(j, l)].clone();
+                covariance[(k, i)] = cross[(j, l)].clone();
+            }
+        }
+        Ok(StateAndCovariance::new(state, covariance))
+    }
+}
+
+#[test]
+fn test_reinitialize() {
+    use crate::test_util::{ConstantVelocity, MatrixObservation};
+    use crate::ObservationModel;
+
+    // Observing position and velocity determines the whole state.
+    let transition = ConstantVelocity::new(0.1, 1.0);
+    let observation = MatrixObservation::new(
+        DMatrix::identity(2, 2),
+        DMatrix::from_row_slice(2, 2, &[0.5, 0.0, 0.0, 0.2]),
+    );
+    let kf = KalmanFilterNoControl::new(&transition, &observation);
+    let z = DVector::from_vec(vec![3.0, -1.0]);
+    let estimate = kf.reinitialize(&z, 4.0).unwrap();
+    approx::assert_relative_eq!(estimate.state(), &z, epsilon = 1e-12);
+    approx::assert_relative_eq!(
+        estimate.covariance(),
+        &(observation.R() * 4.0),
+        epsilon = 1e-12
+    );
+
+    // Resetting a diverged position from a fix of position plus velocity,
+    // keeping the velocity estimate.
+    let fix = MatrixObservation::new(
+        DMatrix::from_row_slice(1, 2, &[1.0, 1.0]),
+        DMatrix::from_element(1, 1, 0.5),
+    );
+    let kf = KalmanFilterNoControl::new(&transition, &fix);
+    let diverged = StateAndCovariance::new(
+        DVector::from_vec(vec![100.0, 2.0]),
+        DMatrix::from_row_slice(2, 2, &[1.0, 0.3, 0.3, 0.4]),
+    );
+    let z = DVector::from_element(1, 3.0);
+    let reset = kf.reset_components(&diverged, &z, &[0], 1.0).unwrap();
+    approx::assert_relative_eq!(reset.state()[0], 1.0, epsilon = 1e-12);
+    approx::assert_relative_eq!(reset.state()[1], 2.0, epsilon = 1e-12);
+    // x = y - v, so Var(x) = R + P_vv and Cov(x, v) = -P_vv.
+    let expected = DMatrix::from_row_slice(2, 2, &[0.9, -0.4, -0.4, 0.4]);
+    approx::assert_relative_eq!(reset.covariance(), &expected, epsilon = 1e-12);
+
+    // A single fix cannot determine both components.
+    assert!(kf.reinitialize(&z, 1.0).is_err());
+    assert!(kf.reset_components(&diverged, &z, &[2], 1.0).is_err());
+}
@@ -0,0 +1,199 @@
+//! Logging filter runs to a rerun recording stream
+//!
+//! [RerunLogger] logs each step of a filter to a [rerun](https://rerun.io)
+//! recording stream for visual debugging of tracker behavior. The prior and
+//! posterior state components, the innovation and the Kalman gain are logged
+//! as scalars, and the posterior position with its covariance ellipse as 2D
+//! geometry, all on a `step` timeline. The position is the pair of state
+//! components chosen with [RerunLogger::with_position].
+
+use na::{DMatrix, DVector, RealField};
+use nalgebra as na;
+use re_sdk::{AsComponents, RecordingStream};
+use re_sdk_types::archetypes::{LineStrips2D, Points2D, Scalars};
+
+use crate::simulate::psd_sqrt;
+use crate::{
+    CovarianceUpdateMethod, Error, ErrorKind, KalmanFilterNoControl, StateAndCovariance, StepRecord,
+};
+
+/// The number of segments of a covariance ellipse.
+const ELLIPSE_SEGMENTS: usize = 64;
+
+/// Logs filter steps to a rerun recording stream
+///
+/// Each step is logged at the next index of the `step` timeline, under the
+/// entity path given on construction:
+///
+/// * `prior/i` and `posterior/i`: component `i` of the state estimates
+/// * `innovation/i`: component `i` of the innovation, if the observation
+///   was used
+/// * `gain/i/j`: entry `(i, j)` of the Kalman gain, if it was computed
+/// * `position` and `covariance`: the posterior position and its one
+///   standard deviation ellipse
+pub struct RerunLogger {
+    stream: RecordingStream,
+    entity: String,
+    position: (usize, usize),
+    step: i64,
+}
+
+impl RerunLogger {
+    /// Log to `stream` under the entity path `entity`, with state
+    /// components 0 and 1 as the position.
+    pub fn new(stream: RecordingStream, entity: &str) -> Self {
+        Self {
+            stream,
+            entity: entity.to_string(),
+            position: (0, 1),
+            step: 0,
+        }
+    }
+
+    /// Use state components `x` and `y` as the position. If either is not a
+    /// component of the state, no position is logged.
+    pub fn with_position(mut self, x: usize, y: usize) -> Self {
+        self.position = (x, y);
+        self
+    }
+
+    /// The index on the `step` timeline of the next step to be logged.
+    pub fn step(&self) -> i64 {
+        self.step
+    }
+
+    /// Log a step, e.g. of a [Recording](crate::Recording) being replayed,
+    /// at the next index of the `step` timeline
+    ///
+    /// An [ErrorKind::LoggingFailed] error is returned if the stream
+    /// rejects the data.
+    pub fn log_step<R>(&mut self, step: &StepRecord<R>) -> Result<(), Error>
+    where
+        R: RealField,
+    {
+        self.stream.set_time_sequence("step", self.step);
+        self.log_vector("prior", step.prior.state())?;
+        self.log_vector("posterior", step.posterior.state())?;
+        if let Some(innovation) = &step.innovation {
+            self.log_vector("innovation", innovation.residual())?;
+        }
+        if let Some(gain) = &step.gain {
+            for i in 0..gain.nrows() {
+                self.log_vector(&format!("gain/{}", i), &gain.row(i).transpose())?;
+            }
+        }
+        let (x, y) = self.position;
+        let ss = step.posterior.state().nrows();
+        if x < ss && y < ss {
+            let state = step.posterior.state();
+            let mean = [to_f64(&state[x]), to_f64(&state[y])];
+            let covariance = step.posterior.covariance();
+            let block = DMatrix::from_fn(2, 2, |i, j| {
+                let index = [x, y];
+                to_f64(&covariance[(index[i], index[j])])
+            });
+            let point = [mean[0] as f32, mean[1] as f32];
+            self.log("position", &Points2D::new([point]))?;
+            let ellipse = covariance_ellipse(mean, &block);
+            self.log("covariance", &LineStrips2D::new([ellipse]))?;
+        }
+        self.step += 1;
+        Ok(())
+    }
+
+    fn log_vector<R: RealField>(&self, name: &str, vector: &DVector<R>) -> Result<(), Error> {
+        for (i, x) in vector.iter().enumerate() {
+            self.log(&format!("{}/{}", name, i), &Scalars::single(to_f64(x)))?;
+        }
+        Ok(())
+    }
+
+    fn log(&self, name: &str, data: &impl AsComponents) -> Result<(), Error> {
+        self.stream
+            .log(format!("{}/{}", self.entity, name), data)
+            .map_err(|_| ErrorKind::LoggingFailed.into())
+    }
+}
+
+impl<'a, R> KalmanFilterNoControl<'a, R>
+where
+    R: RealField,
+{
+    /// Perform prediction and update steps, logging the step to a rerun
+    /// recording stream
+    ///
+    /// This gives the same result as [step](Self::step). The logged
+    /// innovation and gain are those recorded by
+    /// [step_recorded](Self::step_recorded).
+    pub fn step_logged(
+        &self,
+        previous_estimate: &StateAndCovariance<R>,
+        observation: &DVector<R>,
+        logger: &mut RerunLogger,
+    ) -> Result<StateAndCovariance<R>, Error> {
+        let step = self.record_step(
+            previous_estimate,
+            observation,
+            CovarianceUpdateMethod::JosephForm,
+        )?;
+        logger.log_step(&step)?;
+        Ok(step.posterior)
+    }
+}
+
+fn to_f64<R: RealField>(x: &R) -> f64 {
+    na::try_convert(x.clone()).unwrap_or(f64::NAN)
+}
+
+/// The closed outline of the one standard deviation ellipse of a 2D
+/// Gaussian.
+fn covariance_ellipse(mean: [f64; 2], covariance: &DMatrix<f64>) -> Vec<[f32; 2]> {
+    let sqrt = psd_sqrt(covariance);
+    (0..=ELLIPSE_SEGMENTS)
+        .map(|k| {
+            let angle = core::f64::consts::TAU * k as f64 / ELLIPSE_SEGMENTS as f64;
+            let offset = &sqrt * na::Vector2::new(angle.cos(), angle.sin());
+            [(mean[0] + offset[0]) as f32, (mean[1] + offset[1]) as f32]
+        })
+        .collect()
+}
+
+#[test]
+fn test_covariance_ellipse() {
+    // Each point of the outline is at a Mahalanobis distance of one.
+    let covariance = DMatrix::from_row_slice(2, 2, &[2.0, 0.6, 0.6, 0.5]);
+    let inverse = covariance.clone().try_inverse().unwrap();
+    let ellipse = covariance_ellipse([1.0, -2.0], &covariance);
+    assert_eq!(ellipse.len(), ELLIPSE_SEGMENTS + 1);
+    assert_eq!(ellipse.first(), ellipse.last());
+    for point in ellipse.iter() {
+        let offset = DVector::from_vec(vec![point[0] as f64 - 1.0, point[1] as f64 + 2.0]);
+        let distance = (offset.transpose() * &inverse * &offset)[0];
+        approx::assert_relative_eq!(distance, 1.0, epsilon = 1e-5);
+    }
+}
+
+#[test]
+fn test_step_logged() {
+    use crate::test_util::{
+        initial_estimate, simulate_positions, ConstantVelocity, PositionObservation,
+    };
+
+    let transition = ConstantVelocity::new(0.1, 1.0);
+    let observation = PositionObservation::new(0.5);
+    let kf = KalmanFilterNoControl::new(&transition, &observation);
+    let (stream, storage) = re_sdk::RecordingStreamBuilder::new("kalman_test")
+        .memory()
+        .unwrap();
+    let mut logger = RerunLogger::new(stream, "tracker");
+    let observations = simulate_positions(5, 0.1, 1.0, 0.5, 2);
+    let mut expected = initial_estimate();
+    let mut estimate = initial_estimate();
+    for z in observations.iter() {
+        expected = kf.step(&expected, z).unwrap();
+        estimate = kf.step_logged(&estimate, z, &mut logger).unwrap();
+        assert_eq!(estimate, expected);
+    }
+    assert_eq!(logger.step(), 5);
+    assert!(storage.num_msgs() > 0);
+}
@@ -0,0 +1,69 @@
+use na::{DMatrix, RealField};
+use nalgebra as na;
+
+use crate::{Error, ErrorKind};
+
+const MAX_ITERATIONS: usize = 100_000;
+
+/// Solve the discrete algebraic Riccati equation of the Kalman filter
+///
+/// Returns the steady-state prior (predicted) covariance `P`, satisfying
+/// `P = F (P - P H^T (H P H^T + R)^-1 H P) F^T + Q`, found by iterating the
+/// covariance recursion until it converges.
+pub(crate) fn steady_state_prior_covariance<R>(
+    F: &DMatrix<R>,
+    Q: &DMatrix<R>,
+    H: &DMatrix<R>,
+    R: &DMatrix<R>,
+) -> Result<DMatrix<R>, Error>
+where
+    R: RealField,
+{
+    let tolerance: R = na::convert(1e-12);
+    let FT = F.transpose();
+    let HT = H.transpose();
+    let mut P = Q.clone();
+    for _ in 0..MAX_ITERATIONS {
+        let s = H * &P * &HT + R;
+        let s_chol = match na::linalg::Cholesky::new(s) {
+            Some(v) => v,
+            None => return Err(ErrorKind::CovarianceNotPositiveSemiDefinite.into()),
+        };
+        let PHT = &P * &HT;
+        let posterior = &P - &PHT * s_chol.solve(&PHT.transpose());
+        // Symmetrize, or rounding errors in the antisymmetric part grow by
+        // the square of the spectral radius of F at each iteration, which
+        // exceeds one with fading memory.
+        let next = (F * posterior * &FT + Q).symmetric_part();
+        if next.iter().any(|x| !x.is_finite()) {
+            // The covariance diverged, e.g. of an unobserved, unstable state.
+            break;
+        }
+        let scale = next.amax().max(R::one());
+        let change = (&next - &P).amax();
+        P = next;
+        if change <= tolerance.clone() * scale {
+            return Ok(P.symmetric_part());
+        }
+    }
+    Err(ErrorKind::NotConverged.into())
+}
+
+/// Compute the Kalman gain `P H^T (H P H^T + R)^-1` for prior covariance `P`.
+pub(crate) fn kalman_gain<R>(
+    P: &DMatrix<R>,
+    H: &DMatrix<R>,
+    R: &DMatrix<R>,
+) -> Result<DMatrix<R>, Error>
+where
+    R: RealField,
+{
+    let PHT = P * H.transpose();
+    let s = H * &PHT + R;
+    let s_chol = match na::linalg::Cholesky::new(s) {
+        Some(v) => v,
+        None => return Err(ErrorKind::CovarianceNotPositiveSemiDefinite.into()),
+    };
+    // K = P H^T S^-1, so K^T = S^-1 H P (S and P are symmetric).
+    Ok(s_chol.solve(&PHT.transpose()).transpose())
+}
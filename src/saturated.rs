@@ -0,0 +1,283 @@
+//! Censored updates for measurements clipped at saturation limits
+
+use alloc::borrow::Cow;
+use alloc::vec::Vec;
+
+use na::{DMatrix, DVector, RealField};
+use nalgebra as na;
+
+use crate::stats::normal_upper_tail;
+use crate::subset::SubsetObservationModel;
+use crate::{CovarianceUpdateMethod, Error, ObservationModel, StateAndCovariance};
+
+/// An observation model for a sensor which saturates at known limits
+///
+/// A reading at a limit only says that the value is beyond it, and treating
+/// it as exact pulls the estimate towards the limit with full confidence.
+/// Here the components within the limits update the estimate as usual, and
+/// each component at a limit then gives a censored (Tobit) update: the
+/// predicted measurement is truncated at the limit, and the mean and
+/// variance of the truncated Gaussian update the estimate. The noise of the
+/// saturated components is assumed to be uncorrelated with that of the
+/// other components. A non-linear wrapped model is linearized by its
+/// [jacobian_at](ObservationModel::jacobian_at) the estimate each update
+/// starts from.
+pub struct SaturatedObservationModel<'a, R>
+where
+    R: RealField,
+{
+    inner: &'a dyn ObservationModel<R>,
+    lower: DVector<R>,
+    upper: DVector<R>,
+}
+
+impl<'a, R> SaturatedObservationModel<'a, R>
+where
+    R: RealField,
+{
+    /// Wrap `inner`, whose measurement components saturate at `lower` and
+    /// `upper`; an infinite limit never saturates.
+    ///
+    /// # Panics
+    ///
+    /// Panics if there is not one limit of each kind per observation
+    /// component.
+    pub fn new(inner: &'a dyn ObservationModel<R>, lower: DVector<R>, upper: DVector<R>) -> Self {
+        assert_eq!(lower.nrows(), inner.obs_dim());
+        assert_eq!(upper.nrows(), inner.obs_dim());
+        Self {
+            inner,
+            lower,
+            upper,
+        }
+    }
+
+    /// Whether each component of the observation is at or beyond a limit,
+    /// as `Some(true)` for the upper limit and `Some(false)` for the lower.
+    pub fn saturation(&self, observation: &DVector<R>) -> Vec<Option<bool>> {
+        observation
+            .iter()
+            .zip(self.lower.iter().zip(self.upper.iter()))
+            .map(|(z, (lower, upper))| {
+                if z >= upper {
+                    Some(true)
+                } else if z <= lower {
+                    Some(false)
+                } else {
+                    None
+                }
+            })
+            .collect()
+    }
+
+    /// The censored update with component `i`, known to be beyond `limit`.
+    fn censored_update(
+        &self,
+        estimate: &StateAndCovariance<R>,
+        i: usize,
+        limit: R,
+        upper: bool,
+        observation_covariance: &DMatrix<R>,
+    ) -> StateAndCovariance<R> {
+        let mean = self.inner.predict_observation(estimate.state())[i].clone();
+        let h = self.inner.jacobian_at(estimate.state()).row(i).into_owned();
+        let PhT = estimate.covariance() * h.transpose();
+        let variance = (&h * &PhT)[(0, 0)].clone() + observation_covariance[(i, i)].clone();
+        let sd = variance.clone().sqrt();
+
+        // The truncated Gaussian beyond the limit, in standard units.
+        let sign = if upper { R::one() } else { -R::one() };
+        let beta = sign.clone() * (limit - mean) / sd.clone();
+        let half: R = na::convert(0.5);
+        let density = (-beta.clone() * beta.clone() * half).exp() / R::two_pi().sqrt();
+        let tail = normal_upper_tail(beta.clone());
+        // The inverse Mills ratio, tending to beta far in the tail.
+        let lambda = if tail > R::zero() {
+            density / tail
+        } else {
+            beta.clone()
+        };
+        let shift = sign * sd * lambda.clone();
+        let shrink = lambda.clone() * (lambda - beta);
+
+        let gain = PhT / variance.clone();
+        let state = estimate.state() + &gain * shift;
+        let covariance = estimate.covariance() - &gain * gain.transpose() * (variance * shrink);
+        StateAndCovariance::new(state, covariance.symmetric_part())
+    }
+}
+
+impl<'a, R> ObservationModel<R> for SaturatedObservationModel<'a, R>
+where
+    R: RealField,
+{
+    fn predict_observation(&self, state: &DVector<R>) -> DVector<R> {
+        self.inner.predict_observation(state)
+    }
+    fn H(&self) -> &DMatrix<R> {
+        self.inner.H()
+    }
+    fn HT(&self) -> Cow<'_, DMatrix<R>> {
+        self.inner.HT()
+    }
+    fn jacobian_at(&self, state: &DVector<R>) -> Cow<'_, DMatrix<R>> {
+        self.inner.jacobian_at(state)
+    }
+    fn R(&self) -> &DMatrix<R> {
+        self.inner.R()
+    }
+    fn state_dim(&self) -> usize {
+        self.inner.state_dim()
+    }
+    fn obs_dim(&self) -> usize {
+        self.inner.obs_dim()
+    }
+
+    /// Update with the components within the limits, then with a censored
+    /// update for each saturated component.
+    fn update_with_covariance(
+        &self,
+        prior: &StateAndCovariance<R>,
+        observation: &DVector<R>,
+        observation_covariance: &DMatrix<R>,
+        covariance_method: CovarianceUpdateMethod,
+    ) -> Result<StateAndCovariance<R>, Error> {
+        let saturation = self.saturation(observation);
+        if saturation.iter().all(|s| s.is_none()) {
+            return self.inner.update_with_covariance(
+                prior,
+                observation,
+                observation_covariance,
+                covariance_method,
+            );
+        }
+        let rows: Vec<usize> = (0..saturation.len())
+            .filter(|&i| saturation[i].is_none())
+            .collect();
+        let mut estimate = if rows.is_empty() {
+            prior.clone()
+        } else {
+            let selected_R = observation_covariance
+                .select_rows(&rows)
+                .select_columns(&rows);
+            let selected = SubsetObservationModel::new(self.inner, rows);
+            selected.update_with_covariance(
+                prior,
+                &selected.select(observation),
+                &selected_R,
+                covariance_method,
+            )?
+        };
+        for (i, s) in saturation.iter().enumerate() {
+            if let Some(upper) = *s {
+                let limit = if upper {
+                    self.upper[i].clone()
+                } else {
+                    self.lower[i].clone()
+                };
+                estimate = self.censored_update(&estimate, i, limit, upper, observation_covariance);
+            }
+        }
+        Ok(estimate)
+    }
+}
+
+#[test]
+fn test_saturated_update() {
+    use crate::test_util::MatrixObservation;
+
+    approx::assert_relative_eq!(normal_upper_tail(1.0), 0.158655253931457, epsilon = 1e-12);
+    approx::assert_relative_eq!(
+        normal_upper_tail(10.0),
+        7.61985302416e-24,
+        max_relative = 1e-9
+    );
+
+    // A noiseless sensor of x ~ N(0, 1) saturating at 1: the posterior has
+    // the moments of the normal distribution truncated to x >= 1.
+    let sensor = MatrixObservation::new(DMatrix::identity(1, 1), DMatrix::zeros(1, 1));
+    let saturated = SaturatedObservationModel::new(
+        &sensor,
+        DVector::from_element(1, f64::NEG_INFINITY),
+        DVector::from_element(1, 1.0),
+    );
+    let prior = StateAndCovariance::new(DVector::zeros(1), DMatrix::identity(1, 1));
+    let z = DVector::from_element(1, 1.0);
+    assert_eq!(saturated.saturation(&z), vec![Some(true)]);
+    let posterior = saturated
+        .update(&prior, &z, CovarianceUpdateMethod::JosephForm)
+        .unwrap();
+    let lambda = 0.24197072451914337 / 0.15865525393145705;
+    approx::assert_relative_eq!(posterior.state()[0], lambda, epsilon = 1e-9);
+    approx::assert_relative_eq!(
+        posterior.covariance()[(0, 0)],
+        1.0 + lambda - lambda * lambda,
+        epsilon = 1e-9
+    );
+
+    // Components within the limits update as usual.
+    let sensor = MatrixObservation::new(DMatrix::identity(2, 2), DMatrix::identity(2, 2) * 0.5);
+    let saturated = SaturatedObservationModel::new(
+        &sensor,
+        DVector::from_element(2, -1.0),
+        DVector::from_element(2, 1.0),
+    );
+    let prior = StateAndCovariance::new(DVector::zeros(2), DMatrix::identity(2, 2));
+    let z = DVector::from_vec(vec![0.3, -0.2]);
+    assert_eq!(
+        saturated
+            .update(&prior, &z, CovarianceUpdateMethod::JosephForm)
+            .unwrap(),
+        sensor
+            .update(&prior, &z, CovarianceUpdateMethod::JosephForm)
+            .unwrap()
+    );
+    // With one component at its lower limit, the other is used as usual and
+    // the saturated one is pushed below the limit.
+    let z = DVector::from_vec(vec![0.3, -1.0]);
+    let posterior = saturated
+        .update(&prior, &z, CovarianceUpdateMethod::JosephForm)
+        .unwrap();
+    approx::assert_relative_eq!(posterior.state()[0], 0.2, epsilon = 1e-12);
+    assert!(posterior.state()[1] < -0.8);
+    assert!(posterior.covariance()[(1, 1)] < 1.0);
+}
+
+#[test]
+fn test_saturated_nonlinear_update() {
+    use crate::nonlinear::NumericalObservationModel;
+
+    // A product x y, linearized at the nominal state (3, 4), saturating at
+    // 0.5: the censored update at the prior (1, -2) moves the state along
+    // P h^T with h = [-2, 1], the Jacobian at the prior.
+    let product = NumericalObservationModel::new(
+        |x: &DVector<f64>| DVector::from_element(1, x[0] * x[1]),
+        DMatrix::from_element(1, 1, 0.1),
+        1e-7,
+        &DVector::from_vec(vec![3.0, 4.0]),
+    );
+    let saturated = SaturatedObservationModel::new(
+        &product,
+        DVector::from_element(1, f64::NEG_INFINITY),
+        DVector::from_element(1, 0.5),
+    );
+    let prior = StateAndCovariance::new(
+        DVector::from_vec(vec![1.0, -2.0]),
+        DMatrix::from_row_slice(2, 2, &[0.5, 0.1, 0.1, 0.3]),
+    );
+    let posterior = saturated
+        .update(
+            &prior,
+            &DVector::from_element(1, 0.5),
+            CovarianceUpdateMethod::JosephForm,
+        )
+        .unwrap();
+    let shift = posterior.state() - prior.state();
+    let direction = prior.covariance() * DVector::from_vec(vec![-2.0, 1.0]);
+    approx::assert_relative_eq!(
+        shift[0] * direction[1] - shift[1] * direction[0],
+        0.0,
+        epsilon = 1e-8
+    );
+    assert!(posterior.state()[0] * posterior.state()[1] > prior.state()[0] * prior.state()[1]);
+}
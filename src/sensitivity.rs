@@ -0,0 +1,214 @@
+//! Analytic gradient of the log-likelihood by sensitivity equations
+
+use na::{DMatrix, DVector, RealField};
+use nalgebra as na;
+
+use crate::{is_nan, Error, ErrorKind, KalmanFilterNoControl, StateAndCovariance};
+
+/// Derivatives of the model matrices with respect to one parameter
+///
+/// Matrices which do not depend on the parameter should be zero.
+#[derive(Debug, Clone)]
+pub struct ParameterDerivatives<R>
+where
+    R: RealField,
+{
+    /// Derivative of the state transition matrix, `dF/dθ`.
+    pub dF: DMatrix<R>,
+    /// Derivative of the process covariance, `dQ/dθ`.
+    pub dQ: DMatrix<R>,
+    /// Derivative of the observation matrix, `dH/dθ`.
+    pub dH: DMatrix<R>,
+    /// Derivative of the observation noise covariance, `dR/dθ`.
+    pub dR: DMatrix<R>,
+}
+
+impl<R> ParameterDerivatives<R>
+where
+    R: RealField,
+{
+    /// All derivatives zero, for a parameter which appears in none of the
+    /// matrices. Set the relevant fields afterwards.
+    pub fn zeros(state_dim: usize, obs_dim: usize) -> Self {
+        Self {
+            dF: DMatrix::zeros(state_dim, state_dim),
+            dQ: DMatrix::zeros(state_dim, state_dim),
+            dH: DMatrix::zeros(obs_dim, state_dim),
+            dR: DMatrix::zeros(obs_dim, obs_dim),
+        }
+    }
+}
+
+impl<'a, R> KalmanFilterNoControl<'a, R>
+where
+    R: RealField,
+{
+    /// Log-likelihood of a sequence of observations and its gradient
+    ///
+    /// The gradient is with respect to parameters whose effect on the model
+    /// matrices is given by `derivatives`, one entry per parameter. The
+    /// derivatives of the state and covariance are propagated alongside the
+    /// filter (the sensitivity equations), so a single pass suffices rather
+    /// than a finite difference filter run per parameter. The initial
+    /// estimate is taken not to depend on the parameters.
+    ///
+    /// If any observation has a NaN component, it is treated as missing and
+    /// does not contribute to the likelihood.
+    pub fn log_likelihood_with_gradient(
+        &self,
+        initial_estimate: &StateAndCovariance<R>,
+        observations: &[DVector<R>],
+        derivatives: &[ParameterDerivatives<R>],
+    ) -> Result<(R, DVector<R>), Error> {
+        let F = self.transition_model.F();
+        let FT = &*self.transition_model.FT();
+        let Q = self.transition_model.Q();
+        let H = self.observation_matrix.H();
+        let HT = &*self.observation_matrix.HT();
+        let R = self.observation_matrix.R();
+        let n = initial_estimate.state().nrows();
+        let half: R = na::convert(0.5);
+        let ln_2pi: R = na::convert((2.0 * core::f64::consts::PI).ln());
+
+        let mut x = initial_estimate.state().clone();
+        let mut P = initial_estimate.covariance().clone();
+        let mut dx: Vec<DVector<R>> = derivatives.iter().map(|_| DVector::zeros(n)).collect();
+        let mut dP: Vec<DMatrix<R>> = derivatives.iter().map(|_| DMatrix::zeros(n, n)).collect();
+        let mut log_likelihood = R::zero();
+        let mut gradient = DVector::zeros(derivatives.len());
+
+        for observation in observations.iter() {
+            // Prediction and its sensitivity.
+            for (d, (dx_i, dP_i)) in derivatives.iter().zip(dx.iter_mut().zip(dP.iter_mut())) {
+                let dFP = &d.dF * &P;
+                *dx_i = &d.dF * &x + F * &*dx_i;
+                *dP_i = (&dFP * FT + F * &*dP_i * FT + F * dFP.transpose())
+                    * self.fading_memory.clone()
+                    + &d.dQ;
+            }
+            x = F * x;
+            P = F * P * FT * self.fading_memory.clone() + Q;
+
+            if observation.iter().any(|v| is_nan(v.clone())) {
+                continue;
+            }
+
+            let residual = observation - H * &x;
+            let PHT = &P * HT;
+            let S = H * &PHT + R;
+            let S_chol = match na::linalg::Cholesky::new(S.clone()) {
+                Some(v) => v,
+                None => return Err(ErrorKind::CovarianceNotPositiveSemiDefinite.into()),
+            };
+            let S_inv = S_chol.inverse();
+            let S_inv_residual = &S_inv * &residual;
+            let ln_det = S_chol
+                .l_dirty()
+                .diagonal()
+                .iter()
+                .fold(R::zero(), |acc, l| acc + l.clone().ln());
+            let m: R = na::convert(residual.nrows() as f64);
+            log_likelihood -= half.clone()
+                * (m * ln_2pi.clone() + ln_det.clone() + ln_det + residual.dot(&S_inv_residual));
+
+            let K = &PHT * &S_inv;
+            for (i, (d, (dx_i, dP_i))) in derivatives
+                .iter()
+                .zip(dx.iter_mut().zip(dP.iter_mut()))
+                .enumerate()
+            {
+                let dHP = &d.dH * &P;
+                let d_residual = -(&d.dH * &x) - H * &*dx_i;
+                let dS = &dHP * HT + H * &*dP_i * HT + H * dHP.transpose() + &d.dR;
+                // d ln|S| = tr(S^-1 dS), d (e^T S^-1 e) = 2 e^T S^-1 de - e^T S^-1 dS S^-1 e
+                let d_quadratic = (S_inv_residual.dot(&d_residual) * na::convert(2.0))
+                    - S_inv_residual.dot(&(&dS * &S_inv_residual));
+                gradient[i] -= half.clone() * ((&S_inv * &dS).trace() + d_quadratic);
+
+                let dK = (&*dP_i * HT + &P * d.dH.transpose() - &K * &dS) * &S_inv;
+                *dx_i = &*dx_i + &dK * &residual + &K * d_residual;
+                let dKSKT = &dK * &S * K.transpose();
+                *dP_i = &*dP_i - &dKSKT - dKSKT.transpose() - &K * dS * K.transpose();
+            }
+            x += &K * residual;
+            P = (&P - &K * &S * K.transpose()).symmetric_part();
+        }
+        Ok((log_likelihood, gradient))
+    }
+}
+
+#[test]
+fn test_gradient_matches_finite_differences() {
+    use crate::test_util::{
+        initial_estimate, simulate_positions, ConstantVelocity, PositionObservation,
+    };
+
+    let observations = simulate_positions(30, 0.1, 1.0, 0.5, 4);
+    let log_likelihood = |dt: f64, q: f64, r: f64| {
+        let transition = ConstantVelocity::new(dt, q);
+        let observation = PositionObservation::new(r);
+        KalmanFilterNoControl::new(&transition, &observation)
+            .log_likelihood(&initial_estimate(), &observations)
+            .unwrap()
+    };
+
+    let (dt, q, r) = (0.1, 0.8, 0.6);
+    let mut d_dt = ParameterDerivatives::zeros(2, 1);
+    d_dt.dF = DMatrix::from_row_slice(2, 2, &[0.0, 1.0, 0.0, 0.0]);
+    d_dt.dQ = DMatrix::from_row_slice(2, 2, &[dt * dt, dt, dt, 1.0]) * q;
+    let mut d_q = ParameterDerivatives::zeros(2, 1);
+    d_q.dQ = crate::TransitionModelLinearNoControl::Q(&ConstantVelocity::new(dt, 1.0)).clone();
+    let mut d_r = ParameterDerivatives::zeros(2, 1);
+    d_r.dR = DMatrix::identity(1, 1);
+
+    let transition = ConstantVelocity::new(dt, q);
+    let observation = PositionObservation::new(r);
+    let (value, gradient) = KalmanFilterNoControl::new(&transition, &observation)
+        .log_likelihood_with_gradient(&initial_estimate(), &observations, &[d_dt, d_q, d_r])
+        .unwrap();
+    approx::assert_relative_eq!(value, log_likelihood(dt, q, r), max_relative = 1e-10);
+
+    let h = 1e-6;
+    let expected = [
+        (log_likelihood(dt + h, q, r) - log_likelihood(dt - h, q, r)) / (2.0 * h),
+        (log_likelihood(dt, q + h, r) - log_likelihood(dt, q - h, r)) / (2.0 * h),
+        (log_likelihood(dt, q, r + h) - log_likelihood(dt, q, r - h)) / (2.0 * h),
+    ];
+    for (g, e) in gradient.iter().zip(expected.iter()) {
+        approx::assert_relative_eq!(g, e, max_relative = 1e-5);
+    }
+}
+
+#[test]
+fn test_single_step_gradient() {
+    use crate::test_util::{random_walk, scalars};
+
+    // From N(1, 1), x_1 = f x_0 + w with Q = q predicts an observation with
+    // R = r as N(f, f^2 + q + r). For f = 1, q = 1, r = 2 and an
+    // observation of 5, the residual is e = 4 and the variance S = 4, so
+    // the log-likelihood is -(ln(2 pi S) + e^2 / S) / 2 and
+    // d/dq = d/dr = -(1 / S - e^2 / S^2) / 2 = 3 / 8, and
+    // d/df = -(2 f / S - 2 e / S - 2 f e^2 / S^2) / 2 = 7 / 4.
+    let (transition, observation) = random_walk();
+    let kf = KalmanFilterNoControl::new(&transition, &observation);
+    let prior = StateAndCovariance::new(DVector::from_element(1, 1.0), DMatrix::identity(1, 1));
+    let mut d_f = ParameterDerivatives::zeros(1, 1);
+    d_f.dF = DMatrix::identity(1, 1);
+    let mut d_q = ParameterDerivatives::zeros(1, 1);
+    d_q.dQ = DMatrix::identity(1, 1);
+    let mut d_r = ParameterDerivatives::zeros(1, 1);
+    d_r.dR = DMatrix::identity(1, 1);
+    let unused = ParameterDerivatives::zeros(1, 1);
+
+    // A missing observation contributes nothing.
+    let (value, gradient) = kf
+        .log_likelihood_with_gradient(&prior, &scalars(&[5.0, f64::NAN]), &[d_f, d_q, d_r, unused])
+        .unwrap();
+    let expected = -0.5 * ((8.0 * core::f64::consts::PI).ln() + 4.0);
+    approx::assert_relative_eq!(value, expected, epsilon = 1e-12);
+    approx::assert_relative_eq!(
+        gradient.as_slice(),
+        &[1.75, 0.375, 0.375, 0.0][..],
+        epsilon = 1e-12
+    );
+}
@@ -0,0 +1,230 @@
+//! Shaping filters for colored noise given by simple spectra
+
+use na::{DMatrix, RealField};
+use nalgebra as na;
+
+use crate::{DiscretizedTransitionModel, LinearTransitionModel, TransitionModelLinearNoControl};
+
+/// A description of colored noise, such as a sensor bias, from which the
+/// states and matrices of its shaping filter are built
+///
+/// Each spectrum is the output of a linear system driven by white noise, so
+/// it can be modelled by appending the system's states to the state vector.
+/// The parameters are those usually given on datasheets.
+#[derive(Debug, Clone, PartialEq)]
+pub enum NoiseSpectrum<R>
+where
+    R: RealField,
+{
+    /// A first-order Gauss-Markov process, `dx/dt = -x / tau + w`, with
+    /// standard deviation `sigma` and correlation time `tau`; one state.
+    FirstOrderGaussMarkov {
+        /// The stationary standard deviation.
+        sigma: R,
+        /// The correlation time, `tau`.
+        correlation_time: R,
+    },
+    /// A second-order Gauss-Markov process,
+    /// `d^2x/dt^2 + 2 zeta omega dx/dt + omega^2 x = w`, with standard
+    /// deviation `sigma`; two states, `x` and its rate.
+    SecondOrderGaussMarkov {
+        /// The stationary standard deviation of `x`.
+        sigma: R,
+        /// The natural frequency, `omega`, in radians per unit time.
+        natural_frequency: R,
+        /// The damping ratio, `zeta`.
+        damping: R,
+    },
+    /// A random walk, `dx/dt = w`, where `w` has spectral density
+    /// `density`, e.g. the square of an angle random walk coefficient; one
+    /// state.
+    RandomWalk {
+        /// The spectral density of the driving noise.
+        density: R,
+    },
+    /// An integrated random walk, `d^2x/dt^2 = w`, where `w` has spectral
+    /// density `density`; two states, `x` and its rate.
+    IntegratedRandomWalk {
+        /// The spectral density of the driving noise.
+        density: R,
+    },
+}
+
+impl<R> NoiseSpectrum<R>
+where
+    R: RealField,
+{
+    /// The number of states of the shaping filter.
+    pub fn state_dim(&self) -> usize {
+        match self {
+            Self::FirstOrderGaussMarkov { .. } | Self::RandomWalk { .. } => 1,
+            Self::SecondOrderGaussMarkov { .. } | Self::IntegratedRandomWalk { .. } => 2,
+        }
+    }
+
+    /// The continuous-time model of the shaping filter, `dx/dt = A x + w`,
+    /// as `A` and the spectral density `Qc` of `w`.
+    pub fn continuous_model(&self) -> (DMatrix<R>, DMatrix<R>) {
+        let two: R = na::convert(2.0);
+        match self {
+            Self::FirstOrderGaussMarkov {
+                sigma,
+                correlation_time,
+            } => {
+                let beta = R::one() / correlation_time.clone();
+                let variance = sigma.clone() * sigma.clone();
+                (
+                    DMatrix::from_element(1, 1, -beta.clone()),
+                    DMatrix::from_element(1, 1, two * beta * variance),
+                )
+            }
+            Self::SecondOrderGaussMarkov {
+                sigma,
+                natural_frequency,
+                damping,
+            } => {
+                let omega = natural_frequency.clone();
+                let variance = sigma.clone() * sigma.clone();
+                let A = DMatrix::from_row_slice(
+                    2,
+                    2,
+                    &[
+                        R::zero(),
+                        R::one(),
+                        -omega.clone() * omega.clone(),
+                        -two * damping.clone() * omega.clone(),
+                    ],
+                );
+                // The stationary variance of x is q / (4 zeta omega^3).
+                let q =
+                    na::convert::<f64, R>(4.0) * damping.clone() * omega.clone().powi(3) * variance;
+                let mut Qc = DMatrix::zeros(2, 2);
+                Qc[(1, 1)] = q;
+                (A, Qc)
+            }
+            Self::RandomWalk { density } => (
+                DMatrix::zeros(1, 1),
+                DMatrix::from_element(1, 1, density.clone()),
+            ),
+            Self::IntegratedRandomWalk { density } => {
+                let mut A = DMatrix::zeros(2, 2);
+                A[(0, 1)] = R::one();
+                let mut Qc = DMatrix::zeros(2, 2);
+                Qc[(1, 1)] = density.clone();
+                (A, Qc)
+            }
+        }
+    }
+
+    /// The shaping filter's `F` and `Q` for the interval `dt`, discretized
+    /// exactly, see [DiscretizedTransitionModel::new].
+    pub fn discretize(&self, dt: R) -> DiscretizedTransitionModel<R> {
+        let (A, Qc) = self.continuous_model();
+        DiscretizedTransitionModel::new(&A, &Qc, dt)
+    }
+
+    /// The stationary covariance of the shaping filter's states, to
+    /// initialize them, or `None` for the random walks, which have none.
+    pub fn stationary_covariance(&self) -> Option<DMatrix<R>> {
+        match self {
+            Self::FirstOrderGaussMarkov { sigma, .. } => {
+                Some(DMatrix::from_element(1, 1, sigma.clone() * sigma.clone()))
+            }
+            Self::SecondOrderGaussMarkov {
+                sigma,
+                natural_frequency,
+                ..
+            } => {
+                let variance = sigma.clone() * sigma.clone();
+                let mut P = DMatrix::zeros(2, 2);
+                P[(0, 0)] = variance.clone();
+                P[(1, 1)] = variance * natural_frequency.clone() * natural_frequency.clone();
+                Some(P)
+            }
+            Self::RandomWalk { .. } | Self::IntegratedRandomWalk { .. } => None,
+        }
+    }
+}
+
+/// Append the states of shaping filters to a transition model
+///
+/// The result has the states of `base` followed by those of each spectrum
+/// in turn, with block-diagonal `F` and `Q` and the shaping filters
+/// discretized over `dt`. Coupling the noise states into the dynamics, e.g.
+/// a bias into an integrated rate, is left to the caller, by setting the
+/// off-diagonal blocks of `F`.
+pub fn augment_with_shaping_filters<R>(
+    base: &dyn TransitionModelLinearNoControl<R>,
+    spectra: &[NoiseSpectrum<R>],
+    dt: R,
+) -> LinearTransitionModel<R>
+where
+    R: RealField,
+{
+    let n = base.state_dim() + spectra.iter().map(|s| s.state_dim()).sum::<usize>();
+    let mut F = DMatrix::zeros(n, n);
+    let mut Q = DMatrix::zeros(n, n);
+    let mut offset = base.state_dim();
+    F.slice_mut((0, 0), (offset, offset)).copy_from(base.F());
+    Q.slice_mut((0, 0), (offset, offset)).copy_from(base.Q());
+    for spectrum in spectra {
+        let m = spectrum.state_dim();
+        let block = spectrum.discretize(dt.clone());
+        F.slice_mut((offset, offset), (m, m)).copy_from(block.F());
+        Q.slice_mut((offset, offset), (m, m)).copy_from(block.Q());
+        offset += m;
+    }
+    LinearTransitionModel::from_matrices(F, Q)
+}
+
+#[test]
+fn test_shaping_filters() {
+    use crate::test_util::ConstantVelocity;
+
+    let dt = 0.1;
+    let markov = NoiseSpectrum::FirstOrderGaussMarkov {
+        sigma: 2.0,
+        correlation_time: 5.0,
+    };
+    let block = markov.discretize(dt);
+    let decay = (-dt / 5.0f64).exp();
+    approx::assert_relative_eq!(block.F()[(0, 0)], decay, epsilon = 1e-12);
+    approx::assert_relative_eq!(
+        block.Q()[(0, 0)],
+        4.0 * (1.0 - decay * decay),
+        epsilon = 1e-12
+    );
+
+    // The integrated random walk gives the constant velocity model.
+    let walk = NoiseSpectrum::IntegratedRandomWalk { density: 1.0 };
+    let block = walk.discretize(dt);
+    let expected = ConstantVelocity::new(dt, 1.0);
+    approx::assert_relative_eq!(block.F(), expected.F(), epsilon = 1e-12);
+    approx::assert_relative_eq!(block.Q(), expected.Q(), epsilon = 1e-12);
+    assert!(walk.stationary_covariance().is_none());
+
+    // The stationary covariance is unchanged by a step.
+    let oscillator = NoiseSpectrum::SecondOrderGaussMarkov {
+        sigma: 0.5,
+        natural_frequency: 3.0,
+        damping: 0.2,
+    };
+    let block = oscillator.discretize(dt);
+    let P = oscillator.stationary_covariance().unwrap();
+    let next = block.F() * &P * block.F().transpose() + block.Q();
+    approx::assert_relative_eq!(next, P, epsilon = 1e-12);
+
+    let augmented = augment_with_shaping_filters(
+        &expected,
+        &[
+            markov,
+            oscillator,
+            NoiseSpectrum::RandomWalk { density: 0.3 },
+        ],
+        dt,
+    );
+    assert_eq!(augmented.state_dim(), 6);
+    approx::assert_relative_eq!(augmented.F()[(2, 2)], decay, epsilon = 1e-12);
+    approx::assert_relative_eq!(augmented.Q()[(5, 5)], 0.03, epsilon = 1e-12);
+    assert_eq!(augmented.F()[(0, 2)], 0.0);
+}
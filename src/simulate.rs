@@ -0,0 +1,190 @@
+//! Sampling from linear Gaussian state space models
+
+use na::{DMatrix, DVector, RealField};
+use nalgebra as na;
+
+use crate::{
+    is_nan, nan, Error, KalmanFilterNoControl, ObservationModel, StateAndCovariance,
+    TransitionModelLinearNoControl,
+};
+
+/// Square root `S` of a symmetric positive semi-definite matrix, such that
+/// `S S^T` equals the matrix
+///
+/// Small negative eigenvalues due to rounding are treated as zero.
+pub(crate) fn psd_sqrt<R: RealField>(matrix: &DMatrix<R>) -> DMatrix<R> {
+    let eigen = matrix.clone().symmetric_eigen();
+    let sqrt_values = eigen.eigenvalues.map(|x| x.max(R::zero()).sqrt());
+    eigen.eigenvectors * DMatrix::from_diagonal(&sqrt_values)
+}
+
+/// Draw a sample from the Gaussian distribution with the given mean and
+/// covariance
+///
+/// `normal` must return independent samples from the standard normal
+/// distribution. This keeps the choice of random number generator with the
+/// caller.
+pub fn sample_gaussian<R, N>(estimate: &StateAndCovariance<R>, normal: &mut N) -> DVector<R>
+where
+    R: RealField,
+    N: FnMut() -> R,
+{
+    let z = DVector::from_fn(estimate.state().nrows(), |_, _| normal());
+    estimate.state() + psd_sqrt(estimate.covariance()) * z
+}
+
+/// Simulate states and observations of a linear Gaussian model
+///
+/// The initial state is drawn from `initial_estimate`, then for each of the
+/// `n` steps the state is propagated with process noise and observed with
+/// observation noise, just as the [KalmanFilterNoControl] assumes. `normal`
+/// must return independent standard normal samples.
+pub fn simulate<R, N>(
+    transition_model: &dyn TransitionModelLinearNoControl<R>,
+    observation_model: &dyn ObservationModel<R>,
+    initial_estimate: &StateAndCovariance<R>,
+    n: usize,
+    normal: &mut N,
+) -> (Vec<DVector<R>>, Vec<DVector<R>>)
+where
+    R: RealField,
+    N: FnMut() -> R,
+{
+    let Q_sqrt = match transition_model.noise_coupling() {
+        Some((G, Qc)) => G * psd_sqrt(Qc),
+        None => psd_sqrt(transition_model.Q()),
+    };
+    let R_sqrt = psd_sqrt(observation_model.R());
+    let mut state = sample_gaussian(initial_estimate, normal);
+    let mut states = Vec::with_capacity(n);
+    let mut observations = Vec::with_capacity(n);
+    for _ in 0..n {
+        let w = DVector::from_fn(Q_sqrt.ncols(), |_, _| normal());
+        state = transition_model.F() * state + &Q_sqrt * w;
+        let v = DVector::from_fn(R_sqrt.ncols(), |_, _| normal());
+        observations.push(observation_model.predict_observation(&state) + &R_sqrt * v);
+        states.push(state.clone());
+    }
+    (states, observations)
+}
+
+impl<'a, R> KalmanFilterNoControl<'a, R>
+where
+    R: RealField,
+{
+    /// Draw a state trajectory from the joint smoothing distribution
+    ///
+    /// This is the simulation smoother of Durbin and Koopman (2002). A
+    /// trajectory `x+` and observations `y+` are simulated from the model,
+    /// and the sample is `x+ - E[x | y+] + E[x | y]`, where the expectations
+    /// are computed by the RTS smoother. Unlike the marginal smoothed
+    /// estimates, the samples capture the correlation between time steps.
+    ///
+    /// Observations with a NaN component are treated as missing, and the
+    /// same steps are treated as missing in the simulated observations.
+    /// `normal` must return independent standard normal samples.
+    pub fn sample_smoothed<N>(
+        &self,
+        initial_estimate: &StateAndCovariance<R>,
+        observations: &[DVector<R>],
+        normal: &mut N,
+    ) -> Result<Vec<DVector<R>>, Error>
+    where
+        N: FnMut() -> R,
+    {
+        let (simulated_states, mut simulated_observations) = simulate(
+            self.transition_model,
+            self.observation_matrix,
+            initial_estimate,
+            observations.len(),
+            normal,
+        );
+        for (simulated, observation) in simulated_observations.iter_mut().zip(observations) {
+            if observation.iter().any(|x| is_nan(x.clone())) {
+                simulated.fill(nan());
+            }
+        }
+        let smoothed = self.smooth(initial_estimate, observations)?;
+        let smoothed_simulated = self.smooth(initial_estimate, &simulated_observations)?;
+        Ok(simulated_states
+            .into_iter()
+            .zip(smoothed.iter().zip(smoothed_simulated.iter()))
+            .map(|(x, (s, s_sim))| x - s_sim.state() + s.state())
+            .collect())
+    }
+}
+
+#[test]
+fn test_simulation_smoother_mean() {
+    use crate::test_util::{
+        initial_estimate, simulate_positions, ConstantVelocity, Normals, PositionObservation,
+    };
+
+    let transition = ConstantVelocity::new(0.1, 1.0);
+    let observation = PositionObservation::new(0.5);
+    let kf = KalmanFilterNoControl::new(&transition, &observation);
+    let mut observations = simulate_positions(10, 0.1, 1.0, 0.5, 3);
+    observations[4][0] = f64::NAN;
+    let smoothed = kf.smooth(&initial_estimate(), &observations).unwrap();
+
+    let mut normals = Normals::new(7);
+    let mut normal = || normals.sample();
+    let n_samples = 2000;
+    let mut mean = DVector::zeros(2);
+    let mut variance = 0.0;
+    for _ in 0..n_samples {
+        let samples = kf
+            .sample_smoothed(&initial_estimate(), &observations, &mut normal)
+            .unwrap();
+        mean += &samples[4] / n_samples as f64;
+        variance += (samples[4][0] - smoothed[4].state()[0]).powi(2) / n_samples as f64;
+    }
+    approx::assert_relative_eq!(mean, smoothed[4].state().clone(), epsilon = 0.05);
+    approx::assert_relative_eq!(
+        variance,
+        smoothed[4].covariance()[(0, 0)],
+        max_relative = 0.1
+    );
+}
+
+#[test]
+fn test_deterministic_draws() {
+    use crate::test_util::{random_walk, scalars};
+
+    // With every draw one, the sample is one standard deviation from the
+    // mean in each component of a diagonal covariance, on either side
+    // depending on the signs of the eigenvectors.
+    let mean = DVector::from_vec(vec![1.0, 2.0]);
+    let estimate = StateAndCovariance::new(
+        mean.clone(),
+        DMatrix::from_diagonal(&DVector::from_vec(vec![4.0, 9.0])),
+    );
+    let sample = sample_gaussian(&estimate, &mut || 1.0);
+    approx::assert_relative_eq!(
+        (sample - mean).map(|x| x * x),
+        DVector::from_vec(vec![4.0, 9.0])
+    );
+
+    // A random walk from N(0, 1) with Q = 1 and R = 2 then starts at 1 and
+    // moves up by one each step, observed sqrt(2) above.
+    let (transition, observation) = random_walk();
+    let prior = StateAndCovariance::new(DVector::zeros(1), DMatrix::identity(1, 1));
+    let (states, observations) = simulate(&transition, &observation, &prior, 3, &mut || 1.0);
+    for (k, (state, observation)) in states.iter().zip(observations.iter()).enumerate() {
+        approx::assert_relative_eq!(state[0], k as f64 + 2.0);
+        approx::assert_relative_eq!(observation[0], k as f64 + 2.0 + 2.0f64.sqrt());
+    }
+
+    // With every draw zero and a zero prior mean, the simulated trajectory
+    // and its smoothed estimate are zero, so the sample is the smoothed
+    // mean.
+    let kf = KalmanFilterNoControl::new(&transition, &observation);
+    let observations = scalars(&[2.0, f64::NAN, 4.0]);
+    let sample = kf
+        .sample_smoothed(&prior, &observations, &mut || 0.0)
+        .unwrap();
+    let smoothed = kf.smooth(&prior, &observations).unwrap();
+    for (s, e) in sample.iter().zip(smoothed.iter()) {
+        approx::assert_relative_eq!(s, e.state(), epsilon = 1e-12);
+    }
+}
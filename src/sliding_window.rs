@@ -0,0 +1,306 @@
+//! Sliding-window filtering with stochastic clones (MSCKF)
+//!
+//! Following the multi-state constraint Kalman filter of Mourikis and
+//! Roumeliotis (2007), the state is the current state followed by clones of
+//! its pose components at past times. A feature observed from several of
+//! these poses constrains them jointly, without the feature being added to
+//! the state: its measurements are linearized at an estimate of the feature,
+//! and projected onto the left null space of their Jacobian with respect to
+//! the feature, which removes the dependence on the feature's error. The
+//! number of clones is bounded, with the oldest marginalized out.
+//!
+//! Linearizing the measurements, e.g. of a camera, and estimating the
+//! feature are left to the caller, so the filter serves as the back end of
+//! visual-inertial odometry with any sensor model.
+
+use na::{DMatrix, DVector, RealField};
+use nalgebra as na;
+
+use crate::{
+    CovarianceUpdateMethod, Error, ErrorKind, ObservationModel, StateAndCovariance,
+    TransitionModelLinearNoControl,
+};
+
+/// A measurement given as its residual, whose predicted value is zero
+struct ResidualObservation<R>
+where
+    R: RealField,
+{
+    H: DMatrix<R>,
+    R: DMatrix<R>,
+}
+
+impl<R> ObservationModel<R> for ResidualObservation<R>
+where
+    R: RealField,
+{
+    fn predict_observation(&self, _state: &DVector<R>) -> DVector<R> {
+        DVector::zeros(self.H.nrows())
+    }
+    fn H(&self) -> &DMatrix<R> {
+        &self.H
+    }
+    fn R(&self) -> &DMatrix<R> {
+        &self.R
+    }
+    fn state_dim(&self) -> usize {
+        self.H.ncols()
+    }
+    fn obs_dim(&self) -> usize {
+        self.H.nrows()
+    }
+}
+
+/// A sliding-window filter of the current state and cloned past poses
+pub struct SlidingWindowFilter<'a, R>
+where
+    R: RealField,
+{
+    transition_model: &'a dyn TransitionModelLinearNoControl<R>,
+    pose_components: Vec<usize>,
+    max_clones: usize,
+    estimate: StateAndCovariance<R>,
+    clones: Vec<usize>,
+    next_id: usize,
+}
+
+impl<'a, R> SlidingWindowFilter<'a, R>
+where
+    R: RealField,
+{
+    /// Start from an estimate of the current state, with no clones
+    ///
+    /// `pose_components` are the indices of the state components which are
+    /// cloned, and at most `max_clones` clones are kept. An
+    /// [ErrorKind::DimensionMismatch] error is returned if the estimate does
+    /// not have the dimension of the transition model or a pose component is
+    /// out of range.
+    pub fn new(
+        transition_model: &'a dyn TransitionModelLinearNoControl<R>,
+        initial_estimate: StateAndCovariance<R>,
+        pose_components: Vec<usize>,
+        max_clones: usize,
+    ) -> Result<Self, Error> {
+        let n = transition_model.state_dim();
+        crate::check_shape("initial estimate", (n, n), initial_estimate.covariance())?;
+        crate::check_dimension("initial estimate", (n, 1), initial_estimate.state().shape())?;
+        if let Some(&i) = pose_components.iter().find(|&&i| i >= n) {
+            return Err(ErrorKind::DimensionMismatch {
+                expected: (n, 1),
+                actual: (i + 1, 1),
+                matrix: "pose components",
+            }
+            .into());
+        }
+        Ok(Self {
+            transition_model,
+            pose_components,
+            max_clones,
+            estimate: initial_estimate,
+            clones: Vec::new(),
+            next_id: 0,
+        })
+    }
+
+    /// The estimate of the current state followed by the clones, oldest
+    /// first.
+    pub fn estimate(&self) -> &StateAndCovariance<R> {
+        &self.estimate
+    }
+
+    /// The estimate of the current state.
+    pub fn current(&self) -> StateAndCovariance<R> {
+        let n = self.transition_model.state_dim();
+        StateAndCovariance::new(
+            self.estimate.state().rows(0, n).into_owned(),
+            self.estimate
+                .covariance()
+                .slice((0, 0), (n, n))
+                .into_owned(),
+        )
+    }
+
+    /// The identifiers of the clones in the window, oldest first.
+    pub fn clone_ids(&self) -> &[usize] {
+        &self.clones
+    }
+
+    /// The offset in the state of the clone with the given identifier.
+    fn clone_offset(&self, id: usize) -> Result<usize, Error> {
+        match self.clones.iter().position(|&c| c == id) {
+            Some(index) => {
+                Ok(self.transition_model.state_dim() + index * self.pose_components.len())
+            }
+            None => Err(ErrorKind::DimensionMismatch {
+                expected: (self.next_id, 1),
+                actual: (id + 1, 1),
+                matrix: "clone",
+            }
+            .into()),
+        }
+    }
+
+    /// Predict the current state to the next time; the clones are of past
+    /// poses and do not change.
+    pub fn predict(&mut self) {
+        let n = self.transition_model.state_dim();
+        let F = self.transition_model.F();
+        let (mut state, covariance) = self.estimate.clone().inner();
+        let current = F * state.rows(0, n);
+        state.rows_mut(0, n).copy_from(&current);
+        let rows = F * covariance.rows(0, n);
+        let mut new_covariance = covariance;
+        new_covariance.rows_mut(0, n).copy_from(&rows);
+        new_covariance
+            .columns_mut(0, n)
+            .copy_from(&rows.transpose());
+        let block = rows.columns(0, n) * &*self.transition_model.FT() + self.transition_model.Q();
+        new_covariance
+            .slice_mut((0, 0), (n, n))
+            .copy_from(&block.symmetric_part());
+        self.estimate = StateAndCovariance::new(state, new_covariance);
+    }
+
+    /// Clone the current pose, e.g. at the time of a camera image, returning
+    /// the identifier of the clone
+    ///
+    /// The clone is exactly correlated with the current pose. If there are
+    /// then more than the maximum number of clones, the oldest is
+    /// marginalized out.
+    pub fn clone_state(&mut self) -> usize {
+        let pose = &self.pose_components;
+        let P = self.estimate.covariance();
+        let clone = StateAndCovariance::new(
+            self.estimate.state().select_rows(pose),
+            P.select_rows(pose).select_columns(pose),
+        );
+        let cross = P.select_columns(pose);
+        self.estimate = self.estimate.augment_correlated(&clone, &cross).unwrap();
+        let id = self.next_id;
+        self.next_id += 1;
+        self.clones.push(id);
+        if self.clones.len() > self.max_clones {
+            let offset = self.clone_offset(self.clones[0]).unwrap();
+            let oldest: Vec<usize> = (offset..offset + pose.len()).collect();
+            self.estimate = self.estimate.marginalize(&oldest).unwrap();
+            self.clones.remove(0);
+        }
+        id
+    }
+
+    /// Update with the measurements of a feature from several clones
+    ///
+    /// The measurements are linearized at the caller's estimate of the
+    /// feature, as `residual = H_clones δx_clones + H_feature δf + v`, where
+    /// `H_clones` has a block of columns for each clone of `clone_ids`, in
+    /// that order, and `v` has covariance `measurement_covariance`. They are
+    /// projected onto the left null space of `H_feature` before the update,
+    /// so there must be more measurement rows than feature components for
+    /// the feature to constrain the poses. An [ErrorKind::DimensionMismatch]
+    /// error is returned if a clone is no longer in the window or the shapes
+    /// are inconsistent.
+    pub fn update_feature(
+        &mut self,
+        clone_ids: &[usize],
+        residual: &DVector<R>,
+        H_clones: &DMatrix<R>,
+        H_feature: &DMatrix<R>,
+        measurement_covariance: &DMatrix<R>,
+    ) -> Result<(), Error> {
+        let rows = residual.nrows();
+        let m = self.pose_components.len();
+        crate::check_shape("H_clones", (rows, clone_ids.len() * m), H_clones)?;
+        crate::check_shape("H_feature", (rows, H_feature.ncols()), H_feature)?;
+        crate::check_shape(
+            "measurement covariance",
+            (rows, rows),
+            measurement_covariance,
+        )?;
+
+        // Scatter the clone Jacobians into the columns of the whole state.
+        let mut H = DMatrix::zeros(rows, self.estimate.state().nrows());
+        for (k, &id) in clone_ids.iter().enumerate() {
+            let offset = self.clone_offset(id)?;
+            let mut block = H.columns_mut(offset, m);
+            block += H_clones.columns(k * m, m);
+        }
+
+        let A = left_null_space(H_feature);
+        if A.ncols() == 0 {
+            return Ok(());
+        }
+        let AT = A.transpose();
+        let model = ResidualObservation {
+            H: &AT * H,
+            R: (&AT * measurement_covariance * &A).symmetric_part(),
+        };
+        self.estimate = model.update(
+            &self.estimate,
+            &(AT * residual),
+            CovarianceUpdateMethod::JosephForm,
+        )?;
+        Ok(())
+    }
+}
+
+/// An orthonormal basis of the left null space of `matrix`, as columns.
+fn left_null_space<R: RealField>(matrix: &DMatrix<R>) -> DMatrix<R> {
+    let rows = matrix.nrows();
+    let eigen = (matrix * matrix.transpose()).symmetric_eigen();
+    let largest = eigen.eigenvalues.amax();
+    let tolerance = largest * R::default_epsilon() * na::convert(rows.max(1) as f64 * 100.0);
+    let null: Vec<usize> = (0..rows)
+        .filter(|&i| eigen.eigenvalues[i].clone().abs() <= tolerance)
+        .collect();
+    eigen.eigenvectors.select_columns(&null)
+}
+
+#[test]
+fn test_sliding_window_filter() {
+    use crate::test_util::{initial_estimate, ConstantVelocity, MatrixObservation};
+
+    // A position and velocity observing an unknown static feature at
+    // relative position f - x from each cloned position.
+    let transition = ConstantVelocity::new(0.1, 1.0);
+    let mut window = SlidingWindowFilter::new(&transition, initial_estimate(), vec![0], 3).unwrap();
+    let mut ids = Vec::new();
+    for _ in 0..4 {
+        window.predict();
+        ids.push(window.clone_state());
+    }
+    // The oldest clone was marginalized out.
+    assert_eq!(window.clone_ids(), &ids[1..]);
+    assert_eq!(window.estimate().state().nrows(), 2 + 3);
+
+    let feature_estimate = 5.0;
+    let z = DVector::from_vec(vec![4.8, 4.7, 4.6]);
+    let clones = window.estimate().state().rows(2, 3).into_owned();
+    let residual = z.map(|zi| zi - feature_estimate) + &clones;
+    let H_clones = -DMatrix::<f64>::identity(3, 3);
+    let H_feature = DMatrix::from_element(3, 1, 1.0);
+    let noise = DMatrix::identity(3, 3) * 0.01;
+
+    // The feature cancels from the differences between measurements, so the
+    // update is the same as with the differenced measurements and their
+    // correlated noise.
+    let D = DMatrix::from_row_slice(2, 3, &[1.0, -1.0, 0.0, 0.0, 1.0, -1.0]);
+    let mut H = DMatrix::zeros(2, 5);
+    H.columns_mut(2, 3).copy_from(&(&D * &H_clones));
+    let differenced = MatrixObservation::new(H, &D * &noise * D.transpose());
+    let expected = differenced
+        .update(
+            window.estimate(),
+            &(&D * &z),
+            CovarianceUpdateMethod::JosephForm,
+        )
+        .unwrap();
+
+    window
+        .update_feature(&ids[1..], &residual, &H_clones, &H_feature, &noise)
+        .unwrap();
+    approx::assert_relative_eq!(window.estimate(), &expected, epsilon = 1e-10);
+
+    assert!(window
+        .update_feature(&ids[..3], &residual, &H_clones, &H_feature, &noise)
+        .is_err());
+}
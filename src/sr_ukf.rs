@@ -0,0 +1,402 @@
+//! The square-root unscented Kalman filter (SR-UKF)
+
+use na::{DMatrix, DVector, RealField};
+use nalgebra as na;
+
+use crate::{
+    is_nan, Error, ErrorKind, NonlinearTransitionModel, ObservationModel, SigmaPointStrategy,
+    SigmaPoints, StateAndCovariance,
+};
+
+/// An estimate holding a lower triangular square root `S` of its covariance
+/// `P = S S^T` instead of the covariance itself
+#[derive(Debug, Clone, PartialEq)]
+pub struct SquareRootEstimate<R>
+where
+    R: RealField,
+{
+    state: DVector<R>,
+    sqrt_covariance: DMatrix<R>,
+}
+
+impl<R> SquareRootEstimate<R>
+where
+    R: RealField,
+{
+    /// Create a new estimate from the state and the lower triangular square
+    /// root of the covariance.
+    pub fn new(state: DVector<R>, sqrt_covariance: DMatrix<R>) -> Self {
+        Self {
+            state,
+            sqrt_covariance,
+        }
+    }
+
+    /// Convert an estimate by the Cholesky decomposition of its covariance.
+    /// An [ErrorKind::CovarianceNotPositiveSemiDefinite] error is returned if
+    /// there is none.
+    pub fn from_estimate(estimate: &StateAndCovariance<R>) -> Result<Self, Error> {
+        match na::linalg::Cholesky::new(estimate.covariance().clone()) {
+            Some(chol) => Ok(Self::new(estimate.state().clone(), chol.unpack())),
+            None => Err(ErrorKind::CovarianceNotPositiveSemiDefinite.into()),
+        }
+    }
+
+    /// Convert to an estimate with the covariance `S S^T`.
+    pub fn to_estimate(&self) -> StateAndCovariance<R> {
+        StateAndCovariance::new(
+            self.state.clone(),
+            &self.sqrt_covariance * self.sqrt_covariance.transpose(),
+        )
+    }
+
+    /// Get the state estimate.
+    pub fn state(&self) -> &DVector<R> {
+        &self.state
+    }
+
+    /// Get the lower triangular square root of the covariance, `S`.
+    pub fn sqrt_covariance(&self) -> &DMatrix<R> {
+        &self.sqrt_covariance
+    }
+}
+
+/// A square root `B` of a symmetric positive semi-definite matrix, such
+/// that `B B^T` equals the matrix, by its eigendecomposition.
+fn noise_sqrt<R: RealField>(matrix: &DMatrix<R>) -> DMatrix<R> {
+    let eigen = matrix.clone().symmetric_eigen();
+    let sqrt_values = eigen.eigenvalues.map(|x| x.max(R::zero()).sqrt());
+    eigen.eigenvectors * DMatrix::from_diagonal(&sqrt_values)
+}
+
+/// Update the lower triangular Cholesky factor `L` in place to that of
+/// `L L^T + x x^T`, or of `L L^T - x x^T` for a downdate.
+///
+/// An [ErrorKind::CovarianceNotPositiveSemiDefinite] error is returned if a
+/// downdate would make the matrix indefinite.
+pub(crate) fn cholesky_rank_one<R: RealField>(
+    L: &mut DMatrix<R>,
+    mut x: DVector<R>,
+    downdate: bool,
+) -> Result<(), Error> {
+    let sign = if downdate { -R::one() } else { R::one() };
+    let n = x.nrows();
+    for k in 0..n {
+        let lkk = L[(k, k)].clone();
+        let r2 = lkk.clone() * lkk.clone() + sign.clone() * x[k].clone() * x[k].clone();
+        if r2 <= R::zero() {
+            return Err(ErrorKind::CovarianceNotPositiveSemiDefinite.into());
+        }
+        let r = r2.sqrt();
+        let c = r.clone() / lkk.clone();
+        let s = x[k].clone() / lkk;
+        L[(k, k)] = r;
+        for i in k + 1..n {
+            L[(i, k)] = (L[(i, k)].clone() + sign.clone() * s.clone() * x[i].clone()) / c.clone();
+            x[i] = c.clone() * x[i].clone() - s.clone() * L[(i, k)].clone();
+        }
+    }
+    Ok(())
+}
+
+/// The lower triangular square root of `A A^T` for a wide matrix `A`, by QR
+/// decomposition of `A^T`.
+fn triangularize<R: RealField>(A: DMatrix<R>) -> DMatrix<R> {
+    let n = A.nrows();
+    let mut upper = na::linalg::QR::new(A.transpose()).r();
+    // Make the diagonal positive, which leaves R^T R unchanged.
+    for i in 0..n {
+        if upper[(i, i)] < R::zero() {
+            let mut row = upper.row_mut(i);
+            row.neg_mut();
+        }
+    }
+    upper.transpose()
+}
+
+/// The propagated points, their weighted mean and the square root of their
+/// weighted covariance plus `noise_sqrt noise_sqrt^T`.
+fn propagate_sqrt<R: RealField>(
+    sigma_points: &SigmaPoints<R>,
+    outputs: &DMatrix<R>,
+    noise_sqrt: &DMatrix<R>,
+) -> Result<(DVector<R>, DMatrix<R>), Error> {
+    let mean = outputs * sigma_points.mean_weights();
+    let m = outputs.nrows();
+    let weights = sigma_points.covariance_weights();
+
+    // Points with positive weights, with the noise, form the compound matrix
+    // which is triangularized; those with negative weights are downdated.
+    let positive: usize = weights.iter().filter(|w| **w > R::zero()).count();
+    let mut compound = DMatrix::zeros(m, positive + noise_sqrt.ncols());
+    let mut column = 0;
+    for (j, w) in weights.iter().enumerate() {
+        if *w > R::zero() {
+            let deviation = (outputs.column(j) - &mean) * w.clone().sqrt();
+            compound.set_column(column, &deviation);
+            column += 1;
+        }
+    }
+    compound
+        .columns_mut(positive, noise_sqrt.ncols())
+        .copy_from(noise_sqrt);
+    let mut sqrt = triangularize(compound);
+    for (j, w) in weights.iter().enumerate() {
+        if *w < R::zero() {
+            let deviation = (outputs.column(j) - &mean) * (-w.clone()).sqrt();
+            cholesky_rank_one(&mut sqrt, deviation, true)?;
+        }
+    }
+    Ok((mean, sqrt))
+}
+
+/// Evaluate `f` at each sigma point.
+fn evaluate<R, F>(points: &DMatrix<R>, f: F) -> DMatrix<R>
+where
+    R: RealField,
+    F: Fn(&DVector<R>) -> DVector<R>,
+{
+    let mut outputs = DMatrix::zeros(0, 0);
+    for j in 0..points.ncols() {
+        let output = f(&points.column(j).into_owned());
+        if j == 0 {
+            outputs = DMatrix::zeros(output.nrows(), points.ncols());
+        }
+        outputs.set_column(j, &output);
+    }
+    outputs
+}
+
+/// A square-root unscented Kalman filter with no control inputs
+///
+/// This gives the same estimates as the
+/// [UnscentedKalmanFilter](crate::UnscentedKalmanFilter), but propagates a
+/// Cholesky factor of the covariance, following Van der Merwe and Wan
+/// (2001). The predicted factors are found by QR decomposition and the
+/// posterior factor by rank-one Cholesky downdates, so the covariance
+/// cannot lose symmetry or positive definiteness to rounding. This matters
+/// with `f32` arithmetic, where the covariance of the standard UKF can
+/// become indefinite after many steps. A downdate which would make the
+/// covariance indefinite is reported as an error rather than producing an
+/// invalid estimate.
+pub struct SquareRootUnscentedKalmanFilter<'a, R, S>
+where
+    R: RealField,
+    S: SigmaPointStrategy<R>,
+{
+    transition_model: &'a dyn NonlinearTransitionModel<R>,
+    observation_model: &'a dyn ObservationModel<R>,
+    strategy: S,
+}
+
+impl<'a, R, S> SquareRootUnscentedKalmanFilter<'a, R, S>
+where
+    R: RealField,
+    S: SigmaPointStrategy<R>,
+{
+    /// Initialize a new `SquareRootUnscentedKalmanFilter` struct.
+    pub fn new(
+        transition_model: &'a dyn NonlinearTransitionModel<R>,
+        observation_model: &'a dyn ObservationModel<R>,
+        strategy: S,
+    ) -> Self {
+        Self {
+            transition_model,
+            observation_model,
+            strategy,
+        }
+    }
+
+    /// Predict the prior from the previous estimate.
+    pub fn predict(
+        &self,
+        previous_estimate: &SquareRootEstimate<R>,
+    ) -> Result<SquareRootEstimate<R>, Error> {
+        let sigma_points = self.strategy.sigma_points_from_sqrt(
+            previous_estimate.state(),
+            previous_estimate.sqrt_covariance(),
+        );
+        let outputs = evaluate(sigma_points.points(), |x| self.transition_model.f(x));
+        let Q_sqrt = noise_sqrt(self.transition_model.Q());
+        let (state, sqrt_covariance) = propagate_sqrt(&sigma_points, &outputs, &Q_sqrt)?;
+        Ok(SquareRootEstimate::new(state, sqrt_covariance))
+    }
+
+    /// Update the prior with an observation.
+    pub fn update(
+        &self,
+        prior: &SquareRootEstimate<R>,
+        observation: &DVector<R>,
+    ) -> Result<SquareRootEstimate<R>, Error> {
+        let sigma_points = self
+            .strategy
+            .sigma_points_from_sqrt(prior.state(), prior.sqrt_covariance());
+        let outputs = evaluate(sigma_points.points(), |x| {
+            self.observation_model.predict_observation(x)
+        });
+        let R_sqrt = noise_sqrt(self.observation_model.R());
+        let (predicted, S_sqrt) = propagate_sqrt(&sigma_points, &outputs, &R_sqrt)?;
+
+        let mut Pxy = DMatrix::zeros(prior.state().nrows(), outputs.nrows());
+        for (j, w) in sigma_points.covariance_weights().iter().enumerate() {
+            let dx = sigma_points.points().column(j) - prior.state();
+            let dy = outputs.column(j) - &predicted;
+            Pxy += dx * dy.transpose() * w.clone();
+        }
+
+        // K = Pxy (Sy Sy^T)^-1, by two triangular solves.
+        let singular = || Error::from(ErrorKind::CovarianceNotPositiveSemiDefinite);
+        let Kt = S_sqrt
+            .solve_lower_triangular(&Pxy.transpose())
+            .ok_or_else(singular)?;
+        let Kt = S_sqrt
+            .transpose()
+            .solve_upper_triangular(&Kt)
+            .ok_or_else(singular)?;
+        let K = Kt.transpose();
+
+        let state = prior.state() + &K * (observation - predicted);
+        let U = &K * S_sqrt;
+        let mut sqrt_covariance = prior.sqrt_covariance().clone();
+        for column in U.column_iter() {
+            cholesky_rank_one(&mut sqrt_covariance, column.into_owned(), true)?;
+        }
+        Ok(SquareRootEstimate::new(state, sqrt_covariance))
+    }
+
+    /// Perform prediction and update steps
+    ///
+    /// If any component of the observation is NaN (not a number), the
+    /// observation will not be used but rather the prior will be returned as
+    /// the posterior without performing the update step.
+    pub fn step(
+        &self,
+        previous_estimate: &SquareRootEstimate<R>,
+        observation: &DVector<R>,
+    ) -> Result<SquareRootEstimate<R>, Error> {
+        let prior = self.predict(previous_estimate)?;
+        if observation.iter().any(|x| is_nan(x.clone())) {
+            Ok(prior)
+        } else {
+            self.update(&prior, observation)
+        }
+    }
+}
+
+#[test]
+fn test_square_root_ukf() {
+    use crate::test_util::{
+        initial_estimate, simulate_positions, ConstantVelocity, PositionObservation,
+    };
+    use crate::{
+        JulierSigmaPoints, Linearized, MerweScaledSigmaPoints, TransitionModelLinearNoControl,
+        UnscentedKalmanFilter,
+    };
+
+    let transition = ConstantVelocity::new(0.1, 1.0);
+    let linearized = Linearized(&transition);
+    let observation = PositionObservation::new(0.5);
+    let observations = simulate_positions(20, 0.1, 1.0, 0.5, 6);
+
+    // The square-root filter gives the same estimates as the UKF, including
+    // with a negative central weight, which needs a downdate.
+    let merwe = MerweScaledSigmaPoints {
+        alpha: 0.5,
+        ..Default::default()
+    };
+    let ukf = UnscentedKalmanFilter::new(&linearized, &observation, merwe.clone());
+    let sr_ukf = SquareRootUnscentedKalmanFilter::new(&linearized, &observation, merwe);
+    let mut expected = initial_estimate();
+    let mut actual = SquareRootEstimate::from_estimate(&initial_estimate()).unwrap();
+    for z in &observations {
+        expected = ukf.step(&expected, z).unwrap();
+        actual = sr_ukf.step(&actual, z).unwrap();
+    }
+    let actual = actual.to_estimate();
+    approx::assert_relative_eq!(actual.state(), expected.state(), epsilon = 1e-9);
+    approx::assert_relative_eq!(actual.covariance(), expected.covariance(), epsilon = 1e-9);
+
+    // A rank-one update and downdate round trip.
+    let mut L = DMatrix::from_row_slice(2, 2, &[2.0, 0.0, 1.0, 3.0]);
+    let original = L.clone();
+    let x = DVector::from_vec(vec![0.5, -1.0]);
+    cholesky_rank_one(&mut L, x.clone(), false).unwrap();
+    approx::assert_relative_eq!(
+        &L * L.transpose(),
+        &original * original.transpose() + &x * x.transpose(),
+        epsilon = 1e-12
+    );
+    cholesky_rank_one(&mut L, x, true).unwrap();
+    approx::assert_relative_eq!(L, original, epsilon = 1e-12);
+    let too_large = DVector::from_vec(vec![3.0, 0.0]);
+    assert!(cholesky_rank_one(&mut L, too_large, true).is_err());
+
+    // Julier points with κ = 0 have no central weight.
+    let sr_ukf = SquareRootUnscentedKalmanFilter::new(
+        &linearized,
+        &observation,
+        JulierSigmaPoints { kappa: 0.0 },
+    );
+    let prior = sr_ukf
+        .predict(&SquareRootEstimate::from_estimate(&initial_estimate()).unwrap())
+        .unwrap();
+    let expected = TransitionModelLinearNoControl::predict(&transition, &initial_estimate());
+    approx::assert_relative_eq!(
+        prior.to_estimate().covariance(),
+        expected.covariance(),
+        epsilon = 1e-12
+    );
+}
+
+#[test]
+fn test_square_root_factors() {
+    use crate::test_util::{
+        initial_estimate, simulate_positions, ConstantVelocity, PositionObservation,
+    };
+    use crate::{Linearized, MerweScaledSigmaPoints};
+
+    let is_lower_triangular = |S: &DMatrix<f64>| {
+        (0..S.nrows()).all(|i| S[(i, i)] > 0.0 && (i + 1..S.ncols()).all(|j| S[(i, j)] == 0.0))
+    };
+
+    // A wide matrix is triangularized to a factor of its product with its
+    // transpose, and a singular noise covariance still has a square root.
+    let A = DMatrix::from_row_slice(2, 3, &[1.0, -2.0, 0.5, 3.0, 0.0, -1.0]);
+    let S = triangularize(A.clone());
+    assert!(is_lower_triangular(&S));
+    approx::assert_relative_eq!(&S * S.transpose(), &A * A.transpose(), epsilon = 1e-12);
+    let singular = DMatrix::from_row_slice(2, 2, &[1.0, 2.0, 2.0, 4.0]);
+    let B = noise_sqrt(&singular);
+    approx::assert_relative_eq!(&B * B.transpose(), singular, epsilon = 1e-12);
+
+    // The propagated factors stay lower triangular with a positive
+    // diagonal, and a missing observation leaves the predicted factor.
+    let transition = ConstantVelocity::new(0.1, 1.0);
+    let linearized = Linearized(&transition);
+    let observation = PositionObservation::new(0.5);
+    let sr_ukf = SquareRootUnscentedKalmanFilter::new(
+        &linearized,
+        &observation,
+        MerweScaledSigmaPoints::default(),
+    );
+    let mut observations = simulate_positions(6, 0.1, 1.0, 0.5, 3);
+    observations[2] = DVector::from_element(1, f64::NAN);
+    let mut estimate = SquareRootEstimate::from_estimate(&initial_estimate()).unwrap();
+    for z in observations.iter() {
+        let prior = sr_ukf.predict(&estimate).unwrap();
+        assert!(is_lower_triangular(prior.sqrt_covariance()));
+        estimate = sr_ukf.step(&estimate, z).unwrap();
+        assert!(is_lower_triangular(estimate.sqrt_covariance()));
+        if z[0].is_nan() {
+            assert_eq!(estimate, prior);
+        }
+    }
+
+    // A covariance without a Cholesky factor is rejected.
+    let indefinite = StateAndCovariance::new(DVector::zeros(1), DMatrix::from_element(1, 1, -1.0));
+    let err = SquareRootEstimate::from_estimate(&indefinite).unwrap_err();
+    assert!(matches!(
+        err.kind(),
+        ErrorKind::CovarianceNotPositiveSemiDefinite
+    ));
+}
@@ -0,0 +1,240 @@
+//! Stacking simultaneous observations from several models into one update
+
+use alloc::borrow::Cow;
+
+use na::{DMatrix, DVector, RealField};
+use nalgebra as na;
+
+use crate::subset::SubsetObservationModel;
+use crate::{
+    is_nan, CovarianceUpdateMethod, Error, ErrorKind, ObservationModel, StateAndCovariance,
+};
+
+/// Several observation models of the same state, stacked into one
+///
+/// The observation of the stacked model is the concatenation of the
+/// observations of each model. `H` is the models' observation matrices
+/// stacked vertically and `R` is block diagonal with the models' noise
+/// covariances, i.e. the noise of different models is independent. A single
+/// update with the stacked model is equivalent to updating with each model
+/// in turn. Non-linear models are linearized at the prior by their
+/// [jacobian_at](ObservationModel::jacobian_at), stacked in the same way.
+pub struct StackedObservationModel<'a, R>
+where
+    R: RealField,
+{
+    models: Vec<&'a dyn ObservationModel<R>>,
+    H: DMatrix<R>,
+    HT: DMatrix<R>,
+    R: DMatrix<R>,
+}
+
+impl<'a, R> StackedObservationModel<'a, R>
+where
+    R: RealField,
+{
+    /// Stack observation models
+    ///
+    /// An [ErrorKind::DimensionMismatch] error is returned if the models do
+    /// not all have the same state dimension.
+    pub fn new(models: Vec<&'a dyn ObservationModel<R>>) -> Result<Self, Error> {
+        let state_dim = models.first().map_or(0, |m| m.state_dim());
+        if let Some(other) = models.iter().find(|m| m.state_dim() != state_dim) {
+            return Err(ErrorKind::DimensionMismatch {
+                expected: (other.obs_dim(), state_dim),
+                actual: (other.obs_dim(), other.state_dim()),
+                matrix: "H",
+            }
+            .into());
+        }
+        let obs_dim = models.iter().map(|m| m.obs_dim()).sum();
+        let mut H = DMatrix::zeros(obs_dim, state_dim);
+        let mut R = DMatrix::zeros(obs_dim, obs_dim);
+        let mut offset = 0;
+        for model in models.iter() {
+            let m = model.obs_dim();
+            H.rows_mut(offset, m).copy_from(model.H());
+            R.slice_mut((offset, offset), (m, m)).copy_from(model.R());
+            offset += m;
+        }
+        let HT = H.transpose();
+        Ok(Self { models, H, HT, R })
+    }
+
+    /// Concatenate one observation per model into an observation of the
+    /// stacked model.
+    pub fn stack(&self, observations: &[DVector<R>]) -> DVector<R> {
+        assert_eq!(observations.len(), self.models.len());
+        let mut stacked = DVector::zeros(self.H.nrows());
+        let mut offset = 0;
+        for (model, observation) in self.models.iter().zip(observations.iter()) {
+            stacked
+                .rows_mut(offset, model.obs_dim())
+                .copy_from(observation);
+            offset += model.obs_dim();
+        }
+        stacked
+    }
+
+    /// Update the prior with one observation per model in a single step
+    ///
+    /// An observation with a NaN component is treated as missing and only
+    /// the other models' observations are used. If all are missing, the
+    /// prior is returned.
+    pub fn update_all(
+        &self,
+        prior: &StateAndCovariance<R>,
+        observations: &[DVector<R>],
+        covariance_update_method: CovarianceUpdateMethod,
+    ) -> Result<StateAndCovariance<R>, Error> {
+        let stacked = self.stack(observations);
+        let mut rows = Vec::with_capacity(stacked.nrows());
+        let mut offset = 0;
+        for (model, observation) in self.models.iter().zip(observations.iter()) {
+            if !observation.iter().any(|x| is_nan(x.clone())) {
+                rows.extend(offset..offset + model.obs_dim());
+            }
+            offset += model.obs_dim();
+        }
+        if rows.is_empty() {
+            Ok(prior.clone())
+        } else if rows.len() == stacked.nrows() {
+            self.update(prior, &stacked, covariance_update_method)
+        } else {
+            let subset = SubsetObservationModel::new(self, rows);
+            subset.update(prior, &subset.select(&stacked), covariance_update_method)
+        }
+    }
+}
+
+impl<'a, R> ObservationModel<R> for StackedObservationModel<'a, R>
+where
+    R: RealField,
+{
+    fn predict_observation(&self, state: &DVector<R>) -> DVector<R> {
+        let predicted: Vec<DVector<R>> = self
+            .models
+            .iter()
+            .map(|m| m.predict_observation(state))
+            .collect();
+        self.stack(&predicted)
+    }
+    fn H(&self) -> &DMatrix<R> {
+        &self.H
+    }
+    fn HT(&self) -> Cow<'_, DMatrix<R>> {
+        Cow::Borrowed(&self.HT)
+    }
+    /// The models' Jacobians at `state`, stacked vertically, which is `H` if
+    /// every model is linear.
+    fn jacobian_at(&self, state: &DVector<R>) -> Cow<'_, DMatrix<R>> {
+        let jacobians: Vec<Cow<'_, DMatrix<R>>> =
+            self.models.iter().map(|m| m.jacobian_at(state)).collect();
+        if jacobians.iter().all(|j| matches!(j, Cow::Borrowed(_))) {
+            return Cow::Borrowed(&self.H);
+        }
+        let mut H = DMatrix::zeros(self.H.nrows(), self.H.ncols());
+        let mut offset = 0;
+        for jacobian in jacobians.iter() {
+            H.rows_mut(offset, jacobian.nrows()).copy_from(jacobian);
+            offset += jacobian.nrows();
+        }
+        Cow::Owned(H)
+    }
+    fn R(&self) -> &DMatrix<R> {
+        &self.R
+    }
+    fn state_dim(&self) -> usize {
+        self.H.ncols()
+    }
+    fn obs_dim(&self) -> usize {
+        self.H.nrows()
+    }
+}
+
+#[test]
+fn test_stacked_update_matches_sequential() {
+    use crate::test_util::{initial_estimate, MatrixObservation, PositionObservation};
+
+    let position = PositionObservation::new(0.5);
+    let velocity = MatrixObservation::new(
+        DMatrix::from_row_slice(1, 2, &[0.0, 1.0]),
+        DMatrix::from_element(1, 1, 0.2),
+    );
+    let stacked = StackedObservationModel::new(vec![&position, &velocity]).unwrap();
+    let observations = [DVector::from_element(1, 0.3), DVector::from_element(1, 1.2)];
+    let method = CovarianceUpdateMethod::JosephForm;
+    let prior = initial_estimate();
+    let combined = stacked.update_all(&prior, &observations, method).unwrap();
+
+    let sequential = position.update(&prior, &observations[0], method).unwrap();
+    let sequential = velocity
+        .update(&sequential, &observations[1], method)
+        .unwrap();
+    approx::assert_relative_eq!(combined, sequential, epsilon = 1e-12);
+
+    // A missing observation from one model leaves only the other.
+    let observations = [DVector::from_element(1, f64::NAN), observations[1].clone()];
+    let partial = stacked.update_all(&prior, &observations, method).unwrap();
+    let expected = velocity.update(&prior, &observations[1], method).unwrap();
+    approx::assert_relative_eq!(partial, expected, epsilon = 1e-12);
+}
+
+#[test]
+fn test_stacked_nonlinear_model() {
+    use crate::nonlinear::NumericalObservationModel;
+    use crate::test_util::{MatrixObservation, PositionObservation};
+
+    // A range, linearized at the nominal state (3, 4), stacked with a
+    // position: at the prior (1, -2), the stacked Jacobian has the rows
+    // [1, 0] and (1, -2) / sqrt(5), and the update uses it in the gain.
+    let position = PositionObservation::new(0.5);
+    let range = NumericalObservationModel::new(
+        |x: &DVector<f64>| DVector::from_element(1, x.norm()),
+        DMatrix::from_element(1, 1, 0.1),
+        1e-7,
+        &DVector::from_vec(vec![3.0, 4.0]),
+    );
+    let stacked = StackedObservationModel::new(vec![&position, &range]).unwrap();
+    let prior = StateAndCovariance::new(
+        DVector::from_vec(vec![1.0, -2.0]),
+        DMatrix::from_row_slice(2, 2, &[0.5, 0.1, 0.1, 0.3]),
+    );
+    let norm = 5f64.sqrt();
+    let H = DMatrix::from_row_slice(2, 2, &[1.0, 0.0, 1.0 / norm, -2.0 / norm]);
+    approx::assert_relative_eq!(&*stacked.jacobian_at(prior.state()), &H, epsilon = 1e-6);
+
+    let observations = [DVector::from_element(1, 0.8), DVector::from_element(1, 2.5)];
+    let posterior = stacked
+        .update_all(&prior, &observations, CovarianceUpdateMethod::JosephForm)
+        .unwrap();
+    let P = prior.covariance();
+    let S = &H * P * H.transpose() + stacked.R();
+    let K = P * H.transpose() * S.try_inverse().unwrap();
+    let residual = DVector::from_vec(vec![0.8 - 1.0, 2.5 - norm]);
+    approx::assert_relative_eq!(
+        posterior.state(),
+        &(prior.state() + &K * residual),
+        epsilon = 1e-6
+    );
+    let I = DMatrix::<f64>::identity(2, 2);
+    approx::assert_relative_eq!(
+        posterior.covariance(),
+        &((&I - &K * &H) * P),
+        epsilon = 1e-6
+    );
+
+    // Models of different state dimensions cannot be stacked.
+    let scalar = MatrixObservation::new(DMatrix::identity(1, 1), DMatrix::identity(1, 1));
+    let err = StackedObservationModel::new(vec![&position, &scalar])
+        .err()
+        .unwrap();
+    assert!(matches!(
+        err.kind(),
+        ErrorKind::DimensionMismatch {
+            expected: (1, 2),
+            actual: (1, 1),
+            matrix: "H"
+        }
+    ));
+}
@@ -1,12 +1,17 @@
+use alloc::vec::Vec;
+
+use na::{ComplexField, DMatrix, DVector, RealField};
 use nalgebra as na;
-use na::{RealField, DVector, DMatrix};
+
+use crate::{Error, ErrorKind, ObservationModel};
 
 
 /// State and covariance pair for a given estimate
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct StateAndCovariance<R>
 where
-    R: RealField,
+    R: ComplexField,
 {
     state: DVector<R>,
     covariance: DMatrix<R>,
@@ -14,12 +19,12 @@ where
 
 impl<R> StateAndCovariance<R>
 where
-    R: RealField,
+    R: ComplexField,
 {
     /// Create a new `StateAndCovariance`.
     ///
-    /// It is assumed that the covariance matrix is symmetric and positive
-    /// semi-definite.
+    /// It is assumed that the covariance matrix is symmetric (Hermitian, for
+    /// a complex field) and positive semi-definite.
     pub fn new(state: DVector<R>, covariance: DMatrix<R>) -> Self {
         // In theory, checks could be run to ensure the covariance matrix is
         // both symmetric and positive semi-definite. The Cholesky decomposition
@@ -60,3 +65,296 @@ where
         (self.state, self.covariance)
     }
 }
+
+/// Estimates are approximately equal if both their states and their
+/// covariances are.
+impl<R> approx::AbsDiffEq for StateAndCovariance<R>
+where
+    R: RealField,
+{
+    type Epsilon = R;
+
+    fn default_epsilon() -> R {
+        R::default_epsilon()
+    }
+
+    fn abs_diff_eq(&self, other: &Self, epsilon: R) -> bool {
+        self.state.abs_diff_eq(&other.state, epsilon.clone())
+            && self.covariance.abs_diff_eq(&other.covariance, epsilon)
+    }
+}
+
+impl<R> approx::RelativeEq for StateAndCovariance<R>
+where
+    R: RealField,
+{
+    fn default_max_relative() -> R {
+        R::default_max_relative()
+    }
+
+    fn relative_eq(&self, other: &Self, epsilon: R, max_relative: R) -> bool {
+        self.state
+            .relative_eq(&other.state, epsilon.clone(), max_relative.clone())
+            && self
+                .covariance
+                .relative_eq(&other.covariance, epsilon, max_relative)
+    }
+}
+
+impl<R> StateAndCovariance<R>
+where
+    R: RealField,
+{
+    /// Whether two estimates are approximately equal, with separate
+    /// absolute tolerances for the states and the covariances
+    ///
+    /// Covariances have the square of the units of the states, so a single
+    /// tolerance is often too strict for one and too loose for the other.
+    pub fn relative_eq_with(
+        &self,
+        other: &Self,
+        state_epsilon: R,
+        covariance_epsilon: R,
+        max_relative: R,
+    ) -> bool {
+        self.state
+            .relative_eq(&other.state, state_epsilon, max_relative.clone())
+            && self
+                .covariance
+                .relative_eq(&other.covariance, covariance_epsilon, max_relative)
+    }
+}
+
+impl<R> StateAndCovariance<R>
+where
+    R: RealField,
+{
+    /// Append new components, independent of the existing ones
+    ///
+    /// The new components are appended after the existing ones, with the
+    /// state and covariance of `prior` and zero cross-covariances. This adds
+    /// e.g. a newly seen landmark to the state of a SLAM filter.
+    pub fn augment(&self, prior: &StateAndCovariance<R>) -> Self {
+        let n = self.state.nrows();
+        let cross = DMatrix::zeros(n, prior.state.nrows());
+        self.augment_correlated(prior, &cross).unwrap()
+    }
+
+    /// Append new components with the given cross-covariances with the
+    /// existing ones
+    ///
+    /// `cross_covariance` has a row for each existing component and a column
+    /// for each new component, e.g. when a landmark is initialized from an
+    /// observation relative to the current pose. An
+    /// [ErrorKind::DimensionMismatch]
+    /// error is returned if it does not have that shape.
+    pub fn augment_correlated(
+        &self,
+        prior: &StateAndCovariance<R>,
+        cross_covariance: &DMatrix<R>,
+    ) -> Result<Self, Error> {
+        let (n, m) = (self.state.nrows(), prior.state.nrows());
+        crate::check_shape("cross-covariance", (n, m), cross_covariance)?;
+        let mut state = DVector::zeros(n + m);
+        state.rows_mut(0, n).copy_from(&self.state);
+        state.rows_mut(n, m).copy_from(&prior.state);
+        let mut covariance = DMatrix::zeros(n + m, n + m);
+        covariance
+            .slice_mut((0, 0), (n, n))
+            .copy_from(&self.covariance);
+        covariance
+            .slice_mut((n, n), (m, m))
+            .copy_from(&prior.covariance);
+        covariance
+            .slice_mut((0, n), (n, m))
+            .copy_from(cross_covariance);
+        covariance
+            .slice_mut((n, 0), (m, n))
+            .copy_from(&cross_covariance.transpose());
+        Ok(Self::new(state, covariance))
+    }
+
+    /// Remove components, keeping the marginal distribution of the others
+    ///
+    /// For a Gaussian, marginalizing out components is dropping their rows
+    /// and columns; the order of the remaining components is kept. An
+    /// [ErrorKind::DimensionMismatch]
+    /// error is returned if a component index is out of range.
+    pub fn marginalize(&self, components: &[usize]) -> Result<Self, Error> {
+        let n = self.state.nrows();
+        if let Some(&i) = components.iter().find(|&&i| i >= n) {
+            return Err(ErrorKind::DimensionMismatch {
+                expected: (n, 1),
+                actual: (i + 1, 1),
+                matrix: "components",
+            }
+            .into());
+        }
+        let kept: Vec<usize> = (0..n).filter(|i| !components.contains(i)).collect();
+        Ok(Self::new(
+            self.state.select_rows(&kept),
+            self.covariance.select_rows(&kept).select_columns(&kept),
+        ))
+    }
+}
+
+/// The logarithm of the determinant of a positive definite matrix.
+fn ln_det<R: RealField>(matrix: &DMatrix<R>) -> Result<R, Error> {
+    let chol = na::linalg::Cholesky::new(matrix.clone())
+        .ok_or_else(|| Error::from(ErrorKind::CovarianceNotPositiveSemiDefinite))?;
+    let two: R = na::convert(2.0);
+    let ln_diagonal = chol.l_dirty().diagonal().map(|d| d.ln());
+    Ok(ln_diagonal.sum() * two)
+}
+
+impl<R> StateAndCovariance<R>
+where
+    R: RealField,
+{
+    /// The differential entropy of the Gaussian, `(n (1 + ln 2π) + ln det P) / 2`
+    ///
+    /// An [ErrorKind::CovarianceNotPositiveSemiDefinite] error is returned if
+    /// the covariance is not positive definite, for which the entropy is
+    /// minus infinity.
+    pub fn entropy(&self) -> Result<R, Error> {
+        let n: R = na::convert(self.state.nrows() as f64);
+        let half: R = na::convert(0.5);
+        Ok(half * (n * (R::one() + R::two_pi().ln()) + ln_det(&self.covariance)?))
+    }
+
+    /// The expected reduction of the entropy by an update with an
+    /// observation of the given model, before it is taken
+    ///
+    /// For a linear Gaussian model, the entropy of the posterior does not
+    /// depend on the value observed, and the gain is the mutual information
+    /// between the state and the observation, `(ln det S - ln det R) / 2`
+    /// with `S = H P H^T + R`. This holds for a singular covariance too.
+    /// Comparing the gains of candidate measurements lets an active-sensing
+    /// planner choose the most informative. For a linearized model, `H` is
+    /// the model's current linearization. An
+    /// [ErrorKind::CovarianceNotPositiveSemiDefinite] error is returned if
+    /// `R` is not positive definite.
+    pub fn expected_information_gain(
+        &self,
+        observation_model: &dyn ObservationModel<R>,
+    ) -> Result<R, Error> {
+        let H = observation_model.H();
+        let R = observation_model.R();
+        let S = crate::linalg::matmul3(H, &self.covariance, &observation_model.HT()) + R;
+        let half: R = na::convert(0.5);
+        Ok(half * (ln_det(&S)? - ln_det(R)?))
+    }
+}
+
+#[cfg(feature = "std")]
+impl<R> StateAndCovariance<R>
+where
+    R: RealField,
+{
+    /// Summarize the estimate as the mean and standard deviation of each
+    /// component, one per line, e.g. `1.000 ± 0.100`.
+    pub fn summary(&self) -> String {
+        (0..self.state.nrows())
+            .map(|i| {
+                let variance = self.covariance[(i, i)].clone().max(R::zero());
+                format!("{:12.3} ± {:.3}", self.state[i], variance.sqrt())
+            })
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+}
+
+#[cfg(feature = "std")]
+impl<R> std::fmt::Display for StateAndCovariance<R>
+where
+    R: RealField,
+{
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(
+            f,
+            "state:{}\ncovariance:{}",
+            crate::pretty_print(&self.state),
+            crate::pretty_print(&self.covariance)
+        )
+    }
+}
+
+#[test]
+fn test_approx_eq() {
+    let a = StateAndCovariance::new(
+        DVector::from_vec(vec![1.0, 2.0]),
+        DMatrix::from_diagonal(&DVector::from_vec(vec![1e-6, 1e-6])),
+    );
+    let mut b = a.clone();
+    b.state_mut()[0] += 1e-4;
+    b.covariance_mut()[(0, 0)] += 1e-9;
+    approx::assert_relative_eq!(a, b, epsilon = 1e-3);
+    approx::assert_relative_ne!(a, b, epsilon = 1e-6);
+    assert!(a.relative_eq_with(&b, 1e-3, 1e-8, 0.0));
+    assert!(!a.relative_eq_with(&b, 1e-3, 1e-10, 0.0));
+}
+
+#[test]
+fn test_augment_and_marginalize() {
+    let pose = StateAndCovariance::new(
+        DVector::from_vec(vec![1.0, 2.0]),
+        DMatrix::from_row_slice(2, 2, &[1.0, 0.2, 0.2, 2.0]),
+    );
+    let landmark = StateAndCovariance::new(
+        DVector::from_vec(vec![5.0]),
+        DMatrix::from_element(1, 1, 3.0),
+    );
+    let augmented = pose.augment(&landmark);
+    assert_eq!(augmented.state().as_slice(), &[1.0, 2.0, 5.0]);
+    let expected = DMatrix::from_row_slice(3, 3, &[1.0, 0.2, 0.0, 0.2, 2.0, 0.0, 0.0, 0.0, 3.0]);
+    assert_eq!(augmented.covariance(), &expected);
+    assert_eq!(augmented.marginalize(&[2]).unwrap(), pose);
+    assert_eq!(augmented.marginalize(&[0, 1]).unwrap(), landmark);
+
+    let cross = DMatrix::from_column_slice(2, 1, &[0.5, -0.1]);
+    let correlated = pose.augment_correlated(&landmark, &cross).unwrap();
+    assert_eq!(correlated.covariance()[(2, 0)], 0.5);
+    assert_eq!(correlated.covariance()[(1, 2)], -0.1);
+    assert!(pose
+        .augment_correlated(&landmark, &cross.transpose())
+        .is_err());
+    assert!(augmented.marginalize(&[3]).is_err());
+}
+
+#[test]
+fn test_entropy_and_information_gain() {
+    use crate::test_util::{MatrixObservation, PositionObservation};
+    use crate::CovarianceUpdateMethod;
+
+    let estimate = StateAndCovariance::new(
+        DVector::from_vec(vec![0.0, 1.0]),
+        DMatrix::from_row_slice(2, 2, &[2.0, 0.5, 0.5, 1.0]),
+    );
+    // (n (1 + ln 2π) + ln det P) / 2 with det P = 1.75.
+    let expected = (2.0 * (1.0 + (2.0 * core::f64::consts::PI).ln()) + 1.75f64.ln()) / 2.0;
+    approx::assert_relative_eq!(estimate.entropy().unwrap(), expected, epsilon = 1e-12);
+
+    // The gain is the reduction of entropy by the update.
+    let position = PositionObservation::new(0.5);
+    let posterior = position
+        .update(
+            &estimate,
+            &DVector::from_element(1, 0.3),
+            CovarianceUpdateMethod::JosephForm,
+        )
+        .unwrap();
+    let reduction = estimate.entropy().unwrap() - posterior.entropy().unwrap();
+    let gain = estimate.expected_information_gain(&position).unwrap();
+    approx::assert_relative_eq!(gain, reduction, epsilon = 1e-12);
+
+    // The velocity is better known, so observing it is less informative.
+    let velocity = MatrixObservation::new(
+        DMatrix::from_row_slice(1, 2, &[0.0, 1.0]),
+        DMatrix::from_element(1, 1, 0.5),
+    );
+    assert!(estimate.expected_information_gain(&velocity).unwrap() < gain);
+
+    let singular = StateAndCovariance::new(DVector::zeros(2), DMatrix::zeros(2, 2));
+    assert!(singular.entropy().is_err());
+    assert_eq!(singular.expected_information_gain(&position).unwrap(), 0.0);
+}
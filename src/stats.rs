@@ -0,0 +1,187 @@
+//! Chi-square distribution functions
+
+use na::RealField;
+use nalgebra as na;
+
+const LANCZOS_G: f64 = 7.0;
+const LANCZOS_COEFFICIENTS: [f64; 9] = [
+    0.999_999_999_999_809_9,
+    676.520_368_121_885_1,
+    -1_259.139_216_722_402_8,
+    771.323_428_777_653_1,
+    -176.615_029_162_140_6,
+    12.507_343_278_686_905,
+    -0.138_571_095_265_720_12,
+    9.984_369_578_019_572e-6,
+    1.505_632_735_149_311_6e-7,
+];
+
+/// Natural logarithm of the gamma function for positive arguments.
+fn ln_gamma<R: RealField>(x: R) -> R {
+    // Lanczos approximation
+    let half: R = na::convert(0.5);
+    let x = x - R::one();
+    let mut sum: R = na::convert(LANCZOS_COEFFICIENTS[0]);
+    for (i, c) in LANCZOS_COEFFICIENTS.iter().enumerate().skip(1) {
+        sum += na::convert::<f64, R>(*c) / (x.clone() + na::convert(i as f64));
+    }
+    let t = x.clone() + na::convert(LANCZOS_G) + half.clone();
+    half * R::two_pi().ln() + (x + na::convert(0.5)) * t.clone().ln() - t + sum.ln()
+}
+
+/// Regularized lower incomplete gamma function, `P(a, x)`.
+fn regularized_gamma_p<R: RealField>(a: R, x: R) -> R {
+    if x <= R::zero() {
+        return R::zero();
+    }
+    if x < a.clone() + R::one() {
+        gamma_series(a, x)
+    } else {
+        R::one() - gamma_continued_fraction(a, x)
+    }
+}
+
+/// Regularized upper incomplete gamma function, `Q(a, x) = 1 - P(a, x)`,
+/// accurate also when it is tiny.
+fn regularized_gamma_q<R: RealField>(a: R, x: R) -> R {
+    if x <= R::zero() {
+        return R::one();
+    }
+    if x < a.clone() + R::one() {
+        R::one() - gamma_series(a, x)
+    } else {
+        gamma_continued_fraction(a, x)
+    }
+}
+
+fn gamma_prefactor<R: RealField>(a: R, x: R) -> R {
+    (a.clone() * x.clone().ln() - x - ln_gamma(a)).exp()
+}
+
+/// Series expansion of `P(a, x)`, for `x < a + 1`.
+fn gamma_series<R: RealField>(a: R, x: R) -> R {
+    let epsilon: R = na::convert(1e-15);
+    let prefactor = gamma_prefactor(a.clone(), x.clone());
+    let mut term = R::one() / a.clone();
+    let mut sum = term.clone();
+    let mut n = a;
+    for _ in 0..1000 {
+        n += R::one();
+        term *= x.clone() / n.clone();
+        sum += term.clone();
+        if term.clone().abs() < sum.clone().abs() * epsilon.clone() {
+            break;
+        }
+    }
+    sum * prefactor
+}
+
+/// Continued fraction (modified Lentz) for `Q(a, x)`, for `x >= a + 1`.
+fn gamma_continued_fraction<R: RealField>(a: R, x: R) -> R {
+    let epsilon: R = na::convert(1e-15);
+    let prefactor = gamma_prefactor(a.clone(), x.clone());
+    let tiny: R = na::convert(1e-300);
+    let mut b = x + R::one() - a.clone();
+    let mut c = R::one() / tiny.clone();
+    let mut d = R::one() / b.clone();
+    let mut h = d.clone();
+    for i in 1..1000 {
+        let i: R = na::convert(i as f64);
+        let an = -i.clone() * (i - a.clone());
+        b += na::convert(2.0);
+        d = an.clone() * d + b.clone();
+        if d.clone().abs() < tiny {
+            d = tiny.clone();
+        }
+        c = b.clone() + an / c;
+        if c.clone().abs() < tiny {
+            c = tiny.clone();
+        }
+        d = R::one() / d;
+        let delta = d.clone() * c.clone();
+        h *= delta.clone();
+        if (delta - R::one()).abs() < epsilon {
+            break;
+        }
+    }
+    prefactor * h
+}
+
+/// Upper tail probability of the standard normal distribution,
+/// `1 - Phi(x)`, accurate far into the tail.
+pub(crate) fn normal_upper_tail<R: RealField>(x: R) -> R {
+    let half: R = na::convert(0.5);
+    let q = regularized_gamma_q(half.clone(), x.clone() * x.clone() * half.clone()) * half;
+    if x >= R::zero() {
+        q
+    } else {
+        R::one() - q
+    }
+}
+
+/// Cumulative distribution function of the chi-square distribution with
+/// `dof` degrees of freedom.
+pub fn chi_squared_cdf<R: RealField>(x: R, dof: usize) -> R {
+    let half: R = na::convert(0.5);
+    regularized_gamma_p(half.clone() * na::convert(dof as f64), half * x)
+}
+
+/// Quantile function (inverse CDF) of the chi-square distribution with `dof`
+/// degrees of freedom.
+///
+/// `probability` must be in the range [0, 1).
+pub fn chi_squared_quantile<R: RealField>(probability: R, dof: usize) -> R {
+    assert!(dof > 0);
+    assert!(probability >= R::zero() && probability < R::one());
+    let dof_r: R = na::convert(dof as f64);
+    let mut lower = R::zero();
+    let mut upper = dof_r.clone() + na::convert(10.0);
+    while chi_squared_cdf(upper.clone(), dof) < probability {
+        upper *= na::convert(2.0);
+    }
+    let half: R = na::convert(0.5);
+    for _ in 0..200 {
+        let mid = (lower.clone() + upper.clone()) * half.clone();
+        if chi_squared_cdf(mid.clone(), dof) < probability {
+            lower = mid;
+        } else {
+            upper = mid;
+        }
+    }
+    (lower + upper) * half
+}
+
+/// Two-sided interval containing a chi-square distributed variable with
+/// `dof` degrees of freedom with the given `confidence`, e.g. 0.95.
+pub fn chi_squared_interval<R: RealField>(confidence: R, dof: usize) -> (R, R) {
+    let tail = (R::one() - confidence) * na::convert(0.5);
+    (
+        chi_squared_quantile(tail.clone(), dof),
+        chi_squared_quantile(R::one() - tail, dof),
+    )
+}
+
+#[test]
+fn test_chi_squared_quantile() {
+    use approx::assert_relative_eq;
+    assert_relative_eq!(
+        chi_squared_quantile(0.95, 1),
+        3.841458820694124,
+        max_relative = 1e-9
+    );
+    assert_relative_eq!(
+        chi_squared_quantile(0.95, 10),
+        18.307038053275146,
+        max_relative = 1e-9
+    );
+    assert_relative_eq!(
+        chi_squared_quantile(0.025, 4),
+        0.48441855708793,
+        max_relative = 1e-9
+    );
+    assert_relative_eq!(
+        chi_squared_cdf(2.0, 2),
+        1.0 - (-1.0f64).exp(),
+        max_relative = 1e-12
+    );
+}
@@ -0,0 +1,326 @@
+//! The steady-state Kalman filter for time-invariant models
+
+#[cfg(feature = "std")]
+use alloc::vec::Vec;
+
+use na::{DMatrix, DVector, RealField};
+use nalgebra as na;
+
+use crate::riccati::{kalman_gain, steady_state_prior_covariance};
+use crate::{
+    is_nan, linalg, Error, ErrorKind, KalmanFilterNoControl, ObservationModel, StateAndCovariance,
+    TransitionModelLinearNoControl,
+};
+
+/// A Kalman filter using the steady-state gain of a time-invariant model
+///
+/// For models whose matrices do not change, the covariance of the Kalman
+/// filter converges to a steady state, independent of the observations,
+/// and so does the gain `K`. Once they are cached, each step is only
+/// `x = F x + K (y - h(F x))`, without factoring the innovation covariance.
+/// The estimates approach those of the [KalmanFilterNoControl] as its
+/// covariance converges.
+///
+/// Create this with [KalmanFilterNoControl::into_steady_state]. It is the
+/// caller's declaration that the models are time-invariant; if they change,
+/// the cached gain is wrong.
+///
+/// The steady-state smoother gain is cached too, for the fixed-interval
+/// smoother [smooth](Self::smooth).
+pub struct SteadyStateKalmanFilter<'a, R>
+where
+    R: RealField,
+{
+    transition_model: &'a dyn TransitionModelLinearNoControl<R>,
+    observation_model: &'a dyn ObservationModel<R>,
+    gain: DMatrix<R>,
+    smoother_gain: DMatrix<R>,
+    prior_covariance: DMatrix<R>,
+    posterior_covariance: DMatrix<R>,
+}
+
+impl<'a, R> KalmanFilterNoControl<'a, R>
+where
+    R: RealField,
+{
+    /// Convert to a filter using the steady-state gain, for time-invariant
+    /// models
+    ///
+    /// The steady-state covariance is found by solving the discrete
+    /// algebraic Riccati equation, including the fading-memory factor. An
+    /// [ErrorKind::NotConverged](crate::ErrorKind::NotConverged) error is
+    /// returned if there is no steady state, e.g. for an unobservable,
+    /// unstable model, and an
+    /// [ErrorKind::SingularMatrix](crate::ErrorKind::SingularMatrix) error if
+    /// the steady-state prior covariance is singular.
+    pub fn into_steady_state(self) -> Result<SteadyStateKalmanFilter<'a, R>, Error> {
+        // Fading memory scales the propagated covariance F P F^T by alpha^2,
+        // the same as using alpha F with Q unchanged.
+        let alpha = self.fading_memory.clone().sqrt();
+        let F = self.transition_model.F() * alpha;
+        let Q = self.transition_model.Q();
+        let H = self.observation_matrix.H();
+        let R = self.observation_matrix.R();
+        let prior_covariance = steady_state_prior_covariance(&F, Q, H, R)?;
+        let gain = kalman_gain(&prior_covariance, H, R)?;
+        let n = prior_covariance.nrows();
+        let one_minus_kh = DMatrix::<R>::identity(n, n) - &gain * H;
+        let posterior_covariance =
+            (linalg::matmul3(&one_minus_kh, &prior_covariance, &one_minus_kh.transpose())
+                + linalg::matmul3(&gain, R, &gain.transpose()))
+            .symmetric_part();
+        let inv_prior_covariance = linalg::cholesky_inverse(&prior_covariance)
+            .ok_or_else(|| Error::from(ErrorKind::SingularMatrix))?;
+        let smoother_gain = linalg::matmul3(
+            &posterior_covariance,
+            &self.transition_model.FT(),
+            &inv_prior_covariance,
+        );
+        Ok(SteadyStateKalmanFilter {
+            transition_model: self.transition_model,
+            observation_model: self.observation_matrix,
+            gain,
+            smoother_gain,
+            prior_covariance,
+            posterior_covariance,
+        })
+    }
+}
+
+impl<'a, R> SteadyStateKalmanFilter<'a, R>
+where
+    R: RealField,
+{
+    /// Get the steady-state Kalman gain, `K`.
+    pub fn gain(&self) -> &DMatrix<R> {
+        &self.gain
+    }
+
+    /// Get the steady-state smoother gain, `J = P F^T P_prior^-1`, with the
+    /// posterior covariance `P`.
+    pub fn smoother_gain(&self) -> &DMatrix<R> {
+        &self.smoother_gain
+    }
+
+    /// Get the steady-state prior (predicted) covariance.
+    pub fn prior_covariance(&self) -> &DMatrix<R> {
+        &self.prior_covariance
+    }
+
+    /// Get the steady-state posterior covariance.
+    pub fn posterior_covariance(&self) -> &DMatrix<R> {
+        &self.posterior_covariance
+    }
+
+    /// Perform prediction and update steps with the steady-state gain
+    ///
+    /// Only the state of the previous estimate is used. The returned
+    /// covariance is the steady-state posterior covariance or, if any
+    /// component of the observation is NaN (not a number), the steady-state
+    /// prior covariance, as the observation is then not used. Strictly, the
+    /// covariance after a missing observation is larger than in the steady
+    /// state, until the filter converges again.
+    pub fn step(
+        &self,
+        previous_estimate: &StateAndCovariance<R>,
+        observation: &DVector<R>,
+    ) -> StateAndCovariance<R> {
+        let prior = self.transition_model.F() * previous_estimate.state();
+        if observation.iter().any(|x| is_nan(x.clone())) {
+            return StateAndCovariance::new(prior, self.prior_covariance.clone());
+        }
+        let innovation = observation - self.observation_model.predict_observation(&prior);
+        let state = prior + &self.gain * innovation;
+        StateAndCovariance::new(state, self.posterior_covariance.clone())
+    }
+
+    /// Fixed-interval smoother using the steady-state filter and smoother
+    /// gains
+    ///
+    /// The observations are filtered with [step](Self::step), and the
+    /// backward pass is `x_s = x + J (x_s' - F x)`, which costs `O(n^2)` per
+    /// step, without factoring a covariance. The smoothed covariance follows
+    /// `P_s = P + J (P_s' - P_prior) J^T` back from the posterior
+    /// covariance at the last step, and is reused once it has converged, so
+    /// only the last few steps cost `O(n^3)`. As for the filter, the
+    /// estimates approach those of
+    /// [KalmanFilterNoControl::smooth] away from the initial estimate.
+    #[cfg(feature = "std")]
+    pub fn smooth(
+        &self,
+        initial_estimate: &StateAndCovariance<R>,
+        observations: &[DVector<R>],
+    ) -> Vec<StateAndCovariance<R>> {
+        let mut filtered: Vec<StateAndCovariance<R>> = Vec::with_capacity(observations.len());
+        for observation in observations.iter() {
+            let previous = filtered.last().unwrap_or(initial_estimate);
+            filtered.push(self.step(previous, observation));
+        }
+
+        let F = self.transition_model.F();
+        let J = &self.smoother_gain;
+        let JT = J.transpose();
+        let tolerance: R = na::convert(1e-12);
+        let mut converged = false;
+        let mut smoothed = filtered;
+        for k in (0..smoothed.len().saturating_sub(1)).rev() {
+            let future = &smoothed[k + 1];
+            let filt = &smoothed[k];
+            let state = filt.state() + J * (future.state() - F * filt.state());
+            let covariance = if converged {
+                future.covariance().clone()
+            } else {
+                let covariance = (filt.covariance()
+                    + linalg::matmul3(J, &(future.covariance() - &self.prior_covariance), &JT))
+                .symmetric_part();
+                let change = (&covariance - future.covariance()).amax();
+                converged = change <= tolerance.clone() * covariance.amax();
+                covariance
+            };
+            smoothed[k] = StateAndCovariance::new(state, covariance);
+        }
+        smoothed
+    }
+}
+
+#[test]
+fn test_steady_state() {
+    use crate::test_util::{
+        initial_estimate, simulate_positions, ConstantVelocity, PositionObservation,
+    };
+
+    let transition = ConstantVelocity::new(0.1, 1.0);
+    let observation = PositionObservation::new(0.5);
+    let kf = KalmanFilterNoControl::new(&transition, &observation);
+    let observations = simulate_positions(400, 0.1, 1.0, 0.5, 14);
+    let estimates = kf.filter(&initial_estimate(), &observations).unwrap();
+
+    // Once the Kalman filter has converged, the steady-state filter gives
+    // the same estimates.
+    let steady = KalmanFilterNoControl::new(&transition, &observation)
+        .into_steady_state()
+        .unwrap();
+    let converged = &estimates[300];
+    approx::assert_relative_eq!(
+        steady.posterior_covariance(),
+        converged.covariance(),
+        epsilon = 1e-9
+    );
+    let mut estimate = converged.clone();
+    for (z, expected) in observations[301..].iter().zip(&estimates[301..]) {
+        estimate = steady.step(&estimate, z);
+        approx::assert_relative_eq!(&estimate, expected, epsilon = 1e-9);
+    }
+}
+
+#[test]
+fn test_steady_state_fading_memory() {
+    use crate::test_util::{
+        initial_estimate, simulate_positions, ConstantVelocity, PositionObservation,
+    };
+
+    // With fading memory, the steady state is that of the prior covariance
+    // alpha^2 F P F^T + Q, to which the filter converges.
+    let transition = ConstantVelocity::new(0.1, 1.0);
+    let observation = PositionObservation::new(0.5);
+    let kf = || KalmanFilterNoControl::new(&transition, &observation).with_fading_memory(1.1);
+    let observations = simulate_positions(200, 0.1, 1.0, 0.5, 14);
+    let estimates = kf().filter(&initial_estimate(), &observations).unwrap();
+    let steady = kf().into_steady_state().unwrap();
+    let converged = &estimates[150];
+    approx::assert_relative_eq!(
+        steady.posterior_covariance(),
+        converged.covariance(),
+        epsilon = 1e-9
+    );
+    let prior = kf().predict(converged);
+    approx::assert_relative_eq!(
+        steady.prior_covariance(),
+        prior.covariance(),
+        epsilon = 1e-9
+    );
+    let mut estimate = converged.clone();
+    for (z, expected) in observations[151..].iter().zip(&estimates[151..]) {
+        estimate = steady.step(&estimate, z);
+        approx::assert_relative_eq!(&estimate, expected, epsilon = 1e-9);
+    }
+}
+
+#[test]
+fn test_steady_state_smoother() {
+    use crate::test_util::{simulate_positions, ConstantVelocity, PositionObservation};
+
+    let transition = ConstantVelocity::new(0.1, 1.0);
+    let observation = PositionObservation::new(0.5);
+    let kf = KalmanFilterNoControl::new(&transition, &observation);
+    let steady = KalmanFilterNoControl::new(&transition, &observation)
+        .into_steady_state()
+        .unwrap();
+    let observations = simulate_positions(300, 0.1, 1.0, 0.5, 21);
+
+    // Starting from the steady state, the Kalman filter stays there, and
+    // the smoothers agree.
+    let start = StateAndCovariance::new(DVector::zeros(2), steady.posterior_covariance().clone());
+    let expected = kf.smooth(&start, &observations).unwrap();
+    let smoothed = steady.smooth(&start, &observations);
+    approx::assert_relative_eq!(smoothed.as_slice(), expected.as_slice(), epsilon = 1e-8);
+    assert_eq!(smoothed[0].covariance(), smoothed[100].covariance());
+}
+
+#[test]
+fn test_steady_state_fixed_gain() {
+    use crate::test_util::{
+        initial_estimate, simulate_positions, ConstantVelocity, PositionObservation,
+    };
+    use crate::{LinearObservationModel, LinearTransitionModel};
+
+    // The cached covariances are a fixed point of the prediction and update,
+    // with the gains they give.
+    let transition = ConstantVelocity::new(0.1, 1.0);
+    let observation = PositionObservation::new(0.5);
+    let steady = KalmanFilterNoControl::new(&transition, &observation)
+        .into_steady_state()
+        .unwrap();
+    let F = transition.F();
+    let H = observation.H();
+    let prior = steady.prior_covariance();
+    let posterior = steady.posterior_covariance();
+    approx::assert_relative_eq!(
+        prior,
+        &(F * posterior * F.transpose() + transition.Q()),
+        epsilon = 1e-9
+    );
+    let S = H * prior * H.transpose() + observation.R();
+    let gain = prior * H.transpose() * S.try_inverse().unwrap();
+    approx::assert_relative_eq!(steady.gain(), &gain, epsilon = 1e-9);
+    approx::assert_relative_eq!(posterior, &(prior - &gain * H * prior), epsilon = 1e-9);
+    let smoother_gain = posterior * F.transpose() * prior.clone().try_inverse().unwrap();
+    approx::assert_relative_eq!(steady.smoother_gain(), &smoother_gain, epsilon = 1e-9);
+
+    // From a prior away from the steady state, the gain is still fixed, and
+    // the covariance is the steady-state one.
+    let observations = simulate_positions(2, 0.1, 1.0, 0.5, 6);
+    let initial = initial_estimate();
+    let predicted = F * initial.state();
+    let first = steady.step(&initial, &observations[0]);
+    let expected = &predicted + &gain * (&observations[0] - H * &predicted);
+    approx::assert_relative_eq!(first.state(), &expected, epsilon = 1e-9);
+    assert_eq!(first.covariance(), posterior);
+
+    // A missing observation keeps the predicted state, with the prior
+    // covariance.
+    let missing = steady.step(&first, &DVector::from_element(1, f64::NAN));
+    approx::assert_relative_eq!(missing.state(), &(F * first.state()), epsilon = 1e-12);
+    assert_eq!(missing.covariance(), prior);
+    assert!(steady.smooth(&initial, &[]).is_empty());
+
+    // A growing, unobserved state has no steady state.
+    let scalar = |x: f64| DMatrix::from_element(1, 1, x);
+    let unstable = LinearTransitionModel::from_matrices(scalar(2.0), scalar(1.0));
+    let blind = LinearObservationModel::from_matrices(scalar(0.0), scalar(1.0));
+    let err = KalmanFilterNoControl::new(&unstable, &blind)
+        .into_steady_state()
+        .err()
+        .unwrap();
+    assert!(matches!(err.kind(), ErrorKind::NotConverged));
+}
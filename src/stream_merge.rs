@@ -0,0 +1,206 @@
+//! Merging asynchronous timestamped measurement streams
+
+use na::{DVector, RealField};
+use nalgebra as na;
+
+use crate::{
+    is_nan, ContinuousLinearModel, CovarianceUpdateMethod, Error, ErrorKind, ObservationModel,
+    StateAndCovariance, TransitionModelLinearNoControl,
+};
+
+/// A measurement of one sensor, in a chronologically ordered stream of the
+/// measurements of several sensors
+#[derive(Debug, Clone, PartialEq)]
+pub struct MeasurementEvent<R>
+where
+    R: RealField,
+{
+    /// The time of the measurement.
+    pub time: R,
+    /// The time since the previous event, or since the start time for the
+    /// first; zero for measurements simultaneous with the previous one.
+    pub dt: R,
+    /// The index of the sensor's stream.
+    pub sensor: usize,
+    /// The measurement, with NaN components if it is missing.
+    pub observation: DVector<R>,
+}
+
+/// Merge the timestamped measurements of several sensors into a single
+/// chronologically ordered stream
+///
+/// `streams[i]` holds the `(time, observation)` pairs of sensor `i`, in
+/// order of time. Each sensor may have its own rate and gaps. Simultaneous
+/// measurements are ordered by sensor index, and the later ones have a `dt`
+/// of zero. An [ErrorKind::UnorderedTimestamps] error is returned if the
+/// times of a stream decrease or precede `start_time`, with the index of the
+/// offending measurement as its step.
+pub fn merge_streams<R>(
+    start_time: R,
+    streams: &[&[(R, DVector<R>)]],
+) -> Result<Vec<MeasurementEvent<R>>, Error>
+where
+    R: RealField,
+{
+    for (i, stream) in streams.iter().enumerate() {
+        let mut previous = &start_time;
+        for (j, (time, _)) in stream.iter().enumerate() {
+            if time < previous {
+                return Err(Error::from(ErrorKind::UnorderedTimestamps { stream: i }).with_step(j));
+            }
+            previous = time;
+        }
+    }
+
+    let total = streams.iter().map(|s| s.len()).sum();
+    let mut events = Vec::with_capacity(total);
+    let mut heads = vec![0; streams.len()];
+    let mut last_time = start_time;
+    for _ in 0..total {
+        // The earliest head, taking the lowest sensor index among ties.
+        let mut next: Option<(usize, &R)> = None;
+        for (i, stream) in streams.iter().enumerate() {
+            if let Some((time, _)) = stream.get(heads[i]) {
+                if next.as_ref().is_none_or(|(_, earliest)| time < *earliest) {
+                    next = Some((i, time));
+                }
+            }
+        }
+        let (sensor, _) = next.unwrap();
+        let (time, observation) = &streams[sensor][heads[sensor]];
+        heads[sensor] += 1;
+        events.push(MeasurementEvent {
+            time: time.clone(),
+            dt: time.clone() - last_time,
+            sensor,
+            observation: observation.clone(),
+        });
+        last_time = time.clone();
+    }
+    Ok(events)
+}
+
+/// Kalman filter a merged stream of measurements, returning the estimate
+/// after each event
+///
+/// Before each event, the estimate is predicted over its `dt` with the
+/// discretization of the continuous-time model, and it is then updated with
+/// the observation model of its sensor, `sensors[event.sensor]`.
+/// Simultaneous measurements are applied sequentially, without prediction
+/// between them, and missing (NaN) measurements leave the prediction
+/// unchanged. An [ErrorKind::DimensionMismatch] error is returned if an
+/// event's sensor has no observation model. Errors give the index of the
+/// failed event.
+pub fn filter_events<R>(
+    transition_model: &mut ContinuousLinearModel<R>,
+    sensors: &[&dyn ObservationModel<R>],
+    initial_estimate: &StateAndCovariance<R>,
+    events: &[MeasurementEvent<R>],
+) -> Result<Vec<StateAndCovariance<R>>, Error>
+where
+    R: RealField,
+{
+    let mut estimates: Vec<StateAndCovariance<R>> = Vec::with_capacity(events.len());
+    for (k, event) in events.iter().enumerate() {
+        let previous = estimates.last().unwrap_or(initial_estimate);
+        let sensor = sensors.get(event.sensor).ok_or_else(|| {
+            Error::from(ErrorKind::DimensionMismatch {
+                expected: (sensors.len(), 1),
+                actual: (event.sensor + 1, 1),
+                matrix: "sensor",
+            })
+            .with_step(k)
+        })?;
+        let prior = if event.dt == R::zero() {
+            previous.clone()
+        } else {
+            transition_model
+                .discretize(event.dt.clone())
+                .predict(previous)
+        };
+        let estimate = if event.observation.iter().any(|x| is_nan(x.clone())) {
+            prior
+        } else {
+            sensor
+                .update(
+                    &prior,
+                    &event.observation,
+                    CovarianceUpdateMethod::JosephForm,
+                )
+                .map_err(|e| e.with_step(k))?
+        };
+        estimates.push(estimate);
+    }
+    Ok(estimates)
+}
+
+#[test]
+fn test_merge_streams() {
+    use crate::test_util::{initial_estimate, ConstantVelocity, PositionObservation};
+    use crate::KalmanFilterNoControl;
+    use na::DMatrix;
+
+    let z = |x: f64| DVector::from_element(1, x);
+    let fast: Vec<(f64, DVector<f64>)> = (1..=6).map(|i| (0.5 * i as f64, z(i as f64))).collect();
+    // A slow sensor with a dropout, simultaneous with the fast sensor at 1.0
+    // and 3.0.
+    let slow = vec![(1.0, z(2.5)), (2.2, z(f64::NAN)), (3.0, z(6.5))];
+    let events = merge_streams(0.0, &[&fast, &slow]).unwrap();
+    let order: Vec<(f64, usize)> = events.iter().map(|e| (e.time, e.sensor)).collect();
+    assert_eq!(
+        order,
+        vec![
+            (0.5, 0),
+            (1.0, 0),
+            (1.0, 1),
+            (1.5, 0),
+            (2.0, 0),
+            (2.2, 1),
+            (2.5, 0),
+            (3.0, 0),
+            (3.0, 1)
+        ]
+    );
+    assert_eq!(events[2].dt, 0.0);
+    approx::assert_relative_eq!(events[5].dt, 0.2, epsilon = 1e-12);
+
+    let A = DMatrix::from_row_slice(2, 2, &[0.0, 1.0, 0.0, 0.0]);
+    let Qc = DMatrix::from_row_slice(2, 2, &[0.0, 0.0, 0.0, 1.0]);
+    let mut model = ContinuousLinearModel::new(A, Qc);
+    let position = PositionObservation::new(0.5);
+    let sensors: Vec<&dyn ObservationModel<f64>> = vec![&position, &position];
+
+    // The fast sensor alone is filtered as at its regular interval.
+    let estimates = filter_events(
+        &mut model,
+        &sensors,
+        &initial_estimate(),
+        &merge_streams(0.0, &[&fast]).unwrap(),
+    )
+    .unwrap();
+    let regular = ConstantVelocity::new(0.5, 1.0);
+    let observations: Vec<DVector<f64>> = fast.iter().map(|(_, o)| o.clone()).collect();
+    let expected = KalmanFilterNoControl::new(&regular, &position)
+        .filter(&initial_estimate(), &observations)
+        .unwrap();
+    approx::assert_relative_eq!(estimates.as_slice(), expected.as_slice(), epsilon = 1e-9);
+
+    // A simultaneous measurement is a further update without prediction,
+    // and the dropout is a prediction alone.
+    let estimates = filter_events(&mut model, &sensors, &initial_estimate(), &events).unwrap();
+    let update = position
+        .update(&estimates[1], &z(2.5), CovarianceUpdateMethod::JosephForm)
+        .unwrap();
+    assert_eq!(estimates[2], update);
+    let gap = model.discretize(0.2).predict(&estimates[4]);
+    approx::assert_relative_eq!(estimates[5], gap, epsilon = 1e-12);
+
+    let unordered = vec![(1.0, z(1.0)), (0.5, z(2.0))];
+    let err = merge_streams(0.0, &[&fast, &unordered]).unwrap_err();
+    assert!(matches!(
+        err.kind(),
+        ErrorKind::UnorderedTimestamps { stream: 1 }
+    ));
+    assert_eq!(err.step(), Some(1));
+    assert!(filter_events(&mut model, &sensors[..1], &initial_estimate(), &events).is_err());
+}
@@ -0,0 +1,221 @@
+//! Filtering and fixed-lag smoothing of streams of observations
+
+use std::collections::VecDeque;
+
+use na::{DVector, RealField};
+use nalgebra as na;
+
+use crate::{Error, KalmanFilterNoControl, StateAndCovariance};
+
+/// Filters and fixed-lag smooths a stream of observations, one at a time
+///
+/// Observations are given one at a time with [push](Self::push), so a time
+/// series far too large to hold in memory can be read and processed in
+/// chunks of any size; the chunk boundaries make no difference to the
+/// results. Only the last `lag + 1` filtered estimates are kept.
+///
+/// Each estimate is emitted once `lag` further observations have been
+/// pushed, smoothed with the RTS smoother over those observations. The last
+/// `lag` estimates are emitted by [finish](Self::finish), smoothed with the
+/// remaining observations. With a lag of zero, the filtered estimates are
+/// emitted; with a lag at least the length of the series, the estimates are
+/// those of [smooth](KalmanFilterNoControl::smooth).
+pub struct StreamingSmoother<'a, R>
+where
+    R: RealField,
+{
+    filter: KalmanFilterNoControl<'a, R>,
+    lag: usize,
+    previous_estimate: StateAndCovariance<R>,
+    window: VecDeque<StateAndCovariance<R>>,
+    step: usize,
+}
+
+impl<'a, R> StreamingSmoother<'a, R>
+where
+    R: RealField,
+{
+    /// Start processing a stream from the estimate before its first
+    /// observation, smoothing each estimate with `lag` later observations.
+    pub fn new(
+        filter: KalmanFilterNoControl<'a, R>,
+        initial_estimate: StateAndCovariance<R>,
+        lag: usize,
+    ) -> Self {
+        Self {
+            filter,
+            lag,
+            previous_estimate: initial_estimate,
+            window: VecDeque::with_capacity(lag + 1),
+            step: 0,
+        }
+    }
+
+    /// The number of observations pushed so far.
+    pub fn len(&self) -> usize {
+        self.step
+    }
+
+    /// Whether no observations have been pushed.
+    pub fn is_empty(&self) -> bool {
+        self.step == 0
+    }
+
+    /// Filter the next observation, returning the smoothed estimate `lag`
+    /// steps back, if there is one yet
+    ///
+    /// If any component of the observation is NaN, it is treated as
+    /// missing. Errors give the index of the failed step in the stream.
+    pub fn push(
+        &mut self,
+        observation: &DVector<R>,
+    ) -> Result<Option<StateAndCovariance<R>>, Error> {
+        let estimate = self
+            .filter
+            .step(&self.previous_estimate, observation)
+            .map_err(|e| e.with_step(self.step))?;
+        self.step += 1;
+        self.previous_estimate = estimate.clone();
+        self.window.push_back(estimate);
+        if self.window.len() <= self.lag {
+            return Ok(None);
+        }
+        let smoothed = self.smooth_window(1)?;
+        self.window.pop_front();
+        Ok(smoothed.into_iter().next())
+    }
+
+    /// Smooth the estimates remaining at the end of the stream, oldest
+    /// first.
+    pub fn finish(self) -> Result<Vec<StateAndCovariance<R>>, Error> {
+        self.smooth_window(self.window.len())
+    }
+
+    /// Smooth over the window and return the first `count` estimates.
+    fn smooth_window(&self, count: usize) -> Result<Vec<StateAndCovariance<R>>, Error> {
+        let mut smoothed: Vec<StateAndCovariance<R>> = Vec::with_capacity(self.window.len());
+        let mut future = match self.window.back() {
+            Some(last) => last.clone(),
+            None => return Ok(smoothed),
+        };
+        smoothed.push(future.clone());
+        let first_step = self.step - self.window.len();
+        for i in (0..self.window.len() - 1).rev() {
+            future = self
+                .filter
+                .smooth_step(&future, &self.window[i])
+                .map_err(|e| e.with_step(first_step + i))?;
+            smoothed.push(future.clone());
+        }
+        smoothed.reverse();
+        smoothed.truncate(count);
+        Ok(smoothed)
+    }
+}
+
+#[test]
+fn test_streaming_smoother() {
+    use crate::test_util::{
+        initial_estimate, simulate_positions, ConstantVelocity, PositionObservation,
+    };
+
+    let transition = ConstantVelocity::new(0.1, 1.0);
+    let observation = PositionObservation::new(0.5);
+    let kf = KalmanFilterNoControl::new(&transition, &observation);
+    let observations = simulate_positions(12, 0.1, 1.0, 0.5, 11);
+    let lag = 3;
+
+    let mut stream = StreamingSmoother::new(
+        KalmanFilterNoControl::new(&transition, &observation),
+        initial_estimate(),
+        lag,
+    );
+    let mut actual = Vec::new();
+    for chunk in observations.chunks(5) {
+        for z in chunk {
+            actual.extend(stream.push(z).unwrap());
+        }
+    }
+    assert_eq!(actual.len(), observations.len() - lag);
+    actual.extend(stream.finish().unwrap());
+
+    // Each estimate is that of the full smoother over the observations up
+    // to `lag` steps later.
+    for (i, a) in actual.iter().enumerate() {
+        let end = (i + lag + 1).min(observations.len());
+        let expected = kf
+            .smooth(&initial_estimate(), &observations[..end])
+            .unwrap();
+        approx::assert_relative_eq!(a, &expected[i], epsilon = 1e-12);
+    }
+}
+
+#[test]
+fn test_streaming_window() {
+    use crate::test_util::{
+        initial_estimate, simulate_positions, ConstantVelocity, PositionObservation,
+    };
+
+    let transition = ConstantVelocity::new(0.1, 1.0);
+    let observation = PositionObservation::new(0.5);
+    let kf = || KalmanFilterNoControl::new(&transition, &observation);
+    let mut observations = simulate_positions(10, 0.1, 1.0, 0.5, 5);
+    observations[4] = DVector::from_element(1, f64::NAN);
+    let lag = 2;
+
+    // The chunk boundaries make no difference, and only the last `lag + 1`
+    // filtered estimates are kept.
+    let run = |chunk_size: usize| {
+        let mut stream = StreamingSmoother::new(kf(), initial_estimate(), lag);
+        let mut estimates = Vec::new();
+        for chunk in observations.chunks(chunk_size) {
+            for z in chunk {
+                estimates.extend(stream.push(z).unwrap());
+                assert!(stream.window.len() <= lag + 1);
+            }
+        }
+        assert_eq!(stream.len(), observations.len());
+        estimates.extend(stream.finish().unwrap());
+        estimates
+    };
+    let expected = run(1);
+    assert_eq!(expected.len(), observations.len());
+    for chunk_size in [3, 4, observations.len()] {
+        assert_eq!(run(chunk_size), expected);
+    }
+
+    // With no lag, the filtered estimates are emitted at once, and nothing
+    // remains.
+    let mut stream = StreamingSmoother::new(kf(), initial_estimate(), 0);
+    let filtered = kf().filter(&initial_estimate(), &observations).unwrap();
+    for (z, f) in observations.iter().zip(filtered.iter()) {
+        assert_eq!(stream.push(z).unwrap().as_ref(), Some(f));
+    }
+    assert!(stream.finish().unwrap().is_empty());
+
+    // With a lag as long as the series, nothing is emitted until the stream
+    // finishes with the smoothed estimates.
+    let mut stream = StreamingSmoother::new(kf(), initial_estimate(), observations.len());
+    for z in observations.iter() {
+        assert!(stream.push(z).unwrap().is_none());
+    }
+    let smoothed = kf().smooth(&initial_estimate(), &observations).unwrap();
+    approx::assert_relative_eq!(
+        stream.finish().unwrap().as_slice(),
+        smoothed.as_slice(),
+        epsilon = 1e-12
+    );
+
+    // An empty stream finishes with no estimates.
+    let stream = StreamingSmoother::new(kf(), initial_estimate(), lag);
+    assert!(stream.is_empty());
+    assert!(stream.finish().unwrap().is_empty());
+
+    // Errors give the index of the step in the whole stream.
+    let mut stream = StreamingSmoother::new(kf(), initial_estimate(), lag);
+    for z in observations[..3].iter() {
+        stream.push(z).unwrap();
+    }
+    let err = stream.push(&DVector::zeros(2)).unwrap_err();
+    assert_eq!(err.step(), Some(3));
+}
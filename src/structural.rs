@@ -0,0 +1,229 @@
+//! Structural time-series models, composed of level, trend and seasonal
+//! components
+
+use alloc::vec::Vec;
+
+use na::{DMatrix, DVector, RealField};
+use nalgebra as na;
+
+use crate::{LinearObservationModel, LinearTransitionModel, StateAndCovariance};
+
+#[derive(Debug, Clone, PartialEq)]
+enum Component<R>
+where
+    R: RealField,
+{
+    LocalLevel {
+        level_variance: R,
+    },
+    LocalLinearTrend {
+        level_variance: R,
+        slope_variance: R,
+    },
+    Seasonal {
+        period: usize,
+        variance: R,
+    },
+}
+
+impl<R> Component<R>
+where
+    R: RealField,
+{
+    fn state_dim(&self) -> usize {
+        match self {
+            Self::LocalLevel { .. } => 1,
+            Self::LocalLinearTrend { .. } => 2,
+            Self::Seasonal { period, .. } => period - 1,
+        }
+    }
+
+    /// The blocks of `F` and `Q`, and the row of `H`.
+    fn blocks(&self) -> (DMatrix<R>, DMatrix<R>, DMatrix<R>) {
+        let n = self.state_dim();
+        let mut F = DMatrix::zeros(n, n);
+        let mut Q = DMatrix::zeros(n, n);
+        let mut H = DMatrix::zeros(1, n);
+        H[(0, 0)] = R::one();
+        match self {
+            Self::LocalLevel { level_variance } => {
+                F[(0, 0)] = R::one();
+                Q[(0, 0)] = level_variance.clone();
+            }
+            Self::LocalLinearTrend {
+                level_variance,
+                slope_variance,
+            } => {
+                F[(0, 0)] = R::one();
+                F[(0, 1)] = R::one();
+                F[(1, 1)] = R::one();
+                Q[(0, 0)] = level_variance.clone();
+                Q[(1, 1)] = slope_variance.clone();
+            }
+            Self::Seasonal { variance, .. } => {
+                // The seasonal effects over a period sum to zero, up to the
+                // noise, and the earlier effects are shifted down.
+                F.row_mut(0).fill(-R::one());
+                for i in 1..n {
+                    F[(i, i - 1)] = R::one();
+                }
+                Q[(0, 0)] = variance.clone();
+            }
+        }
+        (F, Q, H)
+    }
+}
+
+/// A structural time-series model, the sum of level, trend and seasonal
+/// components and observation noise
+///
+/// The components are added with the `with_` methods, and their states are
+/// stacked in that order, so the transition model is block-diagonal and the
+/// observation is the sum of the first state of each component:
+///
+/// * the local level `mu_{t+1} = mu_t + eta_t` has one state;
+/// * the local linear trend `mu_{t+1} = mu_t + nu_t + eta_t`,
+///   `nu_{t+1} = nu_t + zeta_t` has the level and the slope;
+/// * the seasonal component of period `s` has the `s - 1` latest seasonal
+///   effects, which sum to `-gamma_{t+1} + omega_t` over a period.
+///
+/// A zero variance makes a component fixed, e.g. a deterministic trend.
+#[derive(Debug, Clone, PartialEq)]
+pub struct StructuralModel<R>
+where
+    R: RealField,
+{
+    observation_variance: R,
+    components: Vec<Component<R>>,
+}
+
+impl<R> StructuralModel<R>
+where
+    R: RealField,
+{
+    /// Create a model with no components and the given observation noise
+    /// variance.
+    pub fn new(observation_variance: R) -> Self {
+        Self {
+            observation_variance,
+            components: Vec::new(),
+        }
+    }
+
+    /// Add a local level, a random walk with the given variance.
+    pub fn with_local_level(mut self, level_variance: R) -> Self {
+        self.components
+            .push(Component::LocalLevel { level_variance });
+        self
+    }
+
+    /// Add a local linear trend, a level with a slope which is a random
+    /// walk, with the given variances.
+    pub fn with_local_linear_trend(mut self, level_variance: R, slope_variance: R) -> Self {
+        self.components.push(Component::LocalLinearTrend {
+            level_variance,
+            slope_variance,
+        });
+        self
+    }
+
+    /// Add a seasonal component of the given period, in steps, in dummy
+    /// variable form
+    ///
+    /// # Panics
+    ///
+    /// Panics if the period is less than 2.
+    pub fn with_seasonal(mut self, period: usize, variance: R) -> Self {
+        assert!(period >= 2, "the seasonal period must be at least 2");
+        self.components
+            .push(Component::Seasonal { period, variance });
+        self
+    }
+
+    /// The total number of states of the components.
+    pub fn state_dim(&self) -> usize {
+        self.components.iter().map(|c| c.state_dim()).sum()
+    }
+
+    /// The index of the first state of each component, in the order they
+    /// were added; that of a level or trend is its level.
+    pub fn component_offsets(&self) -> Vec<usize> {
+        let mut offset = 0;
+        self.components
+            .iter()
+            .map(|c| {
+                let start = offset;
+                offset += c.state_dim();
+                start
+            })
+            .collect()
+    }
+
+    /// The block-diagonal transition model of the components.
+    pub fn transition_model(&self) -> LinearTransitionModel<R> {
+        let n = self.state_dim();
+        let mut F = DMatrix::zeros(n, n);
+        let mut Q = DMatrix::zeros(n, n);
+        for (component, offset) in self.components.iter().zip(self.component_offsets()) {
+            let (F_block, Q_block, _) = component.blocks();
+            let m = component.state_dim();
+            F.slice_mut((offset, offset), (m, m)).copy_from(&F_block);
+            Q.slice_mut((offset, offset), (m, m)).copy_from(&Q_block);
+        }
+        LinearTransitionModel::from_matrices(F, Q)
+    }
+
+    /// The observation model, the sum of the components plus noise.
+    pub fn observation_model(&self) -> LinearObservationModel<R> {
+        let n = self.state_dim();
+        let mut H = DMatrix::zeros(1, n);
+        for (component, offset) in self.components.iter().zip(self.component_offsets()) {
+            let (_, _, H_block) = component.blocks();
+            H.slice_mut((0, offset), (1, component.state_dim()))
+                .copy_from(&H_block);
+        }
+        let R = DMatrix::from_element(1, 1, self.observation_variance.clone());
+        LinearObservationModel::from_matrices(H, R)
+    }
+
+    /// A vague initial estimate, with zero states and the given variance,
+    /// large compared with the scale of the series, for each.
+    pub fn initial_estimate(&self, variance: R) -> StateAndCovariance<R> {
+        let n = self.state_dim();
+        StateAndCovariance::new(
+            DVector::zeros(n),
+            DMatrix::from_diagonal_element(n, n, variance),
+        )
+    }
+}
+
+#[test]
+fn test_structural_model() {
+    use crate::{KalmanFilterNoControl, ObservationModel, TransitionModelLinearNoControl};
+
+    let model = StructuralModel::new(0.01)
+        .with_local_linear_trend(0.0, 0.0)
+        .with_seasonal(4, 0.0);
+    assert_eq!(model.state_dim(), 5);
+    assert_eq!(model.component_offsets(), vec![0, 2]);
+    let transition = model.transition_model();
+    let observation = model.observation_model();
+    let effects = DVector::from_vec(vec![0.0, 0.0, 1.0, -1.0, 2.0]);
+    let next = transition.F() * effects;
+    assert_eq!(next.as_slice(), &[0.0, 0.0, -2.0, 1.0, -1.0]);
+    assert_eq!(observation.H().as_slice(), &[1.0, 0.0, 1.0, 0.0, 0.0]);
+
+    // A deterministic trend and seasonal pattern is recovered and
+    // extrapolated.
+    let pattern = [1.0, -1.0, 2.0, -2.0];
+    let series = |t: usize| 2.0 + 0.5 * t as f64 + pattern[t % 4];
+    let observations: Vec<DVector<f64>> = (0..40)
+        .map(|t| DVector::from_element(1, series(t)))
+        .collect();
+    let kf = KalmanFilterNoControl::new(&transition, &observation);
+    let estimates = kf
+        .filter(&model.initial_estimate(1e2), &observations)
+        .unwrap();
+    let forecast = observation.H() * kf.predict(&estimates[39]).state();
+    approx::assert_relative_eq!(forecast[0], series(40), epsilon = 1e-2);
+}
@@ -0,0 +1,233 @@
+use alloc::borrow::Cow;
+use alloc::vec::Vec;
+
+use na::{DMatrix, DVector, RealField};
+use nalgebra as na;
+
+use crate::{
+    check_dimension, is_nan, CovarianceUpdateMethod, Error, KalmanFilterNoControl,
+    ObservationModel, StateAndCovariance,
+};
+
+/// An observation model restricted to a subset of the rows of another
+///
+/// The rows of the Jacobian of a non-linear model are selected in the same
+/// way as those of `H`.
+pub(crate) struct SubsetObservationModel<'a, R>
+where
+    R: RealField,
+{
+    inner: &'a dyn ObservationModel<R>,
+    rows: Vec<usize>,
+    H: DMatrix<R>,
+    HT: DMatrix<R>,
+    R: DMatrix<R>,
+}
+
+impl<'a, R> SubsetObservationModel<'a, R>
+where
+    R: RealField,
+{
+    /// Restrict `inner` to the observation components in `rows`.
+    pub(crate) fn new(inner: &'a dyn ObservationModel<R>, rows: Vec<usize>) -> Self {
+        let H = inner.H().select_rows(rows.iter());
+        let HT = H.transpose();
+        let R = inner
+            .R()
+            .select_rows(rows.iter())
+            .select_columns(rows.iter());
+        Self {
+            inner,
+            rows,
+            H,
+            HT,
+            R,
+        }
+    }
+
+    /// Select the components of a full observation used by this model.
+    pub(crate) fn select(&self, observation: &DVector<R>) -> DVector<R> {
+        observation.select_rows(self.rows.iter())
+    }
+}
+
+impl<'a, R> ObservationModel<R> for SubsetObservationModel<'a, R>
+where
+    R: RealField,
+{
+    fn predict_observation(&self, state: &DVector<R>) -> DVector<R> {
+        self.select(&self.inner.predict_observation(state))
+    }
+    fn H(&self) -> &DMatrix<R> {
+        &self.H
+    }
+    fn HT(&self) -> Cow<'_, DMatrix<R>> {
+        Cow::Borrowed(&self.HT)
+    }
+    fn jacobian_at(&self, state: &DVector<R>) -> Cow<'_, DMatrix<R>> {
+        match self.inner.jacobian_at(state) {
+            Cow::Borrowed(_) => Cow::Borrowed(&self.H),
+            Cow::Owned(H) => Cow::Owned(H.select_rows(self.rows.iter())),
+        }
+    }
+    fn R(&self) -> &DMatrix<R> {
+        &self.R
+    }
+    fn state_dim(&self) -> usize {
+        self.inner.state_dim()
+    }
+    fn obs_dim(&self) -> usize {
+        self.rows.len()
+    }
+}
+
+impl<'a, R> KalmanFilterNoControl<'a, R>
+where
+    R: RealField,
+{
+    /// Perform prediction and update steps using only the observation
+    /// components marked valid
+    ///
+    /// `valid` has one flag per observation component, e.g. as reported by
+    /// a sensor. The update uses the rows of `H`, and the rows and columns
+    /// of `R`, of the valid components only, and follows the
+    /// [FailurePolicy](crate::FailurePolicy) of the filter. A component with
+    /// a NaN value is also treated as invalid. If no component is valid, the
+    /// prior is returned. An
+    /// [ErrorKind::DimensionMismatch](crate::ErrorKind::DimensionMismatch)
+    /// error is returned if `valid` does not have one flag per component.
+    pub fn step_masked(
+        &self,
+        previous_estimate: &StateAndCovariance<R>,
+        observation: &DVector<R>,
+        valid: &[bool],
+    ) -> Result<StateAndCovariance<R>, Error> {
+        self.check_dimensions(previous_estimate, Some(observation))?;
+        check_dimension("valid", (observation.nrows(), 1), (valid.len(), 1))?;
+        let prior = self.predict(previous_estimate);
+        let rows: Vec<usize> = (0..observation.nrows())
+            .filter(|&i| valid[i] && !is_nan(observation[i].clone()))
+            .collect();
+        if rows.is_empty() {
+            return Ok(prior);
+        }
+        let subset = SubsetObservationModel::new(self.observation_matrix, rows);
+        self.with_observation_model(&subset).update_with_policy(
+            &prior,
+            &subset.select(observation),
+            subset.R(),
+            CovarianceUpdateMethod::JosephForm,
+        )
+    }
+}
+
+#[test]
+fn test_step_masked() {
+    use crate::test_util::{initial_estimate, ConstantVelocity, MatrixObservation};
+
+    let transition = ConstantVelocity::new(0.1, 1.0);
+    let observation =
+        MatrixObservation::new(DMatrix::identity(2, 2), DMatrix::identity(2, 2) * 0.5);
+    let kf = KalmanFilterNoControl::new(&transition, &observation);
+    let z = DVector::from_vec(vec![0.3, 100.0]);
+    let masked = kf
+        .step_masked(&initial_estimate(), &z, &[true, false])
+        .unwrap();
+
+    // The same as marking the invalid component NaN.
+    let nan = DVector::from_vec(vec![0.3, f64::NAN]);
+    let expected = kf
+        .step_masked(&initial_estimate(), &nan, &[true, true])
+        .unwrap();
+    approx::assert_relative_eq!(masked, expected);
+
+    // And as an update with the first row only.
+    let position = MatrixObservation::new(
+        DMatrix::from_row_slice(1, 2, &[1.0, 0.0]),
+        DMatrix::identity(1, 1) * 0.5,
+    );
+    let expected = KalmanFilterNoControl::new(&transition, &position)
+        .step(&initial_estimate(), &DVector::from_element(1, 0.3))
+        .unwrap();
+    approx::assert_relative_eq!(masked, expected, epsilon = 1e-12);
+
+    // A flag is needed for each component.
+    match kf.step_masked(&initial_estimate(), &z, &[true]) {
+        Err(e) => assert!(matches!(
+            e.kind(),
+            crate::ErrorKind::DimensionMismatch {
+                matrix: "valid",
+                ..
+            }
+        )),
+        Ok(_) => panic!("a short mask was accepted"),
+    }
+}
+
+#[test]
+fn test_step_masked_failure_policy() {
+    use crate::test_util::{ConstantVelocity, MatrixObservation};
+    use crate::FailurePolicy;
+
+    // The indefinite prior of the failure policy tests, with a noiseless
+    // observation of the difference of the positions in the first
+    // component and a masked-out second component.
+    let stationary = ConstantVelocity::new(0.0, 0.0);
+    let observation = MatrixObservation::new(
+        DMatrix::from_row_slice(2, 2, &[1.0, -1.0, 1.0, 0.0]),
+        DMatrix::from_diagonal_element(2, 2, 0.0),
+    );
+    let initial = StateAndCovariance::new(
+        DVector::from_vec(vec![0.0, 0.0]),
+        DMatrix::from_row_slice(2, 2, &[1.0, 1.0 + 1e-9, 1.0 + 1e-9, 1.0]),
+    );
+    let z = DVector::from_vec(vec![0.1, 100.0]);
+    let kf = KalmanFilterNoControl::new(&stationary, &observation);
+    assert!(kf.step_masked(&initial, &z, &[true, false]).is_err());
+
+    let kf = kf.with_failure_policy(FailurePolicy::SkipUpdate);
+    let posterior = kf.step_masked(&initial, &z, &[true, false]).unwrap();
+    assert_eq!(posterior, initial);
+
+    // Doubling the diagonal gives the gain [0.5, -0.5].
+    let kf = kf.with_failure_policy(FailurePolicy::InflateAndRetry { factor: 2.0 });
+    let posterior = kf.step_masked(&initial, &z, &[true, false]).unwrap();
+    approx::assert_relative_eq!(posterior.state()[0], 0.05, epsilon = 1e-8);
+    approx::assert_relative_eq!(posterior.state()[1], -0.05, epsilon = 1e-8);
+}
+
+#[test]
+fn test_step_masked_nonlinear() {
+    use crate::nonlinear::NumericalObservationModel;
+    use crate::test_util::ConstantVelocity;
+
+    // Masking the middle component of a non-linear observation is the same
+    // as observing the other two, both linearized at the prior rather than
+    // at the nominal state.
+    let nominal = DVector::from_vec(vec![3.0, 4.0]);
+    let full = NumericalObservationModel::new(
+        |x: &DVector<f64>| DVector::from_vec(vec![x.norm(), x[0] * x[1], x[1].exp()]),
+        DMatrix::identity(3, 3) * 0.5,
+        1e-7,
+        &nominal,
+    );
+    let outer = NumericalObservationModel::new(
+        |x: &DVector<f64>| DVector::from_vec(vec![x.norm(), x[1].exp()]),
+        DMatrix::identity(2, 2) * 0.5,
+        1e-7,
+        &nominal,
+    );
+    let transition = ConstantVelocity::new(0.1, 1.0);
+    let previous = StateAndCovariance::new(
+        DVector::from_vec(vec![1.0, -0.5]),
+        DMatrix::identity(2, 2) * 0.2,
+    );
+    let z = DVector::from_vec(vec![1.3, 100.0, 0.4]);
+    let masked = KalmanFilterNoControl::new(&transition, &full)
+        .step_masked(&previous, &z, &[true, false, true])
+        .unwrap();
+    let expected = KalmanFilterNoControl::new(&transition, &outer)
+        .step(&previous, &DVector::from_vec(vec![1.3, 0.4]))
+        .unwrap();
+    approx::assert_relative_eq!(masked, expected, epsilon = 1e-9);
+}
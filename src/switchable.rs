@@ -0,0 +1,292 @@
+//! Switching between known transition models during a run
+
+use na::{DVector, RealField};
+use nalgebra as na;
+
+use crate::{
+    Error, ErrorKind, KalmanFilterNoControl, ObservationModel, StateAndCovariance,
+    TransitionModelLinearNoControl,
+};
+
+/// A set of transition models of the same state, of which one is active at
+/// each step
+///
+/// This suits regimes which are known at each step, e.g. a vehicle known to
+/// be stationary or moving, unlike the [GpbFilter](crate::GpbFilter), which
+/// estimates the regime. The active model of each step is given as its
+/// index, and the indices of a run are its record: the smoother needs the
+/// model that was active at each step, so the same indices are passed to
+/// [smooth](Self::smooth), or to [smooth_filtered](Self::smooth_filtered)
+/// when the regimes were decided during the run. `active[k]` is the model
+/// of the prediction to the time of observation `k`.
+pub struct SwitchableTransitionModel<'a, R>
+where
+    R: RealField,
+{
+    models: Vec<&'a dyn TransitionModelLinearNoControl<R>>,
+}
+
+impl<'a, R> SwitchableTransitionModel<'a, R>
+where
+    R: RealField,
+{
+    /// Create a set of models
+    ///
+    /// An [ErrorKind::DimensionMismatch] error is returned if the models do
+    /// not all have the same state dimension.
+    pub fn new(models: Vec<&'a dyn TransitionModelLinearNoControl<R>>) -> Result<Self, Error> {
+        if let Some(first) = models.first() {
+            let n = first.state_dim();
+            if let Some(other) = models.iter().find(|m| m.state_dim() != n) {
+                return Err(ErrorKind::DimensionMismatch {
+                    expected: (n, n),
+                    actual: (other.state_dim(), other.state_dim()),
+                    matrix: "F",
+                }
+                .into());
+            }
+        }
+        Ok(Self { models })
+    }
+
+    /// The number of models.
+    pub fn len(&self) -> usize {
+        self.models.len()
+    }
+
+    /// Whether there are no models.
+    pub fn is_empty(&self) -> bool {
+        self.models.is_empty()
+    }
+
+    /// Get the model of the given index, if there is one.
+    pub fn model(&self, index: usize) -> Option<&'a dyn TransitionModelLinearNoControl<R>> {
+        self.models.get(index).copied()
+    }
+
+    /// A Kalman filter using the model of the given index, or an
+    /// [ErrorKind::DimensionMismatch] error if there is no such model.
+    pub fn kalman_filter(
+        &self,
+        index: usize,
+        observation_model: &'a dyn ObservationModel<R>,
+    ) -> Result<KalmanFilterNoControl<'a, R>, Error> {
+        match self.model(index) {
+            Some(model) => Ok(KalmanFilterNoControl::new(model, observation_model)),
+            None => Err(ErrorKind::DimensionMismatch {
+                expected: (self.models.len(), 1),
+                actual: (index + 1, 1),
+                matrix: "active model",
+            }
+            .into()),
+        }
+    }
+
+    /// Perform prediction and update steps with the model of the given
+    /// index, see [KalmanFilterNoControl::step].
+    pub fn step(
+        &self,
+        observation_model: &'a dyn ObservationModel<R>,
+        active: usize,
+        previous_estimate: &StateAndCovariance<R>,
+        observation: &DVector<R>,
+    ) -> Result<StateAndCovariance<R>, Error> {
+        self.kalman_filter(active, observation_model)?
+            .step(previous_estimate, observation)
+    }
+
+    /// Kalman filter with the model `active[k]` at step `k`
+    ///
+    /// There must be an index for each observation. Errors give the index of
+    /// the failed step.
+    pub fn filter(
+        &self,
+        observation_model: &'a dyn ObservationModel<R>,
+        initial_estimate: &StateAndCovariance<R>,
+        observations: &[DVector<R>],
+        active: &[usize],
+    ) -> Result<Vec<StateAndCovariance<R>>, Error> {
+        check_record(observations.len(), active)?;
+        let mut estimates: Vec<StateAndCovariance<R>> = Vec::with_capacity(observations.len());
+        for (k, (observation, &index)) in observations.iter().zip(active).enumerate() {
+            let previous = estimates.last().unwrap_or(initial_estimate);
+            let estimate = self
+                .step(observation_model, index, previous, observation)
+                .map_err(|e| e.with_step(k))?;
+            estimates.push(estimate);
+        }
+        Ok(estimates)
+    }
+
+    /// Rauch-Tung-Striebel (RTS) smoother with the model `active[k]` at step
+    /// `k`, see [filter](Self::filter).
+    pub fn smooth(
+        &self,
+        observation_model: &'a dyn ObservationModel<R>,
+        initial_estimate: &StateAndCovariance<R>,
+        observations: &[DVector<R>],
+        active: &[usize],
+    ) -> Result<Vec<StateAndCovariance<R>>, Error> {
+        let filtered = self.filter(observation_model, initial_estimate, observations, active)?;
+        self.smooth_filtered(observation_model, filtered, active)
+    }
+
+    /// Rauch-Tung-Striebel (RTS) smoother of already filtered estimates,
+    /// given the model active at each step of the filter
+    ///
+    /// The step from estimate `k` to `k + 1` is smoothed with the model
+    /// `active[k + 1]`.
+    pub fn smooth_filtered(
+        &self,
+        observation_model: &'a dyn ObservationModel<R>,
+        filtered: Vec<StateAndCovariance<R>>,
+        active: &[usize],
+    ) -> Result<Vec<StateAndCovariance<R>>, Error> {
+        check_record(filtered.len(), active)?;
+        let mut smoothed = filtered;
+        for k in (0..smoothed.len().saturating_sub(1)).rev() {
+            let estimate = self
+                .kalman_filter(active[k + 1], observation_model)?
+                .smooth_step(&smoothed[k + 1], &smoothed[k])
+                .map_err(|e| e.with_step(k))?;
+            smoothed[k] = estimate;
+        }
+        Ok(smoothed)
+    }
+}
+
+fn check_record(steps: usize, active: &[usize]) -> Result<(), Error> {
+    if active.len() != steps {
+        return Err(ErrorKind::DimensionMismatch {
+            expected: (steps, 1),
+            actual: (active.len(), 1),
+            matrix: "active models",
+        }
+        .into());
+    }
+    Ok(())
+}
+
+#[test]
+fn test_switchable_transition_model() {
+    use crate::test_util::{
+        initial_estimate, simulate_positions, ConstantVelocity, PositionObservation,
+    };
+
+    let moving = ConstantVelocity::new(0.1, 1.0);
+    let stationary = ConstantVelocity::new(0.1, 1e-4);
+    let observation = PositionObservation::new(0.5);
+    let observations = simulate_positions(12, 0.1, 1.0, 0.5, 18);
+    let models = SwitchableTransitionModel::new(vec![&moving, &stationary]).unwrap();
+
+    // With a single model throughout, the same as the plain filter and
+    // smoother.
+    let kf = KalmanFilterNoControl::new(&moving, &observation);
+    let active = vec![0; observations.len()];
+    let expected = kf.smooth(&initial_estimate(), &observations).unwrap();
+    let actual = models
+        .smooth(&observation, &initial_estimate(), &observations, &active)
+        .unwrap();
+    approx::assert_relative_eq!(actual.as_slice(), expected.as_slice(), epsilon = 1e-12);
+
+    // Switching regime halfway: the first half is unaffected by the model of
+    // the second half when filtering.
+    let active: Vec<usize> = (0..observations.len()).map(|k| k / 6).collect();
+    let filtered = models
+        .filter(&observation, &initial_estimate(), &observations, &active)
+        .unwrap();
+    let moving_only = kf.filter(&initial_estimate(), &observations).unwrap();
+    assert_eq!(filtered[..6], moving_only[..6]);
+    assert_ne!(filtered[6], moving_only[6]);
+    let smoothed = models
+        .smooth_filtered(&observation, filtered.clone(), &active)
+        .unwrap();
+    assert_eq!(smoothed.last(), filtered.last());
+
+    assert!(models
+        .filter(
+            &observation,
+            &initial_estimate(),
+            &observations,
+            &active[1..]
+        )
+        .is_err());
+    assert!(models
+        .step(&observation, 2, &initial_estimate(), &observations[0])
+        .is_err());
+}
+
+#[test]
+fn test_switchable_pairing() {
+    use crate::test_util::{
+        initial_estimate, simulate_positions, ConstantVelocity, PositionObservation,
+    };
+    use crate::LinearTransitionModel;
+    use na::DMatrix;
+
+    // Alternating regimes, with a gap: step `k` predicts with the model
+    // `active[k]`, and the step from `k + 1` back to `k` smooths with that
+    // same model, whatever the model of step `k`.
+    let moving = ConstantVelocity::new(0.1, 1.0);
+    let stationary = ConstantVelocity::new(0.1, 1e-4);
+    let observation = PositionObservation::new(0.5);
+    let models = SwitchableTransitionModel::new(vec![&moving, &stationary]).unwrap();
+    let kf = [
+        KalmanFilterNoControl::new(&moving, &observation),
+        KalmanFilterNoControl::new(&stationary, &observation),
+    ];
+    let mut observations = simulate_positions(7, 0.1, 1.0, 0.5, 23);
+    observations[3] = DVector::from_element(1, f64::NAN);
+    let active = [1, 0, 1, 1, 0, 1, 0];
+    let filtered = models
+        .filter(&observation, &initial_estimate(), &observations, &active)
+        .unwrap();
+    let mut expected = Vec::new();
+    let mut previous = initial_estimate();
+    for (z, &i) in observations.iter().zip(active.iter()) {
+        previous = kf[i].step(&previous, z).unwrap();
+        expected.push(previous.clone());
+    }
+    assert_eq!(filtered, expected);
+    let smoothed = models
+        .smooth_filtered(&observation, filtered, &active)
+        .unwrap();
+    for k in (0..expected.len() - 1).rev() {
+        expected[k] = kf[active[k + 1]]
+            .smooth_step(&expected[k + 1], &expected[k])
+            .unwrap();
+    }
+    assert_eq!(smoothed, expected);
+
+    // An index of a model which does not exist fails at its step.
+    let mut unknown = active;
+    unknown[4] = 2;
+    let err = models
+        .filter(&observation, &initial_estimate(), &observations, &unknown)
+        .unwrap_err();
+    assert!(matches!(
+        err.kind(),
+        ErrorKind::DimensionMismatch {
+            matrix: "active model",
+            ..
+        }
+    ));
+    assert_eq!(err.step(), Some(4));
+
+    // Models of different state dimensions cannot be switched between.
+    let scalar = LinearTransitionModel::from_matrices(
+        DMatrix::from_element(1, 1, 1.0),
+        DMatrix::from_element(1, 1, 1.0),
+    );
+    let err = SwitchableTransitionModel::new(vec![&moving, &scalar])
+        .err()
+        .unwrap();
+    assert!(matches!(
+        err.kind(),
+        ErrorKind::DimensionMismatch {
+            expected: (2, 2),
+            actual: (1, 1),
+            ..
+        }
+    ));
+}
@@ -0,0 +1,326 @@
+//! Generalized pseudo-Bayesian (GPB) filters for switching linear systems
+//!
+//! The system switches between a finite set of linear modes according to a
+//! Markov chain. The exact posterior is a mixture whose number of components
+//! grows exponentially, so GPB filters merge it after each step: GPB1 keeps a
+//! single Gaussian and GPB2 one Gaussian per mode.
+
+use na::{DMatrix, DVector, RealField};
+use nalgebra as na;
+
+use crate::{is_nan, CovarianceUpdateMethod, Error, KalmanFilterNoControl, StateAndCovariance};
+
+/// Merge weighted Gaussians into a single Gaussian with the same mean and
+/// covariance as the mixture (moment matching)
+///
+/// The weights must sum to one.
+///
+/// # Panics
+///
+/// Panics if there are no estimates, or not one weight per estimate.
+pub fn merge_gaussians<R>(
+    weights: &DVector<R>,
+    estimates: &[StateAndCovariance<R>],
+) -> StateAndCovariance<R>
+where
+    R: RealField,
+{
+    assert!(!estimates.is_empty(), "no estimates to merge");
+    assert_eq!(weights.nrows(), estimates.len());
+    let n = estimates[0].state().nrows();
+    let mut mean = DVector::zeros(n);
+    for (w, estimate) in weights.iter().zip(estimates) {
+        mean += estimate.state() * w.clone();
+    }
+    let mut covariance = DMatrix::zeros(n, n);
+    for (w, estimate) in weights.iter().zip(estimates) {
+        let spread = estimate.state() - &mean;
+        covariance += (estimate.covariance() + &spread * spread.transpose()) * w.clone();
+    }
+    StateAndCovariance::new(mean, covariance)
+}
+
+/// Normalize weights given by their logarithms, guarding against underflow.
+///
+/// If all weights are zero, the result is uniform.
+pub(crate) fn normalize_log_weights<R: RealField>(log_weights: &DVector<R>) -> DVector<R> {
+    let max = log_weights.max();
+    if max == -R::one() / R::zero() {
+        let n = log_weights.nrows();
+        return DVector::from_element(n, R::one() / na::convert(n as f64));
+    }
+    let weights = log_weights.map(|x| (x - max.clone()).exp());
+    let total = weights.sum();
+    weights / total
+}
+
+/// The estimate of a switching linear system
+///
+/// This holds the estimate conditioned on each mode at the current step,
+/// together with the probability of each mode.
+#[derive(Debug, Clone)]
+pub struct SwitchingEstimate<R>
+where
+    R: RealField,
+{
+    /// The state estimate conditioned on each mode.
+    pub mode_estimates: Vec<StateAndCovariance<R>>,
+    /// The probability of each mode.
+    pub mode_probabilities: DVector<R>,
+}
+
+impl<R> SwitchingEstimate<R>
+where
+    R: RealField,
+{
+    /// Start from the same estimate in every mode, with the given mode
+    /// probabilities.
+    pub fn new(initial_estimate: StateAndCovariance<R>, mode_probabilities: DVector<R>) -> Self {
+        let mode_estimates = (0..mode_probabilities.nrows())
+            .map(|_| initial_estimate.clone())
+            .collect();
+        Self {
+            mode_estimates,
+            mode_probabilities,
+        }
+    }
+
+    /// The overall estimate, merging the mode estimates.
+    pub fn combined(&self) -> StateAndCovariance<R> {
+        merge_gaussians(&self.mode_probabilities, &self.mode_estimates)
+    }
+
+    /// The most probable mode.
+    pub fn most_probable_mode(&self) -> usize {
+        self.mode_probabilities.imax()
+    }
+}
+
+/// The number of steps of mode history a [GpbFilter] retains before merging
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum GpbOrder {
+    /// GPB1: merge into a single Gaussian before each prediction, running one
+    /// filter per mode.
+    First,
+    /// GPB2: keep one Gaussian per mode, running one filter per pair of
+    /// previous and current modes.
+    Second,
+}
+
+/// Generalized pseudo-Bayesian filter for switching linear systems
+pub struct GpbFilter<'a, R>
+where
+    R: RealField,
+{
+    modes: Vec<KalmanFilterNoControl<'a, R>>,
+    transition_probabilities: DMatrix<R>,
+    order: GpbOrder,
+}
+
+impl<'a, R> GpbFilter<'a, R>
+where
+    R: RealField,
+{
+    /// Create a new filter with no modes
+    ///
+    /// Element `(i, j)` of `transition_probabilities` is the probability of
+    /// switching from mode `i` to mode `j` in one step, so each row sums to
+    /// one. Add one mode per row with [add_mode](Self::add_mode).
+    pub fn new(transition_probabilities: DMatrix<R>, order: GpbOrder) -> Self {
+        assert!(transition_probabilities.is_square());
+        Self {
+            modes: Vec::new(),
+            transition_probabilities,
+            order,
+        }
+    }
+
+    /// Add a mode, returning its index
+    ///
+    /// The mode is filtered with the models and configuration of `filter`,
+    /// e.g. its fading-memory factor and failure policy.
+    pub fn add_mode(&mut self, filter: KalmanFilterNoControl<'a, R>) -> usize {
+        self.modes.push(filter);
+        self.modes.len() - 1
+    }
+
+    /// Predict and update the prior estimate in one mode, returning the
+    /// posterior and the log-likelihood of the observation.
+    fn mode_step(
+        &self,
+        mode: &KalmanFilterNoControl<'a, R>,
+        previous_estimate: &StateAndCovariance<R>,
+        observation: &DVector<R>,
+        missing: bool,
+    ) -> Result<(StateAndCovariance<R>, R), Error> {
+        let prior = mode.predict(previous_estimate);
+        if missing {
+            return Ok((prior, R::zero()));
+        }
+        let log_likelihood = mode
+            .observation_matrix
+            .innovation(&prior, observation)
+            .log_likelihood()?;
+        let posterior = mode.update_with_policy(
+            &prior,
+            observation,
+            mode.observation_matrix.R(),
+            CovarianceUpdateMethod::JosephForm,
+        )?;
+        Ok((posterior, log_likelihood))
+    }
+
+    /// Perform prediction and update steps
+    ///
+    /// If any component of the observation is NaN (not a number), the mode
+    /// estimates are only predicted and the mode probabilities only
+    /// propagated through the Markov chain.
+    pub fn step(
+        &self,
+        previous: &SwitchingEstimate<R>,
+        observation: &DVector<R>,
+    ) -> Result<SwitchingEstimate<R>, Error> {
+        let r = self.modes.len();
+        assert_eq!(r, self.transition_probabilities.nrows());
+        assert_eq!(r, previous.mode_probabilities.nrows());
+        let missing = observation.iter().any(|x| is_nan(x.clone()));
+
+        match self.order {
+            GpbOrder::First => {
+                let merged = previous.combined();
+                let predicted_probabilities =
+                    self.transition_probabilities.transpose() * &previous.mode_probabilities;
+                let mut mode_estimates = Vec::with_capacity(r);
+                let mut log_weights = DVector::zeros(r);
+                for (j, mode) in self.modes.iter().enumerate() {
+                    let (posterior, log_likelihood) =
+                        self.mode_step(mode, &merged, observation, missing)?;
+                    log_weights[j] = log_likelihood + predicted_probabilities[j].clone().ln();
+                    mode_estimates.push(posterior);
+                }
+                Ok(SwitchingEstimate {
+                    mode_estimates,
+                    mode_probabilities: normalize_log_weights(&log_weights),
+                })
+            }
+            GpbOrder::Second => {
+                // Joint log-probability of previous mode i and current mode j.
+                let mut log_weights = DMatrix::zeros(r, r);
+                let mut joint_estimates = Vec::with_capacity(r * r);
+                for (j, mode) in self.modes.iter().enumerate() {
+                    for i in 0..r {
+                        let (posterior, log_likelihood) = self.mode_step(
+                            mode,
+                            &previous.mode_estimates[i],
+                            observation,
+                            missing,
+                        )?;
+                        log_weights[(i, j)] = log_likelihood
+                            + self.transition_probabilities[(i, j)].clone().ln()
+                            + previous.mode_probabilities[i].clone().ln();
+                        joint_estimates.push(posterior);
+                    }
+                }
+                let joint =
+                    normalize_log_weights(&DVector::from_column_slice(log_weights.as_slice()));
+                let mut mode_probabilities = DVector::zeros(r);
+                let mut mode_estimates = Vec::with_capacity(r);
+                for j in 0..r {
+                    let weights = joint.rows(j * r, r).into_owned();
+                    mode_probabilities[j] = weights.sum();
+                    let estimates = &joint_estimates[j * r..(j + 1) * r];
+                    if mode_probabilities[j] > R::zero() {
+                        let weights = weights / mode_probabilities[j].clone();
+                        mode_estimates.push(merge_gaussians(&weights, estimates));
+                    } else {
+                        let uniform = DVector::from_element(r, R::one() / na::convert(r as f64));
+                        mode_estimates.push(merge_gaussians(&uniform, estimates));
+                    }
+                }
+                Ok(SwitchingEstimate {
+                    mode_estimates,
+                    mode_probabilities,
+                })
+            }
+        }
+    }
+}
+
+#[test]
+fn test_gpb_identifies_mode() {
+    use crate::test_util::{
+        initial_estimate, simulate_positions, ConstantVelocity, PositionObservation,
+    };
+
+    let transition = ConstantVelocity::new(0.1, 1.0);
+    let accurate = PositionObservation::new(0.5);
+    let inaccurate = PositionObservation::new(50.0);
+    let observations = simulate_positions(50, 0.1, 1.0, 0.5, 5);
+    let stay = DMatrix::from_row_slice(2, 2, &[0.95, 0.05, 0.05, 0.95]);
+
+    for order in [GpbOrder::First, GpbOrder::Second] {
+        let mut gpb = GpbFilter::new(stay.clone(), order);
+        gpb.add_mode(crate::KalmanFilterNoControl::new(&transition, &accurate));
+        gpb.add_mode(crate::KalmanFilterNoControl::new(&transition, &inaccurate));
+        let mut estimate =
+            SwitchingEstimate::new(initial_estimate(), DVector::from_vec(vec![0.5, 0.5]));
+        for observation in observations.iter() {
+            estimate = gpb.step(&estimate, observation).unwrap();
+        }
+        assert_eq!(estimate.most_probable_mode(), 0);
+        approx::assert_relative_eq!(estimate.mode_probabilities.sum(), 1.0, epsilon = 1e-12);
+        let combined = estimate.combined();
+        let expected = crate::KalmanFilterNoControl::new(&transition, &accurate)
+            .filter(&initial_estimate(), &observations)
+            .unwrap();
+        approx::assert_relative_eq!(combined.state(), expected[49].state(), epsilon = 0.5);
+    }
+}
+
+#[test]
+fn test_merge_gaussians() {
+    // Equal weights of N(-1, 1) and N(1, 1) have mean 0 and variance
+    // 1 + 1 = 2, the spread of the means adding to the variance.
+    let one =
+        |x: f64| StateAndCovariance::new(DVector::from_element(1, x), DMatrix::identity(1, 1));
+    let merged = merge_gaussians(&DVector::from_vec(vec![0.5, 0.5]), &[one(-1.0), one(1.0)]);
+    approx::assert_relative_eq!(merged.state()[0], 0.0);
+    approx::assert_relative_eq!(merged.covariance()[(0, 0)], 2.0);
+
+    // Weights 0.25 and 0.75 of N(0, 1) and N(4, 1) have mean 3 and variance
+    // 1 + 0.25 * 9 + 0.75 * 1 = 4.
+    let merged = merge_gaussians(&DVector::from_vec(vec![0.25, 0.75]), &[one(0.0), one(4.0)]);
+    approx::assert_relative_eq!(merged.state()[0], 3.0);
+    approx::assert_relative_eq!(merged.covariance()[(0, 0)], 4.0);
+}
+
+#[test]
+#[should_panic(expected = "no estimates to merge")]
+fn test_merge_no_gaussians() {
+    merge_gaussians::<f64>(&DVector::zeros(0), &[]);
+}
+
+#[test]
+fn test_gpb_uses_filter_configuration() {
+    use crate::test_util::{
+        initial_estimate, simulate_positions, ConstantVelocity, PositionObservation,
+    };
+
+    // With a single mode, both orders reduce to the Kalman filter of that
+    // mode, including its fading memory.
+    let transition = ConstantVelocity::new(0.1, 1.0);
+    let observation = PositionObservation::new(0.5);
+    let kf = || KalmanFilterNoControl::new(&transition, &observation).with_fading_memory(1.2);
+    let observations = simulate_positions(10, 0.1, 1.0, 0.5, 3);
+    let expected = kf().filter(&initial_estimate(), &observations).unwrap();
+    for order in [GpbOrder::First, GpbOrder::Second] {
+        let mut gpb = GpbFilter::new(DMatrix::identity(1, 1), order);
+        gpb.add_mode(kf());
+        let mut estimate =
+            SwitchingEstimate::new(initial_estimate(), DVector::from_element(1, 1.0));
+        for (observation, expected) in observations.iter().zip(expected.iter()) {
+            estimate = gpb.step(&estimate, observation).unwrap();
+            approx::assert_relative_eq!(estimate.combined(), expected, epsilon = 1e-12);
+        }
+    }
+}
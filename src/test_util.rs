@@ -0,0 +1,254 @@
+//! Models and data shared by the unit tests
+
+use na::{DMatrix, DVector};
+use nalgebra as na;
+
+use crate::{
+    LinearObservationModel, LinearTransitionModel, ObservationModel, StateAndCovariance,
+    TransitionModelLinearNoControl,
+};
+
+/// One-dimensional constant velocity model with state `[position, velocity]`
+pub struct ConstantVelocity {
+    F: DMatrix<f64>,
+    Q: DMatrix<f64>,
+}
+
+impl ConstantVelocity {
+    pub fn new(dt: f64, noise_scale: f64) -> Self {
+        let F = DMatrix::from_row_slice(2, 2, &[1.0, dt, 0.0, 1.0]);
+        let t3 = dt.powi(3) / 3.0;
+        let t2 = dt.powi(2) / 2.0;
+        let Q = DMatrix::from_row_slice(2, 2, &[t3, t2, t2, dt]) * noise_scale;
+        Self { F, Q }
+    }
+}
+
+impl TransitionModelLinearNoControl<f64> for ConstantVelocity {
+    fn state_dim(&self) -> usize {
+        2
+    }
+    fn F(&self) -> &DMatrix<f64> {
+        &self.F
+    }
+    fn Q(&self) -> &DMatrix<f64> {
+        &self.Q
+    }
+}
+
+/// Observation of the position of a [ConstantVelocity] state
+pub struct PositionObservation {
+    H: DMatrix<f64>,
+    R: DMatrix<f64>,
+}
+
+impl PositionObservation {
+    pub fn new(variance: f64) -> Self {
+        let H = DMatrix::from_row_slice(1, 2, &[1.0, 0.0]);
+        let R = DMatrix::from_element(1, 1, variance);
+        Self { H, R }
+    }
+}
+
+impl ObservationModel<f64> for PositionObservation {
+    fn H(&self) -> &DMatrix<f64> {
+        &self.H
+    }
+    fn R(&self) -> &DMatrix<f64> {
+        &self.R
+    }
+    fn state_dim(&self) -> usize {
+        2
+    }
+    fn obs_dim(&self) -> usize {
+        1
+    }
+}
+
+/// Linear observation model given by its matrices
+pub struct MatrixObservation {
+    H: DMatrix<f64>,
+    R: DMatrix<f64>,
+}
+
+impl MatrixObservation {
+    pub fn new(H: DMatrix<f64>, R: DMatrix<f64>) -> Self {
+        Self { H, R }
+    }
+}
+
+impl ObservationModel<f64> for MatrixObservation {
+    fn H(&self) -> &DMatrix<f64> {
+        &self.H
+    }
+    fn R(&self) -> &DMatrix<f64> {
+        &self.R
+    }
+    fn state_dim(&self) -> usize {
+        self.H.ncols()
+    }
+    fn obs_dim(&self) -> usize {
+        self.H.nrows()
+    }
+}
+
+/// Deterministic pseudo-random standard normal numbers
+pub struct Normals {
+    state: u64,
+}
+
+impl Normals {
+    pub fn new(seed: u64) -> Self {
+        Self { state: seed }
+    }
+    fn uniform(&mut self) -> f64 {
+        // splitmix64
+        self.state = self.state.wrapping_add(0x9e3779b97f4a7c15);
+        let mut z = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+        z ^= z >> 31;
+        ((z >> 11) as f64 + 0.5) / (1u64 << 53) as f64
+    }
+    pub fn sample(&mut self) -> f64 {
+        let u1 = self.uniform();
+        let u2 = self.uniform();
+        (-2.0 * u1.ln()).sqrt() * (2.0 * std::f64::consts::PI * u2).cos()
+    }
+}
+
+/// Simulate `n` noisy position observations of a constant velocity target
+pub fn simulate_positions(
+    n: usize,
+    dt: f64,
+    process_noise: f64,
+    observation_noise: f64,
+    seed: u64,
+) -> Vec<DVector<f64>> {
+    let mut normals = Normals::new(seed);
+    let (mut x, mut v) = (0.0, 1.0);
+    let mut observations = Vec::with_capacity(n);
+    for _ in 0..n {
+        // Integrated white noise acceleration, matching [ConstantVelocity].
+        let a = normals.sample() * (process_noise / dt).sqrt();
+        x += v * dt + 0.5 * a * dt * dt;
+        v += a * dt;
+        observations.push(DVector::from_element(
+            1,
+            x + normals.sample() * observation_noise.sqrt(),
+        ));
+    }
+    observations
+}
+
+/// Scalar random walk `x_{k+1} = x_k + w_k` with `Q = 1`, observed directly
+/// with noise variance 2
+///
+/// From a prior variance of 1, the Kalman filter is in steady
+/// state: the predicted variance is 2, the innovation variance 4, the gain
+/// 1/2 and the filtered variance 1, so the filtered mean is
+/// `x_k = (x_{k-1} + y_k) / 2`. The RTS smoother gain is also 1/2.
+pub fn random_walk() -> (LinearTransitionModel<f64>, LinearObservationModel<f64>) {
+    let transition = LinearTransitionModel::from_matrices(
+        DMatrix::from_element(1, 1, 1.0),
+        DMatrix::from_element(1, 1, 1.0),
+    );
+    let observation = LinearObservationModel::from_matrices(
+        DMatrix::from_element(1, 1, 1.0),
+        DMatrix::from_element(1, 1, 2.0),
+    );
+    (transition, observation)
+}
+
+/// Scalar observations with the given values.
+pub fn scalars(values: &[f64]) -> Vec<DVector<f64>> {
+    values
+        .iter()
+        .map(|&v| DVector::from_element(1, v))
+        .collect()
+}
+
+/// A scalar estimate with mean `x` and variance `p`.
+pub fn scalar_estimate(x: f64, p: f64) -> StateAndCovariance<f64> {
+    StateAndCovariance::new(DVector::from_element(1, x), DMatrix::from_element(1, 1, p))
+}
+
+/// Filtered estimates of a scalar random walk, in closed form
+///
+/// Step `k` adds process noise of variance `noise[k].0` and observes the
+/// state with noise of variance `noise[k].1`; a NaN observation is missing.
+/// From `N(x, p)`, a step predicts `N(x, p + q)`, and an observation `y`
+/// then has gain `g = (p + q) / (p + q + r)`, giving
+/// `N(x + g (y - x), (1 - g) (p + q))`.
+pub fn walk_filtered(
+    prior: &StateAndCovariance<f64>,
+    noise: &[(f64, f64)],
+    observations: &[f64],
+) -> Vec<StateAndCovariance<f64>> {
+    assert_eq!(noise.len(), observations.len());
+    let (mut x, mut p) = (prior.state()[0], prior.covariance()[(0, 0)]);
+    let mut estimates = Vec::with_capacity(observations.len());
+    for (&(q, r), &y) in noise.iter().zip(observations.iter()) {
+        p += q;
+        if !y.is_nan() {
+            let g = p / (p + r);
+            x += g * (y - x);
+            p *= 1.0 - g;
+        }
+        estimates.push(scalar_estimate(x, p));
+    }
+    estimates
+}
+
+/// RTS-smoothed estimates of the random walk of [walk_filtered]
+///
+/// Smoothing back from `N(s, t)` at step `k + 1` to the filtered `N(x, p)`
+/// at step `k` has gain `j = p / (p + q)`, with `q` the process noise of
+/// step `k + 1`, giving `N(x + j (s - x), p + j^2 (t - p - q))`.
+pub fn walk_smoothed(
+    prior: &StateAndCovariance<f64>,
+    noise: &[(f64, f64)],
+    observations: &[f64],
+) -> Vec<StateAndCovariance<f64>> {
+    let mut estimates = walk_filtered(prior, noise, observations);
+    for k in (0..estimates.len().saturating_sub(1)).rev() {
+        let q = noise[k + 1].0;
+        let (x, p) = (estimates[k].state()[0], estimates[k].covariance()[(0, 0)]);
+        let (s, t) = (
+            estimates[k + 1].state()[0],
+            estimates[k + 1].covariance()[(0, 0)],
+        );
+        let j = p / (p + q);
+        estimates[k] = scalar_estimate(x + j * (s - x), p + j * j * (t - p - q));
+    }
+    estimates
+}
+
+pub fn initial_estimate() -> StateAndCovariance<f64> {
+    StateAndCovariance::new(DVector::from_vec(vec![0.0, 1.0]), DMatrix::identity(2, 2))
+}
+
+#[test]
+fn test_walk_moments() {
+    // Filtering 2, 4 and 6 from N(0, 1), with Q = 1 and R = 2, halves each
+    // innovation, giving 1, 2.5 and 4.25 with variance 1. Smoothing back
+    // with gain 1/2 gives 3.375 and 2.1875, with variances 1 + (1 - 2) / 4
+    // and 1 + (0.75 - 2) / 4. A missing observation keeps the prediction.
+    let prior = scalar_estimate(0.0, 1.0);
+    let filtered = walk_filtered(&prior, &[(1.0, 2.0); 3], &[2.0, 4.0, 6.0]);
+    let expected = [
+        scalar_estimate(1.0, 1.0),
+        scalar_estimate(2.5, 1.0),
+        scalar_estimate(4.25, 1.0),
+    ];
+    approx::assert_relative_eq!(filtered.as_slice(), &expected[..]);
+    let smoothed = walk_smoothed(&prior, &[(1.0, 2.0); 3], &[2.0, 4.0, 6.0]);
+    let expected = [
+        scalar_estimate(2.1875, 0.6875),
+        scalar_estimate(3.375, 0.75),
+        scalar_estimate(4.25, 1.0),
+    ];
+    approx::assert_relative_eq!(smoothed.as_slice(), &expected[..]);
+    let missing = walk_filtered(&prior, &[(3.0, 2.0)], &[f64::NAN]);
+    assert_eq!(missing, [scalar_estimate(0.0, 4.0)]);
+}
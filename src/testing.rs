@@ -0,0 +1,214 @@
+//! Helpers for testing models against the library
+//!
+//! These generate random model matrices and give reference implementations
+//! of the predict and update steps, written directly from the textbook
+//! equations with explicit loops and a general matrix inverse, sharing no
+//! code with the filter. Property tests can then compare the library's
+//! steps with the references for many random models, e.g. with
+//! [step_discrepancy].
+//!
+//! As elsewhere in this crate, `normal` must return independent samples
+//! from the standard normal distribution, leaving the choice of random
+//! number generator with the caller.
+
+use na::{ComplexField, DMatrix, DVector, RealField};
+use nalgebra as na;
+
+use crate::{
+    Error, ErrorKind, KalmanFilterNoControl, ObservationModel, StateAndCovariance,
+    TransitionModelLinearNoControl,
+};
+
+/// A random `rows` by `cols` matrix of standard normal samples.
+pub fn random_matrix<R, N>(rows: usize, cols: usize, normal: &mut N) -> DMatrix<R>
+where
+    R: RealField,
+    N: FnMut() -> R,
+{
+    DMatrix::from_fn(rows, cols, |_, _| normal())
+}
+
+/// A random symmetric positive definite `n` by `n` matrix
+///
+/// This is `A A^T / n + I` for a random matrix `A`, so its eigenvalues are
+/// at least one and the condition number stays modest.
+pub fn random_spd_matrix<R, N>(n: usize, normal: &mut N) -> DMatrix<R>
+where
+    R: RealField,
+    N: FnMut() -> R,
+{
+    let a: DMatrix<R> = random_matrix(n, n, normal);
+    let scale: R = na::convert(n.max(1) as f64);
+    let spd = (&a * a.transpose()) / scale + DMatrix::identity(n, n);
+    spd.symmetric_part()
+}
+
+/// A random `n` by `n` matrix with spectral radius at most `radius`
+///
+/// A random matrix is scaled so that its largest singular value is
+/// `radius`, which bounds the magnitudes of its eigenvalues. With a radius
+/// less than one, the matrix is a stable state transition matrix `F`.
+pub fn random_stable_matrix<R, N>(n: usize, radius: R, normal: &mut N) -> DMatrix<R>
+where
+    R: RealField,
+    N: FnMut() -> R,
+{
+    let a: DMatrix<R> = random_matrix(n, n, normal);
+    let norm = a
+        .clone()
+        .singular_values()
+        .iter()
+        .fold(R::zero(), |acc, s| acc.max(s.clone()));
+    if norm == R::zero() {
+        return a;
+    }
+    a * (radius / norm)
+}
+
+/// The product `a b` by explicit summation.
+fn reference_matmul<R: ComplexField>(a: &DMatrix<R>, b: &DMatrix<R>) -> DMatrix<R> {
+    assert_eq!(a.ncols(), b.nrows());
+    DMatrix::from_fn(a.nrows(), b.ncols(), |i, j| {
+        (0..a.ncols()).fold(R::zero(), |acc, k| {
+            acc + a[(i, k)].clone() * b[(k, j)].clone()
+        })
+    })
+}
+
+/// Reference prediction step, `x = F x` and `P = F P F^T + Q`
+///
+/// Over a complex field, the conjugate transpose takes the place of the
+/// transpose, here and in [reference_update].
+pub fn reference_predict<R>(
+    F: &DMatrix<R>,
+    Q: &DMatrix<R>,
+    estimate: &StateAndCovariance<R>,
+) -> StateAndCovariance<R>
+where
+    R: ComplexField,
+{
+    let x = DMatrix::from_column_slice(estimate.state().nrows(), 1, estimate.state().as_slice());
+    let state = reference_matmul(F, &x);
+    let covariance =
+        reference_matmul(&reference_matmul(F, estimate.covariance()), &F.adjoint()) + Q;
+    StateAndCovariance::new(DVector::from_column_slice(state.as_slice()), covariance)
+}
+
+/// Reference update step of a linear observation model, `y = H x`
+///
+/// The gain is `K = P H^T (H P H^T + R)^-1`, with the innovation covariance
+/// inverted by LU decomposition, and the covariance is the simple form
+/// `(I - K H) P`, made symmetric. An [ErrorKind::SingularMatrix] error is
+/// returned if the innovation covariance is singular.
+pub fn reference_update<R>(
+    H: &DMatrix<R>,
+    R: &DMatrix<R>,
+    prior: &StateAndCovariance<R>,
+    observation: &DVector<R>,
+) -> Result<StateAndCovariance<R>, Error>
+where
+    R: ComplexField,
+{
+    let x = DMatrix::from_column_slice(prior.state().nrows(), 1, prior.state().as_slice());
+    let residual = DMatrix::from_column_slice(observation.nrows(), 1, observation.as_slice())
+        - reference_matmul(H, &x);
+    reference_update_with_residual(
+        H,
+        R,
+        prior,
+        &DVector::from_column_slice(residual.as_slice()),
+    )
+}
+
+/// Reference update step given the residual `y - h(x)`, which also suits
+/// linearized models.
+pub(crate) fn reference_update_with_residual<R>(
+    H: &DMatrix<R>,
+    R: &DMatrix<R>,
+    prior: &StateAndCovariance<R>,
+    residual: &DVector<R>,
+) -> Result<StateAndCovariance<R>, Error>
+where
+    R: ComplexField,
+{
+    let P = prior.covariance();
+    let HT = H.adjoint();
+    let S = reference_matmul(&reference_matmul(H, P), &HT) + R;
+    let S_inv = S.try_inverse().ok_or(ErrorKind::SingularMatrix)?;
+    let K = reference_matmul(&reference_matmul(P, &HT), &S_inv);
+    let x = DMatrix::from_column_slice(prior.state().nrows(), 1, prior.state().as_slice());
+    let residual = DMatrix::from_column_slice(residual.nrows(), 1, residual.as_slice());
+    let state = x + reference_matmul(&K, &residual);
+    let n = P.nrows();
+    let covariance =
+        reference_matmul(&(DMatrix::identity(n, n) - reference_matmul(&K, H)), P).hermitian_part();
+    Ok(StateAndCovariance::new(
+        DVector::from_column_slice(state.as_slice()),
+        covariance,
+    ))
+}
+
+/// The largest absolute difference between the library's prediction and
+/// update step and the reference steps, for a linear observation model
+///
+/// This compares [KalmanFilterNoControl::step] with [reference_predict]
+/// followed by [reference_update], elementwise over the state and
+/// covariance. Errors of either step are returned.
+pub fn step_discrepancy<R>(
+    transition_model: &dyn TransitionModelLinearNoControl<R>,
+    observation_model: &dyn ObservationModel<R>,
+    previous_estimate: &StateAndCovariance<R>,
+    observation: &DVector<R>,
+) -> Result<R, Error>
+where
+    R: RealField,
+{
+    let actual = KalmanFilterNoControl::new(transition_model, observation_model)
+        .step(previous_estimate, observation)?;
+    let prior = reference_predict(
+        transition_model.F(),
+        transition_model.Q(),
+        previous_estimate,
+    );
+    let expected = reference_update(
+        observation_model.H(),
+        observation_model.R(),
+        &prior,
+        observation,
+    )?;
+    let state = (actual.state() - expected.state()).amax();
+    let covariance = (actual.covariance() - expected.covariance()).amax();
+    Ok(state.max(covariance))
+}
+
+#[test]
+fn test_step_against_reference() {
+    use crate::test_util::Normals;
+    use crate::{LinearObservationModel, LinearTransitionModel};
+
+    // Random models of several sizes agree with the reference steps.
+    let mut normals = Normals::new(16);
+    let mut normal = || normals.sample();
+    for (n, m) in [(1, 1), (2, 1), (3, 2), (5, 3), (6, 6)] {
+        let F = random_stable_matrix(n, 0.95, &mut normal);
+        assert!(F.clone().singular_values().amax() <= 0.95 + 1e-12);
+        let transition = LinearTransitionModel::from_matrices(F, random_spd_matrix(n, &mut normal));
+        let observation = LinearObservationModel::from_matrices(
+            random_matrix(m, n, &mut normal),
+            random_spd_matrix(m, &mut normal),
+        );
+        let previous = StateAndCovariance::new(
+            random_matrix(n, 1, &mut normal).column(0).into_owned(),
+            random_spd_matrix(n, &mut normal),
+        );
+        let z = random_matrix(m, 1, &mut normal).column(0).into_owned();
+        let discrepancy = step_discrepancy(&transition, &observation, &previous, &z).unwrap();
+        assert!(
+            discrepancy < 1e-10,
+            "{} for n = {}, m = {}",
+            discrepancy,
+            n,
+            m
+        );
+    }
+}
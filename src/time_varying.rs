@@ -0,0 +1,233 @@
+//! Filtering and smoothing with a transition model for each step
+
+use na::{DVector, RealField};
+use nalgebra as na;
+
+use crate::{
+    Error, ErrorKind, KalmanFilterNoControl, StateAndCovariance, TransitionModelLinearNoControl,
+};
+
+impl<'a, R> KalmanFilterNoControl<'a, R>
+where
+    R: RealField,
+{
+    /// This filter's observation model and configuration, with another
+    /// transition model.
+    fn with_transition_model<'b>(
+        &'b self,
+        transition_model: &'b dyn TransitionModelLinearNoControl<R>,
+    ) -> KalmanFilterNoControl<'b, R> {
+        KalmanFilterNoControl {
+            transition_model,
+            observation_matrix: self.observation_matrix,
+            failure_policy: self.failure_policy.clone(),
+            fading_memory: self.fading_memory.clone(),
+            smoother_covariance_method: self.smoother_covariance_method,
+        }
+    }
+
+    /// Kalman filter with the transition model `transition_models[k]` for
+    /// the prediction to the time of observation `k`
+    ///
+    /// This suits irregularly sampled series, whose steps have models for
+    /// different intervals, e.g. from
+    /// [ContinuousLinearModel::discretize](crate::ContinuousLinearModel::discretize).
+    /// The transition model of this filter is not used, but its observation
+    /// model, failure policy and fading-memory factor are. There must be a
+    /// model for each observation, or an [ErrorKind::DimensionMismatch]
+    /// error is returned. Errors give the index of the failed step. If any
+    /// observation has a NaN component, it is treated as missing.
+    pub fn filter_time_varying(
+        &self,
+        transition_models: &[&dyn TransitionModelLinearNoControl<R>],
+        initial_estimate: &StateAndCovariance<R>,
+        observations: &[DVector<R>],
+    ) -> Result<Vec<StateAndCovariance<R>>, Error> {
+        check_models(observations.len(), transition_models)?;
+        let mut estimates: Vec<StateAndCovariance<R>> = Vec::with_capacity(observations.len());
+        for (k, (observation, &model)) in observations.iter().zip(transition_models).enumerate() {
+            let previous = estimates.last().unwrap_or(initial_estimate);
+            let estimate = self
+                .with_transition_model(model)
+                .step(previous, observation)
+                .map_err(|e| e.with_step(k))?;
+            estimates.push(estimate);
+        }
+        Ok(estimates)
+    }
+
+    /// Rauch-Tung-Striebel (RTS) smoother with the transition model
+    /// `transition_models[k]` for the prediction to the time of observation
+    /// `k`, see
+    /// [filter_time_varying](struct.KalmanFilterNoControl.html#method.filter_time_varying).
+    pub fn smooth_time_varying(
+        &self,
+        transition_models: &[&dyn TransitionModelLinearNoControl<R>],
+        initial_estimate: &StateAndCovariance<R>,
+        observations: &[DVector<R>],
+    ) -> Result<Vec<StateAndCovariance<R>>, Error> {
+        let filtered =
+            self.filter_time_varying(transition_models, initial_estimate, observations)?;
+        self.smooth_filtered_time_varying(transition_models, filtered)
+    }
+
+    /// Rauch-Tung-Striebel (RTS) smoother of already filtered estimates,
+    /// given the transition model of each step of the filter
+    ///
+    /// The step from estimate `k` to `k + 1` is smoothed with
+    /// `transition_models[k + 1]`, the model which predicted estimate
+    /// `k + 1`, and with the failure policy, fading-memory factor and
+    /// smoother covariance method of this filter.
+    pub fn smooth_filtered_time_varying(
+        &self,
+        transition_models: &[&dyn TransitionModelLinearNoControl<R>],
+        filtered: Vec<StateAndCovariance<R>>,
+    ) -> Result<Vec<StateAndCovariance<R>>, Error> {
+        check_models(filtered.len(), transition_models)?;
+        let mut smoothed = filtered;
+        for k in (0..smoothed.len().saturating_sub(1)).rev() {
+            let estimate = self
+                .with_transition_model(transition_models[k + 1])
+                .smooth_step(&smoothed[k + 1], &smoothed[k])
+                .map_err(|e| e.with_step(k))?;
+            smoothed[k] = estimate;
+        }
+        Ok(smoothed)
+    }
+}
+
+fn check_models<R: RealField>(
+    steps: usize,
+    transition_models: &[&dyn TransitionModelLinearNoControl<R>],
+) -> Result<(), Error> {
+    if transition_models.len() != steps {
+        return Err(ErrorKind::DimensionMismatch {
+            expected: (steps, 1),
+            actual: (transition_models.len(), 1),
+            matrix: "transition models",
+        }
+        .into());
+    }
+    Ok(())
+}
+
+#[test]
+fn test_time_varying_smoother() {
+    use crate::test_util::{
+        initial_estimate, simulate_positions, ConstantVelocity, PositionObservation,
+    };
+    use crate::SmootherCovarianceMethod;
+
+    let observation = PositionObservation::new(0.5);
+    let observations = simulate_positions(10, 0.1, 1.0, 0.5, 21);
+
+    // With the same model at every step, the same as the plain smoother,
+    // including the configuration of the filter.
+    let regular = ConstantVelocity::new(0.1, 1.0);
+    let models: Vec<&dyn TransitionModelLinearNoControl<f64>> = vec![&regular; 10];
+    let kf = KalmanFilterNoControl::new(&regular, &observation)
+        .with_fading_memory(1.05)
+        .with_smoother_covariance_method(SmootherCovarianceMethod::JosephForm);
+    let expected = kf.smooth(&initial_estimate(), &observations).unwrap();
+    let actual = kf
+        .smooth_time_varying(&models, &initial_estimate(), &observations)
+        .unwrap();
+    approx::assert_relative_eq!(actual.as_slice(), expected.as_slice(), epsilon = 1e-12);
+    let plain = KalmanFilterNoControl::new(&regular, &observation)
+        .smooth(&initial_estimate(), &observations)
+        .unwrap();
+    assert!(actual[5].covariance()[(0, 0)] > plain[5].covariance()[(0, 0)]);
+
+    // A long gap before the last observation: the step into the gap is
+    // smoothed with the model of the gap, not that of the regular steps.
+    let kf = KalmanFilterNoControl::new(&regular, &observation);
+    let long = ConstantVelocity::new(5.0, 1.0);
+    let mut models = models;
+    models[9] = &long;
+    let mut observations = observations;
+    observations[9] = DVector::from_element(1, observations[8][0] + 10.0);
+    let filtered = kf
+        .filter_time_varying(&models, &initial_estimate(), &observations)
+        .unwrap();
+    let smoothed = kf
+        .smooth_filtered_time_varying(&models, filtered.clone())
+        .unwrap();
+    let step = KalmanFilterNoControl::new(&long, &observation)
+        .smooth_step(&smoothed[9], &filtered[8])
+        .unwrap();
+    assert_eq!(smoothed[8], step);
+    let wrong = kf.smooth_step(&smoothed[9], &filtered[8]).unwrap();
+    assert_ne!(smoothed[8], wrong);
+
+    assert!(kf
+        .smooth_time_varying(&models[1..], &initial_estimate(), &observations)
+        .is_err());
+}
+
+#[test]
+fn test_time_varying_intervals() {
+    use crate::test_util::{
+        initial_estimate, simulate_positions, ConstantVelocity, PositionObservation,
+    };
+
+    // Irregular intervals, with a gap: each step predicts with the model of
+    // its own interval, and the step from `k + 1` back to `k` smooths with
+    // the model of step `k + 1`.
+    let observation = PositionObservation::new(0.5);
+    let intervals = [0.05, 0.1, 0.3, 0.025, 0.2, 0.1];
+    let steps: Vec<_> = intervals
+        .iter()
+        .map(|&dt| ConstantVelocity::new(dt, 1.0))
+        .collect();
+    let models: Vec<&dyn TransitionModelLinearNoControl<f64>> = steps
+        .iter()
+        .map(|m| m as &dyn TransitionModelLinearNoControl<f64>)
+        .collect();
+    let kf = KalmanFilterNoControl::new(&steps[0], &observation);
+    let mut observations = simulate_positions(6, 0.1, 1.0, 0.5, 25);
+    observations[2] = DVector::from_element(1, f64::NAN);
+    let filtered = kf
+        .filter_time_varying(&models, &initial_estimate(), &observations)
+        .unwrap();
+    let mut expected = Vec::new();
+    let mut previous = initial_estimate();
+    for (z, model) in observations.iter().zip(steps.iter()) {
+        previous = KalmanFilterNoControl::new(model, &observation)
+            .step(&previous, z)
+            .unwrap();
+        expected.push(previous.clone());
+    }
+    assert_eq!(filtered, expected);
+    let smoothed = kf
+        .smooth_time_varying(&models, &initial_estimate(), &observations)
+        .unwrap();
+    for k in (0..expected.len() - 1).rev() {
+        expected[k] = KalmanFilterNoControl::new(&steps[k + 1], &observation)
+            .smooth_step(&expected[k + 1], &expected[k])
+            .unwrap();
+    }
+    assert_eq!(smoothed, expected);
+
+    // No observations need no models; a surplus model is an error, and a
+    // failed step gives its index.
+    assert!(kf
+        .smooth_time_varying(&[], &initial_estimate(), &[])
+        .unwrap()
+        .is_empty());
+    let err = kf
+        .filter_time_varying(&models, &initial_estimate(), &observations[..5])
+        .unwrap_err();
+    assert!(matches!(
+        err.kind(),
+        ErrorKind::DimensionMismatch {
+            expected: (5, 1),
+            actual: (6, 1),
+            ..
+        }
+    ));
+    observations[4] = DVector::zeros(2);
+    let err = kf
+        .filter_time_varying(&models, &initial_estimate(), &observations)
+        .unwrap_err();
+    assert_eq!(err.step(), Some(4));
+}
@@ -0,0 +1,207 @@
+//! The unscented Kalman filter (UKF)
+
+use na::{DMatrix, DVector, RealField};
+use nalgebra as na;
+
+use crate::{
+    is_nan, linalg, unscented_transform, Error, ErrorKind, NonlinearTransitionModel,
+    ObservationModel, SigmaPointStrategy, StateAndCovariance,
+};
+
+/// An unscented Kalman filter with no control inputs
+///
+/// Both the prediction and the update propagate the estimate through the
+/// non-linear functions with the [unscented_transform], using sigma points
+/// chosen by the strategy `S`. Only the process function `f` and `Q` of the
+/// [NonlinearTransitionModel] and the observation function
+/// [predict_observation](ObservationModel::predict_observation) and `R` of
+/// the [ObservationModel] are used, so their Jacobians need not be accurate.
+pub struct UnscentedKalmanFilter<'a, R, S>
+where
+    R: RealField,
+    S: SigmaPointStrategy<R>,
+{
+    transition_model: &'a dyn NonlinearTransitionModel<R>,
+    observation_model: &'a dyn ObservationModel<R>,
+    strategy: S,
+}
+
+impl<'a, R, S> UnscentedKalmanFilter<'a, R, S>
+where
+    R: RealField,
+    S: SigmaPointStrategy<R>,
+{
+    /// Initialize a new `UnscentedKalmanFilter` struct.
+    pub fn new(
+        transition_model: &'a dyn NonlinearTransitionModel<R>,
+        observation_model: &'a dyn ObservationModel<R>,
+        strategy: S,
+    ) -> Self {
+        Self {
+            transition_model,
+            observation_model,
+            strategy,
+        }
+    }
+
+    /// Get the sigma point strategy.
+    pub fn strategy(&self) -> &S {
+        &self.strategy
+    }
+
+    /// Predict the prior from the previous estimate.
+    pub fn predict(
+        &self,
+        previous_estimate: &StateAndCovariance<R>,
+    ) -> Result<StateAndCovariance<R>, Error> {
+        let (state, covariance, _) = unscented_transform(
+            previous_estimate.state(),
+            previous_estimate.covariance(),
+            |x| self.transition_model.f(x),
+            &self.strategy,
+        )?;
+        Ok(StateAndCovariance::new(
+            state,
+            covariance + self.transition_model.Q(),
+        ))
+    }
+
+    /// Update the prior with an observation.
+    pub fn update(
+        &self,
+        prior: &StateAndCovariance<R>,
+        observation: &DVector<R>,
+    ) -> Result<StateAndCovariance<R>, Error> {
+        let (predicted, Pyy, Pxy) = unscented_transform(
+            prior.state(),
+            prior.covariance(),
+            |x| self.observation_model.predict_observation(x),
+            &self.strategy,
+        )?;
+        let S = Pyy + self.observation_model.R();
+        let S_inv = match linalg::cholesky_inverse(&S) {
+            Some(v) => v,
+            None => return Err(ErrorKind::CovarianceNotPositiveSemiDefinite.into()),
+        };
+        let K: DMatrix<R> = Pxy * S_inv;
+        let state = prior.state() + &K * (observation - predicted);
+        let covariance = prior.covariance() - linalg::matmul3(&K, &S, &K.transpose());
+        Ok(StateAndCovariance::new(state, covariance.symmetric_part()))
+    }
+
+    /// Perform prediction and update steps
+    ///
+    /// If any component of the observation is NaN (not a number), the
+    /// observation will not be used but rather the prior will be returned as
+    /// the posterior without performing the update step.
+    pub fn step(
+        &self,
+        previous_estimate: &StateAndCovariance<R>,
+        observation: &DVector<R>,
+    ) -> Result<StateAndCovariance<R>, Error> {
+        let prior = self.predict(previous_estimate)?;
+        if observation.iter().any(|x| is_nan(x.clone())) {
+            Ok(prior)
+        } else {
+            self.update(&prior, observation)
+        }
+    }
+}
+
+#[test]
+fn test_unscented_kalman_filter() {
+    use crate::test_util::{
+        initial_estimate, simulate_positions, ConstantVelocity, PositionObservation,
+    };
+    use crate::{
+        JulierSigmaPoints, KalmanFilterNoControl, Linearized, MerweScaledSigmaPoints,
+        SphericalSimplexSigmaPoints,
+    };
+
+    // Every strategy reproduces the mean and covariance.
+    let mean = DVector::from_vec(vec![1.0, -2.0, 0.5]);
+    let covariance = DMatrix::from_row_slice(3, 3, &[2.0, 0.5, 0.1, 0.5, 1.0, 0.2, 0.1, 0.2, 3.0]);
+    let strategies: [&dyn SigmaPointStrategy<f64>; 3] = [
+        &JulierSigmaPoints { kappa: 0.0 },
+        &MerweScaledSigmaPoints::default(),
+        &SphericalSimplexSigmaPoints { w0: 0.5 },
+    ];
+    for strategy in strategies {
+        let (m, P, _) = unscented_transform(&mean, &covariance, |x| x.clone(), strategy).unwrap();
+        approx::assert_relative_eq!(m, mean, epsilon = 1e-9);
+        approx::assert_relative_eq!(P, covariance, epsilon = 1e-6);
+    }
+    let points = SphericalSimplexSigmaPoints { w0: 0.5 }
+        .sigma_points(&mean, &covariance)
+        .unwrap();
+    assert_eq!(points.points().ncols(), 5);
+
+    // With linear models, the UKF is the Kalman filter.
+    let transition = ConstantVelocity::new(0.1, 1.0);
+    let linearized = Linearized(&transition);
+    let observation = PositionObservation::new(0.5);
+    let observations = simulate_positions(10, 0.1, 1.0, 0.5, 4);
+    let kf = KalmanFilterNoControl::new(&transition, &observation);
+    let ukf = UnscentedKalmanFilter::new(
+        &linearized,
+        &observation,
+        SphericalSimplexSigmaPoints { w0: 0.2 },
+    );
+    let mut expected = initial_estimate();
+    let mut actual = initial_estimate();
+    for z in &observations {
+        expected = kf.step(&expected, z).unwrap();
+        actual = ukf.step(&actual, z).unwrap();
+    }
+    approx::assert_relative_eq!(actual.state(), expected.state(), epsilon = 1e-9);
+    approx::assert_relative_eq!(actual.covariance(), expected.covariance(), epsilon = 1e-9);
+}
+
+#[test]
+fn test_unscented_transform_of_square() {
+    use crate::nonlinear::NumericalObservationModel;
+    use crate::{JulierSigmaPoints, LinearTransitionModel, Linearized};
+
+    // For x ~ N(1, 1/2), x^2 has mean 1 + 1/2, variance 4 / 2 + 2 / 4, and
+    // covariance 2 * 1 / 2 with x. Julier points with n + κ = 3 match the
+    // fourth moment of the normal distribution, so all three are exact.
+    let mean = DVector::from_element(1, 1.0);
+    let covariance = DMatrix::from_element(1, 1, 0.5);
+    let julier = JulierSigmaPoints { kappa: 2.0 };
+    let (m, P, Pxy) =
+        unscented_transform(&mean, &covariance, |x| x.map(|v| v * v), &julier).unwrap();
+    approx::assert_relative_eq!(m[0], 1.5, epsilon = 1e-12);
+    approx::assert_relative_eq!(P[(0, 0)], 2.5, epsilon = 1e-12);
+    approx::assert_relative_eq!(Pxy[(0, 0)], 1.0, epsilon = 1e-12);
+
+    // Observing x^2 with R = 1, the update uses these moments, so the gain
+    // is 1 / (2.5 + 1), and the posterior variance 1/2 - K^2 3.5.
+    let square = NumericalObservationModel::new(
+        |x: &DVector<f64>| x.map(|v| v * v),
+        DMatrix::identity(1, 1),
+        1e-7,
+        &mean,
+    );
+    let constant = LinearTransitionModel::from_matrices(
+        DMatrix::identity(1, 1),
+        DMatrix::from_element(1, 1, 0.25),
+    );
+    let linearized = Linearized(&constant);
+    let ukf = UnscentedKalmanFilter::new(&linearized, &square, julier);
+    let prior = StateAndCovariance::new(mean, covariance);
+    let posterior = ukf.update(&prior, &DVector::from_element(1, 3.0)).unwrap();
+    let gain = 1.0 / 3.5;
+    approx::assert_relative_eq!(posterior.state()[0], 1.0 + gain * 1.5, epsilon = 1e-12);
+    approx::assert_relative_eq!(
+        posterior.covariance()[(0, 0)],
+        0.5 - gain * gain * 3.5,
+        epsilon = 1e-12
+    );
+
+    // A missing observation leaves the prediction.
+    let missing = DVector::from_element(1, f64::NAN);
+    assert_eq!(
+        ukf.step(&prior, &missing).unwrap(),
+        ukf.predict(&prior).unwrap()
+    );
+}
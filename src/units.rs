@@ -0,0 +1,303 @@
+//! Building model matrices from quantities with physical units
+//!
+//! Unit mistakes, such as a time step in milliseconds where the velocity is
+//! in metres per second, give models which run but are wrong. Here the
+//! units of each state and observation component are declared once, as a
+//! [uom] quantity of one such unit. Every entry of `F`, `Q`, `H` and `R` is
+//! then given as a [uom] quantity, whose dimension is checked against the
+//! one the entry must have and whose value is converted to the declared
+//! units. For example, entry `(i, j)` of `F` must have the units of state
+//! component `i` divided by those of component `j`.
+//!
+//! The entries of a matrix have different dimensions, so they are stored
+//! as a [DynQuantity], which keeps the dimension of a [uom] quantity as a
+//! run-time value. As in [uom], angles are dimensionless: an angle in
+//! degrees is converted to radians, but cannot be told apart from a pure
+//! number.
+//!
+//! This layer is optional and needs the `units` feature.
+
+use core::ops::{Div, Mul};
+
+use na::DMatrix;
+use nalgebra as na;
+use uom::si::{Dimension, Quantity, SI};
+use uom::typenum::Integer;
+
+use crate::{Error, ErrorKind};
+
+/// A [uom] quantity whose dimension is checked at run time
+///
+/// Any SI quantity of `uom::si::f64` converts into this, as does an `f64`,
+/// which is a pure number.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct DynQuantity {
+    /// The value in SI base units.
+    value: f64,
+    /// Powers of length, mass, time, current, temperature, amount of
+    /// substance and luminous intensity.
+    dimension: [i8; 7],
+}
+
+impl DynQuantity {
+    /// A zero, which is accepted for an entry of any unit.
+    pub fn zero() -> Self {
+        Self::from(0.0)
+    }
+
+    /// The value in SI base units.
+    pub fn value(&self) -> f64 {
+        self.value
+    }
+
+    /// The value as a multiple of `unit`, or `None` if the dimensions
+    /// differ.
+    pub fn value_in(&self, unit: &DynQuantity) -> Option<f64> {
+        if self.dimension == unit.dimension {
+            Some(self.value / unit.value)
+        } else {
+            None
+        }
+    }
+}
+
+impl From<f64> for DynQuantity {
+    fn from(value: f64) -> Self {
+        Self {
+            value,
+            dimension: [0; 7],
+        }
+    }
+}
+
+impl<D> From<Quantity<D, SI<f64>, f64>> for DynQuantity
+where
+    D: Dimension + ?Sized,
+{
+    fn from(quantity: Quantity<D, SI<f64>, f64>) -> Self {
+        let power = |p: i64| p as i8;
+        Self {
+            value: quantity.value,
+            dimension: [
+                power(D::L::to_i64()),
+                power(D::M::to_i64()),
+                power(D::T::to_i64()),
+                power(D::I::to_i64()),
+                power(D::Th::to_i64()),
+                power(D::N::to_i64()),
+                power(D::J::to_i64()),
+            ],
+        }
+    }
+}
+
+impl Mul for DynQuantity {
+    type Output = DynQuantity;
+    fn mul(self, rhs: DynQuantity) -> DynQuantity {
+        let mut dimension = self.dimension;
+        for (d, r) in dimension.iter_mut().zip(rhs.dimension.iter()) {
+            *d += r;
+        }
+        DynQuantity {
+            value: self.value * rhs.value,
+            dimension,
+        }
+    }
+}
+
+impl Div for DynQuantity {
+    type Output = DynQuantity;
+    fn div(self, rhs: DynQuantity) -> DynQuantity {
+        let mut dimension = self.dimension;
+        for (d, r) in dimension.iter_mut().zip(rhs.dimension.iter()) {
+            *d -= r;
+        }
+        DynQuantity {
+            value: self.value / rhs.value,
+            dimension,
+        }
+    }
+}
+
+/// The units of the state and observation components of a model
+///
+/// Each matrix is built from rows of [DynQuantity] entries, whose
+/// dimensions are checked and whose values are converted to these units.
+#[derive(Debug, Clone)]
+pub struct ModelUnits {
+    state: Vec<DynQuantity>,
+    observation: Vec<DynQuantity>,
+}
+
+impl ModelUnits {
+    /// Declare the units of each state and observation component, as a
+    /// quantity of one such unit, e.g. `Length::new::<kilometer>(1.0)`.
+    pub fn new(state: Vec<DynQuantity>, observation: Vec<DynQuantity>) -> Self {
+        Self { state, observation }
+    }
+
+    /// Build the state transition matrix `F` from rows of quantities.
+    pub fn transition_matrix(&self, rows: &[&[DynQuantity]]) -> Result<DMatrix<f64>, Error> {
+        build("F", rows, &self.state, &self.state, |a, b| a / b)
+    }
+
+    /// Build the process covariance `Q` from rows of quantities.
+    pub fn process_covariance(&self, rows: &[&[DynQuantity]]) -> Result<DMatrix<f64>, Error> {
+        build("Q", rows, &self.state, &self.state, |a, b| a * b)
+    }
+
+    /// Build the observation matrix `H` from rows of quantities.
+    pub fn observation_matrix(&self, rows: &[&[DynQuantity]]) -> Result<DMatrix<f64>, Error> {
+        build("H", rows, &self.observation, &self.state, |a, b| a / b)
+    }
+
+    /// Build the observation noise covariance `R` from rows of quantities.
+    pub fn observation_covariance(&self, rows: &[&[DynQuantity]]) -> Result<DMatrix<f64>, Error> {
+        build("R", rows, &self.observation, &self.observation, |a, b| {
+            a * b
+        })
+    }
+}
+
+/// Build a matrix whose entry `(i, j)` is a multiple of the unit
+/// `combine(rows[i], cols[j])`.
+fn build(
+    matrix: &'static str,
+    entries: &[&[DynQuantity]],
+    rows: &[DynQuantity],
+    cols: &[DynQuantity],
+    combine: impl Fn(DynQuantity, DynQuantity) -> DynQuantity,
+) -> Result<DMatrix<f64>, Error> {
+    let actual = (entries.len(), entries.first().map_or(0, |r| r.len()));
+    if actual != (rows.len(), cols.len()) || entries.iter().any(|r| r.len() != cols.len()) {
+        return Err(ErrorKind::DimensionMismatch {
+            expected: (rows.len(), cols.len()),
+            actual,
+            matrix,
+        }
+        .into());
+    }
+    let mut result = DMatrix::zeros(rows.len(), cols.len());
+    for (i, row) in entries.iter().enumerate() {
+        for (j, entry) in row.iter().enumerate() {
+            if entry.value == 0.0 {
+                continue;
+            }
+            result[(i, j)] =
+                entry
+                    .value_in(&combine(rows[i], cols[j]))
+                    .ok_or(ErrorKind::UnitMismatch {
+                        matrix,
+                        row: i,
+                        col: j,
+                    })?;
+        }
+    }
+    Ok(result)
+}
+
+#[test]
+fn test_units() {
+    use uom::si::f64::{Length, Time, Velocity};
+    use uom::si::length::{kilometer, meter};
+    use uom::si::time::millisecond;
+    use uom::si::velocity::meter_per_second;
+
+    let units = ModelUnits::new(
+        vec![
+            Length::new::<meter>(1.0).into(),
+            Velocity::new::<meter_per_second>(1.0).into(),
+        ],
+        vec![Length::new::<kilometer>(1.0).into()],
+    );
+    let one = DynQuantity::from(1.0);
+    let zero = DynQuantity::zero();
+
+    // A time step given in milliseconds is converted to seconds.
+    let dt = Time::new::<millisecond>(100.0).into();
+    let F = units
+        .transition_matrix(&[&[one, dt], &[zero, one]])
+        .unwrap();
+    approx::assert_relative_eq!(F, DMatrix::from_row_slice(2, 2, &[1.0, 0.1, 0.0, 1.0]));
+
+    // Observing a position in metres in kilometres scales it by 1/1000.
+    let H = units.observation_matrix(&[&[one, zero]]).unwrap();
+    approx::assert_relative_eq!(H, DMatrix::from_row_slice(1, 2, &[0.001, 0.0]));
+
+    // Forgetting that dt has units is caught.
+    let err = units
+        .transition_matrix(&[&[one, one], &[zero, one]])
+        .unwrap_err();
+    assert!(matches!(
+        err.kind(),
+        ErrorKind::UnitMismatch {
+            matrix: "F",
+            row: 0,
+            col: 1
+        }
+    ));
+}
+
+#[test]
+fn test_unit_covariances() {
+    use uom::si::angle::{degree, radian};
+    use uom::si::angular_velocity::radian_per_second;
+    use uom::si::f64::{Angle, AngularVelocity, Time};
+    use uom::si::time::millisecond;
+
+    let units = ModelUnits::new(
+        vec![
+            Angle::new::<radian>(1.0).into(),
+            AngularVelocity::new::<radian_per_second>(1.0).into(),
+        ],
+        vec![Angle::new::<degree>(1.0).into()],
+    );
+    let zero = DynQuantity::zero();
+    let one_degree = DynQuantity::from(Angle::new::<degree>(1.0));
+
+    // A heading noise of 1 degree squared, and a rate noise in radians per
+    // millisecond, which is 1000 radians per second.
+    let rate = DynQuantity::from(Angle::new::<radian>(1e-3)) / Time::new::<millisecond>(1.0).into();
+    let Q = units
+        .process_covariance(&[&[one_degree * one_degree, zero], &[zero, rate * rate]])
+        .unwrap();
+    let radians_per_degree = core::f64::consts::PI / 180.0;
+    approx::assert_relative_eq!(
+        Q,
+        DMatrix::from_row_slice(
+            2,
+            2,
+            &[radians_per_degree * radians_per_degree, 0.0, 0.0, 1.0]
+        ),
+        max_relative = 1e-12
+    );
+
+    // Observing a heading in radians in degrees scales it by 180 / pi.
+    let H = units
+        .observation_matrix(&[&[DynQuantity::from(1.0), zero]])
+        .unwrap();
+    approx::assert_relative_eq!(H[(0, 0)], 1.0 / radians_per_degree, max_relative = 1e-12);
+
+    // R must be in degrees squared; a rate is caught, and so is a matrix of
+    // the wrong shape.
+    let err = units
+        .observation_covariance(&[&[AngularVelocity::new::<radian_per_second>(1.0).into()]])
+        .unwrap_err();
+    assert!(matches!(
+        err.kind(),
+        ErrorKind::UnitMismatch {
+            matrix: "R",
+            row: 0,
+            col: 0
+        }
+    ));
+    let err = units.observation_covariance(&[&[zero, zero]]).unwrap_err();
+    assert!(matches!(
+        err.kind(),
+        ErrorKind::DimensionMismatch {
+            expected: (1, 1),
+            actual: (1, 2),
+            matrix: "R"
+        }
+    ));
+}
@@ -0,0 +1,302 @@
+//! The unscented transform
+//!
+//! The unscented transform propagates a Gaussian through a non-linear
+//! function by evaluating it at a small, deterministic set of sigma points
+//! chosen to match the mean and covariance, and fitting a Gaussian to the
+//! results. Unlike linearization, it needs no Jacobian and captures the
+//! mean and covariance correctly to second order.
+//!
+//! How the sigma points are chosen is given by a [SigmaPointStrategy]. The
+//! best choice depends on the state dimension and on how strong the
+//! non-linearity is.
+
+use na::{DMatrix, DVector, RealField};
+use nalgebra as na;
+
+use crate::{Error, ErrorKind};
+
+/// A way of choosing sigma points and weights for the unscented transform
+pub trait SigmaPointStrategy<R>
+where
+    R: RealField,
+{
+    /// The sigma points and weights for a Gaussian with `mean` and
+    /// covariance `sqrt sqrt^T`, where `sqrt` is lower triangular, e.g. a
+    /// Cholesky factor.
+    fn sigma_points_from_sqrt(&self, mean: &DVector<R>, sqrt: &DMatrix<R>) -> SigmaPoints<R>;
+
+    /// The sigma points and weights for a Gaussian with `mean` and
+    /// `covariance`.
+    ///
+    /// An [ErrorKind::CovarianceNotPositiveSemiDefinite] error is returned if
+    /// the covariance has no Cholesky decomposition.
+    fn sigma_points(
+        &self,
+        mean: &DVector<R>,
+        covariance: &DMatrix<R>,
+    ) -> Result<SigmaPoints<R>, Error> {
+        match na::linalg::Cholesky::new(covariance.clone()) {
+            Some(chol) => Ok(self.sigma_points_from_sqrt(mean, &chol.unpack())),
+            None => Err(ErrorKind::CovarianceNotPositiveSemiDefinite.into()),
+        }
+    }
+}
+
+/// Sigma points, as columns, with their weights for the mean and the
+/// covariance
+#[derive(Debug, Clone, PartialEq)]
+pub struct SigmaPoints<R>
+where
+    R: RealField,
+{
+    points: DMatrix<R>,
+    mean_weights: DVector<R>,
+    covariance_weights: DVector<R>,
+}
+
+impl<R> SigmaPoints<R>
+where
+    R: RealField,
+{
+    /// Create new sigma points from the points, as columns, and their
+    /// weights for the mean and the covariance.
+    pub fn new(
+        points: DMatrix<R>,
+        mean_weights: DVector<R>,
+        covariance_weights: DVector<R>,
+    ) -> Self {
+        Self {
+            points,
+            mean_weights,
+            covariance_weights,
+        }
+    }
+
+    /// The points, as columns.
+    pub fn points(&self) -> &DMatrix<R> {
+        &self.points
+    }
+
+    /// The weights for the mean.
+    pub fn mean_weights(&self) -> &DVector<R> {
+        &self.mean_weights
+    }
+
+    /// The weights for the covariance.
+    pub fn covariance_weights(&self) -> &DVector<R> {
+        &self.covariance_weights
+    }
+}
+
+/// The `2n + 1` points at the mean and the mean plus and minus each column
+/// of `sqrt`.
+fn symmetric_points<R: RealField>(mean: &DVector<R>, sqrt: &DMatrix<R>) -> DMatrix<R> {
+    let n = mean.nrows();
+    let mut points = DMatrix::from_fn(n, 2 * n + 1, |i, _| mean[i].clone());
+    for j in 0..n {
+        let column = sqrt.column(j);
+        let mut plus = points.column_mut(1 + j);
+        plus += &column;
+        let mut minus = points.column_mut(1 + n + j);
+        minus -= &column;
+    }
+    points
+}
+
+/// The original symmetric sigma points of Julier and Uhlmann
+///
+/// The `2n + 1` sigma points are the mean and the mean plus and minus the
+/// columns of the matrix square root of `(n + κ) P`. For a Gaussian,
+/// `κ = 3 - n` matches some of the fourth moments, but for `n > 3` the
+/// central weight is then negative and the covariance may be indefinite.
+#[derive(Debug, Clone, PartialEq)]
+pub struct JulierSigmaPoints<R>
+where
+    R: RealField,
+{
+    /// The weight of the central point is `κ / (n + κ)`.
+    pub kappa: R,
+}
+
+impl<R> SigmaPointStrategy<R> for JulierSigmaPoints<R>
+where
+    R: RealField,
+{
+    fn sigma_points_from_sqrt(&self, mean: &DVector<R>, sqrt: &DMatrix<R>) -> SigmaPoints<R> {
+        let n = mean.nrows();
+        let scale = na::convert::<f64, R>(n as f64) + self.kappa.clone();
+        let points = symmetric_points(mean, &(sqrt * scale.clone().sqrt()));
+        let half: R = na::convert(0.5);
+        let mut weights = DVector::from_element(2 * n + 1, half / scale.clone());
+        weights[0] = self.kappa.clone() / scale;
+        SigmaPoints::new(points, weights.clone(), weights)
+    }
+}
+
+/// The scaled sigma points of Van der Merwe
+///
+/// The `2n + 1` sigma points are the mean and the mean plus and minus the
+/// columns of the matrix square root of `(n + λ) P`, where
+/// `λ = α² (n + κ) - n`. A small `α` keeps the points close to the mean,
+/// so that strong non-linearities far from it are not sampled.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MerweScaledSigmaPoints<R>
+where
+    R: RealField,
+{
+    /// The spread of the sigma points around the mean, usually small, e.g.
+    /// `1e-3`.
+    pub alpha: R,
+    /// Prior knowledge of the distribution; 2 is optimal for a Gaussian.
+    pub beta: R,
+    /// A secondary scaling parameter, usually 0 or `3 - n`.
+    pub kappa: R,
+}
+
+impl<R> Default for MerweScaledSigmaPoints<R>
+where
+    R: RealField,
+{
+    fn default() -> Self {
+        Self {
+            alpha: na::convert(1e-3),
+            beta: na::convert(2.0),
+            kappa: R::zero(),
+        }
+    }
+}
+
+impl<R> SigmaPointStrategy<R> for MerweScaledSigmaPoints<R>
+where
+    R: RealField,
+{
+    fn sigma_points_from_sqrt(&self, mean: &DVector<R>, sqrt: &DMatrix<R>) -> SigmaPoints<R> {
+        let n = mean.nrows();
+        let n_r: R = na::convert(n as f64);
+        let alpha2 = self.alpha.clone() * self.alpha.clone();
+        let lambda = alpha2.clone() * (n_r.clone() + self.kappa.clone()) - n_r.clone();
+        let scale = n_r + lambda.clone();
+        let points = symmetric_points(mean, &(sqrt * scale.clone().sqrt()));
+
+        let half: R = na::convert(0.5);
+        let mut mean_weights = DVector::from_element(2 * n + 1, half / scale.clone());
+        mean_weights[0] = lambda / scale;
+        let mut covariance_weights = mean_weights.clone();
+        covariance_weights[0] += R::one() - alpha2 + self.beta.clone();
+        SigmaPoints::new(points, mean_weights, covariance_weights)
+    }
+}
+
+/// The spherical simplex sigma points of Julier
+///
+/// Only `n + 2` points are used, close to the minimum for matching the mean
+/// and covariance, which almost halves the cost for large states. All points
+/// but the central one lie on a sphere whose radius grows as `sqrt(n)`, so
+/// for large `n` they sample far from the mean.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SphericalSimplexSigmaPoints<R>
+where
+    R: RealField,
+{
+    /// The weight of the central point, in `[0, 1)`.
+    pub w0: R,
+}
+
+impl<R> SigmaPointStrategy<R> for SphericalSimplexSigmaPoints<R>
+where
+    R: RealField,
+{
+    fn sigma_points_from_sqrt(&self, mean: &DVector<R>, sqrt: &DMatrix<R>) -> SigmaPoints<R> {
+        let n = mean.nrows();
+        let w1 = (R::one() - self.w0.clone()) / na::convert::<f64, R>((n + 1) as f64);
+
+        // Points of a zero-mean, unit-covariance distribution, built up one
+        // dimension at a time. Column 0 is the origin.
+        let mut unit = DMatrix::<R>::zeros(n, n + 2);
+        for j in 1..=n {
+            let j_r: R = na::convert(j as f64);
+            let denominator = (j_r.clone() * (j_r.clone() + R::one()) * w1.clone()).sqrt();
+            for i in 1..=j {
+                unit[(j - 1, i)] = -R::one() / denominator.clone();
+            }
+            unit[(j - 1, j + 1)] = j_r / denominator;
+        }
+
+        let points = sqrt * unit + DMatrix::from_fn(n, n + 2, |i, _| mean[i].clone());
+        let mut weights = DVector::from_element(n + 2, w1);
+        weights[0] = self.w0.clone();
+        SigmaPoints::new(points, weights.clone(), weights)
+    }
+}
+
+/// Propagate a Gaussian with `mean` and `covariance` through the function
+/// `f` by the unscented transform with sigma points chosen by `strategy`
+///
+/// Returns the mean and covariance of `f(x)` and the cross-covariance of `x`
+/// and `f(x)`. An [ErrorKind::CovarianceNotPositiveSemiDefinite] error is
+/// returned if the covariance has no Cholesky decomposition.
+#[allow(clippy::type_complexity)]
+pub fn unscented_transform<R, F, S>(
+    mean: &DVector<R>,
+    covariance: &DMatrix<R>,
+    f: F,
+    strategy: &S,
+) -> Result<(DVector<R>, DMatrix<R>, DMatrix<R>), Error>
+where
+    R: RealField,
+    F: Fn(&DVector<R>) -> DVector<R>,
+    S: SigmaPointStrategy<R> + ?Sized,
+{
+    let sigma_points = strategy.sigma_points(mean, covariance)?;
+    let points = sigma_points.points();
+    let count = points.ncols();
+    let mut outputs = DMatrix::zeros(0, 0);
+    for j in 0..count {
+        let output = f(&points.column(j).into_owned());
+        if j == 0 {
+            outputs = DMatrix::zeros(output.nrows(), count);
+        }
+        outputs.set_column(j, &output);
+    }
+
+    let output_mean = &outputs * sigma_points.mean_weights();
+    let mut output_covariance = DMatrix::zeros(outputs.nrows(), outputs.nrows());
+    let mut cross_covariance = DMatrix::zeros(mean.nrows(), outputs.nrows());
+    for j in 0..count {
+        let dy = outputs.column(j) - &output_mean;
+        let dx = points.column(j) - mean;
+        let w = sigma_points.covariance_weights()[j].clone();
+        output_covariance += &dy * dy.transpose() * w.clone();
+        cross_covariance += dx * dy.transpose() * w;
+    }
+    Ok((output_mean, output_covariance, cross_covariance))
+}
+
+#[test]
+fn test_unscented_transform() {
+    // A linear function is transformed exactly.
+    let mean = DVector::from_vec(vec![1.0, -2.0]);
+    let covariance = DMatrix::from_row_slice(2, 2, &[2.0, 0.5, 0.5, 1.0]);
+    let A = DMatrix::from_row_slice(3, 2, &[1.0, 2.0, 0.0, 1.0, -1.0, 3.0]);
+    let params = MerweScaledSigmaPoints::default();
+    let (y, Pyy, Pxy) = unscented_transform(&mean, &covariance, |x| &A * x, &params).unwrap();
+    approx::assert_relative_eq!(y, &A * &mean, epsilon = 1e-9);
+    approx::assert_relative_eq!(Pyy, &A * &covariance * A.transpose(), epsilon = 1e-6);
+    approx::assert_relative_eq!(Pxy, &covariance * A.transpose(), epsilon = 1e-6);
+
+    // Polar to Cartesian conversion captures the bias E[cos θ] = exp(-σ²/2)
+    // cos θ̄ that linearization misses, to second order.
+    let mean = DVector::from_vec(vec![1.0, 0.0]);
+    let covariance = DMatrix::from_diagonal(&DVector::from_vec(vec![0.0001, 0.01]));
+    let polar = |x: &DVector<f64>| DVector::from_vec(vec![x[0] * x[1].cos(), x[0] * x[1].sin()]);
+    let params = MerweScaledSigmaPoints {
+        alpha: 1.0,
+        ..Default::default()
+    };
+    let (y, Pyy, _) = unscented_transform(&mean, &covariance, polar, &params).unwrap();
+    approx::assert_relative_eq!(y[0], 1.0 - 0.01 / 2.0, epsilon = 1e-4);
+    approx::assert_relative_eq!(Pyy[(1, 1)], 0.01, epsilon = 1e-3);
+
+    let indefinite = DMatrix::from_row_slice(2, 2, &[1.0, 2.0, 2.0, 1.0]);
+    assert!(unscented_transform(&mean, &indefinite, polar, &params).is_err());
+}
@@ -0,0 +1,98 @@
+//! Checks of model consistency before running a filter
+
+use na::{DMatrix, RealField};
+use nalgebra as na;
+
+use crate::{
+    check_model_shapes, check_shape, Error, ErrorKind, ModelError, ObservationModel,
+    TransitionModelLinearNoControl,
+};
+
+/// Check a transition and observation model for mistakes which would
+/// otherwise give silently wrong answers or panic deep inside nalgebra
+///
+/// This checks that
+/// - `F`, `Q`, `H` and `R` have shapes consistent with the state and
+///   observation dimensions reported by the models,
+/// - `FT` and `HT` are exactly the transposes of `F` and `H`, and
+/// - `Q` and `R` are symmetric and positive semi-definite, up to round-off,
+///   as is `Qc` if a noise coupling is given.
+///
+/// The first problem found is returned, as an [ErrorKind::DimensionMismatch]
+/// error for a matrix of the wrong shape and an [ErrorKind::InvalidModel]
+/// error otherwise.
+pub fn validate_models<R>(
+    transition: &dyn TransitionModelLinearNoControl<R>,
+    observation: &dyn ObservationModel<R>,
+) -> Result<(), Error>
+where
+    R: RealField,
+{
+    check_model_shapes(transition, observation)?;
+
+    check_transpose("FT", transition.F(), &transition.FT())?;
+    check_transpose("HT", observation.H(), &observation.HT())?;
+
+    check_covariance("Q", transition.Q())?;
+    check_covariance("R", observation.R())?;
+
+    if let Some((G, Qc)) = transition.noise_coupling() {
+        check_shape("G", (transition.state_dim(), Qc.nrows()), G)?;
+        check_shape("Qc", (G.ncols(), G.ncols()), Qc)?;
+        check_covariance("Qc", Qc)?;
+    }
+    Ok(())
+}
+
+fn check_transpose<R: RealField>(
+    matrix: &'static str,
+    original: &DMatrix<R>,
+    transpose: &DMatrix<R>,
+) -> Result<(), Error> {
+    if &original.transpose() == transpose {
+        Ok(())
+    } else {
+        Err(ErrorKind::InvalidModel(ModelError::NotTranspose { matrix }).into())
+    }
+}
+
+fn check_covariance<R: RealField>(
+    matrix: &'static str,
+    covariance: &DMatrix<R>,
+) -> Result<(), Error> {
+    // Allow for round-off relative to the largest element.
+    let scale = covariance.amax();
+    let tolerance = R::default_epsilon().sqrt() * scale;
+    let asymmetry = (covariance - covariance.transpose()).amax();
+    if asymmetry > tolerance {
+        return Err(ErrorKind::InvalidModel(ModelError::NotSymmetric { matrix }).into());
+    }
+    let eigenvalues = covariance.clone().symmetric_eigenvalues();
+    if eigenvalues.iter().any(|x| *x < -tolerance.clone()) {
+        return Err(ErrorKind::InvalidModel(ModelError::NotPositiveSemiDefinite { matrix }).into());
+    }
+    Ok(())
+}
+
+#[test]
+fn test_validate_models() {
+    use crate::test_util::{ConstantVelocity, MatrixObservation, PositionObservation};
+
+    let transition = ConstantVelocity::new(0.1, 1.0);
+    assert!(validate_models(&transition, &PositionObservation::new(0.5)).is_ok());
+
+    let H = DMatrix::from_row_slice(1, 2, &[1.0, 0.0]);
+    let observation = MatrixObservation::new(H.clone(), DMatrix::from_element(1, 1, -0.5));
+    let err = validate_models(&transition, &observation).unwrap_err();
+    assert!(matches!(
+        err.kind(),
+        ErrorKind::InvalidModel(ModelError::NotPositiveSemiDefinite { matrix: "R" })
+    ));
+
+    let observation = MatrixObservation::new(H, DMatrix::zeros(2, 2));
+    let err = validate_models(&transition, &observation).unwrap_err();
+    assert!(matches!(
+        err.kind(),
+        ErrorKind::DimensionMismatch { matrix: "R", .. }
+    ));
+}
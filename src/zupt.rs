@@ -0,0 +1,157 @@
+//! Zero-velocity updates (ZUPT) for inertial navigation
+
+use std::collections::VecDeque;
+
+use na::RealField;
+use nalgebra as na;
+
+use crate::{Error, SoftConstraint, StateAndCovariance};
+
+/// Detects when an inertial measurement unit is stationary
+///
+/// A sample is stationary when the magnitude of the specific force is within
+/// a tolerance of gravity and the magnitude of the angular rate is below a
+/// threshold. The unit is declared stationary when every sample of a short
+/// window is, which rejects the brief moments of a moving foot or vehicle
+/// that pass the test by chance.
+#[derive(Debug, Clone)]
+pub struct ZuptDetector<R>
+where
+    R: RealField,
+{
+    gravity: R,
+    accel_tolerance: R,
+    gyro_threshold: R,
+    window: usize,
+    history: VecDeque<bool>,
+}
+
+impl<R> ZuptDetector<R>
+where
+    R: RealField,
+{
+    /// Create a detector requiring `window` consecutive stationary samples,
+    /// with tolerances on the specific force (around standard gravity,
+    /// 9.80665 m/s²) and the angular rate.
+    pub fn new(window: usize, accel_tolerance: R, gyro_threshold: R) -> Self {
+        Self {
+            gravity: na::convert(9.80665),
+            accel_tolerance,
+            gyro_threshold,
+            window,
+            history: VecDeque::with_capacity(window),
+        }
+    }
+
+    /// Set the magnitude of gravity, in the units of the specific force.
+    pub fn with_gravity(mut self, gravity: R) -> Self {
+        self.gravity = gravity;
+        self
+    }
+
+    /// Add a sample of the specific force and angular rate, returning
+    /// whether the unit is stationary.
+    pub fn push(&mut self, accel: &[R], gyro: &[R]) -> bool {
+        let norm = |v: &[R]| {
+            v.iter()
+                .fold(R::zero(), |acc, x| acc + x.clone() * x.clone())
+                .sqrt()
+        };
+        let stationary = (norm(accel) - self.gravity.clone()).abs() <= self.accel_tolerance
+            && norm(gyro) <= self.gyro_threshold;
+        if self.history.len() == self.window {
+            self.history.pop_front();
+        }
+        self.history.push_back(stationary);
+        self.is_stationary()
+    }
+
+    /// Whether the last `window` samples were all stationary.
+    pub fn is_stationary(&self) -> bool {
+        self.history.len() == self.window && self.history.iter().all(|s| *s)
+    }
+}
+
+/// Applies a zero-velocity pseudo-measurement whenever a [ZuptDetector]
+/// finds the unit stationary
+///
+/// This is the canonical correction bounding the velocity error, and so the
+/// position drift, of strapdown inertial navigation, e.g. at each footfall
+/// of a pedestrian.
+#[derive(Debug, Clone)]
+pub struct ZeroVelocityUpdate<R>
+where
+    R: RealField,
+{
+    detector: ZuptDetector<R>,
+    constraint: SoftConstraint<R>,
+}
+
+impl<R> ZeroVelocityUpdate<R>
+where
+    R: RealField,
+{
+    /// Create a ZUPT for a state of dimension `state_dim` whose velocity
+    /// components are at `velocity_indices`, with standard deviation `sigma`
+    /// of the zero-velocity pseudo-measurement.
+    pub fn new(
+        detector: ZuptDetector<R>,
+        state_dim: usize,
+        velocity_indices: &[usize],
+        sigma: R,
+    ) -> Self {
+        let zeros = vec![R::zero(); velocity_indices.len()];
+        Self {
+            detector,
+            constraint: SoftConstraint::on_components(state_dim, velocity_indices, &zeros, sigma),
+        }
+    }
+
+    /// Get a reference to the detector.
+    pub fn detector(&self) -> &ZuptDetector<R> {
+        &self.detector
+    }
+
+    /// Add an IMU sample and, if the unit is stationary, apply the zero
+    /// velocity update to the estimate. Returns the estimate and whether
+    /// the update was applied.
+    pub fn step(
+        &mut self,
+        estimate: &StateAndCovariance<R>,
+        accel: &[R],
+        gyro: &[R],
+    ) -> Result<(StateAndCovariance<R>, bool), Error> {
+        if self.detector.push(accel, gyro) {
+            Ok((self.constraint.apply(estimate)?, true))
+        } else {
+            Ok((estimate.clone(), false))
+        }
+    }
+}
+
+#[test]
+fn test_zero_velocity_update() {
+    use na::{DMatrix, DVector};
+
+    let detector = ZuptDetector::new(3, 0.3, 0.1);
+    let mut zupt = ZeroVelocityUpdate::new(detector, 4, &[2, 3], 0.01);
+    let mut estimate = StateAndCovariance::new(
+        DVector::from_vec(vec![5.0f64, 2.0, 0.4, -0.2]),
+        DMatrix::identity(4, 4),
+    );
+
+    // Walking, then standing still.
+    let moving = ([2.0, 1.0, 12.0], [0.5, 0.0, 0.3]);
+    let still = ([0.0, 0.05, 9.8], [0.01, 0.0, 0.02]);
+    let samples = [moving, still, still, still];
+    let mut applied = vec![];
+    for (accel, gyro) in samples.iter() {
+        let (updated, was_applied) = zupt.step(&estimate, accel, gyro).unwrap();
+        estimate = updated;
+        applied.push(was_applied);
+    }
+    assert_eq!(applied, vec![false, false, false, true]);
+    assert!(estimate.state()[2].abs() < 1e-3);
+    assert!(estimate.state()[3].abs() < 1e-3);
+    approx::assert_relative_eq!(estimate.state()[0], 5.0);
+}